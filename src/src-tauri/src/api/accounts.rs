@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use actix_web::web::Data;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+use crate::db::models::user::User;
+use crate::db::models::user_connection::UserConnection;
+use crate::db::repo::run_blocking;
+use crate::user::UserInfo;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetActiveAccountRequest {
+  account_id: u64,
+}
+
+/// Per-connection sync state of one account; tokens are left out.
+fn connection_states(email: &str) -> Vec<Value> {
+  let user_connections = match UserConnection::find_by_user_email(email.to_string()) {
+    Ok(user_connections) => user_connections,
+    Err(e) => {
+      log::error!("Failed to load connections of {}: {:?}", email, e);
+      return Vec::new();
+    }
+  };
+  user_connections
+    .into_iter()
+    .map(|user_connection| {
+      let (provider, scope) = match user_connection.connection {
+        Some(connection) => (Some(connection.provider), Some(connection.scope)),
+        None => (None, None),
+      };
+      json!({
+        "id": user_connection.id,
+        "provider": provider,
+        "scope": scope,
+        "lastSynced": user_connection.last_synced,
+        "syncStatus": user_connection.sync_status,
+        "syncError": user_connection.sync_error,
+      })
+    })
+    .collect()
+}
+
+#[get("/api/knapsack/accounts")]
+async fn list_accounts() -> impl Responder {
+  let accounts = run_blocking(|| {
+    let accounts: Vec<Value> = User::find_all()?
+      .into_iter()
+      .map(|user| {
+        let connections = connection_states(&user.email);
+        json!({
+          "id": user.id,
+          "email": user.email,
+          "isActive": user.is_active,
+          "connections": connections,
+        })
+      })
+      .collect();
+    Ok(accounts)
+  })
+  .await;
+  match accounts {
+    Ok(accounts) => HttpResponse::Ok().json(json!({ "success": true, "data": accounts })),
+    Err(e) => {
+      log::error!("Failed to list accounts: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+/// Switches the account whose emails, calendar events and drive documents
+/// are listed and searched.
+#[post("/api/knapsack/accounts/active")]
+async fn set_active_account(
+  data: web::Json<SetActiveAccountRequest>,
+  user_info: Data<Arc<RwLock<UserInfo>>>,
+) -> impl Responder {
+  let account_id = data.account_id;
+  match run_blocking(move || User::set_active(account_id)).await {
+    Ok(user) => {
+      let mut user_info = user_info.write().await;
+      user_info.email = Some(user.email.clone());
+      HttpResponse::Ok().json(json!({ "success": true, "data": user }))
+    }
+    Err(e) => {
+      log::error!("Failed to switch account: {:?}", e);
+      HttpResponse::BadRequest().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
@@ -4,8 +4,7 @@ use crate::error::Error;
 
 #[delete("/api/knapsack/audio")]
 async fn delete_audio_files() -> impl Responder {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
+  let knapsack_data_dir = crate::config::data_dir();
   let input_wav_path = knapsack_data_dir.join("input.wav");
   let output_wav_path = knapsack_data_dir.join("output.wav");
   let output_raw_path = knapsack_data_dir.join("output.raw");
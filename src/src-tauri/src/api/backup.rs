@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use actix_web::{post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::backup::{create_backup, default_backup_path, restore_backup};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupRequest {
+  destination: Option<String>,
+  include_secrets: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RestoreRequest {
+  path: String,
+  include_secrets: Option<bool>,
+}
+
+/// Writes a full backup archive, to `~/.knapsack/backups` unless a
+/// destination is given.
+#[post("/api/knapsack/backup")]
+async fn create_backup_api(
+  app_handle: web::Data<tauri::AppHandle>,
+  data: web::Json<BackupRequest>,
+) -> impl Responder {
+  let destination = match &data.destination {
+    Some(destination) => PathBuf::from(destination),
+    None => match default_backup_path() {
+      Ok(path) => path,
+      Err(e) => {
+        return HttpResponse::InternalServerError().json(json!({
+          "error": format!("{:?}", e),
+          "success": false
+        }))
+      }
+    },
+  };
+
+  match create_backup(&app_handle, destination.clone(), data.include_secrets.unwrap_or(true)).await {
+    Ok(manifest) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": { "path": destination, "manifest": manifest }
+    })),
+    Err(e) => {
+      log::error!("Failed to create backup: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+#[post("/api/knapsack/backup/restore")]
+async fn restore_backup_api(
+  app_handle: web::Data<tauri::AppHandle>,
+  data: web::Json<RestoreRequest>,
+) -> impl Responder {
+  match restore_backup(
+    &app_handle,
+    PathBuf::from(&data.path),
+    data.include_secrets.unwrap_or(true),
+  )
+  .await
+  {
+    Ok(summary) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": summary
+    })),
+    Err(e) => {
+      log::error!("Failed to restore backup {}: {:?}", data.path, e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
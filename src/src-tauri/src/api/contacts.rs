@@ -0,0 +1,127 @@
+use actix_web::{
+  get, post,
+  web::{self, Json, Query},
+  HttpResponse, Responder,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::db::models::contact::Contact;
+use crate::db::repo::run_blocking;
+use crate::error::Error;
+use crate::memory::contacts::rebuild_contacts;
+
+const DEFAULT_CONTACTS_LIMIT: usize = 50;
+const MAX_CONTACTS_LIMIT: usize = 500;
+const CONTACT_LINKS_LIMIT: usize = 50;
+
+#[derive(Deserialize)]
+struct SearchContactsQuery {
+  query: Option<String>,
+  organization: Option<String>,
+  limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeContactRequest {
+  contact_id: u64,
+}
+
+#[get("/api/knapsack/contacts")]
+async fn search_contacts(query: Query<SearchContactsQuery>) -> impl Responder {
+  let SearchContactsQuery {
+    query,
+    organization,
+    limit,
+  } = query.into_inner();
+  let limit = limit.unwrap_or(DEFAULT_CONTACTS_LIMIT).clamp(1, MAX_CONTACTS_LIMIT);
+  match run_blocking(move || Contact::search(query.as_deref(), organization.as_deref(), limit)).await {
+    Ok(contacts) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": { "contacts": contacts }
+    })),
+    Err(e) => {
+      log::error!("Failed to search contacts: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+#[get("/api/knapsack/contacts/{id}")]
+async fn get_contact(path: web::Path<u64>) -> impl Responder {
+  let contact_id = path.into_inner();
+  let found = run_blocking(move || match Contact::find_by_id(contact_id)? {
+    Some(contact) => Ok(Some((contact, Contact::links(contact_id, CONTACT_LINKS_LIMIT)?))),
+    None => Ok(None),
+  })
+  .await;
+  match found {
+    Ok(Some((contact, links))) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": { "contact": contact, "links": links }
+    })),
+    Ok(None) => HttpResponse::NotFound().json(json!({
+      "error": "Contact not found",
+      "success": false
+    })),
+    Err(e) => {
+      log::error!("Failed to get contact {}: {:?}", contact_id, e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+/// Merges the contact in the body into the one in the path.
+#[post("/api/knapsack/contacts/{id}/merge")]
+async fn merge_contact(path: web::Path<u64>, data: Json<MergeContactRequest>) -> impl Responder {
+  let contact_id = path.into_inner();
+  let other_id = data.contact_id;
+  let merged = run_blocking(move || {
+    Contact::merge(contact_id, other_id)?;
+    Contact::find_by_id(contact_id)
+  })
+  .await;
+  match merged {
+    Ok(contact) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": { "contact": contact }
+    })),
+    Err(Error::RusqliteError(rusqlite::Error::QueryReturnedNoRows)) => {
+      HttpResponse::NotFound().json(json!({
+        "error": "Contact not found",
+        "success": false
+      }))
+    }
+    Err(e) => {
+      log::error!("Failed to merge contacts: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+#[post("/api/knapsack/contacts/rebuild")]
+async fn rebuild_contacts_api() -> impl Responder {
+  match run_blocking(rebuild_contacts).await {
+    Ok(count) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": { "count": count }
+    })),
+    Err(e) => {
+      log::error!("Failed to rebuild contacts: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
@@ -3,10 +3,13 @@ use crate::connections::google::drive::embed_drive_document;
 use crate::connections::google::gmail::embed_email;
 use crate::connections::local::files::embed_local_file;
 use crate::db::models::document::Document;
-use crate::memory::semantic::SemanticService;
-use actix_web::web::Data;
-use actix_web::{post, web::Json};
+use crate::db::repo::run_blocking;
+use crate::memory::related::related_documents;
+use crate::memory::semantic::{documents_to_display, SemanticService};
+use actix_web::web::{self, Data, Query};
+use actix_web::{get, post, web::Json};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
 use tokio::runtime::Handle;
 use tokio::sync::Mutex;
@@ -92,6 +95,45 @@ pub async fn get_document_infos(
   actix_web::HttpResponse::Ok().json(infos)
 }
 
+#[derive(Deserialize)]
+struct RelatedDocumentsQuery {
+  limit: Option<usize>,
+}
+
+#[get("/api/knapsack/documents/{id}/related")]
+pub async fn get_related_documents(
+  path: web::Path<u64>,
+  query: Query<RelatedDocumentsQuery>,
+  semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
+) -> actix_web::HttpResponse {
+  let document_id = path.into_inner();
+  match run_blocking(move || Document::find_by_id(document_id)).await {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      return actix_web::HttpResponse::NotFound()
+        .json(json!({ "success": false, "error": "Document not found" }))
+    }
+    Err(e) => {
+      return actix_web::HttpResponse::InternalServerError()
+        .json(json!({ "success": false, "error": format!("{:?}", e) }))
+    }
+  }
+
+  let limit = query.limit.unwrap_or(10).min(50);
+  let maybe_semantic_service = semantic_service.lock().await.clone();
+  match related_documents(maybe_semantic_service, document_id, limit).await {
+    Ok(documents) => actix_web::HttpResponse::Ok().json(json!({
+      "success": true,
+      "display_documents": documents_to_display(documents)
+    })),
+    Err(e) => {
+      log::error!("Failed to find related documents for {}: {:?}", document_id, e);
+      actix_web::HttpResponse::InternalServerError()
+        .json(json!({ "success": false, "error": format!("{:?}", e) }))
+    }
+  }
+}
+
 async fn to_display_docs(
   request: Json<GetDocumentInfosRequest>,
   semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
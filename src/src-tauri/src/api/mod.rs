@@ -1,4 +1,10 @@
+pub mod accounts;
 pub mod app_info;
 pub mod audio;
+pub mod backup;
+pub mod contacts;
 pub mod document;
 pub mod notes;
+pub mod retention;
+pub mod secrets;
+pub mod sync_runs;
@@ -1,16 +1,21 @@
 use crate::error::Error;
 use actix_web::{
   get, post,
-  web::{self, Json},
+  web::{self, Data, Json},
   HttpResponse, Responder,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::fs::{read_to_string, File};
 use std::io::Write;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 use crate::audio::audio::get_metadata;
+use crate::connections::local::meetings::embed_meeting_notes;
 use crate::db::models::transcript::Transcript;
+use crate::db::repo::run_blocking;
+use crate::memory::semantic::SemanticService;
 
 #[derive(Deserialize)]
 struct SaveNotesRequest {
@@ -30,9 +35,7 @@ struct AllNotesResponse {
 }
 
 fn save_notes_to_file(thread_id: u64, notes_content: &str) -> Result<(), Error> {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let notes_dir = knapsack_data_dir.join("notes");
+  let notes_dir = crate::config::notes_dir();
 
   if let Err(e) = std::fs::create_dir_all(&notes_dir) {
     log::error!("Failed to create notes directory: {:?}", e);
@@ -60,10 +63,16 @@ fn save_notes_to_file(thread_id: u64, notes_content: &str) -> Result<(), Error>
 }
 
 #[post("/api/knapsack/notes")]
-async fn save_notes(data: Json<SaveNotesRequest>) -> impl Responder {
-  match save_notes_to_file(data.thread_id, &data.notes) {
+async fn save_notes(
+  data: Json<SaveNotesRequest>,
+  semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
+) -> impl Responder {
+  let thread_id = data.thread_id;
+  let notes = data.notes.clone();
+  match run_blocking(move || save_notes_to_file(thread_id, &notes)).await {
     Ok(_) => {
       log::debug!("Saved notes.");
+      embed_meeting_notes(data.thread_id, semantic_service.get_ref().clone()).await;
       HttpResponse::Ok().json(json!({
         "success": true,
         "message": "Notes saved successfully"
@@ -89,9 +98,7 @@ fn check_if_legacy_notes_exists(thread_id: u64) -> Result<Option<String>, Error>
     Ok(None) => return Ok(None),
     Err(_) => return Ok(None),
   };
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let notes_dir = knapsack_data_dir.join("notes");
+  let notes_dir = crate::config::notes_dir();
   let notes_path = notes_dir.join(&transcript.filename);
 
   let notes = if notes_path.exists() {
@@ -119,9 +126,7 @@ fn check_if_legacy_notes_exists(thread_id: u64) -> Result<Option<String>, Error>
 async fn get_notes(path: web::Path<u64>) -> impl Responder {
   let thread_id = path.into_inner();
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let notes_dir = knapsack_data_dir.join("notes");
+  let notes_dir = crate::config::notes_dir();
   let notes_path = notes_dir.join(thread_id.to_string());
 
   let notes = if notes_path.exists() {
@@ -138,7 +143,7 @@ async fn get_notes(path: web::Path<u64>) -> impl Responder {
       "Failed to read notes file: {}. Checking for legacy notes.",
       thread_id
     );
-    let legacy_notes = match check_if_legacy_notes_exists(thread_id) {
+    let legacy_notes = match run_blocking(move || check_if_legacy_notes_exists(thread_id)).await {
       Ok(n) => n,
       Err(e) => {
         log::error!("Failed to read legacy notes: {:?}.", e);
@@ -157,9 +162,7 @@ async fn get_notes(path: web::Path<u64>) -> impl Responder {
 
 #[get("/api/knapsack/notes/list")]
 async fn list_all_notes() -> impl Responder {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let notes_dir = knapsack_data_dir.join("notes");
+  let notes_dir = crate::config::notes_dir();
 
   if !notes_dir.exists() {
     return HttpResponse::Ok().json(json!({
@@ -181,7 +184,13 @@ async fn list_all_notes() -> impl Responder {
             if let Some(filename) = path.file_name() {
               if let Some(filename_str) = filename.to_str() {
                 if let Ok(thread_id) = filename_str.parse::<u64>() {
-                  let metadata = get_metadata(thread_id).await.unwrap();
+                  let metadata = match get_metadata(thread_id).await {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                      log::error!("Failed to read notes metadata {}: {:?}", filename_str, e);
+                      continue;
+                    }
+                  };
                   match read_to_string(&path) {
                     Ok(content) => {
                       notes_list.push(json!({
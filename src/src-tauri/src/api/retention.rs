@@ -0,0 +1,86 @@
+use actix_web::{get, post, put, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::db::models::retention_policy::{RetentionDataType, RetentionPolicy, RetentionPurge};
+use crate::db::repo::run_blocking;
+use crate::retention::run_retention_sweep;
+
+const RECENT_PURGES_LIMIT: u32 = 50;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateRetentionPolicyRequest {
+  data_type: RetentionDataType,
+  retain_days: u32,
+  #[serde(default = "default_is_enabled")]
+  is_enabled: bool,
+}
+
+fn default_is_enabled() -> bool {
+  true
+}
+
+/// Configured policies and the most recent purges.
+#[get("/api/knapsack/retention")]
+async fn get_retention() -> impl Responder {
+  let loaded = run_blocking(|| {
+    Ok((RetentionPolicy::find_all()?, RetentionPurge::find_recent(RECENT_PURGES_LIMIT)?))
+  })
+  .await;
+  match loaded {
+    Ok((policies, purges)) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": { "policies": policies, "purges": purges }
+    })),
+    Err(e) => {
+      log::error!("Failed to load retention policies: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+/// Creates or updates the policy of one data type. Expired data is removed
+/// by the next sweep.
+#[put("/api/knapsack/retention")]
+async fn update_retention_policy(data: web::Json<UpdateRetentionPolicyRequest>) -> impl Responder {
+  let data = data.into_inner();
+  let policy = RetentionPolicy {
+    data_type: data.data_type,
+    retain_days: data.retain_days,
+    is_enabled: data.is_enabled,
+    last_applied: None,
+  };
+  let saved = run_blocking(move || {
+    policy.upsert()?;
+    RetentionPolicy::find_by_data_type(data.data_type)
+  })
+  .await;
+  match saved {
+    Ok(policy) => HttpResponse::Ok().json(json!({ "success": true, "data": policy })),
+    Err(e) => {
+      log::error!("Failed to save retention policy: {:?}", e);
+      HttpResponse::BadRequest().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+#[post("/api/knapsack/retention/sweep")]
+async fn run_retention_sweep_api() -> impl Responder {
+  match run_retention_sweep().await {
+    Ok(summary) => HttpResponse::Ok().json(json!({ "success": true, "data": summary })),
+    Err(e) => {
+      log::error!("Retention sweep failed: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
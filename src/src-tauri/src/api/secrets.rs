@@ -0,0 +1,43 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use serde_json::json;
+
+use crate::clawd::service::reseal_tokens;
+use crate::db::models::user_connection::UserConnection;
+use crate::db::repo::run_blocking;
+use crate::error::Error;
+use crate::secrets::SecretStore;
+
+/// Rotates the secret store key: seals everything again under a new key and
+/// only then forgets the old ones, so a failure part-way leaves every secret
+/// readable.
+#[post("/api/knapsack/secrets/rotate")]
+async fn rotate_secret_key(app_handle: web::Data<tauri::AppHandle>) -> impl Responder {
+  let app_handle = app_handle.get_ref().clone();
+  match run_blocking(move || rotate_and_reseal(&app_handle)).await {
+    Ok((key_id, resealed, retired)) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": {
+        "keyId": key_id,
+        "resealedConnections": resealed,
+        "retiredKeys": retired
+      }
+    })),
+    Err(e) => {
+      log::error!("Failed to rotate secret key: {:?}", e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
+
+/// Returns the new key id and how many connections were resealed and keys
+/// retired.
+fn rotate_and_reseal(app_handle: &tauri::AppHandle) -> Result<(u32, usize, usize), Error> {
+  let key_id = SecretStore::rotate_key()?;
+  let resealed = UserConnection::reseal_tokens()?;
+  reseal_tokens(app_handle).map_err(Error::KSError)?;
+  let retired = SecretStore::retire_inactive_keys()?;
+  Ok((key_id, resealed, retired))
+}
@@ -0,0 +1,39 @@
+use actix_web::{
+  get,
+  web::{self, Query},
+  HttpResponse, Responder,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::db::models::sync_run::SyncRun;
+use crate::db::repo::run_blocking;
+
+const DEFAULT_SYNC_RUNS_LIMIT: u32 = 20;
+
+#[derive(Deserialize)]
+struct SyncRunsQuery {
+  limit: Option<u32>,
+}
+
+/// Recent syncs of one user connection, newest first.
+#[get("/api/knapsack/connections/{user_connection_id}/sync_runs")]
+async fn list_sync_runs(path: web::Path<u64>, query: Query<SyncRunsQuery>) -> impl Responder {
+  let user_connection_id = path.into_inner();
+  let limit = query.limit.unwrap_or(DEFAULT_SYNC_RUNS_LIMIT);
+  match run_blocking(move || SyncRun::find_recent_by_user_connection_id(user_connection_id, limit))
+    .await
+  {
+    Ok(sync_runs) => HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": { "syncRuns": sync_runs }
+    })),
+    Err(e) => {
+      log::error!("Failed to list sync runs of connection {}: {:?}", user_connection_id, e);
+      HttpResponse::InternalServerError().json(json!({
+        "error": format!("{:?}", e),
+        "success": false
+      }))
+    }
+  }
+}
@@ -37,10 +37,15 @@ use tokio::time::{sleep, Duration, Instant};
 use uuid::Uuid;
 
 use crate::audio::utils::sanitize_filename;
+use crate::automations::triggers::fire_meeting_ended;
+use crate::config::{self, Cli};
+use crate::connections::local::meetings::embed_meeting_transcript;
 use crate::db::models::calendar_event::CalendarEvent;
+use crate::memory::semantic::SemanticService;
 use crate::db::models::feed_item::{FeedItem, FeedItemComplete};
 use crate::db::models::thread::Thread;
 use crate::db::models::transcript::{Transcript, TranscriptWithContent};
+use crate::db::repo::run_blocking;
 use crate::error::Error;
 use crate::spotlight::WINDOW_LABEL;
 use crate::utils::log::knap_log_error;
@@ -57,27 +62,6 @@ use std::mem::size_of;
 use std::ptr;
 use tokio::sync::Semaphore;
 
-#[derive(Parser, Debug)]
-struct Opt {
-  /// The audio device to use
-  #[arg(short, long, default_value_t = String::from("default"))]
-  device: String,
-
-  /// Use the JACK host
-  #[cfg(all(
-    any(
-      target_os = "linux",
-      target_os = "dragonfly",
-      target_os = "freebsd",
-      target_os = "netbsd"
-    ),
-    feature = "default"
-  ))]
-  #[arg(short, long)]
-  #[allow(dead_code)]
-  jack: bool,
-}
-
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RecordStatusResponse {
@@ -302,10 +286,9 @@ pub async fn start_recording(
   recording_state.is_recording.store(true, Ordering::Relaxed);
   recording_state.is_paused.store(false, Ordering::Relaxed);
 
-  let opt = Opt::parse();
+  let opt = Cli::parse();
   let host = cpal::default_host();
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
+  let knapsack_data_dir = config::data_dir();
 
   // Setup input device
   let input_wav_path = knapsack_data_dir.join(&input_filename);
@@ -414,6 +397,10 @@ pub struct Metadata {
 }
 
 pub async fn get_metadata(thread_id: u64) -> Result<Metadata, Error> {
+  run_blocking(move || load_metadata(thread_id)).await
+}
+
+fn load_metadata(thread_id: u64) -> Result<Metadata, Error> {
   let thread = match Thread::find_by_id(thread_id.clone()) {
     Ok(Some(t)) => t,
     Ok(None) => return Err(Error::KSError("Thread not found".into())),
@@ -466,7 +453,8 @@ pub async fn get_metadata(thread_id: u64) -> Result<Metadata, Error> {
 pub async fn stop_recording(
   data: Json<StopRecordingRequest>,
   recording_state: Data<RecordingState>,
-  _handle: Data<Arc<Handle>>,
+  handle: Data<Arc<Handle>>,
+  semantic_service: Data<Arc<tokio::sync::Mutex<Option<SemanticService>>>>,
 ) -> HttpResponse {
   if !recording_state.is_recording.load(Ordering::Relaxed) {
     return HttpResponse::BadRequest().body("No recording in progress");
@@ -497,12 +485,11 @@ pub async fn stop_recording(
     }
   }
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack/transcripts");
+  let knapsack_data_dir = config::transcripts_dir();
   let input_path = knapsack_data_dir.join(&format!("{}.txt", input_filename));
   let output_path = knapsack_data_dir.join(&format!("{}.txt", output_filename));
   let thread_id = data.thread_id;
-  let transcript = match Transcript::find_by_thread_id(thread_id) {
+  let transcript = match run_blocking(move || Transcript::find_by_thread_id(thread_id)).await {
     Ok(Some(t)) => t,
     Ok(None) => {
       log::error!("Application error: Transcript not found");
@@ -558,14 +545,21 @@ pub async fn stop_recording(
     }
   };
 
+  handle.spawn(embed_meeting_transcript(
+    thread_id,
+    semantic_service.get_ref().clone(),
+  ));
+
   let save_transcript = data.save_transcript;
 
   if save_transcript {
     let event_id = data.event_id;
-    save_transcript_for_user(thread_id.clone(), event_id.clone(), ".transcripts");
+    if let Err(e) = run_blocking(move || save_transcript_for_user(thread_id, event_id)).await {
+      log::error!("Failed to save transcript copy: {:?}", e);
+    }
   }
 
-  let mut thread = match Thread::find_by_id(thread_id.clone()) {
+  let mut thread = match run_blocking(move || Thread::find_by_id(thread_id)).await {
     Ok(Some(t)) => t,
     Ok(None) => {
       return HttpResponse::InternalServerError().json(json!({
@@ -581,7 +575,7 @@ pub async fn stop_recording(
     }
   };
 
-  let transcript_dir = home_dir.join(".transcripts");
+  let transcript_dir = config::saved_transcripts_dir();
   let file_name = generate_filename(
     thread.clone().subtitle.unwrap(),
     thread.clone().timestamp.unwrap(),
@@ -593,8 +587,16 @@ pub async fn stop_recording(
     thread.saved_transcript = Some(file_path.to_string_lossy().to_string());
   }
 
-  match thread.update() {
-    Ok(_) => HttpResponse::Ok().json("Finished recording"),
+  match run_blocking(move || thread.update()).await {
+    Ok(_) => {
+      let event_id = data.event_id;
+      handle.spawn_blocking(move || {
+        if let Err(e) = fire_meeting_ended(thread_id, Some(event_id), Utc::now().timestamp_millis()) {
+          log::error!("Failed to fire meeting ended triggers: {:?}", e);
+        }
+      });
+      HttpResponse::Ok().json("Finished recording")
+    }
     Err(e) => {
       log::error!("Error updating thread: {:?}", e);
       return HttpResponse::InternalServerError().json(json!({
@@ -605,12 +607,10 @@ pub async fn stop_recording(
   }
 }
 
-pub fn save_transcript_for_user(thread_id: u64, event_id: u64, path: &str) -> Result<(), Error> {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let temp_transcripts_dir = knapsack_data_dir.join("transcripts");
+pub fn save_transcript_for_user(thread_id: u64, event_id: u64) -> Result<(), Error> {
+  let temp_transcripts_dir = config::transcripts_dir();
 
-  let participants_str = CalendarEvent::get_event_participants_str(event_id).unwrap();
+  let participants_str = CalendarEvent::get_event_participants_str(event_id)?;
 
   let transcript = match Transcript::find_by_thread_id(thread_id) {
     Ok(Some(t)) => t,
@@ -637,7 +637,7 @@ pub fn save_transcript_for_user(thread_id: u64, event_id: u64, path: &str) -> Re
   let temp_transcript_path = temp_transcripts_dir.join(transcript.filename);
   if temp_transcript_path.exists() {
     let content: String = read_to_string(temp_transcript_path)?;
-    let file_path = home_dir.join(path);
+    let file_path = config::saved_transcripts_dir();
 
     create_dir_all(&file_path)?;
 
@@ -692,9 +692,7 @@ fn delete_transcript_file(thread_id: u64) -> Result<(), Error> {
     }
   };
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let transcript_dir = knapsack_data_dir.join("transcripts");
+  let transcript_dir = config::transcripts_dir();
   let transcript_path = transcript_dir.join(transcript.clone().filename);
 
   if transcript_path.exists() {
@@ -735,7 +733,7 @@ async fn delete_transcript(path: web::Path<u64>) -> impl Responder {
 #[get("/api/knapsack/transcript/{thread_id}")]
 async fn get_transcript_by_thread_id(path: web::Path<u64>) -> impl Responder {
   let thread_id = path.into_inner();
-  let transcript = match Transcript::find_by_thread_id(thread_id) {
+  let transcript = match run_blocking(move || Transcript::find_by_thread_id(thread_id)).await {
     Ok(Some(t)) => t,
     Ok(None) => {
       return actix_web::HttpResponse::BadRequest()
@@ -748,9 +746,7 @@ async fn get_transcript_by_thread_id(path: web::Path<u64>) -> impl Responder {
     }
   };
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let transcripts_dir = knapsack_data_dir.join("transcripts");
+  let transcripts_dir = config::transcripts_dir();
   let transcript_path = transcripts_dir.join(&transcript.filename.clone());
 
   let content = match read_to_string(transcript_path) {
@@ -782,7 +778,7 @@ async fn get_transcript_by_thread_id(path: web::Path<u64>) -> impl Responder {
 
 #[get("/api/knapsack/transcripts/list")]
 pub async fn list_all_transcripts() -> impl Responder {
-  let transcripts_from_db = match Transcript::find_all() {
+  let transcripts_from_db = match run_blocking(Transcript::find_all).await {
     Ok(transcripts) => transcripts,
     Err(e) => {
       log::error!("Failed to get transcripts from DB: {:?}", e);
@@ -793,9 +789,7 @@ pub async fn list_all_transcripts() -> impl Responder {
     }
   };
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let transcripts_dir = knapsack_data_dir.join("transcripts");
+  let transcripts_dir = config::transcripts_dir();
 
   let mut transcripts = Vec::new();
 
@@ -10,9 +10,7 @@ pub fn save_chunk(samples: Vec<i32>, filename: String, channel: usize, sample_ra
     .into_verified()
     .expect("Config data error.");
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack/");
-  let flac_path = knapsack_data_dir.join("audio/");
+  let flac_path = crate::config::audio_dir();
   if !flac_path.exists() {
     fs::create_dir_all(&flac_path).expect("Failed to create directory");
   }
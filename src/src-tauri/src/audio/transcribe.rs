@@ -19,9 +19,7 @@ pub async fn transcribe_audio(audio_file: &PathBuf, filename: String) -> Result<
         "------------------ Groq Transcribed text: {}",
         transcription
       );
-      let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-      let knapsack_data_dir = home_dir.join(".knapsack");
-      let transcripts_dir = knapsack_data_dir.join("transcripts");
+      let transcripts_dir = crate::config::transcripts_dir();
 
       let transcript_path = transcripts_dir.join(filename);
 
@@ -43,9 +41,7 @@ pub async fn transcribe_audio(audio_file: &PathBuf, filename: String) -> Result<
 }
 
 pub async fn finalize_chunk(audio_filename: String, transcript_filename: String) {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
-  let flac_path = knapsack_data_dir.join("audio");
+  let flac_path = crate::config::audio_dir();
   let audio_path = flac_path.join(&audio_filename);
   match transcribe_audio(&audio_path, transcript_filename).await {
     Ok(_) => {
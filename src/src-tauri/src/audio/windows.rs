@@ -28,8 +28,7 @@ pub struct AudioRecorder {
 }
 
 pub fn get_output_path(filename: &str) -> PathBuf {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let knapsack_data_dir = home_dir.join(".knapsack");
+  let knapsack_data_dir = crate::config::data_dir();
   return knapsack_data_dir.join(format!("{}.raw", filename));
 }
 
@@ -0,0 +1,349 @@
+//! Output actions deliver a run's result beyond the feed once its steps
+//! succeed: by email through the user's Gmail or Outlook account, to a
+//! Slack- or Teams-compatible webhook, as a Markdown file in a folder, or as
+//! the app's notification window. Each action's outcome is recorded on the
+//! run; a failing action does not fail the run or stop the others.
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Local, TimeZone};
+use once_cell::sync::OnceCell;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::connections::google::auth::refresh_connection_token;
+use crate::connections::google::constants::GOOGLE_GMAIL_SCOPE;
+use crate::connections::microsoft::auth::refresh_user_connection;
+use crate::connections::microsoft::constants::{MICROSOFT_BASE_URL, MICROSOFT_OUTLOOK_SCOPE};
+use crate::db::models::automation_action::AutomationAction;
+use crate::db::models::automation_run::{ActionResult, AutomationRunStatus};
+use crate::db::models::user_connection::UserConnection;
+use crate::db::repo::run_blocking;
+use crate::error::Error;
+use crate::local_fs::expand_home;
+
+pub const EMAIL: &str = "email";
+pub const WEBHOOK: &str = "webhook";
+pub const FILE: &str = "file";
+pub const NOTIFICATION: &str = "notification";
+
+pub const ACTION_TYPES: &[&str] = &[EMAIL, WEBHOOK, FILE, NOTIFICATION];
+
+const GMAIL_SEND_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/messages/send";
+/// Applies to the whole request, so an unresponsive webhook or mail API
+/// fails the action instead of holding up the rest of the run.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Set once the app starts, so runs can show the notification window.
+static APP_HANDLE: OnceCell<tauri::AppHandle> = OnceCell::new();
+
+static HTTP_CLIENT: OnceCell<Client> = OnceCell::new();
+
+pub fn set_app_handle(app_handle: tauri::AppHandle) {
+  let _ = APP_HANDLE.set(app_handle);
+}
+
+fn http_client() -> &'static Client {
+  HTTP_CLIENT.get_or_init(|| {
+    Client::builder()
+      .timeout(REQUEST_TIMEOUT)
+      .build()
+      .expect("Failed to build the automation actions HTTP client")
+  })
+}
+
+/// `email`: sent from the user's Gmail account, or else their Outlook one.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmailConfig {
+  to: Vec<String>,
+  /// The automation's name when missing.
+  #[serde(default)]
+  subject: Option<String>,
+}
+
+/// `webhook`: receives `{"text": ...}`, which Slack and Teams incoming
+/// webhooks both accept.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebhookConfig {
+  url: String,
+}
+
+/// `file`: a new Markdown file per run.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+  folder: String,
+}
+
+enum ActionConfig {
+  Email(EmailConfig),
+  Webhook(WebhookConfig),
+  File(FileConfig),
+  Notification,
+}
+
+impl ActionConfig {
+  fn parse(action: &AutomationAction) -> Result<ActionConfig, Error> {
+    let config: Value = match action.config_json.as_deref() {
+      Some(config_json) => serde_json::from_str(config_json)?,
+      None => json!({}),
+    };
+    let invalid = |error: serde_json::Error| {
+      Error::KSError(format!("invalid `{}` action: {}", action.action_type, error))
+    };
+    let config = match action.action_type.as_str() {
+      EMAIL => ActionConfig::Email(serde_json::from_value(config).map_err(invalid)?),
+      WEBHOOK => ActionConfig::Webhook(serde_json::from_value(config).map_err(invalid)?),
+      FILE => ActionConfig::File(serde_json::from_value(config).map_err(invalid)?),
+      NOTIFICATION => ActionConfig::Notification,
+      action_type => {
+        return Err(Error::KSError(format!(
+          "unknown action `{}`, expected one of {}",
+          action_type,
+          ACTION_TYPES.join(", ")
+        )))
+      }
+    };
+    Ok(config)
+  }
+}
+
+/// Checks an action's type and configuration before it is saved.
+pub fn validate_action(action: &AutomationAction) -> Result<(), Error> {
+  match ActionConfig::parse(action)? {
+    ActionConfig::Email(config) => {
+      if config.to.is_empty() || config.to.iter().any(|address| !address.contains('@')) {
+        return Err(Error::KSError("`email` actions need `to` email addresses".into()));
+      }
+    }
+    ActionConfig::Webhook(config) => {
+      if !config.url.starts_with("https://") && !config.url.starts_with("http://") {
+        return Err(Error::KSError("`webhook` actions need an http(s) `url`".into()));
+      }
+    }
+    ActionConfig::File(config) => {
+      if config.folder.trim().is_empty() {
+        return Err(Error::KSError("`file` actions need a `folder`".into()));
+      }
+    }
+    ActionConfig::Notification => {}
+  }
+  Ok(())
+}
+
+/// A run's result as the actions deliver it.
+#[derive(Clone)]
+pub struct RunOutput {
+  pub automation_name: String,
+  pub user_email: String,
+  pub text: String,
+  pub timestamp_millis: i64,
+}
+
+fn encode_header(value: &str) -> String {
+  format!("=?UTF-8?B?{}?=", general_purpose::STANDARD.encode(value))
+}
+
+async fn send_gmail(
+  user_connection: UserConnection,
+  output: &RunOutput,
+  to: &[String],
+  subject: &str,
+) -> Result<(), Error> {
+  let access_token = refresh_connection_token(output.user_email.clone(), user_connection).await?;
+  let message = format!(
+    "To: {}\r\nSubject: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+    to.join(", "),
+    encode_header(subject),
+    general_purpose::STANDARD.encode(&output.text)
+  );
+  let response = http_client()
+    .post(GMAIL_SEND_URL)
+    .bearer_auth(access_token)
+    .json(&json!({ "raw": general_purpose::URL_SAFE_NO_PAD.encode(message) }))
+    .send()
+    .await?;
+  if !response.status().is_success() {
+    return Err(Error::KSError(format!("Gmail answered {}", response.status())));
+  }
+  Ok(())
+}
+
+async fn send_outlook(
+  user_connection: UserConnection,
+  output: &RunOutput,
+  to: &[String],
+  subject: &str,
+) -> Result<(), Error> {
+  let access_token = refresh_user_connection(user_connection, output.user_email.clone())
+    .await?
+    .token;
+  let recipients: Vec<Value> = to
+    .iter()
+    .map(|address| json!({ "emailAddress": { "address": address } }))
+    .collect();
+  let response = http_client()
+    .post(format!("{}/me/sendMail", MICROSOFT_BASE_URL))
+    .bearer_auth(access_token)
+    .json(&json!({
+      "message": {
+        "subject": subject,
+        "body": { "contentType": "Text", "content": output.text },
+        "toRecipients": recipients,
+      },
+      "saveToSentItems": true,
+    }))
+    .send()
+    .await?;
+  if !response.status().is_success() {
+    return Err(Error::KSError(format!("Outlook answered {}", response.status())));
+  }
+  Ok(())
+}
+
+async fn send_email(config: &EmailConfig, output: &RunOutput) -> Result<String, Error> {
+  let subject = config.subject.clone().unwrap_or_else(|| output.automation_name.clone());
+  let user_email = output.user_email.clone();
+  let (gmail, outlook) = run_blocking(move || {
+    let gmail = UserConnection::find_by_user_email_and_scope(user_email.clone(), GOOGLE_GMAIL_SCOPE.to_string()).ok();
+    let outlook = UserConnection::find_by_user_email_and_scope(user_email, MICROSOFT_OUTLOOK_SCOPE.to_string()).ok();
+    Ok((gmail, outlook))
+  })
+  .await?;
+  match (gmail, outlook) {
+    (Some(user_connection), _) => send_gmail(user_connection, output, &config.to, &subject).await?,
+    (None, Some(user_connection)) => send_outlook(user_connection, output, &config.to, &subject).await?,
+    (None, None) => return Err(Error::KSError("No Gmail or Outlook account to send from".into())),
+  }
+  Ok(config.to.join(", "))
+}
+
+async fn post_webhook(config: &WebhookConfig, output: &RunOutput) -> Result<String, Error> {
+  let response = http_client()
+    .post(&config.url)
+    .json(&json!({ "text": format!("{}\n\n{}", output.automation_name, output.text) }))
+    .send()
+    .await?;
+  if !response.status().is_success() {
+    return Err(Error::KSError(format!("Webhook answered {}", response.status())));
+  }
+  Ok(config.url.clone())
+}
+
+fn local_time(timestamp_millis: i64) -> chrono::DateTime<Local> {
+  Local
+    .timestamp_millis_opt(timestamp_millis)
+    .single()
+    .unwrap_or_else(Local::now)
+}
+
+/// Name of the file a run writes, e.g. `weekly-pipeline-review-2026-10-18-0900.md`.
+fn file_name(output: &RunOutput) -> String {
+  let slug = output
+    .automation_name
+    .to_lowercase()
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>()
+    .join("-");
+  let time = local_time(output.timestamp_millis).format("%Y-%m-%d-%H%M");
+  format!("{}-{}.md", if slug.is_empty() { "automation" } else { &slug }, time)
+}
+
+fn write_file(config: &FileConfig, output: &RunOutput) -> Result<String, Error> {
+  let folder = expand_home(Path::new(&config.folder));
+  fs::create_dir_all(&folder)?;
+  let path = folder.join(file_name(output));
+  fs::write(&path, format!("# {}\n\n{}\n", output.automation_name, output.text))?;
+  Ok(path.to_string_lossy().to_string())
+}
+
+async fn show_notification(output: &RunOutput) -> Result<String, Error> {
+  let app_handle = APP_HANDLE
+    .get()
+    .cloned()
+    .ok_or_else(|| Error::KSError("The app window is not available".into()))?;
+  let time = local_time(output.timestamp_millis).format("%-I:%M %p").to_string();
+  crate::show_notification_window(app_handle, None, vec![], output.automation_name.clone(), time)
+    .await;
+  Ok(output.automation_name.clone())
+}
+
+async fn run_action(action: &AutomationAction, output: &RunOutput) -> Result<String, Error> {
+  match ActionConfig::parse(action)? {
+    ActionConfig::Email(config) => send_email(&config, output).await,
+    ActionConfig::Webhook(config) => post_webhook(&config, output).await,
+    ActionConfig::File(config) => {
+      let output = output.clone();
+      run_blocking(move || write_file(&config, &output)).await
+    }
+    ActionConfig::Notification => show_notification(output).await,
+  }
+}
+
+/// Delivers `output` through every action in order and reports how each
+/// went.
+pub async fn run_actions(actions: &[AutomationAction], output: &RunOutput) -> Vec<ActionResult> {
+  let mut results = Vec::with_capacity(actions.len());
+  for action in actions {
+    let (status, detail, error) = match run_action(action, output).await {
+      Ok(detail) => (AutomationRunStatus::Succeeded, Some(detail), None),
+      Err(error) => {
+        log::warn!("Automation action {} failed - {:?}", action.action_type, error);
+        (AutomationRunStatus::Failed, None, Some(format!("{:?}", error)))
+      }
+    };
+    results.push(ActionResult {
+      ordering: action.ordering,
+      action_type: action.action_type.clone(),
+      status,
+      detail,
+      error,
+    });
+  }
+  results
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn action(action_type: &str, config_json: Option<&str>) -> AutomationAction {
+    AutomationAction {
+      id: None,
+      automation_uuid: "uuid".to_string(),
+      action_type: action_type.to_string(),
+      config_json: config_json.map(str::to_string),
+      ordering: 0,
+    }
+  }
+
+  #[test]
+  fn validates_action_configs() {
+    assert!(validate_action(&action(EMAIL, Some(r#"{"to": ["team@example.com"]}"#))).is_ok());
+    assert!(validate_action(&action(EMAIL, Some(r#"{"to": []}"#))).is_err());
+    assert!(validate_action(&action(WEBHOOK, Some(r#"{"url": "https://hooks.slack.com/x"}"#))).is_ok());
+    assert!(validate_action(&action(WEBHOOK, Some(r#"{"url": "hooks.slack.com"}"#))).is_err());
+    assert!(validate_action(&action(FILE, Some(r#"{"folder": "~/Reports", "mode": 1}"#))).is_err());
+    assert!(validate_action(&action(NOTIFICATION, None)).is_ok());
+    assert!(validate_action(&action("sms", None)).is_err());
+  }
+
+  #[test]
+  fn file_names_are_slugged() {
+    let output = RunOutput {
+      automation_name: "Weekly Pipeline Review!".to_string(),
+      user_email: "user@example.com".to_string(),
+      text: String::new(),
+      timestamp_millis: 0,
+    };
+    let name = file_name(&output);
+    assert!(name.starts_with("weekly-pipeline-review-"));
+    assert!(name.ends_with(".md"));
+  }
+}
@@ -3,22 +3,25 @@ use actix_web::{
   web::{self, Data, Json},
   Error as ActixError, HttpRequest, HttpResponse,
 };
-use chrono::{DateTime, Datelike, Duration, Local, TimeDelta, Timelike, Utc};
+use chrono::{DateTime, Duration, Local, TimeDelta, Timelike, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
+use tokio::runtime::Handle;
 use tokio::sync::Mutex;
-use std::{ fs, path::Path };
+use std::{ fs, path::{Path, PathBuf} };
 
 use crate::{
+  config,
   db::models::{
     automation::Automation,
-    automation_run::AutomationRun,
+    automation_action::AutomationAction,
+    automation_run::{AutomationRun, AutomationRunFilter, AutomationRunRecord, AutomationRunStatus},
+    automation_step::AutomationStep,
     cadence_trigger::CadenceTrigger,
     calendar_event::CalendarEvent,
     data_source_trigger::DataSourceTrigger,
-    document::Document,
     email::Email,
     feed_item::FeedItem,
     feed_item::FeedItemComplete,
@@ -27,16 +30,30 @@ use crate::{
     thread::{Thread, ThreadType, ThreadWithMessages},
     user::User,
   },
+  db::repo::run_blocking,
   error::Error,
   memory::semantic::SemanticService,
   utils::log::{knap_log_error,knap_log_debug},
 };
 use crate::audio::audio::generate_filename;
+use crate::automations::actions::{run_actions, RunOutput};
+use crate::automations::bundle::{
+  validate_automation, AutomationBundle, BundleFormat, ConflictStrategy, ImportedAutomation,
+};
+use crate::automations::executor::{execute_now, preview, runs_in_backend};
+use crate::automations::pipeline::{PIPELINE_STEPS, RUN_PARAMS};
+use crate::automations::scheduler::{materialize_due_runs, store_run, sync_schedules};
+use crate::automations::triggers::{
+  calendar_event_context, TriggerFilter, DEFAULT_CALENDAR_GUARD, GOOGLE_CALENDAR, MICROSOFT_CALENDAR,
+};
+use crate::connections::google::constants::GOOGLE_PROVIDER_NAME;
+use crate::connections::microsoft::constants::MICROSOFT_PROVIDER_NAME;
 
 #[derive(Deserialize, Clone)]
 struct CreateAutomationRequestStep {
   pub name: String,
   pub args_json: Option<String>,
+  pub guard: Option<String>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -44,6 +61,23 @@ struct CreateAutomationRequestCadence {
   pub cadence_type: String,
   pub day_of_week: Option<String>,
   pub time: Option<String>,
+  pub cron_expr: Option<String>,
+  pub timezone: Option<String>,
+  pub interval_minutes: Option<u32>,
+  pub day_of_month: Option<u32>,
+}
+
+#[derive(Deserialize, Clone)]
+struct CreateAutomationRequestDataSource {
+  pub data_source: String,
+  pub offset_minutes: Option<i64>,
+  pub filter: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Clone)]
+struct CreateAutomationRequestAction {
+  pub action_type: String,
+  pub config: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -53,8 +87,14 @@ struct CreateAutomationRequest {
   description: String,
   is_active: Option<bool>,
   is_beta: Option<bool>,
+  show_library: Option<bool>,
+  icon: Option<String>,
   cadences: Vec<CreateAutomationRequestCadence>,
   steps: Vec<CreateAutomationRequestStep>,
+  /// Replaces the automation's data-source triggers when present.
+  data_sources: Option<Vec<CreateAutomationRequestDataSource>>,
+  /// Replaces the automation's output actions when present.
+  actions: Option<Vec<CreateAutomationRequestAction>>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -94,9 +134,58 @@ struct ScheduleAutomationRunsResponse {
   error: Option<String>,
 }
 
+const DEFAULT_RUNS_PAGE_SIZE: u32 = 50;
+const MAX_RUNS_PAGE_SIZE: u32 = 200;
+
+#[derive(Deserialize, Clone)]
+struct ListAutomationRunsQuery {
+  automation_uuid: Option<String>,
+  status: Option<String>,
+  /// Millis; runs scheduled before are left out.
+  since: Option<i64>,
+  /// Millis; runs scheduled at or after are left out.
+  until: Option<i64>,
+  limit: Option<u32>,
+  offset: Option<u32>,
+}
+
 #[derive(Serialize, Clone)]
 struct ListAutomationRunsResponse {
-  data: Vec<AutomationRun>,
+  data: Vec<AutomationRunRecord>,
+  total: u64,
+  success: bool,
+  error: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct AutomationRunResponse {
+  data: Option<AutomationRunRecord>,
+  success: bool,
+  error: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+struct RunAutomationRequest {
+  /// Defaults to the signed-in user.
+  user_email: Option<String>,
+  run_params: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Clone)]
+struct ExportAutomationsQuery {
+  /// Exports every automation when missing.
+  uuid: Option<String>,
+  format: Option<BundleFormat>,
+}
+
+#[derive(Deserialize, Clone)]
+struct ImportAutomationsQuery {
+  conflict: Option<ConflictStrategy>,
+}
+
+#[derive(Serialize, Clone)]
+struct ImportAutomationsResponse {
+  data: Vec<ImportedAutomation>,
   success: bool,
   error: Option<String>,
 }
@@ -284,6 +373,85 @@ struct StandardResponse {
   message: Option<String>,
 }
 
+fn request_steps(automation_uuid: &str, steps: &[CreateAutomationRequestStep]) -> Vec<AutomationStep> {
+  steps
+    .iter()
+    .enumerate()
+    .map(|(ordering, step)| AutomationStep {
+      id: None,
+      automation_uuid: automation_uuid.to_string(),
+      name: step.name.clone(),
+      ordering: ordering as u64,
+      args_json: step.args_json.clone(),
+      guard: step.guard.clone(),
+    })
+    .collect()
+}
+
+fn request_cadences(
+  automation_uuid: &str,
+  cadences: &[CreateAutomationRequestCadence],
+) -> Vec<CadenceTrigger> {
+  cadences
+    .iter()
+    .map(|cadence| CadenceTrigger {
+      id: None,
+      automation_uuid: automation_uuid.to_string(),
+      cadence_type: cadence.cadence_type.clone(),
+      day_of_week: cadence.day_of_week.clone(),
+      time: cadence.time.clone(),
+      cron_expr: cadence.cron_expr.clone(),
+      timezone: cadence.timezone.clone(),
+      interval_minutes: cadence.interval_minutes,
+      day_of_month: cadence.day_of_month,
+    })
+    .collect()
+}
+
+fn request_data_sources(
+  automation_uuid: &str,
+  data_sources: &[CreateAutomationRequestDataSource],
+) -> Vec<DataSourceTrigger> {
+  data_sources
+    .iter()
+    .map(|data_source| DataSourceTrigger {
+      id: None,
+      automation_uuid: automation_uuid.to_string(),
+      data_source: data_source.data_source.clone(),
+      offset_minutes: data_source.offset_minutes.unwrap_or_default(),
+      filter_json: data_source.filter.as_ref().map(|filter| filter.to_string()),
+      created_timestamp: None,
+      cursor: None,
+    })
+    .collect()
+}
+
+fn request_actions(
+  automation_uuid: &str,
+  actions: &[CreateAutomationRequestAction],
+) -> Vec<AutomationAction> {
+  actions
+    .iter()
+    .enumerate()
+    .map(|(ordering, action)| AutomationAction {
+      id: None,
+      automation_uuid: automation_uuid.to_string(),
+      action_type: action.action_type.clone(),
+      config_json: action.config.as_ref().map(|config| config.to_string()),
+      ordering: ordering as u64,
+    })
+    .collect()
+}
+
+#[get("/api/knapsack/automations/steps/catalog")]
+async fn get_step_catalog() -> Result<HttpResponse, ActixError> {
+  Ok(HttpResponse::Ok().json(json!({
+    "success": true,
+    "steps": PIPELINE_STEPS,
+    "runParams": RUN_PARAMS,
+  })))
+}
+
 #[post("/api/knapsack/automations")]
 async fn create_automation(
   data: Json<CreateAutomationRequest>,
@@ -292,63 +460,69 @@ async fn create_automation(
     success: false,
     error: None,
   };
-  // let mut cadences: Vec<CadenceTrigger> = vec![];
-  // for data_cadence in data.cadences.clone() {
-  //   let cadence = CadenceTrigger {
-  //     id: None,
-  //     automation_uuid: data.uuid.clone(),
-  //     cadence_type: data_cadence.cadence_type,
-  //     day_of_week: data_cadence.day_of_week,
-  //     time: data_cadence.time,
-  //   };
-  //   cadences.push(cadence);
-  // }
-  // let mut steps: Vec<AutomationStep> = vec![];
-  // for (i, data_step) in data.steps.iter().enumerate() {
-  //   let step = AutomationStep {
-  //     id: None,
-  //     automation_uuid: data.uuid.clone(),
-  //     name: data_step.name.clone(),
-  //     ordering: i as u64,
-  //     args_json: data_step.args_json.clone(),
-  //   };
-  //   steps.push(step);
-  // }
-
-  // let is_active = match data.is_active {
-  //   Some(i) => i.clone(),
-  //   None => true,
-  // };
-
-  // let is_beta = match data.is_beta {
-  //   Some(i) => i.clone(),
-  //   None => true,
-  // };
-
-  // let mut automation = Automation {
-  //   id: None,
-  //   uuid: data.uuid.clone(),
-  //   name: data.name.clone(),
-  //   description: data.description.clone(),
-  //   is_active,
-  //   is_beta,
-  //   runs: None,
-  //   trigger_cadences: Some(cadences),
-  //   trigger_data_sources: None,
-  //   steps: Some(steps),
-  // };
-  // automation.create();
-  // response.success = true;
+  let steps = request_steps(&data.uuid, &data.steps);
+  let cadences = request_cadences(&data.uuid, &data.cadences);
+  let data_sources = data
+    .data_sources
+    .as_ref()
+    .map(|data_sources| request_data_sources(&data.uuid, data_sources));
+  let actions = data
+    .actions
+    .as_ref()
+    .map(|actions| request_actions(&data.uuid, actions));
+  if let Err(Error::KSError(message)) = validate_automation(
+    &steps,
+    &cadences,
+    data_sources.as_deref().unwrap_or_default(),
+    actions.as_deref().unwrap_or_default(),
+  ) {
+    response.error = Some(message);
+    return Ok(HttpResponse::BadRequest().json(response));
+  }
+
+  let mut automation = Automation {
+    id: None,
+    uuid: data.uuid.clone(),
+    name: data.name.clone(),
+    description: data.description.clone(),
+    is_active: data.is_active.unwrap_or(true),
+    is_beta: data.is_beta.unwrap_or(true),
+    show_library: data.show_library.unwrap_or(true),
+    icon: data.icon.clone().unwrap_or_default(),
+    runs: None,
+    trigger_cadences: Some(cadences),
+    trigger_data_sources: data_sources,
+    steps: Some(steps),
+    actions,
+  };
+  match run_blocking(move || automation.create()).await {
+    Ok(()) => response.success = true,
+    Err(error) => {
+      log::error!("Failed to create automation: {:?}", error);
+      response.error = Some(format!("Failed to create automation: {:?}", error));
+      return Ok(HttpResponse::InternalServerError().json(response));
+    }
+  }
   Ok(HttpResponse::Ok().json(response))
 }
 
 #[get("/api/knapsack/automations")]
 async fn get_automations() -> Result<HttpResponse, ActixError> {
-  let automations = Automation::find_all();
+  let automations = match run_blocking(Automation::find_all).await {
+    Ok(automations) => automations,
+    Err(error) => {
+      log::error!("Failed to get automations: {:?}", error);
+      return Ok(HttpResponse::InternalServerError().json(GetAutomationsResponse {
+        error: Some(format!("Failed to get automations: {:?}", error)),
+        success: false,
+        data: None,
+      }));
+    }
+  };
   let response = GetAutomationsResponse {
     error: None,
     success: true,
-    data: Some(automations.clone()),
+    data: Some(automations),
   };
   let http_response = HttpResponse::Ok().json(response);
   Ok(http_response)
@@ -356,18 +530,30 @@ async fn get_automations() -> Result<HttpResponse, ActixError> {
 
 #[post("/api/knapsack/messages")]
 async fn create_message(data: Json<CreateMessageRequest>) -> Result<HttpResponse, ActixError> {
-  let mut user_id = None;
-  if data.user_email.is_some() {
-    let user_email = data
-      .user_email
-      .clone()
-      .expect("create_message expected user_email");
-    let user = User::find_by_email(user_email).unwrap();
-    user_id = Some(user.id.unwrap());
-  }
+  let user_email = data.user_email.clone();
+  let thread_id = data.thread_id;
+  let lookup = run_blocking(move || {
+    let user_id = match user_email {
+      Some(user_email) => User::find_by_email(user_email)?.id,
+      None => None,
+    };
+    Ok((user_id, Thread::find_by_id_with_messages(thread_id).ok()))
+  })
+  .await;
+  let (user_id, thread_with_messages) = match lookup {
+    Ok(lookup) => lookup,
+    Err(error) => {
+      log::error!("Failed to look up message user: {:?}", error);
+      return Ok(HttpResponse::BadRequest().json(CreateMessageResponse {
+        success: false,
+        error: Some("User not found.".to_string()),
+        thread: None,
+        document_ids: data.document_ids.clone(),
+      }));
+    }
+  };
 
-  let mut thread_with_messages: ThreadWithMessages =
-    Thread::find_by_id_with_messages(data.thread_id).unwrap_or_default();
+  let mut thread_with_messages: ThreadWithMessages = thread_with_messages.unwrap_or_default();
   if thread_with_messages.thread.id.is_none() {
     return Ok(HttpResponse::BadRequest().json(CreateMessageResponse {
       success: false,
@@ -387,18 +573,25 @@ async fn create_message(data: Json<CreateMessageRequest>) -> Result<HttpResponse
     document_ids: data.document_ids.clone(),
   };
 
-  let message_result = message.create();
-  if let Err(error) = message_result {
-    log::error!("Message creation failed: {:?}", error);
-    return Ok(HttpResponse::BadRequest().json(CreateMessageResponse {
-      success: false,
-      error: Some("message creation failed".to_string()),
-      thread: None,
-      document_ids: data.document_ids.clone(),
-    }));
-  }
+  let message_result = run_blocking(move || {
+    message.create()?;
+    Ok(message)
+  })
+  .await;
+  let message = match message_result {
+    Ok(message) => message,
+    Err(error) => {
+      log::error!("Message creation failed: {:?}", error);
+      return Ok(HttpResponse::BadRequest().json(CreateMessageResponse {
+        success: false,
+        error: Some("message creation failed".to_string()),
+        thread: None,
+        document_ids: data.document_ids.clone(),
+      }));
+    }
+  };
 
-  thread_with_messages.messages.push(message.clone());
+  thread_with_messages.messages.push(message);
 
   Ok(HttpResponse::Ok().json(CreateMessageResponse {
     success: true,
@@ -415,20 +608,21 @@ async fn delete_automation(path: web::Path<String>) -> Result<HttpResponse, Acti
     success: false,
     error: None,
   };
-  let automation_res = Automation::find_by_id(automation_id.clone());
-  match automation_res {
-    Ok(automation_opt) => {
-      match automation_opt {
-        Some(mut automation) => {
-          automation.delete();
-        }
-        None => {
-          response.error = Some("Automation not found".to_string());
-        }
+  let lookup_id = automation_id.clone();
+  let result = run_blocking(move || match Automation::find_by_id(lookup_id)? {
+    Some(mut automation) => automation.delete().map(|_| true),
+    None => Ok(false),
+  })
+  .await;
+  match result {
+    Ok(found) => {
+      if !found {
+        response.error = Some("Automation not found".to_string());
       }
       response.success = true;
     }
-    _Err => {
+    Err(error) => {
+      log::error!("Failed to delete automation {}: {:?}", automation_id, error);
       response.success = false;
       response.error = Some("Error deleting automation with id ".to_string() + &automation_id);
     }
@@ -442,62 +636,62 @@ async fn update_automation(
   data: Json<CreateAutomationRequest>,
 ) -> Result<HttpResponse, ActixError> {
   let automation_id = path.into_inner();
-  let existing_automation = Automation::find_by_id(automation_id)
-    .unwrap()
-    .expect("Automation not found.");
   let mut response = CreateAutomationResponse {
     success: false,
     error: None,
   };
+  let existing_automation = match run_blocking(move || Automation::find_by_id(automation_id)).await {
+    Ok(Some(existing_automation)) => existing_automation,
+    Ok(None) => {
+      response.error = Some("Automation not found.".to_string());
+      return Ok(HttpResponse::NotFound().json(response));
+    }
+    Err(error) => {
+      log::error!("Failed to get automation: {:?}", error);
+      response.error = Some(format!("Failed to get automation: {:?}", error));
+      return Ok(HttpResponse::InternalServerError().json(response));
+    }
+  };
+
+  let steps = request_steps(&existing_automation.uuid, &data.steps);
+  let cadences = request_cadences(&existing_automation.uuid, &data.cadences);
+  let data_sources = data
+    .data_sources
+    .as_ref()
+    .map(|data_sources| request_data_sources(&existing_automation.uuid, data_sources));
+  let actions = data
+    .actions
+    .as_ref()
+    .map(|actions| request_actions(&existing_automation.uuid, actions));
+  if let Err(Error::KSError(message)) = validate_automation(
+    &steps,
+    &cadences,
+    data_sources.as_deref().unwrap_or_default(),
+    actions.as_deref().unwrap_or_default(),
+  ) {
+    response.error = Some(message);
+    return Ok(HttpResponse::BadRequest().json(response));
+  }
 
-  // let mut cadences: Vec<CadenceTrigger> = vec![];
-  // for data_cadence in data.cadences.clone() {
-  //   let cadence = CadenceTrigger {
-  //     id: None,
-  //     automation_uuid: existing_automation.uuid.clone(),
-  //     cadence_type: data_cadence.cadence_type,
-  //     day_of_week: data_cadence.day_of_week,
-  //     time: data_cadence.time,
-  //   };
-  //   cadences.push(cadence);
-  // }
-
-  // let mut steps: Vec<AutomationStep> = vec![];
-  // for (i, data_step) in data.steps.iter().enumerate() {
-  //   let step = AutomationStep {
-  //     id: None,
-  //     automation_uuid: existing_automation.uuid.clone(),
-  //     name: data_step.name.clone(),
-  //     ordering: i as u64,
-  //     args_json: data_step.args_json.clone(),
-  //   };
-  //   steps.push(step);
-  // }
-
-  // let is_active = match data.is_active {
-  //   Some(ia) => ia,
-  //   None => existing_automation.is_active.clone(),
-  // };
-  // let is_beta = match data.is_beta {
-  //   Some(ia) => ia,
-  //   None => existing_automation.is_beta.clone(),
-  // };
-  // let mut automation = Automation {
-  //   id: existing_automation.id.clone(),
-  //   uuid: existing_automation.uuid.clone(),
-  //   name: data.name.clone(),
-  //   description: data.description.clone(),
-  //   is_active,
-  //   is_beta,
-  //   runs: None,
-  //   trigger_cadences: Some(cadences),
-  //   trigger_data_sources: None,
-  //   steps: Some(steps),
-  //   // feedbacks: None,
-  // };
-
-  // automation.update();
-  // response.success = true;
+  let mut automation = Automation {
+    name: data.name.clone(),
+    description: data.description.clone(),
+    is_active: data.is_active.unwrap_or(existing_automation.is_active),
+    is_beta: data.is_beta.unwrap_or(existing_automation.is_beta),
+    trigger_cadences: Some(cadences),
+    trigger_data_sources: data_sources,
+    steps: Some(steps),
+    actions,
+    ..existing_automation
+  };
+  match run_blocking(move || automation.update()).await {
+    Ok(()) => response.success = true,
+    Err(error) => {
+      log::error!("Failed to update automation: {:?}", error);
+      response.error = Some(format!("Failed to update automation: {:?}", error));
+      return Ok(HttpResponse::InternalServerError().json(response));
+    }
+  }
   Ok(HttpResponse::Ok().json(response))
 }
 
@@ -505,12 +699,77 @@ async fn update_automation(
  * Todo: Receive automation id as param and check if its specifics data_source have enough data
  */
 
+#[get("/api/knapsack/automations/export")]
+async fn export_automations(
+  query: web::Query<ExportAutomationsQuery>,
+) -> Result<HttpResponse, ActixError> {
+  let ExportAutomationsQuery { uuid, format } = query.into_inner();
+  let format = format.unwrap_or(BundleFormat::Json);
+  match run_blocking(move || AutomationBundle::export(uuid.as_deref())?.to_text(format)).await {
+    Ok(text) => Ok(
+      HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+          "Content-Disposition",
+          format!("attachment; filename=\"automations.{}\"", format.extension()),
+        ))
+        .body(text),
+    ),
+    Err(Error::KSError(message)) => Ok(HttpResponse::NotFound().json(json!({
+      "success": false,
+      "error": message,
+    }))),
+    Err(error) => {
+      log::error!("Failed to export automations: {:?}", error);
+      Ok(HttpResponse::InternalServerError().json(json!({
+        "success": false,
+        "error": format!("Failed to export automations: {:?}", error),
+      })))
+    }
+  }
+}
+
+/// Takes a JSON or YAML bundle as the request body.
+#[post("/api/knapsack/automations/import")]
+async fn import_automations(
+  query: web::Query<ImportAutomationsQuery>,
+  body: String,
+) -> Result<HttpResponse, ActixError> {
+  let strategy = query.conflict.unwrap_or_default();
+  let result = run_blocking(move || AutomationBundle::parse(&body)?.import(strategy)).await;
+  match result {
+    Ok(imported) => Ok(HttpResponse::Ok().json(ImportAutomationsResponse {
+      data: imported,
+      success: true,
+      error: None,
+    })),
+    Err(Error::KSError(message)) => Ok(HttpResponse::BadRequest().json(ImportAutomationsResponse {
+      data: vec![],
+      success: false,
+      error: Some(message),
+    })),
+    Err(error) => {
+      log::error!("Failed to import automations: {:?}", error);
+      Ok(HttpResponse::InternalServerError().json(ImportAutomationsResponse {
+        data: vec![],
+        success: false,
+        error: Some(format!("Failed to import automations: {:?}", error)),
+      }))
+    }
+  }
+}
+
 #[get("/api/knapsack/automations/start_check")]
 async fn start_check() -> Result<HttpResponse, ActixError> {
-  let calendar_count = CalendarEvent::count().unwrap();
-  let emails_count = Email::count().unwrap();
+  let counts = run_blocking(|| Ok((CalendarEvent::count()?, Email::count()?))).await;
   let response = StartCheckResponse {
-    success: calendar_count > 0 && emails_count > 0,
+    success: match counts {
+      Ok((calendar_count, emails_count)) => calendar_count > 0 && emails_count > 0,
+      Err(error) => {
+        log::error!("Failed to count synced data: {:?}", error);
+        false
+      }
+    },
   };
   Ok(HttpResponse::Ok().json(response))
 }
@@ -518,7 +777,8 @@ async fn start_check() -> Result<HttpResponse, ActixError> {
 #[get("/api/knapsack/automations/feedbacks")]
 async fn get_feedbacks(request: HttpRequest) -> Result<HttpResponse, ActixError> {
   let params = actix_web::web::Query::<GetUserMessagesRequest>::from_query(request.query_string())?;
-  let user = match User::find_by_email(params.email.clone()) {
+  let email = params.email.clone();
+  let user = match run_blocking(move || User::find_by_email(email)).await {
     Ok(user) => user,
     Err(error) => {
       log::error!("Failed to get user: {:?}", error);
@@ -529,7 +789,8 @@ async fn get_feedbacks(request: HttpRequest) -> Result<HttpResponse, ActixError>
       }));
     }
   };
-  let messages = match MessageFeedback::find_by_user_id(user.id.unwrap()) {
+  let user_id = user.id.unwrap();
+  let messages = match run_blocking(move || MessageFeedback::find_by_user_id(user_id)).await {
     Ok(messages) => messages,
     Err(error) => {
       log::error!("Failed to get feedback messages: {:?}", error);
@@ -555,15 +816,32 @@ async fn upsert_automations_feedback(
     success: false,
     error: None,
   };
-  let user = User::find_by_email(data.user_email.clone()).unwrap();
-
-  let maybe_automation = Automation::find_by_message_id(data.message_id.clone()).unwrap();
+  let user_email = data.user_email.clone();
+  let message_id = data.message_id;
+  let lookup = run_blocking(move || {
+    let user = User::find_by_email(user_email)?;
+    let automation = Automation::find_by_message_id(message_id)?;
+    Ok((user, automation))
+  })
+  .await;
+  let (user, automation) = match lookup {
+    Ok((user, Some(automation))) => (user, automation),
+    Ok((_, None)) => {
+      response.error = Some("Automation not found for message.".to_string());
+      return Ok(HttpResponse::NotFound().json(response));
+    }
+    Err(error) => {
+      log::error!("Failed to look up feedback automation: {:?}", error);
+      response.error = Some(format!("Failed to look up feedback automation: {:?}", error));
+      return Ok(HttpResponse::NotFound().json(response));
+    }
+  };
 
   let api_server: &'static str = env!("VITE_KN_API_SERVER", "Missing VITE_KN_API_SERVER env var");
   let api_endpoint = format!("{}/api/automations/feedbacks", api_server);
 
   let feedback_data = MessageFeedbacksServer {
-    automation_name: maybe_automation.unwrap().name.clone(),
+    automation_name: automation.name.clone(),
     user_email: data.user_email.clone(),
     message_id: data.message_id.clone(),
     feedback: data.feedback.clone(),
@@ -572,7 +850,15 @@ async fn upsert_automations_feedback(
   let client = Client::new();
   let _request_result = client.post(&api_endpoint).json(&feedback_data).send().await;
 
-  let _upsert_result = MessageFeedback::upsert(user.id.unwrap(), data.message_id, data.feedback);
+  let user_id = user.id.unwrap();
+  let feedback = data.feedback;
+  if let Err(error) =
+    run_blocking(move || MessageFeedback::upsert(user_id, message_id, feedback)).await
+  {
+    log::error!("Failed to save feedback: {:?}", error);
+    response.error = Some(format!("Failed to save feedback: {:?}", error));
+    return Ok(HttpResponse::InternalServerError().json(response));
+  }
   response.success = true;
 
   Ok(HttpResponse::Ok().json(response))
@@ -580,10 +866,7 @@ async fn upsert_automations_feedback(
 
 // fn schedule_future_run_hourly(current_date: )
 
-const ONE_HOUR: TimeDelta = Duration::hours(1);
-const ONE_DAY: TimeDelta = Duration::days(1);
 const ONE_WEEK: TimeDelta = Duration::days(7);
-const DAYS_TO_SCHEDULE: u32 = 7;
 
 fn create_feed_item_to_schedule_run(
   mut automation_run: AutomationRun,
@@ -609,147 +892,16 @@ fn create_feed_item_to_schedule_run(
   automation_run
 }
 
-fn schedule_cadence_future_run_hourly(
-  automation_uuid: &String,
-  user_id: u64,
-  date: &DateTime<Local>,
-) {
-  let mut current_date = date.clone();
-  let hours = 24 * DAYS_TO_SCHEDULE;
-  for _ in 1..hours {
-    current_date = current_date + ONE_HOUR;
-    let mut automation_run = AutomationRun {
-      id: None,
-      user_id,
-      automation_uuid: automation_uuid.to_string(),
-      thread_id: None,
-      schedule_timestamp: Some(current_date.timestamp_millis()),
-      execution_timestamp: None,
-      run_params: Some(json!({ "timestamp": current_date.timestamp_millis() }).to_string()),
-      feed_item_id: None,
-    };
-    let automation = Automation::find_by_uuid(automation_uuid.clone()).unwrap();
-    automation_run = create_feed_item_to_schedule_run(
-      automation_run,
-      automation.name.clone(),
-      current_date.timestamp_millis(),
-    );
-    automation_run.upsert_schedule();
-  }
-}
-
-fn schedule_cadence_future_run_daily(
-  automation_uuid: &String,
-  user_id: u64,
-  date: &DateTime<Local>,
-) {
-  let mut current_date = date.clone();
-  let now_timestamp = chrono::offset::Local::now().timestamp();
-  for _ in 1..DAYS_TO_SCHEDULE {
-    if current_date.timestamp() > now_timestamp {
-      let mut automation_run = AutomationRun {
-        id: None,
-        user_id,
-        automation_uuid: automation_uuid.to_string(),
-        thread_id: None,
-        schedule_timestamp: Some(current_date.timestamp_millis()),
-        execution_timestamp: None,
-        run_params: Some(json!({ "timestamp": current_date.timestamp_millis() }).to_string()),
-        feed_item_id: None,
-      };
-      let automation = Automation::find_by_uuid(automation_uuid.clone()).unwrap();
-      automation_run = create_feed_item_to_schedule_run(
-        automation_run,
-        automation.name.clone(),
-        current_date.timestamp_millis(),
-      );
-      automation_run.upsert_schedule();
-    }
-    current_date = current_date + ONE_DAY;
-  }
-}
 
-fn schedule_cadence_future_run_weekly(
-  automation_uuid: &String,
-  user_id: u64,
-  date: &DateTime<Local>,
-  cadence_day_of_week: Option<String>,
-) {
-  let mut current_date = date.clone();
-  let now = chrono::offset::Local::now();
-  let cadence_weekday = match cadence_day_of_week
-    .unwrap_or(String::from("Monday"))
-    .as_str()
-  {
-    "Sunday" => 0,
-    "Monday" => 1,
-    "Tuesday" => 2,
-    "Wednesday" => 3,
-    "Thursday" => 4,
-    "Friday" => 5,
-    "Saturday" => 6,
-    _ => 1,
-  };
-  while current_date.weekday().num_days_from_sunday() != cadence_weekday {
-    current_date = current_date + ONE_DAY;
-  }
-  if current_date > now {
-    let mut automation_run = AutomationRun {
-      id: None,
-      user_id,
-      automation_uuid: automation_uuid.to_string(),
-      thread_id: None,
-      schedule_timestamp: Some(current_date.timestamp_millis()),
-      execution_timestamp: None,
-      run_params: Some(json!({ "timestamp": current_date.timestamp_millis() }).to_string()),
-      feed_item_id: None,
-    };
-    let automation = Automation::find_by_uuid(automation_uuid.clone()).unwrap();
-    automation_run = create_feed_item_to_schedule_run(
-      automation_run,
-      automation.name.clone(),
-      current_date.timestamp_millis(),
-    );
-    automation_run.upsert_schedule();
-  }
-}
-
-fn schedule_cadence_future_run(cadence: &CadenceTrigger, automation_uuid: &String, user_id: u64) {
-  let now = chrono::offset::Local::now();
-  let mut current_date = now
-    .with_minute(0)
-    .unwrap()
-    .with_second(0)
-    .unwrap()
-    .with_nanosecond(0)
-    .unwrap();
-  if let Some(time) = &cadence.time {
-    let splitted_time = time.split(":").collect::<Vec<_>>();
-    let hour: u32 = splitted_time.get(0).unwrap().parse().unwrap();
-    let minute: u32 = splitted_time.get(1).unwrap().parse().unwrap();
-    current_date = current_date
-      .with_hour(hour)
-      .unwrap()
-      .with_minute(minute)
-      .unwrap();
-  }
-  match cadence.cadence_type.as_str() {
-    "hourly" => schedule_cadence_future_run_hourly(automation_uuid, user_id, &current_date),
-    "daily" => schedule_cadence_future_run_daily(automation_uuid, user_id, &current_date),
-    "weekly" => schedule_cadence_future_run_weekly(
-      automation_uuid,
-      user_id,
-      &current_date,
-      cadence.day_of_week.clone(),
-    ),
-    _ => log::error!("cadence_type not implement"),
-  }
-}
-
-fn schedule_data_source_future_run_google_calendar(
+/// Schedules runs around the upcoming events of the calendars of `provider`
+/// that pass the trigger's guard, `DEFAULT_CALENDAR_GUARD` unless set.
+/// Events of accounts without a Microsoft connection, including those synced
+/// before accounts were tracked, count as Google events.
+fn schedule_data_source_future_run_calendar(
   data_source: &DataSourceTrigger,
   automation_uuid: &str,
   user_id: u64,
+  provider: &str,
 ) -> Result<(), Error> {
   let now = chrono::offset::Local::now()
     .with_hour(0)
@@ -760,12 +912,15 @@ fn schedule_data_source_future_run_google_calendar(
   let calendar_events =
     CalendarEvent::filter_calendar_events_by_timestamp(now.timestamp(), to_date.timestamp())?;
 
+  let guard = TriggerFilter::parse(data_source)?.guard_or(Some(DEFAULT_CALENDAR_GUARD))?;
+  let user_email = User::find_by_id(user_id)?
+    .map(|user| user.email)
+    .unwrap_or_default();
   let valid_events: Vec<&CalendarEvent> = calendar_events
     .iter()
-    .filter(|event| {
-      event.attendees_json.as_ref()
-        .map(|attendees| attendees.split(',').count() > 1)
-        .unwrap_or(false)
+    .filter(|event| match &guard {
+      Some(guard) => guard.matches(&calendar_event_context(event, &user_email)),
+      None => true,
     })
     .collect();
 
@@ -774,13 +929,23 @@ fn schedule_data_source_future_run_google_calendar(
     .filter_map(|event| event.id)
     .collect();
 
-  if let Err(e) = AutomationRun::delete_outdated_calendar_runs(&valid_event_ids, now.timestamp_millis()) {
+  if let Err(e) = AutomationRun::delete_outdated_calendar_runs(automation_uuid, &valid_event_ids, now.timestamp_millis()) {
     log::error!("Failed to delete outdated calendar runs: {:?}", e);
     let err_msg = "Failed to delete outdated calendar runs".to_string();
     knap_log_error(err_msg, Some(e), None);
   }
 
-  for event in valid_events {
+  let microsoft_account_ids = User::find_ids_by_provider(MICROSOFT_PROVIDER_NAME)?;
+  let is_microsoft_event = |event: &CalendarEvent| {
+    event
+      .account_id
+      .map_or(false, |account_id| microsoft_account_ids.contains(&account_id))
+  };
+  let provider_events = valid_events
+    .into_iter()
+    .filter(|event| is_microsoft_event(*event) == (provider == MICROSOFT_PROVIDER_NAME));
+
+  for event in provider_events {
     let event_id = match event.id {
       Some(id) => id,
       None => {
@@ -842,6 +1007,13 @@ fn schedule_data_source_future_run_google_calendar(
             json!({ "event_id": event_id, "timestamp": run_timestamp }).to_string(),
           ),
           feed_item_id: None,
+          status: Some(AutomationRunStatus::Scheduled),
+          error: None,
+          step_results: None,
+          run_key: None,
+          finished_timestamp: None,
+          attempts: 0,
+          action_results: None,
         }
       },
       Err(e) => {
@@ -873,12 +1045,20 @@ fn schedule_data_source_future_run(
   user_id: u64,
 ) -> Result<(), Error> {
   match data_source.data_source.as_str() {
-    "google_calendar" => {
-      schedule_data_source_future_run_google_calendar(data_source, automation_uuid, user_id)?
-    }
-    _ => {
-      log::error!("data_source not implement")
-    }
+    GOOGLE_CALENDAR => schedule_data_source_future_run_calendar(
+      data_source,
+      automation_uuid,
+      user_id,
+      GOOGLE_PROVIDER_NAME,
+    )?,
+    MICROSOFT_CALENDAR => schedule_data_source_future_run_calendar(
+      data_source,
+      automation_uuid,
+      user_id,
+      MICROSOFT_PROVIDER_NAME,
+    )?,
+    // Event triggers fire from sync, recordings and the executor sweep.
+    _ => {}
   };
   Ok(())
 }
@@ -899,14 +1079,30 @@ async fn schedule_automation_runs(
       );
     }
   };
-  let automations = Automation::find_all();
-
-  for automation in automations {
-    let cadences = automation.trigger_cadences.unwrap_or(vec![]);
-    for cadence in cadences {
-      schedule_cadence_future_run(&cadence, &automation.uuid, user_id);
+  let automations = match run_blocking(Automation::find_all).await {
+    Ok(automations) => automations,
+    Err(error) => {
+      log::error!("Failed to get automations: {:?}", error);
+      return Ok(
+        HttpResponse::InternalServerError().json(ScheduleAutomationRunsResponse {
+          success: false,
+          error: Some(format!("Failed to get automations: {:?}", error)),
+        }),
+      );
     }
+  };
+
+  let now = Utc::now().timestamp_millis();
+  if let Err(e) = run_blocking(move || {
+    sync_schedules(user_id, now)?;
+    materialize_due_runs(now)
+  })
+  .await
+  {
+    knap_log_error("Couldn't schedule cadence runs".to_string(), Some(e), None);
+  }
 
+  for automation in automations {
     let data_sources = automation.trigger_data_sources.unwrap_or(vec![]);
     for data_source in data_sources {
       if let Err(e) = schedule_data_source_future_run(&data_source, &automation.uuid, user_id) {
@@ -923,15 +1119,291 @@ async fn schedule_automation_runs(
 }
 
 #[get("/api/knapsack/automations/runs/")]
-async fn get_automation_runs() -> Result<HttpResponse, ActixError> {
-  let automations = AutomationRun::find_all();
-  Ok(HttpResponse::Ok().json(ListAutomationRunsResponse {
-    data: automations,
+async fn get_automation_runs(
+  query: web::Query<ListAutomationRunsQuery>,
+) -> Result<HttpResponse, ActixError> {
+  let query = query.into_inner();
+  let status = match query.status.as_deref() {
+    Some(status) => match AutomationRunStatus::from_str(status) {
+      Some(status) => Some(status),
+      None => {
+        return Ok(HttpResponse::BadRequest().json(ListAutomationRunsResponse {
+          data: vec![],
+          total: 0,
+          success: false,
+          error: Some(format!("Unknown run status {}", status)),
+        }));
+      }
+    },
+    None => None,
+  };
+  let filter = AutomationRunFilter {
+    run_id: None,
+    automation_uuid: query.automation_uuid,
+    status,
+    since: query.since,
+    until: query.until,
+    limit: query.limit.unwrap_or(DEFAULT_RUNS_PAGE_SIZE).clamp(1, MAX_RUNS_PAGE_SIZE),
+    offset: query.offset.unwrap_or(0),
+  };
+  match run_blocking(move || AutomationRun::find_page(&filter)).await {
+    Ok((runs, total)) => Ok(HttpResponse::Ok().json(ListAutomationRunsResponse {
+      data: runs,
+      total,
+      success: true,
+      error: None,
+    })),
+    Err(error) => {
+      log::error!("Failed to get automation runs: {:?}", error);
+      Ok(HttpResponse::InternalServerError().json(ListAutomationRunsResponse {
+        data: vec![],
+        total: 0,
+        success: false,
+        error: Some(format!("Failed to get automation runs: {:?}", error)),
+      }))
+    }
+  }
+}
+
+fn find_run_record(run_id: u64) -> Result<Option<AutomationRunRecord>, Error> {
+  let filter = AutomationRunFilter {
+    run_id: Some(run_id),
+    limit: 1,
+    ..Default::default()
+  };
+  Ok(AutomationRun::find_page(&filter)?.0.into_iter().next())
+}
+
+fn run_response(status: actix_web::http::StatusCode, error: String) -> HttpResponse {
+  HttpResponse::build(status).json(AutomationRunResponse {
+    data: None,
+    success: false,
+    error: Some(error),
+  })
+}
+
+#[get("/api/knapsack/automations/runs/{run_id}")]
+async fn get_automation_run(path: web::Path<u64>) -> Result<HttpResponse, ActixError> {
+  let run_id = path.into_inner();
+  match run_blocking(move || find_run_record(run_id)).await {
+    Ok(Some(record)) => Ok(HttpResponse::Ok().json(AutomationRunResponse {
+      data: Some(record),
+      success: true,
+      error: None,
+    })),
+    Ok(None) => Ok(run_response(
+      actix_web::http::StatusCode::NOT_FOUND,
+      "Automation run not found".to_string(),
+    )),
+    Err(error) => {
+      log::error!("Failed to get automation run {}: {:?}", run_id, error);
+      Ok(run_response(
+        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to get automation run: {:?}", error),
+      ))
+    }
+  }
+}
+
+/// Outcome of putting a run (back) in line from the API.
+enum QueuedRun {
+  Queued(AutomationRun, bool),
+  NotFound(&'static str),
+  Conflict(&'static str),
+}
+
+/// Executes a queued run right away when the backend runs its steps; runs
+/// the app executes are picked up from the feed instead.
+fn execute_queued_run(
+  run: AutomationRun,
+  in_backend: bool,
+  semantic_service: &Arc<Mutex<Option<SemanticService>>>,
+  handle: &Handle,
+) -> Result<HttpResponse, ActixError> {
+  let run_id = match run.id {
+    Some(run_id) => run_id,
+    None => {
+      return Ok(run_response(
+        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        "Run was not stored".to_string(),
+      ))
+    }
+  };
+  if in_backend {
+    handle.spawn(execute_now(run, semantic_service.clone()));
+  }
+  let record = find_run_record(run_id).ok().flatten();
+  Ok(HttpResponse::Ok().json(AutomationRunResponse {
+    data: record,
     success: true,
     error: None,
   }))
 }
 
+fn queued_run_response(
+  queued: Result<QueuedRun, Error>,
+  semantic_service: &Arc<Mutex<Option<SemanticService>>>,
+  handle: &Handle,
+) -> Result<HttpResponse, ActixError> {
+  match queued {
+    Ok(QueuedRun::Queued(run, in_backend)) => {
+      execute_queued_run(run, in_backend, semantic_service, handle)
+    }
+    Ok(QueuedRun::NotFound(error)) => Ok(run_response(
+      actix_web::http::StatusCode::NOT_FOUND,
+      error.to_string(),
+    )),
+    Ok(QueuedRun::Conflict(error)) => Ok(run_response(
+      actix_web::http::StatusCode::CONFLICT,
+      error.to_string(),
+    )),
+    Err(error) => {
+      log::error!("Failed to queue automation run: {:?}", error);
+      Ok(run_response(
+        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to queue automation run: {:?}", error),
+      ))
+    }
+  }
+}
+
+#[post("/api/knapsack/automations/runs/{run_id}/retry")]
+async fn retry_automation_run(
+  path: web::Path<u64>,
+  semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
+  handle: Data<Arc<Handle>>,
+) -> Result<HttpResponse, ActixError> {
+  let run_id = path.into_inner();
+  let queued = run_blocking(move || {
+    let mut run = match AutomationRun::find_by_id(run_id)? {
+      Some(run) => run,
+      None => return Ok(QueuedRun::NotFound("Automation run not found")),
+    };
+    if !run.reschedule(Utc::now().timestamp_millis())? {
+      return Ok(QueuedRun::Conflict("Only failed or skipped runs can be retried"));
+    }
+    let in_backend = runs_in_backend(&AutomationStep::find_by_automation_uuid(&run.automation_uuid)?);
+    Ok(QueuedRun::Queued(run, in_backend))
+  })
+  .await;
+  queued_run_response(queued, semantic_service.get_ref(), handle.get_ref())
+}
+
+/// The user with `user_email`, or the signed-in user.
+fn request_user_id(user_email: Option<String>) -> Option<u64> {
+  match user_email {
+    Some(email) => User::find_by_email(email).ok().and_then(|user| user.id),
+    None => User::find_active().and_then(|user| user.id),
+  }
+}
+
+#[post("/api/knapsack/automations/{automation_id}/run")]
+async fn run_automation_now(
+  path: web::Path<String>,
+  data: Option<Json<RunAutomationRequest>>,
+  semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
+  handle: Data<Arc<Handle>>,
+) -> Result<HttpResponse, ActixError> {
+  let automation_id = path.into_inner();
+  let request = data.map(|data| data.into_inner()).unwrap_or_default();
+  let queued = run_blocking(move || {
+    let automation = match Automation::find_by_id(automation_id)? {
+      Some(automation) => automation,
+      None => return Ok(QueuedRun::NotFound("Automation not found")),
+    };
+    let user_id = match request_user_id(request.user_email) {
+      Some(user_id) => user_id,
+      None => return Ok(QueuedRun::NotFound("User not found")),
+    };
+    let now = Utc::now().timestamp_millis();
+    let mut run_params = request.run_params.unwrap_or_else(|| json!({}));
+    match run_params.as_object_mut() {
+      Some(params) => {
+        params.entry("timestamp").or_insert(json!(now));
+      }
+      None => return Ok(QueuedRun::Conflict("run_params must be an object")),
+    }
+    let mut run = AutomationRun {
+      id: None,
+      automation_uuid: automation.uuid.clone(),
+      user_id,
+      thread_id: None,
+      schedule_timestamp: Some(now),
+      execution_timestamp: None,
+      run_params: Some(run_params.to_string()),
+      feed_item_id: None,
+      status: Some(AutomationRunStatus::Scheduled),
+      error: None,
+      step_results: None,
+      run_key: Some(format!("{}@manual:{}", automation.uuid, now)),
+      finished_timestamp: None,
+      attempts: 0,
+      action_results: None,
+    };
+    if !store_run(&mut run)? {
+      return Ok(QueuedRun::Conflict("A run of this automation was just started"));
+    }
+    let in_backend = runs_in_backend(&AutomationStep::find_by_automation_uuid(&automation.uuid)?);
+    Ok(QueuedRun::Queued(run, in_backend))
+  })
+  .await;
+  queued_run_response(queued, semantic_service.get_ref(), handle.get_ref())
+}
+
+/// Runs the automation against current data without storing or posting
+/// anything.
+#[post("/api/knapsack/automations/{uuid}/preview")]
+async fn preview_automation(
+  path: web::Path<String>,
+  data: Option<Json<RunAutomationRequest>>,
+  semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
+) -> Result<HttpResponse, ActixError> {
+  let automation_uuid = path.into_inner();
+  let request = data.map(|data| data.into_inner()).unwrap_or_default();
+  let user_email = request.user_email;
+  let user_id = match run_blocking(move || Ok(request_user_id(user_email))).await {
+    Ok(Some(user_id)) => user_id,
+    _ => {
+      return Ok(HttpResponse::NotFound().json(json!({
+        "success": false,
+        "error": "User not found",
+      })));
+    }
+  };
+  let run_params = match request.run_params.unwrap_or_else(|| json!({})) {
+    serde_json::Value::Object(run_params) => run_params,
+    _ => {
+      return Ok(HttpResponse::BadRequest().json(json!({
+        "success": false,
+        "error": "run_params must be an object",
+      })));
+    }
+  };
+  match preview(automation_uuid, user_id, run_params, semantic_service.get_ref().clone()).await {
+    Ok(preview) => Ok(HttpResponse::Ok().json(json!({
+      "success": true,
+      "data": preview,
+    }))),
+    Err(Error::RusqliteError(rusqlite::Error::QueryReturnedNoRows)) => {
+      Ok(HttpResponse::NotFound().json(json!({
+        "success": false,
+        "error": "Automation not found",
+      })))
+    }
+    Err(Error::KSError(message)) => Ok(HttpResponse::BadRequest().json(json!({
+      "success": false,
+      "error": message,
+    }))),
+    Err(error) => {
+      log::error!("Failed to preview automation: {:?}", error);
+      Ok(HttpResponse::InternalServerError().json(json!({
+        "success": false,
+        "error": format!("Failed to preview automation: {:?}", error),
+      })))
+    }
+  }
+}
+
 #[put("/api/knapsack/threads/{thread_id}")]
 async fn update_thread(
   path: web::Path<String>,
@@ -940,7 +1412,7 @@ async fn update_thread(
   let result: Result<u64, _> = path.into_inner().parse();
   match result {
     Ok(thread_id) => {
-      let mut existing_thread = match Thread::find_by_id(thread_id) {
+      let mut existing_thread = match run_blocking(move || Thread::find_by_id(thread_id)).await {
         Ok(Some(thread)) => thread,
         Ok(None) => {
           return Ok(HttpResponse::NotFound().json(StandardResponse {
@@ -967,7 +1439,11 @@ async fn update_thread(
       existing_thread.title = data.thread.title.clone();
       existing_thread.subtitle = data.thread.subtitle.clone();
       existing_thread.prompt_template = data.thread.prompt_template.clone();
-      existing_thread.update();
+      if let Err(e) = run_blocking(move || existing_thread.update()).await {
+        response.error_code = Some("DATABASE_ERROR".to_string());
+        response.message = Some(format!("Error updating thread: {:?}", e));
+        return Ok(HttpResponse::InternalServerError().json(response));
+      }
 
       response.success = true;
       Ok(HttpResponse::Ok().json(response))
@@ -988,8 +1464,11 @@ async fn update_thread(
 async fn create_system_message(
   data: Json<CreateSystemMessageRequest>,
 ) -> Result<HttpResponse, ActixError> {
+  let thread_id = data.thread_id;
   let mut thread_with_messages: ThreadWithMessages =
-    Thread::find_by_id_with_messages(data.thread_id).unwrap_or_default();
+    run_blocking(move || Thread::find_by_id_with_messages(thread_id))
+      .await
+      .unwrap_or_default();
   if thread_with_messages.thread.id.is_none() {
     return Ok(
       HttpResponse::BadRequest().json(CreateSystemMessageResponse {
@@ -1012,9 +1491,14 @@ async fn create_system_message(
     document_ids: data.document_ids.clone(),
   };
 
-  match bot_message.create() {
-    Ok(_) => {
-      thread_with_messages.messages.push(bot_message.clone());
+  let created = run_blocking(move || {
+    bot_message.create()?;
+    Ok(bot_message)
+  })
+  .await;
+  match created {
+    Ok(bot_message) => {
+      thread_with_messages.messages.push(bot_message);
     }
     Err(error) => {
       log::error!("Failed to create message: {:?}", error);
@@ -1052,8 +1536,13 @@ async fn create_threads(data: Json<CreateThreadRequest>) -> Result<HttpResponse,
     prompt_template: None,
   };
 
-  match thread.create() {
-    Ok(_) => (),
+  let created = run_blocking(move || {
+    thread.create()?;
+    Ok(thread)
+  })
+  .await;
+  let thread = match created {
+    Ok(thread) => thread,
     Err(error) => {
       log::error!("Failed to create thread: {:?}", error);
       return Ok(
@@ -1064,7 +1553,7 @@ async fn create_threads(data: Json<CreateThreadRequest>) -> Result<HttpResponse,
         }),
       );
     }
-  }
+  };
   let response = CreateThreadResponse {
     error: None,
     success: true,
@@ -1076,183 +1565,169 @@ async fn create_threads(data: Json<CreateThreadRequest>) -> Result<HttpResponse,
 #[post("/api/knapsack/automations/runs")]
 async fn create_automation_run(
   data: Json<CreateAutomationRunRequest>,
-  semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
+  _semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
 ) -> Result<HttpResponse, ActixError> {
-  let mut response = CreateAutomationRunResponse {
-    success: false,
-    error: None,
-    feed_item: None,
+  let data = data.into_inner();
+  let text = data.result.clone();
+  let user_email = data.user_email.clone();
+  let recorded = run_blocking(move || Ok(record_reported_run(data)))
+    .await
+    .unwrap_or_else(|error| {
+      Err((
+        actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to record automation run: {:?}", error),
+      ))
+    });
+  match recorded {
+    Ok((feed_item, run)) => {
+      // Actions may wait on slow webhooks or mail APIs; the app only needs
+      // to know the run was recorded.
+      tokio::spawn(deliver_reported_run(run, text, user_email));
+      Ok(HttpResponse::Ok().json(CreateAutomationRunResponse {
+        success: true,
+        error: None,
+        feed_item: Some(feed_item),
+      }))
+    }
+    Err((status, error)) => Ok(HttpResponse::build(status).json(CreateAutomationRunResponse {
+      success: false,
+      error: Some(error),
+      feed_item: None,
+    })),
+  }
+}
+
+/// Hands the result of a run the app executed to the automation's output
+/// actions and stores how each went. A run reported again is not delivered
+/// twice.
+async fn deliver_reported_run(run: AutomationRun, text: String, user_email: String) {
+  if text.trim().is_empty() || run.action_results.is_some() {
+    return;
+  }
+  let mut claimed_run = run.clone();
+  let loaded = run_blocking(move || {
+    let actions = AutomationAction::find_by_automation_uuid(&claimed_run.automation_uuid)?;
+    if actions.is_empty() {
+      return Ok(None);
+    }
+    let automation = Automation::find_by_uuid(claimed_run.automation_uuid.clone())?;
+    if !claimed_run.claim_delivery()? {
+      return Ok(None);
+    }
+    Ok(Some((actions, automation.name, claimed_run)))
+  })
+  .await;
+  let (actions, automation_name, mut run) = match loaded {
+    Ok(Some(loaded)) => loaded,
+    Ok(None) => return,
+    Err(error) => {
+      log::error!("Failed to load the actions of automation {} - {:?}", run.automation_uuid, error);
+      return;
+    }
+  };
+  let output = RunOutput {
+    automation_name,
+    user_email,
+    text,
+    timestamp_millis: run.schedule_timestamp.unwrap_or_else(|| Utc::now().timestamp_millis()),
   };
-  let user = User::find_by_email(data.user_email.clone()).unwrap();
+  run.action_results = Some(run_actions(&actions, &output).await);
+  if let Err(error) = run_blocking(move || run.save_action_results()).await {
+    log::error!("Failed to save automation action results - {:?}", error);
+  }
+}
+
+/// Stores a run the app executed itself: its thread, run row and result
+/// message. Returns the refreshed feed item and the run, or the status and
+/// error to respond with.
+fn record_reported_run(
+  data: CreateAutomationRunRequest,
+) -> Result<(FeedItemComplete, AutomationRun), (actix_web::http::StatusCode, String)> {
+  use actix_web::http::StatusCode;
 
-  let feed_item = data
+  let user = User::find_by_email(data.user_email.clone())
+    .map_err(|_| (StatusCode::BAD_REQUEST, String::from("User not found")))?;
+
+  let feed_item_id = data
     .feed_item_id
-    .ok_or_else(|| {
-      HttpResponse::BadRequest().json(CreateAutomationRunResponse {
-        success: false,
-        error: Some(String::from("feed_item_id is required")),
-        feed_item: None,
-      })
-    })
-    .and_then(|id| {
-      FeedItem::find_by_id(id).map_err(|_| {
-        HttpResponse::NotFound().json(CreateAutomationRunResponse {
-          success: false,
-          error: Some(String::from("Failed to get feed item")),
-          feed_item: None,
-        })
-      })
-    })
-    .and_then(|maybe_item| {
-      maybe_item.ok_or_else(|| {
-        HttpResponse::NotFound().json(CreateAutomationRunResponse {
-          success: false,
-          error: Some(String::from("Feed item not found")),
-          feed_item: None,
-        })
-      })
-    });
+    .ok_or_else(|| (StatusCode::BAD_REQUEST, String::from("feed_item_id is required")))?;
+  let feed_item = FeedItem::find_by_id(feed_item_id)
+    .map_err(|_| (StatusCode::NOT_FOUND, String::from("Failed to get feed item")))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, String::from("Feed item not found")))?;
 
-  let automation = Automation::find_by_uuid(data.automation_uuid.clone())
-    .map_err(|_| {
-      HttpResponse::InternalServerError().json(CreateAutomationRunResponse {
-        success: false,
-        error: Some(String::from("Failed to find thread")),
-        feed_item: None,
-      })
-    })
-    .and_then(|automation| Ok(automation));
-
-  let thread = data
-    .thread_id
-    .map(Thread::find_by_id)
-    .unwrap_or_else(|| Ok(None))
-    .map_err(|_| {
-      HttpResponse::InternalServerError().json(CreateAutomationRunResponse {
-        success: false,
-        error: Some(String::from("Failed to find thread")),
-        feed_item: None,
-      })
-    })
-    .and_then(|maybe_thread| {
-      Ok(maybe_thread.unwrap_or_else(|| Thread {
+  let automation = Automation::find_by_uuid(data.automation_uuid.clone()).ok();
+
+  let existing_thread = match data.thread_id {
+    Some(thread_id) => Thread::find_by_id(thread_id)
+      .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to find thread")))?,
+    None => None,
+  };
+  let thread = match existing_thread {
+    Some(thread) => thread,
+    None => {
+      let mut thread = Thread {
         id: None,
         timestamp: Some(data.execution_timestamp.clone()),
         hide_follow_up: Some(false),
         thread_type: ThreadType::Chat,
-        feed_item_id: feed_item.as_ref().ok().and_then(|fi| fi.id),
-        title: automation.as_ref().ok().and_then(|a| Some(a.name.clone())),
-        subtitle: feed_item.as_ref().ok().and_then(|fi| fi.title.clone()),
+        feed_item_id: feed_item.id,
+        title: automation.as_ref().map(|a| a.name.clone()),
+        subtitle: feed_item.title.clone(),
         recorded: Some(false),
         saved_transcript: None,
         prompt_template: None,
-      }))
-    })
-    .and_then(|mut thread| {
-      if thread.id.is_none() {
-        thread.create().map_err(|_| {
-          HttpResponse::InternalServerError().json(CreateAutomationRunResponse {
-            success: false,
-            error: Some(String::from("Failed to create new thread")),
-            feed_item: None,
-          })
-        })?;
-      }
-      Ok(thread)
-    });
+      };
+      thread.create().map_err(|_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to create new thread"))
+      })?;
+      thread
+    }
+  };
 
-  let automation_run = data
-    .automation_run_id
-    .map(AutomationRun::find_by_id)
-    .unwrap_or_else(|| Ok(None))
-    .map_err(|_| {
-      HttpResponse::InternalServerError().json(CreateAutomationRunResponse {
-        success: false,
-        error: Some(String::from("Failed to get AutomationRun")),
-        feed_item: None,
-      })
-    })
-    .and_then(|maybe_run| {
-      Ok(maybe_run.unwrap_or_else(|| AutomationRun {
+  let existing_run = match data.automation_run_id {
+    Some(run_id) => AutomationRun::find_by_id(run_id).map_err(|_| {
+      (StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to get AutomationRun"))
+    })?,
+    None => None,
+  };
+  let run = match existing_run {
+    Some(mut run) => {
+      run.thread_id = thread.id;
+      run.execution_timestamp = Some(data.execution_timestamp.clone());
+      run.status = Some(AutomationRunStatus::Succeeded);
+      run.finished_timestamp = Some(Utc::now().timestamp_millis());
+      run.attempts += 1;
+      run.update().map_err(|_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to update run"))
+      })?;
+      run
+    }
+    None => {
+      let mut run = AutomationRun {
         id: None,
         automation_uuid: data.automation_uuid.clone(),
         user_id: user.id.unwrap(),
-        thread_id: thread.as_ref().ok().and_then(|t| t.id),
+        thread_id: thread.id,
         schedule_timestamp: None,
         execution_timestamp: Some(data.execution_timestamp.clone()),
         run_params: None,
-        feed_item_id: feed_item.as_ref().ok().and_then(|fi| fi.id),
-      }))
-    })
-    .and_then(|mut run| {
-      if run.id.is_none() {
-        run.create().map_err(|_| {
-          HttpResponse::InternalServerError().json(CreateAutomationRunResponse {
-            success: false,
-            error: Some(String::from("Failed to create run")),
-            feed_item: None,
-          })
-        })?;
-      } else {
-        run.thread_id = thread.as_ref().ok().and_then(|t| t.id);
-        run.execution_timestamp = Some(data.execution_timestamp.clone());
-        run.update().map_err(|_| {
-          HttpResponse::InternalServerError().json(CreateAutomationRunResponse {
-            success: false,
-            error: Some(String::from("Failed to update run")),
-            feed_item: None,
-          })
-        })?;
-      }
-      Ok(run)
-    });
-
-  let (feed_item, thread, automation_run) = match (feed_item, thread, automation_run) {
-    (Ok(fi), Ok(t), Ok(ar)) => (fi, t, ar),
-    (Err(response), _, _) => return Ok(response),
-    (_, Err(response), _) => return Ok(response),
-    (_, _, Err(response)) => return Ok(response),
-  };
-
-  // TODO: code a better way than this for the
-  // Vec<u64> -> Vec<Document> conversion..
-  // Should live in the Document model class.
-  let documents: Vec<Document> = match &data.documents {
-    Some(docs) => {
-      let mut results = Vec::new();
-      for doc in docs {
-        let document = match Document::find_by_id(*doc) {
-          Ok(Some(d)) => d,
-          Ok(None) => continue,
-          Err(_) => continue,
-        };
-        results.push(document);
-      }
-      results
+        feed_item_id: feed_item.id,
+        status: Some(AutomationRunStatus::Succeeded),
+        error: None,
+        step_results: None,
+        run_key: None,
+        finished_timestamp: Some(Utc::now().timestamp_millis()),
+        attempts: 1,
+        action_results: None,
+      };
+      run.create().map_err(|_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to create run"))
+      })?;
+      run
     }
-    None => vec![],
   };
-  // let built_user_message = build_user_message(
-  //   data.user_prompt.clone(),
-  //   None,
-  //   Some(documents),
-  //   semantic_service.get_ref().clone(),
-  //   None,
-  // )
-  // .await;
 
   let now_timestamp = chrono::offset::Local::now().timestamp();
-  // let mut user_message = Message {
-  //   id: None,
-  //   timestamp: now_timestamp,
-  //   thread_id: thread.id.unwrap(),
-  //   user_id: user.id,
-  //   content: built_user_message.content.clone(),
-  //   content_facade: data.user_prompt_facade.clone(),
-  //   feedbacks: None,
-  //   document_ids: data.document_ids.clone(),
-  // };
-  // user_message.create();
   let mut bot_message = Message {
     id: None,
     timestamp: now_timestamp,
@@ -1263,17 +1738,22 @@ async fn create_automation_run(
     feedbacks: None,
     document_ids: data.document_ids.clone(),
   };
-  bot_message.create();
+  if let Err(error) = bot_message.create() {
+    log::error!("Failed to save automation result message: {:?}", error);
+  }
 
-  response.success = true;
-  let feed_item_response = FeedItem::find_by_id_complete(feed_item.id.unwrap()).unwrap();
-  response.feed_item = Some(feed_item_response);
-  Ok(HttpResponse::Ok().json(response))
+  let feed_item = FeedItem::find_by_id_complete(feed_item_id).map_err(|error| {
+    (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      format!("Failed to load feed item: {:?}", error),
+    )
+  })?;
+  Ok((feed_item, run))
 }
 
 #[get("/api/knapsack/feed_items")]
 async fn get_feed_items() -> Result<HttpResponse, ActixError> {
-  match FeedItem::find_all_complete() {
+  match run_blocking(FeedItem::find_all_complete).await {
     Ok(feed_items_complete) => {
       let response = GetFeedItemsResponse {
         error: None,
@@ -1302,8 +1782,13 @@ async fn create_feed_item(data: Json<CreateFeedItemRequest>) -> Result<HttpRespo
     deleted: None,
   };
 
-  match feed_item.create() {
-    Ok(_) => Ok(HttpResponse::Ok().json(CreateFeedItemResponse {
+  let created = run_blocking(move || {
+    feed_item.create()?;
+    Ok(feed_item)
+  })
+  .await;
+  match created {
+    Ok(feed_item) => Ok(HttpResponse::Ok().json(CreateFeedItemResponse {
       error: None,
       success: true,
       data: Some(feed_item),
@@ -1327,23 +1812,38 @@ async fn update_feed_item(
   let result: Result<u64, _> = path.into_inner().parse();
   match result {
     Ok(feed_item_id) => {
-      let mut existing_feed_item = FeedItem::find_by_id(feed_item_id)
-        .unwrap()
-        .expect("Feed Item not found.");
-      if let Some(title) = &data.feed_item.title {
-        existing_feed_item.title = Some(title.clone());
-      }
-      if let Some(deleted) = data.feed_item.deleted {
-        existing_feed_item.deleted = Some(deleted);
-      }
+      let title = data.feed_item.title.clone();
+      let deleted = data.feed_item.deleted;
+      let updated = run_blocking(move || {
+        let mut existing_feed_item = FeedItem::find_by_id(feed_item_id)?
+          .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        if let Some(title) = title {
+          existing_feed_item.title = Some(title);
+        }
+        if let Some(deleted) = deleted {
+          existing_feed_item.deleted = Some(deleted);
+        }
+        existing_feed_item.update()
+      })
+      .await;
 
-      if let Err(e) = existing_feed_item.update() {
-        log::error!("Failed to update feed item: {:?}", e);
-        return Ok(HttpResponse::InternalServerError().json(StandardResponse {
-          success: false,
-          error_code: Some("UPDATE_FEED_ITEM_ERROR".to_string()),
-          message: Some("Failed to update feed item".to_string()),
-        }));
+      match updated {
+        Ok(()) => {}
+        Err(Error::RusqliteError(rusqlite::Error::QueryReturnedNoRows)) => {
+          return Ok(HttpResponse::NotFound().json(StandardResponse {
+            success: false,
+            error_code: Some("UPDATE_FEED_ITEM_ERROR".to_string()),
+            message: Some("Feed item not found".to_string()),
+          }));
+        }
+        Err(e) => {
+          log::error!("Failed to update feed item: {:?}", e);
+          return Ok(HttpResponse::InternalServerError().json(StandardResponse {
+            success: false,
+            error_code: Some("UPDATE_FEED_ITEM_ERROR".to_string()),
+            message: Some("Failed to update feed item".to_string()),
+          }));
+        }
       }
 
       Ok(HttpResponse::Ok().json(StandardResponse {
@@ -1369,7 +1869,8 @@ async fn get_thread_transcript(req: HttpRequest) -> Result<HttpResponse, ActixEr
   let params =
     actix_web::web::Query::<GetTranscriptDataRequest>::from_query(req.query_string()).unwrap();
 
-  let thread = match Thread::find_by_id(params.id) {
+  let thread_id = params.id;
+  let thread = match run_blocking(move || Thread::find_by_id(thread_id)).await {
     Ok(Some(t)) => t,
     Ok(None) => {
       return Ok(HttpResponse::NotFound().json(StandardResponse {
@@ -1389,7 +1890,8 @@ async fn get_thread_transcript(req: HttpRequest) -> Result<HttpResponse, ActixEr
   };
 
   let mut feed_item = None;
-  match FeedItem::find_by_id(thread.feed_item_id.unwrap()) {
+  let feed_item_id = thread.feed_item_id.unwrap();
+  match run_blocking(move || FeedItem::find_by_id(feed_item_id)).await {
     Ok(Some(fi)) => feed_item = Some(fi),
     Ok(None) => feed_item = None,
     Err(e) => {
@@ -1402,12 +1904,15 @@ async fn get_thread_transcript(req: HttpRequest) -> Result<HttpResponse, ActixEr
     }
   };
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let transcript_data_dir = home_dir.join(".transcripts");
-
-
-  let file_name = generate_filename(thread.subtitle.clone().unwrap(), thread.timestamp.clone().unwrap());
-  let file_path = transcript_data_dir.join(file_name);
+  // Threads remember where their copy was saved, including copies saved to
+  // `~/.transcripts` before paths moved under the data root.
+  let file_path = match &thread.saved_transcript {
+    Some(saved_transcript) => PathBuf::from(saved_transcript),
+    None => config::saved_transcripts_dir().join(generate_filename(
+      thread.subtitle.clone().unwrap(),
+      thread.timestamp.clone().unwrap(),
+    )),
+  };
 
   if !file_path.exists() {
     return Ok(HttpResponse::NotFound().json(GetTranscriptDataResponse {
@@ -0,0 +1,498 @@
+//! Portable automation bundles.
+//!
+//! A bundle holds one or more automations with their cadences, data-source
+//! triggers, steps and output actions in a single JSON or YAML document, so an automation
+//! tuned on one machine can be imported on another. The built-in library is
+//! shipped as bundles too and imported on every start without touching
+//! automations that already exist.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::automations::actions::validate_action;
+use crate::automations::pipeline::validate_steps;
+use crate::automations::schedule::CadenceSchedule;
+use crate::automations::triggers::validate_data_source;
+use crate::db::models::automation::Automation;
+use crate::db::models::automation_action::AutomationAction;
+use crate::db::models::automation_step::AutomationStep;
+use crate::db::models::cadence_trigger::CadenceTrigger;
+use crate::db::models::data_source_trigger::DataSourceTrigger;
+use crate::error::Error;
+
+/// Bumped whenever a bundle written by this version cannot be read by older
+/// ones. Version 2 added output actions, version 3 step guards.
+pub const BUNDLE_VERSION: u32 = 3;
+
+const LIBRARY: &[&str] = &[
+  include_str!("library/meeting_prep.yaml"),
+  include_str!("library/email_summary.yaml"),
+  include_str!("library/email_regcheck.yaml"),
+  include_str!("library/strategic_plan.yaml"),
+  include_str!("library/linkedin_post_ideas.yaml"),
+  include_str!("library/business_coach.yaml"),
+  include_str!("library/social_media_planner.yaml"),
+  include_str!("library/sales_lead_scoring.yaml"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleFormat {
+  Json,
+  Yaml,
+}
+
+impl BundleFormat {
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      BundleFormat::Json => "application/json",
+      BundleFormat::Yaml => "application/yaml",
+    }
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      BundleFormat::Json => "json",
+      BundleFormat::Yaml => "yaml",
+    }
+  }
+}
+
+/// What importing does with an automation whose uuid already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+  /// Keeps the existing automation.
+  #[default]
+  Skip,
+  /// Overwrites the existing automation, keeping its runs.
+  Replace,
+  /// Imports the automation again under a new uuid.
+  Duplicate,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AutomationBundle {
+  pub version: u32,
+  pub automations: Vec<BundledAutomation>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BundledAutomation {
+  pub uuid: String,
+  pub name: String,
+  #[serde(default)]
+  pub description: String,
+  #[serde(default)]
+  pub is_active: bool,
+  #[serde(default)]
+  pub is_beta: bool,
+  #[serde(default = "default_show_library")]
+  pub show_library: bool,
+  #[serde(default)]
+  pub icon: String,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub cadences: Vec<BundledCadence>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub data_sources: Vec<BundledDataSource>,
+  /// In the order they run.
+  pub steps: Vec<BundledStep>,
+  /// In the order they run.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub actions: Vec<BundledAction>,
+}
+
+fn default_show_library() -> bool {
+  true
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BundledCadence {
+  pub cadence_type: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub day_of_week: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub time: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cron_expr: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timezone: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub interval_minutes: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub day_of_month: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BundledDataSource {
+  pub data_source: String,
+  #[serde(default)]
+  pub offset_minutes: i64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub filter: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BundledStep {
+  pub name: String,
+  /// The step's `args_json`, kept structured so bundles stay readable.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub args: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub guard: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BundledAction {
+  pub action_type: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub config: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportOutcome {
+  Created,
+  Replaced,
+  Duplicated,
+  Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedAutomation {
+  /// The uuid the automation was stored under, new for duplicates.
+  pub uuid: String,
+  pub name: String,
+  pub outcome: ImportOutcome,
+}
+
+impl BundledAutomation {
+  fn from_automation(automation: Automation) -> Result<BundledAutomation, Error> {
+    let cadences = CadenceTrigger::find_by_automation_uuid(&automation.uuid)?
+      .into_iter()
+      .map(|cadence| BundledCadence {
+        cadence_type: cadence.cadence_type,
+        day_of_week: cadence.day_of_week,
+        time: cadence.time,
+        cron_expr: cadence.cron_expr,
+        timezone: cadence.timezone,
+        interval_minutes: cadence.interval_minutes,
+        day_of_month: cadence.day_of_month,
+      })
+      .collect();
+    let data_sources = DataSourceTrigger::find_by_automation_uuid(&automation.uuid)?
+      .into_iter()
+      .map(|trigger| BundledDataSource {
+        data_source: trigger.data_source,
+        offset_minutes: trigger.offset_minutes,
+        filter: trigger.filter_json.as_deref().map(structured),
+      })
+      .collect();
+    let steps = AutomationStep::find_by_automation_uuid(&automation.uuid)?
+      .into_iter()
+      .map(|step| BundledStep {
+        name: step.name,
+        args: step.args_json.as_deref().map(structured),
+        guard: step.guard,
+      })
+      .collect();
+    let actions = AutomationAction::find_by_automation_uuid(&automation.uuid)?
+      .into_iter()
+      .map(|action| BundledAction {
+        action_type: action.action_type,
+        config: action.config_json.as_deref().map(structured),
+      })
+      .collect();
+    Ok(BundledAutomation {
+      uuid: automation.uuid,
+      name: automation.name,
+      description: automation.description,
+      is_active: automation.is_active,
+      is_beta: automation.is_beta,
+      show_library: automation.show_library,
+      icon: automation.icon,
+      cadences,
+      data_sources,
+      steps,
+      actions,
+    })
+  }
+
+  /// The automation as stored, under `uuid`.
+  fn to_automation(&self, uuid: &str) -> Automation {
+    Automation {
+      id: None,
+      uuid: uuid.to_string(),
+      name: self.name.clone(),
+      description: self.description.clone(),
+      is_active: self.is_active,
+      is_beta: self.is_beta,
+      show_library: self.show_library,
+      icon: self.icon.clone(),
+      runs: None,
+      trigger_cadences: Some(
+        self
+          .cadences
+          .iter()
+          .map(|cadence| CadenceTrigger {
+            id: None,
+            automation_uuid: uuid.to_string(),
+            cadence_type: cadence.cadence_type.clone(),
+            day_of_week: cadence.day_of_week.clone(),
+            time: cadence.time.clone(),
+            cron_expr: cadence.cron_expr.clone(),
+            timezone: cadence.timezone.clone(),
+            interval_minutes: cadence.interval_minutes,
+            day_of_month: cadence.day_of_month,
+          })
+          .collect(),
+      ),
+      trigger_data_sources: Some(
+        self
+          .data_sources
+          .iter()
+          .map(|data_source| DataSourceTrigger {
+            id: None,
+            automation_uuid: uuid.to_string(),
+            data_source: data_source.data_source.clone(),
+            offset_minutes: data_source.offset_minutes,
+            filter_json: data_source.filter.as_ref().map(|filter| filter.to_string()),
+            created_timestamp: None,
+            cursor: None,
+          })
+          .collect(),
+      ),
+      steps: Some(
+        self
+          .steps
+          .iter()
+          .enumerate()
+          .map(|(ordering, step)| AutomationStep {
+            id: None,
+            automation_uuid: uuid.to_string(),
+            name: step.name.clone(),
+            ordering: ordering as u64,
+            args_json: step.args.as_ref().map(|args| args.to_string()),
+            guard: step.guard.clone(),
+          })
+          .collect(),
+      ),
+      actions: Some(
+        self
+          .actions
+          .iter()
+          .enumerate()
+          .map(|(ordering, action)| AutomationAction {
+            id: None,
+            automation_uuid: uuid.to_string(),
+            action_type: action.action_type.clone(),
+            config_json: action.config.as_ref().map(|config| config.to_string()),
+            ordering: ordering as u64,
+          })
+          .collect(),
+      ),
+    }
+  }
+}
+
+/// Stored JSON as a value; text that is not JSON is kept as a string.
+fn structured(json: &str) -> Value {
+  serde_json::from_str(json).unwrap_or_else(|_| Value::String(json.to_string()))
+}
+
+/// Checks steps, triggers and actions before an automation is saved.
+pub(crate) fn validate_automation(
+  steps: &[AutomationStep],
+  cadences: &[CadenceTrigger],
+  data_sources: &[DataSourceTrigger],
+  actions: &[AutomationAction],
+) -> Result<(), Error> {
+  validate_steps(steps)?;
+  for cadence in cadences {
+    CadenceSchedule::from_cadence(cadence)?;
+  }
+  for data_source in data_sources {
+    validate_data_source(data_source)?;
+  }
+  for action in actions {
+    validate_action(action)?;
+  }
+  Ok(())
+}
+
+impl AutomationBundle {
+  /// Reads a bundle in either format; JSON documents start with `{`.
+  pub fn parse(text: &str) -> Result<AutomationBundle, Error> {
+    let bundle: AutomationBundle = if text.trim_start().starts_with('{') {
+      serde_json::from_str(text)
+        .map_err(|error| Error::KSError(format!("Invalid automation bundle: {}", error)))?
+    } else {
+      serde_yaml::from_str(text)
+        .map_err(|error| Error::KSError(format!("Invalid automation bundle: {}", error)))?
+    };
+    if bundle.version == 0 || bundle.version > BUNDLE_VERSION {
+      return Err(Error::KSError(format!(
+        "Automation bundle version {} is not supported; expected at most {}",
+        bundle.version, BUNDLE_VERSION
+      )));
+    }
+    Ok(bundle)
+  }
+
+  pub fn to_text(&self, format: BundleFormat) -> Result<String, Error> {
+    match format {
+      BundleFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+      BundleFormat::Yaml => serde_yaml::to_string(self)
+        .map_err(|error| Error::KSError(format!("Failed to write automation bundle: {}", error))),
+    }
+  }
+
+  /// Bundles the automation with `uuid`, or every automation when `None`.
+  pub fn export(uuid: Option<&str>) -> Result<AutomationBundle, Error> {
+    let automations = match uuid {
+      Some(uuid) => vec![Automation::find_by_uuid(uuid.to_string())
+        .map_err(|_| Error::KSError(format!("Automation {} not found", uuid)))?],
+      None => Automation::find_by_ids()?,
+    };
+    Ok(AutomationBundle {
+      version: BUNDLE_VERSION,
+      automations: automations
+        .into_iter()
+        .map(BundledAutomation::from_automation)
+        .collect::<Result<_, _>>()?,
+    })
+  }
+
+  /// Checks every automation before any is stored, so a bundle that fails
+  /// validation imports nothing.
+  fn validate(&self) -> Result<(), Error> {
+    let mut uuids = HashSet::new();
+    for bundled in &self.automations {
+      if bundled.uuid.trim().is_empty() || bundled.name.trim().is_empty() {
+        return Err(Error::KSError("Bundled automations need a uuid and a name".to_string()));
+      }
+      if !uuids.insert(bundled.uuid.as_str()) {
+        return Err(Error::KSError(format!("Automation {} is bundled twice", bundled.uuid)));
+      }
+      let automation = bundled.to_automation(&bundled.uuid);
+      validate_automation(
+        automation.steps.as_deref().unwrap_or_default(),
+        automation.trigger_cadences.as_deref().unwrap_or_default(),
+        automation.trigger_data_sources.as_deref().unwrap_or_default(),
+        automation.actions.as_deref().unwrap_or_default(),
+      )
+      .map_err(|error| match error {
+        Error::KSError(message) => Error::KSError(format!("{}: {}", bundled.name, message)),
+        error => error,
+      })?;
+    }
+    Ok(())
+  }
+
+  /// Stores the bundled automations, resolving uuid conflicts with
+  /// `strategy`. Each automation is stored on its own, so when storing one
+  /// fails the ones before it stay imported.
+  pub fn import(&self, strategy: ConflictStrategy) -> Result<Vec<ImportedAutomation>, Error> {
+    self.validate()?;
+    let mut imported = Vec::with_capacity(self.automations.len());
+    for bundled in &self.automations {
+      let existing = Automation::find_by_uuid(bundled.uuid.clone()).ok();
+      let (mut automation, outcome) = match (existing, strategy) {
+        (None, _) => (bundled.to_automation(&bundled.uuid), ImportOutcome::Created),
+        (Some(_), ConflictStrategy::Skip) => {
+          imported.push(ImportedAutomation {
+            uuid: bundled.uuid.clone(),
+            name: bundled.name.clone(),
+            outcome: ImportOutcome::Skipped,
+          });
+          continue;
+        }
+        (Some(existing), ConflictStrategy::Replace) => {
+          let mut automation = bundled.to_automation(&bundled.uuid);
+          automation.id = existing.id;
+          automation.update()?;
+          imported.push(ImportedAutomation {
+            uuid: automation.uuid,
+            name: automation.name,
+            outcome: ImportOutcome::Replaced,
+          });
+          continue;
+        }
+        (Some(_), ConflictStrategy::Duplicate) => {
+          let mut automation = bundled.to_automation(&Uuid::new_v4().to_string());
+          automation.name = format!("{} (copy)", automation.name);
+          (automation, ImportOutcome::Duplicated)
+        }
+      };
+      automation.create()?;
+      imported.push(ImportedAutomation {
+        uuid: automation.uuid,
+        name: automation.name,
+        outcome,
+      });
+    }
+    Ok(imported)
+  }
+}
+
+/// Adds the built-in automations that are missing. Automations the user
+/// already has, including edited library ones, are left alone.
+pub fn seed_library() {
+  for text in LIBRARY {
+    let result = AutomationBundle::parse(text).and_then(|bundle| bundle.import(ConflictStrategy::Skip));
+    match result {
+      Ok(imported) => {
+        for automation in imported {
+          if automation.outcome == ImportOutcome::Created {
+            log::info!("Created {} automation", automation.name);
+          }
+        }
+      }
+      Err(error) => log::error!("Error creating library automation: {:?}", error),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn library_bundles_parse_and_validate() {
+    let mut uuids = HashSet::new();
+    for text in LIBRARY {
+      let bundle = AutomationBundle::parse(text).expect("library bundle parses");
+      bundle.validate().expect("library bundle is valid");
+      for automation in bundle.automations {
+        assert!(uuids.insert(automation.uuid), "library uuids are unique");
+      }
+    }
+  }
+
+  #[test]
+  fn bundles_round_trip_through_both_formats() {
+    let bundle = AutomationBundle::parse(LIBRARY[0]).unwrap();
+    for format in [BundleFormat::Json, BundleFormat::Yaml] {
+      let text = bundle.to_text(format).unwrap();
+      let parsed = AutomationBundle::parse(&text).unwrap();
+      assert_eq!(parsed.automations.len(), bundle.automations.len());
+      assert_eq!(parsed.automations[0].uuid, bundle.automations[0].uuid);
+      assert_eq!(parsed.automations[0].data_sources.len(), 2);
+    }
+  }
+
+  #[test]
+  fn newer_bundle_versions_are_rejected() {
+    let text = format!("{{\"version\": {}, \"automations\": []}}", BUNDLE_VERSION + 1);
+    assert!(AutomationBundle::parse(&text).is_err());
+  }
+}
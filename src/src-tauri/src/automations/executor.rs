@@ -0,0 +1,626 @@
+//! Executes scheduled automation runs in the backend, so automations fire on
+//! time while the window is hidden.
+//!
+//! Every minute the executor picks up due runs whose steps all have a handler
+//! in `steps` or are `pipeline` steps, claims them and runs the steps in
+//! order. Library steps write their answer to the run's thread and feed item;
+//! pipeline steps pass typed outputs along until a `notify` step posts one. A
+//! step whose guard is false ends the run there, skipping the rest.
+//! Runs of other automations stay pending for the frontend scheduler. Each
+//! sweep first creates the cadence runs that came due (`scheduler`) and the
+//! runs of files changed in watched folders (`triggers`). Whatever a run
+//! posted is then handed to the automation's output actions (`actions`).
+
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::Mutex;
+
+use crate::automations::actions::{run_actions, RunOutput};
+use crate::automations::guard::Guard;
+use crate::automations::pipeline::{
+  find_pipeline_step, pipeline_step_names, recorded_outputs, run_pipeline_step, PipelineState,
+  PipelineStepOutcome, PipelineStepSpec,
+};
+use crate::automations::scheduler::materialize_due_runs;
+use crate::automations::steps::{
+  find_step_handler, step_names, StepContext, StepHandler, StepPrompt,
+};
+use crate::automations::triggers::scan_watched_folders;
+use crate::backup::BackgroundWork;
+use crate::db::models::automation::Automation;
+use crate::db::models::automation_action::AutomationAction;
+use crate::db::models::automation_run::{AutomationRun, AutomationRunStatus, StepResult};
+use crate::db::models::automation_step::AutomationStep;
+use crate::db::models::feed_item::FeedItem;
+use crate::db::models::message::Message;
+use crate::db::models::thread::{Thread, ThreadType};
+use crate::db::models::user::User;
+use crate::db::repo::run_blocking;
+use crate::error::Error;
+use crate::llm::prompt::{build_system_message, build_user_message};
+use crate::llm::use_cases::complete::{
+  multi_provider_completion, CompletionCost, AUTOMATION_RUN_ID, COMPLETION_COSTS,
+};
+use crate::memory::semantic::SemanticService;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+const DUE_RUNS_PER_SWEEP: u32 = 20;
+/// Runs due longer ago than this were missed while the app was closed. They
+/// are skipped rather than sent to the LLM all at once on startup.
+const MAX_LATENESS_MILLIS: i64 = 6 * 60 * 60 * 1000;
+
+fn now_millis() -> i64 {
+  chrono::Utc::now().timestamp_millis()
+}
+
+/// Whether `run` came due too long before `now` to still be worth running.
+fn missed(run: &AutomationRun, now: i64) -> bool {
+  now - run.schedule_timestamp.unwrap_or(now) > MAX_LATENESS_MILLIS
+}
+
+#[derive(Clone, Copy)]
+enum RunnableStep {
+  Library(StepHandler),
+  Pipeline(&'static PipelineStepSpec),
+}
+
+/// How to run every step, in order, or `None` if any step cannot run in the
+/// backend. Steps may have changed since the run was picked up.
+fn resolve_steps(steps: &[AutomationStep]) -> Option<Vec<RunnableStep>> {
+  if steps.is_empty() {
+    return None;
+  }
+  steps
+    .iter()
+    .map(|step| {
+      find_step_handler(&step.name)
+        .map(RunnableStep::Library)
+        .or_else(|| find_pipeline_step(&step.name).map(RunnableStep::Pipeline))
+    })
+    .collect()
+}
+
+/// Whether the executor runs automations made of `steps`; the app runs the
+/// others.
+pub fn runs_in_backend(steps: &[AutomationStep]) -> bool {
+  resolve_steps(steps).is_some()
+}
+
+fn text_outputs(text: &str) -> Map<String, Value> {
+  let mut outputs = Map::new();
+  outputs.insert("text".to_string(), json!(text));
+  outputs
+}
+
+/// Stores one step answer as a bot message, creating the run's feed item and
+/// thread on first use.
+fn write_result(
+  run: &mut AutomationRun,
+  automation: &Automation,
+  document_ids: Vec<u64>,
+  result: String,
+) -> Result<(), Error> {
+  let mut feed_item = match run.feed_item_id {
+    Some(feed_item_id) => FeedItem::find_by_id(feed_item_id)?,
+    None => None,
+  };
+  if feed_item.is_none() {
+    let mut new_feed_item = FeedItem {
+      id: None,
+      title: Some(automation.name.clone()),
+      timestamp: Some(run.schedule_timestamp.unwrap_or_else(now_millis)),
+      deleted: None,
+    };
+    new_feed_item.create()?;
+    run.feed_item_id = new_feed_item.id;
+    feed_item = Some(new_feed_item);
+  }
+
+  if run.thread_id.is_none() {
+    let mut thread = Thread {
+      id: None,
+      timestamp: run.execution_timestamp,
+      hide_follow_up: Some(false),
+      thread_type: ThreadType::Chat,
+      feed_item_id: run.feed_item_id,
+      title: Some(automation.name.clone()),
+      subtitle: feed_item.and_then(|feed_item| feed_item.title),
+      recorded: Some(false),
+      saved_transcript: None,
+      prompt_template: None,
+    };
+    thread.create()?;
+    run.thread_id = thread.id;
+  }
+
+  let mut bot_message = Message {
+    id: None,
+    timestamp: chrono::offset::Local::now().timestamp(),
+    thread_id: run
+      .thread_id
+      .ok_or_else(|| Error::KSError("Automation run thread was not created".into()))?,
+    user_id: None,
+    content: result,
+    content_facade: None,
+    feedbacks: None,
+    document_ids: Some(document_ids),
+  };
+  bot_message.create()
+}
+
+async fn complete(
+  step_prompt: &StepPrompt,
+  user: &User,
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+  trace: &mut StepTrace,
+) -> Result<String, Error> {
+  let user_name = user.email.split('@').next().unwrap_or_default().to_string();
+  let user_message = build_user_message(
+    step_prompt.prompt.clone(),
+    None,
+    None,
+    semantic_service,
+    Some(step_prompt.additional_documents.clone()),
+  )
+  .await;
+  trace.prompt = Some(user_message.content.clone());
+  let messages = vec![build_system_message(user_name, user.email.clone()), user_message];
+  let result = multi_provider_completion(messages).await?;
+  trace.output = Some(result.clone());
+  Ok(result)
+}
+
+/// Posts `result` to the run's thread on the blocking pool.
+async fn post_result(
+  run: &mut AutomationRun,
+  automation: &Automation,
+  document_ids: Vec<u64>,
+  result: String,
+) -> Result<(), Error> {
+  let mut updated_run = run.clone();
+  let automation = automation.clone();
+  *run = run_blocking(move || {
+    write_result(&mut updated_run, &automation, document_ids, result)?;
+    Ok(updated_run)
+  })
+  .await?;
+  Ok(())
+}
+
+/// What every step of a run shares.
+struct RunEnv<'a> {
+  automation: &'a Automation,
+  user: User,
+  context: StepContext,
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+  /// Previews post nothing to the feed.
+  preview: bool,
+}
+
+/// What one step gathered and produced, as shown in previews.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepTrace {
+  pub ordering: u64,
+  pub name: String,
+  pub status: Option<AutomationRunStatus>,
+  /// The user message sent to the LLM, documents included.
+  pub prompt: Option<String>,
+  /// The emails, events or documents the step gathered.
+  pub data: Option<Value>,
+  /// The LLM answer, or the text a `notify` step posts.
+  pub output: Option<String>,
+  pub error: Option<String>,
+}
+
+/// Result of running an automation's steps without storing anything.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutomationPreview {
+  pub steps: Vec<StepTrace>,
+  pub completions: Vec<CompletionCost>,
+  pub input_tokens: i64,
+  pub output_tokens: i64,
+  pub estimated_cost_usd: f64,
+  /// The output actions a real run would hand its result to.
+  pub actions: Vec<AutomationAction>,
+  /// Why the preview stopped early.
+  pub error: Option<String>,
+}
+
+/// Runs one step and returns its outputs, or `None` when a library step had
+/// nothing to do. Text the step posts is added to `posted`.
+async fn run_step(
+  run: &mut AutomationRun,
+  env: &RunEnv<'_>,
+  step: &AutomationStep,
+  runnable: RunnableStep,
+  state: &PipelineState,
+  trace: &mut StepTrace,
+  posted: &mut Vec<String>,
+) -> Result<Option<Map<String, Value>>, Error> {
+  match runnable {
+    RunnableStep::Library(handler) => {
+      let step_context = env.context.clone();
+      let step_prompt = match run_blocking(move || handler(&step_context)).await? {
+        Some(step_prompt) => step_prompt,
+        None => return Ok(None),
+      };
+      trace.data = Some(json!({
+        "documents": step_prompt.additional_documents,
+        "documentIds": step_prompt.document_ids,
+      }));
+      let result = complete(&step_prompt, &env.user, env.semantic_service.clone(), trace).await?;
+      let outputs = text_outputs(&result);
+      if !env.preview {
+        post_result(run, env.automation, step_prompt.document_ids, result.clone()).await?;
+      }
+      posted.push(result);
+      Ok(Some(outputs))
+    }
+    RunnableStep::Pipeline(spec) => {
+      let inputs = state.resolve_inputs(spec, step.args_json.as_deref())?;
+      let step_context = env.context.clone();
+      match run_blocking(move || run_pipeline_step(spec, &inputs, &step_context)).await? {
+        PipelineStepOutcome::Outputs(outputs) => {
+          trace.data = Some(Value::Object(outputs.clone()));
+          Ok(Some(outputs))
+        }
+        PipelineStepOutcome::Prompt(step_prompt) => {
+          let result = complete(&step_prompt, &env.user, env.semantic_service.clone(), trace).await?;
+          Ok(Some(text_outputs(&result)))
+        }
+        PipelineStepOutcome::Notify(text) => {
+          trace.output = Some(text.clone());
+          if !env.preview {
+            post_result(run, env.automation, Vec::new(), text.clone()).await?;
+          }
+          posted.push(text);
+          Ok(Some(Map::new()))
+        }
+      }
+    }
+  }
+}
+
+/// Runs every step of a claimed run, recording a result per step. Stops at
+/// the first failing step or false guard. Returns the texts posted, in order. With
+/// `traces` the run is a preview: nothing is posted and each step's trace is
+/// collected instead.
+async fn run_steps(
+  run: &mut AutomationRun,
+  automation: &Automation,
+  steps: &[AutomationStep],
+  runnable_steps: Vec<RunnableStep>,
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+  mut traces: Option<&mut Vec<StepTrace>>,
+) -> Result<Vec<String>, Error> {
+  let user_id = run.user_id;
+  let user = run_blocking(move || User::find_by_id(user_id))
+    .await?
+    .ok_or_else(|| Error::KSError(format!("User {} not found", user_id)))?;
+  let run_params: Map<String, Value> = run
+    .run_params
+    .as_deref()
+    .and_then(|run_params| serde_json::from_str(run_params).ok())
+    .unwrap_or_default();
+  let timestamp = run.schedule_timestamp.unwrap_or_else(now_millis);
+  let env = RunEnv {
+    automation,
+    context: StepContext {
+      account_id: user_id,
+      user_email: user.email.clone(),
+      run_params: Value::Object(run_params.clone()),
+      timestamp,
+    },
+    user,
+    semantic_service,
+    preview: traces.is_some(),
+  };
+  let mut state = PipelineState {
+    run_params,
+    outputs: Default::default(),
+  };
+  state
+    .run_params
+    .entry("timestamp")
+    .or_insert_with(|| json!(timestamp));
+
+  let mut step_results = Vec::new();
+  let mut posted = Vec::new();
+  let mut outcome = Ok(());
+  let mut guarded = false;
+  for (step, runnable) in steps.iter().zip(runnable_steps) {
+    let mut trace = StepTrace {
+      ordering: step.ordering,
+      name: step.name.clone(),
+      ..Default::default()
+    };
+    let result = match Guard::parse_optional(step.guard.as_deref()) {
+      Ok(Some(guard)) if !guard.matches(&state.guard_context()) => {
+        guarded = true;
+        Ok(None)
+      }
+      Ok(_) => run_step(run, &env, step, runnable, &state, &mut trace, &mut posted).await,
+      Err(error) => Err(error),
+    };
+    let (status, outputs, error) = match result {
+      Ok(Some(outputs)) => {
+        let recorded = recorded_outputs(&outputs);
+        state.outputs.insert(step.ordering, outputs);
+        (AutomationRunStatus::Succeeded, Some(recorded), None)
+      }
+      Ok(None) => (AutomationRunStatus::Skipped, None, None),
+      Err(error) => (AutomationRunStatus::Failed, None, Some(format!("{:?}", error))),
+    };
+    step_results.push(StepResult {
+      ordering: step.ordering,
+      name: step.name.clone(),
+      status,
+      outputs,
+      error: error.clone(),
+    });
+    if let Some(traces) = traces.as_deref_mut() {
+      trace.status = Some(status);
+      trace.error = error.clone();
+      traces.push(trace);
+    }
+    if let Some(error) = error {
+      outcome = Err(Error::KSError(format!("Step {} ({}) failed: {}", step.ordering, step.name, error)));
+      break;
+    }
+    if guarded {
+      log::info!("Guard of step {} ({}) is false, skipping the rest", step.ordering, step.name);
+      break;
+    }
+  }
+  run.step_results = Some(step_results);
+  outcome.map(|_| posted)
+}
+
+async fn execute_run(
+  mut run: AutomationRun,
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+) -> Result<(), Error> {
+  let automation_uuid = run.automation_uuid.clone();
+  let (automation, steps, actions) = run_blocking(move || {
+    let automation = Automation::find_by_uuid(automation_uuid.clone())?;
+    let steps = AutomationStep::find_by_automation_uuid(&automation_uuid)?;
+    let actions = AutomationAction::find_by_automation_uuid(&automation_uuid)?;
+    Ok((automation, steps, actions))
+  })
+  .await?;
+  let runnable_steps = match resolve_steps(&steps) {
+    Some(runnable_steps) => runnable_steps,
+    None => return Ok(()),
+  };
+
+  let now = now_millis();
+  let (claimed, claimed_run) = run_blocking(move || {
+    let claimed = run.claim(now)?;
+    Ok((claimed, run))
+  })
+  .await?;
+  if !claimed {
+    return Ok(());
+  }
+  run = claimed_run;
+
+  let (status, error) = if missed(&run, now) {
+    (
+      AutomationRunStatus::Skipped,
+      Some("Missed while the app was closed".to_string()),
+    )
+  } else {
+    let run_id = run.id.unwrap_or_default();
+    let steps_run = run_steps(&mut run, &automation, &steps, runnable_steps, semantic_service, None);
+    match AUTOMATION_RUN_ID.scope(run_id, steps_run).await {
+      Ok(posted) if posted.is_empty() => (AutomationRunStatus::Skipped, None),
+      Ok(posted) => {
+        if !actions.is_empty() {
+          let user_id = run.user_id;
+          let user_email = run_blocking(move || User::find_by_id(user_id))
+            .await
+            .ok()
+            .flatten()
+            .map(|user| user.email)
+            .unwrap_or_default();
+          let output = RunOutput {
+            automation_name: automation.name.clone(),
+            user_email,
+            text: posted.join("\n\n"),
+            timestamp_millis: run.schedule_timestamp.unwrap_or(now),
+          };
+          run.action_results = Some(run_actions(&actions, &output).await);
+        }
+        (AutomationRunStatus::Succeeded, None)
+      }
+      Err(error) => (AutomationRunStatus::Failed, Some(format!("{:?}", error))),
+    }
+  };
+  log::info!(
+    "Automation run {:?} of {} finished as {}",
+    run.id,
+    automation.name,
+    status.as_str()
+  );
+  run_blocking(move || run.finish(status, error)).await
+}
+
+/// Runs the steps of the automation with `automation_uuid` for `user_id`
+/// against current data. Nothing is stored or posted; the steps' prompts,
+/// gathered data and answers come back with what the completions cost.
+pub async fn preview(
+  automation_uuid: String,
+  user_id: u64,
+  run_params: Map<String, Value>,
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+) -> Result<AutomationPreview, Error> {
+  let lookup_uuid = automation_uuid.clone();
+  let (automation, steps, actions) = run_blocking(move || {
+    let automation = Automation::find_by_uuid(lookup_uuid.clone())?;
+    let steps = AutomationStep::find_by_automation_uuid(&lookup_uuid)?;
+    let actions = AutomationAction::find_by_automation_uuid(&lookup_uuid)?;
+    Ok((automation, steps, actions))
+  })
+  .await?;
+  let runnable_steps = resolve_steps(&steps).ok_or_else(|| {
+    Error::KSError(format!("{} has steps only the app can run", automation.name))
+  })?;
+
+  let now = now_millis();
+  let mut run = AutomationRun {
+    id: None,
+    automation_uuid,
+    user_id,
+    thread_id: None,
+    schedule_timestamp: Some(now),
+    execution_timestamp: Some(now),
+    run_params: Some(Value::Object(run_params).to_string()),
+    feed_item_id: None,
+    status: Some(AutomationRunStatus::Running),
+    error: None,
+    step_results: None,
+    run_key: None,
+    finished_timestamp: None,
+    attempts: 1,
+    action_results: None,
+  };
+  let costs = Arc::new(StdMutex::new(Vec::new()));
+  let mut traces = Vec::new();
+  let steps_run = run_steps(&mut run, &automation, &steps, runnable_steps, semantic_service, Some(&mut traces));
+  let error = COMPLETION_COSTS
+    .scope(costs.clone(), steps_run)
+    .await
+    .err()
+    .map(|error| format!("{:?}", error));
+
+  let completions = costs.lock().map(|costs| costs.clone()).unwrap_or_default();
+  Ok(AutomationPreview {
+    steps: traces,
+    input_tokens: completions.iter().map(|cost| cost.input_tokens).sum(),
+    output_tokens: completions.iter().map(|cost| cost.output_tokens).sum(),
+    estimated_cost_usd: completions.iter().map(|cost| cost.cost_usd).sum(),
+    completions,
+    actions,
+    error,
+  })
+}
+
+/// Executes `run` now rather than on the next sweep, e.g. when retried.
+pub async fn execute_now(run: AutomationRun, semantic_service: Arc<Mutex<Option<SemanticService>>>) {
+  let run_id = run.id;
+  // Left pending for the next sweep while a restore runs.
+  let _work = match BackgroundWork::begin() {
+    Some(work) => work,
+    None => return,
+  };
+  if let Err(error) = execute_run(run, semantic_service).await {
+    log::error!("Failed to execute automation run {:?} - {:?}", run_id, error);
+  }
+}
+
+async fn execute_due_runs(
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+) -> Result<(), Error> {
+  let now = now_millis();
+  let mut runnable_names = step_names();
+  runnable_names.extend(pipeline_step_names());
+  let runs =
+    run_blocking(move || AutomationRun::find_due(now, &runnable_names, DUE_RUNS_PER_SWEEP)).await?;
+  for run in runs {
+    let run_id = run.id;
+    if let Err(error) = execute_run(run, semantic_service.clone()).await {
+      log::error!("Failed to execute automation run {:?} - {:?}", run_id, error);
+    }
+  }
+  Ok(())
+}
+
+pub fn start_automation_executor(semantic_service: Arc<Mutex<Option<SemanticService>>>) {
+  tokio::spawn(async move {
+    if let Err(error) = run_blocking(AutomationRun::fail_interrupted).await {
+      log::error!("Failed to reset interrupted automation runs - {:?}", error);
+    }
+    loop {
+      if let Some(_work) = BackgroundWork::begin() {
+        if let Err(error) = run_blocking(|| materialize_due_runs(now_millis())).await {
+          log::error!("Failed to create due cadence runs - {:?}", error);
+        }
+        if let Err(error) = run_blocking(|| scan_watched_folders(now_millis())).await {
+          log::error!("Failed to scan watched folders - {:?}", error);
+        }
+        if let Err(error) = execute_due_runs(semantic_service.clone()).await {
+          log::error!("Automation sweep failed - {:?}", error);
+        }
+      }
+      tokio::time::sleep(POLL_INTERVAL).await;
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn step(name: &str, ordering: u64) -> AutomationStep {
+    AutomationStep {
+      id: None,
+      automation_uuid: "executor-test".to_string(),
+      name: name.to_string(),
+      ordering,
+      args_json: None,
+      guard: None,
+    }
+  }
+
+  fn run_due_at(schedule_timestamp: Option<i64>) -> AutomationRun {
+    AutomationRun {
+      id: None,
+      automation_uuid: "executor-test".to_string(),
+      user_id: 1,
+      thread_id: None,
+      schedule_timestamp,
+      execution_timestamp: None,
+      run_params: None,
+      feed_item_id: None,
+      status: Some(AutomationRunStatus::Scheduled),
+      error: None,
+      step_results: None,
+      run_key: None,
+      finished_timestamp: None,
+      attempts: 0,
+      action_results: None,
+    }
+  }
+
+  #[test]
+  fn resolves_library_and_pipeline_steps_in_order() {
+    let steps = vec![step("fetch_emails", 0), step("email-summary", 1), step("notify", 2)];
+    let resolved = resolve_steps(&steps).unwrap();
+    assert!(matches!(
+      resolved.as_slice(),
+      [RunnableStep::Pipeline(_), RunnableStep::Library(_), RunnableStep::Pipeline(_)]
+    ));
+  }
+
+  #[test]
+  fn leaves_automations_with_app_only_steps_to_the_app() {
+    assert!(resolve_steps(&[]).is_none());
+    let steps = vec![step("email-summary", 0), step("social-media-planner", 1)];
+    assert!(resolve_steps(&steps).is_none());
+    assert!(!runs_in_backend(&steps));
+  }
+
+  #[test]
+  fn skips_runs_due_more_than_six_hours_ago() {
+    let now = now_millis();
+    assert!(!missed(&run_due_at(Some(now - MAX_LATENESS_MILLIS)), now));
+    assert!(missed(&run_due_at(Some(now - MAX_LATENESS_MILLIS - 1)), now));
+    assert!(!missed(&run_due_at(Some(now + 60_000)), now));
+    assert!(!missed(&run_due_at(None), now));
+  }
+}
@@ -0,0 +1,467 @@
+//! Guard expressions deciding whether a trigger fires or a step runs.
+//!
+//! Guards are a small, side-effect free language over JSON:
+//!
+//! - literals: `3`, `2.5`, `'text'` or `"text"`, `true`, `false`, `null`
+//! - paths into the context: `event.attendees`, `steps.1.text`, `run.email.subject`
+//! - comparisons `==`, `!=`, `<`, `<=`, `>`, `>=`, combined with `&&`, `||`,
+//!   `!` and parentheses
+//! - functions: `len(x)` of a list, text or object, `count(list, 'field')`
+//!   of the items whose field is true or non-empty, `lower(x)`,
+//!   `contains(x, y)` for text and lists, and `number(x)`, which reads the
+//!   first number in a text such as an LLM answer
+//!
+//! e.g. `len(event.external_attendees) >= 3`,
+//! `count(steps.0.emails, 'unread') > 5` or `number(steps.2.text) > 70`.
+//! Paths that do not exist are `null`. Evaluation never fails: comparing
+//! values of different types is false.
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// Bounds what a stored guard can make the parser do.
+const MAX_GUARD_CHARS: usize = 1000;
+const MAX_DEPTH: usize = 32;
+
+const FUNCTIONS: &[(&str, usize)] =
+  &[("len", 1), ("count", 2), ("lower", 1), ("number", 1), ("contains", 2)];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Number(f64),
+  Text(String),
+  Ident(String),
+  Dot,
+  Comma,
+  LeftParen,
+  RightParen,
+  Not,
+  And,
+  Or,
+  Compare(CompareOp),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+  Literal(Value),
+  Path(Vec<String>),
+  Call(String, Vec<Expr>),
+  Not(Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Or(Box<Expr>, Box<Expr>),
+  Compare(CompareOp, Box<Expr>, Box<Expr>),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut tokens = Vec::new();
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    let next = chars.get(i + 1).copied();
+    match c {
+      c if c.is_whitespace() => i += 1,
+      '.' => {
+        tokens.push(Token::Dot);
+        i += 1;
+      }
+      ',' => {
+        tokens.push(Token::Comma);
+        i += 1;
+      }
+      '(' => {
+        tokens.push(Token::LeftParen);
+        i += 1;
+      }
+      ')' => {
+        tokens.push(Token::RightParen);
+        i += 1;
+      }
+      '&' if next == Some('&') => {
+        tokens.push(Token::And);
+        i += 2;
+      }
+      '|' if next == Some('|') => {
+        tokens.push(Token::Or);
+        i += 2;
+      }
+      '=' if next == Some('=') => {
+        tokens.push(Token::Compare(CompareOp::Eq));
+        i += 2;
+      }
+      '!' if next == Some('=') => {
+        tokens.push(Token::Compare(CompareOp::Ne));
+        i += 2;
+      }
+      '!' => {
+        tokens.push(Token::Not);
+        i += 1;
+      }
+      '<' | '>' => {
+        let op = match (c, next == Some('=')) {
+          ('<', false) => CompareOp::Lt,
+          ('<', true) => CompareOp::Le,
+          ('>', false) => CompareOp::Gt,
+          _ => CompareOp::Ge,
+        };
+        tokens.push(Token::Compare(op));
+        i += if next == Some('=') { 2 } else { 1 };
+      }
+      '\'' | '"' => {
+        let end = chars[i + 1..]
+          .iter()
+          .position(|&other| other == c)
+          .ok_or_else(|| format!("unterminated text starting at {}", i))?;
+        tokens.push(Token::Text(chars[i + 1..i + 1 + end].iter().collect()));
+        i += end + 2;
+      }
+      c if c.is_ascii_digit() => {
+        let start = i;
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+          i += 1;
+        }
+        let number: String = chars[start..i].iter().collect();
+        // `steps.1.text` splits into path segments; `1.5` is a number.
+        let after_path = matches!(tokens.last(), Some(Token::Dot));
+        if after_path {
+          let segments: Vec<&str> = number.split('.').collect();
+          for (index, segment) in segments.iter().enumerate() {
+            if index > 0 {
+              tokens.push(Token::Dot);
+            }
+            if !segment.is_empty() {
+              tokens.push(Token::Ident(segment.to_string()));
+            }
+          }
+        } else {
+          tokens.push(Token::Number(
+            number
+              .parse()
+              .map_err(|_| format!("`{}` is not a number", number))?,
+          ));
+        }
+      }
+      c if c.is_alphabetic() || c == '_' => {
+        let start = i;
+        while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+          i += 1;
+        }
+        tokens.push(Token::Ident(chars[start..i].iter().collect()));
+      }
+      c => return Err(format!("unexpected `{}`", c)),
+    }
+  }
+  Ok(tokens)
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  position: usize,
+  depth: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn advance(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.position).cloned();
+    self.position += 1;
+    token
+  }
+
+  fn expect(&mut self, expected: Token) -> Result<(), String> {
+    match self.advance() {
+      Some(token) if token == expected => Ok(()),
+      Some(token) => Err(format!("expected {:?} but found {:?}", expected, token)),
+      None => Err(format!("expected {:?} at the end", expected)),
+    }
+  }
+
+  fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser) -> Result<T, String>) -> Result<T, String> {
+    self.depth += 1;
+    if self.depth > MAX_DEPTH {
+      return Err("expression is nested too deeply".to_string());
+    }
+    let result = parse(self);
+    self.depth -= 1;
+    result
+  }
+
+  fn or(&mut self) -> Result<Expr, String> {
+    let mut left = self.and()?;
+    while self.peek() == Some(&Token::Or) {
+      self.advance();
+      left = Expr::Or(Box::new(left), Box::new(self.and()?));
+    }
+    Ok(left)
+  }
+
+  fn and(&mut self) -> Result<Expr, String> {
+    let mut left = self.not()?;
+    while self.peek() == Some(&Token::And) {
+      self.advance();
+      left = Expr::And(Box::new(left), Box::new(self.not()?));
+    }
+    Ok(left)
+  }
+
+  fn not(&mut self) -> Result<Expr, String> {
+    if self.peek() == Some(&Token::Not) {
+      self.advance();
+      return self.nested(|parser| Ok(Expr::Not(Box::new(parser.not()?))));
+    }
+    self.compare()
+  }
+
+  fn compare(&mut self) -> Result<Expr, String> {
+    let left = self.primary()?;
+    if let Some(Token::Compare(op)) = self.peek().cloned() {
+      self.advance();
+      return Ok(Expr::Compare(op, Box::new(left), Box::new(self.primary()?)));
+    }
+    Ok(left)
+  }
+
+  fn primary(&mut self) -> Result<Expr, String> {
+    match self.advance() {
+      Some(Token::Number(number)) => Ok(Expr::Literal(Value::from(number))),
+      Some(Token::Text(text)) => Ok(Expr::Literal(Value::String(text))),
+      Some(Token::LeftParen) => {
+        let expr = self.nested(Parser::or)?;
+        self.expect(Token::RightParen)?;
+        Ok(expr)
+      }
+      Some(Token::Ident(ident)) => match ident.as_str() {
+        "true" => Ok(Expr::Literal(Value::Bool(true))),
+        "false" => Ok(Expr::Literal(Value::Bool(false))),
+        "null" => Ok(Expr::Literal(Value::Null)),
+        _ if self.peek() == Some(&Token::LeftParen) => self.call(ident),
+        _ => self.path(ident),
+      },
+      Some(token) => Err(format!("unexpected {:?}", token)),
+      None => Err("unexpected end of expression".to_string()),
+    }
+  }
+
+  fn call(&mut self, name: String) -> Result<Expr, String> {
+    let arity = FUNCTIONS
+      .iter()
+      .find(|(function, _)| *function == name)
+      .map(|(_, arity)| *arity)
+      .ok_or_else(|| format!("unknown function `{}`", name))?;
+    self.expect(Token::LeftParen)?;
+    let mut args = Vec::new();
+    if self.peek() != Some(&Token::RightParen) {
+      loop {
+        args.push(self.nested(Parser::or)?);
+        if self.peek() != Some(&Token::Comma) {
+          break;
+        }
+        self.advance();
+      }
+    }
+    self.expect(Token::RightParen)?;
+    if args.len() != arity {
+      return Err(format!("`{}` takes {} argument(s)", name, arity));
+    }
+    Ok(Expr::Call(name, args))
+  }
+
+  fn path(&mut self, first: String) -> Result<Expr, String> {
+    let mut segments = vec![first];
+    while self.peek() == Some(&Token::Dot) {
+      self.advance();
+      match self.advance() {
+        Some(Token::Ident(segment)) => segments.push(segment),
+        _ => return Err(format!("`{}.` must be followed by a name", segments.join("."))),
+      }
+    }
+    Ok(Expr::Path(segments))
+  }
+}
+
+fn lookup<'a>(context: &'a Value, segments: &[String]) -> &'a Value {
+  segments.iter().fold(context, |value, segment| match value {
+    Value::Object(object) => object.get(segment).unwrap_or(&Value::Null),
+    Value::Array(items) => segment
+      .parse::<usize>()
+      .ok()
+      .and_then(|index| items.get(index))
+      .unwrap_or(&Value::Null),
+    _ => &Value::Null,
+  })
+}
+
+fn truthy(value: &Value) -> bool {
+  match value {
+    Value::Null => false,
+    Value::Bool(value) => *value,
+    Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+    Value::String(text) => !text.is_empty(),
+    Value::Array(items) => !items.is_empty(),
+    Value::Object(object) => !object.is_empty(),
+  }
+}
+
+/// The first number in `text`, e.g. 72 in "Score: 72/100".
+fn first_number(text: &str) -> Option<f64> {
+  if let Ok(number) = text.trim().parse() {
+    return Some(number);
+  }
+  let start = text.find(|c: char| c.is_ascii_digit())?;
+  let negative = text[..start].ends_with('-');
+  let digits: String = text[start..]
+    .chars()
+    .take_while(|c| c.is_ascii_digit() || *c == '.')
+    .collect();
+  let number: f64 = digits.trim_end_matches('.').parse().ok()?;
+  Some(if negative { -number } else { number })
+}
+
+fn call(name: &str, args: Vec<Value>) -> Value {
+  match (name, args.as_slice()) {
+    ("len", [Value::Array(items)]) => Value::from(items.len()),
+    ("len", [Value::String(text)]) => Value::from(text.chars().count()),
+    ("len", [Value::Object(object)]) => Value::from(object.len()),
+    ("len", [_]) => Value::from(0),
+    ("count", [Value::Array(items), Value::String(field)]) => {
+      let segments: Vec<String> = field.split('.').map(str::to_string).collect();
+      Value::from(items.iter().filter(|item| truthy(lookup(item, &segments))).count())
+    }
+    ("count", [_, _]) => Value::from(0),
+    ("lower", [Value::String(text)]) => Value::String(text.to_lowercase()),
+    ("number", [Value::Number(number)]) => Value::Number(number.clone()),
+    ("number", [Value::String(text)]) => first_number(text).map(Value::from).unwrap_or(Value::Null),
+    ("contains", [Value::String(text), Value::String(needle)]) => {
+      Value::Bool(text.to_lowercase().contains(&needle.to_lowercase()))
+    }
+    ("contains", [Value::Array(items), needle]) => Value::Bool(items.iter().any(|item| equal(item, needle))),
+    _ => Value::Null,
+  }
+}
+
+fn equal(left: &Value, right: &Value) -> bool {
+  match (left.as_f64(), right.as_f64()) {
+    (Some(left), Some(right)) => left == right,
+    _ => left == right,
+  }
+}
+
+fn compare(op: CompareOp, left: &Value, right: &Value) -> bool {
+  let ordering = match (left, right) {
+    (Value::Number(_), Value::Number(_)) => left.as_f64().partial_cmp(&right.as_f64()),
+    (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+    _ => None,
+  };
+  match op {
+    CompareOp::Eq => equal(left, right),
+    CompareOp::Ne => !equal(left, right),
+    CompareOp::Lt => ordering == Some(Ordering::Less),
+    CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+    CompareOp::Gt => ordering == Some(Ordering::Greater),
+    CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+  }
+}
+
+fn evaluate(expr: &Expr, context: &Value) -> Value {
+  match expr {
+    Expr::Literal(value) => value.clone(),
+    Expr::Path(segments) => lookup(context, segments).clone(),
+    Expr::Call(name, args) => call(name, args.iter().map(|arg| evaluate(arg, context)).collect()),
+    Expr::Not(expr) => Value::Bool(!truthy(&evaluate(expr, context))),
+    Expr::And(left, right) => {
+      Value::Bool(truthy(&evaluate(left, context)) && truthy(&evaluate(right, context)))
+    }
+    Expr::Or(left, right) => {
+      Value::Bool(truthy(&evaluate(left, context)) || truthy(&evaluate(right, context)))
+    }
+    Expr::Compare(op, left, right) => {
+      Value::Bool(compare(*op, &evaluate(left, context), &evaluate(right, context)))
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guard {
+  expr: Expr,
+}
+
+impl Guard {
+  pub fn parse(text: &str) -> Result<Guard, Error> {
+    let invalid = |error: String| Error::KSError(format!("invalid guard `{}`: {}", text, error));
+    if text.chars().count() > MAX_GUARD_CHARS {
+      return Err(invalid(format!("longer than {} characters", MAX_GUARD_CHARS)));
+    }
+    let mut parser = Parser {
+      tokens: tokenize(text).map_err(invalid)?,
+      position: 0,
+      depth: 0,
+    };
+    let expr = parser.or().map_err(invalid)?;
+    if let Some(token) = parser.peek() {
+      return Err(invalid(format!("unexpected {:?}", token)));
+    }
+    Ok(Guard { expr })
+  }
+
+  /// Parses an optional stored guard; blank guards are none.
+  pub fn parse_optional(text: Option<&str>) -> Result<Option<Guard>, Error> {
+    match text.map(str::trim) {
+      None | Some("") => Ok(None),
+      Some(text) => Guard::parse(text).map(Some),
+    }
+  }
+
+  pub fn matches(&self, context: &Value) -> bool {
+    truthy(&evaluate(&self.expr, context))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn check(guard: &str, context: &Value) -> bool {
+    Guard::parse(guard).unwrap().matches(context)
+  }
+
+  #[test]
+  fn evaluates_comparisons_on_paths_and_functions() {
+    let context = json!({
+      "event": { "attendees": ["a@acme.com", "b@x.io", "c@y.io"], "title": "Quarterly Review" },
+      "steps": { "1": { "text": "Score: 72/100" } },
+    });
+    assert!(check("len(event.attendees) >= 3", &context));
+    assert!(check("number(steps.1.text) > 70 && contains(event.title, 'review')", &context));
+    assert!(!check("!(len(event.attendees) > 1) || event.title == \"Standup\"", &context));
+    assert!(check("contains(event.attendees, 'b@x.io')", &context));
+    let emails = json!({ "emails": [{ "unread": true }, { "unread": false }, { "unread": true }] });
+    assert!(check("count(emails, 'unread') == 2", &emails));
+    assert!(!check("event.missing.field > 0", &context));
+    assert!(check("event.missing == null", &context));
+  }
+
+  #[test]
+  fn rejects_malformed_guards() {
+    for guard in ["", "len(", "a >", "exec('rm')", "len(a, b)", "a.", "'open", "a = 1"] {
+      assert!(Guard::parse(guard).is_err(), "{}", guard);
+    }
+    assert!(Guard::parse(&format!("{}a{}", "(".repeat(64), ")".repeat(64))).is_err());
+  }
+}
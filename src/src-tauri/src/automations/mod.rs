@@ -1 +1,10 @@
+pub mod actions;
 pub mod api;
+pub mod bundle;
+pub mod executor;
+pub mod guard;
+pub mod pipeline;
+pub mod schedule;
+pub mod scheduler;
+pub mod steps;
+pub mod triggers;
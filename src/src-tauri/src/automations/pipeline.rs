@@ -0,0 +1,598 @@
+//! Typed steps that users chain into their own automations.
+//!
+//! Every pipeline step declares typed inputs and outputs. Its `args_json` is
+//! an object binding each input to a literal or to a reference:
+//! `{"$ref": "steps.<ordering>.<output>"}` reads an output of an earlier step
+//! and `{"$ref": "run.<param>"}` one of the run's `run_params`. Bindings are
+//! checked when an automation is saved and resolved again when it runs.
+//!
+//! Any step the backend runs can carry a `guard` (see `guard`), evaluated
+//! against `run.<param>` and `steps.<ordering>.<output>` before it runs.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::automations::guard::Guard;
+use crate::automations::steps::{
+  email_documents, email_to_json, find_step_handler, StepContext, StepPrompt,
+};
+use crate::db::models::automation_step::AutomationStep;
+use crate::db::models::email::Email;
+use crate::error::Error;
+use crate::llm::prompt::AdditionalDocument;
+
+const DEFAULT_FETCH_LIMIT: u64 = 50;
+const MAX_FETCH_LIMIT: u64 = 500;
+const ONE_DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+  Text,
+  Number,
+  /// Unix milliseconds.
+  Timestamp,
+  /// Array of emails as produced by `fetch_emails`.
+  Emails,
+}
+
+impl ValueType {
+  pub fn matches(&self, value: &Value) -> bool {
+    match self {
+      ValueType::Text => value.is_string(),
+      ValueType::Number => value.is_number(),
+      ValueType::Timestamp => value.is_i64() || value.is_u64(),
+      ValueType::Emails => value
+        .as_array()
+        .map(|emails| emails.iter().all(Value::is_object))
+        .unwrap_or(false),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Port {
+  pub name: &'static str,
+  pub value_type: ValueType,
+  pub required: bool,
+}
+
+const fn port(name: &'static str, value_type: ValueType, required: bool) -> Port {
+  Port { name, value_type, required }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineStepKind {
+  FetchEmails,
+  FilterEmails,
+  LlmSummarize,
+  Notify,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineStepSpec {
+  pub name: &'static str,
+  pub description: &'static str,
+  pub inputs: &'static [Port],
+  pub outputs: &'static [Port],
+  #[serde(skip)]
+  pub kind: PipelineStepKind,
+}
+
+pub const PIPELINE_STEPS: &[PipelineStepSpec] = &[
+  PipelineStepSpec {
+    name: "fetch_emails",
+    description: "Emails received between `since` and `until`, by default the day before the run.",
+    inputs: &[
+      port("since", ValueType::Timestamp, false),
+      port("until", ValueType::Timestamp, false),
+      port("limit", ValueType::Number, false),
+    ],
+    outputs: &[port("emails", ValueType::Emails, true)],
+    kind: PipelineStepKind::FetchEmails,
+  },
+  PipelineStepSpec {
+    name: "filter_emails",
+    description: "Keeps emails whose sender and subject contain the given text.",
+    inputs: &[
+      port("emails", ValueType::Emails, true),
+      port("sender", ValueType::Text, false),
+      port("subject", ValueType::Text, false),
+    ],
+    outputs: &[port("emails", ValueType::Emails, true)],
+    kind: PipelineStepKind::FilterEmails,
+  },
+  PipelineStepSpec {
+    name: "llm_summarize",
+    description: "Answers `template` with the emails and text as context.",
+    inputs: &[
+      port("template", ValueType::Text, true),
+      port("emails", ValueType::Emails, false),
+      port("text", ValueType::Text, false),
+    ],
+    outputs: &[port("text", ValueType::Text, true)],
+    kind: PipelineStepKind::LlmSummarize,
+  },
+  PipelineStepSpec {
+    name: "notify",
+    description: "Posts `text` to the run's thread in the feed.",
+    inputs: &[port("text", ValueType::Text, true)],
+    outputs: &[],
+    kind: PipelineStepKind::Notify,
+  },
+];
+
+/// `run_params` a step can reference.
+pub const RUN_PARAMS: &[Port] = &[
+  port("timestamp", ValueType::Timestamp, true),
+  port("event_id", ValueType::Number, false),
+  port("emails", ValueType::Emails, false),
+];
+
+/// What library steps with a backend handler expose to later steps.
+const LIBRARY_STEP_OUTPUTS: &[Port] = &[port("text", ValueType::Text, true)];
+
+/// Library steps only the app can run (`src/automations/steps`).
+const APP_ONLY_STEPS: &[&str] = &[
+  "about-me",
+  "business-coach",
+  "lead-scoring",
+  "post-safely",
+  "prompt",
+  "semantic-search",
+  "social-media-planner",
+  "strategic-plan",
+];
+
+pub fn find_pipeline_step(name: &str) -> Option<&'static PipelineStepSpec> {
+  PIPELINE_STEPS.iter().find(|spec| spec.name == name)
+}
+
+pub fn pipeline_step_names() -> Vec<&'static str> {
+  PIPELINE_STEPS.iter().map(|spec| spec.name).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Binding {
+  Literal(Value),
+  StepOutput { ordering: u64, output: String },
+  RunParam(String),
+}
+
+fn parse_binding(value: &Value) -> Result<Binding, String> {
+  let reference = match value.as_object().and_then(|object| object.get("$ref")) {
+    Some(reference) => reference,
+    None => return Ok(Binding::Literal(value.clone())),
+  };
+  let reference = reference
+    .as_str()
+    .ok_or_else(|| "`$ref` must be a string".to_string())?;
+  let parts: Vec<&str> = reference.split('.').collect();
+  match parts.as_slice() {
+    ["steps", ordering, output] => Ok(Binding::StepOutput {
+      ordering: ordering
+        .parse()
+        .map_err(|_| format!("`{}` does not name a step ordering", reference))?,
+      output: output.to_string(),
+    }),
+    ["run", param] => Ok(Binding::RunParam(param.to_string())),
+    _ => Err(format!(
+      "`{}` is not of the form `steps.<ordering>.<output>` or `run.<param>`",
+      reference
+    )),
+  }
+}
+
+fn parse_args(args_json: Option<&str>) -> Result<Map<String, Value>, String> {
+  match args_json.map(str::trim) {
+    None | Some("") => Ok(Map::new()),
+    Some(args_json) => match serde_json::from_str(args_json) {
+      Ok(Value::Object(args)) => Ok(args),
+      Ok(_) => Err("args_json must be an object".to_string()),
+      Err(error) => Err(format!("args_json is not valid JSON: {}", error)),
+    },
+  }
+}
+
+fn check_bindings(
+  spec: &PipelineStepSpec,
+  args: &Map<String, Value>,
+  earlier_outputs: &HashMap<u64, &'static [Port]>,
+) -> Vec<String> {
+  let mut errors = Vec::new();
+  for name in args.keys() {
+    if !spec.inputs.iter().any(|input| input.name == name) {
+      errors.push(format!("unknown input `{}`", name));
+    }
+  }
+  for input in spec.inputs {
+    let value = match args.get(input.name) {
+      Some(value) => value,
+      None => {
+        if input.required {
+          errors.push(format!("input `{}` is required", input.name));
+        }
+        continue;
+      }
+    };
+    let source_type = match parse_binding(value) {
+      Err(error) => {
+        errors.push(format!("input `{}`: {}", input.name, error));
+        continue;
+      }
+      Ok(Binding::Literal(value)) => {
+        if !input.value_type.matches(&value) {
+          errors.push(format!(
+            "input `{}` must be {:?}",
+            input.name, input.value_type
+          ));
+        }
+        continue;
+      }
+      Ok(Binding::StepOutput { ordering, output }) => {
+        match earlier_outputs.get(&ordering) {
+          None => {
+            errors.push(format!(
+              "input `{}` refers to step {}, which does not run before it",
+              input.name, ordering
+            ));
+            continue;
+          }
+          Some(outputs) => match outputs.iter().find(|port| port.name == output) {
+            Some(port) => port.value_type,
+            None => {
+              errors.push(format!(
+                "input `{}` refers to `{}`, which step {} does not output",
+                input.name, output, ordering
+              ));
+              continue;
+            }
+          },
+        }
+      }
+      Ok(Binding::RunParam(param)) => match RUN_PARAMS.iter().find(|port| port.name == param) {
+        Some(port) => port.value_type,
+        None => {
+          errors.push(format!("input `{}` refers to unknown run param `{}`", input.name, param));
+          continue;
+        }
+      },
+    };
+    if source_type != input.value_type {
+      errors.push(format!(
+        "input `{}` must be {:?} but is bound to {:?}",
+        input.name, input.value_type, source_type
+      ));
+    }
+  }
+  errors
+}
+
+/// Checks the steps of an automation before it is saved: names are known,
+/// pipeline inputs are bound to values or earlier outputs of the right type,
+/// guards parse, and app-only steps are not mixed with steps only the
+/// backend can run.
+pub fn validate_steps(steps: &[AutomationStep]) -> Result<(), Error> {
+  let mut steps: Vec<&AutomationStep> = steps.iter().collect();
+  steps.sort_by_key(|step| step.ordering);
+
+  let mut errors = Vec::new();
+  let mut orderings = HashSet::new();
+  let mut earlier_outputs: HashMap<u64, &'static [Port]> = HashMap::new();
+  let mut has_pipeline_step = false;
+  let mut app_only_step = None;
+  for step in steps {
+    if !orderings.insert(step.ordering) {
+      errors.push(format!("step {}: ordering is used twice", step.ordering));
+      continue;
+    }
+    if let Err(Error::KSError(error)) = Guard::parse_optional(step.guard.as_deref()) {
+      errors.push(format!("step {} ({}): {}", step.ordering, step.name, error));
+    }
+    if let Some(spec) = find_pipeline_step(&step.name) {
+      has_pipeline_step = true;
+      match parse_args(step.args_json.as_deref()) {
+        Ok(args) => errors.extend(
+          check_bindings(spec, &args, &earlier_outputs)
+            .into_iter()
+            .map(|error| format!("step {} ({}): {}", step.ordering, step.name, error)),
+        ),
+        Err(error) => errors.push(format!("step {} ({}): {}", step.ordering, step.name, error)),
+      }
+      earlier_outputs.insert(step.ordering, spec.outputs);
+    } else if find_step_handler(&step.name).is_some() {
+      earlier_outputs.insert(step.ordering, LIBRARY_STEP_OUTPUTS);
+    } else if APP_ONLY_STEPS.contains(&step.name.as_str()) {
+      if step.guard.as_deref().is_some_and(|guard| !guard.trim().is_empty()) {
+        errors.push(format!(
+          "step {} ({}): guards only apply to steps the backend runs",
+          step.ordering, step.name
+        ));
+      }
+      app_only_step = Some(step.name.clone());
+    } else {
+      errors.push(format!("step {}: `{}` is not a known step", step.ordering, step.name));
+    }
+  }
+  if let (true, Some(name)) = (has_pipeline_step, app_only_step) {
+    errors.push(format!(
+      "`{}` only runs in the app and cannot be combined with pipeline steps",
+      name
+    ));
+  }
+
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(Error::KSError(errors.join("; ")))
+  }
+}
+
+/// Outputs of the steps that ran so far, plus the run's parameters.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineState {
+  pub run_params: Map<String, Value>,
+  pub outputs: HashMap<u64, Map<String, Value>>,
+}
+
+impl PipelineState {
+  /// What step guards are evaluated against.
+  pub fn guard_context(&self) -> Value {
+    let steps: Map<String, Value> = self
+      .outputs
+      .iter()
+      .map(|(ordering, outputs)| (ordering.to_string(), Value::Object(outputs.clone())))
+      .collect();
+    json!({ "run": self.run_params, "steps": steps })
+  }
+
+  fn resolve(&self, binding: Binding) -> Option<Value> {
+    match binding {
+      Binding::Literal(value) => Some(value),
+      Binding::StepOutput { ordering, output } => self
+        .outputs
+        .get(&ordering)
+        .and_then(|outputs| outputs.get(&output))
+        .cloned(),
+      Binding::RunParam(param) => self.run_params.get(&param).cloned(),
+    }
+  }
+
+  /// The inputs of `step`, with references replaced by the values they
+  /// point to.
+  pub fn resolve_inputs(
+    &self,
+    spec: &PipelineStepSpec,
+    args_json: Option<&str>,
+  ) -> Result<Map<String, Value>, Error> {
+    let args = parse_args(args_json).map_err(Error::KSError)?;
+    let mut inputs = Map::new();
+    for input in spec.inputs {
+      let value = match args.get(input.name) {
+        Some(value) => parse_binding(value)
+          .map_err(Error::KSError)
+          .map(|binding| self.resolve(binding))?,
+        None => None,
+      };
+      match value {
+        Some(value) if input.value_type.matches(&value) => {
+          inputs.insert(input.name.to_string(), value);
+        }
+        Some(_) => {
+          return Err(Error::KSError(format!(
+            "Input `{}` of {} is not {:?}",
+            input.name, spec.name, input.value_type
+          )))
+        }
+        None if input.required => {
+          return Err(Error::KSError(format!(
+            "Input `{}` of {} has no value",
+            input.name, spec.name
+          )))
+        }
+        None => {}
+      }
+    }
+    Ok(inputs)
+  }
+}
+
+pub enum PipelineStepOutcome {
+  Outputs(Map<String, Value>),
+  /// Needs an LLM answer, which becomes the step's `text` output.
+  Prompt(StepPrompt),
+  /// Text to post to the run's thread.
+  Notify(String),
+}
+
+fn fetch_emails(inputs: &Map<String, Value>, context: &StepContext) -> Result<Map<String, Value>, Error> {
+  let until = inputs
+    .get("until")
+    .and_then(Value::as_i64)
+    .unwrap_or(context.timestamp);
+  let since = inputs
+    .get("since")
+    .and_then(Value::as_i64)
+    .unwrap_or(until - ONE_DAY_MILLIS);
+  let limit = inputs
+    .get("limit")
+    .and_then(Value::as_f64)
+    .map(|limit| limit.max(0.0) as u64)
+    .unwrap_or(DEFAULT_FETCH_LIMIT)
+    .min(MAX_FETCH_LIMIT);
+  let emails = Email::filter_account_emails(
+    Some(context.account_id),
+    limit as usize,
+    None,
+    Some(since / 1000),
+    Some(until / 1000),
+  )?;
+  let mut outputs = Map::new();
+  outputs.insert(
+    "emails".to_string(),
+    Value::Array(emails.iter().map(email_to_json).collect()),
+  );
+  Ok(outputs)
+}
+
+fn filter_emails(inputs: &Map<String, Value>) -> Map<String, Value> {
+  let contains = |email: &Value, field: &str, input: &str| match inputs.get(input).and_then(Value::as_str) {
+    Some(needle) => email
+      .get(field)
+      .and_then(Value::as_str)
+      .map(|value| value.to_lowercase().contains(&needle.to_lowercase()))
+      .unwrap_or(false),
+    None => true,
+  };
+  let emails: Vec<Value> = inputs
+    .get("emails")
+    .and_then(Value::as_array)
+    .map(|emails| {
+      emails
+        .iter()
+        .filter(|email| contains(email, "sender", "sender") && contains(email, "subject", "subject"))
+        .cloned()
+        .collect()
+    })
+    .unwrap_or_default();
+  let mut outputs = Map::new();
+  outputs.insert("emails".to_string(), Value::Array(emails));
+  outputs
+}
+
+fn llm_summarize(inputs: &Map<String, Value>) -> Result<StepPrompt, Error> {
+  let emails = inputs
+    .get("emails")
+    .and_then(Value::as_array)
+    .cloned()
+    .unwrap_or_default();
+  let (mut additional_documents, document_ids) = email_documents(&emails)?;
+  if let Some(text) = inputs.get("text").and_then(Value::as_str) {
+    additional_documents.push(AdditionalDocument {
+      title: "Previous step".to_string(),
+      content: text.to_string(),
+      doc_type: None,
+    });
+  }
+  Ok(StepPrompt {
+    prompt: inputs
+      .get("template")
+      .and_then(Value::as_str)
+      .unwrap_or_default()
+      .to_string(),
+    additional_documents,
+    document_ids,
+  })
+}
+
+/// Runs one pipeline step on resolved inputs. Touches SQLite, so callers run
+/// it on the blocking pool.
+pub fn run_pipeline_step(
+  spec: &PipelineStepSpec,
+  inputs: &Map<String, Value>,
+  context: &StepContext,
+) -> Result<PipelineStepOutcome, Error> {
+  match spec.kind {
+    PipelineStepKind::FetchEmails => fetch_emails(inputs, context).map(PipelineStepOutcome::Outputs),
+    PipelineStepKind::FilterEmails => Ok(PipelineStepOutcome::Outputs(filter_emails(inputs))),
+    PipelineStepKind::LlmSummarize => llm_summarize(inputs).map(PipelineStepOutcome::Prompt),
+    PipelineStepKind::Notify => Ok(PipelineStepOutcome::Notify(
+      inputs
+        .get("text")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string(),
+    )),
+  }
+}
+
+/// Outputs as stored in the run's step results. Email bodies are dropped;
+/// the emails themselves stay in the database.
+pub fn recorded_outputs(outputs: &Map<String, Value>) -> Value {
+  let mut recorded = outputs.clone();
+  for value in recorded.values_mut() {
+    if let Some(emails) = value.as_array_mut() {
+      for email in emails.iter_mut() {
+        if let Some(email) = email.as_object_mut() {
+          email.remove("body");
+        }
+      }
+    }
+  }
+  json!(recorded)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn step(ordering: u64, name: &str, args_json: Option<&str>) -> AutomationStep {
+    AutomationStep {
+      id: None,
+      automation_uuid: "uuid".to_string(),
+      name: name.to_string(),
+      ordering,
+      args_json: args_json.map(str::to_string),
+      guard: None,
+    }
+  }
+
+  #[test]
+  fn accepts_a_chained_pipeline() {
+    let steps = vec![
+      step(0, "fetch_emails", Some(r#"{"until": {"$ref": "run.timestamp"}}"#)),
+      step(1, "filter_emails", Some(r#"{"emails": {"$ref": "steps.0.emails"}, "sender": "acme.com"}"#)),
+      step(2, "llm_summarize", Some(r#"{"template": "Summarize", "emails": {"$ref": "steps.1.emails"}}"#)),
+      step(3, "notify", Some(r#"{"text": {"$ref": "steps.2.text"}}"#)),
+    ];
+    assert!(validate_steps(&steps).is_ok());
+  }
+
+  #[test]
+  fn rejects_forward_references_and_type_mismatches() {
+    let forward = vec![
+      step(0, "notify", Some(r#"{"text": {"$ref": "steps.1.text"}}"#)),
+      step(1, "llm_summarize", Some(r#"{"template": "Hi"}"#)),
+    ];
+    assert!(validate_steps(&forward).is_err());
+
+    let mismatch = vec![
+      step(0, "fetch_emails", None),
+      step(1, "notify", Some(r#"{"text": {"$ref": "steps.0.emails"}}"#)),
+    ];
+    assert!(validate_steps(&mismatch).is_err());
+  }
+
+  #[test]
+  fn resolves_references_against_earlier_outputs() {
+    let mut state = PipelineState::default();
+    let mut outputs = Map::new();
+    outputs.insert("text".to_string(), json!("done"));
+    state.outputs.insert(2, outputs);
+    let spec = find_pipeline_step("notify").unwrap();
+    let inputs = state
+      .resolve_inputs(spec, Some(r#"{"text": {"$ref": "steps.2.text"}}"#))
+      .unwrap();
+    assert_eq!(inputs.get("text"), Some(&json!("done")));
+  }
+
+  #[test]
+  fn guards_are_checked_and_see_earlier_outputs() {
+    let mut guarded = step(1, "notify", Some(r#"{"text": "Hot lead"}"#));
+    guarded.guard = Some("number(steps.0.text) >".to_string());
+    assert!(validate_steps(&[guarded.clone()]).is_err());
+    guarded.guard = Some("number(steps.0.text) > 70".to_string());
+    assert!(validate_steps(&[guarded]).is_ok());
+
+    let mut state = PipelineState::default();
+    let mut outputs = Map::new();
+    outputs.insert("text".to_string(), json!("Score: 82"));
+    state.outputs.insert(0, outputs);
+    let guard = Guard::parse("number(steps.0.text) > 70").unwrap();
+    assert!(guard.matches(&state.guard_context()));
+  }
+}
@@ -0,0 +1,511 @@
+//! When cadence triggers fire.
+//!
+//! A cadence is a five-field cron expression, a fixed number of minutes or a
+//! day of the month, evaluated in an IANA timezone (the system one when the
+//! cadence has none). Wall times skipped by a DST change fire when the clocks
+//! jump, and repeated wall times fire once, on the first pass.
+//!
+//! `parse_schedule_text` turns what users type ("every weekday at 9am",
+//! "every 15 minutes", raw cron) into a schedule. Cadence triggers and
+//! Clawdbot scheduled tasks share it.
+
+use chrono::{
+  DateTime, Datelike, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime,
+  TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::db::models::cadence_trigger::CadenceTrigger;
+use crate::error::Error;
+
+/// How far `next_after` searches, enough for `0 0 29 2 *`.
+const MAX_SEARCH_DAYS: i64 = 366 * 8;
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+const MONTH_NAMES: &[&str] = &[
+  "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const FULL_DAY_NAMES: &[&str] = &[
+  "sunday", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday",
+];
+
+static EVERY_INTERVAL: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"every\s+(\d+)\s*(minute|min|hour|hr|day)s?").unwrap());
+static EVERY_DAY_AT: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"every\s+day\s+at\s+(\d{1,2})(?::(\d{2}))?\s*(am|pm)?").unwrap());
+static AT_TIME: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"at\s+(\d{1,2})(?::(\d{2}))?\s*(am|pm)?").unwrap());
+
+/// A standard five-field cron expression: minute, hour, day of month, month
+/// and day of week. Each field is a bit set of the values it allows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+  minutes: u64,
+  hours: u64,
+  days_of_month: u64,
+  months: u64,
+  days_of_week: u64,
+  any_day_of_month: bool,
+  any_day_of_week: bool,
+}
+
+fn parse_cron_value(text: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, String> {
+  let value = match names.iter().position(|name| name.eq_ignore_ascii_case(text)) {
+    Some(index) => index as u32 + min,
+    None => text
+      .parse()
+      .map_err(|_| format!("`{}` is not a number", text))?,
+  };
+  if value < min || value > max {
+    return Err(format!("{} is outside {}-{}", value, min, max));
+  }
+  Ok(value)
+}
+
+fn parse_cron_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+  let mut bits = 0u64;
+  for part in field.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) => (
+        range,
+        step
+          .parse::<u32>()
+          .ok()
+          .filter(|step| *step > 0)
+          .ok_or_else(|| format!("`{}` is not a step", step))?,
+      ),
+      None => (part, 1),
+    };
+    let (start, end) = if range == "*" {
+      (min, max)
+    } else if let Some((start, end)) = range.split_once('-') {
+      (
+        parse_cron_value(start, min, max, names)?,
+        parse_cron_value(end, min, max, names)?,
+      )
+    } else {
+      let start = parse_cron_value(range, min, max, names)?;
+      (start, if part.contains('/') { max } else { start })
+    };
+    if start > end {
+      return Err(format!("`{}` is an empty range", range));
+    }
+    for value in (start..=end).step_by(step as usize) {
+      bits |= 1 << value;
+    }
+  }
+  Ok(bits)
+}
+
+fn values(bits: u64) -> impl Iterator<Item = u32> {
+  (0..64).filter(move |value| bits & (1 << value) != 0)
+}
+
+/// The instant a wall time in `tz` happens at.
+fn resolve_local<Z: TimeZone>(tz: &Z, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+  match tz.from_local_datetime(&naive) {
+    LocalResult::Single(datetime) => Some(datetime.with_timezone(&Utc)),
+    LocalResult::Ambiguous(earliest, _) => Some(earliest.with_timezone(&Utc)),
+    LocalResult::None => tz
+      .from_local_datetime(&(naive + Duration::hours(1)))
+      .earliest()
+      .map(|datetime| datetime.with_timezone(&Utc)),
+  }
+}
+
+impl CronExpr {
+  pub fn parse(expr: &str) -> Result<CronExpr, Error> {
+    let expr = match expr.trim() {
+      "@hourly" => "0 * * * *",
+      "@daily" | "@midnight" => "0 0 * * *",
+      "@weekly" => "0 0 * * 0",
+      "@monthly" => "0 0 1 * *",
+      "@yearly" | "@annually" => "0 0 1 1 *",
+      expr => expr,
+    };
+    let fields: Vec<&str> = expr.split_whitespace().collect();
+    if fields.len() != 5 {
+      return Err(Error::KSError(format!(
+        "`{}` is not a cron expression; expected minute hour day month weekday",
+        expr
+      )));
+    }
+    let field = |index: usize, min: u32, max: u32, names: &[&str]| {
+      parse_cron_field(fields[index], min, max, names)
+        .map_err(|error| Error::KSError(format!("Invalid cron expression `{}`: {}", expr, error)))
+    };
+    let mut days_of_week = field(4, 0, 7, DAY_NAMES)?;
+    if days_of_week & (1 << 7) != 0 {
+      days_of_week = (days_of_week | 1) & !(1 << 7);
+    }
+    Ok(CronExpr {
+      minutes: field(0, 0, 59, &[])?,
+      hours: field(1, 0, 23, &[])?,
+      days_of_month: field(2, 1, 31, &[])?,
+      months: field(3, 1, 12, MONTH_NAMES)?,
+      days_of_week,
+      any_day_of_month: fields[2] == "*",
+      any_day_of_week: fields[4] == "*",
+    })
+  }
+
+  /// Like cron, a date matches either day field when both are restricted.
+  fn matches_date(&self, date: NaiveDate) -> bool {
+    if self.months & (1 << date.month()) == 0 {
+      return false;
+    }
+    let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+    let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+    match (self.any_day_of_month, self.any_day_of_week) {
+      (false, false) => day_of_month || day_of_week,
+      _ => day_of_month && day_of_week,
+    }
+  }
+
+  fn next_after<Z: TimeZone>(&self, tz: &Z, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let local = after.with_timezone(tz).naive_local();
+    let start = local.with_second(0)?.with_nanosecond(0)?;
+    for day in 0..MAX_SEARCH_DAYS {
+      let date = start.date().checked_add_signed(Duration::days(day))?;
+      if !self.matches_date(date) {
+        continue;
+      }
+      for hour in values(self.hours) {
+        for minute in values(self.minutes) {
+          let naive = date.and_hms_opt(hour, minute, 0)?;
+          if naive < start {
+            continue;
+          }
+          match resolve_local(tz, naive) {
+            Some(fire) if fire > after => return Some(fire),
+            _ => {}
+          }
+        }
+      }
+    }
+    None
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+  Cron(CronExpr),
+  /// Every so many minutes from local midnight.
+  EveryMinutes(u32),
+  /// The day is clamped to the last day of shorter months.
+  Monthly { day: u32, time: NaiveTime },
+}
+
+fn last_day_of_month(year: i32, month: u32) -> u32 {
+  let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+  NaiveDate::from_ymd_opt(next_year, next_month, 1)
+    .and_then(|date| date.pred_opt())
+    .map(|date| date.day())
+    .unwrap_or(28)
+}
+
+impl Schedule {
+  fn next_after<Z: TimeZone>(&self, tz: &Z, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let local = after.with_timezone(tz).naive_local();
+    match self {
+      Schedule::Cron(expr) => expr.next_after(tz, after),
+      Schedule::EveryMinutes(minutes) => {
+        let mut date = local.date();
+        let mut slot = (local.hour() * 60 + local.minute()) / minutes * minutes;
+        for _ in 0..3 {
+          while slot < MINUTES_PER_DAY {
+            let naive = date.and_hms_opt(slot / 60, slot % 60, 0)?;
+            match resolve_local(tz, naive) {
+              Some(fire) if fire > after => return Some(fire),
+              _ => slot += minutes,
+            }
+          }
+          date = date.succ_opt()?;
+          slot = 0;
+        }
+        None
+      }
+      Schedule::Monthly { day, time } => {
+        let (mut year, mut month) = (local.year(), local.month());
+        for _ in 0..13 {
+          let date = NaiveDate::from_ymd_opt(year, month, (*day).min(last_day_of_month(year, month)))?;
+          match resolve_local(tz, date.and_time(*time)) {
+            Some(fire) if fire > after => return Some(fire),
+            _ => {}
+          }
+          (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        }
+        None
+      }
+    }
+  }
+}
+
+/// A schedule and the timezone its wall times are in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CadenceSchedule {
+  pub schedule: Schedule,
+  pub timezone: Option<Tz>,
+}
+
+fn parse_clock(time: Option<&str>) -> Result<NaiveTime, Error> {
+  match time {
+    Some(time) => NaiveTime::parse_from_str(time.trim(), "%H:%M")
+      .map_err(|_| Error::KSError(format!("`{}` is not a HH:MM time", time))),
+    None => Ok(NaiveTime::MIN),
+  }
+}
+
+fn parse_weekday(day_of_week: Option<&str>) -> Result<u32, Error> {
+  let day_of_week = match day_of_week {
+    Some(day_of_week) => day_of_week.trim().to_lowercase(),
+    None => return Ok(1),
+  };
+  if let Some(index) = DAY_NAMES.iter().position(|name| day_of_week.starts_with(name)) {
+    return Ok(index as u32);
+  }
+  match day_of_week.parse::<u32>() {
+    Ok(day) if day <= 7 => Ok(day % 7),
+    _ => Err(Error::KSError(format!("`{}` is not a day of the week", day_of_week))),
+  }
+}
+
+pub fn parse_timezone(timezone: &str) -> Result<Tz, Error> {
+  timezone
+    .trim()
+    .parse()
+    .map_err(|_| Error::KSError(format!("`{}` is not an IANA timezone", timezone)))
+}
+
+impl CadenceSchedule {
+  /// The schedule of a cadence, or `None` for cadences that only run on
+  /// request.
+  pub fn from_cadence(cadence: &CadenceTrigger) -> Result<Option<CadenceSchedule>, Error> {
+    if matches!(cadence.cadence_type.as_str(), "never" | "other") {
+      return Ok(None);
+    }
+    let time = parse_clock(cadence.time.as_deref())?;
+    let at = |days_of_month: &str, days_of_week: &str| {
+      CronExpr::parse(&format!(
+        "{} {} {} * {}",
+        time.minute(),
+        time.hour(),
+        days_of_month,
+        days_of_week
+      ))
+      .map(Schedule::Cron)
+    };
+    let schedule = match cadence.cadence_type.as_str() {
+      "every_minute" => Schedule::EveryMinutes(1),
+      "every_minutes" => match cadence.interval_minutes {
+        Some(minutes) if (1..=MINUTES_PER_DAY).contains(&minutes) => Schedule::EveryMinutes(minutes),
+        _ => {
+          return Err(Error::KSError(format!(
+            "every_minutes cadences need an interval between 1 and {} minutes",
+            MINUTES_PER_DAY
+          )))
+        }
+      },
+      "hourly" => Schedule::Cron(CronExpr::parse(&format!("{} * * * *", time.minute()))?),
+      "daily" => at("*", "*")?,
+      "weekly" => at("*", &parse_weekday(cadence.day_of_week.as_deref())?.to_string())?,
+      "business_days" => at("*", "1-5")?,
+      "monthly" => match cadence.day_of_month.unwrap_or(1) {
+        day @ 1..=31 => Schedule::Monthly { day, time },
+        day => return Err(Error::KSError(format!("{} is not a day of the month", day))),
+      },
+      "cron" => {
+        let text = cadence
+          .cron_expr
+          .as_deref()
+          .ok_or_else(|| Error::KSError("cron cadences need a cron expression".into()))?;
+        match parse_schedule_text(text) {
+          Some(ScheduleText::Cron(expr)) => Schedule::Cron(CronExpr::parse(&expr)?),
+          Some(ScheduleText::Interval { minutes }) if minutes <= MINUTES_PER_DAY as u64 => {
+            Schedule::EveryMinutes(minutes as u32)
+          }
+          _ => return Err(Error::KSError(format!("`{}` is not a schedule", text))),
+        }
+      }
+      cadence_type => {
+        return Err(Error::KSError(format!("Unknown cadence type `{}`", cadence_type)))
+      }
+    };
+    let timezone = match cadence.timezone.as_deref() {
+      Some(timezone) if !timezone.trim().is_empty() => Some(parse_timezone(timezone)?),
+      _ => None,
+    };
+    Ok(Some(CadenceSchedule { schedule, timezone }))
+  }
+
+  /// The first time strictly after `after` the cadence fires.
+  pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match &self.timezone {
+      Some(timezone) => self.schedule.next_after(timezone, after),
+      None => self.schedule.next_after(&Local, after),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleText {
+  Interval { minutes: u64 },
+  /// A validated five-field cron expression.
+  Cron(String),
+}
+
+/// Hour and minute of an `at 9`, `at 9:30pm` capture.
+fn captured_clock(caps: &regex::Captures) -> Option<(u32, u32)> {
+  let mut hour: u32 = caps.get(1)?.as_str().parse().ok()?;
+  let minute = match caps.get(2) {
+    Some(minute) => minute.as_str().parse::<u32>().ok()?,
+    None => 0,
+  };
+  match caps.get(3).map(|m| m.as_str()) {
+    Some("pm") if hour < 12 => hour += 12,
+    Some("am") if hour == 12 => hour = 0,
+    _ => {}
+  }
+  (hour < 24 && minute < 60).then_some((hour, minute))
+}
+
+/// Reads a schedule users typed: `every N minutes|hours|days`, `every hour`,
+/// `every minute`, `every day at 9am`, `every weekday at 8:30`,
+/// `every monday at 5pm` or a cron expression.
+pub fn parse_schedule_text(text: &str) -> Option<ScheduleText> {
+  let s = text.trim().to_lowercase();
+
+  if s.contains("every") {
+    if let Some(caps) = EVERY_INTERVAL.captures(&s) {
+      let num: u64 = caps.get(1).and_then(|m| m.as_str().parse().ok()).filter(|num| *num > 0)?;
+      let minutes = match caps.get(2).map(|m| m.as_str()) {
+        Some("minute") | Some("min") => num,
+        Some("day") => num * 24 * 60,
+        _ => num * 60,
+      };
+      return Some(ScheduleText::Interval { minutes });
+    }
+    if s.contains("minute") && !s.contains("at") {
+      return Some(ScheduleText::Interval { minutes: 1 });
+    }
+    if s.contains("hour") && !s.contains("at") {
+      return Some(ScheduleText::Interval { minutes: 60 });
+    }
+    let clock = |default: (u32, u32)| match AT_TIME.captures(&s) {
+      Some(caps) => captured_clock(&caps),
+      None => Some(default),
+    };
+    if let Some(caps) = EVERY_DAY_AT.captures(&s) {
+      let (hour, minute) = captured_clock(&caps)?;
+      return Some(ScheduleText::Cron(format!("{} {} * * *", minute, hour)));
+    }
+    if s.contains("weekday") || s.contains("business day") {
+      let (hour, minute) = clock((9, 0))?;
+      return Some(ScheduleText::Cron(format!("{} {} * * 1-5", minute, hour)));
+    }
+    let day = s.split(|c: char| !c.is_alphabetic()).find_map(|word| {
+      let word = word.trim_end_matches('s');
+      DAY_NAMES
+        .iter()
+        .zip(FULL_DAY_NAMES)
+        .position(|(name, full_name)| word == *name || word == full_name.trim_end_matches('s'))
+    });
+    if let Some(day) = day {
+      let (hour, minute) = clock((9, 0))?;
+      return Some(ScheduleText::Cron(format!("{} {} * * {}", minute, hour, day)));
+    }
+  }
+
+  CronExpr::parse(&s).ok().map(|_| ScheduleText::Cron(s))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn utc(text: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+  }
+
+  fn cadence(cadence_type: &str, time: Option<&str>) -> CadenceTrigger {
+    CadenceTrigger {
+      id: None,
+      automation_uuid: "uuid".to_string(),
+      cadence_type: cadence_type.to_string(),
+      day_of_week: None,
+      time: time.map(str::to_string),
+      cron_expr: None,
+      timezone: Some("America/New_York".to_string()),
+      interval_minutes: None,
+      day_of_month: None,
+    }
+  }
+
+  #[test]
+  fn parses_cron_fields() {
+    let expr = CronExpr::parse("*/15 9-17 * jan-mar mon-fri").unwrap();
+    assert!(expr.matches_date(NaiveDate::from_ymd_opt(2026, 2, 2).unwrap()));
+    assert!(!expr.matches_date(NaiveDate::from_ymd_opt(2026, 2, 1).unwrap()));
+    assert!(!expr.matches_date(NaiveDate::from_ymd_opt(2026, 4, 1).unwrap()));
+    assert!(CronExpr::parse("60 * * * *").is_err());
+    assert!(CronExpr::parse("* * *").is_err());
+  }
+
+  #[test]
+  fn daily_cadences_follow_dst() {
+    let schedule = CadenceSchedule::from_cadence(&cadence("daily", Some("02:30")))
+      .unwrap()
+      .unwrap();
+    // 02:30 does not exist on 2026-03-08 in New York; the run moves to 03:30 EDT.
+    assert_eq!(
+      schedule.next_after(utc("2026-03-08T05:00:00Z")),
+      Some(utc("2026-03-08T07:30:00Z"))
+    );
+    assert_eq!(
+      schedule.next_after(utc("2026-03-08T07:30:00Z")),
+      Some(utc("2026-03-09T06:30:00Z"))
+    );
+  }
+
+  #[test]
+  fn repeated_wall_times_fire_once() {
+    let schedule = CadenceSchedule::from_cadence(&cadence("daily", Some("01:30")))
+      .unwrap()
+      .unwrap();
+    let first = schedule.next_after(utc("2026-11-01T04:00:00Z")).unwrap();
+    assert_eq!(first, utc("2026-11-01T05:30:00Z"));
+    assert_eq!(schedule.next_after(first), Some(utc("2026-11-02T06:30:00Z")));
+  }
+
+  #[test]
+  fn monthly_cadences_clamp_to_the_last_day() {
+    let mut monthly = cadence("monthly", Some("09:00"));
+    monthly.day_of_month = Some(31);
+    let schedule = CadenceSchedule::from_cadence(&monthly).unwrap().unwrap();
+    assert_eq!(
+      schedule.next_after(utc("2026-02-01T00:00:00Z")),
+      Some(utc("2026-02-28T14:00:00Z"))
+    );
+  }
+
+  #[test]
+  fn reads_schedule_text() {
+    assert_eq!(
+      parse_schedule_text("every 15 minutes"),
+      Some(ScheduleText::Interval { minutes: 15 })
+    );
+    assert_eq!(
+      parse_schedule_text("Every weekday at 8:30am"),
+      Some(ScheduleText::Cron("30 8 * * 1-5".to_string()))
+    );
+    assert_eq!(
+      parse_schedule_text("every friday at 5pm"),
+      Some(ScheduleText::Cron("0 17 * * 5".to_string()))
+    );
+    assert_eq!(parse_schedule_text("0 9 1 * *"), Some(ScheduleText::Cron("0 9 1 * *".to_string())));
+    assert_eq!(parse_schedule_text("whenever"), None);
+  }
+}
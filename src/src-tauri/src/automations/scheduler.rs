@@ -0,0 +1,243 @@
+//! Creates cadence runs as they come due instead of a week ahead.
+//!
+//! Each cadence of each user has one `automation_schedules` row holding its
+//! next fire time. Once that time passes a sweep stores the run, keyed by
+//! automation and fire time so repeated sweeps cannot duplicate it, and moves
+//! the row to the following occurrence. Runs the executor handles get their
+//! feed item when they execute; runs the app executes get one right away,
+//! since the app finds runs through the feed.
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::automations::executor::runs_in_backend;
+use crate::automations::schedule::CadenceSchedule;
+use crate::db::models::automation::Automation;
+use crate::db::models::automation_run::{AutomationRun, AutomationRunStatus};
+use crate::db::models::automation_schedule::AutomationSchedule;
+use crate::db::models::automation_step::AutomationStep;
+use crate::db::models::cadence_trigger::CadenceTrigger;
+use crate::db::models::feed_item::FeedItem;
+use crate::error::Error;
+
+const DUE_SCHEDULES_PER_SWEEP: u32 = 50;
+/// Bounds the walk over fire times missed while the app was closed.
+const MAX_MISSED_FIRES: usize = 100_000;
+
+fn from_millis(millis: i64) -> Result<DateTime<Utc>, Error> {
+  DateTime::from_timestamp_millis(millis)
+    .ok_or_else(|| Error::KSError(format!("{} is not a valid timestamp", millis)))
+}
+
+/// Starts a schedule for every cadence `user_id` has none for yet and drops
+/// the schedules of removed cadences.
+pub fn sync_schedules(user_id: u64, now_millis: i64) -> Result<(), Error> {
+  AutomationSchedule::delete_orphaned()?;
+  let now = from_millis(now_millis)?;
+  for cadence in CadenceTrigger::find_all()? {
+    let cadence_id = match cadence.id {
+      Some(cadence_id) => cadence_id,
+      None => continue,
+    };
+    if AutomationSchedule::find_by_cadence(cadence_id, user_id)?.is_some() {
+      continue;
+    }
+    let schedule = match CadenceSchedule::from_cadence(&cadence) {
+      Ok(Some(schedule)) => schedule,
+      Ok(None) => continue,
+      Err(error) => {
+        log::warn!(
+          "Not scheduling cadence {} of {} - {:?}",
+          cadence_id,
+          cadence.automation_uuid,
+          error
+        );
+        continue;
+      }
+    };
+    let mut automation_schedule = AutomationSchedule {
+      id: None,
+      cadence_id,
+      automation_uuid: cadence.automation_uuid.clone(),
+      user_id,
+      next_fire_timestamp: schedule.next_after(now).map(|fire| fire.timestamp_millis()),
+      last_fire_timestamp: None,
+    };
+    automation_schedule.create()?;
+  }
+  Ok(())
+}
+
+/// Stores `run` unless a run with its `run_key` exists and returns whether
+/// it did. Runs the app executes also get their feed item here.
+pub(crate) fn store_run(run: &mut AutomationRun) -> Result<bool, Error> {
+  if !run.create_if_new()? {
+    return Ok(false);
+  }
+  if !runs_in_backend(&AutomationStep::find_by_automation_uuid(&run.automation_uuid)?) {
+    let automation = Automation::find_by_uuid(run.automation_uuid.clone())?;
+    let mut feed_item = FeedItem {
+      id: None,
+      title: Some(automation.name),
+      timestamp: run.schedule_timestamp,
+      deleted: None,
+    };
+    feed_item.create()?;
+    run.feed_item_id = feed_item.id;
+    run.update()?;
+  }
+  Ok(true)
+}
+
+/// Stores the run of a due schedule and advances it. Of several fire times
+/// missed in a row only the latest gets a run. Returns whether a run was
+/// created.
+fn materialize(automation_schedule: &mut AutomationSchedule, now: DateTime<Utc>) -> Result<bool, Error> {
+  let due = match automation_schedule.next_fire_timestamp {
+    Some(due) => due,
+    None => return Ok(false),
+  };
+  let schedule = match CadenceTrigger::find_by_id(automation_schedule.cadence_id)? {
+    Some(cadence) => CadenceSchedule::from_cadence(&cadence).unwrap_or_else(|error| {
+      log::warn!("Stopping schedule of cadence {} - {:?}", cadence.id.unwrap_or_default(), error);
+      None
+    }),
+    None => None,
+  };
+  let schedule = match schedule {
+    Some(schedule) => schedule,
+    None => {
+      automation_schedule.advance(due, None)?;
+      return Ok(false);
+    }
+  };
+
+  let mut fire = from_millis(due)?;
+  for _ in 0..MAX_MISSED_FIRES {
+    match schedule.next_after(fire) {
+      Some(next) if next <= now => fire = next,
+      _ => break,
+    }
+  }
+  let fire_millis = fire.timestamp_millis();
+
+  let automation_uuid = automation_schedule.automation_uuid.clone();
+  let mut run = AutomationRun {
+    id: None,
+    automation_uuid: automation_uuid.clone(),
+    user_id: automation_schedule.user_id,
+    thread_id: None,
+    schedule_timestamp: Some(fire_millis),
+    execution_timestamp: None,
+    run_params: Some(json!({ "timestamp": fire_millis }).to_string()),
+    feed_item_id: None,
+    status: Some(AutomationRunStatus::Scheduled),
+    error: None,
+    step_results: None,
+    run_key: Some(AutomationRun::cadence_run_key(&automation_uuid, fire_millis)),
+    finished_timestamp: None,
+    attempts: 0,
+    action_results: None,
+  };
+  let created = store_run(&mut run)?;
+
+  let next_fire = schedule.next_after(now).map(|next| next.timestamp_millis());
+  automation_schedule.advance(fire_millis, next_fire)?;
+  Ok(created)
+}
+
+/// Creates the runs of every due schedule. Returns how many were created.
+pub fn materialize_due_runs(now_millis: i64) -> Result<usize, Error> {
+  let now = from_millis(now_millis)?;
+  let mut created = 0;
+  for mut automation_schedule in AutomationSchedule::find_due(now_millis, DUE_SCHEDULES_PER_SWEEP)? {
+    match materialize(&mut automation_schedule, now) {
+      Ok(true) => created += 1,
+      Ok(false) => {}
+      Err(error) => log::error!(
+        "Failed to create the due run of cadence {} - {:?}",
+        automation_schedule.cadence_id,
+        error
+      ),
+    }
+  }
+  Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+  /// 2024-01-01 00:00 UTC.
+  const MIDNIGHT: i64 = 1_704_067_200_000;
+
+  fn hourly_schedule(automation_uuid: &str) -> AutomationSchedule {
+    let mut step = AutomationStep {
+      id: None,
+      automation_uuid: automation_uuid.to_string(),
+      name: "email-summary".to_string(),
+      ordering: 0,
+      args_json: None,
+      guard: None,
+    };
+    step.create().unwrap();
+    let mut cadence = CadenceTrigger {
+      id: None,
+      automation_uuid: automation_uuid.to_string(),
+      cadence_type: "every_minutes".to_string(),
+      day_of_week: None,
+      time: None,
+      cron_expr: None,
+      timezone: Some("UTC".to_string()),
+      interval_minutes: Some(60),
+      day_of_month: None,
+    };
+    cadence.create().unwrap();
+    let mut automation_schedule = AutomationSchedule {
+      id: None,
+      cadence_id: cadence.id.unwrap(),
+      automation_uuid: automation_uuid.to_string(),
+      user_id: 1,
+      next_fire_timestamp: Some(MIDNIGHT),
+      last_fire_timestamp: None,
+    };
+    automation_schedule.create().unwrap();
+    automation_schedule
+  }
+
+  #[test]
+  fn only_the_latest_missed_fire_gets_a_run() {
+    let automation_uuid = "scheduler-test-missed";
+    let mut automation_schedule = hourly_schedule(automation_uuid);
+    let now = from_millis(MIDNIGHT + 3 * HOUR_MILLIS + HOUR_MILLIS / 2).unwrap();
+
+    assert!(materialize(&mut automation_schedule, now).unwrap());
+
+    for hour in 0..3 {
+      let run_key = AutomationRun::cadence_run_key(automation_uuid, MIDNIGHT + hour * HOUR_MILLIS);
+      assert!(AutomationRun::find_by_run_key(&run_key).unwrap().is_none());
+    }
+    let latest = AutomationRun::cadence_run_key(automation_uuid, MIDNIGHT + 3 * HOUR_MILLIS);
+    let run = AutomationRun::find_by_run_key(&latest).unwrap().unwrap();
+    assert_eq!(run.schedule_timestamp, Some(MIDNIGHT + 3 * HOUR_MILLIS));
+    assert_eq!(automation_schedule.last_fire_timestamp, Some(MIDNIGHT + 3 * HOUR_MILLIS));
+    assert_eq!(automation_schedule.next_fire_timestamp, Some(MIDNIGHT + 4 * HOUR_MILLIS));
+  }
+
+  #[test]
+  fn a_repeated_sweep_creates_no_second_run() {
+    let automation_uuid = "scheduler-test-repeated";
+    let mut automation_schedule = hourly_schedule(automation_uuid);
+    let mut stale_copy = automation_schedule.clone();
+    let now = from_millis(MIDNIGHT + HOUR_MILLIS / 2).unwrap();
+
+    assert!(materialize(&mut automation_schedule, now).unwrap());
+    assert!(!materialize(&mut stale_copy, now).unwrap());
+
+    let stored = AutomationSchedule::find_by_cadence(automation_schedule.cadence_id, 1)
+      .unwrap()
+      .unwrap();
+    assert_eq!(stored.next_fire_timestamp, Some(MIDNIGHT + HOUR_MILLIS));
+  }
+}
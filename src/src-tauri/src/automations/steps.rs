@@ -0,0 +1,299 @@
+//! Backend versions of the frontend automation steps (`src/automations/steps`).
+//!
+//! A handler reads what its step needs from the local database and returns
+//! the prompt for one LLM call, or `None` when there is nothing to run, e.g.
+//! the meeting was deleted. Handlers only touch SQLite, so the executor runs
+//! them on the blocking pool.
+
+use chrono::{Duration, Local, NaiveTime, TimeZone};
+use html2text::from_read;
+use serde_json::{json, Value};
+
+use crate::db::models::calendar_event::CalendarEvent;
+use crate::db::models::document::Document;
+use crate::db::models::email::Email;
+use crate::error::Error;
+use crate::llm::prompt::AdditionalDocument;
+use crate::memory::contacts::parse_attendees_json;
+
+// The app imports these same files (src/prompts.ts), so edit prompts here only.
+const MEETING_PREP_PROMPT: &str = include_str!("prompts/meeting_prep.md");
+const EMAIL_SUMMARY_PROMPT: &str = include_str!("prompts/email_summary.md");
+const FINRA_COMPLIANCE_PROMPT: &str = include_str!("prompts/finra_compliance.md");
+const SAAS_LIST: &str = include_str!("prompts/saas_list.txt");
+
+const MAX_EMAIL_BODY_CHARS: usize = 1500;
+const EMAILS_PER_PARTICIPANT_GROUP: usize = 15;
+const DAY_EMAILS_LIMIT: usize = 1000;
+const SENT_EMAILS_LIMIT: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct StepContext {
+  /// The account the run belongs to. Steps read its data only, whichever
+  /// account is active in the app.
+  pub account_id: u64,
+  pub user_email: String,
+  /// `run_params` of the run being executed.
+  pub run_params: Value,
+  /// When the run was scheduled, in milliseconds.
+  pub timestamp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct StepPrompt {
+  pub prompt: String,
+  pub additional_documents: Vec<AdditionalDocument>,
+  pub document_ids: Vec<u64>,
+}
+
+pub type StepHandler = fn(&StepContext) -> Result<Option<StepPrompt>, Error>;
+
+/// Steps the executor can run, by `automation_steps.name`. Runs of
+/// automations with any other step are left to the frontend.
+const STEP_HANDLERS: &[(&str, StepHandler)] = &[
+  ("meeting-prep", meeting_prep),
+  ("email-summary", email_summary),
+  ("finra-compliance", finra_compliance),
+];
+
+pub fn step_names() -> Vec<&'static str> {
+  STEP_HANDLERS.iter().map(|(name, _)| *name).collect()
+}
+
+pub fn find_step_handler(name: &str) -> Option<StepHandler> {
+  STEP_HANDLERS
+    .iter()
+    .find(|(step_name, _)| *step_name == name)
+    .map(|(_, handler)| *handler)
+}
+
+/// Unix seconds of the local midnight starting the day of `timestamp_millis`
+/// and of the one after it.
+fn local_day_bounds(timestamp_millis: i64) -> (i64, i64) {
+  let date = Local
+    .timestamp_millis_opt(timestamp_millis)
+    .single()
+    .unwrap_or_else(Local::now)
+    .date_naive();
+  let midnight = |date: chrono::NaiveDate| {
+    date
+      .and_time(NaiveTime::MIN)
+      .and_local_timezone(Local)
+      .earliest()
+      .map(|datetime| datetime.timestamp())
+  };
+  let start = midnight(date).unwrap_or(timestamp_millis / 1000);
+  let end = date
+    .succ_opt()
+    .and_then(midnight)
+    .unwrap_or(start + Duration::days(1).num_seconds());
+  (start, end)
+}
+
+/// An email as it flows between steps. The body is plain text, trimmed to
+/// keep prompts and stored step results small.
+pub(crate) fn email_to_json(email: &Email) -> Value {
+  let body_bytes = email.body.as_bytes();
+  let body: String = from_read(body_bytes, body_bytes.len())
+    .chars()
+    .take(MAX_EMAIL_BODY_CHARS)
+    .collect();
+  json!({
+    "id": email.id,
+    "uid": email.email_uid,
+    "subject": email.subject,
+    "sender": email.sender,
+    "recipient": email.recipient,
+    "cc": email.cc,
+    "date": email.date,
+    "unread": email.is_read != Some(true),
+    "body": body,
+  })
+}
+
+/// Email contents for the prompt, plus the ids of their documents so the
+/// answer can cite them.
+pub(crate) fn email_documents(
+  emails: &[Value],
+) -> Result<(Vec<AdditionalDocument>, Vec<u64>), Error> {
+  let mut additional_documents = Vec::new();
+  let mut document_ids = Vec::new();
+  for email in emails {
+    let field = |name: &str| email.get(name).and_then(Value::as_str).unwrap_or_default();
+    let date = email
+      .get("date")
+      .and_then(Value::as_i64)
+      .and_then(|date| Local.timestamp_opt(date, 0).single())
+      .map(|date| date.to_rfc2822())
+      .unwrap_or_default();
+    additional_documents.push(AdditionalDocument {
+      title: field("subject").to_string(),
+      content: format!(
+        "From: {}\nTo: {}\nCc: {}\nDate: {}\n\n{}",
+        field("sender"),
+        field("recipient"),
+        field("cc"),
+        date,
+        field("body")
+      ),
+      doc_type: Some("email".to_string()),
+    });
+    if let Some(email_id) = email.get("id").and_then(Value::as_u64) {
+      if let Some(document) = Document::find_by_foreign_table_and_id("emails", email_id)? {
+        document_ids.extend(document.id);
+      }
+    }
+  }
+  Ok((additional_documents, document_ids))
+}
+
+fn emails_to_json(emails: &[Email]) -> Vec<Value> {
+  emails.iter().map(email_to_json).collect()
+}
+
+fn meeting_prep(context: &StepContext) -> Result<Option<StepPrompt>, Error> {
+  let meeting = match context.run_params.get("event_id").and_then(|id| id.as_u64()) {
+    Some(event_id) => CalendarEvent::find_account_event_by_id(event_id, Some(context.account_id))?,
+    None => CalendarEvent::get_recent_account_calendar_events(Some(context.account_id), 1)?
+      .into_iter()
+      .next(),
+  };
+  let meeting = match meeting {
+    Some(meeting) => meeting,
+    None => return Ok(None),
+  };
+
+  let participants = meeting
+    .attendees_json
+    .as_deref()
+    .map(parse_attendees_json)
+    .unwrap_or_default();
+  let user_email = context.user_email.to_lowercase();
+  let user_domain = user_email.rsplit_once('@').map(|(_, domain)| domain.to_string());
+  let (internal, external): (Vec<String>, Vec<String>) = participants
+    .iter()
+    .map(|participant| participant.email.clone())
+    .filter(|email| *email != user_email)
+    .partition(|email| email.rsplit_once('@').map(|(_, domain)| domain.to_string()) == user_domain);
+
+  let ten_days_ago = (chrono::Utc::now() - Duration::days(10)).timestamp();
+  let mut emails = Vec::new();
+  for addresses in [internal, external] {
+    if !addresses.is_empty() {
+      emails.extend(Email::filter_account_emails(
+        Some(context.account_id),
+        EMAILS_PER_PARTICIPANT_GROUP,
+        Some(addresses),
+        Some(ten_days_ago),
+        None,
+      )?);
+    }
+  }
+  let (additional_documents, document_ids) = email_documents(&emails_to_json(&emails))?;
+
+  let start = meeting
+    .start
+    .and_then(|start| Local.timestamp_opt(start, 0).single())
+    .map(|start| start.format("%A, %B %-d %Y %H:%M").to_string())
+    .unwrap_or_default();
+  let participant_emails = participants
+    .iter()
+    .map(|participant| participant.email.clone())
+    .collect::<Vec<_>>()
+    .join(", ");
+  let participants_with_names = participants
+    .iter()
+    .map(|participant| match &participant.name {
+      Some(name) => format!("{} ({})", participant.email, name),
+      None => participant.email.clone(),
+    })
+    .collect::<Vec<_>>()
+    .join(", ");
+  let call_link = match meeting.location {
+    Some(_) => String::new(),
+    None => meeting.google_meet_url.clone().unwrap_or_default(),
+  };
+  let prompt = MEETING_PREP_PROMPT
+    .replace("{title}", meeting.title.as_deref().unwrap_or_default())
+    .replace("{participant_emails}", &participant_emails)
+    .replace("{location}", meeting.location.as_deref().unwrap_or_default())
+    .replace("{google_meet_url}", meeting.google_meet_url.as_deref().unwrap_or_default())
+    .replace("{start}", &start)
+    .replace("{participants}", &participants_with_names)
+    .replace("{description}", meeting.description.as_deref().unwrap_or_default())
+    .replace(
+      "{recurrence}",
+      if meeting.recurrence_id.is_some() { "recurring" } else { "not recurring" },
+    )
+    .replace("{call_link}", &call_link)
+    .replace("{user_email}", &context.user_email);
+
+  Ok(Some(StepPrompt {
+    prompt,
+    additional_documents,
+    document_ids,
+  }))
+}
+
+fn email_summary(context: &StepContext) -> Result<Option<StepPrompt>, Error> {
+  let (start, end) = local_day_bounds(context.timestamp);
+  let emails = Email::filter_account_emails(
+    Some(context.account_id),
+    DAY_EMAILS_LIMIT,
+    None,
+    Some(start),
+    Some(end - 1),
+  )?;
+  let (additional_documents, document_ids) = email_documents(&emails_to_json(&emails))?;
+  let prompt = EMAIL_SUMMARY_PROMPT
+    .replace("{saas_list}", SAAS_LIST)
+    .replace("{user_email}", &context.user_email);
+
+  Ok(Some(StepPrompt {
+    prompt,
+    additional_documents,
+    document_ids,
+  }))
+}
+
+fn finra_compliance(context: &StepContext) -> Result<Option<StepPrompt>, Error> {
+  let (start, end) = local_day_bounds(context.timestamp);
+  let emails = Email::filter_account_emails_by_sender(
+    Some(context.account_id),
+    SENT_EMAILS_LIMIT,
+    &context.user_email,
+    start,
+    end,
+  )?;
+  let mut prompt = FINRA_COMPLIANCE_PROMPT.to_string();
+  if emails.is_empty() {
+    prompt.push_str("\nThere are no documents to analyze");
+  }
+  let (additional_documents, document_ids) = email_documents(&emails_to_json(&emails))?;
+
+  Ok(Some(StepPrompt {
+    prompt,
+    additional_documents,
+    document_ids,
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn day_bounds_contain_the_timestamp() {
+    let timestamp = chrono::Utc::now().timestamp_millis();
+    let (start, end) = local_day_bounds(timestamp);
+    assert!(start <= timestamp / 1000 && timestamp / 1000 < end);
+    assert!(end - start >= 23 * 60 * 60 && end - start <= 25 * 60 * 60);
+  }
+
+  #[test]
+  fn resolves_registered_steps_only() {
+    assert!(find_step_handler("email-summary").is_some());
+    assert!(find_step_handler("social-media-planner").is_none());
+    assert_eq!(step_names().len(), STEP_HANDLERS.len());
+  }
+}
@@ -0,0 +1,467 @@
+//! Data-source triggers that fire on events rather than on a schedule.
+//!
+//! Email sync calls `fire_new_email` for every stored email, `stop_recording`
+//! calls `fire_meeting_ended`, and the executor sweep scans the folders of
+//! `local_file` triggers for files changed since the previous scan. Each
+//! matching trigger stores one run carrying the triggering object in its
+//! `run_params`, keyed by the object so a resync never fires it twice.
+//! Calendar triggers (`google_calendar`, `microsoft_calendar`) are scheduled
+//! ahead from upcoming events by `api::schedule_automation_runs`.
+//!
+//! A trigger's `guard` (see `guard`) decides whether it fires: event triggers
+//! evaluate it against the run's `run_params`, calendar triggers against
+//! `event` (`calendar_event_context`).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::automations::guard::Guard;
+use crate::automations::scheduler::store_run;
+use crate::automations::steps::email_to_json;
+use crate::db::models::automation_run::{AutomationRun, AutomationRunStatus};
+use crate::db::models::calendar_event::CalendarEvent;
+use crate::db::models::data_source_trigger::DataSourceTrigger;
+use crate::db::models::email::Email;
+use crate::db::models::thread::Thread;
+use crate::db::models::transcript::Transcript;
+use crate::db::models::user::User;
+use crate::error::Error;
+use crate::local_fs::expand_home;
+use crate::memory::contacts::parse_attendees_json;
+
+pub const GOOGLE_CALENDAR: &str = "google_calendar";
+pub const MICROSOFT_CALENDAR: &str = "microsoft_calendar";
+pub const NEW_EMAIL: &str = "new_email";
+pub const LOCAL_FILE: &str = "local_file";
+pub const MEETING_ENDED: &str = "meeting_ended";
+
+pub const DATA_SOURCES: &[&str] =
+  &[GOOGLE_CALENDAR, MICROSOFT_CALENDAR, NEW_EMAIL, LOCAL_FILE, MEETING_ENDED];
+
+/// Bounds a single scan of a watched folder.
+const MAX_SCANNED_FILES: usize = 10_000;
+
+/// Calendar triggers without a guard skip events the user attends alone.
+pub const DEFAULT_CALENDAR_GUARD: &str = "len(event.attendees) > 1";
+
+/// `filter_json` of a trigger. Text fields match case-insensitively as
+/// substrings; every field that is set has to match.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TriggerFilter {
+  /// `new_email`: sender address or name.
+  pub from: Option<String>,
+  /// `new_email`: recipients and cc.
+  pub to: Option<String>,
+  /// `new_email`: subject.
+  pub subject: Option<String>,
+  /// `meeting_ended`: meeting title.
+  pub title: Option<String>,
+  /// `local_file`: folder watched recursively; required.
+  pub folder: Option<String>,
+  /// `local_file`: extensions without the dot; any when empty.
+  pub extensions: Vec<String>,
+  /// Fires the trigger only when true.
+  pub guard: Option<String>,
+}
+
+fn contains(haystack: &str, needle: &Option<String>) -> bool {
+  match needle {
+    Some(needle) => haystack.to_lowercase().contains(&needle.to_lowercase()),
+    None => true,
+  }
+}
+
+impl TriggerFilter {
+  pub fn parse(trigger: &DataSourceTrigger) -> Result<TriggerFilter, Error> {
+    match trigger.filter_json.as_deref().map(str::trim) {
+      None | Some("") => Ok(TriggerFilter::default()),
+      Some(filter_json) => serde_json::from_str(filter_json).map_err(|error| {
+        Error::KSError(format!("invalid filter of `{}` trigger: {}", trigger.data_source, error))
+      }),
+    }
+  }
+
+  /// The trigger's guard, or `default` when it has none.
+  pub fn guard_or(&self, default: Option<&str>) -> Result<Option<Guard>, Error> {
+    Guard::parse_optional(self.guard.as_deref().or(default))
+  }
+
+  fn matches_email(&self, email: &Email) -> bool {
+    contains(&email.sender, &self.from)
+      && contains(&format!("{} {}", email.recipient, email.cc), &self.to)
+      && contains(&email.subject, &self.subject)
+  }
+
+  fn matches_extension(&self, path: &Path) -> bool {
+    if self.extensions.is_empty() {
+      return true;
+    }
+    let extension = path
+      .extension()
+      .and_then(|extension| extension.to_str())
+      .unwrap_or_default();
+    self
+      .extensions
+      .iter()
+      .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(extension))
+  }
+}
+
+/// Checks a trigger before its automation is saved.
+pub fn validate_data_source(trigger: &DataSourceTrigger) -> Result<(), Error> {
+  if !DATA_SOURCES.contains(&trigger.data_source.as_str()) {
+    return Err(Error::KSError(format!(
+      "unknown data source `{}`, expected one of {}",
+      trigger.data_source,
+      DATA_SOURCES.join(", ")
+    )));
+  }
+  let filter = TriggerFilter::parse(trigger)?;
+  filter.guard_or(None)?;
+  if trigger.data_source == LOCAL_FILE && filter.folder.as_deref().unwrap_or_default().is_empty() {
+    return Err(Error::KSError("`local_file` triggers need a `folder` filter".into()));
+  }
+  Ok(())
+}
+
+/// `event` as calendar guards see it. Attendees are email addresses;
+/// external ones have another domain than `user_email`.
+pub fn calendar_event_context(event: &CalendarEvent, user_email: &str) -> Value {
+  let attendees: Vec<String> = event
+    .attendees_json
+    .as_deref()
+    .map(parse_attendees_json)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|participant| participant.email)
+    .collect();
+  let domain = |email: &str| email.rsplit_once('@').map(|(_, domain)| domain.to_lowercase());
+  let user_domain = domain(user_email);
+  let external_attendees: Vec<&String> = attendees
+    .iter()
+    .filter(|email| domain(email) != user_domain)
+    .collect();
+  json!({
+    "event": {
+      "id": event.id,
+      "title": event.title,
+      "description": event.description,
+      "location": event.location,
+      "start": event.start,
+      "end": event.end,
+      "creator": event.creator_email,
+      "attendees": attendees,
+      "external_attendees": external_attendees,
+      "recurring": event.recurrence_id.is_some() || event.recurrence_json.is_some(),
+    }
+  })
+}
+
+/// Stores the run `trigger` fires for the object identified by `object_key`
+/// unless its guard is false. Returns whether a new run was stored.
+fn fire(
+  trigger: &DataSourceTrigger,
+  user_id: u64,
+  object_key: &str,
+  now_millis: i64,
+  mut run_params: Value,
+) -> Result<bool, Error> {
+  if let Some(guard) = TriggerFilter::parse(trigger)?.guard_or(None)? {
+    if !guard.matches(&run_params) {
+      return Ok(false);
+    }
+  }
+  let schedule_timestamp = now_millis + trigger.offset_minutes.max(0) * 60 * 1000;
+  run_params["timestamp"] = json!(schedule_timestamp);
+  let mut run = AutomationRun {
+    id: None,
+    automation_uuid: trigger.automation_uuid.clone(),
+    user_id,
+    thread_id: None,
+    schedule_timestamp: Some(schedule_timestamp),
+    execution_timestamp: None,
+    run_params: Some(run_params.to_string()),
+    feed_item_id: None,
+    status: Some(AutomationRunStatus::Scheduled),
+    error: None,
+    step_results: None,
+    run_key: Some(format!(
+      "{}@{}:{}",
+      trigger.automation_uuid, trigger.data_source, object_key
+    )),
+    finished_timestamp: None,
+    attempts: 0,
+    action_results: None,
+  };
+  store_run(&mut run)
+}
+
+/// Fires the `new_email` triggers matching the stored email `email_uid`.
+/// The trigger's filter, or `None` after logging why it cannot be read, so
+/// one broken trigger does not stop the others from firing.
+fn readable_filter(trigger: &DataSourceTrigger) -> Option<TriggerFilter> {
+  match TriggerFilter::parse(trigger) {
+    Ok(filter) => Some(filter),
+    Err(error) => {
+      log::warn!("Skipping trigger {:?} with an unreadable filter - {:?}", trigger.id, error);
+      None
+    }
+  }
+}
+
+/// Emails older than a trigger are ignored so a first sync does not fire it
+/// for the whole mailbox.
+pub fn fire_new_email(email_uid: &str, now_millis: i64) -> Result<usize, Error> {
+  let triggers = DataSourceTrigger::find_active_by_data_source(NEW_EMAIL)?;
+  if triggers.is_empty() {
+    return Ok(0);
+  }
+  let email = match Email::find_by_uid(email_uid)? {
+    Some(email) if email.is_deleted != Some(true) => email,
+    _ => return Ok(0),
+  };
+  let user_id = match email.account_id.or_else(User::active_account_id) {
+    Some(user_id) => user_id,
+    None => return Ok(0),
+  };
+  let email_json = email_to_json(&email);
+  let mut fired = 0;
+  for trigger in triggers {
+    if (email.date as i64) < trigger.created_timestamp.unwrap_or_default() {
+      continue;
+    }
+    match readable_filter(&trigger) {
+      Some(filter) if filter.matches_email(&email) => {}
+      _ => continue,
+    }
+    let run_params = json!({ "email": email_json, "emails": [email_json] });
+    if fire(&trigger, user_id, &email.email_uid, now_millis, run_params)? {
+      fired += 1;
+    }
+  }
+  Ok(fired)
+}
+
+/// Fires the `meeting_ended` triggers for the recording of `thread_id`.
+pub fn fire_meeting_ended(thread_id: u64, event_id: Option<u64>, now_millis: i64) -> Result<usize, Error> {
+  let triggers = DataSourceTrigger::find_active_by_data_source(MEETING_ENDED)?;
+  if triggers.is_empty() {
+    return Ok(0);
+  }
+  let user_id = match User::active_account_id() {
+    Some(user_id) => user_id,
+    None => return Ok(0),
+  };
+  let event = match event_id {
+    Some(event_id) => CalendarEvent::find_by_id(event_id)?,
+    None => None,
+  };
+  let transcript = Transcript::find_by_thread_id(thread_id)?;
+  let title = event
+    .as_ref()
+    .and_then(|event| event.title.clone())
+    .or_else(|| Thread::find_by_id(thread_id).ok().flatten().and_then(|thread| thread.title))
+    .unwrap_or_default();
+  let meeting = json!({
+    "thread_id": thread_id,
+    "event_id": event.as_ref().and_then(|event| event.id),
+    "transcript_id": transcript.as_ref().and_then(|transcript| transcript.id),
+    "title": title,
+    "start": transcript.as_ref().and_then(|transcript| transcript.start_time),
+    "end": transcript.as_ref().and_then(|transcript| transcript.end_time),
+  });
+
+  let mut fired = 0;
+  for trigger in triggers {
+    match readable_filter(&trigger) {
+      Some(filter) if contains(&title, &filter.title) => {}
+      _ => continue,
+    }
+    let run_params = json!({ "meeting": meeting });
+    if fire(&trigger, user_id, &thread_id.to_string(), now_millis, run_params)? {
+      fired += 1;
+    }
+  }
+  Ok(fired)
+}
+
+fn modified_secs(metadata: &fs::Metadata) -> Option<i64> {
+  let modified = metadata.modified().ok()?;
+  Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+}
+
+fn created_secs(metadata: &fs::Metadata) -> Option<i64> {
+  let created = metadata.created().ok()?;
+  Some(created.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+}
+
+/// Files under `dir` modified after `since`, skipping hidden entries.
+fn changed_files(dir: &Path, since: i64, filter: &TriggerFilter, files: &mut Vec<(PathBuf, fs::Metadata)>) {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(error) => {
+      log::warn!("Cannot read watched folder {:?} - {:?}", dir, error);
+      return;
+    }
+  };
+  for entry in entries.flatten() {
+    if files.len() >= MAX_SCANNED_FILES {
+      return;
+    }
+    let path = entry.path();
+    if entry.file_name().to_string_lossy().starts_with('.') {
+      continue;
+    }
+    let metadata = match entry.metadata() {
+      Ok(metadata) => metadata,
+      Err(_) => continue,
+    };
+    if metadata.is_dir() {
+      changed_files(&path, since, filter, files);
+    } else if modified_secs(&metadata).map_or(false, |modified| modified > since)
+      && filter.matches_extension(&path)
+    {
+      files.push((path, metadata));
+    }
+  }
+}
+
+/// Fires the `local_file` triggers for files created or modified in their
+/// folder since the previous scan.
+pub fn scan_watched_folders(now_millis: i64) -> Result<usize, Error> {
+  let user_id = match User::active_account_id() {
+    Some(user_id) => user_id,
+    None => return Ok(0),
+  };
+  let scanned_at = now_millis / 1000;
+  let mut fired = 0;
+  for mut trigger in DataSourceTrigger::find_active_by_data_source(LOCAL_FILE)? {
+    let filter = match readable_filter(&trigger) {
+      Some(filter) => filter,
+      None => continue,
+    };
+    let folder = match filter.folder.as_deref() {
+      Some(folder) if !folder.is_empty() => expand_home(Path::new(folder)),
+      _ => continue,
+    };
+    let since = trigger.cursor.or(trigger.created_timestamp).unwrap_or(scanned_at);
+    let mut files = Vec::new();
+    changed_files(&folder, since, &filter, &mut files);
+    for (path, metadata) in files {
+      let modified = modified_secs(&metadata).unwrap_or(scanned_at);
+      let change = match created_secs(&metadata) {
+        Some(created) if created > since => "created",
+        _ => "modified",
+      };
+      let path = path.to_string_lossy().into_owned();
+      let run_params = json!({
+        "file": {
+          "path": path,
+          "filename": Path::new(&path).file_name().map(|name| name.to_string_lossy()),
+          "size": metadata.len(),
+          "modified": modified,
+          "change": change,
+        }
+      });
+      if fire(&trigger, user_id, &format!("{}:{}", path, modified), now_millis, run_params)? {
+        fired += 1;
+      }
+    }
+    trigger.update_cursor(scanned_at)?;
+  }
+  Ok(fired)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn trigger(data_source: &str, filter_json: Option<&str>) -> DataSourceTrigger {
+    DataSourceTrigger {
+      id: None,
+      automation_uuid: "automation".to_string(),
+      data_source: data_source.to_string(),
+      offset_minutes: 0,
+      filter_json: filter_json.map(str::to_string),
+      created_timestamp: None,
+      cursor: None,
+    }
+  }
+
+  #[test]
+  fn validates_data_sources_and_filters() {
+    assert!(validate_data_source(&trigger(NEW_EMAIL, None)).is_ok());
+    assert!(validate_data_source(&trigger(NEW_EMAIL, Some(r#"{"from": "@acme.com"}"#))).is_ok());
+    assert!(validate_data_source(&trigger("slack", None)).is_err());
+    assert!(validate_data_source(&trigger(NEW_EMAIL, Some(r#"{"sender": "x"}"#))).is_err());
+    assert!(validate_data_source(&trigger(LOCAL_FILE, None)).is_err());
+    assert!(validate_data_source(&trigger(LOCAL_FILE, Some(r#"{"folder": "~/Reports"}"#))).is_ok());
+    assert!(validate_data_source(&trigger(NEW_EMAIL, Some(r#"{"guard": "email.subject =="}"#))).is_err());
+  }
+
+  #[test]
+  fn unreadable_filters_skip_only_their_trigger() {
+    assert!(readable_filter(&trigger(LOCAL_FILE, Some("{\"folder\": "))).is_none());
+    let filter = readable_filter(&trigger(LOCAL_FILE, Some(r#"{"folder": "~/Reports"}"#))).unwrap();
+    assert_eq!(filter.folder.as_deref(), Some("~/Reports"));
+  }
+
+  #[test]
+  fn calendar_guards_see_external_attendees() {
+    let event = CalendarEvent {
+      id: Some(1),
+      event_id: "event".to_string(),
+      title: Some("Pipeline review".to_string()),
+      description: None,
+      creator_email: None,
+      attendees_json: Some(
+        r#"[{"email": "me@acme.com"}, {"email": "ana@acme.com"}, {"email": "Cy@ACME.com"}, {"email": "bo@client.io"}]"#
+          .to_string(),
+      ),
+      location: None,
+      start: None,
+      end: None,
+      google_meet_url: None,
+      recurrence_json: None,
+      recurrence_id: None,
+      account_id: None,
+    };
+    let context = calendar_event_context(&event, "me@acme.com");
+    let default_guard = TriggerFilter::default().guard_or(Some(DEFAULT_CALENDAR_GUARD)).unwrap().unwrap();
+    assert!(default_guard.matches(&context));
+    let external = Guard::parse("len(event.external_attendees) >= 2").unwrap();
+    assert!(!external.matches(&context));
+  }
+
+  #[test]
+  fn matches_emails_case_insensitively() {
+    let filter = TriggerFilter::parse(&trigger(
+      NEW_EMAIL,
+      Some(r#"{"from": "ACME.com", "subject": "invoice"}"#),
+    ))
+    .unwrap();
+    let email = Email {
+      id: None,
+      email_uid: "uid".to_string(),
+      thread_id: None,
+      subject: "Your Invoice for March".to_string(),
+      date: 0,
+      sender: "billing@acme.com".to_string(),
+      recipient: "me@example.com".to_string(),
+      cc: String::new(),
+      body: String::new(),
+      is_starred: None,
+      is_read: None,
+      is_archived: None,
+      is_deleted: None,
+      account_id: None,
+    };
+    assert!(filter.matches_email(&email));
+    assert!(!filter.matches_email(&Email { subject: "Hello".to_string(), ..email }));
+  }
+}
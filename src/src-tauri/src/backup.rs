@@ -0,0 +1,621 @@
+//! Full backups of the user's Knapsack data in a single zip archive.
+//!
+//! An archive holds an online SQLite backup of `~/.knapsack.db`, the
+//! transcripts, notes and audio under `~/.knapsack`, the clawdbot home and,
+//! when Qdrant is reachable, a snapshot of the vector collection. A
+//! `manifest.json` lists every entry with its size and SHA-256 so a restore
+//! can reject a damaged archive before touching anything on disk.
+//!
+//! Sealed tokens only travel with their keys when the key set lives in
+//! `secret_keys.json` (passphrase mode); keys kept in the OS keyring never
+//! leave it. A restore therefore keeps only the connections and tokens that
+//! decrypt with the key set in place afterwards.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rusqlite::backup::Progress;
+use rusqlite::DatabaseName;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::clawd::service::app_clawdbot_home;
+use crate::config;
+use crate::db::db::try_get_db_conn;
+use crate::db::diesel_setup::{execute_migrations, latest_migration_version};
+use crate::db::models::user_connection::UserConnection;
+use crate::error::Error;
+use crate::memory::qdrant::{download_collection_snapshot, upload_collection_snapshot};
+use crate::secrets::SecretStore;
+
+/// Bumped whenever the archive layout changes in a way older builds can't
+/// read.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const DATABASE_PATH: &str = "knapsack.db";
+const QDRANT_SNAPSHOT_PATH: &str = "qdrant/knapsack.snapshot";
+const DATA_DIR_PREFIX: &str = "data";
+const CLAWDBOT_PREFIX: &str = "clawdbot";
+
+const DATA_SUBDIRS: &[&str] = &["transcripts", "notes", "audio"];
+const DATA_FILES: &[&str] = &["uuid.txt", "secret_keys.json"];
+const SECRET_KEYS_PATH: &str = "data/secret_keys.json";
+const TOKENS_PATH: &str = "clawdbot/tokens.json";
+/// Chrome profiles are tied to the machine and can run to gigabytes.
+const CLAWDBOT_SKIPPED_DIRS: &[&str] = &["browser"];
+/// Entries holding credentials, left out when secrets are excluded.
+const SECRET_PATHS: &[&str] = &[SECRET_KEYS_PATH, TOKENS_PATH];
+
+const BACKUPS_DIR: &str = "backups";
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+/// How long a restore waits for running syncs and sweeps to finish.
+const RESTORE_WAIT_TIMEOUT: Duration = Duration::from_secs(120);
+const RESTORE_WAIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+static RESTORING: AtomicBool = AtomicBool::new(false);
+static BACKGROUND_WORK: AtomicUsize = AtomicUsize::new(0);
+
+/// Held by connection syncs and background sweeps while they run. A restore
+/// waits for outstanding work to finish and refuses new work until it's
+/// done, so nothing writes to a database that is being replaced.
+pub struct BackgroundWork(());
+
+impl BackgroundWork {
+  /// `None` while a restore is running; the caller should skip this round.
+  pub fn begin() -> Option<BackgroundWork> {
+    BACKGROUND_WORK.fetch_add(1, Ordering::SeqCst);
+    if RESTORING.load(Ordering::SeqCst) {
+      BACKGROUND_WORK.fetch_sub(1, Ordering::SeqCst);
+      return None;
+    }
+    Some(BackgroundWork(()))
+  }
+}
+
+impl Drop for BackgroundWork {
+  fn drop(&mut self) {
+    BACKGROUND_WORK.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+/// Marks a restore as running until dropped.
+struct RestoreInProgress(());
+
+impl RestoreInProgress {
+  async fn begin() -> Result<RestoreInProgress, Error> {
+    if RESTORING
+      .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+      .is_err()
+    {
+      return Err(Error::KSError("A restore is already running".to_string()));
+    }
+    let restore = RestoreInProgress(());
+    let started = Instant::now();
+    while BACKGROUND_WORK.load(Ordering::SeqCst) > 0 {
+      if started.elapsed() > RESTORE_WAIT_TIMEOUT {
+        return Err(Error::KSError(
+          "Timed out waiting for syncs and automations to finish; try again".to_string(),
+        ));
+      }
+      tokio::time::sleep(RESTORE_WAIT_POLL_INTERVAL).await;
+    }
+    Ok(restore)
+  }
+}
+
+impl Drop for RestoreInProgress {
+  fn drop(&mut self) {
+    RESTORING.store(false, Ordering::SeqCst);
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupEntry {
+  pub path: String,
+  pub size: u64,
+  pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+  pub format_version: u32,
+  pub app_version: String,
+  /// Newest applied migration of the backed up database.
+  pub schema_version: String,
+  pub created_at: i64,
+  pub includes_secrets: bool,
+  /// The key set that sealed the archived tokens is in the archive. False
+  /// when the keys live in the OS keyring.
+  #[serde(default)]
+  pub includes_secret_keys: bool,
+  pub includes_vectors: bool,
+  pub entries: Vec<BackupEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreSummary {
+  pub manifest: BackupManifest,
+  pub restored_files: usize,
+  pub restored_vectors: bool,
+  /// Connections were dropped because their tokens were not restored or
+  /// don't decrypt here; the user has to sign in to them again.
+  pub connections_cleared: bool,
+}
+
+/// Where backups go when the caller doesn't pick a destination.
+pub fn default_backup_path() -> Result<PathBuf, Error> {
+  let filename = format!(
+    "knapsack-backup-{}.zip",
+    chrono::Local::now().format("%Y%m%d-%H%M%S")
+  );
+  Ok(config::data_dir().join(BACKUPS_DIR).join(filename))
+}
+
+fn is_secret(path: &str) -> bool {
+  SECRET_PATHS.contains(&path)
+}
+
+/// Archive paths are relative, `/`-separated and never leave the root.
+fn is_safe_archive_path(path: &str) -> bool {
+  !path.is_empty()
+    && !path.contains('\\')
+    && Path::new(path)
+      .components()
+      .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Copies `reader` into `writer`, returning the byte count and SHA-256.
+fn copy_hashed(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<(u64, String)> {
+  let mut hasher = Sha256::new();
+  let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+  let mut size = 0u64;
+  loop {
+    let read = reader.read(&mut buffer)?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buffer[..read]);
+    writer.write_all(&buffer[..read])?;
+    size += read as u64;
+  }
+  Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+/// Files to archive as `(archive path, file on disk)`.
+fn collect_files(clawdbot_home: &Path, include_secrets: bool) -> Result<Vec<(String, PathBuf)>, Error> {
+  let data_dir = config::data_dir();
+  let mut files = Vec::new();
+
+  for filename in DATA_FILES {
+    let path = data_dir.join(filename);
+    if path.is_file() {
+      files.push((format!("{}/{}", DATA_DIR_PREFIX, filename), path));
+    }
+  }
+  for subdir in DATA_SUBDIRS {
+    collect_dir(&data_dir.join(subdir), &format!("{}/{}", DATA_DIR_PREFIX, subdir), &[], &mut files);
+  }
+  collect_dir(clawdbot_home, CLAWDBOT_PREFIX, CLAWDBOT_SKIPPED_DIRS, &mut files);
+
+  if !include_secrets {
+    files.retain(|(archive_path, _)| !is_secret(archive_path));
+  }
+  Ok(files)
+}
+
+fn collect_dir(dir: &Path, prefix: &str, skipped_dirs: &[&str], files: &mut Vec<(String, PathBuf)>) {
+  if !dir.is_dir() {
+    return;
+  }
+  let walker = WalkDir::new(dir).into_iter().filter_entry(|entry| {
+    entry.depth() != 1
+      || !entry.file_type().is_dir()
+      || !skipped_dirs.contains(&&*entry.file_name().to_string_lossy())
+  });
+  for entry in walker {
+    let entry = match entry {
+      Ok(entry) => entry,
+      Err(e) => {
+        log::warn!("Skipping unreadable backup entry: {}", e);
+        continue;
+      }
+    };
+    if !entry.file_type().is_file() {
+      continue;
+    }
+    let relative = match entry.path().strip_prefix(dir) {
+      Ok(relative) => relative,
+      Err(_) => continue,
+    };
+    let relative: Vec<String> = relative
+      .components()
+      .map(|component| component.as_os_str().to_string_lossy().to_string())
+      .collect();
+    files.push((format!("{}/{}", prefix, relative.join("/")), entry.into_path()));
+  }
+}
+
+fn recorded_schema_version(database_path: &Path) -> Result<String, Error> {
+  let connection = rusqlite::Connection::open(database_path)?;
+  let version: Option<String> = connection.query_row(
+    "SELECT MAX(version) FROM __diesel_schema_migrations",
+    [],
+    |row| row.get(0),
+  )?;
+  Ok(version.unwrap_or_default())
+}
+
+fn write_archive(
+  destination: &Path,
+  files: &[(String, PathBuf)],
+  mut manifest: BackupManifest,
+) -> Result<BackupManifest, Error> {
+  if let Some(parent) = destination.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let partial_path = destination.with_extension("partial");
+  let mut zip = ZipWriter::new(File::create(&partial_path)?);
+  let options = FileOptions::default()
+    .compression_method(CompressionMethod::Deflated)
+    .large_file(true);
+
+  for (archive_path, path) in files {
+    let mut file = match File::open(path) {
+      Ok(file) => file,
+      Err(e) => {
+        // Audio and notes can be deleted while the backup runs.
+        log::warn!("Skipping {} in backup: {}", path.display(), e);
+        continue;
+      }
+    };
+    zip.start_file(archive_path.as_str(), options)?;
+    let (size, sha256) = copy_hashed(&mut file, &mut zip)?;
+    manifest.entries.push(BackupEntry {
+      path: archive_path.clone(),
+      size,
+      sha256,
+    });
+  }
+
+  zip.start_file(MANIFEST_PATH, options)?;
+  zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+  zip.finish()?;
+  fs::rename(&partial_path, destination)?;
+  Ok(manifest)
+}
+
+/// Writes a full backup to `destination`.
+pub async fn create_backup(
+  app_handle: &tauri::AppHandle,
+  destination: PathBuf,
+  include_secrets: bool,
+) -> Result<BackupManifest, Error> {
+  let staging_dir = tempfile::tempdir()?;
+
+  let vector_snapshot = match download_collection_snapshot().await {
+    Ok(snapshot) => {
+      let snapshot_path = staging_dir.path().join("knapsack.snapshot");
+      fs::write(&snapshot_path, snapshot)?;
+      Some(snapshot_path)
+    }
+    Err(e) => {
+      log::warn!("Backing up without vectors, Qdrant snapshot failed: {}", e);
+      None
+    }
+  };
+
+  let clawdbot_home = app_clawdbot_home(app_handle);
+  let app_version = app_handle.package_info().version.to_string();
+
+  tokio::task::spawn_blocking(move || {
+    let database_path = staging_dir.path().join(DATABASE_PATH);
+    try_get_db_conn()?.backup(DatabaseName::Main, &database_path, None)?;
+
+    let mut files = vec![(DATABASE_PATH.to_string(), database_path.clone())];
+    if let Some(snapshot_path) = &vector_snapshot {
+      files.push((QDRANT_SNAPSHOT_PATH.to_string(), snapshot_path.clone()));
+    }
+    files.extend(collect_files(&clawdbot_home, include_secrets)?);
+
+    let manifest = BackupManifest {
+      format_version: BACKUP_FORMAT_VERSION,
+      app_version,
+      schema_version: recorded_schema_version(&database_path)?,
+      created_at: chrono::Utc::now().timestamp(),
+      includes_secrets: include_secrets,
+      includes_secret_keys: files
+        .iter()
+        .any(|(archive_path, _)| archive_path == SECRET_KEYS_PATH),
+      includes_vectors: vector_snapshot.is_some(),
+      entries: Vec::new(),
+    };
+    write_archive(&destination, &files, manifest)
+  })
+  .await?
+}
+
+/// Rejects archives this build can't restore faithfully.
+fn validate_manifest(manifest: &BackupManifest, latest_schema_version: &str) -> Result<(), Error> {
+  if manifest.format_version > BACKUP_FORMAT_VERSION {
+    return Err(Error::KSError(format!(
+      "Backup format {} is newer than this version of Knapsack supports ({})",
+      manifest.format_version, BACKUP_FORMAT_VERSION
+    )));
+  }
+  if manifest.schema_version.as_str() > latest_schema_version {
+    return Err(Error::KSError(format!(
+      "Backup was made by Knapsack {} with a newer database schema; update Knapsack before restoring",
+      manifest.app_version
+    )));
+  }
+  if !manifest.entries.iter().any(|entry| entry.path == DATABASE_PATH) {
+    return Err(Error::KSError("Backup has no database".to_string()));
+  }
+  if let Some(entry) = manifest
+    .entries
+    .iter()
+    .find(|entry| !is_safe_archive_path(&entry.path))
+  {
+    return Err(Error::KSError(format!("Backup entry {} has an unsafe path", entry.path)));
+  }
+  Ok(())
+}
+
+fn read_manifest(archive: &mut ZipArchive<File>) -> Result<BackupManifest, Error> {
+  let mut manifest_file = archive
+    .by_name(MANIFEST_PATH)
+    .map_err(|_| Error::KSError("Not a Knapsack backup: manifest.json is missing".to_string()))?;
+  let mut manifest_json = Vec::new();
+  manifest_file.read_to_end(&mut manifest_json)?;
+  Ok(serde_json::from_slice(&manifest_json)?)
+}
+
+/// Extracts the entries to restore into `staging_dir`, checking each one
+/// against the manifest.
+fn extract_verified(
+  archive: &mut ZipArchive<File>,
+  entries: &[BackupEntry],
+  staging_dir: &Path,
+) -> Result<(), Error> {
+  for entry in entries {
+    let staged_path = staging_dir.join(&entry.path);
+    if let Some(parent) = staged_path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    let mut archived = archive.by_name(&entry.path)?;
+    let (size, sha256) = copy_hashed(&mut archived, &mut File::create(&staged_path)?)?;
+    if size != entry.size || sha256 != entry.sha256 {
+      return Err(Error::KSError(format!(
+        "Backup entry {} is corrupted (checksum mismatch)",
+        entry.path
+      )));
+    }
+  }
+  Ok(())
+}
+
+fn install_files(
+  staging_dir: &Path,
+  entries: &[BackupEntry],
+  clawdbot_home: &Path,
+) -> Result<usize, Error> {
+  let data_dir = config::data_dir();
+  let mut installed = 0;
+  for entry in entries {
+    // Installed separately once we know it decrypts.
+    if entry.path == TOKENS_PATH {
+      continue;
+    }
+    let target = if let Some(relative) = entry.path.strip_prefix(&format!("{}/", DATA_DIR_PREFIX)) {
+      data_dir.join(relative)
+    } else if let Some(relative) = entry.path.strip_prefix(&format!("{}/", CLAWDBOT_PREFIX)) {
+      clawdbot_home.join(relative)
+    } else {
+      continue;
+    };
+    if let Some(parent) = target.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::copy(staging_dir.join(&entry.path), &target)?;
+    installed += 1;
+  }
+  Ok(installed)
+}
+
+/// True when every sealed value in a staged `tokens.json` decrypts with the
+/// current key set.
+fn tokens_file_opens(path: &Path) -> Result<bool, Error> {
+  let tokens: serde_json::Map<String, serde_json::Value> =
+    serde_json::from_slice(&fs::read(path)?)?;
+  Ok(
+    tokens
+      .values()
+      .filter_map(|value| value.as_str())
+      .all(|value| SecretStore::decrypt(value).is_ok()),
+  )
+}
+
+/// Restores a backup made by `create_backup` over the current data. Files
+/// in the archive replace their local copies; local files the archive
+/// doesn't mention are kept. Syncs and background sweeps are held off
+/// until the restore finishes.
+///
+/// With `include_secrets` off, stored keys and tokens are left alone and
+/// connections must be signed in again. With it on, restored connections
+/// and `tokens.json` are kept only if they decrypt with the key set in place
+/// afterwards: the archive's own keys in passphrase mode, otherwise this
+/// machine's keyring.
+pub async fn restore_backup(
+  app_handle: &tauri::AppHandle,
+  archive_path: PathBuf,
+  include_secrets: bool,
+) -> Result<RestoreSummary, Error> {
+  let _restore = RestoreInProgress::begin().await?;
+  let clawdbot_home = app_clawdbot_home(app_handle);
+  let staging_dir = tempfile::tempdir()?;
+  let staging_path = staging_dir.path().to_path_buf();
+
+  let (manifest, restored_files, connections_cleared) = tokio::task::spawn_blocking(move || {
+    let mut archive = ZipArchive::new(File::open(&archive_path)?)?;
+    let manifest = read_manifest(&mut archive)?;
+    let latest_schema_version = latest_migration_version().unwrap_or_default();
+    validate_manifest(&manifest, &latest_schema_version)?;
+
+    let entries: Vec<BackupEntry> = manifest
+      .entries
+      .iter()
+      .filter(|entry| include_secrets || !is_secret(&entry.path))
+      .cloned()
+      .collect();
+    extract_verified(&mut archive, &entries, &staging_path)?;
+
+    try_get_db_conn()?.restore(
+      DatabaseName::Main,
+      staging_path.join(DATABASE_PATH),
+      None::<fn(Progress)>,
+    )?;
+    execute_migrations().map_err(|e| Error::KSError(format!("{}", e)))?;
+
+    let mut restored_files = install_files(&staging_path, &entries, &clawdbot_home)?;
+    if !include_secrets {
+      try_get_db_conn()?.execute("DELETE FROM user_connections", [])?;
+      return Ok::<_, Error>((manifest, restored_files, true));
+    }
+
+    // Tokens only decrypt with the keys they were sealed with.
+    SecretStore::reload();
+    let staged_tokens = staging_path.join(TOKENS_PATH);
+    if staged_tokens.is_file() {
+      if tokens_file_opens(&staged_tokens)? {
+        fs::create_dir_all(&clawdbot_home)?;
+        fs::copy(&staged_tokens, clawdbot_home.join("tokens.json"))?;
+        restored_files += 1;
+      } else {
+        log::warn!("Keeping local tokens.json; the backup's copy was sealed with other keys");
+      }
+    }
+    let connections_cleared = UserConnection::delete_unreadable()? > 0;
+    Ok::<_, Error>((manifest, restored_files, connections_cleared))
+  })
+  .await??;
+
+  let mut restored_vectors = false;
+  if manifest.includes_vectors {
+    let snapshot = fs::read(staging_dir.path().join(QDRANT_SNAPSHOT_PATH))?;
+    match upload_collection_snapshot(snapshot).await {
+      Ok(()) => restored_vectors = true,
+      Err(e) => log::error!("Failed to restore Qdrant snapshot: {}", e),
+    }
+  }
+
+  Ok(RestoreSummary {
+    manifest,
+    restored_files,
+    restored_vectors,
+    connections_cleared,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn manifest(schema_version: &str, entries: &[&str]) -> BackupManifest {
+    BackupManifest {
+      format_version: BACKUP_FORMAT_VERSION,
+      app_version: "1.0.0".to_string(),
+      schema_version: schema_version.to_string(),
+      created_at: 0,
+      includes_secrets: true,
+      includes_secret_keys: false,
+      includes_vectors: false,
+      entries: entries
+        .iter()
+        .map(|path| BackupEntry {
+          path: path.to_string(),
+          size: 0,
+          sha256: String::new(),
+        })
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn rejects_newer_schemas_and_unsafe_paths() {
+    let latest = "20261018000003";
+    assert!(validate_manifest(&manifest("20250127175534", &[DATABASE_PATH]), latest).is_ok());
+    assert!(validate_manifest(&manifest("20261019000001", &[DATABASE_PATH]), latest).is_err());
+    assert!(validate_manifest(&manifest(latest, &[]), latest).is_err());
+    assert!(
+      validate_manifest(&manifest(latest, &[DATABASE_PATH, "data/../../.ssh/id_rsa"]), latest)
+        .is_err()
+    );
+    assert!(validate_manifest(&manifest(latest, &[DATABASE_PATH, "/etc/passwd"]), latest).is_err());
+  }
+
+  #[test]
+  fn detects_corrupted_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive_path = dir.path().join("backup.zip");
+    let source = dir.path().join("note.md");
+    fs::write(&source, "meeting notes").unwrap();
+
+    let written = write_archive(
+      &archive_path,
+      &[("data/notes/note.md".to_string(), source)],
+      manifest("", &[]),
+    )
+    .unwrap();
+    let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+    assert_eq!(read_manifest(&mut archive).unwrap().entries.len(), 1);
+
+    let staging = dir.path().join("staging");
+    assert!(extract_verified(&mut archive, &written.entries, &staging).is_ok());
+    assert_eq!(
+      fs::read_to_string(staging.join("data/notes/note.md")).unwrap(),
+      "meeting notes"
+    );
+
+    let mut tampered = written.entries.clone();
+    tampered[0].sha256 = "0".repeat(64);
+    assert!(extract_verified(&mut archive, &tampered, &staging).is_err());
+  }
+
+  #[test]
+  fn only_restores_tokens_that_decrypt() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tokens.json");
+    fs::write(&path, r#"{"gateway_token":"plain","openai_model":null}"#).unwrap();
+    assert!(tokens_file_opens(&path).unwrap());
+
+    fs::write(&path, r#"{"gateway_token":"ks1:999:AAAA"}"#).unwrap();
+    assert!(!tokens_file_opens(&path).unwrap());
+  }
+
+  #[tokio::test]
+  async fn restore_waits_for_background_work_and_holds_off_new_work() {
+    let work = BackgroundWork::begin().unwrap();
+    let restore = tokio::spawn(RestoreInProgress::begin());
+    tokio::time::sleep(RESTORE_WAIT_POLL_INTERVAL * 2).await;
+    assert!(!restore.is_finished());
+    assert!(BackgroundWork::begin().is_none());
+
+    drop(work);
+    let restore = restore.await.unwrap().unwrap();
+    assert!(BackgroundWork::begin().is_none());
+    assert!(RestoreInProgress::begin().await.is_err());
+
+    drop(restore);
+    assert!(BackgroundWork::begin().is_some());
+  }
+}
@@ -2,100 +2,17 @@ use actix_web::{get, post, web, HttpResponse, Responder};
 use base64::Engine;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
-use std::fs;
-use std::path::{Path, PathBuf};
 use tauri::Manager;
 
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
+use crate::automations::schedule::{parse_schedule_text, ScheduleText};
 use crate::clawd::chat_agent;
+use crate::clawd::service::load_or_create_tokens;
 use crate::clawd::sidecar::SharedClawdbotConfig;
-
-// --- local token storage (shared with service.rs) ---
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct StoredTokens {
-  gateway_token: String,
-  browser_control_token: String,
-
-  // Keep these fields compatible with service.rs tokens.json
-  groq_api_key: Option<String>,
-  openai_api_key: Option<String>,
-  #[serde(default)]
-  openai_model: Option<String>,
-
-  // Multi-provider support
-  #[serde(default)]
-  anthropic_api_key: Option<String>,
-  #[serde(default)]
-  gemini_api_key: Option<String>,
-  #[serde(default)]
-  active_provider: Option<String>,
-}
-
-fn app_clawdbot_home(app_handle: &tauri::AppHandle) -> PathBuf {
-  app_handle
-    .path_resolver()
-    .app_data_dir()
-    .unwrap_or_else(|| PathBuf::from("."))
-    .join("clawdbot")
-}
-
-fn ensure_dir(p: &Path) -> Result<(), String> {
-  fs::create_dir_all(p).map_err(|e| format!("Failed to create dir {}: {}", p.display(), e))
-}
-
-fn tokens_path(app_handle: &tauri::AppHandle) -> PathBuf {
-  app_clawdbot_home(app_handle).join("tokens.json")
-}
-
-/// Set restrictive file permissions (owner read/write only) on sensitive files.
-fn harden_file_permissions(path: &Path) {
-  #[cfg(unix)]
-  {
-    use std::os::unix::fs::PermissionsExt;
-    let perms = std::fs::Permissions::from_mode(0o600);
-    let _ = std::fs::set_permissions(path, perms);
-  }
-  let _ = path;
-}
-
-fn load_or_create_tokens(app_handle: &tauri::AppHandle) -> Result<StoredTokens, String> {
-  let home = app_clawdbot_home(app_handle);
-  ensure_dir(&home)?;
-
-  let path = tokens_path(app_handle);
-  if path.exists() {
-    harden_file_permissions(&path);
-    let s =
-      fs::read_to_string(&path).map_err(|e| format!("Failed reading {}: {}", path.display(), e))?;
-    let t: StoredTokens =
-      serde_json::from_str(&s).map_err(|e| format!("Failed parsing {}: {}", path.display(), e))?;
-    return Ok(t);
-  }
-
-  // Generate long-ish random-ish tokens. (We can switch to a cryptographic RNG later.)
-  let gateway_token = uuid::Uuid::new_v4().to_string() + &uuid::Uuid::new_v4().to_string();
-  let browser_control_token = uuid::Uuid::new_v4().to_string() + &uuid::Uuid::new_v4().to_string();
-  let t = StoredTokens {
-    gateway_token,
-    browser_control_token,
-    groq_api_key: None,
-    openai_api_key: None,
-    openai_model: None,
-    anthropic_api_key: None,
-    gemini_api_key: None,
-    active_provider: None,
-  };
-
-  fs::write(&path, serde_json::to_string_pretty(&t).unwrap_or_default())
-    .map_err(|e| format!("Failed writing {}: {}", path.display(), e))?;
-  harden_file_permissions(&path);
-
-  Ok(t)
-}
+use crate::memory::semantic::SemanticService;
 
 fn bearer_token_for_control(app_handle: &tauri::AppHandle) -> Option<String> {
   // Prefer explicit env var if present.
@@ -128,6 +45,15 @@ fn clawd_profile(chrome: Option<bool>) -> &'static str {
   }
 }
 
+/// The embedding service, once the app has started one. Document tools fall
+/// back to SQL-only lookups without it.
+async fn managed_semantic_service(app_handle: &tauri::AppHandle) -> Option<SemanticService> {
+  let semantic_service =
+    app_handle.try_state::<Arc<tokio::sync::Mutex<Option<SemanticService>>>>()?;
+  let semantic_service = semantic_service.lock().await.clone();
+  semantic_service
+}
+
 async fn control_client() -> Result<reqwest::Client, String> {
   reqwest::Client::builder()
     .timeout(std::time::Duration::from_millis(15000))
@@ -638,95 +564,21 @@ pub async fn act(
 
 /// Parse a natural language schedule string into a cron schedule JSON value
 fn parse_schedule_to_cron(schedule_str: &str, timezone: Option<&str>) -> serde_json::Value {
-  let s = schedule_str.to_lowercase();
-
-  // Check for interval patterns like "every hour", "every 30 minutes"
-  if s.contains("every") {
-    // Every X minutes/hours
-    if let Some(caps) = regex::Regex::new(r"every\s+(\d+)\s*(minute|min|hour|hr|day)s?")
-      .ok()
-      .and_then(|re| re.captures(&s))
-    {
-      let num: u64 = caps.get(1).and_then(|m| m.as_str().parse().ok()).unwrap_or(1);
-      let unit = caps.get(2).map(|m| m.as_str()).unwrap_or("hour");
-      let ms = match unit {
-        "minute" | "min" => num * 60 * 1000,
-        "hour" | "hr" => num * 60 * 60 * 1000,
-        "day" => num * 24 * 60 * 60 * 1000,
-        _ => num * 60 * 60 * 1000, // default to hours
-      };
-      return json!({ "kind": "every", "everyMs": ms });
-    }
-
-    // Every hour (simple)
-    if s.contains("hour") && !s.contains("at") {
-      return json!({ "kind": "every", "everyMs": 3600000 }); // 1 hour
-    }
-
-    // Every day at X
-    if let Some(caps) = regex::Regex::new(r"every\s+day\s+at\s+(\d{1,2})(?::(\d{2}))?\s*(am|pm)?")
-      .ok()
-      .and_then(|re| re.captures(&s))
-    {
-      let mut hour: u32 = caps.get(1).and_then(|m| m.as_str().parse().ok()).unwrap_or(9);
-      let minute: u32 = caps.get(2).and_then(|m| m.as_str().parse().ok()).unwrap_or(0);
-      let ampm = caps.get(3).map(|m| m.as_str());
-      if ampm == Some("pm") && hour < 12 { hour += 12; }
-      if ampm == Some("am") && hour == 12 { hour = 0; }
-      let cron_expr = format!("{} {} * * *", minute, hour);
-      let mut result = json!({ "kind": "cron", "expr": cron_expr });
-      if let Some(tz) = timezone {
-        result["tz"] = json!(tz);
-      }
-      return result;
-    }
-
-    // Every [weekday] at X
-    let days = [
-      ("sunday", "0"), ("monday", "1"), ("tuesday", "2"), ("wednesday", "3"),
-      ("thursday", "4"), ("friday", "5"), ("saturday", "6"),
-      ("sun", "0"), ("mon", "1"), ("tue", "2"), ("wed", "3"),
-      ("thu", "4"), ("fri", "5"), ("sat", "6"),
-    ];
-    for (day_name, day_num) in days {
-      if s.contains(day_name) {
-        // Try to extract time
-        let hour_minute = regex::Regex::new(r"at\s+(\d{1,2})(?::(\d{2}))?\s*(am|pm)?")
-          .ok()
-          .and_then(|re| re.captures(&s));
-        let (hour, minute) = if let Some(caps) = hour_minute {
-          let mut h: u32 = caps.get(1).and_then(|m| m.as_str().parse().ok()).unwrap_or(9);
-          let m: u32 = caps.get(2).and_then(|m| m.as_str().parse().ok()).unwrap_or(0);
-          let ampm = caps.get(3).map(|m| m.as_str());
-          if ampm == Some("pm") && h < 12 { h += 12; }
-          if ampm == Some("am") && h == 12 { h = 0; }
-          (h, m)
-        } else {
-          (9, 0) // default 9am
-        };
-        let cron_expr = format!("{} {} * * {}", minute, hour, day_num);
-        let mut result = json!({ "kind": "cron", "expr": cron_expr });
-        if let Some(tz) = timezone {
-          result["tz"] = json!(tz);
-        }
-        return result;
-      }
+  let cron_expr = match parse_schedule_text(schedule_str) {
+    Some(ScheduleText::Interval { minutes }) => {
+      return json!({ "kind": "every", "everyMs": minutes * 60 * 1000 });
     }
+    Some(ScheduleText::Cron(expr)) => expr,
+    // Clawdbot also takes six-field expressions with seconds
+    None if schedule_str.split_whitespace().count() == 6 => schedule_str.to_string(),
+    // Default to every hour if we can't parse
+    None => return json!({ "kind": "every", "everyMs": 3600000 }),
+  };
+  let mut result = json!({ "kind": "cron", "expr": cron_expr });
+  if let Some(tz) = timezone {
+    result["tz"] = json!(tz);
   }
-
-  // Try to parse as a cron expression directly (5 or 6 fields)
-  let parts: Vec<&str> = schedule_str.split_whitespace().collect();
-  if parts.len() >= 5 && parts.len() <= 6 {
-    // Looks like a cron expression
-    let mut result = json!({ "kind": "cron", "expr": schedule_str });
-    if let Some(tz) = timezone {
-      result["tz"] = json!(tz);
-    }
-    return result;
-  }
-
-  // Default to every hour if we can't parse
-  json!({ "kind": "every", "everyMs": 3600000 })
+  result
 }
 
 /// Extract text from a PDF that's encoded as a base64 data URL
@@ -1619,6 +1471,147 @@ pub async fn chat(
       }
     }
 
+    if name == "list_accounts" {
+      use crate::db::models::user::User;
+
+      return match User::find_all() {
+        Ok(users) => {
+          let accounts: Vec<JsonValue> = users
+            .iter()
+            .map(|user| json!({ "account_id": user.id, "email": user.email, "active": user.is_active }))
+            .collect();
+          Ok(json!({"ok": true, "accounts": accounts}))
+        }
+        Err(e) => Ok(json!({"ok": false, "error": format!("Failed to list accounts: {:?}", e)})),
+      };
+    }
+
+    if name == "search_documents" {
+      use crate::db::models::user::User;
+      use crate::memory::search_filter::SearchFilter;
+
+      let args_value = serde_json::from_str::<JsonValue>(args).unwrap_or(json!({}));
+      let mut filter: SearchFilter = match serde_json::from_value(args_value) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(json!({"ok": false, "error": format!("Invalid filter: {}", e)})),
+      };
+      if let Some(account) = args_map.get("account").and_then(|v| v.as_str()) {
+        match User::find_by_email(account.trim().to_string()) {
+          Ok(user) => filter.account_id = user.id,
+          Err(_) => return Ok(json!({"ok": false, "error": format!("Unknown account: {}", account)})),
+        }
+      }
+      let query = args_map
+        .get("query")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .trim()
+        .to_string();
+      let limit = args_map
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(10)
+        .min(50) as usize;
+
+      let semantic_service = managed_semantic_service(app_handle).await;
+      match crate::memory::semantic::search_documents(semantic_service, query, limit, &filter).await {
+        Ok(documents) => {
+          let results: Vec<JsonValue> = documents
+            .iter()
+            .filter_map(|document| {
+              let knowledge = document.as_knowledge_snippet().ok()?;
+              Some(json!({
+                "document_id": document.id,
+                "type": knowledge.get_document_type(),
+                "title": knowledge.get_title(),
+                "summary": knowledge.get_summary(),
+                "uri": knowledge.get_hyperlink(),
+              }))
+            })
+            .collect();
+          return Ok(json!({"ok": true, "documents": results}));
+        }
+        Err(e) => {
+          return Ok(json!({"ok": false, "error": format!("Search failed: {:?}", e)}));
+        }
+      }
+    }
+
+    if name == "get_related_documents" {
+      let document_id = match args_map.get("document_id").and_then(|v| v.as_u64()) {
+        Some(document_id) => document_id,
+        None => return Ok(json!({"ok": false, "error": "Missing document_id"})),
+      };
+      let limit = args_map
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(10)
+        .min(50) as usize;
+
+      let semantic_service = managed_semantic_service(app_handle).await;
+      match crate::memory::related::related_documents(semantic_service, document_id, limit).await {
+        Ok(documents) => {
+          let results: Vec<JsonValue> = documents
+            .iter()
+            .filter_map(|document| {
+              let knowledge = document.as_knowledge_snippet().ok()?;
+              Some(json!({
+                "document_id": document.id,
+                "type": knowledge.get_document_type(),
+                "title": knowledge.get_title(),
+                "summary": knowledge.get_summary(),
+                "uri": knowledge.get_hyperlink(),
+              }))
+            })
+            .collect();
+          return Ok(json!({"ok": true, "documents": results}));
+        }
+        Err(e) => {
+          return Ok(json!({"ok": false, "error": format!("Related lookup failed: {:?}", e)}));
+        }
+      }
+    }
+
+    if name == "search_contacts" {
+      use crate::db::models::contact::Contact;
+
+      let query = args_map.get("query").and_then(|v| v.as_str());
+      let organization = args_map.get("organization").and_then(|v| v.as_str());
+      let limit = args_map
+        .get("limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(10)
+        .min(50) as usize;
+      match Contact::search(query, organization, limit) {
+        Ok(contacts) => return Ok(json!({"ok": true, "contacts": contacts})),
+        Err(e) => {
+          return Ok(json!({"ok": false, "error": format!("Contact search failed: {:?}", e)}));
+        }
+      }
+    }
+
+    if name == "get_contact" {
+      use crate::db::models::contact::Contact;
+
+      let contact = if let Some(contact_id) = args_map.get("contact_id").and_then(|v| v.as_u64()) {
+        Contact::find_by_id(contact_id)
+      } else if let Some(email) = args_map.get("email").and_then(|v| v.as_str()) {
+        Contact::find_by_email(email.trim())
+      } else {
+        return Ok(json!({"ok": false, "error": "Provide contact_id or email"}));
+      };
+      let contact = match contact {
+        Ok(Some(contact)) => contact,
+        Ok(None) => return Ok(json!({"ok": false, "error": "Contact not found"})),
+        Err(e) => return Ok(json!({"ok": false, "error": format!("{:?}", e)})),
+      };
+      let links = contact
+        .id
+        .map(|contact_id| Contact::links(contact_id, 25).unwrap_or_default())
+        .unwrap_or_default();
+      return Ok(json!({"ok": true, "contact": contact, "links": links}));
+    }
+
     // Meeting context tools
     if name == "list_recent_meetings" {
       let days = args_map
@@ -318,6 +318,89 @@ pub fn default_tools() -> Vec<OaiToolSpec> {
         }),
       },
     },
+    // Knowledge search
+    OaiToolSpec {
+      kind: "function".to_string(),
+      function: OaiToolSpecFn {
+        name: "list_accounts".to_string(),
+        description: "List the Google and Microsoft accounts the user has signed in with, and which one is active. Searches cover the active account unless another account is given.".to_string(),
+        parameters: json!({
+          "type": "object",
+          "properties": {},
+          "additionalProperties": false
+        }),
+      },
+    },
+    OaiToolSpec {
+      kind: "function".to_string(),
+      function: OaiToolSpecFn {
+        name: "search_documents".to_string(),
+        description: "Search the user's synced emails, Google Drive documents, local files, meeting transcripts and meeting notes. Combine a keyword query with structured filters, e.g. emails from alice@example.com last week about pricing. Dates are unix timestamps in seconds.".to_string(),
+        parameters: json!({
+          "type": "object",
+          "properties": {
+            "query": { "type": "string", "description": "Keywords to look for" },
+            "sources": { "type": "array", "items": { "type": "string", "enum": ["email", "drive", "file", "transcript", "notes"] } },
+            "date_from": { "type": "integer", "description": "Only documents dated at or after this unix timestamp" },
+            "date_to": { "type": "integer", "description": "Only documents dated at or before this unix timestamp" },
+            "participants": { "type": "array", "items": { "type": "string" }, "description": "Names or email addresses that must appear as sender, recipient or cc" },
+            "email_thread_id": { "type": "string" },
+            "calendar_event_id": { "type": "integer" },
+            "path_prefix": { "type": "string", "description": "Only local files under this folder" },
+            "account": { "type": "string", "description": "Email address of the account whose emails and drive documents to search (default: the active account, see list_accounts)" },
+            "limit": { "type": "integer", "description": "Maximum results (default: 10, max: 50)" }
+          },
+          "additionalProperties": false
+        }),
+      },
+    },
+    OaiToolSpec {
+      kind: "function".to_string(),
+      function: OaiToolSpecFn {
+        name: "get_related_documents".to_string(),
+        description: "Find documents related to a document_id returned by search_documents: other messages in the same email thread or meeting, and emails, files and meetings that share participants.".to_string(),
+        parameters: json!({
+          "type": "object",
+          "properties": {
+            "document_id": { "type": "integer" },
+            "limit": { "type": "integer", "description": "Maximum results (default: 10, max: 50)" }
+          },
+          "required": ["document_id"],
+          "additionalProperties": false
+        }),
+      },
+    },
+    OaiToolSpec {
+      kind: "function".to_string(),
+      function: OaiToolSpecFn {
+        name: "search_contacts".to_string(),
+        description: "Search the people the user has emailed or met with, built from emails, calendar events and meeting transcripts. Returns contact_ids with names, addresses, organization, interaction count and last contacted time (unix seconds). Without query, returns the most recently contacted people.".to_string(),
+        parameters: json!({
+          "type": "object",
+          "properties": {
+            "query": { "type": "string", "description": "Part of a name or email address" },
+            "organization": { "type": "string", "description": "Organization domain, e.g. acme.com" },
+            "limit": { "type": "integer", "description": "Maximum results (default: 10, max: 50)" }
+          },
+          "additionalProperties": false
+        }),
+      },
+    },
+    OaiToolSpec {
+      kind: "function".to_string(),
+      function: OaiToolSpecFn {
+        name: "get_contact".to_string(),
+        description: "Get one contact by contact_id or email address, with the emails, calendar events and meetings they appear in (most recent first).".to_string(),
+        parameters: json!({
+          "type": "object",
+          "properties": {
+            "contact_id": { "type": "integer" },
+            "email": { "type": "string" }
+          },
+          "additionalProperties": false
+        }),
+      },
+    },
     // Meeting context tools
     OaiToolSpec {
       kind: "function".to_string(),
@@ -2,6 +2,7 @@ use actix_web::{get, web, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 
 use crate::db::models::email::Email;
+use crate::db::repo::run_blocking;
 
 #[derive(Debug, Deserialize)]
 pub struct UnreadImportantParams {
@@ -40,7 +41,14 @@ pub async fn get_unread_important(query: web::Query<UnreadImportantParams>) -> i
 
   // NOTE: This relies on emails already being synced into the local DB.
   // We currently filter in-memory because Email model APIs are limited.
-  let mut emails = Email::get_recent_emails(top * 5);
+  let mut emails = match run_blocking(move || Email::get_recent_emails(top * 5)).await {
+    Ok(emails) => emails,
+    Err(error) => {
+      log::error!("Failed to list recent emails: {:?}", error);
+      return HttpResponse::InternalServerError()
+        .json(serde_json::json!({ "success": false, "error": format!("{:?}", error) }));
+    }
+  };
 
   emails.retain(|e| e.is_deleted != Some(true));
   emails.retain(|e| e.is_read != Some(true));
@@ -173,11 +173,7 @@ pub fn get_transcript_content(thread_id: u64) -> Result<String, String> {
     .map_err(|e| format!("DB error: {:?}", e))?
     .ok_or_else(|| format!("No transcript found for thread_id {}", thread_id))?;
 
-  let home_dir = dirs::home_dir().ok_or("Could not determine home directory")?;
-  let transcript_path = home_dir
-    .join(".knapsack")
-    .join("transcripts")
-    .join(&transcript.filename);
+  let transcript_path = crate::config::transcripts_dir().join(&transcript.filename);
 
   let content =
     read_to_string(&transcript_path).map_err(|e| format!("Failed to read transcript file: {}", e))?;
@@ -198,11 +194,7 @@ pub fn get_transcript_content(thread_id: u64) -> Result<String, String> {
 
 /// Get the user's notes for a specific meeting. Returns None if no notes exist.
 pub fn get_notes_content(thread_id: u64) -> Result<Option<String>, String> {
-  let home_dir = dirs::home_dir().ok_or("Could not determine home directory")?;
-  let notes_path = home_dir
-    .join(".knapsack")
-    .join("notes")
-    .join(thread_id.to_string());
+  let notes_path = crate::config::notes_dir().join(thread_id.to_string());
 
   if !notes_path.exists() {
     return Ok(None);
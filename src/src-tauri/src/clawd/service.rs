@@ -5,6 +5,7 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use crate::clawd::sidecar::SharedClawdbotConfig;
+use crate::secrets::SecretStore;
 
 const LAUNCH_AGENT_LABEL: &str = "ai.knap.knapsack.clawdbot";
 
@@ -46,7 +47,7 @@ fn launch_agent_plist_path() -> Result<PathBuf, String> {
   )
 }
 
-fn app_clawdbot_home(app_handle: &tauri::AppHandle) -> PathBuf {
+pub(crate) fn app_clawdbot_home(app_handle: &tauri::AppHandle) -> PathBuf {
   app_handle
     .path_resolver()
     .app_data_dir()
@@ -59,23 +60,56 @@ fn ensure_dir(p: &Path) -> Result<(), String> {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct StoredTokens {
-  gateway_token: String,
-  browser_control_token: String,
+pub(crate) struct StoredTokens {
+  pub(crate) gateway_token: String,
+  pub(crate) browser_control_token: String,
 
   // Optional: used by the embedded Clawdbot browser server chat agent.
-  groq_api_key: Option<String>,
-  openai_api_key: Option<String>,
-  openai_model: Option<String>,
+  pub(crate) groq_api_key: Option<String>,
+  pub(crate) openai_api_key: Option<String>,
+  pub(crate) openai_model: Option<String>,
 
   // Multi-provider support
   #[serde(default)]
-  anthropic_api_key: Option<String>,
+  pub(crate) anthropic_api_key: Option<String>,
   #[serde(default)]
-  gemini_api_key: Option<String>,
+  pub(crate) gemini_api_key: Option<String>,
   /// Which provider is currently selected: "openai", "anthropic", "gemini"
   #[serde(default)]
-  active_provider: Option<String>,
+  pub(crate) active_provider: Option<String>,
+}
+
+impl StoredTokens {
+  /// Applies `f` to every secret field. Bearer tokens and API keys are
+  /// encrypted in tokens.json; the model and provider names stay readable.
+  fn map_secrets(
+    self,
+    f: impl Fn(&str) -> Result<String, crate::error::Error>,
+  ) -> Result<StoredTokens, String> {
+    let apply = |value: String| f(&value).map_err(|e| format!("Failed to read secret: {:?}", e));
+    let apply_opt = |value: Option<String>| value.map(|value| apply(value)).transpose();
+    Ok(StoredTokens {
+      gateway_token: apply(self.gateway_token)?,
+      browser_control_token: apply(self.browser_control_token)?,
+      groq_api_key: apply_opt(self.groq_api_key)?,
+      openai_api_key: apply_opt(self.openai_api_key)?,
+      openai_model: self.openai_model,
+      anthropic_api_key: apply_opt(self.anthropic_api_key)?,
+      gemini_api_key: apply_opt(self.gemini_api_key)?,
+      active_provider: self.active_provider,
+    })
+  }
+
+  fn needs_reseal(&self) -> Result<bool, crate::error::Error> {
+    let api_keys = [&self.groq_api_key, &self.openai_api_key, &self.anthropic_api_key, &self.gemini_api_key];
+    let secrets = api_keys.into_iter().flatten().chain([&self.gateway_token, &self.browser_control_token]);
+    for secret in secrets {
+      if SecretStore::needs_reseal(secret)? {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
 }
 
 fn tokens_path(app_handle: &tauri::AppHandle) -> PathBuf {
@@ -106,7 +140,18 @@ fn harden_dir_permissions(path: &Path) {
   let _ = path;
 }
 
-fn load_or_create_tokens(app_handle: &tauri::AppHandle) -> Result<StoredTokens, String> {
+/// Seals the secrets and writes tokens.json with owner-only permissions.
+fn write_tokens(path: &Path, tokens: &StoredTokens) -> Result<(), String> {
+  let sealed = tokens
+    .clone()
+    .map_secrets(SecretStore::encrypt)?;
+  fs::write(path, serde_json::to_string_pretty(&sealed).unwrap_or_default())
+    .map_err(|e| format!("Failed writing {}: {}", path.display(), e))?;
+  harden_file_permissions(path);
+  Ok(())
+}
+
+pub(crate) fn load_or_create_tokens(app_handle: &tauri::AppHandle) -> Result<StoredTokens, String> {
   let home = app_clawdbot_home(app_handle);
   ensure_dir(&home)?;
   harden_dir_permissions(&home);
@@ -119,6 +164,16 @@ fn load_or_create_tokens(app_handle: &tauri::AppHandle) -> Result<StoredTokens,
       fs::read_to_string(&path).map_err(|e| format!("Failed reading {}: {}", path.display(), e))?;
     let t: StoredTokens =
       serde_json::from_str(&s).map_err(|e| format!("Failed parsing {}: {}", path.display(), e))?;
+    // Files written before encryption, or under a rotated key, are
+    // rewritten on first read.
+    let needs_reseal = t.needs_reseal().unwrap_or_else(|e| {
+      log::warn!("Not resealing {}: {:?}", path.display(), e);
+      false
+    });
+    let t = t.map_secrets(SecretStore::decrypt)?;
+    if needs_reseal {
+      write_tokens(&path, &t)?;
+    }
     return Ok(t);
   }
 
@@ -136,9 +191,7 @@ fn load_or_create_tokens(app_handle: &tauri::AppHandle) -> Result<StoredTokens,
     active_provider: None, // Defaults to openai
   };
 
-  fs::write(&path, serde_json::to_string_pretty(&t).unwrap_or_default())
-    .map_err(|e| format!("Failed writing {}: {}", path.display(), e))?;
-  harden_file_permissions(&path);
+  write_tokens(&path, &t)?;
 
   Ok(t)
 }
@@ -186,14 +239,13 @@ fn save_tokens(app_handle: &tauri::AppHandle, tokens: &StoredTokens) -> Result<(
   let home = app_clawdbot_home(app_handle);
   ensure_dir(&home)?;
   harden_dir_permissions(&home);
-  let path = tokens_path(app_handle);
-  fs::write(
-    &path,
-    serde_json::to_string_pretty(tokens).unwrap_or_default(),
-  )
-  .map_err(|e| format!("Failed writing {}: {}", path.display(), e))?;
-  harden_file_permissions(&path);
-  Ok(())
+  write_tokens(&tokens_path(app_handle), tokens)
+}
+
+/// Rewrites tokens.json under the active secret key, after a key rotation.
+pub(crate) fn reseal_tokens(app_handle: &tauri::AppHandle) -> Result<(), String> {
+  let tokens = load_or_create_tokens(app_handle)?;
+  save_tokens(app_handle, &tokens)
 }
 
 /// Get the configured OpenAI model (defaults to gpt-4o if not set)
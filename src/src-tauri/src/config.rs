@@ -1,18 +1,161 @@
 use std::path::PathBuf;
-use tokio::sync::RwLock;
+use std::sync::RwLock;
+
+use clap::Parser;
 use lazy_static::lazy_static;
 
+use crate::db::db::KNAPSACK_DB_FILENAME;
+use crate::{KNAPSACK_DATA_DIR, TRANSCRIPTS_DIR};
+
+/// Overrides the data root, like `--data-root`.
+pub const DATA_ROOT_ENV: &str = "KNAPSACK_DATA_ROOT";
+const NOTES_DIR: &str = "notes";
+const AUDIO_DIR: &str = "audio";
+/// Readable copies of transcripts the user asked to keep, named after the
+/// meeting.
+const SAVED_TRANSCRIPTS_DIR: &str = "saved";
+
 lazy_static! {
     pub static ref CONFIG: RwLock<KnapsackConfig> = RwLock::new(KnapsackConfig::default());
 }
 
-#[derive(Debug, Default)]
+#[derive(Parser, Debug)]
+pub struct Cli {
+  /// Directory holding `.knapsack.db` and `.knapsack/`, so several
+  /// profiles can run side by side. Defaults to the home directory.
+  #[arg(long)]
+  pub data_root: Option<PathBuf>,
+
+  /// The audio device to use
+  #[arg(short, long, default_value_t = String::from("default"))]
+  pub device: String,
+
+  /// Use the JACK host
+  #[cfg(all(
+    any(
+      target_os = "linux",
+      target_os = "dragonfly",
+      target_os = "freebsd",
+      target_os = "netbsd"
+    ),
+    feature = "default"
+  ))]
+  #[arg(short, long)]
+  #[allow(dead_code)]
+  pub jack: bool,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct KnapsackConfig {
-  pub data_dir: PathBuf,
+  /// Every database and data file path is resolved under this directory.
+  pub data_root: PathBuf,
   pub was_initialized: bool,
 }
 
-pub async fn init_knapsack_config(data_dir: PathBuf) {
-  let mut config = CONFIG.write().await;
-  config.data_dir = data_dir;
+impl KnapsackConfig {
+  pub fn db_path(&self) -> PathBuf {
+    self.data_root.join(KNAPSACK_DB_FILENAME)
+  }
+
+  pub fn data_dir(&self) -> PathBuf {
+    self.data_root.join(KNAPSACK_DATA_DIR)
+  }
+
+  pub fn transcripts_dir(&self) -> PathBuf {
+    self.data_dir().join(TRANSCRIPTS_DIR)
+  }
+
+  pub fn saved_transcripts_dir(&self) -> PathBuf {
+    self.transcripts_dir().join(SAVED_TRANSCRIPTS_DIR)
+  }
+
+  pub fn notes_dir(&self) -> PathBuf {
+    self.data_dir().join(NOTES_DIR)
+  }
+
+  pub fn audio_dir(&self) -> PathBuf {
+    self.data_dir().join(AUDIO_DIR)
+  }
+}
+
+/// Picks the data root: `--data-root`, then `KNAPSACK_DATA_ROOT`, then the
+/// home directory.
+pub fn resolve_data_root(cli_data_root: Option<PathBuf>) -> PathBuf {
+  cli_data_root
+    .or_else(|| {
+      std::env::var_os(DATA_ROOT_ENV)
+        .filter(|data_root| !data_root.is_empty())
+        .map(PathBuf::from)
+    })
+    .unwrap_or_else(|| dirs::home_dir().expect("Couldn't get home_dir for platform."))
+}
+
+pub fn init_knapsack_config(data_root: PathBuf) {
+  let mut config = CONFIG.write().unwrap();
+  config.data_root = data_root;
+  config.was_initialized = true;
+}
+
+/// The active configuration. Before `init_knapsack_config` runs, e.g. in
+/// tests, paths resolve as if no flag was given.
+pub fn knapsack_config() -> KnapsackConfig {
+  let config = CONFIG.read().unwrap();
+  if config.was_initialized {
+    return config.clone();
+  }
+  KnapsackConfig {
+    data_root: resolve_data_root(None),
+    was_initialized: false,
+  }
+}
+
+pub fn data_root() -> PathBuf {
+  knapsack_config().data_root
+}
+
+pub fn db_path() -> PathBuf {
+  knapsack_config().db_path()
+}
+
+pub fn data_dir() -> PathBuf {
+  knapsack_config().data_dir()
+}
+
+pub fn transcripts_dir() -> PathBuf {
+  knapsack_config().transcripts_dir()
+}
+
+pub fn saved_transcripts_dir() -> PathBuf {
+  knapsack_config().saved_transcripts_dir()
+}
+
+pub fn notes_dir() -> PathBuf {
+  knapsack_config().notes_dir()
+}
+
+pub fn audio_dir() -> PathBuf {
+  knapsack_config().audio_dir()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn resolves_every_path_under_the_data_root() {
+    let data_root = resolve_data_root(Some(PathBuf::from("/tmp/knapsack-profile")));
+    let config = KnapsackConfig {
+      data_root: data_root.clone(),
+      was_initialized: true,
+    };
+    assert_eq!(data_root, PathBuf::from("/tmp/knapsack-profile"));
+    assert_eq!(config.db_path(), data_root.join(".knapsack.db"));
+    assert_eq!(config.notes_dir(), data_root.join(".knapsack/notes"));
+    assert_eq!(config.transcripts_dir(), data_root.join(".knapsack/transcripts"));
+    assert_eq!(
+      config.saved_transcripts_dir(),
+      data_root.join(".knapsack/transcripts/saved")
+    );
+    assert_eq!(config.audio_dir(), data_root.join(".knapsack/audio"));
+  }
 }
@@ -2,6 +2,7 @@ use serde_json::Value;
 
 use crate::db::models::{
   document::Document, drive_document::DriveDocument, email::Email, local_file::LocalFile,
+  meeting_notes::MeetingNotes, transcript::Transcript,
 };
 use crate::error::Error;
 
@@ -41,6 +42,20 @@ impl KnowledgeSource {
           }
           Some(Box::new(local_file.unwrap()))
         }
+        "transcripts" => {
+          let transcript = Transcript::find_by_id(doc.foreign_table_id)?;
+          if transcript.is_none() {
+            return Ok(None);
+          }
+          Some(Box::new(transcript.unwrap()))
+        }
+        "meeting_notes" => {
+          let notes = MeetingNotes::find_by_thread_id(doc.foreign_table_id)?;
+          if notes.is_none() {
+            return Ok(None);
+          }
+          Some(Box::new(notes.unwrap()))
+        }
         _ => None,
       };
       let title = data.as_ref().map(|d| d.get_title().clone());
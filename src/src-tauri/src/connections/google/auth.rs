@@ -26,6 +26,7 @@ use crate::spotlight::WINDOW_LABEL;
 use crate::db::models::connection::Connection;
 use crate::db::models::user::User;
 use crate::db::models::user_connection::UserConnection;
+use crate::db::repo::run_blocking;
 
 use super::constants::GOOGLE_CALENDAR_SCOPE;
 use super::constants::GOOGLE_DRIVE_SCOPE;
@@ -387,11 +388,17 @@ async fn complete_google_signin(
     email.hash(&mut hasher);
     format!("local-{:x}", hasher.finish())
   });
-  let _ = User {
-    id: None,
-    email: email.clone(),
-    uuid: Some(uuid),
-  }.create();
+  let user_email = email.clone();
+  let _ = run_blocking(move || {
+    User {
+      id: None,
+      email: user_email,
+      uuid: Some(uuid),
+      is_active: false,
+    }
+    .create()
+  })
+  .await;
 
   // Create Knapsack API connection only if we have internal tokens (non-self-hosted mode)
   if let Some(ref refresh_internal) = response.refresh_internal {
@@ -517,6 +524,8 @@ pub fn create_user_connection(
     refresh_token: Some(refresh_token.clone()),
     connection: None,
     last_synced: None,
+    sync_status: None,
+    sync_error: None,
   };
   user_connection.upsert()
 }
@@ -538,11 +547,11 @@ async fn fetch_google_auth_token_api(
 ) -> impl Responder {
   let params =
     actix_web::web::Query::<FetchAccessTokenParams>::from_query(req.query_string()).unwrap();
-  let user_connection = UserConnection::find_by_user_email_and_scope(
-    params.email.clone(),
-    params.scope.clone(),
-  )
-  .unwrap();
+  let (email, scope) = (params.email.clone(), params.scope.clone());
+  let user_connection =
+    run_blocking(move || UserConnection::find_by_user_email_and_scope(email, scope))
+      .await
+      .unwrap();
   match refresh_connection_token(params.email.clone(), user_connection.clone()).await {
     Ok(access_token) => HttpResponse::Ok().json(AccessTokenResponse {
       success: true,
@@ -10,12 +10,16 @@ use tokio::sync::Mutex;
 use crate::spotlight::WINDOW_LABEL;
 use tauri::Manager;
 
+use crate::backup::BackgroundWork;
 use crate::connections::utils::get_knapsack_api_connection;
 use crate::connections::api::ConnectionsEnum;
 use crate::db::models::calendar_event::CalendarEvent;
+use crate::db::repo::run_blocking;
+use crate::db::models::sync_run::{SyncOutcome, SyncRun};
 use crate::db::models::user_connection::UserConnection;
 
 use crate::error::Error;
+use crate::memory::contacts::index_calendar_event_by_event_id;
 use crate::ConnectionsData;
 
 use super::auth::refresh_connection_token;
@@ -72,6 +76,10 @@ pub async fn fetch_calendar(
     }
   };
 
+  let work = match BackgroundWork::begin() {
+    Some(work) => work,
+    None => return Ok(()),
+  };
   if ConnectionsData::lock_and_get_connection_is_syncing(
     connections_data.clone(),
     ConnectionsEnum::GoogleCalendar,
@@ -88,7 +96,11 @@ pub async fn fetch_calendar(
   )
   .await;
 
+  let account_id = user_connection.user_id;
+  let sync_run = SyncRun::start(user_connection.id.unwrap());
+
   tauri::async_runtime::spawn(async move {
+    let _work = work;
     let hub = CalendarHub::new(
       hyper::Client::builder().build(
         hyper_rustls::HttpsConnectorBuilder::new()
@@ -108,7 +120,15 @@ pub async fn fetch_calendar(
     let mut page_token: Option<String> = None;
 
     let mut event_ids_total: Vec<String> = Vec::new();
-    loop {
+    let mut sync_result: Result<SyncOutcome, Error> = Ok(SyncOutcome::default());
+    let known_event_ids = match CalendarEvent::find_event_ids_by_account(account_id) {
+      Ok(known_event_ids) => known_event_ids,
+      Err(error) => {
+        sync_result = Err(error);
+        Default::default()
+      }
+    };
+    while sync_result.is_ok() {
       let mut request = hub
         .events()
         .list(&email)
@@ -195,10 +215,13 @@ pub async fn fetch_calendar(
               google_meet_url,
               recurrence_json,
               recurrence_id,
+              account_id: Some(account_id),
             };
             if let Err(e) = calendar_event.create() {
               let msg = format!("Failed to create calendar event: {:?}", e);
               knap_log_error(msg, Some(e), Some(true));
+            } else {
+              index_calendar_event_by_event_id(&calendar_event.event_id);
             }
           }
 
@@ -211,7 +234,8 @@ pub async fn fetch_calendar(
           log::error!("Calendar sync failed {:?}", error.to_string());
           let error_msg = format!("Fetch calendar failed: {:?}", error.to_string()
           );
-          knap_log_error(error_msg, None, Some(true));
+          knap_log_error(error_msg.clone(), None, Some(true));
+          sync_result = Err(Error::KSError(error_msg));
           ConnectionsData::lock_and_set_connection_is_syncing(
             connections_data.clone(),
             ConnectionsEnum::GoogleCalendar,
@@ -223,14 +247,18 @@ pub async fn fetch_calendar(
       };
     }
     let event_count = event_ids_total.len();
-    CalendarEvent::delete_calendar_events_removed(event_ids_total);
+    // A partial listing would make every event it missed look removed.
+    if sync_result.is_ok() {
+      sync_result = CalendarEvent::delete_calendar_events_removed(event_ids_total.clone(), account_id)
+        .map(|deleted| SyncOutcome::from_ids(&known_event_ids, &event_ids_total, deleted as u64));
+    }
+    sync_run.finish(&sync_result);
     ConnectionsData::lock_and_set_connection_is_syncing(
       connections_data,
       ConnectionsEnum::GoogleCalendar,
       false,
     )
     .await;
-    UserConnection::update_last_sync_by_id(user_connection.id.unwrap(), two_weeks_ago);
     let window = app_handle.get_window(WINDOW_LABEL).unwrap();
     window.emit(
       "finish_fetch_calendar",
@@ -283,12 +311,20 @@ pub struct GoogleCalendarGetEventsParams {
 #[get("/api/knapsack/connections/google/calendar/get_events")]
 async fn get_events(req: HttpRequest) -> impl Responder {
   let params =
-    actix_web::web::Query::<GoogleCalendarGetEventsParams>::from_query(req.query_string()).unwrap();
-  let events = CalendarEvent::find_by_timestamp_range(
-    params.start_timestamp.clone(),
-    params.end_timestamp.clone(),
-  );
-  HttpResponse::Ok().json(events)
+    match actix_web::web::Query::<GoogleCalendarGetEventsParams>::from_query(req.query_string()) {
+      Ok(params) => params,
+      Err(error) => return HttpResponse::BadRequest().json(format!("{:?}", error)),
+    };
+  let (start_timestamp, end_timestamp) = (params.start_timestamp, params.end_timestamp);
+  match run_blocking(move || CalendarEvent::find_by_timestamp_range(start_timestamp, end_timestamp))
+    .await
+  {
+    Ok(events) => HttpResponse::Ok().json(events),
+    Err(error) => {
+      log::error!("Failed to list calendar events: {:?}", error);
+      HttpResponse::InternalServerError().json(format!("{:?}", error))
+    }
+  }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -298,13 +334,25 @@ pub struct GoogleCalendarGetRecurrenceEventsParams {
 
 #[get("/api/knapsack/connections/google/calendar/get_emails_by_recurrence_id")]
 async fn get_event_ids_by_recurrence_ids(req: HttpRequest) -> impl Responder {
-  let params = actix_web::web::Query::<GoogleCalendarGetRecurrenceEventsParams>::from_query(
+  let params = match actix_web::web::Query::<GoogleCalendarGetRecurrenceEventsParams>::from_query(
     req.query_string(),
-  )
-  .unwrap();
+  ) {
+    Ok(params) => params,
+    Err(error) => return HttpResponse::BadRequest().json(format!("{:?}", error)),
+  };
   let recurrence_id = params.recurrence_id.clone();
 
-  let calendar_events = CalendarEvent::get_calendar_event_by_recurrence_id(recurrence_id);
+  let calendar_events = match run_blocking(move || {
+    CalendarEvent::get_calendar_event_by_recurrence_id(recurrence_id)
+  })
+  .await
+  {
+    Ok(calendar_events) => calendar_events,
+    Err(error) => {
+      log::error!("Failed to list recurring calendar events: {:?}", error);
+      return HttpResponse::InternalServerError().json(format!("{:?}", error));
+    }
+  };
 
   let mut event_ids = Vec::new();
 
@@ -1,3 +1,4 @@
+use crate::backup::BackgroundWork;
 use crate::connections::api::ConnectionsEnum;
 use crate::db::models::drive_document::{self, create_drive_document};
 use crate::memory::text_splitter::TextSplitter;
@@ -18,12 +19,15 @@ use std::sync::Arc;
 use tokio::sync::{Mutex, Semaphore};
 
 use crate::constants::EMBEDDING_BATCH_SIZE;
+use crate::db::repo::run_blocking;
 use crate::db::models::{
   drive_document::DriveDocument, local_file::LocalFile,
+  sync_run::{SyncOutcome, SyncRun},
   user_connection::UserConnection,
 };
 use crate::error::Error;
 use crate::local_fs;
+use crate::memory::contacts::{drive_people_json, index_drive_document};
 use crate::memory::semantic::SemanticService;
 
 use crate::utils::log::knap_log_error;
@@ -280,6 +284,7 @@ pub async fn get_or_create_drive_document_from_file(
   file: &File,
   temp_dir: &PathBuf,
   hub: &DriveHub<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>,
+  account_id: u64,
 ) -> DriveDocument {
   let mime_type = file.mime_type.clone().unwrap();
   let drive_id = file.id.clone().unwrap();
@@ -301,6 +306,10 @@ pub async fn get_or_create_drive_document_from_file(
   if existing_drive_document.is_some() {
     let mut drive_document = existing_drive_document.unwrap().clone();
     drive_document.content_chunks = maybe_content;
+    if let Err(e) = drive_document.claim_for_account(account_id) {
+      log::error!("Error assigning drive document to account: {:?}", e);
+    }
+    index_drive_file_people(&drive_document, file);
     return drive_document;
   }
 
@@ -316,6 +325,7 @@ pub async fn get_or_create_drive_document_from_file(
     url,
     timestamp: None,
     content_chunks: maybe_content,
+    account_id: Some(account_id),
   };
 
   let insert_result = drive_document.create();
@@ -323,15 +333,57 @@ pub async fn get_or_create_drive_document_from_file(
   if let Err(e) = insert_result {
     log::error!("Error inserting drive document: {:?}", e);
   }
+  index_drive_file_people(&drive_document, file);
   drive_document
 }
 
+/// Owners and last editor of `file`, in the shape of
+/// `drive_documents.people_json`.
+fn drive_file_people_json(file: &File) -> String {
+  let mut people = Vec::new();
+  for owner in file.owners.clone().unwrap_or_default() {
+    if let Some(email) = owner.email_address {
+      people.push((email, owner.display_name, "owner"));
+    }
+  }
+  if let Some(editor) = file.last_modifying_user.clone() {
+    if let Some(email) = editor.email_address {
+      people.push((email, editor.display_name, "editor"));
+    }
+  }
+  drive_people_json(people)
+}
+
+/// Links the file's owners, last editor and the known people its text
+/// mentions as contacts.
+fn index_drive_file_people(drive_document: &DriveDocument, file: &File) {
+  let id = match drive_document.id {
+    Some(id) => id,
+    None => return,
+  };
+  let people_json = drive_file_people_json(file);
+  let text = drive_document
+    .content_chunks
+    .clone()
+    .unwrap_or_default()
+    .join("\n");
+  let result = DriveDocument::set_people_json(id, &people_json).and_then(|_| {
+    index_drive_document(id, &people_json, &text, drive_document.date_modified)
+  });
+  if let Err(e) = result {
+    log::error!("Failed to index contacts for drive document {}: {:?}", id, e);
+  }
+}
+
 pub async fn fetch_drive(
   access_token: String,
   _semantic_service: Arc<Mutex<Option<SemanticService>>>,
   user_connection: UserConnection,
-) -> Result<(), Error> {
+) -> Result<SyncOutcome, Error> {
   let mut maybe_next_page_token: Option<String> = None;
+  let account_id = user_connection.user_id;
+  let known_drive_ids = DriveDocument::find_drive_ids_by_account(account_id)?;
+  let mut fetched_drive_ids: Vec<String> = Vec::new();
   let hub = DriveHub::new(
     hyper::Client::builder().build(
       hyper_rustls::HttpsConnectorBuilder::new()
@@ -343,6 +395,14 @@ pub async fn fetch_drive(
     ),
     access_token,
   );
+  // Read before listing, so a later incremental sync replays from here.
+  let start_page_token = match hub.changes().get_start_page_token().doit().await {
+    Ok((_, token)) => token.start_page_token,
+    Err(error) => {
+      log::warn!("Failed to read drive start page token: {:?}", error);
+      None
+    }
+  };
   let days_in_month = 30;
   let limit_date = chrono::Utc::now() - chrono::Duration::days(days_in_month);
   let query = format!(
@@ -355,8 +415,7 @@ pub async fn fetch_drive(
       .join(" or "),
     limit_date.format("%Y-%m-%dT%H:%M:%S")
   );
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let temp_dir = home_dir.join("knapsack_temp");
+  let temp_dir = crate::config::data_root().join("knapsack_temp");
   fs::create_dir_all(temp_dir.clone()).unwrap();
   // let attrs = DriveDocument::get_attrs();
   loop {
@@ -367,7 +426,7 @@ pub async fn fetch_drive(
         .q(&query)
         .param(
           "fields",
-          "nextPageToken,files(id,name,md5Checksum,version,createdTime,modifiedTime,size,mimeType,webViewLink)",
+          "nextPageToken,files(id,name,md5Checksum,version,createdTime,modifiedTime,size,mimeType,webViewLink,owners(emailAddress,displayName),lastModifyingUser(emailAddress,displayName))",
         )
         .page_size(500);
     if let Some(next_page_token) = maybe_next_page_token {
@@ -380,6 +439,7 @@ pub async fn fetch_drive(
     let drive_documents = Arc::new(Mutex::new(Vec::new()));
     maybe_next_page_token = file_list.clone().next_page_token;
     for file in file_list.files.unwrap() {
+      fetched_drive_ids.extend(file.id.clone());
       let semaphore_clone = Arc::clone(&semaphore);
       let temp_dir_clone = temp_dir.clone();
       let hub_clone = hub.clone();
@@ -387,7 +447,7 @@ pub async fn fetch_drive(
       let task = tauri::async_runtime::spawn(async move {
         let _permit = semaphore_clone.acquire().await.unwrap();
         let drive_document =
-          get_or_create_drive_document_from_file(&file, &temp_dir_clone, &hub_clone).await;
+          get_or_create_drive_document_from_file(&file, &temp_dir_clone, &hub_clone, account_id).await;
         drive_documents_clone.lock().await.push(drive_document);
       });
       tasks.push(task);
@@ -428,8 +488,7 @@ pub async fn fetch_drive(
   //   .add_handle_embed_finish_to_queue(ConnectionsEnum::GoogleDrive, 1)
   //   .await;
   let _ = fs::remove_dir_all(temp_dir);
-  UserConnection::update_last_sync_by_id(user_connection.id.unwrap(), limit_date);
-  Ok(())
+  Ok(SyncOutcome::from_ids(&known_drive_ids, &fetched_drive_ids, 0).with_cursor(start_page_token))
 }
 
 async fn start_drive_data_fetching(
@@ -460,6 +519,10 @@ async fn start_drive_data_fetching(
   };
 
   tauri::async_runtime::spawn(async move {
+    let _work = match BackgroundWork::begin() {
+      Some(work) => work,
+      None => return,
+    };
     if ConnectionsData::lock_and_get_connection_is_syncing(
       connections_data.clone(),
       ConnectionsEnum::GoogleDrive,
@@ -474,7 +537,9 @@ async fn start_drive_data_fetching(
       true,
     )
     .await;
+    let sync_run = SyncRun::start(user_connection.id.unwrap());
     let result = fetch_drive(access_token, semantic_service, user_connection.clone()).await;
+    sync_run.finish(&result);
     if let Err(error) = result {
       let msg = format!("Failed to fetch drive files: {}", email);
       knap_log_error(msg, Some(error), Some(true));
@@ -537,8 +602,7 @@ async fn create_temp_drive_file(
     ),
     access_token,
   );
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let temp_dir = home_dir.join("knapsack_drive_temp");
+  let temp_dir = crate::config::data_root().join("knapsack_drive_temp");
   fs::create_dir_all(temp_dir.clone()).unwrap();
   if GOOGLE_MIME_TYPES.contains(&mime_type.as_str()) {
     let mut export_mime_type = "text/plain".to_string();
@@ -590,9 +654,12 @@ async fn fetch_google_drive_files(
     actix_web::web::Query::<FetchGoogleDriveParams>::from_query(req.query_string()).unwrap();
   let email = params.email.clone();
   let attrs = DriveDocument::get_attrs();
-  let user_connection =
-    UserConnection::find_by_user_email_and_scope(email.clone(), String::from(GOOGLE_DRIVE_SCOPE))
-      .unwrap();
+  let lookup_email = email.clone();
+  let user_connection = run_blocking(move || {
+    UserConnection::find_by_user_email_and_scope(lookup_email, String::from(GOOGLE_DRIVE_SCOPE))
+  })
+  .await
+  .unwrap();
   let access_token = refresh_connection_token(
     email.clone(),
     user_connection.clone(),
@@ -610,8 +677,7 @@ async fn fetch_google_drive_files(
     ),
     access_token,
   );
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let temp_dir = home_dir.join("knapsack_temp");
+  let temp_dir = crate::config::data_root().join("knapsack_temp");
   fs::create_dir_all(temp_dir.clone()).unwrap();
 
   // let mut embedding_documents = vec![];
@@ -619,7 +685,8 @@ async fn fetch_google_drive_files(
   for file in data.files.iter() {
     let (_response, file) = hub.files().get(&file.id).doit().await.unwrap();
 
-    let drive_document = get_or_create_drive_document_from_file(&file, &temp_dir, &hub).await;
+    let drive_document =
+      get_or_create_drive_document_from_file(&file, &temp_dir, &hub, user_connection.user_id).await;
     let document =
       create_drive_document(drive_document.id.unwrap(), drive_document.checksum.clone());
 
@@ -680,7 +747,7 @@ async fn fetch_google_drive_documents_ids_shared_by_users(
   .await
   .unwrap();
 
-  let documents = DriveDocument::find_by_ids(ids).ok();
+  let documents = run_blocking(move || DriveDocument::find_by_ids(ids)).await.ok();
   let drive_documents = documents.unwrap();
 
   let mut drive_document_ids = Vec::new();
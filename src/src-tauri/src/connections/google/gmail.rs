@@ -1,10 +1,15 @@
 use std::sync::Arc;
 
+use crate::automations::triggers::fire_new_email;
+use crate::backup::BackgroundWork;
 use crate::connections::api::ConnectionsEnum;
 use crate::connections::google::constants::GOOGLE_GMAIL_SCOPE;
 use crate::constants::{EMBEDDING_BATCH_SIZE, GMAIL_DOWNLOADS_THREAD_POOL_SIZE};
+use crate::db::models::sync_run::{SyncOutcome, SyncRun};
 use crate::db::models::user_connection::UserConnection;
+use crate::db::repo::run_blocking;
 use crate::memory::semantic::SemanticService;
+use crate::memory::contacts::index_email_by_uid;
 use crate::ConnectionsData;
 use crate::utils::log::knap_log_error;
 use crate::connections::utils::get_knapsack_api_connection;
@@ -51,7 +56,6 @@ pub struct FetchEmailEventPayload {
   pub success: bool,
 }
 
-use chrono::{DateTime, NaiveDateTime, Utc};
 use gmail1::api::ModifyMessageRequest;
 use gmail1::{chrono, hyper, hyper_rustls, Gmail};
 
@@ -77,7 +81,12 @@ fn get_body_content(maybe_body: Option<MessagePartBody>) -> Option<String> {
   }
 }
 
-pub async fn upsert_email_by_uid(email_uid: &str, access_token: &str, flag_update: bool) -> Result<Email, Error> {
+pub async fn upsert_email_by_uid(
+  email_uid: &str,
+  access_token: &str,
+  flag_update: bool,
+  account_id: Option<u64>,
+) -> Result<Email, Error> {
   let email_result = Email::find_by_uid(email_uid).ok().flatten();
   if let Some(email) = email_result {
     if email.thread_id.is_some() && !flag_update {
@@ -189,10 +198,16 @@ pub async fn upsert_email_by_uid(email_uid: &str, access_token: &str, flag_updat
     is_read: Some(is_read),
     is_archived: Some(is_archived),
     is_deleted: Some(false),
+    account_id,
   };
   let email_message_creation_response = email_message.create();
   if let Err(error) = email_message_creation_response {
     log::error!("Failed to create email message {:?}", error);
+  } else {
+    index_email_by_uid(email_uid);
+    if let Err(error) = fire_new_email(email_uid, chrono::Utc::now().timestamp_millis()) {
+      log::error!("Failed to fire new email triggers {:?}", error);
+    }
   }
   Ok(email_message)
 }
@@ -208,7 +223,7 @@ pub async fn embed_email(
   )?;
   let access_token = refresh_connection_token(email.clone().to_string(), user_connection.clone()).await?;
 
-  let email = upsert_email_by_uid(email_uid, &access_token, false).await?;
+  let email = upsert_email_by_uid(email_uid, &access_token, false, Some(user_connection.user_id)).await?;
 
   let documents = email.get_documents();
 
@@ -226,9 +241,11 @@ pub async fn fetch_gmail(
   days: u16,
   embedding_priority: u16,
   flag_update: bool,
-) -> Result<(), Error> {
+) -> Result<SyncOutcome, Error> {
   let mut maybe_next_page_token: Option<String> = None;
   let mut all_email_uuids = Vec::new(); 
+  let account_id = user_connection.user_id;
+  let known_uids = Email::find_uids_by_account(account_id)?;
   let hub = Gmail::new(
     hyper::Client::builder().build(
       hyper_rustls::HttpsConnectorBuilder::new()
@@ -241,8 +258,16 @@ pub async fn fetch_gmail(
     access_token.clone(),
   );
 
+  // Read before listing, so a later incremental sync replays from here.
+  let history_id = match hub.users().get_profile("me").doit().await {
+    Ok((_, profile)) => profile.history_id.map(|history_id| history_id.to_string()),
+    Err(error) => {
+      log::warn!("Failed to read gmail history id: {:?}", error);
+      None
+    }
+  };
+
   let limit_date = chrono::Utc::now() - chrono::Duration::days(days.into());
-  let mut older_date = chrono::Utc::now();
   loop {
     let mut list_request = hub
       .users()
@@ -267,13 +292,9 @@ pub async fn fetch_gmail(
       let email_documents_clone = email_documents.clone();
       let task = tauri::async_runtime::spawn(async move {
         let _permit = semaphore_clone.acquire().await.unwrap();
-        let result = upsert_email_by_uid(&message_id, &access_token_clone, flag_update.clone()).await;
+        let result = upsert_email_by_uid(&message_id, &access_token_clone, flag_update.clone(), Some(account_id)).await;
         match result {
           Ok(email_message) => {
-            if (older_date.timestamp() as u64 > email_message.clone().date) {
-              let naive_date = NaiveDateTime::from_timestamp(email_message.clone().date as i64, 0);
-              older_date = DateTime::<Utc>::from_utc(naive_date, Utc);
-            }
             email_documents_clone.lock().await.push(email_message);
           }
           Err(error) => {
@@ -318,10 +339,9 @@ pub async fn fetch_gmail(
     }
   }
 
-  Email::mark_deleted_emails(&all_email_uuids, 3).await?;
+  let deleted = Email::mark_deleted_emails(&all_email_uuids, 3, account_id).await?;
 
-  UserConnection::update_last_sync_by_id(user_connection.id.unwrap(), older_date);
-  Ok(())
+  Ok(SyncOutcome::from_ids(&known_uids, &all_email_uuids, deleted as u64).with_cursor(history_id))
 }
 
 async fn start_gmail_data_fetching(
@@ -357,6 +377,10 @@ async fn start_gmail_data_fetching(
 
   };
   tauri::async_runtime::spawn(async move {
+    let _work = match BackgroundWork::begin() {
+      Some(work) => work,
+      None => return,
+    };
     if ConnectionsData::lock_and_get_connection_is_syncing(
       connections_data.clone(),
       ConnectionsEnum::GoogleGmail,
@@ -371,6 +395,7 @@ async fn start_gmail_data_fetching(
       true,
     )
     .await;
+    let sync_run = SyncRun::start(user_connection.id.unwrap());
     let fetching_day_result = fetch_gmail(
       access_token.clone(),
       user_connection.clone(),
@@ -380,6 +405,7 @@ async fn start_gmail_data_fetching(
       true
     )
     .await;
+    sync_run.finish(&fetching_day_result);
 
     let window = app_handle.get_window(WINDOW_LABEL).unwrap();
     window.emit(
@@ -433,10 +459,11 @@ async fn fetch_google_gmail_api(
 
 #[post("/api/knapsack/connections/google/gmail/read")]
 async fn set_email_as_read(payload: Json<SetEmailReadResponseParams>) -> impl Responder {
-  let user_connection = UserConnection::find_by_user_email_and_scope(
-    payload.email.clone(),
-    String::from(GOOGLE_GMAIL_SCOPE),
-  )
+  let email = payload.email.clone();
+  let user_connection = run_blocking(move || {
+    UserConnection::find_by_user_email_and_scope(email, String::from(GOOGLE_GMAIL_SCOPE))
+  })
+  .await
   .unwrap();
   let access_token = refresh_connection_token(payload.email.clone(), user_connection.clone())
     .await
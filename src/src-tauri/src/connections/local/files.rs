@@ -1,5 +1,5 @@
+use crate::backup::BackgroundWork;
 use crate::connections::api::ConnectionsEnum;
-use crate::constants::EMBEDDING_BATCH_SIZE;
 use actix_web::web::Data;
 use actix_web::{get, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
@@ -12,10 +12,7 @@ use crate::error::Error;
 use crate::memory::semantic::SemanticService;
 use std::fs::File;
 
-use std::{
-  path::Path,
-  sync::{atomic::AtomicU16, Arc},
-};
+use std::{path::Path, sync::Arc};
 
 use crate::{local_fs, ConnectionsData};
 
@@ -80,31 +77,13 @@ pub async fn embed_local_file(
 }
 
 async fn fetch_files(semantic_service: Arc<Mutex<Option<SemanticService>>>) -> Result<(), Error> {
-  let files_progress = Arc::new(AtomicU16::new(0));
-  let _doc_infos = local_fs::read_home_dir(&files_progress).expect("Couldn't get doc_paths.");
-  // let mut documents = Vec::new();
-  // for doc_info in doc_infos.iter() {
-  //   let file_document = create_local_file(doc_info.path.clone()).await;
-  //   let local_file_get_docs_result = file_document.get_documents();
-  //   documents.extend(local_file_get_docs_result);
-  // }
-
-  // let chunks = documents
-  //   .chunks(EMBEDDING_BATCH_SIZE)
-  //   .map(|chunk| chunk.to_vec())
-  //   .collect::<Vec<_>>();
-  // let attrs = LocalFile::get_attrs();
-  // let maybe_locked_semantic_service = semantic_service.lock().await;
-  // let locked_semantic_service = maybe_locked_semantic_service.as_ref().unwrap();
-
-  // for chunk in chunks {
-  //   locked_semantic_service
-  //     .learn(chunk.clone(), attrs.clone(), 1)
-  //     .await
-  // }
-  // locked_semantic_service
-  //   .add_handle_embed_finish_to_queue(ConnectionsEnum::LocalFiles, 1)
-  //   .await;
+  let doc_infos = local_fs::read_home_dir().map_err(Error::KSError)?;
+  let paths = doc_infos.into_iter().map(|doc_info| doc_info.path).collect();
+  let maybe_locked_semantic_service = semantic_service.lock().await;
+  let locked_semantic_service = maybe_locked_semantic_service
+    .as_ref()
+    .ok_or(Error::KSError("Semantic service is not running".to_string()))?;
+  locked_semantic_service.add_local_files_to_queue(paths).await;
   Ok(())
 }
 
@@ -114,6 +93,10 @@ async fn start_files_data_fetching(
   handle: Data<Arc<Handle>>,
 ) -> Result<(), Error> {
   handle.spawn(async move {
+    let _work = match BackgroundWork::begin() {
+      Some(work) => work,
+      None => return,
+    };
     if ConnectionsData::lock_and_get_connection_is_syncing(
       connections_data.clone(),
       ConnectionsEnum::LocalFiles,
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::db::models::meeting_notes::MeetingNotes;
+use crate::db::models::transcript::Transcript;
+use crate::memory::contacts::index_transcript;
+use crate::memory::semantic::SemanticService;
+
+/// Refreshes the meeting transcript's `documents` row, links its
+/// participants as contacts and queues its chunks for embedding.
+pub async fn embed_meeting_transcript(
+  thread_id: u64,
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+) {
+  let transcript = match Transcript::find_by_thread_id(thread_id) {
+    Ok(Some(transcript)) => transcript,
+    Ok(None) => return,
+    Err(e) => {
+      log::error!("Couldn't load transcript for thread {}: {:?}", thread_id, e);
+      return;
+    }
+  };
+  if let Err(e) = index_transcript(&transcript) {
+    log::error!("Couldn't index contacts for thread {}: {:?}", thread_id, e);
+  }
+  let documents = transcript.get_documents();
+  if documents.is_empty() {
+    return;
+  }
+  let maybe_locked_semantic_service = semantic_service.lock().await;
+  if let Some(locked_semantic_service) = maybe_locked_semantic_service.as_ref() {
+    locked_semantic_service
+      .learn(documents, Transcript::get_attrs(), 2)
+      .await;
+  }
+}
+
+/// Same as `embed_meeting_transcript`, for the notes saved on a meeting.
+pub async fn embed_meeting_notes(
+  thread_id: u64,
+  semantic_service: Arc<Mutex<Option<SemanticService>>>,
+) {
+  let notes = match MeetingNotes::find_by_thread_id(thread_id) {
+    Ok(Some(notes)) => notes,
+    Ok(None) => return,
+    Err(e) => {
+      log::error!("Couldn't load notes for thread {}: {:?}", thread_id, e);
+      return;
+    }
+  };
+  let documents = notes.get_documents();
+  if documents.is_empty() {
+    return;
+  }
+  let maybe_locked_semantic_service = semantic_service.lock().await;
+  if let Some(locked_semantic_service) = maybe_locked_semantic_service.as_ref() {
+    locked_semantic_service
+      .learn(documents, MeetingNotes::get_attrs(), 2)
+      .await;
+  }
+}
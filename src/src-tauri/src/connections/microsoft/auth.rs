@@ -317,6 +317,8 @@ pub async fn refresh_user_connection(user_connection: UserConnection, email: Str
     refresh_token: refresh_response.refresh_token,
     connection: user_connection.connection,
     last_synced: user_connection.last_synced,
+    sync_status: user_connection.sync_status,
+    sync_error: user_connection.sync_error,
   };
   updated_user_connection.clone().update();
 
@@ -381,6 +383,8 @@ pub fn create_user_connection(
     refresh_token: refresh_token,
     connection: Some(connection),
     last_synced: None,
+    sync_status: None,
+    sync_error: None,
   };
   user_connection.upsert()
 }
@@ -434,6 +438,7 @@ async fn microsoft_signin(code: String, state: String, app_handle: tauri::AppHan
         id: None,
         email: email.clone(),
         uuid: Some(uuid),
+        is_active: false,
       }.create();
 
       let connection_keys = create_connections_from_scopes(
@@ -12,14 +12,17 @@ use actix_web::web::Data;
 use actix_web::{get, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 
+use crate::backup::BackgroundWork;
 use crate::connections::api::ConnectionsEnum;
 use crate::connections::microsoft::constants::{ MICROSOFT_CALENDAR_SCOPE, MICROSOFT_BASE_URL };
 use crate::connections::microsoft::auth::{ refresh_user_connection };
 use crate::ConnectionsData;
 
 use crate::db::models::calendar_event::CalendarEvent;
+use crate::db::models::sync_run::{SyncOutcome, SyncRun};
 use crate::db::models::user_connection::UserConnection;
 use crate::error::Error;
+use crate::memory::contacts::index_calendar_event_by_event_id;
 use crate::spotlight::WINDOW_LABEL;
 use tauri::Manager;
 use crate::connections::google::calendar::FetchCalendarEventPayload;
@@ -151,7 +154,7 @@ fn convert_date_string_to_timestamp(date: &str) -> Option<i64> {
   }
 }
 
-fn create_calendar_event(event: Event) -> Result<(), Error> {
+fn create_calendar_event(event: Event, account_id: u64) -> Result<(), Error> {
   let event_id = event.id.unwrap();
   let title = event.subject;
   let description = event.body.unwrap().content;
@@ -221,9 +224,70 @@ fn create_calendar_event(event: Event) -> Result<(), Error> {
     google_meet_url,
     recurrence_json,
     recurrence_id,
+    account_id: Some(account_id),
   };
 
-  calendar_event.create()
+  calendar_event.create()?;
+  index_calendar_event_by_event_id(&calendar_event.event_id);
+  Ok(())
+}
+
+/// Stores the events from a month before to a month after today and deletes
+/// stored ones the calendar no longer has.
+async fn sync_calendar_events(
+  access_token: &str,
+  account_id: u64,
+  email: &str,
+) -> Result<SyncOutcome, Error> {
+  let known_event_ids = CalendarEvent::find_event_ids_by_account(account_id)?;
+  let client = Client::new();
+
+  let one_month_ago = (chrono::Utc::now() - chrono::Duration::days(31)).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+  let one_month_later = (chrono::Utc::now() + chrono::Duration::days(31)).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+  let mut url = format!(
+    "{}/me/events?$filter=start/dateTime ge '{}' and start/dateTime lt '{}'&$top=500",
+    MICROSOFT_BASE_URL, one_month_ago, one_month_later
+  );
+
+  let mut event_ids_total: Vec<String> = Vec::new();
+  loop {
+    let response = client
+      .get(&url)
+      .header("Authorization", access_token)
+      .send()
+      .await?;
+
+    if response.status().is_success() {
+      let body = response.text().await?;
+      let events_response: EventResponse = serde_json::from_str(&body)?;
+      let events = events_response.value;
+
+      let event_ids: Vec<String> = events.iter().filter_map(|event| event.id.clone()).collect();
+      event_ids_total.extend(event_ids);
+
+      for event in events {
+        if let Err(e) = create_calendar_event(event, account_id) {
+          let msg = format!("Error creating calendar event: {}", email);
+          knap_log_error(msg, Some(e), Some(true));
+        };
+      }
+
+      if let Some(next_link) = events_response.next_link {
+        url = next_link;
+      } else {
+        break;
+      }
+    } else {
+      return Err(Error::from(format!(
+        "Error when fetch calendar events: {}",
+        response.status()
+      )));
+    }
+  }
+
+  let deleted = CalendarEvent::delete_calendar_events_removed(event_ids_total.clone(), account_id)?;
+  Ok(SyncOutcome::from_ids(&known_event_ids, &event_ids_total, deleted as u64))
 }
 
 async fn fetch_calendar(  
@@ -254,6 +318,10 @@ async fn fetch_calendar(
     }
   };
 
+  let _work = match BackgroundWork::begin() {
+    Some(work) => work,
+    None => return Ok(()),
+  };
   if ConnectionsData::lock_and_get_connection_is_syncing(
     connections_data.clone(),
     ConnectionsEnum::MicrosoftCalendar,
@@ -268,76 +336,27 @@ async fn fetch_calendar(
     true,
   )
   .await;
-
-  let client = Client::new();
-  let now = Utc::now();
-
-  let one_month_ago = (chrono::Utc::now() - chrono::Duration::days(31)).format("%Y-%m-%dT%H:%M:%SZ").to_string();;
-  let one_month_later = (chrono::Utc::now() + chrono::Duration::days(31)).format("%Y-%m-%dT%H:%M:%SZ").to_string();;
-
-  let mut url = format!(
-    "{}/me/events?$filter=start/dateTime ge '{}' and start/dateTime lt '{}'&$top=500",
-    MICROSOFT_BASE_URL, one_month_ago, one_month_later
-  );
-
-  let mut event_ids_total: Vec<String> = Vec::new();
-  loop {
-    let response = client
-      .get(&url)
-      .header("Authorization", update_user_connection.token.clone())
-      .send()
-      .await?;
-
-    if response.status().is_success() {
-      let body = response.text().await?;
-      let events_response: EventResponse = serde_json::from_str(&body).unwrap();
-      let events = events_response.value;
-
-      let event_ids: Vec<String> = events.iter().filter_map(|event| event.id.clone()).collect();
-      event_ids_total.extend(event_ids);
-
-      for event in events {
-        if let Err(e) = create_calendar_event(event) {
-          let msg = format!("Error creating calendar event: {}", email);
-          knap_log_error(msg, Some(e), Some(true));
-        };
-      }
-
-      if let Some(next_link) = events_response.next_link {
-        url = next_link;
-      } else {
-        break;
-      }
-    } else {
-      ConnectionsData::lock_and_set_connection_is_syncing(
-        connections_data.clone(),
-        ConnectionsEnum::MicrosoftCalendar,
-        false,
-      )
-      .await;
-
-      return Err(Error::from(format!(
-        "Error when fetch calendar events: {}",
-        response.status()
-      )));
-    }
-  }
-
-  let event_count = event_ids_total.len();
-  CalendarEvent::delete_calendar_events_removed(event_ids_total.clone());
-  UserConnection::update_last_sync_by_id(user_connection.id.unwrap(), (chrono::Utc::now() + chrono::Duration::days(31)));
+  let sync_run = SyncRun::start(update_user_connection.id.unwrap());
+  let sync_result = sync_calendar_events(
+    &update_user_connection.token,
+    update_user_connection.user_id,
+    &email,
+  )
+  .await;
+  sync_run.finish(&sync_result);
 
   ConnectionsData::lock_and_set_connection_is_syncing(
     connections_data.clone(),
     ConnectionsEnum::MicrosoftCalendar,
     false,
   ).await;
+  let outcome = sync_result?;
   let window = app_handle.get_window(WINDOW_LABEL).unwrap();
   window.emit(
     "finish_fetch_calendar",
     FetchCalendarEventPayload {
       success: true,
-      synced_events_count: event_count,
+      synced_events_count: (outcome.items_added + outcome.items_updated) as usize,
     }
   );
   
@@ -6,13 +6,18 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use crate::automations::triggers::fire_new_email;
+use crate::backup::BackgroundWork;
 use crate::connections::api::ConnectionsEnum;
 use crate::connections::google::gmail::FetchEmailEventPayload;
 use crate::connections::microsoft::auth::refresh_user_connection;
 use crate::connections::microsoft::constants::{MICROSOFT_BASE_URL, MICROSOFT_OUTLOOK_SCOPE};
 use crate::db::models::email::Email;
+use crate::db::models::sync_run::{SyncOutcome, SyncRun};
 use crate::db::models::user_connection::UserConnection;
+use crate::db::repo::run_blocking;
 use crate::error::Error;
+use crate::memory::contacts::index_email_by_uid;
 use crate::utils::log::knap_log_error;
 use crate::ConnectionsData;
 
@@ -103,6 +108,7 @@ async fn upsert_email_by_uid(
   email_data: EmailData,
   archive_folder_id: String,
   flag_update: bool,
+  account_id: u64,
 ) -> Result<Email, Error> {
   if let Ok(Some(email)) = Email::find_by_uid(&email_data.id) {
     if !flag_update {
@@ -151,9 +157,15 @@ async fn upsert_email_by_uid(
     is_read: Some(email_data.isRead),
     is_archived: Some(is_archived),
     is_deleted: Some(false),
+    account_id: Some(account_id),
   };
 
-  email_entry.create();
+  if email_entry.create().is_ok() {
+    index_email_by_uid(&email_entry.email_uid);
+    if let Err(error) = fire_new_email(&email_entry.email_uid, Utc::now().timestamp_millis()) {
+      log::error!("Failed to fire new email triggers {:?}", error);
+    }
+  }
   Ok(email_entry)
 }
 
@@ -207,6 +219,10 @@ async fn start_outlook_data_fetching(
     }
   };
 
+  let _work = match BackgroundWork::begin() {
+    Some(work) => work,
+    None => return Ok(()),
+  };
   if ConnectionsData::lock_and_get_connection_is_syncing(
     connections_data.clone(),
     ConnectionsEnum::MicrosoftOutlook,
@@ -222,7 +238,16 @@ async fn start_outlook_data_fetching(
     true,
   )
   .await;
-  fetch_outlook_emails(email.clone(), update_user_connection.token.clone(), 3, true).await;
+  let sync_run = SyncRun::start(update_user_connection.id.unwrap());
+  let fetch_result = fetch_outlook_emails(
+    email.clone(),
+    update_user_connection.token.clone(),
+    3,
+    true,
+    update_user_connection.user_id,
+  )
+  .await;
+  sync_run.finish(&fetch_result);
   let window = app_handle.get_window(WINDOW_LABEL).unwrap();
   window.emit(
     "finish_fetch_email",
@@ -244,8 +269,10 @@ pub async fn fetch_outlook_emails(
   access_token: String,
   days: u16,
   flag_update: bool,
-) -> Result<(), Error> {
+  account_id: u64,
+) -> Result<SyncOutcome, Error> {
   let client = Client::new();
+  let known_uids = Email::find_uids_by_account(account_id)?;
   let mut older_date = Utc::now();
   let limit_date = Utc::now() - Duration::days(days.into());
   let mut skip = 0;
@@ -286,7 +313,7 @@ pub async fn fetch_outlook_emails(
       all_email_uuids.push(email_data.id.clone());
       let email_documents_clone = email_documents.clone();
       let email_record =
-        match upsert_email_by_uid(email_data, archive_folder_id.clone(), flag_update.clone()).await
+        match upsert_email_by_uid(email_data, archive_folder_id.clone(), flag_update.clone(), account_id).await
         {
           Ok(email) => email,
           Err(e) => {
@@ -312,9 +339,11 @@ pub async fn fetch_outlook_emails(
     }
   }
 
-  Email::mark_deleted_emails(&all_email_uuids, 3).await?;
+  let deleted = Email::mark_deleted_emails(&all_email_uuids, 3, account_id).await?;
 
-  Ok(())
+  // Paged with $skip rather than a delta query, so there is no deltaLink to
+  // resume from.
+  Ok(SyncOutcome::from_ids(&known_uids, &all_email_uuids, deleted as u64))
 }
 
 #[get("/api/knapsack/connections/microsoft/outlook")]
@@ -348,10 +377,12 @@ async fn fetch_microsoft_email_api(
 
 #[post("/api/knapsack/connections/microsoft/outlook/read")]
 async fn set_email_as_read(payload: Json<SetEmailReadResponseParams>) -> impl Responder {
-  let user_connection = match UserConnection::find_by_user_email_and_scope(
-    payload.email.clone(),
-    String::from(MICROSOFT_OUTLOOK_SCOPE),
-  ) {
+  let email = payload.email.clone();
+  let user_connection = match run_blocking(move || {
+    UserConnection::find_by_user_email_and_scope(email, String::from(MICROSOFT_OUTLOOK_SCOPE))
+  })
+  .await
+  {
     Ok(connection) => connection,
     Err(_) => {
       return HttpResponse::BadRequest().json(SetEmailReadResponse {
@@ -397,10 +428,12 @@ async fn set_email_as_read(payload: Json<SetEmailReadResponseParams>) -> impl Re
 
 #[post("/api/knapsack/connections/microsoft/outlook/reply")]
 async fn reply_to_email(payload: Json<ReplyEmailParams>) -> impl Responder {
-  let user_connection = match UserConnection::find_by_user_email_and_scope(
-    payload.email.clone(),
-    String::from(MICROSOFT_OUTLOOK_SCOPE),
-  ) {
+  let email = payload.email.clone();
+  let user_connection = match run_blocking(move || {
+    UserConnection::find_by_user_email_and_scope(email, String::from(MICROSOFT_OUTLOOK_SCOPE))
+  })
+  .await
+  {
     Ok(connection) => connection,
     Err(_) => {
       return HttpResponse::BadRequest().json(ReplyEmailResponse {
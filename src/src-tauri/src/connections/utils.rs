@@ -117,7 +117,7 @@ pub async fn get_api_access_token(email: &str, refresh_internal: Option<String>)
     }
 }
 
-pub fn create_knapsack_api_connection(user_email: String, refresh_internal: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn create_knapsack_api_connection(user_email: String, refresh_internal: &str) -> Result<(), crate::error::Error> {
     let connection = Connection::find_by_scope(KNAPSACK_ACCESS_KEY.to_string())?;
     let user = User::find_by_email(user_email.clone())?;
   
@@ -135,6 +135,8 @@ pub fn create_knapsack_api_connection(user_email: String, refresh_internal: &str
         refresh_token: Some(refresh_internal.to_string()),
         connection: None,
         last_synced: None,
+        sync_status: None,
+        sync_error: None,
       };
       user_connection.upsert(); 
     } else {
@@ -1,8 +1,10 @@
 use once_cell::sync::Lazy;
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::automations::bundle::seed_library;
 use crate::connections::google::constants::{
   GOOGLE_CALENDAR_SCOPE, GOOGLE_DRIVE_SCOPE, GOOGLE_GMAIL_SCOPE, GOOGLE_PROFILE_SCOPE,
   GOOGLE_PROVIDER_NAME,
@@ -12,21 +14,20 @@ use crate::connections::microsoft::constants::{
   MICROSOFT_PROVIDER_NAME, MICROSOFT_ONEDRIVE_SCOPE,
 };
 use crate::db::models::{
-  automation::Automation,
-  automation_step::AutomationStep,
-  cadence_trigger::CadenceTrigger,
   connection::Connection,
+  embedding_manifest::EmbeddingManifestEntry,
   feed_item::FeedItem,
   message::Message,
+  sync_run::SyncRun,
   thread::{Thread, ThreadType},
+  user_connection::UserConnection,
 };
 use crate::memory::qdrant;
 
-use super::models::data_source_trigger::DataSourceTrigger;
 use crate::db::diesel_setup::execute_migrations;
+use crate::error::Error;
 
 pub const KNAPSACK_DB_FILENAME: &str = ".knapsack.db";
-const DB_VERSION: u16 = 18;
 const QDRANT_DB_VERSION: u16 = 2;
 const MAX_DB_CONNECTIONS: u32 = 10;
 
@@ -35,9 +36,28 @@ type SqlitePool = Pool<SqliteConnectionManager>;
 const AFTER_CONNECT: &str = "PRAGMA journal_mode=WAL;
 PRAGMA busy_timeout=1000;";
 
+#[cfg(not(test))]
+fn pool_db_path() -> std::path::PathBuf {
+  crate::config::db_path()
+}
+
+/// Tests never touch the real data root: the pool opens a fresh, fully
+/// migrated database in the temp dir, one per test binary run. Tests share
+/// it, so each one writes rows under its own ids.
+#[cfg(test)]
+fn pool_db_path() -> std::path::PathBuf {
+  use diesel::Connection as _;
+
+  let db_path = std::env::temp_dir().join(format!("knapsack-test-{}.db", std::process::id()));
+  let _ = std::fs::remove_file(&db_path);
+  let mut connection = diesel::SqliteConnection::establish(db_path.to_str().unwrap())
+    .expect("Failed to create test database");
+  crate::db::diesel_setup::run_migrations(&mut connection).expect("Failed to migrate test database");
+  db_path
+}
+
 fn create_pool() -> SqlitePool {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let db_pathbuf = home_dir.join(KNAPSACK_DB_FILENAME);
+  let db_pathbuf = pool_db_path();
   let db_path = db_pathbuf.as_path();
 
   let manager = SqliteConnectionManager::file(db_path.to_str().unwrap())
@@ -55,97 +75,80 @@ pub fn get_db_conn() -> PooledConnection<SqliteConnectionManager> {
   return conn;
 }
 
-pub fn delete_old_tables() {
-  let connection = create_pool().get().unwrap();
-  let current_tables = vec![
-    "__diesel_schema_migrations",
-    "db_version",
-    "emails",
-    "local_files",
-    "calendar_events",
-    "drive_documents",
-    "automation_steps",
-    "automations",
-    "cadence_triggers",
-    "data_source_triggers",
-    "automation_runs",
-    "messages",
-    "threads",
-    "documents",
-    "message_feedbacks",
-    "feed_items",
-    "user_connections",
-  ];
-  for table in current_tables {
-    let sql = format!("DROP TABLE IF EXISTS {table}");
-    connection
-      .execute(&sql, ())
-      .expect(&format!("Could not create {table} database table"));
+/// Like `get_db_conn`, but returns an error instead of panicking when the
+/// pool times out.
+pub fn try_get_db_conn() -> Result<PooledConnection<SqliteConnectionManager>, Error> {
+  Ok(DB_CONNECTION_POOL.get()?)
+}
+
+/// Reads the vector schema version recorded next to the SQLite schema.
+/// `None` on a fresh install.
+fn recorded_qdrant_version() -> Option<u16> {
+  let connection = get_db_conn();
+  connection
+    .query_row("SELECT qdrant_version FROM db_version LIMIT 1", [], |row| {
+      row.get::<_, u16>(0)
+    })
+    .optional()
+    .unwrap_or_else(|e| {
+      log::error!("Failed to read qdrant version: {:?}", e);
+      None
+    })
+}
+
+fn record_qdrant_version() -> Result<(), rusqlite::Error> {
+  let connection = get_db_conn();
+  let updated = connection.execute(
+    "UPDATE db_version SET qdrant_version = ?1",
+    params![QDRANT_DB_VERSION],
+  )?;
+  if updated == 0 {
+    // `version` predates Diesel migrations and is no longer read.
+    connection.execute(
+      "INSERT INTO db_version (version, qdrant_version) VALUES (0, ?1)",
+      params![QDRANT_DB_VERSION],
+    )?;
   }
+  Ok(())
 }
 
-fn should_delete_old_tables() -> bool {
-  let connection = create_pool().get().unwrap();
-  let stmt = connection.prepare("SELECT version FROM db_version");
-  match stmt {
-    Ok(mut stmt) => {
-      let rows = stmt.query_map([], |row| Ok(row.get::<_, u16>(0).unwrap()));
-      match rows {
-        Ok(rows) => {
-          for row in rows {
-            match row {
-              Ok(current_database_version) => {
-                if current_database_version != DB_VERSION {
-                  return true;
-                }
-              }
-              Err(_) => return true,
-            }
-          }
-        }
-        Err(_) => return true,
+async fn clean_qdrant() -> bool {
+  match qdrant::delete_knapsack_collection().await {
+    Ok(_) => {
+      log::info!("Deleted knapsack collection to update VectorDB schema.");
+      if let Err(e) = EmbeddingManifestEntry::delete_all() {
+        log::error!("Failed to clear embedding manifest: {:?}", e);
       }
+      true
+    }
+    Err(e) => {
+      log::error!("Failed to delete knapsack collection: {:?}", e);
+      false
     }
-    Err(_) => return true,
   }
-  return false;
 }
 
-async fn clean_qdrant() {
-  match qdrant::delete_knapsack_collection().await {
-    Ok(_) => log::info!("Deleted knapsack collection to update VectorDB schema."),
-    Err(e) => log::error!("Failed to delete knapsack collection: {:?}", e),
+fn should_clean_qdrant() -> bool {
+  match recorded_qdrant_version() {
+    Some(qdrant_version) => qdrant_version != QDRANT_DB_VERSION,
+    None => false,
   }
 }
 
-fn should_clean_qdrant() -> bool {
-  let connection = create_pool().get().unwrap();
-  let stmt = connection.prepare("SELECT qdrant_version FROM db_version");
-  match stmt {
-    Ok(mut stmt) => {
-      let rows = stmt.query_map([], |row| Ok(row.get::<_, u16>(0).unwrap()));
-      match rows {
-        Ok(rows) => {
-          for row in rows {
-            match row {
-              Ok(current_database_version) => {
-                if current_database_version != DB_VERSION {
-                  return true;
-                }
-              }
-              Err(_) => return true,
-            }
-          }
-        }
-        Err(_) => return true,
-      }
+/// The vector collection cannot be migrated in place: when `QDRANT_DB_VERSION`
+/// changes it is dropped and rebuilt by re-embedding. Runs before the
+/// collection is created, once Qdrant is up.
+pub async fn migrate_qdrant() {
+  if should_clean_qdrant() && !clean_qdrant().await {
+    return;
+  }
+  if recorded_qdrant_version() != Some(QDRANT_DB_VERSION) {
+    if let Err(e) = record_qdrant_version() {
+      log::error!("Failed to record qdrant version: {:?}", e);
     }
-    Err(_) => return true,
   }
-  return false;
 }
 
-
 //TODO: return a error if the start fails
 pub async fn start_database() {
   if let Err(e) = execute_migrations() {
@@ -153,255 +156,21 @@ pub async fn start_database() {
     std::process::exit(1);
   }
 
-  let mut meeting_prep_automation = Automation {
-    id: None,
-    uuid: String::from("46a65eb7-d0df-5b0c-9191-5c86f84f532d"),
-    name: String::from("Meeting Prep"),
-    description: String::from(
-      "Prepare a report for your next meeting by synthesizing data from your email, calendar, contacts, and web.",
-    ),
-    is_active: false,
-    is_beta: false,
-    show_library: false,
-    icon: String::from("/assets/images/icons/meeting-prep-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: None,
-    trigger_data_sources: Some(vec![DataSourceTrigger {
-      id: None,
-      automation_uuid: String::from("46a65eb7-d0df-5b0c-9191-5c86f84f532d"),
-      data_source: String::from("google_calendar"),
-      offset_minutes: -60,
-    }]),
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("46a65eb7-d0df-5b0c-9191-5c86f84f532d"),
-      name: String::from("meeting-prep"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  let mut email_summary_automation = Automation {
-    id: None,
-    uuid: String::from("6a9b3fa2-ec22-5f09-8808-424b987a019e"),
-    name: String::from("Email Summary"),
-    description: String::from("Provide a summary of all emails within the last 24 hours."),
-    is_active: false,
-    is_beta: false,
-    show_library: true,
-    icon: String::from("/assets/images/icons/email-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: Some(vec![]),
-    trigger_data_sources: None,
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("6a9b3fa2-ec22-5f09-8808-424b987a019e"),
-      name: String::from("email-summary"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  let mut finra_compliance_automation = Automation {
-    id: None,
-    uuid: String::from("ee3c0d1e-603a-5450-a0d7-86c1dbf745d4"),
-    name: String::from("Email RegCheck"),
-    description: String::from("Check FINRA compliance of all emails that were sent today."),
-    is_active: false,
-    is_beta: false,
-    show_library: true,
-    icon: String::from("/assets/images/icons/email-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: Some(vec![]),
-    trigger_data_sources: None,
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("ee3c0d1e-603a-5450-a0d7-86c1dbf745d4"),
-      name: String::from("finra-compliance"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  // let mut about_me_automation = Automation {
-  //   id: None,
-  //   uuid: String::from("87f5545c-5ee1-41a4-8ddb-9a00c650a791"),
-  //   name: String::from("About me"),
-  //   description: String::from(
-  //     "Returns a detailed bio of the user based on their documents and web search.",
-  //   ),
-  //   is_active: false,
-  //   is_beta: true,
-  //   show_library: true,
-  //   icon: String::from("/assets/images/icons/profile-icon.svg"),
-  //   runs: Some(vec![]),
-  //   trigger_cadences: Some(vec![]),
-  //   trigger_data_sources: None,
-  //   steps: Some(vec![AutomationStep {
-  //     id: None,
-  //     automation_uuid: String::from("87f5545c-5ee1-41a4-8ddb-9a00c650a791"),
-  //     name: String::from("about-me"),
-  //     ordering: 0,
-  //     args_json: None,
-  //   }]),
-  // };
-
-  let mut strategic_plan_automation = Automation {
-    id: None,
-    uuid: String::from("fc7f6f0e-f85a-4eed-a093-0bbe317004ef"),
-    name: String::from("Strategic Plan"),
-    description: String::from("Create Strategic Plan"),
-    is_active: false,
-    is_beta: true,
-    show_library: true,
-    icon: String::from("/assets/images/icons/strategy-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: Some(vec![]),
-    trigger_data_sources: None,
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("fc7f6f0e-f85a-4eed-a093-0bbe317004ef"),
-      name: String::from("strategic-plan"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  let mut post_safely_automation = Automation {
-    id: None,
-    uuid: String::from("fa5bc3e1-ecb6-454b-85b7-6694abd03309"),
-    name: String::from("LinkedIn Post Ideas"),
-    description: String::from(
-      "Write LinkedIn posts using my own expertise and relevant topics.",
-    ),
-    is_active: false,
-    is_beta: true,
-    show_library: true,
-    icon: String::from("/assets/images/icons/content-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: Some(vec![]),
-    trigger_data_sources: None,
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("fa5bc3e1-ecb6-454b-85b7-6694abd03309"),
-      name: String::from("post-safely"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  let mut business_coach_automation = Automation {
-    id: None,
-    uuid: String::from("0874e460-9d14-4d89-9a78-8c79d8718ab9"),
-    name: String::from("Business Coach"),
-    description: String::from("Thought partner for business strategies."),
-    is_active: false,
-    is_beta: true,
-    show_library: true,
-    icon: String::from("/assets/images/icons/strategy-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: Some(vec![]),
-    trigger_data_sources: None,
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("0874e460-9d14-4d89-9a78-8c79d8718ab9"),
-      name: String::from("business-coach"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  let mut social_media_planner_automation = Automation {
-    id: None,
-    uuid: String::from("c07df858-c664-47af-a965-4b28efaa18fc"),
-    name: String::from("Social Media Planner"),
-    description: String::from("Create a cohesive social media campaign."),
-    is_active: false,
-    is_beta: true,
-    show_library: true,
-    icon: String::from("/assets/images/icons/content-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: Some(vec![]),
-    trigger_data_sources: None,
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("c07df858-c664-47af-a965-4b28efaa18fc"),
-      name: String::from("social-media-planner"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  let mut lead_scoring_automation = Automation {
-    id: None,
-    uuid: String::from("012d3c1a-1749-48e4-9be3-608bde9679c6"),
-    name: String::from("Sales Lead Scoring"),
-    description: String::from("Lead Scoring from email and web data."),
-    is_active: false,
-    is_beta: true,
-    show_library: true,
-    icon: String::from("/assets/images/icons/profile-icon.svg"),
-    runs: Some(vec![]),
-    trigger_cadences: Some(vec![]),
-    trigger_data_sources: None,
-    steps: Some(vec![AutomationStep {
-      id: None,
-      automation_uuid: String::from("012d3c1a-1749-48e4-9be3-608bde9679c6"),
-      name: String::from("lead-scoring"),
-      ordering: 0,
-      args_json: None,
-    }]),
-  };
-
-  let meeting_prep_res = meeting_prep_automation.create();
-  match meeting_prep_res {
-    Ok(_) => println!("Created meeting prep automation"),
-    Err(e) => println!("Error creating meeting prep automation: {:?}", e),
-  }
-  let email_summary_res = email_summary_automation.create();
-  match email_summary_res {
-    Ok(_) => println!("Created email summary automation"),
-    Err(e) => println!("Error creating email summary automation: {:?}", e),
-  }
-  let finra_compliance_automation_res = finra_compliance_automation.create();
-  match finra_compliance_automation_res {
-    Ok(_) => println!("Created FINRA compliance automation"),
-    Err(e) => println!("Error creating FINRA compliance automation: {:?}", e),
-  }
-  let strategic_plan_res = strategic_plan_automation.create();
-  match strategic_plan_res {
-    Ok(_) => println!("Created strategic plan automation"),
-    Err(e) => println!("Error creating strategic plan automation: {:?}", e),
+  // Tokens saved before they were encrypted at rest.
+  match UserConnection::reseal_tokens() {
+    Ok(0) => {}
+    Ok(count) => log::info!("Encrypted {} stored connection tokens", count),
+    Err(e) => log::error!("Failed to encrypt stored connection tokens: {:?}", e),
   }
 
-  let post_safely_automation_res = post_safely_automation.create();
-  match post_safely_automation_res {
-    Ok(_) => println!("Created Post Safely automation"),
-    Err(e) => println!("Error creating Post Safely automation: {:?}", e),
+  if let Err(e) = UserConnection::reset_interrupted_syncs() {
+    log::error!("Failed to reset interrupted syncs: {:?}", e);
   }
-
-  let business_coach_automation_res = business_coach_automation.create();
-  match business_coach_automation_res {
-    Ok(_) => println!("Created Business Coach automation"),
-    Err(e) => println!("Error creating Business Coach automation: {:?}", e),
-  }
-  // let about_me_automation_res = about_me_automation.create();
-  // match about_me_automation_res {
-  //   Ok(_) => println!("Created About me automation"),
-  //   Err(e) => println!("Error creating About me automation: {:?}", e),
-  // }
-
-  let social_media_planner_automation_res = social_media_planner_automation.create();
-  match social_media_planner_automation_res {
-    Ok(_) => println!("Created Social Media Planner automation"),
-    Err(e) => println!("Error creating Social Media Planner automation: {:?}", e),
+  if let Err(e) = SyncRun::fail_interrupted() {
+    log::error!("Failed to close interrupted sync runs: {:?}", e);
   }
 
-  let lead_scoring_res = lead_scoring_automation.create();
-  match lead_scoring_res {
-    Ok(_) => println!("Created Lead Scoring automation"),
-    Err(e) => println!("Error creating Lead Scoring automation: {:?}", e),
-  }
+  seed_library();
 
   let start = SystemTime::now();
   let now_timestamp = start
@@ -1,24 +1,136 @@
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
-use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-
-use crate::db::db::{ KNAPSACK_DB_FILENAME };
+use diesel::sqlite::Sqlite;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness, MigrationSource};
 
+/// Every schema change ships as a new numbered directory under
+/// `src/migrations`. Migrations are forward-only and must keep existing
+/// rows: add columns and tables, backfill with `UPDATE`, never drop user
+/// data. `down.sql` exists for local development only.
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
+/// Applies every pending migration, each in its own transaction, and
+/// returns the versions that were applied.
+pub fn run_migrations(
+    connection: &mut SqliteConnection,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let applied = connection.run_pending_migrations(MIGRATIONS)?;
+    Ok(applied.iter().map(|version| version.to_string()).collect())
+}
+
+/// Version of the newest migration this build knows about, e.g.
+/// `20261018000003`.
+pub fn latest_migration_version() -> Option<String> {
+    <EmbeddedMigrations as MigrationSource<Sqlite>>::migrations(&MIGRATIONS)
+        .ok()?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .max()
+}
+
 pub fn execute_migrations() -> Result<(), Box<dyn std::error::Error>> {
     log::debug!("--------------------- Executing migrations --------------------");
-    let home_dir = dirs::home_dir().expect("Could not determine the home directory");
-    let db_path = home_dir.join(KNAPSACK_DB_FILENAME);
+    let db_path = crate::config::db_path();
 
     let db_url = db_path.to_str().ok_or("Invalid database path")?;
 
     let mut connection = SqliteConnection::establish(db_url)
         .map_err(|e| format!("Failed to connect to database: {}", e))?;
 
-    connection.run_pending_migrations(MIGRATIONS)
+    let applied = run_migrations(&mut connection)
         .map_err(|e| format!("Failed to run migrations: {}", e))?;
+    for version in applied {
+        log::info!("Applied migration {}", version);
+    }
 
     log::debug!("--------------- Migrations executed successfully ---------------");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+    use diesel::sql_query;
+    use diesel::sql_types::BigInt;
+
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+
+    /// Rows written against the initial schema, with the condition that
+    /// finds each of them again after upgrading.
+    const FIXTURE_ROWS: &[(&str, &str)] = &[
+        ("automations", "uuid = 'fixture-automation'"),
+        ("feed_items", "title = 'Fixture feed item'"),
+        ("threads", "title = 'Fixture thread'"),
+        ("messages", "content = 'Fixture message'"),
+        ("transcripts", "filename = 'fixture.txt'"),
+        ("emails", "email_uid = 'fixture-email'"),
+    ];
+
+    fn migration_count() -> usize {
+        <EmbeddedMigrations as MigrationSource<Sqlite>>::migrations(&MIGRATIONS)
+            .expect("Failed to list migrations")
+            .len()
+    }
+
+    /// An in-memory database as an install that stopped at `applied`
+    /// migrations would have left it, holding some user data.
+    fn fixture_db(applied: usize) -> SqliteConnection {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        for _ in 0..applied {
+            connection.run_next_migration(MIGRATIONS).unwrap();
+        }
+        if applied > 0 {
+            connection
+                .batch_execute(
+                    "INSERT INTO automations (uuid, name) VALUES ('fixture-automation', 'Fixture');
+                     INSERT INTO feed_items (title) VALUES ('Fixture feed item');
+                     INSERT INTO threads (feed_item_id, title) VALUES (last_insert_rowid(), 'Fixture thread');
+                     INSERT INTO messages (thread_id, content) VALUES (last_insert_rowid(), 'Fixture message');
+                     INSERT INTO transcripts (thread_id, filename) VALUES (1, 'fixture.txt');
+                     INSERT INTO emails (email_uid, subject, date, sender, recipient, cc, body)
+                       VALUES ('fixture-email', 'Hello', 1700000000, 'a@acme.com', 'b@acme.com', '', 'Hi');",
+                )
+                .unwrap();
+        }
+        connection
+    }
+
+    fn count(connection: &mut SqliteConnection, table: &str, condition: &str) -> i64 {
+        sql_query(format!(
+            "SELECT COUNT(*) AS count FROM {} WHERE {}",
+            table, condition
+        ))
+        .get_result::<Count>(connection)
+        .unwrap()
+        .count
+    }
+
+    #[test]
+    fn upgrades_every_past_version_without_losing_data() {
+        let total = migration_count();
+        for applied in 0..=total {
+            let mut connection = fixture_db(applied);
+            let upgraded = run_migrations(&mut connection)
+                .unwrap_or_else(|e| panic!("Upgrade from version {} failed: {}", applied, e));
+            assert_eq!(upgraded.len(), total - applied);
+            assert!(!connection.has_pending_migration(MIGRATIONS).unwrap());
+
+            if applied > 0 {
+                for (table, condition) in FIXTURE_ROWS {
+                    assert_eq!(
+                        count(&mut connection, table, condition),
+                        1,
+                        "{} lost rows upgrading from version {}",
+                        table,
+                        applied
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod db;
 pub mod models;
 pub mod diesel_setup;
+pub mod repo;
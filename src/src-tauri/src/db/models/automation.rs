@@ -1,11 +1,13 @@
-use rusqlite::{params, OptionalExtension, Result};
+use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use crate::error::Error;
 
-use crate::db::db::get_db_conn;
-use crate::db::models::automation_run::AutomationRun;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::db::models::automation_action::AutomationAction;
+use crate::db::models::automation_run::{AutomationRun, AutomationRunStatus};
 use crate::db::models::automation_step::AutomationStep;
 use crate::db::models::cadence_trigger::CadenceTrigger;
 
@@ -22,87 +24,88 @@ pub struct Automation {
   pub trigger_cadences: Option<Vec<CadenceTrigger>>,
   pub trigger_data_sources: Option<Vec<DataSourceTrigger>>,
   pub steps: Option<Vec<AutomationStep>>,
+  /// Where results are delivered besides the feed; left as stored when
+  /// `None`.
+  #[serde(default)]
+  pub actions: Option<Vec<AutomationAction>>,
   pub is_active: bool,
   pub is_beta: bool,
   pub show_library: bool,
   pub icon: String,
 }
 
-impl Automation {
-  pub fn find_by_id(id: String) -> Result<Option<Automation>> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT id, uuid, name, is_active, description, is_beta, show_library FROM automations WHERE id = ?1",
-    )?;
-    let automation = stmt
-      .query_row(params![id], |row| Automation::build_struct_from_row(row))
-      .optional()?;
-
-    Ok(automation)
-  }
+impl FromRow for Automation {
+  const COLUMNS: &'static str =
+    "automations.id, automations.uuid, automations.name, automations.is_active, automations.description, automations.is_beta, automations.show_library, automations.icon";
 
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
-    let is_active_val: i64 = row.get(3)?;
-    let is_beta_val: i64 = row.get(5)?;
-    let show_library_val: i64 = row.get(6)?;
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     Ok(Automation {
       id: Some(row.get(0)?),
       uuid: row.get(1)?,
       name: row.get(2)?,
-      is_active: is_active_val != 0,
+      is_active: row.get(3)?,
       description: row.get(4)?,
-      is_beta: is_beta_val != 0,
+      is_beta: row.get(5)?,
       runs: None,
       trigger_cadences: None,
       trigger_data_sources: None,
       steps: None,
-      show_library: show_library_val != 0,
+      actions: None,
+      show_library: row.get(6)?,
       icon: row.get(7)?,
     })
   }
+}
 
-  pub fn find_by_message_id(message_id: u64) -> Result<Option<Automation>> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "
-    SELECT automations.id, automations.uuid, automations.name, automations.is_active, automations.description, automation.is_beta, automation.show_library, automation.icon FROM automations
-    LEFT JOIN automation_runs ON automation_runs.automation_uuid = automations.uuid
-    LEFT JOIN messages ON automation_runs.thread_id = messages.thread_id
-    WHERE messages.id = ?1",
-    )?;
-    let automation = stmt
-      .query_row(params![message_id], |row| Automation::build_struct_from_row(row))
-      .optional()?;
-    Ok(automation)
+impl Automation {
+  pub fn find_by_id(id: String) -> Result<Option<Automation>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM automations WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
   }
 
-  pub fn find_by_ids() -> Result<Vec<Automation>> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, uuid, name, is_active, description, is_beta, show_library, icon FROM automations")?;
-    let rows = stmt.query_map(params![], |row| Automation::build_struct_from_row(row))?;
-    let mut automations = Vec::new();
-    for automation in rows {
-      automations.push(automation?);
-    }
-    Ok(automations)
+  pub fn find_by_message_id(message_id: u64) -> Result<Option<Automation>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM automations
+        LEFT JOIN automation_runs ON automation_runs.automation_uuid = automations.uuid
+        LEFT JOIN messages ON automation_runs.thread_id = messages.thread_id
+        WHERE messages.id = ?1",
+        Self::COLUMNS
+      ),
+      params![message_id],
+    )
   }
 
-  pub fn find_by_uuid(uuid: String) -> Result<Automation> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT id, uuid, name, is_active, description, is_beta, show_library, icon  FROM automations WHERE uuid = ?1",
-    )?;
-    let automation = stmt.query_row(params![uuid], |row| Automation::build_struct_from_row(row))?;
+  pub fn find_by_ids() -> Result<Vec<Automation>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM automations", Self::COLUMNS),
+      [],
+    )
+  }
+
+  pub fn find_by_uuid(uuid: String) -> Result<Automation, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(&format!(
+      "SELECT {} FROM automations WHERE uuid = ?1",
+      Self::COLUMNS
+    ))?;
+    let automation = stmt.query_row(params![uuid], |row| Automation::from_row(row))?;
 
     Ok(automation)
   }
 
-  pub fn find_all() -> Vec<Automation> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare(
-        "
+  pub fn find_all() -> Result<Vec<Automation>, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "
         SELECT
           automations.id as automation_id,
           automations.uuid as automation_uuid,
@@ -129,47 +132,61 @@ impl Automation {
           data_source_trigger.offset_minutes as trigger_data_source_offset_minutes,
           automations.is_beta as automation_is_beta,
           automations.show_library as automation_show_library,
-          automations.icon as automation_icon
+          automations.icon as automation_icon,
+          automation_runs.status as run_status,
+          automation_runs.error as run_error,
+          cadence_triggers.cron_expr as cadence_cron_expr,
+          cadence_triggers.timezone as cadence_timezone,
+          cadence_triggers.interval_minutes as cadence_interval_minutes,
+          cadence_triggers.day_of_month as cadence_day_of_month,
+          data_source_trigger.filter_json as trigger_data_source_filter_json,
+          automation_steps.guard as step_guard
         FROM automations
         LEFT JOIN automation_steps ON automation_steps.automation_uuid = automations.uuid
         LEFT JOIN cadence_triggers ON cadence_triggers.automation_uuid = automations.uuid
         LEFT JOIN automation_runs ON automation_runs.automation_uuid = automations.uuid
         LEFT JOIN data_source_trigger ON data_source_trigger.automation_uuid = automations.uuid
         ORDER BY automations.name, step_ordering",
-      )
-      .expect("could not prepare query get automations");
+    )?;
     let rows = stmt
       .query_map([], |row| {
         Ok((
-          row.get::<_, u64>(0).unwrap(),             // automation_id
-          row.get::<_, String>(1).unwrap(),          // automation_uuid
-          row.get::<_, String>(2).unwrap(),          // automation_name
-          row.get::<_, String>(3).unwrap(),          // automation_description
-          row.get::<_, bool>(4).unwrap(),            // automation_is_active
-          row.get::<_, Option<u64>>(5).unwrap(),     // step_id
-          row.get::<_, Option<String>>(6).unwrap(),  // step_name
-          row.get::<_, Option<u64>>(7).unwrap(),     // step_ordering
-          row.get::<_, Option<String>>(8).unwrap(),  // step_args_json
-          row.get::<_, Option<u64>>(9).unwrap(),     // cadence_id
-          row.get::<_, Option<String>>(10).unwrap(), // cadence_type
-          row.get::<_, Option<String>>(11).unwrap(), // cadence_day_of_week
-          row.get::<_, Option<String>>(12).unwrap(), // cadence_time
-          row.get::<_, Option<u64>>(13).unwrap(),    // run_id
-          row.get::<_, Option<i64>>(14).unwrap(),    // run_schedule_timestamp
-          row.get::<_, Option<i64>>(15).unwrap(),    // run_schedule_timestamp
-          row.get::<_, Option<u64>>(16).unwrap(),    // run_thread_id
-          row.get::<_, Option<u64>>(17).unwrap(),    // run_user_id
-          row.get::<_, Option<String>>(18).unwrap(), // run_run_params
-          row.get::<_, Option<u64>>(19).unwrap(),    // run_feed_item_id
-          row.get::<_, Option<u64>>(20).unwrap(),    // trigger_data_source_id
-          row.get::<_, Option<String>>(21).unwrap(), // trigger_data_source_data_source
-          row.get::<_, Option<i64>>(22).unwrap(),    // trigger_data_source_offset_minutes
-          row.get::<_, bool>(23).unwrap(),           // automation_is_beta
-          row.get::<_, bool>(24).unwrap(),           // automation_show_library
-          row.get::<_, String>(25).unwrap(), // automation_icon
+          row.get::<_, u64>(0)?,             // automation_id
+          row.get::<_, String>(1)?,          // automation_uuid
+          row.get::<_, String>(2)?,          // automation_name
+          row.get::<_, String>(3)?,          // automation_description
+          row.get::<_, bool>(4)?,            // automation_is_active
+          row.get::<_, Option<u64>>(5)?,     // step_id
+          row.get::<_, Option<String>>(6)?,  // step_name
+          row.get::<_, Option<u64>>(7)?,     // step_ordering
+          row.get::<_, Option<String>>(8)?,  // step_args_json
+          row.get::<_, Option<u64>>(9)?,     // cadence_id
+          row.get::<_, Option<String>>(10)?, // cadence_type
+          row.get::<_, Option<String>>(11)?, // cadence_day_of_week
+          row.get::<_, Option<String>>(12)?, // cadence_time
+          row.get::<_, Option<u64>>(13)?,    // run_id
+          row.get::<_, Option<i64>>(14)?,    // run_schedule_timestamp
+          row.get::<_, Option<i64>>(15)?,    // run_schedule_timestamp
+          row.get::<_, Option<u64>>(16)?,    // run_thread_id
+          row.get::<_, Option<u64>>(17)?,    // run_user_id
+          row.get::<_, Option<String>>(18)?, // run_run_params
+          row.get::<_, Option<u64>>(19)?,    // run_feed_item_id
+          row.get::<_, Option<u64>>(20)?,    // trigger_data_source_id
+          row.get::<_, Option<String>>(21)?, // trigger_data_source_data_source
+          row.get::<_, Option<i64>>(22)?,    // trigger_data_source_offset_minutes
+          row.get::<_, bool>(23)?,           // automation_is_beta
+          row.get::<_, bool>(24)?,           // automation_show_library
+          row.get::<_, String>(25)?, // automation_icon
+          row.get::<_, Option<String>>(26)?, // run_status
+          row.get::<_, Option<String>>(27)?, // run_error
+          row.get::<_, Option<String>>(28)?, // cadence_cron_expr
+          row.get::<_, Option<String>>(29)?, // cadence_timezone
+          row.get::<_, Option<u32>>(30)?,    // cadence_interval_minutes
+          row.get::<_, Option<u32>>(31)?,    // cadence_day_of_month
+          row.get::<_, Option<String>>(32)?, // trigger_data_source_filter_json
+          row.get::<_, Option<String>>(33)?, // step_guard
         ))
-      })
-      .expect("Could not execute query");
+      })?;
     let mut automations: HashMap<u64, Automation> = HashMap::new();
     let mut steps: HashMap<u64, HashMap<u64, AutomationStep>> = HashMap::new();
     let mut cadences: HashMap<u64, HashMap<u64, CadenceTrigger>> = HashMap::new();
@@ -203,7 +220,15 @@ impl Automation {
         automation_is_beta,
         automation_show_library,
         automation_icon,
-      ) = row.unwrap();
+        run_status,
+        run_error,
+        cadence_cron_expr,
+        cadence_timezone,
+        cadence_interval_minutes,
+        cadence_day_of_month,
+        trigger_data_source_filter_json,
+        step_guard,
+      ) = row?;
       let automation = match automations.get(&automation_id) {
         Some(automation) => automation.clone(),
         None => Automation {
@@ -217,6 +242,7 @@ impl Automation {
           trigger_cadences: None,
           trigger_data_sources: None,
           steps: None,
+          actions: None,
           show_library: automation_show_library,
           icon: automation_icon,
         },
@@ -230,9 +256,10 @@ impl Automation {
         let step = AutomationStep {
           id: Some(step_id),
           automation_uuid: automation_uuid.clone(),
-          name: step_name.unwrap(),
-          ordering: step_ordering.unwrap(),
+          name: step_name.unwrap_or_default(),
+          ordering: step_ordering.unwrap_or_default(),
           args_json: step_args_json,
+          guard: step_guard,
         };
         automation_steps.insert(step_id, step);
         steps.insert(automation_id.clone(), automation_steps);
@@ -245,9 +272,13 @@ impl Automation {
         let cadence = CadenceTrigger {
           id: Some(cadence_id),
           automation_uuid: automation_uuid.clone(),
-          cadence_type: cadence_type.unwrap(),
+          cadence_type: cadence_type.unwrap_or_default(),
           day_of_week: cadence_day_of_week,
           time: cadence_time,
+          cron_expr: cadence_cron_expr,
+          timezone: cadence_timezone,
+          interval_minutes: cadence_interval_minutes,
+          day_of_month: cadence_day_of_month,
         };
         automation_cadences.insert(cadence_id, cadence);
         cadences.insert(automation_id.clone(), automation_cadences);
@@ -261,8 +292,11 @@ impl Automation {
         let trigger_data_source = DataSourceTrigger {
           id: Some(trigger_data_source_id),
           automation_uuid: automation_uuid.clone(),
-          data_source: trigger_data_source_data_source.unwrap(),
+          data_source: trigger_data_source_data_source.unwrap_or_default(),
           offset_minutes: trigger_data_source_offset_minutes.unwrap_or(0),
+          filter_json: trigger_data_source_filter_json,
+          created_timestamp: None,
+          cursor: None,
         };
         data_source_trigger.insert(trigger_data_source_id, trigger_data_source);
         trigger_data_sources.insert(automation_id.clone(), data_source_trigger);
@@ -278,67 +312,81 @@ impl Automation {
           id: Some(run_id),
           automation_uuid: automation_uuid.clone(),
           thread_id: run_thread_id,
-          user_id: run_user_id.unwrap(),
+          user_id: run_user_id.unwrap_or_default(),
           schedule_timestamp: run_schedule_timestamp,
           execution_timestamp: run_execution_timestamp,
           run_params: run_run_params,
           feed_item_id: run_feed_item_id,
+          status: run_status.as_deref().and_then(AutomationRunStatus::from_str),
+          error: run_error,
+          step_results: None,
+          run_key: None,
+          finished_timestamp: None,
+          attempts: 0,
+          action_results: None,
         };
 
         automation_runs.insert(run_id, run);
         runs.insert(automation_id.clone(), automation_runs);
       }
     }
-    automations
-      .values()
-      .into_iter()
-      .map(|automation| Automation {
-        id: automation.id.clone(),
-        uuid: automation.uuid.clone(),
-        name: automation.name.clone(),
-        description: automation.description.clone(),
-        is_active: automation.is_active,
-        is_beta: automation.is_beta,
-        show_library: automation.show_library,
-        icon: automation.icon.clone(),
-        runs: Some(
-          runs
-            .get(&automation.id.unwrap())
-            .cloned()
-            .unwrap_or(HashMap::new())
-            .values()
-            .cloned()
-            .collect::<Vec<_>>(),
-        ),
-        trigger_cadences: Some(
-          cadences
-            .get(&automation.id.unwrap())
-            .cloned()
-            .unwrap_or(HashMap::new())
-            .values()
-            .cloned()
-            .collect::<Vec<_>>(),
-        ),
-        trigger_data_sources: Some(
-          trigger_data_sources
-            .get(&automation.id.unwrap())
-            .cloned()
-            .unwrap_or(HashMap::new())
-            .values()
-            .cloned()
-            .collect::<Vec<_>>(),
-        ),
-        steps: Some(
-          steps
-            .get(&automation.id.unwrap())
-            .cloned()
-            .unwrap_or(HashMap::new())
-            .values()
-            .cloned()
-            .collect::<Vec<_>>(),
-        ),
-      })
-      .collect::<Vec<Automation>>()
+    let mut actions: HashMap<String, Vec<AutomationAction>> = HashMap::new();
+    for action in AutomationAction::find_all()? {
+      actions.entry(action.automation_uuid.clone()).or_default().push(action);
+    }
+    Ok(
+      automations
+        .values()
+        .into_iter()
+        .map(|automation| Automation {
+          id: automation.id.clone(),
+          uuid: automation.uuid.clone(),
+          name: automation.name.clone(),
+          description: automation.description.clone(),
+          is_active: automation.is_active,
+          is_beta: automation.is_beta,
+          show_library: automation.show_library,
+          icon: automation.icon.clone(),
+          runs: Some(
+            runs
+              .get(&automation.id.unwrap())
+              .cloned()
+              .unwrap_or(HashMap::new())
+              .values()
+              .cloned()
+              .collect::<Vec<_>>(),
+          ),
+          trigger_cadences: Some(
+            cadences
+              .get(&automation.id.unwrap())
+              .cloned()
+              .unwrap_or(HashMap::new())
+              .values()
+              .cloned()
+              .collect::<Vec<_>>(),
+          ),
+          trigger_data_sources: Some(
+            trigger_data_sources
+              .get(&automation.id.unwrap())
+              .cloned()
+              .unwrap_or(HashMap::new())
+              .values()
+              .cloned()
+              .collect::<Vec<_>>(),
+          ),
+          steps: Some(
+            steps
+              .get(&automation.id.unwrap())
+              .cloned()
+              .unwrap_or(HashMap::new())
+              .values()
+              .cloned()
+              .collect::<Vec<_>>(),
+          ),
+          actions: Some(actions.get(&automation.uuid).cloned().unwrap_or_default()),
+        })
+        .collect::<Vec<Automation>>(),
+    )
   }
 
   pub fn set_cadences(&mut self, cadences: Vec<CadenceTrigger>) {
@@ -350,19 +398,17 @@ impl Automation {
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let existing_automation = Automation::find_by_uuid(self.uuid.clone());
     if let Ok(automation) = existing_automation {
       self.id = automation.id;
       return Ok(());
     }
 
-    connection
-            .execute(
-                "INSERT INTO automations (uuid, name, description, is_active, is_beta, show_library, icon) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) ON CONFLICT(uuid) DO UPDATE SET name = ?2, description = ?3, is_active = ?4, is_beta = ?5",
-                (&self.uuid, &self.name, &self.description, &self.is_active, &self.is_beta,&self.show_library, &self.icon),
-            )
-            .expect("Could not insert automation");
+    connection.execute(
+      "INSERT INTO automations (uuid, name, description, is_active, is_beta, show_library, icon) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) ON CONFLICT(uuid) DO UPDATE SET name = ?2, description = ?3, is_active = ?4, is_beta = ?5",
+      (&self.uuid, &self.name, &self.description, &self.is_active, &self.is_beta,&self.show_library, &self.icon),
+    )?;
     if self.id.is_none() {
       self.id = Some(connection.last_insert_rowid() as u64);
     }
@@ -371,7 +417,7 @@ impl Automation {
       connection.execute(
         "DELETE FROM cadence_triggers WHERE automation_uuid = ?1",
         [&self.uuid],
-      );
+      )?;
       for cadence in cadences {
         cadence.create()?;
       }
@@ -381,7 +427,7 @@ impl Automation {
       connection.execute(
         "DELETE FROM data_source_trigger WHERE automation_uuid = ?1",
         [&self.uuid],
-      );
+      )?;
       for trigger_data_source in trigger_data_sources {
         trigger_data_source.create()?;
       }
@@ -389,90 +435,93 @@ impl Automation {
     connection.execute(
       "DELETE FROM automation_steps WHERE automation_uuid = ?1",
       [&self.uuid],
-    );
+    )?;
     if let Some(ref mut steps) = self.steps {
       for step in steps {
         step.create()?;
       }
     }
+    if let Some(ref mut actions) = self.actions {
+      AutomationAction::delete_by_automation_uuid(&self.uuid)?;
+      for action in actions {
+        action.create()?;
+      }
+    }
     Ok(())
   }
 
-  pub fn delete(&mut self) {
-    let connection = get_db_conn();
-    connection
-      .execute("DELETE FROM automations WHERE id = ?1", [&self.id])
-      .expect("Could not delete automation");
+  pub fn delete(&mut self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM automations WHERE id = ?1", [&self.id])?;
 
     connection.execute(
       "DELETE FROM cadence_triggers WHERE automation_uuid = ?1",
-      [&self.id],
-    );
+      [&self.uuid],
+    )?;
 
     connection.execute(
       "DELETE FROM data_source_trigger WHERE automation_uuid = ?1",
-      [&self.id],
-    );
+      [&self.uuid],
+    )?;
 
     connection.execute(
       "DELETE FROM automation_steps WHERE automation_uuid = ?1",
-      [&self.id],
-    );
+      [&self.uuid],
+    )?;
+    AutomationAction::delete_by_automation_uuid(&self.uuid)?;
+    Ok(())
   }
 
   pub fn update(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
-    println!(
-      "----------- IS ACTIVE: {:?} -----------",
-      (&self.name, &self.description, &self.id, &self.is_active,)
-    );
-    connection
-      .execute(
-        "UPDATE automations SET name = ?1, description = ?2, is_active = ?3 WHERE id = ?4",
-        (&self.name, &self.description, &self.is_active, &self.id),
-      )
-      .expect("Could not update automation");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE automations SET name = ?1, description = ?2, is_active = ?3, is_beta = ?5, show_library = ?6, icon = ?7 WHERE id = ?4",
+      (&self.name, &self.description, &self.is_active, &self.id, &self.is_beta, &self.show_library, &self.icon),
+    )?;
 
     if let Some(ref trigger_cadences) = self.trigger_cadences {
       connection.execute(
         "DELETE FROM cadence_triggers WHERE automation_uuid = ?1",
         [&self.uuid],
-      );
+      )?;
       for cadence in trigger_cadences {
-        connection
-                    .execute(
-                        "INSERT INTO cadence_triggers (cadence_type, day_of_week, time, automation_uuid) VALUES (?1, ?2, ?3, ?4)",
-                        (&cadence.cadence_type, &cadence.day_of_week, &cadence.time, &self.uuid)
-                    )
-                    .expect("Could not insert cadence");
+        connection.execute(
+          "INSERT INTO cadence_triggers (cadence_type, day_of_week, time, automation_uuid, cron_expr, timezone, interval_minutes, day_of_month) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+          (&cadence.cadence_type, &cadence.day_of_week, &cadence.time, &self.uuid, &cadence.cron_expr, &cadence.timezone, &cadence.interval_minutes, &cadence.day_of_month),
+        )?;
       }
     }
     if let Some(ref trigger_data_sources) = self.trigger_data_sources {
       connection.execute(
         "DELETE FROM data_source_trigger WHERE automation_uuid = ?1",
         [&self.uuid],
-      );
+      )?;
       for trigger_data_source in trigger_data_sources {
-        connection
-          .execute(
-            "INSERT INTO data_source_trigger (offset_minutes, automation_uuid) VALUES (?1, ?2)",
-            (&trigger_data_source.offset_minutes, &self.uuid),
-          )
-          .expect("Could not insert cadence");
+        connection.execute(
+          "INSERT INTO data_source_trigger (data_source, offset_minutes, automation_uuid, filter_json) VALUES (?1, ?2, ?3, ?4)",
+          (&trigger_data_source.data_source, &trigger_data_source.offset_minutes, &self.uuid, &trigger_data_source.filter_json),
+        )?;
       }
     }
     connection.execute(
       "DELETE FROM automation_steps WHERE automation_uuid = ?1",
       [&self.uuid],
-    );
+    )?;
     if let Some(ref steps) = self.steps {
       for step in steps {
-        connection
-                    .execute(
-                        "INSERT INTO automation_steps (name, ordering, args_json, automation_uuid) VALUES (?1, ?2, ?3, ?4)",
-                        (&step.name, &step.ordering, &step.args_json, &self.uuid)
-                    )
-                    .expect("Could not insert automation step");
+        connection.execute(
+          "INSERT INTO automation_steps (name, ordering, args_json, guard, automation_uuid) VALUES (?1, ?2, ?3, ?4, ?5)",
+          (&step.name, &step.ordering, &step.args_json, &step.guard, &self.uuid),
+        )?;
+      }
+    }
+    if let Some(ref actions) = self.actions {
+      AutomationAction::delete_by_automation_uuid(&self.uuid)?;
+      for action in actions {
+        connection.execute(
+          "INSERT INTO automation_actions (action_type, config_json, ordering, automation_uuid) VALUES (?1, ?2, ?3, ?4)",
+          (&action.action_type, &action.config_json, &action.ordering, &self.uuid),
+        )?;
       }
     }
     Ok(())
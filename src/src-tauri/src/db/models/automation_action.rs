@@ -0,0 +1,80 @@
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_rows, FromRow};
+use crate::error::Error;
+
+/// Where a run's result is delivered once its steps succeed, besides the
+/// feed. `config_json` depends on `action_type`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutomationAction {
+  pub id: Option<u64>,
+  pub automation_uuid: String,
+  pub action_type: String,
+  pub config_json: Option<String>,
+  pub ordering: u64,
+}
+
+impl FromRow for AutomationAction {
+  const COLUMNS: &'static str = "id, automation_uuid, action_type, config_json, ordering";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(AutomationAction {
+      id: Some(row.get(0)?),
+      automation_uuid: row.get(1)?,
+      action_type: row.get(2)?,
+      config_json: row.get(3)?,
+      ordering: row.get(4)?,
+    })
+  }
+}
+
+impl AutomationAction {
+  pub fn find_all() -> Result<Vec<AutomationAction>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM automation_actions ORDER BY ordering", Self::COLUMNS),
+      [],
+    )
+  }
+
+  pub fn find_by_automation_uuid(automation_uuid: &str) -> Result<Vec<AutomationAction>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM automation_actions WHERE automation_uuid = ?1 ORDER BY ordering",
+        Self::COLUMNS
+      ),
+      params![automation_uuid],
+    )
+  }
+
+  pub fn create(&mut self) -> Result<(), Error> {
+    if self.id.is_some() {
+      return Err(Error::KSError(
+        "Cannot create AutomationAction; AutomationAction already exists.".into(),
+      ));
+    }
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO automation_actions (automation_uuid, action_type, config_json, ordering) VALUES (?1, ?2, ?3, ?4)",
+      (&self.automation_uuid, &self.action_type, &self.config_json, &self.ordering),
+    )?;
+
+    self.id = Some(connection.last_insert_rowid() as u64);
+    Ok(())
+  }
+
+  pub fn delete_by_automation_uuid(automation_uuid: &str) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "DELETE FROM automation_actions WHERE automation_uuid = ?1",
+      params![automation_uuid],
+    )?;
+    Ok(())
+  }
+}
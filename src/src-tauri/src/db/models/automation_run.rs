@@ -1,11 +1,72 @@
-use rusqlite::{params, params_from_iter, OptionalExtension, Result};
+use rusqlite::{params, params_from_iter, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
 use serde_json::Value;
 use crate::db::models::feed_item::FeedItem;
 
+/// Where a scheduled run is in its execution. Runs created before statuses
+/// existed have none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationRunStatus {
+  #[serde(alias = "pending")]
+  Scheduled,
+  Running,
+  Succeeded,
+  Failed,
+  Skipped,
+}
+
+impl AutomationRunStatus {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AutomationRunStatus::Scheduled => "scheduled",
+      AutomationRunStatus::Running => "running",
+      AutomationRunStatus::Succeeded => "succeeded",
+      AutomationRunStatus::Failed => "failed",
+      AutomationRunStatus::Skipped => "skipped",
+    }
+  }
+
+  pub fn from_str(status: &str) -> Option<AutomationRunStatus> {
+    match status {
+      "scheduled" | "pending" => Some(AutomationRunStatus::Scheduled),
+      "running" => Some(AutomationRunStatus::Running),
+      "succeeded" => Some(AutomationRunStatus::Succeeded),
+      "failed" => Some(AutomationRunStatus::Failed),
+      "skipped" => Some(AutomationRunStatus::Skipped),
+      _ => None,
+    }
+  }
+}
+
+/// What one step of a run did. `outputs` are the values later steps could
+/// reference.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepResult {
+  pub ordering: u64,
+  pub name: String,
+  pub status: AutomationRunStatus,
+  pub outputs: Option<Value>,
+  pub error: Option<String>,
+}
+
+/// How delivering a run's result through one output action went. `detail`
+/// says where it went, e.g. the recipients or the written file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionResult {
+  pub ordering: u64,
+  pub action_type: String,
+  pub status: AutomationRunStatus,
+  pub detail: Option<String>,
+  pub error: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AutomationRun {
@@ -17,87 +78,210 @@ pub struct AutomationRun {
   pub execution_timestamp: Option<i64>,
   pub run_params: Option<String>,
   pub feed_item_id: Option<u64>,
+  #[serde(default)]
+  pub status: Option<AutomationRunStatus>,
+  #[serde(default)]
+  pub error: Option<String>,
+  #[serde(default)]
+  pub step_results: Option<Vec<StepResult>>,
+  /// Identifies a cadence occurrence so it is only ever stored once.
+  #[serde(default)]
+  pub run_key: Option<String>,
+  /// When the last attempt ended, in milliseconds.
+  #[serde(default)]
+  pub finished_timestamp: Option<i64>,
+  /// How often the run was claimed for execution, retries included.
+  #[serde(default)]
+  pub attempts: u32,
+  #[serde(default)]
+  pub action_results: Option<Vec<ActionResult>>,
+}
+
+impl FromRow for AutomationRun {
+  const COLUMNS: &'static str = "id, automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id, status, error, step_results, run_key, finished_timestamp, attempts, action_results";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    let status: Option<String> = row.get(8)?;
+    let step_results: Option<String> = row.get(10)?;
+    let action_results: Option<String> = row.get(14)?;
+    Ok(AutomationRun {
+      id: Some(row.get(0)?),
+      automation_uuid: row.get(1)?,
+      user_id: row.get(2)?,
+      thread_id: row.get(3)?,
+      schedule_timestamp: row.get(4)?,
+      execution_timestamp: row.get(5)?,
+      run_params: row.get(6)?,
+      feed_item_id: row.get(7)?,
+      status: status.as_deref().and_then(AutomationRunStatus::from_str),
+      error: row.get(9)?,
+      step_results: step_results.and_then(|step_results| serde_json::from_str(&step_results).ok()),
+      run_key: row.get(11)?,
+      finished_timestamp: row.get(12)?,
+      attempts: row.get(13)?,
+      action_results: action_results.and_then(|action_results| serde_json::from_str(&action_results).ok()),
+    })
+  }
+}
+
+/// Narrows a page of run history. Timestamps bound `schedule_timestamp`.
+#[derive(Debug, Clone, Default)]
+pub struct AutomationRunFilter {
+  pub run_id: Option<u64>,
+  pub automation_uuid: Option<String>,
+  pub status: Option<AutomationRunStatus>,
+  pub since: Option<i64>,
+  pub until: Option<i64>,
+  pub limit: u32,
+  pub offset: u32,
+}
+
+/// A run as listed in its history, with what it cost.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutomationRunRecord {
+  #[serde(flatten)]
+  pub run: AutomationRun,
+  pub automation_name: Option<String>,
+  /// From claim to finish of the last attempt.
+  pub duration_millis: Option<i64>,
+  pub input_tokens: i64,
+  pub output_tokens: i64,
+  pub cost_usd: f64,
+}
+
+impl AutomationRunFilter {
+  fn where_clause(&self) -> (String, Vec<rusqlite::types::Value>) {
+    let mut conditions = vec!["1 = 1".to_string()];
+    let mut values: Vec<rusqlite::types::Value> = Vec::new();
+    let mut condition = |sql: &str, value: rusqlite::types::Value| {
+      values.push(value);
+      conditions.push(sql.replace('?', &format!("?{}", values.len())));
+    };
+    if let Some(run_id) = self.run_id {
+      condition("id = ?", (run_id as i64).into());
+    }
+    if let Some(automation_uuid) = &self.automation_uuid {
+      condition("automation_uuid = ?", automation_uuid.clone().into());
+    }
+    if let Some(status) = self.status {
+      condition("status = ?", status.as_str().to_string().into());
+    }
+    if let Some(since) = self.since {
+      condition("schedule_timestamp >= ?", since.into());
+    }
+    if let Some(until) = self.until {
+      condition("schedule_timestamp < ?", until.into());
+    }
+    (conditions.join(" AND "), values)
+  }
 }
 
 impl AutomationRun {
+  /// One page of runs matching `filter`, newest first, and how many match
+  /// in total.
+  pub fn find_page(filter: &AutomationRunFilter) -> Result<(Vec<AutomationRunRecord>, u64), Error> {
+    let connection = try_get_db_conn()?;
+    let (where_clause, mut values) = filter.where_clause();
+    let total: u64 = connection.query_row(
+      &format!("SELECT COUNT(*) FROM automation_runs WHERE {}", where_clause),
+      params_from_iter(values.iter()),
+      |row| row.get(0),
+    )?;
+
+    let limit_index = values.len() + 1;
+    values.push((filter.limit as i64).into());
+    values.push((filter.offset as i64).into());
+    let mut stmt = connection.prepare(&format!(
+      "SELECT runs.*, automations.name,
+        COALESCE(usage.input_tokens, 0), COALESCE(usage.output_tokens, 0), COALESCE(usage.cost_usd, 0.0)
+      FROM (SELECT {} FROM automation_runs WHERE {}) AS runs
+      LEFT JOIN automations ON automations.uuid = runs.automation_uuid
+      LEFT JOIN (
+        SELECT automation_run_id, SUM(input_tokens) AS input_tokens,
+          SUM(output_tokens) AS output_tokens, SUM(cost_usd) AS cost_usd
+        FROM token_usage WHERE automation_run_id IS NOT NULL GROUP BY automation_run_id
+      ) AS usage ON usage.automation_run_id = runs.id
+      ORDER BY COALESCE(runs.schedule_timestamp, runs.execution_timestamp) DESC, runs.id DESC
+      LIMIT ?{} OFFSET ?{}",
+      Self::COLUMNS,
+      where_clause,
+      limit_index,
+      limit_index + 1
+    ))?;
+    let records = stmt
+      .query_map(params_from_iter(values.iter()), |row| {
+        let run = AutomationRun::from_row(row)?;
+        let duration_millis = match (run.execution_timestamp, run.finished_timestamp) {
+          (Some(started), Some(finished)) => Some(finished - started),
+          _ => None,
+        };
+        Ok(AutomationRunRecord {
+          run,
+          automation_name: row.get(15)?,
+          duration_millis,
+          input_tokens: row.get(16)?,
+          output_tokens: row.get(17)?,
+          cost_usd: row.get(18)?,
+        })
+      })?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok((records, total))
+  }
+
+  fn step_results_json(&self) -> Result<Option<String>, Error> {
+    Ok(match &self.step_results {
+      Some(step_results) => Some(serde_json::to_string(step_results)?),
+      None => None,
+    })
+  }
+
+  fn action_results_json(&self) -> Result<Option<String>, Error> {
+    Ok(match &self.action_results {
+      Some(action_results) => Some(serde_json::to_string(action_results)?),
+      None => None,
+    })
+  }
+
   pub fn find_run_by_calendar_event(
     event_id: u64,
     timestamp: i64,
     automation_uuid: &str,
     user_id: u64
 ) -> Result<Option<Self>, Error> {
-    let conn = get_db_conn();
-    let mut stmt = conn.prepare(
-      "SELECT id, automation_uuid, user_id, thread_id, 
-        schedule_timestamp, execution_timestamp, run_params, feed_item_id
-        FROM automation_runs
+    let conn = try_get_db_conn()?;
+    query_optional(
+      &conn,
+      &format!(
+        "SELECT {} FROM automation_runs
         WHERE automation_uuid = ?1
         AND user_id = ?2
         AND json_extract(run_params, '$.event_id') = ?3
-        AND json_extract(run_params, '$.timestamp') = ?4"
-    ).map_err(|e| Error::KSError(e.to_string()))?;
-
-    stmt.query_row(
+        AND json_extract(run_params, '$.timestamp') = ?4",
+        Self::COLUMNS
+      ),
       params![automation_uuid, user_id, event_id, timestamp],
-      |row| Self::build_struct_from_row(row)
-    ).optional().map_err(|e| Error::KSError(e.to_string()))
+    )
   }
 
-  pub fn find_by_id(id: u64) -> Result<Option<AutomationRun>> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT id, automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id FROM automation_runs WHERE id = ?1",
-    )?;
-    let run = stmt
-      .query_row(params![id], |row| AutomationRun::build_struct_from_row(row))
-      .optional()?;
-
-    Ok(run)
+  pub fn find_by_id(id: u64) -> Result<Option<AutomationRun>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM automation_runs WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
   }
 
-  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<AutomationRun>> {
-    let connection = get_db_conn();
+  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<AutomationRun>, Error> {
+    let connection = try_get_db_conn()?;
     let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
     let query = format!(
-      "SELECT id, automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id FROM automation_runs WHERE id IN ({})",
+      "SELECT {} FROM automation_runs WHERE id IN ({})",
+      Self::COLUMNS,
       placeholders
     );
-    let mut stmt = connection.prepare(&query)?;
-
-    let id_refs: Vec<&u64> = ids.iter().collect();
-    let rows = stmt.query_map(rusqlite::params_from_iter(id_refs), |row| {
-      AutomationRun::build_struct_from_row(row)
-    })?;
-
-    let mut runs = Vec::new();
-    for run in rows {
-      runs.push(run?);
-    }
-    Ok(runs)
-  }
-
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
-    Ok(AutomationRun {
-      id: Some(row.get(0)?),
-      automation_uuid: row.get(1)?,
-      user_id: row.get(2)?,
-      thread_id: row.get(3)?,
-      schedule_timestamp: row.get(4)?,
-      execution_timestamp: row.get(5)?,
-      run_params: row.get(6)?,
-      feed_item_id: row.get(7)?,
-    })
-  }
-
-  pub fn find_all() -> Vec<AutomationRun> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id FROM automation_runs ORDER BY date DESC")
-      .expect("could not prepare query get automation runs");
-    let rows = stmt
-      .query_map([], |row| AutomationRun::build_struct_from_row(row))
-      .expect("Could not execute query");
-    rows.filter_map(Result::ok).collect()
+    query_rows(&connection, &query, rusqlite::params_from_iter(ids.iter()))
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
@@ -106,61 +290,96 @@ impl AutomationRun {
         "Cannot create AutomationRun; AutomationRun already exists.".into(),
       ));
     }
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "INSERT INTO automation_runs (automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-        (&self.automation_uuid, &self.user_id,  &self.thread_id, &self.schedule_timestamp, &self.execution_timestamp, &self.run_params,  &self.feed_item_id),
-        )
-      .expect("Could not insert automation run");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO automation_runs (automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id, status, error, step_results, run_key, finished_timestamp, attempts, action_results) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+      params![self.automation_uuid, self.user_id, self.thread_id, self.schedule_timestamp, self.execution_timestamp, self.run_params, self.feed_item_id, self.status.map(|status| status.as_str()), self.error, self.step_results_json()?, self.run_key, self.finished_timestamp, self.attempts, self.action_results_json()?],
+    )?;
 
     self.id = Some(connection.last_insert_rowid() as u64);
     Ok(())
   }
 
-  pub fn update(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "UPDATE automation_runs SET automation_uuid = ?2, user_id = ?3, thread_id = ?4, schedule_timestamp = ?5, execution_timestamp = ?6, run_params = ?7, feed_item_id = ?8 WHERE id = ?1",
-        (&self.id, &self.automation_uuid, &self.user_id,  &self.thread_id, &self.schedule_timestamp, &self.execution_timestamp, &self.run_params,  &self.feed_item_id),
-        )
-      .expect("Could not insert automation run");
-
+  /// Creates the run unless one with the same `run_key` exists. Returns
+  /// whether it was created.
+  pub fn create_if_new(&mut self) -> Result<bool, Error> {
+    if self.id.is_some() {
+      return Err(Error::KSError(
+        "Cannot create AutomationRun; AutomationRun already exists.".into(),
+      ));
+    }
+    let connection = try_get_db_conn()?;
+    let inserted = connection.execute(
+      "INSERT OR IGNORE INTO automation_runs (automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id, status, error, step_results, run_key, finished_timestamp, attempts, action_results) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+      params![self.automation_uuid, self.user_id, self.thread_id, self.schedule_timestamp, self.execution_timestamp, self.run_params, self.feed_item_id, self.status.map(|status| status.as_str()), self.error, self.step_results_json()?, self.run_key, self.finished_timestamp, self.attempts, self.action_results_json()?],
+    )?;
+    if inserted == 0 {
+      return Ok(false);
+    }
     self.id = Some(connection.last_insert_rowid() as u64);
+    Ok(true)
+  }
+
+  /// Key of the run of `automation_uuid` firing at `fire_millis`.
+  pub fn cadence_run_key(automation_uuid: &str, fire_millis: i64) -> String {
+    format!("{}@{}", automation_uuid, fire_millis)
+  }
+
+  pub fn find_by_run_key(run_key: &str) -> Result<Option<AutomationRun>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM automation_runs WHERE run_key = ?1", Self::COLUMNS),
+      params![run_key],
+    )
+  }
+
+  pub fn update(&mut self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE automation_runs SET automation_uuid = ?2, user_id = ?3, thread_id = ?4, schedule_timestamp = ?5, execution_timestamp = ?6, run_params = ?7, feed_item_id = ?8, status = ?9, error = ?10, step_results = ?11, finished_timestamp = ?12, attempts = ?13, action_results = ?14 WHERE id = ?1",
+      params![self.id, self.automation_uuid, self.user_id, self.thread_id, self.schedule_timestamp, self.execution_timestamp, self.run_params, self.feed_item_id, self.status.map(|status| status.as_str()), self.error, self.step_results_json()?, self.finished_timestamp, self.attempts, self.action_results_json()?],
+    )?;
     Ok(())
   }
 
+  /// Creates the run or refreshes the stored one for the same occurrence:
+  /// the same calendar event and time, or else the same `run_key`.
   pub fn upsert_schedule(&mut self) -> Result<AutomationRun, Error> {
-    let (event_id, timestamp) = match &self.run_params {
-      Some(params) => {
-        if let Ok(json) = serde_json::from_str::<serde_json::Value>(params) {
-          let event_id = json.get("event_id").and_then(|v| v.as_u64());
-          let timestamp = json.get("timestamp").and_then(|v| v.as_i64());
-          
-          match (event_id, timestamp) {
-            (Some(e), Some(t)) => (e, t),
-            _ => return Err(Error::KSError("Missing event_id or timestamp in run_params".into()))
-          }
-        } else {
-          return Err(Error::KSError("Invalid JSON in run_params".into()));
-        }
-      },
-      None => return Err(Error::KSError("Missing run_params".into()))
+    let run_params: Value = match &self.run_params {
+      Some(params) => serde_json::from_str(params)
+        .map_err(|_| Error::KSError("Invalid JSON in run_params".into()))?,
+      None => return Err(Error::KSError("Missing run_params".into())),
+    };
+    let timestamp = run_params
+      .get("timestamp")
+      .and_then(|v| v.as_i64())
+      .ok_or_else(|| Error::KSError("Missing timestamp in run_params".into()))?;
+
+    let maybe_instance = match run_params.get("event_id").and_then(|v| v.as_u64()) {
+      Some(event_id) => AutomationRun::find_run_by_calendar_event(
+        event_id,
+        timestamp,
+        &self.automation_uuid,
+        self.user_id,
+      )?,
+      None => {
+        let run_key = self
+          .run_key
+          .clone()
+          .unwrap_or_else(|| Self::cadence_run_key(&self.automation_uuid, timestamp));
+        self.run_key = Some(run_key.clone());
+        AutomationRun::find_by_run_key(&run_key)?
+      }
     };
-  
-    let maybe_instance = AutomationRun::find_run_by_calendar_event(
-      event_id,
-      timestamp,
-      &self.automation_uuid,
-      self.user_id,
-    )?;
   
     match maybe_instance {
       Some(instance) => {
         self.id = instance.id;
         self.execution_timestamp = instance.execution_timestamp;
         self.thread_id = instance.thread_id;
+        self.status = instance.status;
+        self.error = instance.error;
         self.update()?;
         Ok(self.clone())
       }
@@ -177,25 +396,30 @@ impl AutomationRun {
         "Cannot delete AutomationRun; AutomationRun does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
-    connection
-      .execute("DELETE FROM automation_runs WHERE id = ?1", [self.id])
-      .expect("Could not delete automation run");
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM automation_runs WHERE id = ?1", [self.id])?;
     Ok(())
   }
 
-  pub fn delete_outdated_calendar_runs(calendar_event_ids: &[u64], from_timestamp: i64) -> Result<(), Error> {
-    let connection = get_db_conn();
+  /// Deletes the upcoming calendar runs of `automation_uuid` whose event is
+  /// not in `calendar_event_ids`.
+  pub fn delete_outdated_calendar_runs(
+    automation_uuid: &str,
+    calendar_event_ids: &[u64],
+    from_timestamp: i64,
+  ) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare(
       "
         SELECT id, run_params, feed_item_id
         FROM automation_runs
         WHERE run_params IS NOT NULL
         AND schedule_timestamp >= ?1
+        AND automation_uuid = ?2
     ",
     )?;
 
-    let rows = stmt.query_map([from_timestamp], |row| {
+    let rows = stmt.query_map(params![from_timestamp, automation_uuid], |row| {
       Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<u64>>(2)?))
     })?;
 
@@ -242,14 +466,338 @@ impl AutomationRun {
     Ok(())
   }
 
-  pub fn find_by_feed_item_id(id: u64) -> Result<Option<AutomationRun>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT id, automation_uuid, user_id, thread_id, schedule_timestamp, execution_timestamp, run_params, feed_item_id FROM automation_runs WHERE feed_item_id = ?1",
+  /// Runs of active automations whose time has come and that nobody has
+  /// executed yet, oldest first. Only automations made of `step_names` steps
+  /// are returned.
+  pub fn find_due(now_millis: i64, step_names: &[&str], limit: u32) -> Result<Vec<AutomationRun>, Error> {
+    let connection = try_get_db_conn()?;
+    let placeholders = step_names
+      .iter()
+      .enumerate()
+      .map(|(index, _)| format!("?{}", index + 3))
+      .collect::<Vec<_>>()
+      .join(",");
+    let query = format!(
+      "SELECT {} FROM automation_runs
+      WHERE schedule_timestamp <= ?1
+      AND execution_timestamp IS NULL
+      AND automation_uuid IN (SELECT uuid FROM automations WHERE is_active = 1)
+      AND automation_uuid IN (SELECT automation_uuid FROM automation_steps)
+      AND automation_uuid NOT IN (
+        SELECT automation_uuid FROM automation_steps WHERE name NOT IN ({})
+      )
+      ORDER BY schedule_timestamp
+      LIMIT ?2",
+      Self::COLUMNS,
+      placeholders
+    );
+    let mut values: Vec<rusqlite::types::Value> = vec![now_millis.into(), (limit as i64).into()];
+    values.extend(step_names.iter().map(|name| name.to_string().into()));
+    query_rows(&connection, &query, params_from_iter(values))
+  }
+
+  /// Marks the run as running unless the frontend or another sweep already
+  /// executed it. Returns whether this caller owns the run.
+  pub fn claim(&mut self, now_millis: i64) -> Result<bool, Error> {
+    let connection = try_get_db_conn()?;
+    let claimed = connection.execute(
+      "UPDATE automation_runs SET status = ?2, execution_timestamp = ?3, error = NULL,
+      finished_timestamp = NULL, attempts = attempts + 1
+      WHERE id = ?1 AND execution_timestamp IS NULL",
+      params![self.id, AutomationRunStatus::Running.as_str(), now_millis],
+    )? == 1;
+    if claimed {
+      self.status = Some(AutomationRunStatus::Running);
+      self.execution_timestamp = Some(now_millis);
+      self.error = None;
+      self.finished_timestamp = None;
+      self.attempts += 1;
+    }
+    Ok(claimed)
+  }
+
+  /// Reserves the run's output actions for this caller, so a run reported
+  /// twice is only delivered once. Returns whether this caller owns them.
+  pub fn claim_delivery(&mut self) -> Result<bool, Error> {
+    let connection = try_get_db_conn()?;
+    let claimed = connection.execute(
+      "UPDATE automation_runs SET action_results = '[]' WHERE id = ?1 AND action_results IS NULL",
+      params![self.id],
+    )? == 1;
+    if claimed {
+      self.action_results = Some(Vec::new());
+    }
+    Ok(claimed)
+  }
+
+  pub fn save_action_results(&self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE automation_runs SET action_results = ?2 WHERE id = ?1",
+      params![self.id, self.action_results_json()?],
+    )?;
+    Ok(())
+  }
+
+  /// Stores how a claimed run ended along with its step and action results
+  /// and the thread and feed item it wrote to.
+  pub fn finish(&mut self, status: AutomationRunStatus, error: Option<String>) -> Result<(), Error> {
+    self.status = Some(status);
+    self.error = error;
+    self.finished_timestamp = Some(chrono::Utc::now().timestamp_millis());
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE automation_runs SET status = ?2, error = ?3, thread_id = ?4, feed_item_id = ?5, step_results = ?6, finished_timestamp = ?7, action_results = ?8 WHERE id = ?1",
+      params![self.id, status.as_str(), self.error, self.thread_id, self.feed_item_id, self.step_results_json()?, self.finished_timestamp, self.action_results_json()?],
     )?;
-    let row = stmt
-      .query_row(params![id], |row| AutomationRun::build_struct_from_row(row))
-      .optional()?;
-    Ok(row)
+    Ok(())
+  }
+
+  /// Puts a failed or skipped run back in line to execute at `now_millis`.
+  /// Returns false when the run is in any other state.
+  pub fn reschedule(&mut self, now_millis: i64) -> Result<bool, Error> {
+    let connection = try_get_db_conn()?;
+    let rescheduled = connection.execute(
+      "UPDATE automation_runs SET status = ?2, schedule_timestamp = ?3, execution_timestamp = NULL,
+      finished_timestamp = NULL, error = NULL, step_results = NULL, action_results = NULL
+      WHERE id = ?1 AND status IN ('failed', 'skipped')",
+      params![self.id, AutomationRunStatus::Scheduled.as_str(), now_millis],
+    )? == 1;
+    if rescheduled {
+      self.status = Some(AutomationRunStatus::Scheduled);
+      self.schedule_timestamp = Some(now_millis);
+      self.execution_timestamp = None;
+      self.finished_timestamp = None;
+      self.error = None;
+      self.step_results = None;
+      self.action_results = None;
+    }
+    Ok(rescheduled)
+  }
+
+  /// Runs cut short by quitting the app would otherwise stay `running`.
+  pub fn fail_interrupted() -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE automation_runs SET status = 'failed', error = 'Interrupted' WHERE status = 'running'",
+      [],
+    )?;
+    Ok(())
+  }
+
+  pub fn find_by_feed_item_id(id: u64) -> Result<Option<AutomationRun>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM automation_runs WHERE feed_item_id = ?1", Self::COLUMNS),
+      params![id],
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::models::token_usage::TokenUsage;
+
+  fn scheduled_run(automation_uuid: &str, schedule_timestamp: i64) -> AutomationRun {
+    AutomationRun {
+      id: None,
+      automation_uuid: automation_uuid.to_string(),
+      user_id: 1,
+      thread_id: None,
+      schedule_timestamp: Some(schedule_timestamp),
+      execution_timestamp: None,
+      run_params: None,
+      feed_item_id: None,
+      status: Some(AutomationRunStatus::Scheduled),
+      error: None,
+      step_results: None,
+      run_key: None,
+      finished_timestamp: None,
+      attempts: 0,
+      action_results: None,
+    }
+  }
+
+  #[test]
+  fn a_run_is_claimed_only_once() {
+    let mut run = scheduled_run("run-test-claim", 1_000);
+    run.create().unwrap();
+    let mut other_sweep = AutomationRun::find_by_id(run.id.unwrap()).unwrap().unwrap();
+
+    assert!(run.claim(2_000).unwrap());
+    assert_eq!(run.status, Some(AutomationRunStatus::Running));
+    assert_eq!(run.attempts, 1);
+    assert!(!other_sweep.claim(3_000).unwrap());
+    assert_eq!(other_sweep.execution_timestamp, None);
+
+    let stored = AutomationRun::find_by_id(run.id.unwrap()).unwrap().unwrap();
+    assert_eq!(stored.execution_timestamp, Some(2_000));
+    assert_eq!(stored.attempts, 1);
+  }
+
+  #[test]
+  fn a_run_is_delivered_only_once() {
+    let mut run = scheduled_run("run-test-delivery", 1_000);
+    run.create().unwrap();
+    let mut reported_again = AutomationRun::find_by_id(run.id.unwrap()).unwrap().unwrap();
+
+    assert!(run.claim_delivery().unwrap());
+    assert!(!reported_again.claim_delivery().unwrap());
+    assert_eq!(reported_again.action_results, None);
+
+    run.action_results = Some(vec![ActionResult {
+      ordering: 0,
+      action_type: "file".to_string(),
+      status: AutomationRunStatus::Succeeded,
+      detail: Some("/tmp/report.md".to_string()),
+      error: None,
+    }]);
+    run.save_action_results().unwrap();
+    let stored = AutomationRun::find_by_id(run.id.unwrap()).unwrap().unwrap();
+    assert_eq!(stored.action_results.unwrap().len(), 1);
+  }
+
+  #[test]
+  fn a_run_key_is_stored_once() {
+    let run_key = AutomationRun::cadence_run_key("run-test-run-key", 1_000);
+    let mut run = scheduled_run("run-test-run-key", 1_000);
+    run.run_key = Some(run_key.clone());
+    let mut repeated = run.clone();
+
+    assert!(run.create_if_new().unwrap());
+    assert!(!repeated.create_if_new().unwrap());
+    assert_eq!(repeated.id, None);
+    let stored = AutomationRun::find_by_run_key(&run_key).unwrap().unwrap();
+    assert_eq!(stored.id, run.id);
+  }
+
+  #[test]
+  fn interrupted_runs_fail_on_startup() {
+    let mut running = scheduled_run("run-test-interrupted", 1_000);
+    running.create().unwrap();
+    assert!(running.claim(2_000).unwrap());
+    let mut finished = scheduled_run("run-test-interrupted", 1_000);
+    finished.create().unwrap();
+    assert!(finished.claim(2_000).unwrap());
+    finished.finish(AutomationRunStatus::Succeeded, None).unwrap();
+
+    AutomationRun::fail_interrupted().unwrap();
+
+    let running = AutomationRun::find_by_id(running.id.unwrap()).unwrap().unwrap();
+    assert_eq!(running.status, Some(AutomationRunStatus::Failed));
+    assert_eq!(running.error.as_deref(), Some("Interrupted"));
+    let finished = AutomationRun::find_by_id(finished.id.unwrap()).unwrap().unwrap();
+    assert_eq!(finished.status, Some(AutomationRunStatus::Succeeded));
+  }
+
+  #[test]
+  fn where_clause_numbers_placeholders_in_filter_order() {
+    let filter = AutomationRunFilter {
+      automation_uuid: Some("abc".to_string()),
+      status: Some(AutomationRunStatus::Failed),
+      since: Some(10),
+      until: Some(20),
+      ..Default::default()
+    };
+    let (sql, values) = filter.where_clause();
+    assert_eq!(
+      sql,
+      "1 = 1 AND automation_uuid = ?1 AND status = ?2 AND schedule_timestamp >= ?3 AND schedule_timestamp < ?4"
+    );
+    assert_eq!(
+      values,
+      vec![
+        rusqlite::types::Value::Text("abc".to_string()),
+        rusqlite::types::Value::Text("failed".to_string()),
+        rusqlite::types::Value::Integer(10),
+        rusqlite::types::Value::Integer(20),
+      ]
+    );
+
+    let (sql, values) = AutomationRunFilter::default().where_clause();
+    assert_eq!(sql, "1 = 1");
+    assert!(values.is_empty());
+  }
+
+  #[test]
+  fn find_page_counts_every_match_and_sums_usage() {
+    let automation_uuid = "run-test-page";
+    let mut ids = Vec::new();
+    for schedule_timestamp in [1_000, 2_000, 3_000] {
+      let mut run = scheduled_run(automation_uuid, schedule_timestamp);
+      run.create().unwrap();
+      ids.push(run.id.unwrap());
+    }
+    for cost_usd in [0.25, 0.5] {
+      let mut usage = TokenUsage {
+        id: None,
+        provider: "test".to_string(),
+        model: "test".to_string(),
+        input_tokens: 100,
+        output_tokens: 10,
+        cost_usd,
+        request_type: "automation".to_string(),
+        timestamp: 3_000,
+        automation_run_id: Some(ids[2]),
+      };
+      usage.create().unwrap();
+    }
+
+    let mut filter = AutomationRunFilter {
+      automation_uuid: Some(automation_uuid.to_string()),
+      limit: 2,
+      ..Default::default()
+    };
+    let (records, total) = AutomationRun::find_page(&filter).unwrap();
+    assert_eq!(total, 3);
+    let page: Vec<u64> = records.iter().map(|record| record.run.id.unwrap()).collect();
+    assert_eq!(page, vec![ids[2], ids[1]]);
+    assert_eq!(records[0].input_tokens, 200);
+    assert_eq!(records[0].output_tokens, 20);
+    assert_eq!(records[0].cost_usd, 0.75);
+    assert_eq!(records[1].input_tokens, 0);
+
+    filter.offset = 2;
+    let (records, total) = AutomationRun::find_page(&filter).unwrap();
+    assert_eq!(total, 3);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].run.id, Some(ids[0]));
+
+    filter.offset = 0;
+    filter.since = Some(2_000);
+    filter.until = Some(3_000);
+    let (records, total) = AutomationRun::find_page(&filter).unwrap();
+    assert_eq!(total, 1);
+    assert_eq!(records[0].run.id, Some(ids[1]));
+  }
+
+  #[test]
+  fn only_failed_or_skipped_runs_can_be_rescheduled() {
+    let mut scheduled = scheduled_run("run-test-reschedule", 1_000);
+    scheduled.create().unwrap();
+    assert!(!scheduled.reschedule(5_000).unwrap());
+
+    let mut succeeded = scheduled_run("run-test-reschedule", 1_000);
+    succeeded.create().unwrap();
+    assert!(succeeded.claim(2_000).unwrap());
+    succeeded.finish(AutomationRunStatus::Succeeded, None).unwrap();
+    assert!(!succeeded.reschedule(5_000).unwrap());
+
+    for status in [AutomationRunStatus::Failed, AutomationRunStatus::Skipped] {
+      let mut run = scheduled_run("run-test-reschedule", 1_000);
+      run.create().unwrap();
+      assert!(run.claim(2_000).unwrap());
+      run.finish(status, Some("boom".to_string())).unwrap();
+      assert!(run.reschedule(5_000).unwrap());
+
+      let mut stored = AutomationRun::find_by_id(run.id.unwrap()).unwrap().unwrap();
+      assert_eq!(stored.status, Some(AutomationRunStatus::Scheduled));
+      assert_eq!(stored.schedule_timestamp, Some(5_000));
+      assert_eq!(stored.error, None);
+      assert!(stored.claim(6_000).unwrap());
+      assert_eq!(stored.attempts, 2);
+    }
   }
 }
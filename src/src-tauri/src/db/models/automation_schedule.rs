@@ -0,0 +1,136 @@
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::error::Error;
+
+/// Where a user's cadence is in its sequence of fire times. Runs are only
+/// created once `next_fire_timestamp` (millis) has passed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutomationSchedule {
+  pub id: Option<u64>,
+  pub cadence_id: u64,
+  pub automation_uuid: String,
+  pub user_id: u64,
+  pub next_fire_timestamp: Option<i64>,
+  pub last_fire_timestamp: Option<i64>,
+}
+
+impl FromRow for AutomationSchedule {
+  const COLUMNS: &'static str =
+    "id, cadence_id, automation_uuid, user_id, next_fire_timestamp, last_fire_timestamp";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(AutomationSchedule {
+      id: Some(row.get(0)?),
+      cadence_id: row.get(1)?,
+      automation_uuid: row.get(2)?,
+      user_id: row.get(3)?,
+      next_fire_timestamp: row.get(4)?,
+      last_fire_timestamp: row.get(5)?,
+    })
+  }
+}
+
+impl AutomationSchedule {
+  pub fn find_by_cadence(cadence_id: u64, user_id: u64) -> Result<Option<AutomationSchedule>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM automation_schedules WHERE cadence_id = ?1 AND user_id = ?2",
+        Self::COLUMNS
+      ),
+      params![cadence_id, user_id],
+    )
+  }
+
+  /// Schedules of active automations that are due, soonest first.
+  pub fn find_due(now_millis: i64, limit: u32) -> Result<Vec<AutomationSchedule>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM automation_schedules
+        WHERE next_fire_timestamp <= ?1
+        AND automation_uuid IN (SELECT uuid FROM automations WHERE is_active = 1)
+        ORDER BY next_fire_timestamp
+        LIMIT ?2",
+        Self::COLUMNS
+      ),
+      params![now_millis, limit],
+    )
+  }
+
+  pub fn create(&mut self) -> Result<(), Error> {
+    if self.id.is_some() {
+      return Err(Error::KSError(
+        "Cannot create AutomationSchedule; AutomationSchedule already exists.".into(),
+      ));
+    }
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO automation_schedules (cadence_id, automation_uuid, user_id, next_fire_timestamp, last_fire_timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
+      (&self.cadence_id, &self.automation_uuid, &self.user_id, &self.next_fire_timestamp, &self.last_fire_timestamp),
+    )?;
+
+    self.id = Some(connection.last_insert_rowid() as u64);
+    Ok(())
+  }
+
+  /// Moves the schedule past `fired_at` unless another sweep already did.
+  /// Returns whether this caller advanced it.
+  pub fn advance(&mut self, fired_at: i64, next_fire_timestamp: Option<i64>) -> Result<bool, Error> {
+    let connection = try_get_db_conn()?;
+    let updated = connection.execute(
+      "UPDATE automation_schedules SET next_fire_timestamp = ?2, last_fire_timestamp = ?3
+      WHERE id = ?1 AND next_fire_timestamp IS ?4",
+      params![self.id, next_fire_timestamp, fired_at, self.next_fire_timestamp],
+    )?;
+    if updated == 0 {
+      return Ok(false);
+    }
+    self.last_fire_timestamp = Some(fired_at);
+    self.next_fire_timestamp = next_fire_timestamp;
+    Ok(true)
+  }
+
+  /// Drops schedules whose cadence was removed or replaced.
+  pub fn delete_orphaned() -> Result<usize, Error> {
+    let connection = try_get_db_conn()?;
+    let deleted = connection.execute(
+      "DELETE FROM automation_schedules WHERE cadence_id NOT IN (SELECT id FROM cadence_triggers)",
+      [],
+    )?;
+    Ok(deleted)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn advance_refuses_a_stale_fire_time() {
+    let mut schedule = AutomationSchedule {
+      id: None,
+      cadence_id: 1,
+      automation_uuid: "schedule-test-advance".to_string(),
+      user_id: 44,
+      next_fire_timestamp: Some(1_000),
+      last_fire_timestamp: None,
+    };
+    schedule.create().unwrap();
+    let mut other_sweep = schedule.clone();
+
+    assert!(schedule.advance(1_000, Some(2_000)).unwrap());
+    assert!(!other_sweep.advance(1_000, Some(2_000)).unwrap());
+    assert_eq!(other_sweep.last_fire_timestamp, None);
+
+    let stored = AutomationSchedule::find_by_cadence(1, 44).unwrap().unwrap();
+    assert_eq!(stored.next_fire_timestamp, Some(2_000));
+    assert_eq!(stored.last_fire_timestamp, Some(1_000));
+  }
+}
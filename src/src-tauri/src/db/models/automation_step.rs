@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
-use rusqlite::{params, OptionalExtension, Result};
+use rusqlite::{params, Result};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -12,67 +13,67 @@ pub struct AutomationStep {
   pub name: String,
   pub ordering: u64,
   pub args_json: Option<String>,
+  /// Expression checked before the step runs; when false the step and every
+  /// later one are skipped. See `automations::guard`.
+  #[serde(default)]
+  pub guard: Option<String>,
 }
 
-impl AutomationStep {
-  pub fn find_by_id(id: u64) -> Result<Option<AutomationStep>> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT * FROM automation_steps WHERE id = ?1")?;
-    let step = stmt.query_row(params![id], |row| {
-      Ok(AutomationStep {
-        id: Some(row.get(0)?),
-        automation_uuid: row.get(1)?,
-        name: row.get(2)?,
-        ordering: row.get(3)?,
-        args_json: row.get(4)?,
-      })
-    }).optional()?;
+impl FromRow for AutomationStep {
+  const COLUMNS: &'static str = "id, automation_uuid, name, ordering, args_json, guard";
 
-    Ok(step)
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(AutomationStep {
+      id: Some(row.get(0)?),
+      automation_uuid: row.get(1)?,
+      name: row.get(2)?,
+      ordering: row.get(3)?,
+      args_json: row.get(4)?,
+      guard: row.get(5)?,
+    })
   }
+}
 
-  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<AutomationStep>> {
-    let connection = get_db_conn();
-    let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-    let query = format!("SELECT * FROM automation_steps WHERE id IN ({})", placeholders);
-    let mut stmt = connection.prepare(&query)?;
+impl AutomationStep {
+  pub fn find_by_id(id: u64) -> Result<Option<AutomationStep>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM automation_steps WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
+  }
 
-    let id_refs: Vec<&u64> = ids.iter().collect();
-    let rows = stmt.query_map(rusqlite::params_from_iter(id_refs), |row| {
-        Ok(AutomationStep {
-            id: Some(row.get(0)?),
-            automation_uuid: row.get(1)?,
-            name: row.get(2)?,
-            ordering: row.get(3)?,
-            args_json: row.get(4)?,
-        })
-    })?;
+  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<AutomationStep>, Error> {
+    let connection = try_get_db_conn()?;
+    let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let query = format!(
+      "SELECT {} FROM automation_steps WHERE id IN ({})",
+      Self::COLUMNS,
+      placeholders
+    );
+    query_rows(&connection, &query, rusqlite::params_from_iter(ids.iter()))
+  }
 
-    let mut steps = Vec::new();
-    for step in rows {
-        steps.push(step?);
-    }
-    Ok(steps)
+  pub fn find_all() -> Result<Vec<AutomationStep>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM automation_steps ORDER BY ordering", Self::COLUMNS),
+      [],
+    )
   }
 
-  pub fn find_all() -> Vec<AutomationStep> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT * FROM automation_steps ORDER BY ordering")
-      .expect("could not prepare query get automation steps");
-    let rows = stmt
-      .query_map([], |row| {
-        Ok(AutomationStep {
-          id: Some(row.get(0)?),
-          automation_uuid: row.get(1)?,
-          name: row.get(2)?,
-          ordering: row.get(3)?,
-          args_json: row.get(4)?,
-        })
-      })
-    .expect("Could not execute query");
-    rows.filter_map(Result::ok).collect()
+  pub fn find_by_automation_uuid(automation_uuid: &str) -> Result<Vec<AutomationStep>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM automation_steps WHERE automation_uuid = ?1 ORDER BY ordering",
+        Self::COLUMNS
+      ),
+      params![automation_uuid],
+    )
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
@@ -80,13 +81,11 @@ impl AutomationStep {
       return Err(Error::KSError(
         "Cannot create AutomationStep; AutomationStep already exists.".into()));
     }
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "INSERT INTO automation_steps (automation_uuid, name, ordering, args_json) VALUES (?1, ?2, ?3, ?4)",
-        (&self.automation_uuid, &self.name, &self.ordering, &self.args_json),
-      )
-      .expect("Could not insert automation step");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO automation_steps (automation_uuid, name, ordering, args_json, guard) VALUES (?1, ?2, ?3, ?4, ?5)",
+      (&self.automation_uuid, &self.name, &self.ordering, &self.args_json, &self.guard),
+    )?;
 
     self.id = Some(connection.last_insert_rowid() as u64);
     Ok(())
@@ -97,13 +96,11 @@ impl AutomationStep {
       return Err(Error::KSError(
         "Cannot update AutomationStep; AutomationStep does not exist.".into()));
     }
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "UPDATE automation_steps SET automation_uuid = ?1, name = ?2, ordering = ?3, args_json = ?4 WHERE id = ?5",
-        (&self.automation_uuid, &self.name, &self.ordering, &self.args_json, &self.id),
-        )
-      .expect("Could not update automation step");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE automation_steps SET automation_uuid = ?1, name = ?2, ordering = ?3, args_json = ?4, guard = ?5 WHERE id = ?6",
+      (&self.automation_uuid, &self.name, &self.ordering, &self.args_json, &self.guard, &self.id),
+    )?;
     Ok(())
   }
 
@@ -112,13 +109,8 @@ impl AutomationStep {
       return Err(Error::KSError(
         "Cannot delete AutomationStep; AutomationStep does not exist.".into()));
     }
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "DELETE FROM automation_steps WHERE id = ?1",
-        [self.id],
-        )
-      .expect("Could not delete automation step");
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM automation_steps WHERE id = ?1", [self.id])?;
     Ok(())
   }
 }
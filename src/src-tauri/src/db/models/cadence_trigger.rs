@@ -1,7 +1,8 @@
-use rusqlite::{params, OptionalExtension, Result};
+use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -12,71 +13,79 @@ pub struct CadenceTrigger {
   pub cadence_type: String,
   pub day_of_week: Option<String>,
   pub time: Option<String>,
+  /// Cron expression or schedule text for `cron` cadences.
+  #[serde(default)]
+  pub cron_expr: Option<String>,
+  /// IANA timezone the cadence fires in; the system timezone when unset.
+  #[serde(default)]
+  pub timezone: Option<String>,
+  /// Minutes between runs of `every_minutes` cadences.
+  #[serde(default)]
+  pub interval_minutes: Option<u32>,
+  /// Day of `monthly` cadences, clamped to the month's last day.
+  #[serde(default)]
+  pub day_of_month: Option<u32>,
 }
 
-impl CadenceTrigger {
-  pub fn find_by_id(id: u64) -> Result<Option<CadenceTrigger>> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare("SELECT * FROM cadence_triggers WHERE id = ?1")?;
-    let cadence = stmt
-      .query_row(params![id], |row| {
-        Ok(CadenceTrigger {
-          id: Some(row.get(0)?),
-          automation_uuid: row.get(1)?,
-          cadence_type: row.get(2)?,
-          day_of_week: row.get(3)?,
-          time: row.get(4)?,
-        })
-      })
-      .optional()?;
+impl FromRow for CadenceTrigger {
+  const COLUMNS: &'static str =
+    "id, automation_uuid, cadence_type, day_of_week, time, cron_expr, timezone, interval_minutes, day_of_month";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(CadenceTrigger {
+      id: Some(row.get(0)?),
+      automation_uuid: row.get(1)?,
+      cadence_type: row.get(2)?,
+      day_of_week: row.get(3)?,
+      time: row.get(4)?,
+      cron_expr: row.get(5)?,
+      timezone: row.get(6)?,
+      interval_minutes: row.get(7)?,
+      day_of_month: row.get(8)?,
+    })
+  }
+}
 
-    Ok(cadence)
+impl CadenceTrigger {
+  pub fn find_by_id(id: u64) -> Result<Option<CadenceTrigger>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM cadence_triggers WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
   }
 
-  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<CadenceTrigger>> {
-    let connection = get_db_conn();
+  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<CadenceTrigger>, Error> {
+    let connection = try_get_db_conn()?;
     let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
     let query = format!(
-      "SELECT * FROM cadence_triggers WHERE id IN ({})",
+      "SELECT {} FROM cadence_triggers WHERE id IN ({})",
+      Self::COLUMNS,
       placeholders
     );
-    let mut stmt = connection.prepare(&query)?;
-
-    let id_refs: Vec<&u64> = ids.iter().collect();
-    let rows = stmt.query_map(rusqlite::params_from_iter(id_refs), |row| {
-      Ok(CadenceTrigger {
-        id: Some(row.get(0)?),
-        automation_uuid: row.get(1)?,
-        cadence_type: row.get(2)?,
-        day_of_week: row.get(3)?,
-        time: row.get(4)?,
-      })
-    })?;
+    query_rows(&connection, &query, rusqlite::params_from_iter(ids.iter()))
+  }
 
-    let mut cadences = Vec::new();
-    for cadence in rows {
-      cadences.push(cadence?);
-    }
-    Ok(cadences)
+  pub fn find_by_automation_uuid(automation_uuid: &str) -> Result<Vec<CadenceTrigger>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM cadence_triggers WHERE automation_uuid = ?1 ORDER BY id",
+        Self::COLUMNS
+      ),
+      params![automation_uuid],
+    )
   }
 
-  pub fn find_all() -> Vec<CadenceTrigger> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT * FROM cadence_triggers")
-      .expect("could not prepare query get automation cadences");
-    let rows = stmt
-      .query_map([], |row| {
-        Ok(CadenceTrigger {
-          id: Some(row.get(0)?),
-          automation_uuid: row.get(1)?,
-          cadence_type: row.get(2)?,
-          day_of_week: row.get(3)?,
-          time: row.get(4)?,
-        })
-      })
-      .expect("Could not execute query");
-    rows.filter_map(Result::ok).collect()
+  pub fn find_all() -> Result<Vec<CadenceTrigger>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM cadence_triggers", Self::COLUMNS),
+      [],
+    )
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
@@ -85,13 +94,11 @@ impl CadenceTrigger {
         "Cannot create CadenceTrigger; CadenceTrigger already exists.".into(),
       ));
     }
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "INSERT INTO cadence_triggers (automation_uuid, cadence_type, day_of_week, time) VALUES (?1, ?2, ?3, ?4)",
-        (&self.automation_uuid, &self.cadence_type, &self.day_of_week, &self.time),
-      )
-      .expect("Could not insert automation cadence");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO cadence_triggers (automation_uuid, cadence_type, day_of_week, time, cron_expr, timezone, interval_minutes, day_of_month) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+      (&self.automation_uuid, &self.cadence_type, &self.day_of_week, &self.time, &self.cron_expr, &self.timezone, &self.interval_minutes, &self.day_of_month),
+    )?;
 
     self.id = Some(connection.last_insert_rowid() as u64);
     Ok(())
@@ -103,13 +110,11 @@ impl CadenceTrigger {
         "Cannot update CadenceTrigger; CadenceTrigger does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "UPDATE cadence_triggers SET automation_id = ?1, cadence_type = ?2, day_of_week = ?3, time = ?4 WHERE id = ?5",
-        (&self.automation_uuid, &self.cadence_type, &self.day_of_week, &self.time, &self.id),
-      )
-      .expect("Could not update automation cadence");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE cadence_triggers SET automation_uuid = ?1, cadence_type = ?2, day_of_week = ?3, time = ?4, cron_expr = ?6, timezone = ?7, interval_minutes = ?8, day_of_month = ?9 WHERE id = ?5",
+      (&self.automation_uuid, &self.cadence_type, &self.day_of_week, &self.time, &self.id, &self.cron_expr, &self.timezone, &self.interval_minutes, &self.day_of_month),
+    )?;
     Ok(())
   }
 
@@ -119,10 +124,8 @@ impl CadenceTrigger {
         "Cannot delete CadenceTrigger; CadenceTrigger does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
-    connection
-      .execute("DELETE FROM cadence_triggers WHERE id = ?1", [self.id])
-      .expect("Could not delete automation cadence");
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM cadence_triggers WHERE id = ?1", [self.id])?;
     Ok(())
   }
 }
@@ -1,8 +1,11 @@
-use rusqlite::{params, OptionalExtension, Result};
+use rusqlite::{params, Result};
+use std::collections::HashSet;
 use serde_json;
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::db::models::user::{account_clause, active_account_clause, User};
 
 use crate::error::Error;
 use crate::utils::log::knap_log_error;
@@ -21,6 +24,8 @@ pub struct CalendarEvent {
   pub google_meet_url: Option<String>,
   pub recurrence_json: Option<String>,
   pub recurrence_id: Option<String>,
+  /// The `users` row of the calendar this event was synced from.
+  pub account_id: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -29,34 +34,10 @@ struct Participant {
     name: String,
 }
 
-// table calendar_events
-impl CalendarEvent {
-  pub fn find_by_id(id: u64) -> Result<Option<CalendarEvent>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare("SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id FROM calendar_events WHERE id = ?1")?;
-    let calendar_event = stmt
-      .query_row(params![id], |row| {
-        Ok(CalendarEvent {
-          id: Some(row.get(0)?),
-          event_id: row.get(1)?,
-          title: row.get(2)?,
-          description: row.get(3)?,
-          creator_email: row.get(4)?,
-          attendees_json: row.get(5)?,
-          location: row.get(6)?,
-          start: row.get(7)?,
-          end: row.get(8)?,
-          google_meet_url: row.get(9)?,
-          recurrence_json: row.get(10)?,
-          recurrence_id: row.get(11)?,
-        })
-      })
-      .optional()?;
+impl FromRow for CalendarEvent {
+  const COLUMNS: &'static str = "id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id, account_id";
 
-    Ok(calendar_event)
-  }
-
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     Ok(CalendarEvent {
       id: Some(row.get(0)?),
       event_id: row.get(1)?,
@@ -70,10 +51,49 @@ impl CalendarEvent {
       google_meet_url: row.get(9)?,
       recurrence_json: row.get(10)?,
       recurrence_id: row.get(11)?,
+      account_id: row.get(12)?,
     })
   }
+}
+
+// table calendar_events
+impl CalendarEvent {
+  pub fn find_by_id(id: u64) -> Result<Option<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM calendar_events WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
+  }
 
-  pub fn get_event_participants_str(event_id: u64) -> Result<(String), Error> {
+  /// `find_by_id` that skips events owned by another account.
+  pub fn find_account_event_by_id(
+    id: u64,
+    account_id: Option<u64>,
+  ) -> Result<Option<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM calendar_events WHERE id = ?1 AND {}",
+        Self::COLUMNS,
+        account_clause("account_id", account_id)
+      ),
+      params![id],
+    )
+  }
+
+  pub fn find_by_event_id(event_id: &str) -> Result<Option<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM calendar_events WHERE event_id = ?1", Self::COLUMNS),
+      [event_id],
+    )
+  }
+
+  pub fn get_event_participants_str(event_id: u64) -> Result<String, Error> {
     let calendar_event = match CalendarEvent::find_by_id(event_id) {
       Ok(Some(calendar_event)) => calendar_event,
       Ok(None) => {
@@ -85,8 +105,8 @@ impl CalendarEvent {
         return Ok("".to_string());
       }
     };
-    let participants = calendar_event.attendees_json.unwrap();
-    let participants_json: Vec<Participant> = serde_json::from_str(&participants).unwrap();
+    let participants = calendar_event.attendees_json.unwrap_or_else(|| "[]".to_string());
+    let participants_json: Vec<Participant> = serde_json::from_str(&participants)?;
     let emails: Vec<String> = participants_json
       .iter()
       .map(|participant| participant.email.clone())
@@ -95,57 +115,45 @@ impl CalendarEvent {
     Ok(format!("Participants: {}", emails.join(", ")))
   }
 
-  pub fn find_by_ids(ids: Vec<String>) -> Result<Vec<CalendarEvent>> {
-    let connection = get_db_conn();
+  pub fn find_by_ids(ids: Vec<String>) -> Result<Vec<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
     let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
     let query = format!(
-      "SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id FROM calendar_events WHERE id IN ({})",
+      "SELECT {} FROM calendar_events WHERE id IN ({})",
+      Self::COLUMNS,
       placeholders
     );
-    let mut stmt = connection.prepare(&query)?;
-
-    let id_refs: Vec<&str> = ids.iter().map(AsRef::as_ref).collect();
-    let rows = stmt.query_map(rusqlite::params_from_iter(id_refs), |row| {
-      CalendarEvent::build_struct_from_row(row)
-    })?;
-
-    let mut events = Vec::new();
-    for event in rows {
-      events.push(event?);
-    }
-    Ok(events)
+    query_rows(&connection, &query, rusqlite::params_from_iter(ids.iter()))
   }
 
-  pub fn find_all() -> Vec<CalendarEvent> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id FROM calendar_events ORDER BY start")
-      .expect("could not prepare query get calendar events");
-    let rows = stmt
-      .query_map([], |row| CalendarEvent::build_struct_from_row(row))
-      .expect("Could not execute query");
-    rows.filter_map(Result::ok).collect()
+  pub fn find_all() -> Result<Vec<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM calendar_events ORDER BY start", Self::COLUMNS),
+      [],
+    )
   }
 
-  pub fn count() -> Result<u64> {
-    let connection = get_db_conn();
+  pub fn count() -> Result<u64, Error> {
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("SELECT count(*) FROM calendar_events")?;
     let count = stmt.query_row(params![], |row| Ok(row.get::<_, u64>(0)?))?;
 
     Ok(count)
   }
 
-  pub fn find_by_timestamp_range(start: u64, end: u64) -> Vec<CalendarEvent> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id FROM calendar_events WHERE start >= ?1 AND end <= ?2 ORDER BY start")
-      .expect("could not prepare query get calendar events");
-    let rows = stmt
-      .query_map([start, end], |row| {
-        CalendarEvent::build_struct_from_row(row)
-      })
-      .expect("Could not execute query");
-    rows.filter_map(Result::ok).collect()
+  pub fn find_by_timestamp_range(start: u64, end: u64) -> Result<Vec<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM calendar_events WHERE start >= ?1 AND end <= ?2 AND {} ORDER BY start",
+        Self::COLUMNS,
+        active_account_clause("account_id")
+      ),
+      [start, end],
+    )
   }
 
   pub fn find_by_run_params(run_params: Option<String>) -> Result<Option<CalendarEvent>, Error> {
@@ -166,11 +174,11 @@ impl CalendarEvent {
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let result = connection
       .execute(
-        "INSERT INTO calendar_events (id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12) ON CONFLICT(event_id) DO UPDATE SET title = ?3, description = ?4, creator_email = ?5, attendees_json = ?6, location = ?7, start = ?8, end = ?9, google_meet_url = ?10",
-        (&self.id, &self.event_id, &self.title, &self.description, &self.creator_email, &self.attendees_json, &self.location, &self.start, &self.end, &self.google_meet_url, &self.recurrence_json, &self.recurrence_id),
+        "INSERT INTO calendar_events (id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id, account_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13) ON CONFLICT(event_id) DO UPDATE SET title = ?3, description = ?4, creator_email = ?5, attendees_json = ?6, location = ?7, start = ?8, end = ?9, google_meet_url = ?10, account_id = COALESCE(account_id, ?13)",
+        (&self.id, &self.event_id, &self.title, &self.description, &self.creator_email, &self.attendees_json, &self.location, &self.start, &self.end, &self.google_meet_url, &self.recurrence_json, &self.recurrence_id, &self.account_id),
       )
       .map_err(|e| e.into());
     match result {
@@ -180,21 +188,17 @@ impl CalendarEvent {
   }
 
   pub fn update(&self) -> Result<(), Error> {
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "UPDATE calendar_events SET event_id = ?2, title = ?3, description = ?4, creator_email = ?5, attendees_json = ?6, location = ?7, start = ?8, end = ?9, google_meet_url = ?10, recurrence_json =11?, recurrence_id =12? WHERE id = ?1",
-        (&self.id, &self.event_id, &self.title, &self.description, &self.creator_email, &self.attendees_json, &self.location, &self.start, &self.end, &self.google_meet_url, &self.recurrence_json, &self.recurrence_id),
-        )
-      .expect("Could not update calendar event");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE calendar_events SET event_id = ?2, title = ?3, description = ?4, creator_email = ?5, attendees_json = ?6, location = ?7, start = ?8, end = ?9, google_meet_url = ?10, recurrence_json = ?11, recurrence_id = ?12 WHERE id = ?1",
+      (&self.id, &self.event_id, &self.title, &self.description, &self.creator_email, &self.attendees_json, &self.location, &self.start, &self.end, &self.google_meet_url, &self.recurrence_json, &self.recurrence_id),
+    )?;
     Ok(())
   }
 
   pub fn delete(&self) -> Result<(), Error> {
-    let connection = get_db_conn();
-    connection
-      .execute("DELETE FROM calendar_events WHERE id = ?1", [&self.id])
-      .expect("Could not delete calendar event");
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM calendar_events WHERE id = ?1", [&self.id])?;
     Ok(())
   }
 
@@ -202,9 +206,13 @@ impl CalendarEvent {
     from_timestamp: i64,
     to_timestamp: i64,
   ) -> Result<Vec<CalendarEvent>, Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
-      .prepare("SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id FROM calendar_events WHERE start >= ?1 and start <= ?2  ORDER BY start ASC")
+      .prepare(&format!(
+        "SELECT {} FROM calendar_events WHERE start >= ?1 and start <= ?2 AND {} ORDER BY start ASC",
+        Self::COLUMNS,
+        active_account_clause("account_id")
+      ))
       .map_err(|error| {
         log::error!("Failed to prepare calendar events query: {:?}", error);
         Error::KSError(format!(
@@ -215,7 +223,7 @@ impl CalendarEvent {
 
     let rows = stmt
       .query_map([&from_timestamp, &to_timestamp], |row| {
-        CalendarEvent::build_struct_from_row(row)
+        CalendarEvent::from_row(row)
       })?;
     let mut calendar_events = Vec::new();
     for calendar_event_result in rows {
@@ -235,65 +243,48 @@ impl CalendarEvent {
     Ok(calendar_events)
   }
 
-  pub fn get_recent_calendar_events(limit: usize) -> Vec<CalendarEvent> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id FROM calendar_events WHERE start > strftime('%s', 'now') ORDER BY start ASC LIMIT ?1")
-      .expect("could not prepare query emails");
-    let rows = stmt
-      .query_map([limit], |row| {
-        Ok((
-          row.get::<_, u64>(0),
-          row.get::<_, String>(1),
-          row.get::<_, Option<String>>(2),
-          row.get::<_, Option<String>>(3),
-          row.get::<_, Option<String>>(4),
-          row.get::<_, Option<String>>(5),
-          row.get::<_, Option<String>>(6),
-          row.get::<_, Option<i64>>(7),
-          row.get::<_, Option<i64>>(8),
-          row.get::<_, Option<String>>(9),
-          row.get::<_, Option<String>>(10),
-          row.get::<_, Option<String>>(11),
-        ))
-      })
-      .expect("could not execute query");
-    let mut calendar_events = Vec::new();
-    for row in rows {
-      let (
-        id,
-        event_id,
-        title,
-        description,
-        creator_email,
-        attendees_json,
-        location,
-        start,
-        end,
-        google_meet_url,
-        recurrence_json,
-        recurrence_id,
-      ) = row.unwrap();
-      calendar_events.push(CalendarEvent {
-        id: Some(id.unwrap()),
-        event_id: event_id.unwrap(),
-        title: title.unwrap(),
-        description: description.unwrap(),
-        creator_email: creator_email.unwrap(),
-        attendees_json: attendees_json.unwrap(),
-        location: location.unwrap(),
-        start: start.unwrap(),
-        end: end.unwrap(),
-        google_meet_url: google_meet_url.unwrap(),
-        recurrence_json: recurrence_json.unwrap(),
-        recurrence_id: recurrence_id.unwrap(),
-      });
-    }
-    return calendar_events;
+  pub fn get_recent_calendar_events(limit: usize) -> Result<Vec<CalendarEvent>, Error> {
+    CalendarEvent::get_recent_account_calendar_events(User::active_account_id(), limit)
+  }
+
+  /// `get_recent_calendar_events` for a given account rather than the active
+  /// one, for work that runs in the background.
+  pub fn get_recent_account_calendar_events(
+    account_id: Option<u64>,
+    limit: usize,
+  ) -> Result<Vec<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM calendar_events WHERE start > strftime('%s', 'now') AND {} ORDER BY start ASC LIMIT ?1",
+        Self::COLUMNS,
+        account_clause("account_id", account_id)
+      ),
+      [limit],
+    )
+  }
+
+  /// Provider ids of the events stored for `account_id`, including unowned
+  /// ones the account's next sync will claim.
+  pub fn find_event_ids_by_account(account_id: u64) -> Result<HashSet<String>, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "SELECT event_id FROM calendar_events WHERE account_id = ?1 OR account_id IS NULL",
+    )?;
+    let event_ids = stmt
+      .query_map([account_id], |row| row.get::<_, String>(0))?
+      .collect::<Result<HashSet<_>, _>>()?;
+    Ok(event_ids)
   }
 
-  pub fn delete_calendar_events_removed(calendar_events: Vec<String>) -> Result<(), Error> {
-    let connection = get_db_conn();
+  /// Deletes events of `account_id` missing from `calendar_events`, the ids
+  /// just fetched from that account's calendar. Returns how many were deleted.
+  pub fn delete_calendar_events_removed(
+    calendar_events: Vec<String>,
+    account_id: u64,
+  ) -> Result<usize, Error> {
+    let connection = try_get_db_conn()?;
 
     let id_list = calendar_events
       .iter()
@@ -301,123 +292,27 @@ impl CalendarEvent {
       .collect::<Vec<String>>()
       .join(",");
     let query = format!(
-      "SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id
+      "SELECT {}
          FROM calendar_events
-         WHERE event_id NOT IN ({})",
+         WHERE event_id NOT IN ({}) AND (account_id = ?1 OR (account_id IS NULL AND ?2))",
+      Self::COLUMNS,
       id_list
     );
-    let mut stmt = connection
-      .prepare(&query)
-      .expect("could not prepare query for filtered calendar events");
-
-    let rows = stmt
-      .query_map([], |row| {
-        Ok((
-          row.get::<_, u64>(0),
-          row.get::<_, String>(1),
-          row.get::<_, Option<String>>(2),
-          row.get::<_, Option<String>>(3),
-          row.get::<_, Option<String>>(4),
-          row.get::<_, Option<String>>(5),
-          row.get::<_, Option<String>>(6),
-          row.get::<_, Option<i64>>(7),
-          row.get::<_, Option<i64>>(8),
-          row.get::<_, Option<String>>(9),
-          row.get::<_, Option<String>>(10),
-          row.get::<_, Option<String>>(11),
-        ))
-      })
-      .expect("could not execute query");
-
-    for row in rows {
-      let (
-        id,
-        event_id,
-        title,
-        description,
-        creator_email,
-        attendees_json,
-        location,
-        start,
-        end,
-        google_meet_url,
-        recurrence_json,
-        recurrence_id,
-      ) = row.unwrap();
-      let event = CalendarEvent {
-        id: Some(id.unwrap()),
-        event_id: event_id.unwrap(),
-        title: title.unwrap(),
-        description: description.unwrap(),
-        creator_email: creator_email.unwrap(),
-        attendees_json: attendees_json.unwrap(),
-        location: location.unwrap(),
-        start: start.unwrap(),
-        end: end.unwrap(),
-        google_meet_url: google_meet_url.unwrap(),
-        recurrence_json: recurrence_json.unwrap(),
-        recurrence_id: recurrence_id.unwrap(),
-      };
-      event.delete()?;
+    let includes_unowned = User::primary_account_id()? == Some(account_id);
+    let removed: Vec<CalendarEvent> =
+      query_rows(&connection, &query, params![account_id, includes_unowned])?;
+    for calendar_event in removed.iter() {
+      calendar_event.delete()?;
     }
-    Ok(())
+    Ok(removed.len())
   }
 
-  pub fn get_calendar_event_by_recurrence_id(recurrence_id: String) -> Vec<CalendarEvent> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, event_id, title, description, creator_email, attendees_json, location, start, end, google_meet_url, recurrence_json, recurrence_id FROM calendar_events WHERE recurrence_id = ?1")
-      .expect("could not prepare query emails");
-    let rows = stmt
-      .query_map([recurrence_id], |row| {
-        Ok((
-          row.get::<_, u64>(0),
-          row.get::<_, String>(1),
-          row.get::<_, Option<String>>(2),
-          row.get::<_, Option<String>>(3),
-          row.get::<_, Option<String>>(4),
-          row.get::<_, Option<String>>(5),
-          row.get::<_, Option<String>>(6),
-          row.get::<_, Option<i64>>(7),
-          row.get::<_, Option<i64>>(8),
-          row.get::<_, Option<String>>(9),
-          row.get::<_, Option<String>>(10),
-          row.get::<_, Option<String>>(11),
-        ))
-      })
-      .expect("could not execute query");
-
-    let mut calendar_events = Vec::new();
-    for row in rows {
-      let (
-        id,
-        event_id,
-        title,
-        description,
-        creator_email,
-        attendees_json,
-        location,
-        start,
-        end,
-        google_meet_url,
-        recurrence_json,
-        recurrence_id,
-      ) = row.unwrap();
-      calendar_events.push(CalendarEvent {
-        id: Some(id.unwrap()),
-        event_id: event_id.unwrap(),
-        title: title.unwrap(),
-        description: description.unwrap(),
-        creator_email: creator_email.unwrap(),
-        attendees_json: attendees_json.unwrap(),
-        location: location.unwrap(),
-        start: start.unwrap(),
-        end: end.unwrap(),
-        google_meet_url: google_meet_url.unwrap(),
-        recurrence_json: recurrence_json.unwrap(),
-        recurrence_id: recurrence_id.unwrap(),
-      });
-    }
-    return calendar_events;
+  pub fn get_calendar_event_by_recurrence_id(recurrence_id: String) -> Result<Vec<CalendarEvent>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM calendar_events WHERE recurrence_id = ?1", Self::COLUMNS),
+      [recurrence_id],
+    )
   }
 }
@@ -1,7 +1,8 @@
 use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -11,20 +12,30 @@ pub struct Connection {
   pub provider: String,
 }
 
-impl Connection {
-  pub fn find_by_scope(scope: String) -> Result<Connection> {
-    let connection = get_db_conn();
-    let mut stmt =
-      connection.prepare("SELECT id, scope, provider FROM connections WHERE scope = ?1")?;
-    let connection_instance = stmt.query_row(params![scope], |row| {
-      Ok(Connection {
-        id: row.get(0)?,
-        scope: row.get(1)?,
-        provider: row.get(2)?,
-      })
-    })?;
+impl FromRow for Connection {
+  const COLUMNS: &'static str = "connections.id, connections.scope, connections.provider";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(Connection {
+      id: row.get(0)?,
+      scope: row.get(1)?,
+      provider: row.get(2)?,
+    })
+  }
+}
 
-    Ok(connection_instance)
+impl Connection {
+  pub fn find_by_scope(scope: String) -> Result<Connection, Error> {
+    let connection = try_get_db_conn()?;
+    let connection_instance: Option<Connection> = query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM connections WHERE scope = ?1",
+        Connection::COLUMNS
+      ),
+      params![scope],
+    )?;
+    Ok(connection_instance.ok_or(rusqlite::Error::QueryReturnedNoRows)?)
   }
 
   pub fn create(self) -> Result<(), Error> {
@@ -33,7 +44,7 @@ impl Connection {
         "Cannot create Connection; Connection already exists.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     connection.execute(
       "INSERT OR IGNORE INTO connections (scope, provider) VALUES (?1, ?2)",
       (&self.scope, &self.provider),
@@ -42,35 +53,18 @@ impl Connection {
   }
 
   pub fn find_connections_from_user_email(email: String) -> Result<Vec<Self>, Error> {
-    let mut connections: Vec<Connection> = vec![];
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT
-        connections.id,
-        connections.scope,
-        connections.provider,
-        user_connections.connection_id,
-        users.email
-      FROM connections
-      LEFT JOIN user_connections on connection_id = connections.id
-      LEFT JOIN users on user_id = users.id
-      WHERE users.email = ?1",
-    )?;
-    let rows = stmt.query_map(params![email], |row| {
-      Ok((
-        row.get::<_, u64>(0)?,
-        row.get::<_, String>(1)?,
-        row.get::<_, String>(2)?,
-      ))
-    })?;
-    for row in rows {
-      let (connection_id, scope, provider) = row.unwrap();
-      connections.push(Connection {
-        id: Some(connection_id),
-        scope,
-        provider,
-      })
-    }
-    Ok(connections)
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {}
+        FROM connections
+        LEFT JOIN user_connections on connection_id = connections.id
+        LEFT JOIN users on user_id = users.id
+        WHERE users.email = ?1",
+        Connection::COLUMNS
+      ),
+      params![email],
+    )
   }
 }
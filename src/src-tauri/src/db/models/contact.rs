@@ -0,0 +1,316 @@
+use rusqlite::{params, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::error::Error;
+use crate::memory::search_filter::contains_pattern;
+
+/// A person, merged across every address and display name they have used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contact {
+  pub id: Option<u64>,
+  pub display_name: Option<String>,
+  pub organization: Option<String>,
+  pub interaction_count: u64,
+  pub last_contacted: Option<i64>,
+  pub emails: Vec<String>,
+  pub aliases: Vec<String>,
+}
+
+/// An edge from a contact to something they appear in, e.g. an email they
+/// sent (`emails`, `sender`) or a meeting they attended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactLink {
+  pub id: Option<u64>,
+  pub contact_id: u64,
+  pub foreign_table: String,
+  pub foreign_table_id: u64,
+  pub role: String,
+  pub occurred_at: Option<i64>,
+}
+
+impl FromRow for Contact {
+  const COLUMNS: &'static str =
+    "c.id, c.display_name, c.organization, c.interaction_count, c.last_contacted";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(Contact {
+      id: Some(row.get(0)?),
+      display_name: row.get(1)?,
+      organization: row.get(2)?,
+      interaction_count: row.get(3)?,
+      last_contacted: row.get(4)?,
+      emails: Vec::new(),
+      aliases: Vec::new(),
+    })
+  }
+}
+
+impl FromRow for ContactLink {
+  const COLUMNS: &'static str = "id, contact_id, foreign_table, foreign_table_id, role, occurred_at";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(ContactLink {
+      id: Some(row.get(0)?),
+      contact_id: row.get(1)?,
+      foreign_table: row.get(2)?,
+      foreign_table_id: row.get(3)?,
+      role: row.get(4)?,
+      occurred_at: row.get(5)?,
+    })
+  }
+}
+
+impl Contact {
+  fn load_details(mut self) -> Result<Self, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt =
+      connection.prepare("SELECT email FROM contact_emails WHERE contact_id = ?1 ORDER BY id")?;
+    self.emails = stmt
+      .query_map(params![self.id], |row| row.get(0))?
+      .collect::<Result<Vec<String>, _>>()?;
+    let mut stmt =
+      connection.prepare("SELECT alias FROM contact_aliases WHERE contact_id = ?1 ORDER BY id")?;
+    self.aliases = stmt
+      .query_map(params![self.id], |row| row.get(0))?
+      .collect::<Result<Vec<String>, _>>()?;
+    Ok(self)
+  }
+
+  pub fn find_by_id(id: u64) -> Result<Option<Contact>, Error> {
+    let connection = try_get_db_conn()?;
+    let contact: Option<Contact> = query_optional(
+      &connection,
+      &format!("SELECT {} FROM contacts c WHERE c.id = ?1", Contact::COLUMNS),
+      params![id],
+    )?;
+    match contact {
+      Some(contact) => Ok(Some(contact.load_details()?)),
+      None => Ok(None),
+    }
+  }
+
+  pub fn find_by_email(email: &str) -> Result<Option<Contact>, Error> {
+    let connection = try_get_db_conn()?;
+    let contact: Option<Contact> = query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM contacts c JOIN contact_emails ce ON ce.contact_id = c.id WHERE ce.email = ?1",
+        Contact::COLUMNS
+      ),
+      params![email.to_lowercase()],
+    )?;
+    match contact {
+      Some(contact) => Ok(Some(contact.load_details()?)),
+      None => Ok(None),
+    }
+  }
+
+  /// Most recently contacted people first. `query` matches names, aliases
+  /// and addresses; `organization` matches exactly.
+  pub fn search(
+    query: Option<&str>,
+    organization: Option<&str>,
+    limit: usize,
+  ) -> Result<Vec<Contact>, Error> {
+    let connection = try_get_db_conn()?;
+    let pattern = query.map(|query| contains_pattern(query.trim()));
+    let sql = format!(
+      "SELECT {} FROM contacts c
+       WHERE (?1 IS NULL
+         OR c.display_name LIKE ?1 ESCAPE '\\'
+         OR EXISTS (SELECT 1 FROM contact_aliases ca
+           WHERE ca.contact_id = c.id AND ca.alias LIKE ?1 ESCAPE '\\')
+         OR EXISTS (SELECT 1 FROM contact_emails ce
+           WHERE ce.contact_id = c.id AND ce.email LIKE ?1 ESCAPE '\\'))
+       AND (?2 IS NULL OR c.organization = ?2)
+       ORDER BY c.last_contacted DESC NULLS LAST, c.interaction_count DESC
+       LIMIT ?3",
+      Contact::COLUMNS
+    );
+    let rows: Vec<Contact> =
+      query_rows(&connection, &sql, params![pattern, organization, limit as i64])?;
+    rows.into_iter().map(Contact::load_details).collect()
+  }
+
+  /// Returns the contact owning `email`, creating it if needed. New names
+  /// are recorded as aliases; the first one becomes the display name.
+  pub fn find_or_create(
+    email: &str,
+    name: Option<&str>,
+    organization: Option<&str>,
+  ) -> Result<u64, Error> {
+    let email = email.to_lowercase();
+    let connection = try_get_db_conn()?;
+    let existing: Option<u64> = connection
+      .query_row(
+        "SELECT contact_id FROM contact_emails WHERE email = ?1",
+        params![email],
+        |row| row.get(0),
+      )
+      .optional()?;
+
+    let contact_id = match existing {
+      Some(contact_id) => contact_id,
+      None => {
+        connection.execute(
+          "INSERT INTO contacts (display_name, organization) VALUES (?1, ?2)",
+          params![name, organization],
+        )?;
+        let contact_id = connection.last_insert_rowid() as u64;
+        connection.execute(
+          "INSERT INTO contact_emails (contact_id, email) VALUES (?1, ?2)",
+          params![contact_id, email],
+        )?;
+        contact_id
+      }
+    };
+
+    if let Some(name) = name {
+      connection.execute(
+        "INSERT OR IGNORE INTO contact_aliases (contact_id, alias) VALUES (?1, ?2)",
+        params![contact_id, name],
+      )?;
+      connection.execute(
+        "UPDATE contacts SET display_name = ?2 WHERE id = ?1 AND display_name IS NULL",
+        params![contact_id, name],
+      )?;
+    }
+    if organization.is_some() {
+      connection.execute(
+        "UPDATE contacts SET organization = ?2 WHERE id = ?1 AND organization IS NULL",
+        params![contact_id, organization],
+      )?;
+    }
+    Ok(contact_id)
+  }
+
+  /// Records that the contact appears in a row, once per role, and refreshes
+  /// the contact's interaction stats. Being `mentioned` in a file is not an
+  /// interaction.
+  pub fn add_link(
+    contact_id: u64,
+    foreign_table: &str,
+    foreign_table_id: u64,
+    role: &str,
+    occurred_at: Option<i64>,
+  ) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO contact_links (contact_id, foreign_table, foreign_table_id, role, occurred_at)
+       VALUES (?1, ?2, ?3, ?4, ?5)
+       ON CONFLICT(contact_id, foreign_table, foreign_table_id, role) DO UPDATE SET occurred_at = ?5",
+      params![contact_id, foreign_table, foreign_table_id, role, occurred_at],
+    )?;
+    Contact::refresh_stats(contact_id)
+  }
+
+  fn refresh_stats(contact_id: u64) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE contacts SET
+         interaction_count = (
+           SELECT COUNT(*) FROM (
+             SELECT DISTINCT foreign_table, foreign_table_id FROM contact_links
+             WHERE contact_id = ?1 AND role != 'mentioned'
+           )
+         ),
+         last_contacted = (
+           SELECT MAX(occurred_at) FROM contact_links WHERE contact_id = ?1 AND role != 'mentioned'
+         ),
+         updated_timestamp = strftime('%s','now')
+       WHERE id = ?1",
+      params![contact_id],
+    )?;
+    Ok(())
+  }
+
+  pub fn links(contact_id: u64, limit: usize) -> Result<Vec<ContactLink>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM contact_links WHERE contact_id = ?1
+         ORDER BY occurred_at DESC NULLS LAST
+         LIMIT ?2",
+        ContactLink::COLUMNS
+      ),
+      params![contact_id, limit as i64],
+    )
+  }
+
+  /// Contact ids linked to a row, e.g. everyone on an email.
+  pub fn ids_linked_to(foreign_table: &str, foreign_table_id: u64) -> Result<Vec<u64>, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "SELECT DISTINCT contact_id FROM contact_links WHERE foreign_table = ?1 AND foreign_table_id = ?2",
+    )?;
+    let ids = stmt
+      .query_map(params![foreign_table, foreign_table_id], |row| row.get(0))?
+      .collect::<Result<Vec<u64>, _>>()?;
+    Ok(ids)
+  }
+
+  /// Folds `other_id` into `contact_id`: addresses, aliases and links move
+  /// over and the other contact is removed. Fails with `QueryReturnedNoRows`
+  /// when either contact does not exist.
+  pub fn merge(contact_id: u64, other_id: u64) -> Result<(), Error> {
+    if contact_id == other_id {
+      return Err(Error::KSError("Cannot merge a contact into itself".to_string()));
+    }
+    let mut connection = try_get_db_conn()?;
+    let tx = connection.transaction()?;
+    let found: u64 = tx.query_row(
+      "SELECT COUNT(*) FROM contacts WHERE id IN (?1, ?2)",
+      params![contact_id, other_id],
+      |row| row.get(0),
+    )?;
+    if found != 2 {
+      return Err(rusqlite::Error::QueryReturnedNoRows.into());
+    }
+    tx.execute(
+      "UPDATE contact_emails SET contact_id = ?1 WHERE contact_id = ?2",
+      params![contact_id, other_id],
+    )?;
+    tx.execute(
+      "INSERT OR IGNORE INTO contact_aliases (contact_id, alias)
+       SELECT ?1, alias FROM contact_aliases WHERE contact_id = ?2",
+      params![contact_id, other_id],
+    )?;
+    tx.execute(
+      "INSERT OR IGNORE INTO contact_links (contact_id, foreign_table, foreign_table_id, role, occurred_at)
+       SELECT ?1, foreign_table, foreign_table_id, role, occurred_at FROM contact_links WHERE contact_id = ?2",
+      params![contact_id, other_id],
+    )?;
+    tx.execute(
+      "UPDATE contacts SET
+         display_name = COALESCE(display_name, (SELECT display_name FROM contacts WHERE id = ?2)),
+         organization = COALESCE(organization, (SELECT organization FROM contacts WHERE id = ?2))
+       WHERE id = ?1",
+      params![contact_id, other_id],
+    )?;
+    tx.execute("DELETE FROM contact_aliases WHERE contact_id = ?1", params![other_id])?;
+    tx.execute("DELETE FROM contact_links WHERE contact_id = ?1", params![other_id])?;
+    tx.execute("DELETE FROM contacts WHERE id = ?1", params![other_id])?;
+    tx.commit()?;
+    Contact::refresh_stats(contact_id)
+  }
+
+  pub fn count() -> Result<u64, Error> {
+    let connection = try_get_db_conn()?;
+    let count = connection.query_row("SELECT COUNT(*) FROM contacts", [], |row| row.get(0))?;
+    Ok(count)
+  }
+
+  pub fn delete_all() -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM contact_links", [])?;
+    connection.execute("DELETE FROM contact_aliases", [])?;
+    connection.execute("DELETE FROM contact_emails", [])?;
+    connection.execute("DELETE FROM contacts", [])?;
+    Ok(())
+  }
+}
@@ -1,7 +1,8 @@
-use rusqlite::Result;
+use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_rows, FromRow};
 use crate::error::Error;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -11,17 +12,61 @@ pub struct DataSourceTrigger {
   pub automation_uuid: String,
   pub data_source: String,
   pub offset_minutes: i64,
+  /// JSON filter narrowing which emails, files or meetings fire the trigger.
+  #[serde(default)]
+  pub filter_json: Option<String>,
+  /// Unix seconds the trigger was saved; earlier emails do not fire it.
+  #[serde(default)]
+  pub created_timestamp: Option<i64>,
+  /// Unix seconds of the last watched-folder scan of `local_file` triggers.
+  #[serde(default)]
+  pub cursor: Option<i64>,
 }
 
-impl DataSourceTrigger {
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<DataSourceTrigger> {
+impl FromRow for DataSourceTrigger {
+  const COLUMNS: &'static str =
+    "id, automation_uuid, data_source, offset_minutes, filter_json, created_timestamp, cursor";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     Ok(DataSourceTrigger {
       id: Some(row.get(0)?),
       automation_uuid: row.get(1)?,
       data_source: row.get(2)?,
-      offset_minutes: row.get(3)?,
+      offset_minutes: row.get::<_, Option<i64>>(3)?.unwrap_or_default(),
+      filter_json: row.get(4)?,
+      created_timestamp: row.get(5)?,
+      cursor: row.get(6)?,
     })
   }
+}
+
+impl DataSourceTrigger {
+  pub fn find_by_automation_uuid(automation_uuid: &str) -> Result<Vec<DataSourceTrigger>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM data_source_trigger WHERE automation_uuid = ?1 ORDER BY id",
+        Self::COLUMNS
+      ),
+      params![automation_uuid],
+    )
+  }
+
+  /// Triggers of active automations listening to `data_source`.
+  pub fn find_active_by_data_source(data_source: &str) -> Result<Vec<DataSourceTrigger>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM data_source_trigger
+        WHERE data_source = ?1
+        AND automation_uuid IN (SELECT uuid FROM automations WHERE is_active = 1)",
+        Self::COLUMNS
+      ),
+      params![data_source],
+    )
+  }
 
   pub fn create(&mut self) -> Result<(), Error> {
     if self.id.is_some() {
@@ -29,17 +74,25 @@ impl DataSourceTrigger {
         "Cannot create DataSourceTrigger; DataSourceTrigger already exists.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
-    connection
-      .execute(
-        "INSERT INTO data_source_trigger (automation_uuid, data_source, offset_minutes) VALUES (?1, ?2, ?3)",
-        (&self.automation_uuid, &self.data_source, &self.offset_minutes),
-      )
-      .expect("Could not insert automation data_source trigger");
+    connection.execute(
+      "INSERT INTO data_source_trigger (automation_uuid, data_source, offset_minutes, filter_json, cursor) VALUES (?1, ?2, ?3, ?4, ?5)",
+      (&self.automation_uuid, &self.data_source, &self.offset_minutes, &self.filter_json, &self.cursor),
+    )?;
 
     self.id = Some(connection.last_insert_rowid() as u64);
 
     Ok(())
   }
+
+  pub fn update_cursor(&mut self, cursor: i64) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE data_source_trigger SET cursor = ?2 WHERE id = ?1",
+      params![self.id, cursor],
+    )?;
+    self.cursor = Some(cursor);
+    Ok(())
+  }
 }
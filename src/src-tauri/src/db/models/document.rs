@@ -3,11 +3,13 @@ use serde::Serialize;
 use serde_json::Value;
 
 use crate::connections::data_source::{KnowledgeSnippet, KnowledgeSource};
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
 use crate::db::models::local_file::LocalFile;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 
 use crate::error::Error;
 use crate::local_fs;
+use crate::memory::search_filter::{contains_pattern, SearchFilter, SQL_DOCUMENT_JOINS};
 
 use super::drive_document::DriveDocument;
 use super::email::Email;
@@ -21,15 +23,29 @@ pub struct Document {
   pub timestamp: Option<u64>,
 }
 
+impl FromRow for Document {
+  const COLUMNS: &'static str = "id, foreign_table, foreign_table_id, timestamp, hash";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(Document {
+      id: row.get(0)?,
+      foreign_table: row.get(1)?,
+      foreign_table_id: row.get(2)?,
+      timestamp: row.get(3)?,
+      hash: row.get(4)?,
+    })
+  }
+}
+
 impl Document {
-  pub fn delete(&self) -> Result<()> {
-    let connection = get_db_conn();
+  pub fn delete(&self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
     connection.execute("DELETE FROM documents WHERE id = ?1", params![self.id])?;
     Ok(())
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     if self.id.is_none() {
       connection.execute(
         "INSERT INTO documents (foreign_table, foreign_table_id, hash) VALUES (?1, ?2, ?3)",
@@ -46,73 +62,185 @@ impl Document {
     Ok(())
   }
 
-  pub fn find_by_id(id: u64) -> Result<Option<Document>> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare("SELECT * FROM documents WHERE id = ?1")?;
-    let mut rows = stmt.query_map(params![id], |row| {
-      Ok(Document {
-        id: row.get(0)?,
-        foreign_table: row.get(1)?,
-        foreign_table_id: row.get(2)?,
-        timestamp: row.get(3)?,
-        hash: row.get(4)?,
-      })
-    })?;
+  pub fn find_by_id(id: u64) -> Result<Option<Document>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM documents WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
+  }
 
-    if let Some(row) = rows.next() {
-      return Ok(Some(row?));
+  pub fn find_by_ids(ids: &Vec<u64>) -> Result<Vec<Document>, Error> {
+    if ids.is_empty() {
+      return Ok(Vec::new());
     }
-    Ok(None)
+    let connection = try_get_db_conn()?;
+    let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM documents WHERE id IN ({})",
+        Self::COLUMNS,
+        placeholders
+      ),
+      rusqlite::params_from_iter(ids.iter()),
+    )
   }
 
-  pub fn find_by_ids(ids: &Vec<u64>) -> Result<Vec<Document>> {
-    let connection = get_db_conn();
+  /// Documents whose source row is gone, e.g. an email removed by sync or a
+  /// deleted meeting. Meeting notes are keyed by their thread.
+  pub fn find_orphaned() -> Result<Vec<Document>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      "SELECT d.id, d.foreign_table, d.foreign_table_id, d.timestamp, d.hash
+       FROM documents d
+       WHERE (d.foreign_table = 'emails'
+           AND NOT EXISTS (SELECT 1 FROM emails WHERE id = d.foreign_table_id))
+       OR (d.foreign_table = 'drive_documents'
+           AND NOT EXISTS (SELECT 1 FROM drive_documents WHERE id = d.foreign_table_id))
+       OR (d.foreign_table = 'local_files'
+           AND NOT EXISTS (SELECT 1 FROM local_files WHERE id = d.foreign_table_id))
+       OR (d.foreign_table = 'transcripts'
+           AND NOT EXISTS (SELECT 1 FROM transcripts WHERE id = d.foreign_table_id))
+       OR (d.foreign_table = 'meeting_notes'
+           AND NOT EXISTS (SELECT 1 FROM threads WHERE id = d.foreign_table_id))",
+      [],
+    )
+  }
+
+  pub fn delete_by_ids(ids: &Vec<u64>) -> Result<usize, Error> {
+    if ids.is_empty() {
+      return Ok(0);
+    }
+    let connection = try_get_db_conn()?;
     let id_where_clause = ids
       .iter()
       .map(|id| id.to_string())
       .collect::<Vec<String>>()
       .join(",");
-    let mut stmt = connection.prepare(&format!(
-      "SELECT * FROM documents WHERE id IN ({})",
-      id_where_clause
-    ))?;
-    let rows = stmt.query_map([], |row| {
-      Ok(Document {
-        id: row.get(0)?,
-        foreign_table: row.get(1)?,
-        foreign_table_id: row.get(2)?,
-        timestamp: row.get(3)?,
-        hash: row.get(4)?,
-      })
-    })?;
-
-    let mut documents = Vec::new();
-    for row in rows {
-      documents.push(row?);
-    }
-    Ok(documents)
+    connection.execute(
+      &format!("DELETE FROM documents WHERE id IN ({})", id_where_clause),
+      [],
+    )
   }
 
   pub fn find_by_foreign_table_and_id(
     foreign_table: &str,
     foreign_table_id: u64,
-  ) -> Result<Option<Document>> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT * FROM documents WHERE foreign_table = ?1 AND foreign_table_id = ?2")?;
-    let mut rows = stmt.query_map(params![foreign_table, foreign_table_id], |row| {
-      Ok(Document {
-        id: row.get(0)?,
-        foreign_table: row.get(1)?,
-        foreign_table_id: row.get(2)?,
-        timestamp: row.get(3)?,
-        hash: row.get(4)?,
-      })
+  ) -> Result<Option<Document>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM documents WHERE foreign_table = ?1 AND foreign_table_id = ?2",
+        Self::COLUMNS
+      ),
+      params![foreign_table, foreign_table_id],
+    )
+  }
+
+  /// Newest documents matching `filter`, optionally narrowed to those whose
+  /// title or body mentions every word of `query`.
+  pub fn find_by_filter(
+    filter: &SearchFilter,
+    query: Option<&str>,
+    limit: usize,
+  ) -> Result<Vec<Document>, Error> {
+    let (mut where_clause, mut sql_params) = filter.to_sql();
+    for word in query.unwrap_or("").split_whitespace() {
+      let columns = ["e.subject", "e.body", "dd.filename", "lf.filename", "lf.title", "th.title"];
+      let conditions: Vec<String> = columns
+        .iter()
+        .map(|column| format!("{} LIKE ? ESCAPE '\\'", column))
+        .collect();
+      where_clause.push_str(&format!(" AND ({})", conditions.join(" OR ")));
+      let pattern = contains_pattern(word);
+      for _ in 0..6 {
+        sql_params.push(rusqlite::types::Value::Text(pattern.clone()));
+      }
+    }
+    sql_params.push(rusqlite::types::Value::Integer(limit as i64));
+
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT d.id, d.foreign_table, d.foreign_table_id, d.timestamp, d.hash
+         FROM documents d {}
+         WHERE {}
+         ORDER BY COALESCE(e.date, dd.date_modified, lf.date_modified, th.timestamp, d.timestamp) DESC
+         LIMIT ?",
+        SQL_DOCUMENT_JOINS, where_clause
+      ),
+      rusqlite::params_from_iter(sql_params),
+    )
+  }
+
+  /// Other documents from the same email thread or meeting, newest first.
+  /// A meeting's transcript and notes are siblings of each other.
+  pub fn find_thread_siblings(document_id: u64, limit: usize) -> Result<Vec<Document>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "WITH source AS (
+           SELECT e.thread_id AS email_thread_id, th.id AS meeting_thread_id
+           FROM documents d {joins}
+           WHERE d.id = ?1
+         )
+         SELECT d.id, d.foreign_table, d.foreign_table_id, d.timestamp, d.hash
+         FROM documents d {joins}, source
+         WHERE d.id != ?1
+         AND (e.thread_id = source.email_thread_id OR th.id = source.meeting_thread_id)
+         ORDER BY COALESCE(e.date, th.timestamp, d.timestamp) DESC
+         LIMIT ?2",
+        joins = SQL_DOCUMENT_JOINS
+      ),
+      params![document_id, limit as i64],
+    )
+  }
+
+  /// Documents that share contacts with `document_id`, paired with how many
+  /// contacts they share, most shared first. Meeting notes borrow the
+  /// participants of their meeting's transcript.
+  pub fn find_sharing_contacts(
+    document_id: u64,
+    limit: usize,
+  ) -> Result<Vec<(Document, u64)>, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "WITH source_contacts AS (
+         SELECT DISTINCT cl.contact_id
+         FROM documents src
+         LEFT JOIN transcripts st
+           ON src.foreign_table = 'meeting_notes' AND st.thread_id = src.foreign_table_id
+         JOIN contact_links cl
+           ON (cl.foreign_table = src.foreign_table AND cl.foreign_table_id = src.foreign_table_id)
+           OR (cl.foreign_table = 'transcripts' AND cl.foreign_table_id = st.id)
+         WHERE src.id = ?1
+       )
+       SELECT d.id, d.foreign_table, d.foreign_table_id, d.timestamp, d.hash,
+         COUNT(DISTINCT cl.contact_id) AS shared
+       FROM contact_links cl
+       JOIN documents d
+         ON d.foreign_table = cl.foreign_table AND d.foreign_table_id = cl.foreign_table_id
+       WHERE cl.contact_id IN (SELECT contact_id FROM source_contacts)
+       AND d.id != ?1
+       GROUP BY d.id
+       ORDER BY shared DESC, MAX(cl.occurred_at) DESC
+       LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![document_id, limit as i64], |row| {
+      Ok((Document::from_row(row)?, row.get(5)?))
     })?;
-    if let Some(row) = rows.next() {
-      return Ok(Some(row?));
+
+    let mut documents = Vec::new();
+    for row in rows {
+      documents.push(row?);
     }
-    Ok(None)
+    Ok(documents)
   }
 
   pub fn find_by_type(foreign_table: String, identifier: String) -> Option<Document> {
@@ -125,7 +253,13 @@ impl Document {
       let document = match LocalFile::find_by_filename(filename) {
         Ok(Some(lf)) => {
           log::info!("Document::find_by_type. Found local file: {:?}", lf);
-          Document::find_by_foreign_table_and_id("local_files", lf.id.unwrap()).unwrap()
+          match Document::find_by_foreign_table_and_id("local_files", lf.id.unwrap()) {
+            Ok(document) => document,
+            Err(error) => {
+              log::error!("Error finding document: {:?}", error);
+              None
+            }
+          }
         }
         Ok(None) => None,
         Err(e) => {
@@ -136,7 +270,13 @@ impl Document {
       return document;
     }
     if foreign_table == "emails" {
-      let email = Email::get_email_message(&identifier);
+      let email = match Email::get_email_message(&identifier) {
+        Ok(email) => email,
+        Err(error) => {
+          log::error!("Error finding email {}: {:?}", identifier, error);
+          None
+        }
+      };
       let document = email.map(|email| {
         match Document::find_by_foreign_table_and_id("emails", email.id.unwrap()) {
           Ok(document) => document,
@@ -230,34 +370,54 @@ fn contains_document(documents: &Vec<Document>, id: u64) -> bool {
   contains_doc
 }
 
-pub fn get_documents(ids: Vec<u64>) -> Vec<Document> {
-  let connection = get_db_conn();
-  let id_where_clause = ids
-    .iter()
-    .map(|id| id.to_string())
-    .collect::<Vec<String>>()
-    .join(",");
-  let mut stmt = connection
-    .prepare("SELECT * FROM documents WHERE id IN (?)")
-    .expect("could not prepare query to get documents");
-  stmt
-    .execute([id_where_clause])
-    .expect("could not execute query to get documents");
-  let rows = stmt
-    .query_map([], |row| {
-      Ok(Document {
-        id: row.get(0)?,
-        foreign_table: row.get(1)?,
-        foreign_table_id: row.get(2)?,
-        timestamp: row.get(3)?,
-        hash: row.get(4)?,
-      })
-    })
-    .expect("could not execute query");
-  let mut documents = Vec::new();
-  for row in rows {
-    let document = row.unwrap();
-    documents.push(document);
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::db::get_db_conn;
+  use crate::memory::search_filter::SourceType;
+
+  fn insert_local_file_document(filename: &str) -> u64 {
+    let connection = get_db_conn();
+    connection
+      .execute(
+        "INSERT INTO local_files (filename, path, file_size, date_modified, date_created, title)
+         VALUES (?1, ?2, 1, 1, 1, ?1)",
+        params![filename, format!("/tmp/{}", filename)],
+      )
+      .unwrap();
+    let local_file_id = connection.last_insert_rowid();
+    connection
+      .execute(
+        "INSERT INTO documents (foreign_table, foreign_table_id, hash)
+         VALUES ('local_files', ?1, 'hash')",
+        params![local_file_id],
+      )
+      .unwrap();
+    connection.last_insert_rowid() as u64
+  }
+
+  #[test]
+  fn find_by_filter_matches_wildcards_literally() {
+    let percent = insert_local_file_document("like-test 100% done.txt");
+    let digits = insert_local_file_document("like-test 1000 done.txt");
+    let underscore = insert_local_file_document("like-test a_b.txt");
+    insert_local_file_document("like-test axb.txt");
+    let filter = SearchFilter {
+      sources: vec![SourceType::File],
+      ..Default::default()
+    };
+
+    let ids = |query: &str| -> Vec<u64> {
+      Document::find_by_filter(&filter, Some(query), 10)
+        .unwrap()
+        .into_iter()
+        .filter_map(|document| document.id)
+        .collect()
+    };
+    assert_eq!(ids("like-test 100%"), vec![percent]);
+    assert_eq!(ids("like-test a_b"), vec![underscore]);
+    let mut both = ids("like-test 100");
+    both.sort();
+    assert_eq!(both, vec![percent, digits]);
   }
-  return documents;
 }
@@ -1,10 +1,11 @@
-use rusqlite::{params, OptionalExtension};
+use rusqlite::params;
 use serde_json::{Map, Value};
 use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::connections::data_source::KnowledgeSnippet;
-use crate::db::{db::get_db_conn, models::document::Document};
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::db::{db::try_get_db_conn, models::document::Document};
 use crate::error::Error;
 use std::collections::HashSet;
 
@@ -21,96 +22,71 @@ pub struct DriveDocument {
   pub url: String,
   pub timestamp: Option<u64>,
   pub content_chunks: Option<Vec<String>>,
+  /// The `users` row of the drive this document was synced from.
+  pub account_id: Option<u64>,
+}
+
+impl FromRow for DriveDocument {
+  const COLUMNS: &'static str = "id, drive_id, filename, file_size, date_modified, date_created, summary, checksum, url, timestamp, account_id";
+
+  fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+    Ok(DriveDocument {
+      id: row.get(0)?,
+      drive_id: row.get(1)?,
+      filename: row.get(2)?,
+      file_size: row.get(3)?,
+      date_modified: row.get(4)?,
+      date_created: row.get(5)?,
+      summary: row.get(6)?,
+      checksum: row.get(7)?,
+      url: row.get(8)?,
+      timestamp: row.get(9)?,
+      content_chunks: None,
+      account_id: row.get(10)?,
+    })
+  }
 }
 
 impl DriveDocument {
   pub fn find_by_id(id: u64) -> Result<Option<DriveDocument>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, drive_id, filename, file_size, date_modified, date_created, summary, checksum, url, timestamp FROM drive_documents WHERE id = ?1")?;
-
-    let drive_document = stmt
-      .query_row(params![id], |row| {
-        Ok(DriveDocument {
-          id: row.get(0)?,
-          drive_id: row.get(1)?,
-          filename: row.get(2)?,
-          file_size: row.get(3)?,
-          date_modified: row.get(4)?,
-          date_created: row.get(5)?,
-          summary: row.get(6)?,
-          checksum: row.get(7)?,
-          url: row.get(8)?,
-          timestamp: row.get(9)?,
-          content_chunks: None,
-        })
-      })
-      .optional()?;
-
-    Ok(drive_document)
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM drive_documents WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
   }
 
   pub fn find_by_drive_id(drive_id: &str) -> Result<Option<DriveDocument>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, drive_id, filename, file_size, date_modified, date_created, summary, checksum, url, timestamp FROM drive_documents WHERE drive_id = ?1")?;
-
-    let drive_document = stmt
-      .query_row(params![drive_id], |row| {
-        Ok(DriveDocument {
-          id: row.get(0)?,
-          drive_id: row.get(1)?,
-          filename: row.get(2)?,
-          file_size: row.get(3)?,
-          date_modified: row.get(4)?,
-          date_created: row.get(5)?,
-          summary: row.get(6)?,
-          checksum: row.get(7)?,
-          url: row.get(8)?,
-          timestamp: row.get(9)?,
-          content_chunks: None,
-        })
-      })
-      .optional()?;
-
-    Ok(drive_document)
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM drive_documents WHERE drive_id = ?1", Self::COLUMNS),
+      params![drive_id],
+    )
   }
 
   pub fn find_by_ids(ids: Vec<String>) -> Result<Vec<DriveDocument>, Error>{
-    let connection = get_db_conn();
-
-    let formatted_ids = ids
-    .iter()
-    .map(|name| format!("\"{}\"", name))
-    .collect::<Vec<_>>()
-    .join(", ");
-    let mut stmt = connection
-      .prepare(&format!("SELECT id, drive_id, filename, file_size, date_modified, date_created, summary, checksum, url, timestamp FROM drive_documents WHERE drive_id IN ({})", 
-      formatted_ids))?;
-
-    let rows = stmt
-      .query_map([], |row| {
-        Ok(DriveDocument {
-          id: row.get(0)?,
-          drive_id: row.get(1)?,
-          filename: row.get(2)?,
-          file_size: row.get(3)?,
-          date_modified: row.get(4)?,
-          date_created: row.get(5)?,
-          summary: row.get(6)?,
-          checksum: row.get(7)?,
-          url: row.get(8)?,
-          timestamp: row.get(9)?,
-          content_chunks: None,
-      })
-    })?;
+    if ids.is_empty() {
+      return Ok(Vec::new());
+    }
+    let connection = try_get_db_conn()?;
+    let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let rows: Vec<DriveDocument> = query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM drive_documents WHERE drive_id IN ({})",
+        Self::COLUMNS,
+        placeholders
+      ),
+      rusqlite::params_from_iter(ids.iter()),
+    )?;
 
     let mut unique_drive_ids: HashSet<String> = HashSet::new();
       
     let mut drive_document = Vec::new();
     
-    for row in rows {
-      let document = row?.clone();
+    for document in rows {
       let drive_id = document.drive_id.clone();
       if !unique_drive_ids.contains(&drive_id) {
         drive_document.push(document);
@@ -122,10 +98,10 @@ impl DriveDocument {
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let result = connection
       .execute(
-        "INSERT INTO drive_documents (id, drive_id, filename, file_size, date_modified, date_created, summary, checksum, url) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT INTO drive_documents (id, drive_id, filename, file_size, date_modified, date_created, summary, checksum, url, account_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         (
           &self.id,
           &self.drive_id,
@@ -136,6 +112,7 @@ impl DriveDocument {
           &self.summary,
           &self.checksum,
           &self.url,
+          &self.account_id,
         ),
       )
       .map_err(|e| e.into());
@@ -148,8 +125,47 @@ impl DriveDocument {
     }
   }
 
+  /// Assigns documents synced before accounts were tracked to the account
+  /// that syncs them next.
+  pub fn claim_for_account(&mut self, account_id: u64) -> Result<(), Error> {
+    if self.account_id.is_some() {
+      return Ok(());
+    }
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE drive_documents SET account_id = ?2 WHERE id = ?1 AND account_id IS NULL",
+      params![self.id, account_id],
+    )?;
+    self.account_id = Some(account_id);
+    Ok(())
+  }
+
+  /// Stores the owners and last editor reported by Drive, see
+  /// `memory::contacts::drive_people_json`.
+  pub fn set_people_json(id: u64, people_json: &str) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE drive_documents SET people_json = ?2 WHERE id = ?1",
+      params![id, people_json],
+    )?;
+    Ok(())
+  }
+
+  /// Drive ids of the documents stored for `account_id`, including unowned
+  /// ones the account's next sync will claim.
+  pub fn find_drive_ids_by_account(account_id: u64) -> Result<HashSet<String>, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "SELECT drive_id FROM drive_documents WHERE account_id = ?1 OR account_id IS NULL",
+    )?;
+    let drive_ids = stmt
+      .query_map([account_id], |row| row.get::<_, String>(0))?
+      .collect::<Result<HashSet<_>, _>>()?;
+    Ok(drive_ids)
+  }
+
   pub fn count() -> Result<u64, Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("SELECT count(*) FROM drive_documents")?;
     let count = stmt.query_row(params![], |row| Ok(row.get::<_, u64>(0)?))?;
 
@@ -157,7 +173,7 @@ impl DriveDocument {
   }
 
   pub fn upsert(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let result = connection
       .execute(
         "INSERT INTO google_drive (id, filename, file_size, date_modified, date_created, summary, checksum, url) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
@@ -191,7 +207,7 @@ impl DriveDocument {
   // }
 
   pub fn delete(&self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let result = connection
       .execute("DELETE FROM drive_documents WHERE id = ?1", [&self.id])
       .map_err(|e| e.into());
@@ -215,7 +231,13 @@ impl DriveDocument {
     // let chunks = splitter.split_text(content);
 
     let document =
-      Document::find_by_foreign_table_and_id("drive_documents", self.id.unwrap()).unwrap();
+      match Document::find_by_foreign_table_and_id("drive_documents", self.id.unwrap()) {
+        Ok(document) => document,
+        Err(error) => {
+          log::error!("Error finding document for drive file {:?}: {:?}", self.id, error);
+          return documents;
+        }
+      };
     let mut document_id = document.map(|document| document.id).flatten();
     if document_id.is_none() {
       let document = create_drive_document(self.id.unwrap(), String::from(""));
@@ -271,6 +293,10 @@ impl DriveDocument {
           "date_created".to_string(),
           serde_json::Value::Number(serde_json::Number::from(self.date_created.clone())),
         ),
+        (
+          "date".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(self.date_modified)),
+        ),
         ("content".to_string(), serde_json::Value::String(chunk)),
         (
           "url".to_string(),
@@ -285,6 +311,12 @@ impl DriveDocument {
           serde_json::Value::Number(serde_json::Number::from(document_id.unwrap())),
         ),
       ]);
+      if let Some(account_id) = self.account_id {
+        doc_payload.insert(
+          "account_id".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(account_id)),
+        );
+      }
 
       documents.push(doc_payload);
     }
@@ -300,8 +332,10 @@ impl DriveDocument {
           "drive_id".to_string(),
           "url".to_string(),
           "date_created".to_string(),
+          "date".to_string(),
           "file_size".to_string(),
           "type".to_string(),
+          "account_id".to_string(),
           "document_id".to_string(),
           "chunk_id".to_string(),
         ],
@@ -1,16 +1,18 @@
 use chrono::DateTime;
 use html2text::from_read;
-use rusqlite::{params, OptionalExtension, Result};
+use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 use crate::connections::data_source::KnowledgeSnippet;
-use crate::db::{db::get_db_conn, models::document::Document};
+use crate::db::models::user::{account_clause, active_account_clause, User};
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::db::{db::try_get_db_conn, models::document::Document};
 use crate::error::Error;
-use crate::memory::text_splitter::TextSplitter;
+use crate::memory::text_splitter::{ContentType, TextChunk, TextSplitter};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Email {
@@ -27,10 +29,14 @@ pub struct Email {
   pub is_read: Option<bool>,
   pub is_archived: Option<bool>,
   pub is_deleted: Option<bool>,
+  /// The `users` row of the mailbox this email was synced from.
+  pub account_id: Option<u64>,
 }
 
-impl Email {
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+impl FromRow for Email {
+  const COLUMNS: &'static str = "id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted, account_id";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     Ok(Email {
       id: Some(row.get(0)?),
       email_uid: row.get(1)?,
@@ -39,22 +45,26 @@ impl Email {
       sender: row.get(4)?,
       body: row.get(5)?,
       recipient: row.get(6)?,
-      cc: row.get(7)?,
-      thread_id: Some(row.get(8)?),
+      cc: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+      thread_id: row.get(8)?,
       is_starred: row.get(9)?,
       is_read: row.get(10)?,
       is_archived: row.get(11)?,
-      is_deleted: Some(row.get(12)?),
+      is_deleted: row.get(12)?,
+      account_id: row.get(13)?,
     })
   }
-  pub fn delete(&self) -> Result<()> {
-    let connection = get_db_conn();
+}
+
+impl Email {
+  pub fn delete(&self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
     connection.execute("DELETE FROM emails WHERE id = ?1", params![self.email_uid])?;
     Ok(())
   }
 
   pub fn update(&self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     connection.execute(
         "UPDATE emails SET 
             subject = ?1, 
@@ -91,123 +101,109 @@ impl Email {
     if self.id.is_some() {
       return Ok(());
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     connection
       .execute(
-        "INSERT INTO emails (email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12) ON CONFLICT(email_uid) DO UPDATE SET subject = ?2, date = ?3, sender = ?4, body = ?5, recipient = ?6, cc = ?7, thread_id = ?8, is_starred = ?9,  is_read = ?10, is_archived =?11, is_deleted =?12",
-        params![self.email_uid, self.subject, self.date, self.sender, self.body, self.recipient, self.cc, self.thread_id, self.is_starred, self.is_read, self.is_archived, self.is_deleted],
+        "INSERT INTO emails (email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted, account_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13) ON CONFLICT(email_uid) DO UPDATE SET subject = ?2, date = ?3, sender = ?4, body = ?5, recipient = ?6, cc = ?7, thread_id = ?8, is_starred = ?9,  is_read = ?10, is_archived =?11, is_deleted =?12, account_id = COALESCE(account_id, ?13)",
+        params![self.email_uid, self.subject, self.date, self.sender, self.body, self.recipient, self.cc, self.thread_id, self.is_starred, self.is_read, self.is_archived, self.is_deleted, self.account_id],
       )?;
     self.id = Some(connection.last_insert_rowid() as u64);
     Ok(())
   }
 
-  pub fn find_by_id(id: u64) -> Result<Option<Email>, Error> {
-    let connection = get_db_conn();
+  /// Empties the bodies of emails sent before `cutoff`, keeping subject,
+  /// participants and dates. Returns the ids of the emails that changed.
+  pub fn clear_bodies_before(cutoff: u64) -> Result<Vec<u64>, Error> {
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
-      .prepare("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails WHERE id = ?1")
-      .expect("could not prepare query emails");
-    let email = stmt
-      .query_row([id], |row| Email::build_struct_from_row(row))
-      .optional()?;
-    Ok(email)
+      .prepare("UPDATE emails SET body = '' WHERE date < ?1 AND body != '' RETURNING id")?;
+    let ids = stmt
+      .query_map(params![cutoff], |row| row.get(0))?
+      .collect::<Result<Vec<u64>, _>>()?;
+    Ok(ids)
   }
 
-  pub fn find_by_uid(uid: &str) -> Result<Option<Email>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails WHERE email_uid = ?1")
-      .expect("could not prepare query emails");
-    let email = stmt
-      .query_row([uid], |row| Email::build_struct_from_row(row))
-      .optional()?;
-    Ok(email)
+  pub fn find_by_id(id: u64) -> Result<Option<Email>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM emails WHERE id = ?1", Self::COLUMNS),
+      [id],
+    )
   }
 
-  pub fn get_recent_emails_with(sender_or_recipient: &str, limit: usize) -> Vec<Email> {
-    let connection = get_db_conn();
-    let sender_or_recipient_query_string = format!("%{}%", sender_or_recipient).clone().to_string();
-    let sender_or_recipient_query_str = sender_or_recipient_query_string.as_str();
-    let limit_string = limit.clone().to_string();
-    let limit_str = limit_string.as_str();
-    let mut stmt = connection
-      .prepare("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails WHERE sender LIKE ?1 OR recipient LIKE ?1 ORDER BY date DESC LIMIT ?2")
-      .expect("could not prepare query emails");
-    let rows = stmt
-      .query_map([&sender_or_recipient_query_str, &limit_str], |row| {
-        Email::build_struct_from_row(row)
-      })
-      .expect("could not execute query");
-
-    rows.filter_map(Result::ok).collect()
+  pub fn find_by_uid(uid: &str) -> Result<Option<Email>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM emails WHERE email_uid = ?1", Self::COLUMNS),
+      [uid],
+    )
   }
 
-  pub fn get_most_recent_email_timestamp() -> Option<i64> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT MAX(date) FROM emails")
-      .expect("could not prepare query for most recent email timestamp");
-    let result = stmt.query_row([], |row| row.get(0));
-
-    match result {
-      Ok(timestamp) => Some(timestamp),
-      Err(_) => None,
-    }
+  pub fn get_recent_emails_with(sender_or_recipient: &str, limit: usize) -> Result<Vec<Email>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM emails WHERE (sender LIKE ?1 OR recipient LIKE ?1) AND {} ORDER BY date DESC LIMIT ?2",
+        Self::COLUMNS,
+        active_account_clause("account_id")
+      ),
+      params![format!("%{}%", sender_or_recipient), limit],
+    )
   }
 
-  pub fn get_email_message(email_uid: &str) -> Option<Email> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails WHERE email_uid = ?1")
-      .expect("could not prepare query emails");
-    //TODO use build_struct_from_row
-    let result = stmt.query_row(&[email_uid], |row| Email::build_struct_from_row(row));
+  pub fn get_most_recent_email_timestamp() -> Result<Option<i64>, Error> {
+    let connection = try_get_db_conn()?;
+    let timestamp = connection.query_row("SELECT MAX(date) FROM emails", [], |row| row.get(0))?;
+    Ok(timestamp)
+  }
 
-    match result {
-      Ok(email) => Some(email),
-      Err(error) => {
-        println!("Error while retrieving email {email_uid}: {:?}", error);
-        None
-      }
-    }
+  pub fn get_email_message(email_uid: &str) -> Result<Option<Email>, Error> {
+    Email::find_by_uid(email_uid)
   }
 
-  pub fn get_recent_emails(limit: usize) -> Vec<Email> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails ORDER BY date DESC LIMIT ?1")
-      .expect("could not prepare query emails");
-    //TODO use build_struct_from_row
-    let rows = stmt
-      .query_map([limit], |row| Email::build_struct_from_row(row))
-      .expect("could not execute query");
-
-    rows.filter_map(Result::ok).collect()
+  pub fn get_recent_emails(limit: usize) -> Result<Vec<Email>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM emails WHERE {} ORDER BY date DESC LIMIT ?1",
+        Self::COLUMNS,
+        active_account_clause("account_id")
+      ),
+      [limit],
+    )
   }
 
-  pub fn count() -> Result<u64> {
-    let connection = get_db_conn();
+  pub fn count() -> Result<u64, Error> {
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("SELECT count(*) FROM emails")?;
     let count = stmt.query_row(params![], |row| Ok(row.get::<_, u64>(0)?))?;
 
     Ok(count)
   }
 
-  pub fn get_all_email_uids() -> Vec<String> {
-    let connection = get_db_conn();
+  /// Uids of the emails stored for `account_id`, including unowned ones the
+  /// account's next sync will claim.
+  pub fn find_uids_by_account(account_id: u64) -> Result<HashSet<String>, Error> {
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
-      .prepare("SELECT email_uid FROM emails")
-      .expect("could not prepare query get all email email_uids");
-    let rows = stmt
-      .query_map([], |row| Ok(row.get::<_, String>(0).unwrap()))
-      .expect("could not execute query");
-    let mut email_uids = Vec::new();
-    for row in rows {
-      match row {
-        Ok(email_uid) => email_uids.push(email_uid),
-        Err(_) => (),
-      }
-    }
-    email_uids
+      .prepare("SELECT email_uid FROM emails WHERE account_id = ?1 OR account_id IS NULL")?;
+    let email_uids = stmt
+      .query_map([account_id], |row| row.get::<_, String>(0))?
+      .collect::<Result<HashSet<_>, _>>()?;
+    Ok(email_uids)
+  }
+
+  pub fn get_all_email_uids() -> Result<Vec<String>, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare("SELECT email_uid FROM emails")?;
+    let email_uids = stmt
+      .query_map([], |row| row.get::<_, String>(0))?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(email_uids)
   }
 
   pub fn filter_emails_by_sender(
@@ -215,25 +211,42 @@ impl Email {
     sender: &str,
     min_timestamp: i64,
     max_timestamp: i64,
-  ) -> Vec<Email> {
-    let connection = get_db_conn();
+  ) -> Result<Vec<Email>, Error> {
+    Email::filter_account_emails_by_sender(
+      User::active_account_id(),
+      limit,
+      sender,
+      min_timestamp,
+      max_timestamp,
+    )
+  }
 
-    let where_query = "WHERE sender LIKE ?1 AND date >= ?2 AND date <= ?3".to_string();
+  /// `filter_emails_by_sender` for a given account rather than the active
+  /// one, for work that runs in the background.
+  pub fn filter_account_emails_by_sender(
+    account_id: Option<u64>,
+    limit: usize,
+    sender: &str,
+    min_timestamp: i64,
+    max_timestamp: i64,
+  ) -> Result<Vec<Email>, Error> {
+    let connection = try_get_db_conn()?;
 
-    let query = format!("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails {} ORDER BY date DESC LIMIT ?4", where_query);
-    let mut stmt = connection
-      .prepare(&query)
-      .expect("could not prepare query emails");
-    //TODO use build_struct_from_row
-
-    let rows = stmt
-      .query_map(
-        (&format!("%{sender}%"), min_timestamp, max_timestamp, limit),
-        |row| Email::build_struct_from_row(row),
-      )
-      .expect("could not execute query");
-
-    rows.filter_map(Result::ok).collect()
+    let where_query = format!(
+      "WHERE sender LIKE ?1 AND date >= ?2 AND date <= ?3 AND {}",
+      account_clause("account_id", account_id)
+    );
+
+    let query = format!(
+      "SELECT {} FROM emails {} ORDER BY date DESC LIMIT ?4",
+      Self::COLUMNS,
+      where_query
+    );
+    query_rows(
+      &connection,
+      &query,
+      (&format!("%{sender}%"), min_timestamp, max_timestamp, limit),
+    )
   }
 
   pub fn filter_emails(
@@ -241,8 +254,26 @@ impl Email {
     maybe_email_address: Option<Vec<String>>,
     maybe_minimum_timestamp: Option<i64>,
     maybe_maximum_timestamp: Option<i64>,
-  ) -> Vec<Email> {
-    let connection = get_db_conn();
+  ) -> Result<Vec<Email>, Error> {
+    Email::filter_account_emails(
+      User::active_account_id(),
+      limit,
+      maybe_email_address,
+      maybe_minimum_timestamp,
+      maybe_maximum_timestamp,
+    )
+  }
+
+  /// `filter_emails` for a given account rather than the active one, for
+  /// work that runs in the background.
+  pub fn filter_account_emails(
+    account_id: Option<u64>,
+    limit: usize,
+    maybe_email_address: Option<Vec<String>>,
+    maybe_minimum_timestamp: Option<i64>,
+    maybe_maximum_timestamp: Option<i64>,
+  ) -> Result<Vec<Email>, Error> {
+    let connection = try_get_db_conn()?;
     let mut where_queries = Vec::new();
     let mut params = Vec::new();
     let mut or_queries = Vec::new();
@@ -268,47 +299,54 @@ impl Email {
       params.push(maximum_timestamp.to_string());
     }
 
+    where_queries.push(account_clause("account_id", account_id));
+
     let mut where_query = "".to_string();
     if where_queries.len() > 0 {
       where_query = format!("WHERE {}", where_queries.join(" and "));
     }
 
-    let query = format!("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails {} ORDER BY date DESC LIMIT ?{}", where_query, params.len() + 1);
+    let query = format!(
+      "SELECT {} FROM emails {} ORDER BY date DESC LIMIT ?{}",
+      Self::COLUMNS,
+      where_query,
+      params.len() + 1
+    );
     params.push(limit.to_string());
-    let mut stmt = connection
-      .prepare(&query)
-      .expect("could not prepare query emails");
-    //TODO use build_struct_from_row
+    query_rows(&connection, &query, rusqlite::params_from_iter(params))
+  }
 
-    let rows = stmt
-      .query_map(rusqlite::params_from_iter(params), |row| {
-        Email::build_struct_from_row(row)
+  /// Splits the html-stripped body with quoted replies removed. Offsets
+  /// refer to the html-stripped body.
+  fn body_chunks(&self) -> Vec<TextChunk> {
+    if self.body == "" {
+      return vec![];
+    }
+    let body_in_bytes = self.body.as_bytes();
+    let html_stripped_body = from_read(&body_in_bytes[..], body_in_bytes.len());
+    let splitter = TextSplitter::for_content_type(ContentType::Email);
+    splitter
+      .split_chunks(&html_stripped_body)
+      .into_iter()
+      .map(|mut chunk| {
+        chunk.text = normalize_body_text(&chunk.text);
+        chunk
       })
-      .expect("could not execute query");
-
-    rows.filter_map(Result::ok).collect()
+      .filter(|chunk| !chunk.text.is_empty())
+      .collect()
   }
 
   pub fn get_documents(&self) -> Vec<HashMap<String, serde_json::Value>> {
-    let body_in_bytes = self.body.as_bytes();
-    let mut parsed_body = String::from("");
-    if self.body != "" {
-      let html_stripped_body = from_read(&body_in_bytes[..], body_in_bytes.len());
-      parsed_body = html_stripped_body
-        .replace("\n", " ")
-        .replace("\r", " ")
-        .replace("\t", " ")
-        .trim()
-        .split(' ')
-        .filter(|s| !s.is_empty() && s.len() < 70)
-        .collect::<Vec<_>>()
-        .join(" ");
-    }
     let mut documents = Vec::new();
-    let splitter = TextSplitter::default();
-    let chunks = splitter.split_text(&parsed_body);
+    let chunks = self.body_chunks();
 
-    let document = Document::find_by_foreign_table_and_id("emails", self.id.unwrap()).unwrap();
+    let document = match Document::find_by_foreign_table_and_id("emails", self.id.unwrap()) {
+      Ok(document) => document,
+      Err(error) => {
+        log::error!("Error finding document for email {:?}: {:?}", self.id, error);
+        return documents;
+      }
+    };
     let mut document_id = document.map(|document| document.id).flatten();
 
     if document_id.is_none() {
@@ -353,7 +391,15 @@ impl Email {
           serde_json::Value::String(self.recipient.clone()),
         ),
         ("cc".to_string(), serde_json::Value::String(self.cc.clone())),
-        ("body".to_string(), serde_json::Value::String(chunk)),
+        ("body".to_string(), serde_json::Value::String(chunk.text)),
+        (
+          "chunk_start".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(chunk.start)),
+        ),
+        (
+          "chunk_end".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(chunk.end)),
+        ),
         (
           "type".to_string(),
           serde_json::Value::String("gmail".to_string()),
@@ -363,6 +409,18 @@ impl Email {
           serde_json::Value::Number(serde_json::Number::from(document_id.unwrap())),
         ),
       ]);
+      if let Some(thread_id) = &self.thread_id {
+        doc_payload.insert(
+          "thread_id".to_string(),
+          serde_json::Value::String(thread_id.clone()),
+        );
+      }
+      if let Some(account_id) = self.account_id {
+        doc_payload.insert(
+          "account_id".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(account_id)),
+        );
+      }
 
       documents.push(doc_payload);
     }
@@ -388,30 +446,37 @@ impl Email {
           "date".to_string(),
           "type".to_string(),
           "email_uid".to_string(),
+          "thread_id".to_string(),
+          "account_id".to_string(),
           "document_id".to_string(),
           "chunk_id".to_string(),
+          "chunk_start".to_string(),
+          "chunk_end".to_string(),
         ],
       ),
     ])
   }
 
   pub fn get_last_email_by_thread_id(thread_id: &str) -> Result<Option<Email>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-        .prepare("SELECT id, email_uid, subject, date, sender, body, recipient, cc, thread_id, is_starred, is_read, is_archived, is_deleted FROM emails WHERE thread_id = ?1 ORDER BY date DESC LIMIT 1")
-        .expect("could not prepare query emails by thread_id");
-
-    let email_result = stmt.query_row([thread_id], |row| Email::build_struct_from_row(row));
-
-    match email_result {
-      Ok(email) => Ok(Some(email)),
-      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-      Err(e) => Err(Error::from(e)),
-    }
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM emails WHERE thread_id = ?1 ORDER BY date DESC LIMIT 1",
+        Self::COLUMNS
+      ),
+      [thread_id],
+    )
   }
 
-  pub async fn mark_deleted_emails(fetched_uuids: &[String], days: i64) -> Result<(), Error> {
-    let connection = get_db_conn();
+  /// Only emails of `account_id` are considered, so syncing one mailbox
+  /// never marks another mailbox's emails as deleted.
+  pub async fn mark_deleted_emails(
+    fetched_uuids: &[String],
+    days: i64,
+    account_id: u64,
+  ) -> Result<usize, Error> {
+    let connection = try_get_db_conn()?;
     let days_ago = chrono::Utc::now() - chrono::Duration::days(days);
     let days_ago_timestamp = days_ago.timestamp() as u64;
     let current_timestamp = chrono::Utc::now().timestamp() as u64;
@@ -422,22 +487,38 @@ impl Email {
         WHERE email_uid NOT IN ({}) 
         AND date >= ? 
         AND date <= ? 
-        AND is_deleted = FALSE",
+        AND is_deleted = FALSE
+        AND (account_id = ? OR (account_id IS NULL AND ?))",
         placeholders
     );
+    let includes_unowned = User::primary_account_id()? == Some(account_id);
   
     let mut stmt = connection.prepare(&query)?;
     
     let mut params: Vec<&dyn rusqlite::ToSql> = fetched_uuids.iter().map(|s| s as &dyn rusqlite::ToSql).collect();
     params.push(&days_ago_timestamp);
     params.push(&current_timestamp);
+    params.push(&account_id);
+    params.push(&includes_unowned);
   
-    stmt.execute(params.as_slice())?;
+    let deleted = stmt.execute(params.as_slice())?;
   
-    Ok(())
+    Ok(deleted)
   }
 }
 
+fn normalize_body_text(text: &str) -> String {
+  text
+    .replace("\n", " ")
+    .replace("\r", " ")
+    .replace("\t", " ")
+    .trim()
+    .split(' ')
+    .filter(|s| !s.is_empty() && s.len() < 70)
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
 fn create_email_document(id: u64, hash: String) -> Document {
   let mut document = Document {
     id: None,
@@ -475,20 +556,11 @@ impl KnowledgeSnippet for Email {
       _ => {
         let body_in_bytes = self.body.as_bytes();
         let html_stripped_body = from_read(&body_in_bytes[..], body_in_bytes.len());
-        html_stripped_body
-          .replace("\n", " ")
-          .replace("\r", " ")
-          .replace("\t", " ")
-          .trim()
-          .split(' ')
-          .filter(|s| !s.is_empty() && s.len() < 70)
-          .collect::<Vec<_>>()
-          .join(" ")
+        normalize_body_text(&html_stripped_body)
       }
     };
 
-    let splitter = TextSplitter::default();
-    let chunks = splitter.split_text(&parsed_body);
+    let chunks = self.body_chunks();
 
     let mut prompt = format!(
       "> Start of Email\n\n {} sent on {}\n Subject:{}\n\n",
@@ -500,7 +572,7 @@ impl KnowledgeSnippet for Email {
     } else {
       for chunk_id in chunk_ids {
         if chunk_id as usize <= chunks.len() - 1 {
-          prompt = format!("{}\n\n{}", prompt, chunks[chunk_id as usize]);
+          prompt = format!("{}\n\n{}", prompt, chunks[chunk_id as usize].text);
         }
       }
     }
@@ -518,3 +590,59 @@ impl KnowledgeSnippet for Email {
   }
   
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn email(uid: &str, account_id: Option<u64>) -> Email {
+    Email {
+      id: None,
+      email_uid: uid.to_string(),
+      thread_id: None,
+      subject: "Quarterly numbers".to_string(),
+      date: 1_700_000_000,
+      sender: "scoped-sender@example.com".to_string(),
+      recipient: "me@example.com".to_string(),
+      cc: "".to_string(),
+      body: "".to_string(),
+      is_starred: None,
+      is_read: None,
+      is_archived: None,
+      is_deleted: None,
+      account_id,
+    }
+  }
+
+  #[test]
+  fn account_filters_skip_other_accounts_mail() {
+    email("scoped-own", Some(9_410_001)).create().unwrap();
+    email("scoped-other", Some(9_410_002)).create().unwrap();
+    email("scoped-unowned", None).create().unwrap();
+
+    let mut uids = Email::filter_account_emails(
+      Some(9_410_001),
+      10,
+      Some(vec!["scoped-sender@example.com".to_string()]),
+      None,
+      None,
+    )
+    .unwrap()
+    .into_iter()
+    .map(|email| email.email_uid)
+    .collect::<Vec<_>>();
+    uids.sort();
+    assert_eq!(uids, vec!["scoped-own", "scoped-unowned"]);
+
+    let sent = Email::filter_account_emails_by_sender(
+      Some(9_410_002),
+      10,
+      "scoped-sender@example.com",
+      0,
+      i64::MAX,
+    )
+    .unwrap();
+    assert!(sent.iter().all(|email| email.email_uid != "scoped-own"));
+    assert_eq!(sent.len(), 2);
+  }
+}
@@ -0,0 +1,274 @@
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::error::Error;
+
+/// Seconds before the first retry; doubled on every further attempt.
+const RETRY_BASE_DELAY_SECS: u64 = 5;
+const RETRY_MAX_DELAY_SECS: u64 = 60 * 60;
+/// Finished jobs are only kept around long enough to report progress.
+const DONE_RETENTION_SECS: u64 = 24 * 60 * 60;
+
+/// `Failed` jobs are waiting out their backoff and will be picked up again;
+/// `Dead` jobs ran out of attempts and stay put until someone looks at them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingJobStatus {
+  Pending,
+  Running,
+  Failed,
+  Done,
+  Dead,
+}
+
+impl EmbeddingJobStatus {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      EmbeddingJobStatus::Pending => "pending",
+      EmbeddingJobStatus::Running => "running",
+      EmbeddingJobStatus::Failed => "failed",
+      EmbeddingJobStatus::Done => "done",
+      EmbeddingJobStatus::Dead => "dead",
+    }
+  }
+
+  pub fn from_str(status: &str) -> Option<Self> {
+    match status {
+      "pending" => Some(EmbeddingJobStatus::Pending),
+      "running" => Some(EmbeddingJobStatus::Running),
+      "failed" => Some(EmbeddingJobStatus::Failed),
+      "done" => Some(EmbeddingJobStatus::Done),
+      "dead" => Some(EmbeddingJobStatus::Dead),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingJob {
+  pub id: Option<u64>,
+  pub action: String,
+  pub payload: Option<Value>,
+  pub priority: u16,
+  pub status: EmbeddingJobStatus,
+  pub attempts: u32,
+  pub max_attempts: u32,
+  pub next_attempt_at: u64,
+  pub last_error: Option<String>,
+  pub created_timestamp: Option<u64>,
+  pub updated_timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingJobProgress {
+  pub pending: u64,
+  pub running: u64,
+  pub failed: u64,
+  pub done: u64,
+  pub dead: u64,
+  /// Share of the current backlog that has finished, 0-100.
+  pub progress: u16,
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .as_secs()
+}
+
+impl FromRow for EmbeddingJob {
+  const COLUMNS: &'static str = "id, action, payload, priority, status, attempts, max_attempts,
+    next_attempt_at, last_error, created_timestamp, updated_timestamp";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    let payload: Option<String> = row.get(2)?;
+    let status: String = row.get(4)?;
+    Ok(EmbeddingJob {
+      id: Some(row.get(0)?),
+      action: row.get(1)?,
+      payload: payload.and_then(|payload| serde_json::from_str(&payload).ok()),
+      priority: row.get(3)?,
+      status: EmbeddingJobStatus::from_str(&status).unwrap_or(EmbeddingJobStatus::Pending),
+      attempts: row.get(5)?,
+      max_attempts: row.get(6)?,
+      next_attempt_at: row.get(7)?,
+      last_error: row.get(8)?,
+      created_timestamp: row.get(9)?,
+      updated_timestamp: row.get(10)?,
+    })
+  }
+}
+
+impl EmbeddingJob {
+  pub fn enqueue(
+    action: &str,
+    payload: Option<Value>,
+    priority: u16,
+    max_attempts: u32,
+  ) -> Result<EmbeddingJob, Error> {
+    let connection = try_get_db_conn()?;
+    let payload_string = payload.as_ref().map(|payload| payload.to_string());
+    let job: Option<EmbeddingJob> = query_optional(
+      &connection,
+      &format!(
+        "INSERT INTO embedding_jobs (action, payload, priority, max_attempts, next_attempt_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         RETURNING {}",
+        EmbeddingJob::COLUMNS
+      ),
+      params![action, payload_string, priority, max_attempts, now_secs()],
+    )?;
+    Ok(job.ok_or(rusqlite::Error::QueryReturnedNoRows)?)
+  }
+
+  /// Atomically moves the most urgent runnable job to `running` and returns
+  /// it. Retries only become runnable once their backoff has elapsed.
+  pub fn claim_next() -> Result<Option<EmbeddingJob>, Error> {
+    let connection = try_get_db_conn()?;
+    let sql = format!(
+      "UPDATE embedding_jobs
+       SET status = 'running', attempts = attempts + 1, updated_timestamp = ?1
+       WHERE id = (
+         SELECT id FROM embedding_jobs
+         WHERE status IN ('pending', 'failed') AND next_attempt_at <= ?1
+         ORDER BY priority DESC, id ASC
+         LIMIT 1
+       )
+       RETURNING {}",
+      EmbeddingJob::COLUMNS
+    );
+    let jobs: Vec<EmbeddingJob> = query_rows(&connection, &sql, params![now_secs()])?;
+    Ok(jobs.into_iter().next())
+  }
+
+  pub fn mark_done(&mut self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    let now = now_secs();
+    connection.execute(
+      "UPDATE embedding_jobs SET status = 'done', last_error = NULL, updated_timestamp = ?2
+       WHERE id = ?1",
+      params![self.id, now],
+    )?;
+    self.status = EmbeddingJobStatus::Done;
+    self.last_error = None;
+    self.updated_timestamp = Some(now);
+    Ok(())
+  }
+
+  /// Schedules a retry with exponential backoff, or dead-letters the job once
+  /// it has used up its attempts.
+  pub fn mark_failed(&mut self, error: &str) -> Result<(), Error> {
+    let now = now_secs();
+    let (status, next_attempt_at) = if self.attempts >= self.max_attempts {
+      (EmbeddingJobStatus::Dead, self.next_attempt_at)
+    } else {
+      let exponent = self.attempts.saturating_sub(1).min(16);
+      let delay = (RETRY_BASE_DELAY_SECS << exponent).min(RETRY_MAX_DELAY_SECS);
+      (EmbeddingJobStatus::Failed, now + delay)
+    };
+
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE embedding_jobs
+       SET status = ?2, last_error = ?3, next_attempt_at = ?4, updated_timestamp = ?5
+       WHERE id = ?1",
+      params![self.id, status.as_str(), error, next_attempt_at, now],
+    )?;
+    self.status = status;
+    self.last_error = Some(error.to_string());
+    self.next_attempt_at = next_attempt_at;
+    self.updated_timestamp = Some(now);
+    Ok(())
+  }
+
+  /// Jobs left `running` belong to a process that died mid-job; hand them
+  /// back to the queue. Also trims old finished jobs.
+  pub fn recover_interrupted() -> Result<usize, Error> {
+    let connection = try_get_db_conn()?;
+    let now = now_secs();
+    let recovered = connection.execute(
+      "UPDATE embedding_jobs SET status = 'pending', next_attempt_at = ?1, updated_timestamp = ?1
+       WHERE status = 'running'",
+      params![now],
+    )?;
+    connection.execute(
+      "DELETE FROM embedding_jobs WHERE status = 'done' AND updated_timestamp < ?1",
+      params![now.saturating_sub(DONE_RETENTION_SECS)],
+    )?;
+    Ok(recovered)
+  }
+
+  /// Drops everything that has not finished yet. Dead-lettered jobs are kept.
+  pub fn delete_unfinished() -> Result<usize, Error> {
+    let connection = try_get_db_conn()?;
+    let deleted = connection.execute(
+      "DELETE FROM embedding_jobs WHERE status IN ('pending', 'running', 'failed')",
+      [],
+    )?;
+    Ok(deleted)
+  }
+
+  pub fn find_recent(
+    status: Option<EmbeddingJobStatus>,
+    limit: u32,
+  ) -> Result<Vec<EmbeddingJob>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM embedding_jobs
+         WHERE ?1 IS NULL OR status = ?1
+         ORDER BY updated_timestamp DESC, id DESC
+         LIMIT ?2",
+        EmbeddingJob::COLUMNS
+      ),
+      params![status.map(|status| status.as_str()), limit],
+    )
+  }
+
+  /// Counts jobs by status within the current backlog, i.e. everything
+  /// created since the oldest job that has not finished yet.
+  pub fn progress() -> Result<EmbeddingJobProgress, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "SELECT status, COUNT(*) FROM embedding_jobs
+       WHERE created_timestamp >= COALESCE(
+         (SELECT MIN(created_timestamp) FROM embedding_jobs
+          WHERE status IN ('pending', 'running', 'failed')),
+         ?1
+       ) OR status = 'dead'
+       GROUP BY status",
+    )?;
+    let rows = stmt.query_map(params![now_secs()], |row| {
+      Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+    })?;
+
+    let mut progress = EmbeddingJobProgress::default();
+    for row in rows {
+      let (status, count) = row?;
+      match EmbeddingJobStatus::from_str(&status) {
+        Some(EmbeddingJobStatus::Pending) => progress.pending = count,
+        Some(EmbeddingJobStatus::Running) => progress.running = count,
+        Some(EmbeddingJobStatus::Failed) => progress.failed = count,
+        Some(EmbeddingJobStatus::Done) => progress.done = count,
+        Some(EmbeddingJobStatus::Dead) => progress.dead = count,
+        None => {}
+      }
+    }
+
+    let unfinished = progress.pending + progress.running + progress.failed;
+    progress.progress = if unfinished == 0 {
+      100
+    } else {
+      ((progress.done * 100) / (progress.done + unfinished)) as u16
+    };
+    Ok(progress)
+  }
+}
@@ -0,0 +1,252 @@
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_rows, FromRow};
+use crate::error::Error;
+
+/// One row per embedded chunk. Lets `SemanticService` decide locally whether a
+/// chunk needs a new vector, only a payload update, or nothing at all.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingManifestEntry {
+  pub id: Option<u64>,
+  pub base_id: String,
+  pub chunk_id: u64,
+  pub embed_hash: String,
+  pub metadata_hash: String,
+  pub model_id: String,
+  pub point_ids: Vec<String>,
+  /// The `documents` row the chunk belongs to; unknown for entries written
+  /// before it was tracked.
+  pub document_id: Option<u64>,
+  pub timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestChange {
+  Unchanged,
+  MetadataOnly,
+  Embedding,
+}
+
+impl FromRow for EmbeddingManifestEntry {
+  const COLUMNS: &'static str =
+    "id, base_id, chunk_id, embed_hash, metadata_hash, model_id, point_ids, timestamp, document_id";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    let point_ids: String = row.get(6)?;
+    Ok(EmbeddingManifestEntry {
+      id: Some(row.get(0)?),
+      base_id: row.get(1)?,
+      chunk_id: row.get(2)?,
+      embed_hash: row.get(3)?,
+      metadata_hash: row.get(4)?,
+      model_id: row.get(5)?,
+      point_ids: serde_json::from_str(&point_ids).unwrap_or_default(),
+      timestamp: row.get(7)?,
+      document_id: row.get(8)?,
+    })
+  }
+}
+
+impl EmbeddingManifestEntry {
+  /// Compares the stored hashes against freshly computed ones. A different
+  /// embedding model always forces a new vector.
+  pub fn classify(&self, embed_hash: &str, metadata_hash: &str, model_id: &str) -> ManifestChange {
+    if self.model_id != model_id || self.embed_hash != embed_hash {
+      return ManifestChange::Embedding;
+    }
+    if self.metadata_hash != metadata_hash {
+      return ManifestChange::MetadataOnly;
+    }
+    ManifestChange::Unchanged
+  }
+
+  /// Points written for this entry that a re-embed no longer produces, e.g.
+  /// when the text now splits into fewer chunks.
+  pub fn orphaned_point_ids(&self, point_ids: &[String]) -> Vec<String> {
+    self
+      .point_ids
+      .iter()
+      .filter(|previous_id| !point_ids.contains(previous_id))
+      .cloned()
+      .collect()
+  }
+
+  pub fn upsert(&mut self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    let point_ids = serde_json::to_string(&self.point_ids).unwrap_or("[]".to_string());
+    connection.execute(
+      "INSERT INTO embedding_manifest (base_id, chunk_id, embed_hash, metadata_hash, model_id, point_ids, document_id)
+       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+       ON CONFLICT(base_id, chunk_id) DO UPDATE SET
+         embed_hash = ?3,
+         metadata_hash = ?4,
+         model_id = ?5,
+         point_ids = ?6,
+         document_id = COALESCE(?7, document_id),
+         timestamp = strftime('%s','now')",
+      params![
+        self.base_id,
+        self.chunk_id,
+        self.embed_hash,
+        self.metadata_hash,
+        self.model_id,
+        point_ids,
+        self.document_id
+      ],
+    )?;
+    let mut stmt = connection
+      .prepare("SELECT id FROM embedding_manifest WHERE base_id = ?1 AND chunk_id = ?2")?;
+    self.id = Some(stmt.query_row(params![self.base_id, self.chunk_id], |row| row.get(0))?);
+    Ok(())
+  }
+
+  pub fn find_by_base_ids(base_ids: &[String]) -> Result<Vec<EmbeddingManifestEntry>, Error> {
+    if base_ids.is_empty() {
+      return Ok(vec![]);
+    }
+    let connection = try_get_db_conn()?;
+    let placeholders = base_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let query = format!(
+      "SELECT {} FROM embedding_manifest WHERE base_id IN ({})",
+      EmbeddingManifestEntry::COLUMNS,
+      placeholders
+    );
+    query_rows(&connection, &query, rusqlite::params_from_iter(base_ids))
+  }
+
+  pub fn delete_by_base_ids(base_ids: &[String]) -> Result<(), Error> {
+    if base_ids.is_empty() {
+      return Ok(());
+    }
+    let connection = try_get_db_conn()?;
+    let placeholders = base_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    connection.execute(
+      &format!(
+        "DELETE FROM embedding_manifest WHERE base_id IN ({})",
+        placeholders
+      ),
+      rusqlite::params_from_iter(base_ids),
+    )?;
+    Ok(())
+  }
+
+  /// Drops the entries of purged documents. Returns the number removed.
+  pub fn delete_by_document_ids(document_ids: &[u64]) -> Result<usize, Error> {
+    if document_ids.is_empty() {
+      return Ok(0);
+    }
+    let connection = try_get_db_conn()?;
+    let placeholders = document_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let deleted = connection.execute(
+      &format!(
+        "DELETE FROM embedding_manifest WHERE document_id IN ({})",
+        placeholders
+      ),
+      rusqlite::params_from_iter(document_ids),
+    )?;
+    Ok(deleted)
+  }
+
+  pub fn delete_all() -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM embedding_manifest", [])?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(base_id: &str, model_id: &str, point_ids: &[&str]) -> EmbeddingManifestEntry {
+    EmbeddingManifestEntry {
+      id: None,
+      base_id: base_id.to_string(),
+      chunk_id: 0,
+      embed_hash: "embed".to_string(),
+      metadata_hash: "metadata".to_string(),
+      model_id: model_id.to_string(),
+      point_ids: point_ids.iter().map(|id| id.to_string()).collect(),
+      document_id: None,
+      timestamp: None,
+    }
+  }
+
+  #[test]
+  fn classifies_changes_against_the_stored_hashes() {
+    let model = "model.gguf:10";
+    let stored = entry("doc", model, &[]);
+    assert_eq!(stored.classify("embed", "metadata", model), ManifestChange::Unchanged);
+    assert_eq!(stored.classify("embed", "renamed", model), ManifestChange::MetadataOnly);
+    assert_eq!(stored.classify("edited", "metadata", model), ManifestChange::Embedding);
+    // A new embedder re-embeds even when neither text nor metadata changed.
+    let new_model = "other.gguf:20";
+    assert_eq!(stored.classify("embed", "metadata", new_model), ManifestChange::Embedding);
+    assert_eq!(stored.classify("embed", "renamed", new_model), ManifestChange::Embedding);
+  }
+
+  #[test]
+  fn orphans_only_points_the_re_embed_did_not_rewrite() {
+    let stored = entry("doc", "model.gguf:10", &["a", "b", "c"]);
+    let rewritten = vec!["a".to_string(), "d".to_string()];
+    assert_eq!(stored.orphaned_point_ids(&rewritten), vec!["b".to_string(), "c".to_string()]);
+    assert!(stored.orphaned_point_ids(&stored.point_ids).is_empty());
+  }
+
+  #[test]
+  fn upsert_replaces_the_entry_for_the_same_chunk() {
+    let base_id = "manifest-test-upsert".to_string();
+    let mut first = entry(&base_id, "old.gguf:1", &["p1", "p2"]);
+    first.upsert().unwrap();
+
+    let mut re_embedded = entry(&base_id, "new.gguf:2", &["p1"]);
+    re_embedded.embed_hash = "edited".to_string();
+    re_embedded.upsert().unwrap();
+    assert_eq!(first.id, re_embedded.id);
+
+    let mut other_chunk = entry(&base_id, "new.gguf:2", &["p3"]);
+    other_chunk.chunk_id = 1;
+    other_chunk.upsert().unwrap();
+
+    let mut stored = EmbeddingManifestEntry::find_by_base_ids(&[base_id.clone()]).unwrap();
+    stored.sort_by_key(|stored_entry| stored_entry.chunk_id);
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].model_id, "new.gguf:2");
+    assert_eq!(stored[0].embed_hash, "edited");
+    assert_eq!(stored[0].point_ids, vec!["p1".to_string()]);
+    assert_eq!(
+      stored[0].classify("edited", "metadata", "new.gguf:2"),
+      ManifestChange::Unchanged
+    );
+
+    EmbeddingManifestEntry::delete_by_base_ids(&[base_id.clone()]).unwrap();
+    assert!(EmbeddingManifestEntry::find_by_base_ids(&[base_id]).unwrap().is_empty());
+  }
+
+  #[test]
+  fn deletes_entries_by_document() {
+    let base_id = "manifest-test-document".to_string();
+    let mut purged = entry(&base_id, "model.gguf:10", &["p1"]);
+    purged.document_id = Some(9_380_001);
+    purged.upsert().unwrap();
+
+    // An upsert without a document id keeps the one already recorded.
+    let mut unchanged = entry(&base_id, "model.gguf:10", &["p1"]);
+    unchanged.upsert().unwrap();
+
+    let mut kept = entry(&base_id, "model.gguf:10", &["p2"]);
+    kept.chunk_id = 1;
+    kept.document_id = Some(9_380_002);
+    kept.upsert().unwrap();
+
+    assert_eq!(EmbeddingManifestEntry::delete_by_document_ids(&[9_380_001]).unwrap(), 1);
+    let remaining = EmbeddingManifestEntry::find_by_base_ids(&[base_id.clone()]).unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].document_id, Some(9_380_002));
+
+    EmbeddingManifestEntry::delete_by_base_ids(&[base_id]).unwrap();
+  }
+}
@@ -3,7 +3,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::error::Error;
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::utils::log::knap_log_error;
 use crate::db::models::automation::Automation;
 use crate::db::models::automation_run::AutomationRun;
@@ -53,8 +54,10 @@ impl Default for FeedItem {
   }
 }
 
-impl FeedItem {
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+impl FromRow for FeedItem {
+  const COLUMNS: &'static str = "id, title, timestamp, deleted";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     Ok(FeedItem {
       id: Some(row.get(0)?),
       title: Some(row.get(1)?),
@@ -64,42 +67,38 @@ impl FeedItem {
       .map(|v| v != 0),
     })
   }
+}
 
+impl FeedItem {
   pub fn find_by_id(id: u64) -> Result<Option<FeedItem>, Error> {
-    let connection = get_db_conn();
-    let mut stmt =
-      connection.prepare("SELECT f.id, f.title, f.timestamp, f.deleted FROM feed_items AS f WHERE (deleted is NULL OR deleted = 0) AND id = ?1")?;
-    let feed_item = stmt.query_row([id], |row| FeedItem::build_struct_from_row(row));
-
-    match feed_item {
-      Ok(feed_item) => Ok(Some(feed_item)),
-      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-      Err(err) => Err(err.into()),
-    }
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM feed_items WHERE (deleted is NULL OR deleted = 0) AND id = ?1",
+        Self::COLUMNS
+      ),
+      [id],
+    )
   }
 
   pub fn find_all_complete() -> Result<Vec<FeedItemComplete>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT f.id, f.title, f.timestamp, f.deleted FROM feed_items AS f WHERE (f.deleted IS NULL OR f.deleted = 0)
-         ORDER BY f.timestamp DESC",
-    ).map_err(|e| {
-      log::error!("Failed to prepare feed items query: {:?}", e);
-      let error_msg = format!("Failed to prepare feed items query: {:?}", e);
-      Error::KSError(error_msg)
+    let connection = try_get_db_conn()?;
+    let feed_items: Vec<FeedItem> = query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM feed_items WHERE (deleted IS NULL OR deleted = 0) ORDER BY timestamp DESC",
+        Self::COLUMNS
+      ),
+      [],
+    )
+    .map_err(|e| {
+      log::error!("Failed to read feed items: {:?}", e);
+      e
     })?;
-    let feed_item_iter = stmt.query_map([], |row| FeedItem::build_struct_from_row(row))?;
 
     let mut feed_items_complete = Vec::new();
-    for feed_item_result in feed_item_iter {
-      let feed_item = match feed_item_result {
-        Ok(item) => item,
-        Err(e) => {
-          log::error!("Error processing feed item: {:?}", e);
-          knap_log_error("Couldn't retrieve feed item in find_all_complete".to_string(), Some(Error::from(e)), None);
-          continue;
-        }
-      };
+    for feed_item in feed_items {
       let threads_with_messages = match Thread::find_by_feed_item_id(feed_item.id.unwrap()) {
         Ok(threads) => threads,
         Err(e) => {
@@ -153,12 +152,7 @@ impl FeedItem {
   }
 
   pub fn find_by_id_complete(id: u64) -> Result<FeedItemComplete, Error> {
-    let connection = get_db_conn();
-    let mut stmt =
-      connection.prepare("SELECT f.id, f.title, f.timestamp, f.deleted FROM feed_items AS f WHERE (deleted is NULL OR deleted = 0) AND id = ?1")?;
-    let feed_item_result = stmt.query_row([id], |row| FeedItem::build_struct_from_row(row));
-
-    let feed_item = feed_item_result?;
+    let feed_item = FeedItem::find_by_id(id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
     let threads_with_messages = Thread::find_by_feed_item_id(feed_item.id.unwrap())?;
     let automation_run = AutomationRun::find_by_feed_item_id(feed_item.id.unwrap())?;
     let automation = match &automation_run {
@@ -180,7 +174,7 @@ impl FeedItem {
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
     if self.title.is_none() {
       return Err(Error::KSError(
@@ -211,10 +205,12 @@ impl FeedItem {
       let existing_id = check_stmt.query_row(params![title, timestamp], |row| row.get::<_, u64>(0));
 
       if let Ok(id) = existing_id {
-        let mut stmt =
-      connection.prepare("SELECT f.id, f.title, f.timestamp, f.deleted FROM feed_items AS f WHERE id = ?1")?;
-        let feed_item = stmt.query_row([id], |row| FeedItem::build_struct_from_row(row));
-        if let Ok(existing_feed_item) = feed_item {
+        let feed_item: Option<FeedItem> = query_optional(
+          &connection,
+          &format!("SELECT {} FROM feed_items WHERE id = ?1", Self::COLUMNS),
+          [id],
+        )?;
+        if let Some(existing_feed_item) = feed_item {
           self.id = existing_feed_item.id;
           self.title = existing_feed_item.title;
           self.timestamp = existing_feed_item.timestamp;
@@ -252,14 +248,14 @@ impl FeedItem {
         "Cannot delete FeedItem; FeedItem does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("UPDATE feed_items set deleted = 1 WHERE id = ?1")?;
     stmt.execute([self.id])?;
     Ok(())
   }
 
   pub fn delete_multiple(ids: &[u64]) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("UPDATE feed_items set deleted = \"true\" WHERE id IN (?)")?;
     match stmt.execute(params_from_iter(ids.iter().cloned())) {
       Ok(_) => Ok(()),
@@ -277,7 +273,7 @@ impl FeedItem {
         "Cannot update Feed Item; Feed Item does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
     let mut stmt = connection.prepare("UPDATE feed_items SET title = ?2, deleted = ?3 WHERE id = ?1")?;
 
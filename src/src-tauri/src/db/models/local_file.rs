@@ -1,13 +1,14 @@
 use std::collections::HashMap;
 
-use rusqlite::{params, OptionalExtension, Result};
+use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha256::digest;
 use uuid::Uuid;
 
 use crate::connections::data_source::KnowledgeSnippet;
-use crate::db::{db::get_db_conn, models::document::Document};
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::db::{db::try_get_db_conn, models::document::Document};
 use crate::error::Error;
 use crate::local_fs;
 
@@ -25,82 +26,55 @@ pub struct LocalFile {
   pub timestamp: Option<u64>,
 }
 
+impl FromRow for LocalFile {
+  const COLUMNS: &'static str = "id, filename, path, file_size, date_modified, date_created, title, summary, checksum, timestamp";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(LocalFile {
+      id: Some(row.get(0)?),
+      filename: row.get(1)?,
+      path: row.get(2)?,
+      file_size: row.get(3)?,
+      date_modified: row.get(4)?,
+      date_created: row.get(5)?,
+      title: row.get(6)?,
+      summary: row.get(7)?,
+      checksum: row.get(8)?,
+      timestamp: row.get(9)?,
+    })
+  }
+}
+
 impl LocalFile {
   pub fn find_by_id(id: u64) -> Result<Option<LocalFile>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare("SELECT * FROM local_files WHERE id = ?1")?;
-    let local_file = stmt
-      .query_row([id], |row| {
-        Ok(LocalFile {
-          id: Some(row.get(0)?),
-          filename: row.get(1)?,
-          path: row.get(2)?,
-          file_size: row.get(3)?,
-          date_modified: row.get(4)?,
-          date_created: row.get(5)?,
-          title: row.get(6)?,
-          summary: row.get(7)?,
-          checksum: row.get(8)?,
-          timestamp: row.get(9)?,
-        })
-      })
-      .optional()?;
-
-    Ok(local_file)
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM local_files WHERE id = ?1", Self::COLUMNS),
+      [id],
+    )
   }
 
   pub fn find_all() -> Result<Vec<LocalFile>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare("SELECT * FROM local_files ORDER BY timestamp DESC")?;
-    let file_iter = stmt.query_map([], |row| {
-      Ok(LocalFile {
-        id: Some(row.get(0)?),
-        filename: row.get(1)?,
-        path: row.get(2)?,
-        file_size: row.get(3)?,
-        date_modified: row.get(4)?,
-        date_created: row.get(5)?,
-        title: row.get(6)?,
-        summary: row.get(7)?,
-        checksum: row.get(8)?,
-        timestamp: row.get(9)?,
-      })
-    })?;
-
-    let mut files = Vec::new();
-    for file in file_iter {
-      files.push(file?);
-    }
-    Ok(files)
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM local_files ORDER BY timestamp DESC", Self::COLUMNS),
+      [],
+    )
   }
 
   pub fn find_by_filename(filename: String) -> Result<Option<LocalFile>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare("SELECT * FROM local_files WHERE filename = ?1")?;
-    let result = stmt.query_row([filename], |row| {
-      Ok(LocalFile {
-        id: Some(row.get(0)?),
-        filename: row.get(1)?,
-        path: row.get(2)?,
-        file_size: row.get(3)?,
-        date_modified: row.get(4)?,
-        date_created: row.get(5)?,
-        title: row.get(6)?,
-        summary: row.get(7)?,
-        checksum: row.get(8)?,
-        timestamp: row.get(9)?,
-      })
-    });
-
-    match result {
-      Ok(local_file) => Ok(Some(local_file)),
-      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-      Err(e) => Err(e.into()),
-    }
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM local_files WHERE filename = ?1", Self::COLUMNS),
+      [filename],
+    )
   }
 
   pub fn upsert(&mut self) -> Result<Self, Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
     // First, check if a file with this filename already exists
     // and has a different checksum
@@ -165,8 +139,8 @@ impl LocalFile {
     Ok(self.clone())
   }
 
-  pub fn count() -> Result<u64> {
-    let connection = get_db_conn();
+  pub fn count() -> Result<u64, Error> {
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("SELECT count(*) FROM local_files")?;
     let count = stmt.query_row(params![], |row| Ok(row.get::<_, u64>(0)?))?;
 
@@ -179,7 +153,7 @@ impl LocalFile {
         "Cannot delete LocalFile; LocalFile does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("DELETE FROM local_files WHERE id = ?1")?;
     stmt.execute([self.id])?;
     Ok(())
@@ -190,7 +164,7 @@ impl LocalFile {
       Some(s) => s.clone(),
       None => "".to_string(),
     };
-    let text_chunks = match local_fs::read_file_contents(&self) {
+    let text_chunks = match local_fs::read_file_chunks(&self) {
       Ok(contents) => contents,
       Err(e) => {
         log::debug!(
@@ -201,12 +175,24 @@ impl LocalFile {
       }
     };
 
-    let hash = digest(text_chunks.join(""));
+    let hash = digest(
+      text_chunks
+        .iter()
+        .map(|chunk| chunk.text.as_str())
+        .collect::<Vec<_>>()
+        .join(""),
+    );
 
     let mut documents = Vec::new();
 
     let mut document =
-      Document::find_by_foreign_table_and_id("local_files", self.id.unwrap()).unwrap();
+      match Document::find_by_foreign_table_and_id("local_files", self.id.unwrap()) {
+        Ok(document) => document,
+        Err(error) => {
+          log::error!("Error finding document for local file {:?}: {:?}", self.id, error);
+          return documents;
+        }
+      };
 
     if document.is_none() {
       document = Some(create_local_files_document(self.id.unwrap(), hash.clone()));
@@ -220,7 +206,7 @@ impl LocalFile {
     let document_id = document.unwrap().id.unwrap();
 
     for (chunk_idx, chunk) in text_chunks.iter().enumerate() {
-      let doc_payload = HashMap::from([
+      let mut doc_payload = HashMap::from([
         (
           "id".to_string(),
           serde_json::Value::String(
@@ -233,7 +219,15 @@ impl LocalFile {
         ),
         (
           "content".to_string(),
-          serde_json::Value::String(chunk.to_string()),
+          serde_json::Value::String(chunk.text.clone()),
+        ),
+        (
+          "chunk_start".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(chunk.start)),
+        ),
+        (
+          "chunk_end".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(chunk.end)),
         ),
         (
           "chunk_id".to_string(),
@@ -251,6 +245,10 @@ impl LocalFile {
           "file_size".to_string(),
           serde_json::Value::Number(serde_json::Number::from(self.file_size.clone())),
         ),
+        (
+          "date".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(self.date_modified)),
+        ),
         (
           "title".to_string(),
           serde_json::Value::String(self.title.clone()),
@@ -268,6 +266,18 @@ impl LocalFile {
           serde_json::Value::Number(serde_json::Number::from(document_id)),
         ),
       ]);
+      if let Some(page) = chunk.page {
+        doc_payload.insert(
+          "page".to_string(),
+          serde_json::Value::Number(serde_json::Number::from(page)),
+        );
+      }
+      if let Some(heading) = &chunk.heading {
+        doc_payload.insert(
+          "heading".to_string(),
+          serde_json::Value::String(heading.clone()),
+        );
+      }
       documents.push(doc_payload);
     }
 
@@ -291,9 +301,14 @@ impl LocalFile {
         "metadata",
         vec![
           "file_size".to_string(),
+          "date".to_string(),
           "type".to_string(),
           "document_id".to_string(),
           "chunk_id".to_string(),
+          "chunk_start".to_string(),
+          "chunk_end".to_string(),
+          "page".to_string(),
+          "heading".to_string(),
         ],
       ),
     ])
@@ -0,0 +1,111 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+use crate::connections::data_source::KnowledgeSnippet;
+use crate::db::models::transcript::{meeting_attrs, meeting_chunk_payloads, MeetingDetails};
+use crate::error::Error;
+use crate::memory::text_splitter::{ContentType, TextSplitter};
+
+/// The user's notes for a meeting. They live in `~/.knapsack/notes/{thread_id}`
+/// rather than in a table, so their `documents` row is keyed by thread id.
+#[derive(Debug, Clone)]
+pub struct MeetingNotes {
+  pub thread_id: u64,
+  pub content: String,
+}
+
+impl MeetingNotes {
+  pub fn path(thread_id: u64) -> PathBuf {
+    crate::config::notes_dir().join(thread_id.to_string())
+  }
+
+  pub fn find_by_thread_id(thread_id: u64) -> Result<Option<MeetingNotes>, Error> {
+    let path = MeetingNotes::path(thread_id);
+    if !path.exists() {
+      return Ok(None);
+    }
+    Ok(Some(MeetingNotes {
+      thread_id,
+      content: read_to_string(path)?,
+    }))
+  }
+
+  pub fn get_documents(&self) -> Vec<HashMap<String, Value>> {
+    meeting_chunk_payloads(
+      "meeting_notes",
+      "meeting_notes",
+      self.thread_id,
+      self.thread_id,
+      &self.content,
+      ContentType::Markdown,
+    )
+  }
+
+  pub fn get_attrs() -> HashMap<&'static str, Vec<String>> {
+    meeting_attrs()
+  }
+}
+
+impl KnowledgeSnippet for MeetingNotes {
+  fn get_title(&self) -> String {
+    format!("Notes: {}", MeetingDetails::for_thread(self.thread_id).title)
+  }
+
+  fn get_document_type(&self) -> String {
+    "meeting_notes".to_string()
+  }
+
+  fn to_prompt_string(&self, chunk_ids: Option<Vec<u64>>, _payloads: Option<Vec<Value>>) -> String {
+    let chunk_ids = chunk_ids.unwrap_or(vec![]);
+    let title = self.get_title();
+
+    let mut prompt = format!("\n\n> Start of excerpts from: {}", title);
+    if chunk_ids.len() == 0 {
+      prompt = format!("{}\n\n{}", prompt, self.content);
+    } else {
+      let chunks = TextSplitter::for_content_type(ContentType::Markdown).split_chunks(&self.content);
+      for (chunk_idx, chunk) in chunks.iter().enumerate() {
+        if chunk_ids.contains(&(chunk_idx as u64)) {
+          prompt = format!("{}\n\n{}", prompt, chunk.text);
+        }
+      }
+    }
+    format!("{}\n> End of excerpts from: {}", prompt, title)
+  }
+
+  fn get_summary(&self) -> String {
+    TextSplitter::for_content_type(ContentType::Markdown)
+      .split_text(&self.content)
+      .first()
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  fn get_hyperlink(&self) -> String {
+    "".to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn prompt_string_only_includes_the_matched_chunks() {
+    let notes = MeetingNotes {
+      thread_id: 9_300_002,
+      content: "# Pricing\nAcme wants a discount.\n\n# Hiring\nTwo open roles.\n".to_string(),
+    };
+    let prompt = notes.to_prompt_string(Some(vec![1]), None);
+    assert!(prompt.contains("Two open roles."));
+    assert!(!prompt.contains("Acme wants a discount."));
+    assert!(prompt.contains("Start of excerpts from: Notes: Meeting"));
+
+    let prompt = notes.to_prompt_string(None, None);
+    assert!(prompt.contains("Acme wants a discount."));
+    assert!(prompt.contains("Two open roles."));
+    assert_eq!(notes.get_summary(), "# Pricing\nAcme wants a discount.");
+  }
+}
@@ -1,7 +1,8 @@
 use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 
 use super::message_feedback::MessageFeedback;
 use crate::error::Error;
@@ -18,27 +19,17 @@ pub struct Message {
   pub feedbacks: Option<Vec<MessageFeedback>>,
   pub document_ids: Option<Vec<u64>>,
 }
-impl Message {
-  pub fn find_by_id(id: u64) -> Result<Option<Message>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT id, timestamp, thread_id, user_id, content, content_facade FROM messages WHERE id = ?1",
-    )?;
-    let message = stmt.query_row([id], |row| Message::build_struct_from_row(row));
 
-    match message {
-      Ok(message) => Ok(Some(message)),
-      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-      Err(err) => Err(err.into()),
-    }
-  }
+impl FromRow for Message {
+  const COLUMNS: &'static str =
+    "id, timestamp, thread_id, user_id, content, content_facade, document_ids";
 
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     let document_ids: Option<Vec<u64>> = row
       .get::<_, Option<String>>(6)?
       .map(|s| s.split(',').filter_map(|id| id.parse().ok()).collect());
 
-    let mut message = Message {
+    Ok(Message {
       id: Some(row.get(0)?),
       timestamp: row.get(1)?,
       thread_id: row.get(2)?,
@@ -47,27 +38,31 @@ impl Message {
       content_facade: row.get(5)?,
       feedbacks: None,
       document_ids,
-    };
+    })
+  }
+}
 
-    Ok(message)
+impl Message {
+  pub fn find_by_id(id: u64) -> Result<Option<Message>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM messages WHERE id = ?1", Self::COLUMNS),
+      [id],
+    )
   }
 
   pub fn find_all() -> Result<Vec<Message>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT id, timestamp, thread_id, user_id, content, content_facade, document_ids FROM messages ORDER BY timestamp DESC",
-    )?;
-    let message_iter = stmt.query_map([], |row| Message::build_struct_from_row(row))?;
-
-    let mut messages = Vec::new();
-    for message in message_iter {
-      messages.push(message?);
-    }
-    Ok(messages)
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM messages ORDER BY timestamp DESC", Self::COLUMNS),
+      [],
+    )
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare(
       "INSERT INTO messages (timestamp, thread_id, user_id, content, content_facade, document_ids) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
     )?;
@@ -94,7 +89,7 @@ impl Message {
   }
 
   pub fn create_once(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare(
       "INSERT INTO messages (id, timestamp, thread_id, user_id, content, content_facade, document_ids) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
     )?;
@@ -130,7 +125,7 @@ impl Message {
         "Cannot update Message; Message does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
         .prepare("UPDATE messages SET timestamp = ?2, thread_id = ?3, user_id = ?4, content = ?5, content_facade = ?6, document_ids = ?7 WHERE id = ?1")?;
 
@@ -160,22 +155,21 @@ impl Message {
         "Cannot delete Message; Message does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("DELETE FROM messages WHERE id = ?1")?;
     stmt.execute([self.id])?;
     Ok(())
   }
 
   pub fn find_by_thread_id(thread_id: u64) -> Result<Vec<Message>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, timestamp, thread_id, user_id, content, content_facade, document_ids FROM messages WHERE thread_id = ?1 ORDER BY id ASC")?;
-    let message_iter = stmt.query_map([thread_id], |row| Message::build_struct_from_row(row))?;
-
-    let mut messages = Vec::new();
-    for message in message_iter {
-      messages.push(message?);
-    }
-    Ok(messages)
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM messages WHERE thread_id = ?1 ORDER BY id ASC",
+        Self::COLUMNS
+      ),
+      [thread_id],
+    )
   }
 }
@@ -1,7 +1,8 @@
-use rusqlite::{params, OptionalExtension, Result};
+use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -14,75 +15,68 @@ pub struct MessageFeedback {
   pub timestamp: u64,
 }
 
-impl MessageFeedback {
-  pub fn find_by_id(id: u64) -> Result<Option<MessageFeedback>> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT id, user_id, message_id, feedback, timestamp FROM message_feedbacks WHERE id = ?1",
-    )?;
-    let feedback = stmt
-      .query_row(params![id], |row| {
-        MessageFeedback::build_struct_from_row(row)
-      })
-      .optional()?;
+impl FromRow for MessageFeedback {
+  const COLUMNS: &'static str = "id, user_id, message_id, feedback, timestamp";
 
-    Ok(feedback)
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(MessageFeedback {
+      id: Some(row.get(0)?),
+      // automation_name: row.get(1)?,
+      user_id: row.get(1)?,
+      message_id: row.get(2)?,
+      feedback: row.get(3)?,
+      timestamp: row.get(4)?,
+    })
   }
+}
 
-  pub fn find_by_user_id(user_id: u64) -> Result<Vec<MessageFeedback>> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, user_id, message_id, feedback, timestamp FROM message_feedbacks where user_id = ?1")?;
-    let rows = stmt.query_map([user_id], |row| MessageFeedback::build_struct_from_row(row))?;
+impl MessageFeedback {
+  pub fn find_by_id(id: u64) -> Result<Option<MessageFeedback>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM message_feedbacks WHERE id = ?1",
+        MessageFeedback::COLUMNS
+      ),
+      params![id],
+    )
+  }
 
-    let mut feedbacks = Vec::new();
-    for feedback in rows {
-      feedbacks.push(feedback?);
-    }
-    Ok(feedbacks)
+  pub fn find_by_user_id(user_id: u64) -> Result<Vec<MessageFeedback>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM message_feedbacks WHERE user_id = ?1",
+        MessageFeedback::COLUMNS
+      ),
+      [user_id],
+    )
   }
 
-  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<MessageFeedback>> {
-    let connection = get_db_conn();
+  pub fn find_by_ids(ids: Vec<u64>) -> Result<Vec<MessageFeedback>, Error> {
+    let connection = try_get_db_conn()?;
     let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
     let query = format!(
-      "SELECT id, user_id, message_id, feedback, timestamp FROM message_feedbacks WHERE id IN ({})",
+      "SELECT {} FROM message_feedbacks WHERE id IN ({})",
+      MessageFeedback::COLUMNS,
       placeholders
     );
-    let mut stmt = connection.prepare(&query)?;
-
     let id_refs: Vec<&u64> = ids.iter().collect();
-    let rows = stmt.query_map(rusqlite::params_from_iter(id_refs), |row| {
-      MessageFeedback::build_struct_from_row(row)
-    })?;
-
-    let mut feedbacks = Vec::new();
-    for feedback in rows {
-      feedbacks.push(feedback?);
-    }
-    Ok(feedbacks)
+    query_rows(&connection, &query, rusqlite::params_from_iter(id_refs))
   }
 
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
-    Ok(MessageFeedback {
-      id: Some(row.get(0)?),
-      // automation_name: row.get(1)?,
-      user_id: row.get(1)?,
-      message_id: row.get(2)?,
-      feedback: row.get(3)?,
-      timestamp: row.get(4)?,
-    })
-  }
-
-  pub fn find_all() -> Vec<Self> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, user_id, message_id, feedback, timestamp FROM message_feedbacks ORDER BY date DESC")
-      .expect("could not prepare query get automation feedbacks");
-    let rows = stmt
-      .query_map([], |row| MessageFeedback::build_struct_from_row(row))
-      .expect("Could not execute query");
-    rows.filter_map(Result::ok).collect()
+  pub fn find_all() -> Result<Vec<Self>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM message_feedbacks ORDER BY timestamp DESC",
+        MessageFeedback::COLUMNS
+      ),
+      [],
+    )
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
@@ -91,14 +85,12 @@ impl MessageFeedback {
         "Cannot create MessageFeedbacks; MessageFeedbacks already exists.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
-    connection
-      .execute(
-        "INSERT INTO message_feedbacks (user_id, message_id, feedback) VALUES (?1, ?2, ?3)",
-        (&self.user_id, &self.message_id, &self.feedback),
-      )
-      .expect("Could not insert automation feedback");
+    connection.execute(
+      "INSERT INTO message_feedbacks (user_id, message_id, feedback) VALUES (?1, ?2, ?3)",
+      (&self.user_id, &self.message_id, &self.feedback),
+    )?;
 
     self.id = Some(connection.last_insert_rowid() as u64);
     Ok(())
@@ -110,24 +102,20 @@ impl MessageFeedback {
         "Cannot update MessageFeedbacks; MessageFeedbacks does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "UPDATE message_feedbacks SET feedback = ?1 WHERE id = ?2",
-        (&self.feedback, &self.id),
-      )
-      .expect("Could not update automation feedback");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE message_feedbacks SET feedback = ?1 WHERE id = ?2",
+      (&self.feedback, &self.id),
+    )?;
     Ok(())
   }
 
   pub fn upsert(user_id: u64, message_id: u64, feedback: i32) -> Result<(), Error> {
-    let connection = get_db_conn();
-    connection
-      .execute(
-        "INSERT INTO message_feedbacks (user_id, message_id, feedback) VALUES (?1, ?2, ?3) ON CONFLICT (user_id, message_id) DO UPDATE SET feedback = ?3",
-        (&user_id, &message_id, &feedback),
-      )
-      .expect("Could not upsert automation feedback");
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO message_feedbacks (user_id, message_id, feedback) VALUES (?1, ?2, ?3) ON CONFLICT (user_id, message_id) DO UPDATE SET feedback = ?3",
+      (&user_id, &message_id, &feedback),
+    )?;
     Ok(())
   }
 
@@ -137,10 +125,8 @@ impl MessageFeedback {
         "Cannot delete MessageFeedbacks; MessageFeedbacks does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
-    connection
-      .execute("DELETE FROM message_feedbacks WHERE id = ?1", [self.id])
-      .expect("Could not delete automation feedback");
+    let connection = try_get_db_conn()?;
+    connection.execute("DELETE FROM message_feedbacks WHERE id = ?1", [self.id])?;
     Ok(())
   }
 }
@@ -1,14 +1,18 @@
 pub mod automation;
+pub mod automation_action;
 pub mod automation_run;
+pub mod automation_schedule;
 pub mod automation_step;
 pub mod cadence_trigger;
 pub mod calendar_event;
 pub mod connection;
+pub mod contact;
 pub mod data_source_trigger;
 pub mod document;
 pub mod drive_document;
 pub mod email;
 pub mod local_file;
+pub mod meeting_notes;
 pub mod message;
 pub mod message_feedback;
 pub mod thread;
@@ -17,3 +21,7 @@ pub mod user;
 pub mod user_connection;
 pub mod feed_item;
 pub mod token_usage;
+pub mod embedding_manifest;
+pub mod embedding_job;
+pub mod retention_policy;
+pub mod sync_run;
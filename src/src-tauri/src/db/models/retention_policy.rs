@@ -0,0 +1,188 @@
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::error::Error;
+
+/// Kinds of data a retention policy can expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionDataType {
+  /// Meeting transcripts, their files and vectors.
+  Transcripts,
+  /// Email bodies and vectors; subject, participants and dates are kept.
+  EmailBodies,
+  /// Recorded audio chunks left in the audio directory.
+  Audio,
+}
+
+impl RetentionDataType {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      RetentionDataType::Transcripts => "transcripts",
+      RetentionDataType::EmailBodies => "email_bodies",
+      RetentionDataType::Audio => "audio",
+    }
+  }
+
+  pub fn from_str(data_type: &str) -> Option<Self> {
+    match data_type {
+      "transcripts" => Some(RetentionDataType::Transcripts),
+      "email_bodies" => Some(RetentionDataType::EmailBodies),
+      "audio" => Some(RetentionDataType::Audio),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+  pub data_type: RetentionDataType,
+  pub retain_days: u32,
+  pub is_enabled: bool,
+  pub last_applied: Option<u64>,
+}
+
+impl FromRow for RetentionPolicy {
+  const COLUMNS: &'static str = "data_type, retain_days, is_enabled, last_applied";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    let data_type: String = row.get(0)?;
+    let data_type = RetentionDataType::from_str(&data_type).ok_or_else(|| {
+      rusqlite::Error::FromSqlConversionFailure(
+        0,
+        rusqlite::types::Type::Text,
+        format!("Unknown retention data type {}", data_type).into(),
+      )
+    })?;
+    Ok(RetentionPolicy {
+      data_type,
+      retain_days: row.get(1)?,
+      is_enabled: row.get(2)?,
+      last_applied: row.get(3)?,
+    })
+  }
+}
+
+impl RetentionPolicy {
+  pub fn find_all() -> Result<Vec<RetentionPolicy>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM retention_policies ORDER BY data_type",
+        RetentionPolicy::COLUMNS
+      ),
+      [],
+    )
+  }
+
+  pub fn find_by_data_type(data_type: RetentionDataType) -> Result<Option<RetentionPolicy>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM retention_policies WHERE data_type = ?1",
+        RetentionPolicy::COLUMNS
+      ),
+      params![data_type.as_str()],
+    )
+  }
+
+  pub fn upsert(&self) -> Result<(), Error> {
+    if self.retain_days == 0 {
+      return Err(Error::KSError(
+        "Retention must keep data for at least one day".into(),
+      ));
+    }
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO retention_policies (data_type, retain_days, is_enabled)
+       VALUES (?1, ?2, ?3)
+       ON CONFLICT(data_type) DO UPDATE SET
+         retain_days = ?2,
+         is_enabled = ?3,
+         updated_timestamp = strftime('%s','now')",
+      params![self.data_type.as_str(), self.retain_days, self.is_enabled],
+    )?;
+    Ok(())
+  }
+
+  pub fn delete(data_type: RetentionDataType) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "DELETE FROM retention_policies WHERE data_type = ?1",
+      params![data_type.as_str()],
+    )?;
+    Ok(())
+  }
+
+  pub fn mark_applied(data_type: RetentionDataType, applied_at: u64) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE retention_policies SET last_applied = ?2 WHERE data_type = ?1",
+      params![data_type.as_str(), applied_at],
+    )?;
+    Ok(())
+  }
+
+  /// Data created before this unix timestamp is expired.
+  pub fn cutoff(&self, now: u64) -> u64 {
+    now.saturating_sub(self.retain_days as u64 * 24 * 60 * 60)
+  }
+}
+
+/// Audit record of one purge, kept so it can be shown what was removed and
+/// when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPurge {
+  pub id: Option<u64>,
+  /// A `RetentionDataType` name, or `orphaned_documents`.
+  pub data_type: String,
+  pub purged_count: u64,
+  pub details: Option<String>,
+  pub created_timestamp: Option<u64>,
+}
+
+impl FromRow for RetentionPurge {
+  const COLUMNS: &'static str = "id, data_type, purged_count, details, created_timestamp";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(RetentionPurge {
+      id: row.get(0)?,
+      data_type: row.get(1)?,
+      purged_count: row.get(2)?,
+      details: row.get(3)?,
+      created_timestamp: row.get(4)?,
+    })
+  }
+}
+
+impl RetentionPurge {
+  pub fn create(&mut self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO retention_purges (data_type, purged_count, details) VALUES (?1, ?2, ?3)",
+      params![self.data_type, self.purged_count, self.details],
+    )?;
+    self.id = Some(connection.last_insert_rowid() as u64);
+    Ok(())
+  }
+
+  pub fn find_recent(limit: u32) -> Result<Vec<RetentionPurge>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM retention_purges
+         ORDER BY created_timestamp DESC, id DESC
+         LIMIT ?1",
+        RetentionPurge::COLUMNS
+      ),
+      params![limit],
+    )
+  }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+
+use rusqlite::{params, Result};
+use serde::Serialize;
+
+use crate::db::db::try_get_db_conn;
+use crate::db::models::user_connection::{SyncStatus, UserConnection};
+use crate::db::repo::{query_rows, FromRow};
+use crate::error::Error;
+
+/// What one sync of a connection changed, and where the provider said to
+/// resume from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncOutcome {
+  pub items_added: u64,
+  /// Items that were already stored and got written again.
+  pub items_updated: u64,
+  pub items_deleted: u64,
+  /// Gmail historyId, Graph deltaLink or Drive page token.
+  pub cursor: Option<String>,
+}
+
+impl SyncOutcome {
+  /// Splits `fetched` provider ids into added and updated by whether they
+  /// were in `known`, the ids stored before the sync started.
+  pub fn from_ids(known: &HashSet<String>, fetched: &[String], items_deleted: u64) -> Self {
+    let fetched: HashSet<&String> = fetched.iter().collect();
+    let items_updated = fetched.iter().filter(|id| known.contains(**id)).count() as u64;
+    SyncOutcome {
+      items_added: fetched.len() as u64 - items_updated,
+      items_updated,
+      items_deleted,
+      cursor: None,
+    }
+  }
+
+  pub fn with_cursor(mut self, cursor: Option<String>) -> Self {
+    self.cursor = cursor;
+    self
+  }
+}
+
+/// One sync of a user connection, kept so a missing email or file can be
+/// traced to the run that should have fetched it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncRun {
+  pub id: Option<u64>,
+  pub user_connection_id: u64,
+  pub started_at: u64,
+  pub finished_at: Option<u64>,
+  pub status: SyncStatus,
+  pub items_added: u64,
+  pub items_updated: u64,
+  pub items_deleted: u64,
+  pub error: Option<String>,
+  pub cursor: Option<String>,
+}
+
+impl FromRow for SyncRun {
+  const COLUMNS: &'static str = "id, user_connection_id, started_at, finished_at, status, items_added, items_updated, items_deleted, error, cursor";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    let status: String = row.get(4)?;
+    let status = SyncStatus::from_str(&status).ok_or_else(|| {
+      rusqlite::Error::FromSqlConversionFailure(
+        4,
+        rusqlite::types::Type::Text,
+        format!("Unknown sync status {}", status).into(),
+      )
+    })?;
+    Ok(SyncRun {
+      id: row.get(0)?,
+      user_connection_id: row.get(1)?,
+      started_at: row.get(2)?,
+      finished_at: row.get(3)?,
+      status,
+      items_added: row.get(5)?,
+      items_updated: row.get(6)?,
+      items_deleted: row.get(7)?,
+      error: row.get(8)?,
+      cursor: row.get(9)?,
+    })
+  }
+}
+
+impl SyncRun {
+  /// Records the start of a sync and marks the connection as syncing.
+  /// Bookkeeping failures are logged; they never stop the sync itself.
+  pub fn start(user_connection_id: u64) -> SyncRun {
+    let mut sync_run = SyncRun {
+      id: None,
+      user_connection_id,
+      started_at: chrono::Utc::now().timestamp() as u64,
+      finished_at: None,
+      status: SyncStatus::Syncing,
+      items_added: 0,
+      items_updated: 0,
+      items_deleted: 0,
+      error: None,
+      cursor: None,
+    };
+    if let Err(error) = sync_run.create() {
+      log::error!("Failed to record sync of connection {}: {:?}", user_connection_id, error);
+    }
+    if let Err(error) = UserConnection::set_sync_status(user_connection_id, SyncStatus::Syncing, None) {
+      log::error!("Failed to store sync status of connection {}: {:?}", user_connection_id, error);
+    }
+    sync_run
+  }
+
+  fn create(&mut self) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "INSERT INTO sync_runs (user_connection_id, started_at, status) VALUES (?1, ?2, ?3)",
+      params![self.user_connection_id, self.started_at, self.status.as_str()],
+    )?;
+    self.id = Some(connection.last_insert_rowid() as u64);
+    Ok(())
+  }
+
+  /// Stores the outcome of the run on both the run and its connection. A
+  /// successful run also becomes the connection's `last_synced`.
+  pub fn finish(mut self, result: &Result<SyncOutcome, Error>) -> SyncRun {
+    let finished_at = chrono::Utc::now();
+    self.finished_at = Some(finished_at.timestamp() as u64);
+    match result {
+      Ok(outcome) => {
+        self.status = SyncStatus::Synced;
+        self.items_added = outcome.items_added;
+        self.items_updated = outcome.items_updated;
+        self.items_deleted = outcome.items_deleted;
+        self.cursor = outcome.cursor.clone();
+      }
+      Err(error) => {
+        self.status = SyncStatus::Failed;
+        self.error = Some(format!("{:?}", error));
+      }
+    }
+    if let Err(error) = self.update() {
+      log::error!("Failed to record sync of connection {}: {:?}", self.user_connection_id, error);
+    }
+    UserConnection::record_sync_result(self.user_connection_id, result);
+    if result.is_ok() {
+      if let Err(error) = UserConnection::update_last_sync_by_id(self.user_connection_id, finished_at) {
+        log::error!("Failed to store last sync of connection {}: {:?}", self.user_connection_id, error);
+      }
+    }
+    self
+  }
+
+  fn update(&self) -> Result<(), Error> {
+    if self.id.is_none() {
+      return Err(Error::KSError("Cannot update sync run because it is not persisted".into()));
+    }
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE sync_runs SET finished_at = ?2, status = ?3, items_added = ?4, items_updated = ?5, items_deleted = ?6, error = ?7, cursor = ?8 WHERE id = ?1",
+      params![
+        self.id,
+        self.finished_at,
+        self.status.as_str(),
+        self.items_added,
+        self.items_updated,
+        self.items_deleted,
+        self.error,
+        self.cursor
+      ],
+    )?;
+    Ok(())
+  }
+
+  pub fn find_recent_by_user_connection_id(
+    user_connection_id: u64,
+    limit: u32,
+  ) -> Result<Vec<SyncRun>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM sync_runs WHERE user_connection_id = ?1 ORDER BY started_at DESC, id DESC LIMIT ?2",
+        Self::COLUMNS
+      ),
+      params![user_connection_id, limit],
+    )
+  }
+
+  /// Runs cut short by quitting the app would otherwise stay `syncing`.
+  pub fn fail_interrupted() -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE sync_runs SET status = 'failed', error = 'Interrupted' WHERE status = 'syncing'",
+      [],
+    )?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn outcome_splits_fetched_ids_by_whether_they_were_stored() {
+    let known: HashSet<String> = ["a", "b"].iter().map(|id| id.to_string()).collect();
+    let fetched: Vec<String> = ["b", "c", "d", "c"].iter().map(|id| id.to_string()).collect();
+    let outcome = SyncOutcome::from_ids(&known, &fetched, 1);
+    assert_eq!(outcome.items_added, 2);
+    assert_eq!(outcome.items_updated, 1);
+    assert_eq!(outcome.items_deleted, 1);
+  }
+}
@@ -32,7 +32,8 @@ impl ToString for ThreadType {
   }
 }
 
-use crate::db::{db::get_db_conn, models::message::Message};
+use crate::db::repo::{query_optional, query_rows, FromRow};
+use crate::db::{db::try_get_db_conn, models::message::Message};
 
 // add FeedItem ID, title and subtile
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -83,8 +84,10 @@ impl Default for Thread {
   }
 }
 
-impl Thread {
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+impl FromRow for Thread {
+  const COLUMNS: &'static str = "id, timestamp, title, subtitle, hideFollowUp, feed_item_id, thread_type, recorded, saved_transcript, prompt_template";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     let thread_type_str: String = row.get(6)?;
     let thread_type = match ThreadType::from_str(&thread_type_str) {
       Ok(t) => t,
@@ -103,65 +106,44 @@ impl Thread {
       prompt_template: row.get(9)?,
     })
   }
+}
 
+impl Thread {
   pub fn find_by_id(id: u64) -> Result<Option<Thread>, Error> {
-    let connection = get_db_conn();
-    let mut stmt =
-      connection.prepare("SELECT t.id, t.timestamp, t.title, t.subtitle, t.hideFollowUp, t.feed_item_id, t.thread_type, t.recorded, t.saved_transcript, t.prompt_template FROM threads as t WHERE id = ?1")?;
-    let thread = stmt.query_row([id], |row| Thread::build_struct_from_row(row));
-
-    match thread {
-      Ok(thread) => Ok(Some(thread)),
-      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-      Err(err) => Err(err.into()),
-    }
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM threads WHERE id = ?1", Self::COLUMNS),
+      [id],
+    )
   }
 
   pub fn find_all() -> Result<Vec<Thread>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT t.id, t.timestamp, t.title, t.subtitle, t.hideFollowUp, t.feed_item_id, t.thread_type, t.recorded, t.saved_transcript, t.prompt_template FROM threads as t ORDER BY timestamp DESC")?;
-    let thread_iter = stmt.query_map([], |row| Thread::build_struct_from_row(row))?;
-
-    let mut threads = Vec::new();
-    for thread in thread_iter {
-      threads.push(thread?);
-    }
-    Ok(threads)
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM threads ORDER BY timestamp DESC", Self::COLUMNS),
+      [],
+    )
   }
 
   pub fn find_all_with_messages() -> Result<Vec<ThreadWithMessages>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT t.id, t.timestamp, t.title, t.subtitle, t.hideFollowUp, t.feed_item_id, t.thread_type, t.recorded, t.saved_transcript, t.prompt_template FROM threads AS t
-         ORDER BY t.timestamp DESC",
-    )?;
-    let thread_iter = stmt.query_map([], |row| Thread::build_struct_from_row(row))?;
-
     let mut threads_with_messages = Vec::new();
-    for thread_result in thread_iter {
-      let thread = thread_result?;
+    for thread in Thread::find_all()? {
       let messages = Message::find_by_thread_id(thread.id.unwrap())?;
-      let thread_with_messages = ThreadWithMessages { thread, messages };
-      threads_with_messages.push(thread_with_messages);
+      threads_with_messages.push(ThreadWithMessages { thread, messages });
     }
     Ok(threads_with_messages)
   }
 
   pub fn find_by_id_with_messages(id: u64) -> Result<ThreadWithMessages, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "SELECT t.id, t.timestamp, t.title, t.subtitle, t.hideFollowUp, t.feed_item_id, t.thread_type, t.recorded, t.saved_transcript, t.prompt_template FROM threads AS t
-         WHERE id = ?1",
-    )?;
-    let thread = stmt.query_row([id], |row| Thread::build_struct_from_row(row))?;
-    let messages = Message::find_by_thread_id(thread.id.unwrap())?;
-    let thread_with_messages = ThreadWithMessages { thread, messages };
-    Ok(thread_with_messages)
+    let thread = Thread::find_by_id(id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+    let messages = Message::find_by_thread_id(id)?;
+    Ok(ThreadWithMessages { thread, messages })
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
     if self.hide_follow_up.is_none() {
       return Err(Error::KSError(
@@ -203,7 +185,7 @@ impl Thread {
         "Cannot update Thread; Thread does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt =
       connection.prepare("UPDATE threads SET timestamp = ?2, hideFollowUp = ?3, thread_type = ?4, title = ?5, subtitle = ?6, recorded = ?7, saved_transcript = ?8, prompt_template = ?9 WHERE id = ?1")?;
     stmt.execute(params![self.id, self.timestamp, self.hide_follow_up, self.thread_type.to_string(), self.title, self.subtitle, self.recorded, self.saved_transcript, self.prompt_template])?;
@@ -216,27 +198,24 @@ impl Thread {
         "Cannot delete Thread; Thread does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("DELETE FROM threads WHERE id = ?1")?;
     stmt.execute([self.id])?;
     Ok(())
   }
 
   pub fn find_by_feed_item_id(id: u64) -> Result<Option<Vec<ThreadWithMessages>>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-        "SELECT t.id, t.timestamp, t.title, t.subtitle, t.hideFollowUp, t.feed_item_id, t.thread_type, t.recorded, t.saved_transcript, t.prompt_template FROM threads AS t
-         WHERE feed_item_id = ?1",
+    let connection = try_get_db_conn()?;
+    let threads: Vec<Thread> = query_rows(
+      &connection,
+      &format!("SELECT {} FROM threads WHERE feed_item_id = ?1", Self::COLUMNS),
+      params![id],
     )?;
 
-    let thread_iter = stmt.query_map(params![id], |row| Thread::build_struct_from_row(row))?;
-
     let mut threads_with_messages = Vec::new();
-    for thread_result in thread_iter {
-      let thread = thread_result?;
+    for thread in threads {
       let messages = Message::find_by_thread_id(thread.id.unwrap())?;
-      let thread_with_messages = ThreadWithMessages { thread, messages };
-      threads_with_messages.push(thread_with_messages);
+      threads_with_messages.push(ThreadWithMessages { thread, messages });
     }
 
     if threads_with_messages.is_empty() {
@@ -2,7 +2,7 @@ use rusqlite::{params, Result};
 
 use crate::error::Error;
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
 
 #[derive(Debug, Clone)]
 pub struct Thread {
@@ -12,7 +12,7 @@ pub struct Thread {
 
 impl Thread {
   pub fn find_by_id(id: u64) -> Result<Option<Thread>, Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
       .prepare("SELECT id, timestamp FROM threads WHERE id = ?1")?;
     let thread = stmt.query_row([id], |row| {
@@ -30,7 +30,7 @@ impl Thread {
   }
 
   pub fn find_all() -> Result<Vec<Thread>, Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
       .prepare("SELECT id, timestamp FROM threads ORDER BY timestamp DESC")?;
     let thread_iter = stmt.query_map([], |row| {
@@ -48,7 +48,7 @@ impl Thread {
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     if self.id.is_none() && self.timestamp.is_none() {
       let mut stmt = connection
         .prepare("INSERT INTO threads (timestamp) VALUES (CURRENT_TIMESTAMP)")?;
@@ -71,7 +71,7 @@ impl Thread {
     if self.id.is_none() {
       return Err(Error::KSError("Cannot update Thread; Thread does not exist.".into()));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
       .prepare("UPDATE threads SET timestamp = ?2 WHERE id = ?1")?;
     stmt.execute(params![self.id, self.timestamp])?;
@@ -82,7 +82,7 @@ impl Thread {
     if self.id.is_none() {
       return Err(Error::KSError("Cannot delete Thread; Thread does not exist.".into()));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection
       .prepare("DELETE FROM threads WHERE id = ?1")?;
     stmt.execute([self.id])?;
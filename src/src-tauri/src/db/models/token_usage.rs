@@ -2,7 +2,8 @@ use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_rows, FromRow};
 use crate::error::Error;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +17,9 @@ pub struct TokenUsage {
     pub cost_usd: f64,
     pub request_type: String,
     pub timestamp: i64,
+    /// The automation run the request was made for, if any.
+    #[serde(default)]
+    pub automation_run_id: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +43,55 @@ pub struct DailyUsage {
     pub request_count: i64,
 }
 
+impl FromRow for TokenUsage {
+    const COLUMNS: &'static str = "id, provider, model, input_tokens, output_tokens, cost_usd, request_type, timestamp, automation_run_id";
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(TokenUsage {
+            id: Some(row.get(0)?),
+            provider: row.get(1)?,
+            model: row.get(2)?,
+            input_tokens: row.get(3)?,
+            output_tokens: row.get(4)?,
+            cost_usd: row.get(5)?,
+            request_type: row.get(6)?,
+            timestamp: row.get(7)?,
+            automation_run_id: row.get(8)?,
+        })
+    }
+}
+
+impl FromRow for UsageSummary {
+    const COLUMNS: &'static str =
+        "provider, model, SUM(input_tokens), SUM(output_tokens), SUM(cost_usd), COUNT(*)";
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(UsageSummary {
+            provider: row.get(0)?,
+            model: row.get(1)?,
+            total_input_tokens: row.get(2)?,
+            total_output_tokens: row.get(3)?,
+            total_cost_usd: row.get(4)?,
+            request_count: row.get(5)?,
+        })
+    }
+}
+
+impl FromRow for DailyUsage {
+    const COLUMNS: &'static str = "date(timestamp, 'unixepoch', 'localtime') as day,
+        SUM(input_tokens), SUM(output_tokens), SUM(cost_usd), COUNT(*)";
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self> {
+        Ok(DailyUsage {
+            date: row.get(0)?,
+            total_input_tokens: row.get(1)?,
+            total_output_tokens: row.get(2)?,
+            total_cost_usd: row.get(3)?,
+            request_count: row.get(4)?,
+        })
+    }
+}
+
 impl TokenUsage {
     pub fn new(
         provider: String,
@@ -61,13 +114,14 @@ impl TokenUsage {
             cost_usd,
             request_type,
             timestamp: now,
+            automation_run_id: None,
         }
     }
 
     pub fn create(&mut self) -> Result<(), Error> {
-        let connection = get_db_conn();
+        let connection = try_get_db_conn()?;
         let mut stmt = connection.prepare(
-            "INSERT INTO token_usage (provider, model, input_tokens, output_tokens, cost_usd, request_type, timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO token_usage (provider, model, input_tokens, output_tokens, cost_usd, request_type, timestamp, automation_run_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
         )?;
         stmt.execute(params![
             self.provider,
@@ -77,6 +131,7 @@ impl TokenUsage {
             self.cost_usd,
             self.request_type,
             self.timestamp,
+            self.automation_run_id,
         ])?;
         self.id = Some(connection.last_insert_rowid() as u64);
         Ok(())
@@ -84,30 +139,19 @@ impl TokenUsage {
 
     /// Get usage summary grouped by provider and model for a given time range.
     pub fn summary_since(since_timestamp: i64) -> Result<Vec<UsageSummary>, Error> {
-        let connection = get_db_conn();
-        let mut stmt = connection.prepare(
-            "SELECT provider, model, SUM(input_tokens), SUM(output_tokens), SUM(cost_usd), COUNT(*)
-             FROM token_usage
-             WHERE timestamp >= ?1
-             GROUP BY provider, model
-             ORDER BY SUM(cost_usd) DESC",
-        )?;
-        let rows = stmt.query_map([since_timestamp], |row| {
-            Ok(UsageSummary {
-                provider: row.get(0)?,
-                model: row.get(1)?,
-                total_input_tokens: row.get(2)?,
-                total_output_tokens: row.get(3)?,
-                total_cost_usd: row.get(4)?,
-                request_count: row.get(5)?,
-            })
-        })?;
-
-        let mut results = Vec::new();
-        for row in rows {
-            results.push(row?);
-        }
-        Ok(results)
+        let connection = try_get_db_conn()?;
+        query_rows(
+            &connection,
+            &format!(
+                "SELECT {}
+                 FROM token_usage
+                 WHERE timestamp >= ?1
+                 GROUP BY provider, model
+                 ORDER BY SUM(cost_usd) DESC",
+                UsageSummary::COLUMNS
+            ),
+            [since_timestamp],
+        )
     }
 
     /// Get daily usage breakdown for the last N days.
@@ -117,35 +161,24 @@ impl TokenUsage {
             .unwrap()
             .as_secs() as i64;
         let since = now - (days * 86400);
-        let connection = get_db_conn();
-        let mut stmt = connection.prepare(
-            "SELECT date(timestamp, 'unixepoch', 'localtime') as day,
-                    SUM(input_tokens), SUM(output_tokens), SUM(cost_usd), COUNT(*)
-             FROM token_usage
-             WHERE timestamp >= ?1
-             GROUP BY day
-             ORDER BY day ASC",
-        )?;
-        let rows = stmt.query_map([since], |row| {
-            Ok(DailyUsage {
-                date: row.get(0)?,
-                total_input_tokens: row.get(1)?,
-                total_output_tokens: row.get(2)?,
-                total_cost_usd: row.get(3)?,
-                request_count: row.get(4)?,
-            })
-        })?;
-
-        let mut results = Vec::new();
-        for row in rows {
-            results.push(row?);
-        }
-        Ok(results)
+        let connection = try_get_db_conn()?;
+        query_rows(
+            &connection,
+            &format!(
+                "SELECT {}
+                 FROM token_usage
+                 WHERE timestamp >= ?1
+                 GROUP BY day
+                 ORDER BY day ASC",
+                DailyUsage::COLUMNS
+            ),
+            [since],
+        )
     }
 
     /// Get total cost since a given timestamp.
     pub fn total_cost_since(since_timestamp: i64) -> Result<f64, Error> {
-        let connection = get_db_conn();
+        let connection = try_get_db_conn()?;
         let mut stmt = connection.prepare(
             "SELECT COALESCE(SUM(cost_usd), 0.0) FROM token_usage WHERE timestamp >= ?1",
         )?;
@@ -155,30 +188,14 @@ impl TokenUsage {
 
     /// Get recent usage records (for display).
     pub fn recent(limit: i64) -> Result<Vec<TokenUsage>, Error> {
-        let connection = get_db_conn();
-        let mut stmt = connection.prepare(
-            "SELECT id, provider, model, input_tokens, output_tokens, cost_usd, request_type, timestamp
-             FROM token_usage
-             ORDER BY timestamp DESC
-             LIMIT ?1",
-        )?;
-        let rows = stmt.query_map([limit], |row| {
-            Ok(TokenUsage {
-                id: Some(row.get(0)?),
-                provider: row.get(1)?,
-                model: row.get(2)?,
-                input_tokens: row.get(3)?,
-                output_tokens: row.get(4)?,
-                cost_usd: row.get(5)?,
-                request_type: row.get(6)?,
-                timestamp: row.get(7)?,
-            })
-        })?;
-
-        let mut results = Vec::new();
-        for row in rows {
-            results.push(row?);
-        }
-        Ok(results)
+        let connection = try_get_db_conn()?;
+        query_rows(
+            &connection,
+            &format!(
+                "SELECT {} FROM token_usage ORDER BY timestamp DESC LIMIT ?1",
+                TokenUsage::COLUMNS
+            ),
+            [limit],
+        )
     }
 }
@@ -1,8 +1,20 @@
 use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha256::digest;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use uuid::Uuid;
 
-use crate::db::db::get_db_conn;
+use crate::connections::data_source::KnowledgeSnippet;
+use crate::db::db::try_get_db_conn;
+use crate::db::models::document::Document;
+use crate::db::models::feed_item::FeedItem;
+use crate::db::models::thread::Thread;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
+use crate::memory::text_splitter::{ContentType, TextChunk, TextSplitter};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -41,8 +53,10 @@ impl Default for Transcript {
   }
 }
 
-impl Transcript {
-  fn build_struct_from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+impl FromRow for Transcript {
+  const COLUMNS: &'static str = "id, thread_id, filename, start_time, end_time, timestamp";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
     Ok(Transcript {
       id: Some(row.get(0)?),
       thread_id: row.get(1)?,
@@ -52,62 +66,64 @@ impl Transcript {
       timestamp: row.get(5)?,
     })
   }
+}
 
+impl Transcript {
   pub fn find_by_id(id: u64) -> Result<Option<Transcript>, Error> {
-    let connection = get_db_conn();
-    let mut stmt =
-      connection.prepare("SELECT id, thread_id, filename, start_time, end_time, timestamp FROM transcripts WHERE id = ?1")?;
-    let thread = stmt.query_row([id], |row| Transcript::build_struct_from_row(row));
-
-    match thread {
-      Ok(thread) => Ok(Some(thread)),
-      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-      Err(err) => Err(err.into()),
-    }
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM transcripts WHERE id = ?1", Self::COLUMNS),
+      [id],
+    )
   }
 
   pub fn find_by_thread_id(thread_id: u64) -> Result<Option<Transcript>, Error> {
-    let connection = get_db_conn();
-    let mut stmt =
-      connection.prepare("SELECT id, thread_id, filename, start_time, end_time, timestamp FROM transcripts WHERE thread_id = ?1")?;
-    let thread = stmt.query_row([thread_id], |row| Transcript::build_struct_from_row(row));
-
-    match thread {
-      Ok(thread) => Ok(Some(thread)),
-      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-      Err(err) => Err(err.into()),
-    }
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM transcripts WHERE thread_id = ?1", Self::COLUMNS),
+      [thread_id],
+    )
   }
 
   pub fn find_all() -> Result<Vec<Transcript>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, thread_id, filename, start_time, end_time, timestamp FROM transcripts ORDER BY timestamp DESC")?;
-    let thread_iter = stmt.query_map([], |row| Transcript::build_struct_from_row(row))?;
-
-    let mut threads = Vec::new();
-    for thread in thread_iter {
-      threads.push(thread?);
-    }
-    Ok(threads)
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM transcripts ORDER BY timestamp DESC", Self::COLUMNS),
+      [],
+    )
   }
 
   /// Find transcripts created since the given Unix timestamp (seconds).
   pub fn find_recent(since_timestamp: i64) -> Result<Vec<Transcript>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection
-      .prepare("SELECT id, thread_id, filename, start_time, end_time, timestamp FROM transcripts WHERE timestamp >= ?1 ORDER BY timestamp DESC")?;
-    let iter = stmt.query_map(params![since_timestamp], |row| Transcript::build_struct_from_row(row))?;
-
-    let mut results = Vec::new();
-    for item in iter {
-      results.push(item?);
-    }
-    Ok(results)
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM transcripts WHERE timestamp >= ?1 ORDER BY timestamp DESC",
+        Self::COLUMNS
+      ),
+      params![since_timestamp],
+    )
+  }
+
+  /// Find transcripts created before the given Unix timestamp (seconds).
+  pub fn find_before(before_timestamp: i64) -> Result<Vec<Transcript>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM transcripts WHERE timestamp < ?1 ORDER BY timestamp ASC",
+        Self::COLUMNS
+      ),
+      params![before_timestamp],
+    )
   }
 
   pub fn create(&mut self) -> Result<(), Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
     if self.filename.is_empty() {
       return Err(Error::KSError(
@@ -146,7 +162,7 @@ impl Transcript {
         "Cannot update Transcript; Transcript does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt =
       connection.prepare("UPDATE transcripts SET thread_id = ?2, filename = ?3, start_time = ?4, end_time = ?5, timestamp = ?6 WHERE id = ?1")?;
     stmt.execute(params![
@@ -166,9 +182,331 @@ impl Transcript {
         "Cannot delete Transcript; Transcript does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare("DELETE FROM transcripts WHERE id = ?1")?;
     stmt.execute([self.id])?;
     Ok(())
   }
 }
+
+/// What the meeting's thread knows about the meeting itself, shared by the
+/// transcript and notes payloads.
+#[derive(Debug, Clone, Default)]
+pub struct MeetingDetails {
+  pub title: String,
+  pub participants: Option<String>,
+  pub calendar_event_id: Option<u64>,
+  pub date: Option<i64>,
+}
+
+impl MeetingDetails {
+  pub fn for_thread(thread_id: u64) -> MeetingDetails {
+    let thread = match Thread::find_by_id(thread_id) {
+      Ok(Some(thread)) => thread,
+      _ => {
+        return MeetingDetails {
+          title: "Meeting".to_string(),
+          ..Default::default()
+        }
+      }
+    };
+    let feed_item_complete = thread
+      .feed_item_id
+      .and_then(|feed_item_id| FeedItem::find_by_id_complete(feed_item_id).ok());
+    let calendar_event = feed_item_complete
+      .as_ref()
+      .and_then(|complete| complete.calendar_event.clone());
+
+    let title = feed_item_complete
+      .as_ref()
+      .and_then(|complete| complete.feed_item.title.clone())
+      .or(thread.title.clone())
+      .unwrap_or("Meeting".to_string());
+    MeetingDetails {
+      title,
+      participants: calendar_event
+        .as_ref()
+        .and_then(|event| event.attendees_json.clone()),
+      calendar_event_id: calendar_event.as_ref().and_then(|event| event.id),
+      date: thread.timestamp,
+    }
+  }
+
+  /// Payload fields shared by every chunk of a meeting document.
+  pub fn insert_payload(&self, thread_id: u64, payload: &mut HashMap<String, Value>) {
+    payload.insert("title".to_string(), Value::String(self.title.clone()));
+    payload.insert(
+      "meeting_thread_id".to_string(),
+      Value::Number(serde_json::Number::from(thread_id)),
+    );
+    if let Some(participants) = &self.participants {
+      payload.insert("participants".to_string(), Value::String(participants.clone()));
+    }
+    if let Some(calendar_event_id) = self.calendar_event_id {
+      payload.insert(
+        "calendar_event_id".to_string(),
+        Value::Number(serde_json::Number::from(calendar_event_id)),
+      );
+    }
+    if let Some(date) = self.date {
+      payload.insert("date".to_string(), Value::Number(serde_json::Number::from(date)));
+    }
+  }
+}
+
+/// Creates or refreshes the `documents` row for a meeting document and
+/// returns its id.
+pub fn upsert_meeting_document(foreign_table: &str, foreign_table_id: u64, hash: String) -> Option<u64> {
+  let mut document = match Document::find_by_foreign_table_and_id(foreign_table, foreign_table_id) {
+    Ok(Some(document)) => document,
+    Ok(None) => Document {
+      id: None,
+      foreign_table: foreign_table.to_string(),
+      foreign_table_id,
+      hash: hash.clone(),
+      timestamp: None,
+    },
+    Err(e) => {
+      log::error!("Couldn't look up {} document {}: {:?}", foreign_table, foreign_table_id, e);
+      return None;
+    }
+  };
+  document.hash = hash;
+  if let Err(e) = document.create() {
+    log::error!("Couldn't save {} document {}: {:?}", foreign_table, foreign_table_id, e);
+    return None;
+  }
+  document.id
+}
+
+pub fn meeting_chunk_payloads(
+  foreign_table: &str,
+  payload_type: &str,
+  foreign_table_id: u64,
+  thread_id: u64,
+  content: &str,
+  content_type: ContentType,
+) -> Vec<HashMap<String, Value>> {
+  let chunks: Vec<TextChunk> = TextSplitter::for_content_type(content_type).split_chunks(content);
+  let document_id = match upsert_meeting_document(foreign_table, foreign_table_id, digest(content)) {
+    Some(document_id) => document_id,
+    None => return Vec::new(),
+  };
+  let details = MeetingDetails::for_thread(thread_id);
+
+  let mut documents = Vec::new();
+  for (chunk_idx, chunk) in chunks.into_iter().enumerate() {
+    let mut doc_payload = HashMap::from([
+      (
+        "id".to_string(),
+        Value::String(
+          Uuid::new_v5(
+            &Uuid::NAMESPACE_DNS,
+            format!("{}/{}/{}", foreign_table, foreign_table_id, chunk_idx).as_bytes(),
+          )
+          .to_string(),
+        ),
+      ),
+      (
+        "chunk_id".to_string(),
+        Value::Number(serde_json::Number::from(chunk_idx)),
+      ),
+      ("content".to_string(), Value::String(chunk.text)),
+      (
+        "chunk_start".to_string(),
+        Value::Number(serde_json::Number::from(chunk.start)),
+      ),
+      (
+        "chunk_end".to_string(),
+        Value::Number(serde_json::Number::from(chunk.end)),
+      ),
+      ("type".to_string(), Value::String(payload_type.to_string())),
+      (
+        "document_id".to_string(),
+        Value::Number(serde_json::Number::from(document_id)),
+      ),
+    ]);
+    if let Some(speaker) = chunk.speaker {
+      doc_payload.insert("speaker".to_string(), Value::String(speaker));
+    }
+    details.insert_payload(thread_id, &mut doc_payload);
+    documents.push(doc_payload);
+  }
+  documents
+}
+
+pub fn meeting_attrs() -> HashMap<&'static str, Vec<String>> {
+  HashMap::from([
+    ("embed", vec!["title".to_string(), "content".to_string()]),
+    (
+      "metadata",
+      vec![
+        "type".to_string(),
+        "document_id".to_string(),
+        "chunk_id".to_string(),
+        "chunk_start".to_string(),
+        "chunk_end".to_string(),
+        "speaker".to_string(),
+        "meeting_thread_id".to_string(),
+        "participants".to_string(),
+        "calendar_event_id".to_string(),
+        "date".to_string(),
+      ],
+    ),
+  ])
+}
+
+impl Transcript {
+  pub fn path(&self) -> PathBuf {
+    crate::config::transcripts_dir().join(&self.filename)
+  }
+
+  pub fn read_content(&self) -> Result<String, Error> {
+    Ok(read_to_string(self.path())?)
+  }
+
+  pub fn get_documents(&self) -> Vec<HashMap<String, Value>> {
+    let (id, thread_id) = match (self.id, self.thread_id) {
+      (Some(id), Some(thread_id)) => (id, thread_id),
+      _ => return Vec::new(),
+    };
+    let content = match self.read_content() {
+      Ok(content) => content,
+      Err(e) => {
+        log::debug!("Error reading transcript {}: {:?}", self.filename, e);
+        return Vec::new();
+      }
+    };
+    meeting_chunk_payloads(
+      "transcripts",
+      "transcript",
+      id,
+      thread_id,
+      &content,
+      ContentType::Transcript,
+    )
+  }
+
+  pub fn get_attrs() -> HashMap<&'static str, Vec<String>> {
+    meeting_attrs()
+  }
+}
+
+impl KnowledgeSnippet for Transcript {
+  fn get_title(&self) -> String {
+    self
+      .thread_id
+      .map(|thread_id| MeetingDetails::for_thread(thread_id).title)
+      .unwrap_or(self.filename.clone())
+  }
+
+  fn get_document_type(&self) -> String {
+    "transcript".to_string()
+  }
+
+  fn to_prompt_string(&self, chunk_ids: Option<Vec<u64>>, _payloads: Option<Vec<Value>>) -> String {
+    let chunk_ids = chunk_ids.unwrap_or(vec![]);
+    let content = self.read_content().unwrap_or_default();
+    let title = self.get_title();
+
+    let mut prompt = format!("\n\n> Start of excerpts from meeting transcript: {}", title);
+    if chunk_ids.len() == 0 {
+      prompt = format!("{}\n\n{}", prompt, content);
+    } else {
+      let chunks = TextSplitter::for_content_type(ContentType::Transcript).split_chunks(&content);
+      for (chunk_idx, chunk) in chunks.iter().enumerate() {
+        if chunk_ids.contains(&(chunk_idx as u64)) {
+          prompt = format!("{}\n\n{}", prompt, chunk.text);
+        }
+      }
+    }
+    format!("{}\n> End of excerpts from meeting transcript: {}", prompt, title)
+  }
+
+  fn get_summary(&self) -> String {
+    let content = self.read_content().unwrap_or_default();
+    TextSplitter::for_content_type(ContentType::Transcript)
+      .split_text(&content)
+      .first()
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  fn get_hyperlink(&self) -> String {
+    "".to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn meeting_details_fill_the_shared_payload_fields() {
+    let details = MeetingDetails {
+      title: "Acme pricing".to_string(),
+      participants: Some("[\"alice@acme.com\"]".to_string()),
+      calendar_event_id: Some(12),
+      date: Some(1_700_000_000),
+    };
+    let mut payload = HashMap::new();
+    details.insert_payload(5, &mut payload);
+    assert_eq!(payload["title"], "Acme pricing");
+    assert_eq!(payload["meeting_thread_id"], 5);
+    assert_eq!(payload["calendar_event_id"], 12);
+    assert_eq!(payload["date"], 1_700_000_000);
+
+    let mut payload = HashMap::new();
+    MeetingDetails::default().insert_payload(5, &mut payload);
+    assert!(!payload.contains_key("participants"));
+    assert!(!payload.contains_key("calendar_event_id"));
+  }
+
+  #[test]
+  fn meeting_chunks_share_one_document_and_stable_ids() {
+    // No thread with this id exists, so the details fall back to defaults.
+    let thread_id = 9_300_001;
+    let content = format!("Me: {}\nThem: {}\n", "pricing ".repeat(50), "discount ".repeat(50));
+    let payloads = meeting_chunk_payloads(
+      "transcripts",
+      "transcript",
+      thread_id,
+      thread_id,
+      &content,
+      ContentType::Transcript,
+    );
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(payloads[0]["speaker"], "Me");
+    assert_eq!(payloads[1]["speaker"], "Them");
+    assert_eq!(payloads[1]["chunk_id"], 1);
+    assert_eq!(payloads[0]["title"], "Meeting");
+    assert_eq!(payloads[0]["document_id"], payloads[1]["document_id"]);
+
+    let document = Document::find_by_foreign_table_and_id("transcripts", thread_id)
+      .unwrap()
+      .unwrap();
+    assert_eq!(payloads[0]["document_id"], document.id.unwrap());
+
+    // Re-indexing reuses the document row and the point ids.
+    let again = meeting_chunk_payloads(
+      "transcripts",
+      "transcript",
+      thread_id,
+      thread_id,
+      &content,
+      ContentType::Transcript,
+    );
+    assert_eq!(again[0]["document_id"], payloads[0]["document_id"]);
+    assert_eq!(again[0]["id"], payloads[0]["id"]);
+
+    // Everything but the point id is either embedded or kept as metadata.
+    let attrs = meeting_attrs();
+    for key in payloads[0].keys().filter(|key| key.as_str() != "id") {
+      assert!(
+        attrs["embed"].contains(key) || attrs["metadata"].contains(key),
+        "{} is dropped from the payload",
+        key
+      );
+    }
+  }
+}
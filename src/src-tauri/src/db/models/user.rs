@@ -1,41 +1,77 @@
 use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
 
+/// Each signed-in Google or Microsoft account is a user row; synced emails,
+/// calendar events and drive documents point at it through `account_id`.
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct User {
   pub id: Option<u64>,
   pub email: String,
   pub uuid: Option<String>,
+  #[serde(default)]
+  pub is_active: bool,
+}
+
+impl FromRow for User {
+  const COLUMNS: &'static str = "id, email, uuid, is_active";
+
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    Ok(User {
+      id: row.get(0)?,
+      email: row.get(1)?,
+      uuid: row.get(2)?,
+      is_active: row.get(3)?,
+    })
+  }
 }
 
 impl User {
-  pub fn find_by_email(email: String) -> Result<User> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare("SELECT id, email, uuid FROM users WHERE email = ?1")?;
-    let user = stmt.query_row(params![email], |row| {
-      Ok(User {
-        id: row.get(0)?,
-        email: row.get(1)?,
-        uuid: row.get(2)?,
-      })
-    })?;
+  pub fn find_by_email(email: String) -> Result<User, Error> {
+    let connection = try_get_db_conn()?;
+    let user: Option<User> = query_optional(
+      &connection,
+      &format!("SELECT {} FROM users WHERE email = ?1", Self::COLUMNS),
+      params![email],
+    )?;
+    Ok(user.ok_or(rusqlite::Error::QueryReturnedNoRows)?)
+  }
+
+  pub fn find_by_id(id: u64) -> Result<Option<User>, Error> {
+    let connection = try_get_db_conn()?;
+    query_optional(
+      &connection,
+      &format!("SELECT {} FROM users WHERE id = ?1", Self::COLUMNS),
+      params![id],
+    )
+  }
 
-    Ok(user)
+  pub fn find_all() -> Result<Vec<User>, Error> {
+    let connection = try_get_db_conn()?;
+    query_rows(
+      &connection,
+      &format!("SELECT {} FROM users ORDER BY id", Self::COLUMNS),
+      [],
+    )
   }
 
+  /// The first account to sign in becomes the active one.
   pub fn create(&self) -> Result<(), Error> {
     if self.id.is_some() {
       return Err(Error::KSError(
         "Cannot create User; User already exists.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
     connection.execute(
-      "INSERT INTO users (email, uuid) VALUES (?1, ?2)",
+      "INSERT INTO users (email, uuid, is_active)
+      VALUES (?1, ?2, NOT EXISTS (SELECT 1 FROM users WHERE is_active = 1))",
       params![self.email, self.uuid],
     )?;
 
@@ -48,7 +84,7 @@ impl User {
         "Cannot update User; User does not exist.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
     connection.execute(
       "UPDATE users SET uuid = ?1 WHERE id = ?2",
@@ -57,4 +93,90 @@ impl User {
 
     Ok(())
   }
+
+  pub fn find_active() -> Option<User> {
+    try_get_db_conn()
+      .and_then(|connection| {
+        query_optional(
+          &connection,
+          &format!("SELECT {} FROM users WHERE is_active = 1 LIMIT 1", Self::COLUMNS),
+          [],
+        )
+      })
+      .unwrap_or_else(|err| {
+        log::error!("Failed to read active account: {:?}", err);
+        None
+      })
+  }
+
+  pub fn active_account_id() -> Option<u64> {
+    User::find_active().and_then(|user| user.id)
+  }
+
+  /// The account signed in first. Rows synced before accounts were tracked
+  /// came from it, so its syncs also reconcile unowned rows.
+  pub fn primary_account_id() -> Result<Option<u64>, Error> {
+    let connection = try_get_db_conn()?;
+    let id = connection.query_row("SELECT MIN(id) FROM users", [], |row| row.get(0))?;
+    Ok(id)
+  }
+
+  /// Ids of the accounts connected through `provider`, e.g. "microsoft".
+  pub fn find_ids_by_provider(provider: &str) -> Result<HashSet<u64>, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "SELECT DISTINCT user_connections.user_id FROM user_connections
+      JOIN connections ON connections.id = user_connections.connection_id
+      WHERE connections.provider = ?1",
+    )?;
+    let ids = stmt
+      .query_map(params![provider], |row| row.get::<_, u64>(0))?
+      .collect::<Result<HashSet<_>, _>>()?;
+
+    Ok(ids)
+  }
+
+  /// Makes `id` the only active account.
+  pub fn set_active(id: u64) -> Result<User, Error> {
+    let user = User::find_by_id(id)?
+      .ok_or_else(|| Error::KSError(format!("Account {} does not exist", id)))?;
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE users SET is_active = (id = ?1)",
+      params![id],
+    )?;
+
+    Ok(User {
+      is_active: true,
+      ..user
+    })
+  }
+}
+
+/// SQL condition restricting `column` to rows of `account_id`. Rows synced
+/// before accounts were tracked have no owner and stay visible.
+pub fn account_clause(column: &str, account_id: Option<u64>) -> String {
+  match account_id {
+    Some(account_id) => format!("({column} IS NULL OR {column} = {account_id})"),
+    None => "1 = 1".to_string(),
+  }
+}
+
+/// `account_clause` for the active account.
+pub fn active_account_clause(column: &str) -> String {
+  account_clause(column, User::active_account_id())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn account_clause_keeps_unowned_rows_visible() {
+    assert_eq!(
+      account_clause("emails.account_id", Some(2)),
+      "(emails.account_id IS NULL OR emails.account_id = 2)"
+    );
+    assert_eq!(account_clause("emails.account_id", None), "1 = 1");
+  }
 }
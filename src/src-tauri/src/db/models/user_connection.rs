@@ -2,9 +2,11 @@ use chrono::Utc;
 use rusqlite::{params, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::db::db::get_db_conn;
+use crate::db::db::try_get_db_conn;
 use crate::db::models::connection::Connection;
+use crate::db::repo::{query_optional, query_rows, FromRow};
 use crate::error::Error;
+use crate::secrets::SecretStore;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -23,20 +25,169 @@ pub struct UserConnection {
   pub refresh_token: Option<String>,
   pub connection: Option<Connection>,
   pub last_synced: Option<u64>,
+  pub sync_status: Option<SyncStatus>,
+  pub sync_error: Option<String>,
+}
+
+/// Sync state of one account's connection, so two signed-in accounts of the
+/// same provider report progress and failures separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+  Syncing,
+  Synced,
+  Failed,
+}
+
+impl SyncStatus {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SyncStatus::Syncing => "syncing",
+      SyncStatus::Synced => "synced",
+      SyncStatus::Failed => "failed",
+    }
+  }
+
+  pub fn from_str(status: &str) -> Option<SyncStatus> {
+    match status {
+      "syncing" => Some(SyncStatus::Syncing),
+      "synced" => Some(SyncStatus::Synced),
+      "failed" => Some(SyncStatus::Failed),
+      _ => None,
+    }
+  }
+}
+
+/// Joins the connection so rows read through `FromRow` carry their scope.
+const JOINED_TABLES: &str = "user_connections
+  LEFT JOIN connections ON connection_id = connections.id
+  LEFT JOIN users ON user_id = users.id";
+
+impl FromRow for UserConnection {
+  const COLUMNS: &'static str = "user_connections.id, user_connections.user_id, user_connections.connection_id, user_connections.token, user_connections.refresh_token, user_connections.last_synced, connections.scope, connections.provider, user_connections.sync_status, user_connections.sync_error";
+
+  /// Reads tokens as stored; callers decrypt them with `unsealed`.
+  fn from_row(row: &rusqlite::Row) -> Result<Self> {
+    let connection_id = row.get(2)?;
+    let sync_status: Option<String> = row.get(8)?;
+    Ok(UserConnection {
+      id: Some(row.get(0)?),
+      user_id: row.get(1)?,
+      connection_id,
+      token: row.get(3)?,
+      refresh_token: row.get(4)?,
+      connection: Some(Connection {
+        id: Some(connection_id),
+        scope: row.get(6)?,
+        provider: row.get(7)?,
+      }),
+      last_synced: row.get(5)?,
+      sync_status: sync_status.as_deref().and_then(SyncStatus::from_str),
+      sync_error: row.get(9)?,
+    })
+  }
 }
 
 impl UserConnection {
+  /// Tokens are encrypted at rest; the struct always holds plaintext.
+  fn sealed_tokens(&self) -> Result<(String, Option<String>), Error> {
+    Ok((
+      SecretStore::encrypt(&self.token)?,
+      self.refresh_token.as_deref().map(SecretStore::encrypt).transpose()?,
+    ))
+  }
+
+  fn unsealed(mut self) -> Result<Self, Error> {
+    self.token = SecretStore::decrypt(&self.token)?;
+    self.refresh_token = match self.refresh_token {
+      Some(refresh_token) => Some(SecretStore::decrypt(&refresh_token)?),
+      None => None,
+    };
+    Ok(self)
+  }
+
+  /// Encrypts tokens still stored as plaintext and re-encrypts those sealed
+  /// with a rotated key. Returns the number of rows rewritten.
+  pub fn reseal_tokens() -> Result<usize, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare("SELECT id, token, refresh_token FROM user_connections")?;
+    let rows = stmt
+      .query_map([], |row| {
+        Ok((
+          row.get::<_, u64>(0)?,
+          row.get::<_, String>(1)?,
+          row.get::<_, Option<String>>(2)?,
+        ))
+      })?
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let mut resealed = 0;
+    for (id, token, refresh_token) in rows {
+      let refresh_needs_reseal = match refresh_token.as_deref() {
+        Some(refresh_token) => SecretStore::needs_reseal(refresh_token)?,
+        None => false,
+      };
+      if !SecretStore::needs_reseal(&token)? && !refresh_needs_reseal {
+        continue;
+      }
+      let token = SecretStore::reseal(&token)?;
+      let refresh_token = match refresh_token {
+        Some(refresh_token) => Some(SecretStore::reseal(&refresh_token)?),
+        None => None,
+      };
+      connection.execute(
+        "UPDATE user_connections SET token = ?2, refresh_token = ?3 WHERE id = ?1",
+        params![id, token, refresh_token],
+      )?;
+      resealed += 1;
+    }
+    Ok(resealed)
+  }
+
+  /// Deletes connections whose tokens don't decrypt with the current key
+  /// set, e.g. after restoring a backup sealed under other keys. Returns the
+  /// number of connections deleted.
+  pub fn delete_unreadable() -> Result<usize, Error> {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare("SELECT id, token, refresh_token FROM user_connections")?;
+    let rows = stmt
+      .query_map([], |row| {
+        Ok((
+          row.get::<_, u64>(0)?,
+          row.get::<_, String>(1)?,
+          row.get::<_, Option<String>>(2)?,
+        ))
+      })?
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let mut deleted = 0;
+    for (id, token, refresh_token) in rows {
+      let readable = SecretStore::decrypt(&token).is_ok()
+        && refresh_token
+          .as_deref()
+          .map_or(true, |refresh_token| SecretStore::decrypt(refresh_token).is_ok());
+      if readable {
+        continue;
+      }
+      connection.execute("DELETE FROM user_connections WHERE id = ?1", [id])?;
+      connection.execute("DELETE FROM sync_runs WHERE user_connection_id = ?1", [id])?;
+      deleted += 1;
+    }
+    Ok(deleted)
+  }
+
   pub fn upsert(self) -> Result<(), Error> {
     if self.id.is_some() {
       return Err(Error::KSError(
         "Cannot create UserConnection; UserConnection already exists.".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
+    let (token, refresh_token) = self.sealed_tokens()?;
     connection.execute(
       "INSERT INTO user_connections (user_id, connection_id, token, refresh_token) VALUES (?1, ?2, ?3, ?4) ON CONFLICT (user_id, connection_id) DO UPDATE SET token = ?3",
-      (&self.user_id, &self.connection_id, &self.token, &self.refresh_token),
+      (&self.user_id, &self.connection_id, &token, &refresh_token),
     )?;
     Ok(())
   }
@@ -47,11 +198,12 @@ impl UserConnection {
         "Cannot update user_connection because it is not persisted".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
 
+    let (token, refresh_token) = self.sealed_tokens()?;
     connection.execute(
       "UPDATE user_connections SET token = ?2, refresh_token = ?3 WHERE id = ?1",
-      (&self.id, &self.token, &self.refresh_token),
+      (&self.id, &token, &refresh_token),
     )?;
 
     Ok(())
@@ -63,8 +215,9 @@ impl UserConnection {
         "Cannot delete user_connection because it is not persisted".into(),
       ));
     }
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     connection.execute("DELETE FROM user_connections where id = ?1", [&self.id])?;
+    connection.execute("DELETE FROM sync_runs where user_connection_id = ?1", [&self.id])?;
     Ok(())
   }
 
@@ -72,63 +225,19 @@ impl UserConnection {
     user_email: String,
     scope: String,
   ) -> Result<UserConnection, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "
-      SELECT
-        user_connections.id,
-        user_connections.user_id,
-        user_connections.connection_id,
-        user_connections.token,
-        user_connections.refresh_token,
-        user_connections.last_synced,
-        scope,
-        provider,
-        email
-      FROM user_connections
-      LEFT JOIN connections on connection_id = connections.id
-      LEFT JOIN users on user_id = users.id
-      WHERE users.email = ?1 and scope = ?2",
+    let connection = try_get_db_conn()?;
+    let user_connection: Option<UserConnection> = query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM {} WHERE users.email = ?1 AND scope = ?2",
+        Self::COLUMNS,
+        JOINED_TABLES
+      ),
+      params![user_email, scope],
     )?;
-    let row = stmt.query_row(params![user_email, scope], |row| {
-      Ok((
-        row.get::<_, u64>(0)?,
-        row.get::<_, u64>(1)?,
-        row.get::<_, u64>(2)?,
-        row.get::<_, String>(3)?,
-        row.get::<_, Option<String>>(4)?,
-        row.get::<_, Option<u64>>(5)?,
-        row.get::<_, String>(6)?,
-        row.get::<_, String>(7)?,
-        row.get::<_, String>(8)?,
-      ))
-    })?;
-    let (
-      user_connections_id,
-      user_id,
-      connection_id,
-      token,
-      refresh_token,
-      last_synced,
-      scope,
-      provider,
-      _email,
-    ) = row;
-    let connection_instance = Connection {
-      id: Some(connection_id),
-      scope,
-      provider,
-    };
-    let user_connection = UserConnection {
-      id: Some(user_connections_id),
-      user_id,
-      connection_id,
-      token,
-      refresh_token,
-      connection: Some(connection_instance),
-      last_synced: last_synced,
-    };
-    Ok(user_connection)
+    user_connection
+      .ok_or(rusqlite::Error::QueryReturnedNoRows)?
+      .unsealed()
   }
 
   pub fn update_last_sync_by_id(
@@ -136,7 +245,7 @@ impl UserConnection {
     last_synced_date: chrono::DateTime<Utc>,
   ) -> Result<(), Error> {
     let date = last_synced_date.timestamp() as u64;
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     connection.execute(
       "UPDATE user_connections SET last_synced = ?1 WHERE id = ?2",
       (&date, &id),
@@ -144,56 +253,60 @@ impl UserConnection {
     Ok(())
   }
 
-  pub fn find_by_user_email(user_email: String) -> Result<Vec<UserConnection>, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "
-      SELECT
-        user_connections.id,
-        user_connections.user_id,
-        user_connections.connection_id,
-        user_connections.token,
-        user_connections.last_synced,
-        scope,
-        provider,
-        user_connections.refresh_token
-      FROM user_connections
-      LEFT JOIN connections on connection_id = connections.id
-      LEFT JOIN users on user_id = users.id
-      WHERE users.email = ?1",
+  pub fn set_sync_status(
+    id: u64,
+    status: SyncStatus,
+    sync_error: Option<String>,
+  ) -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE user_connections SET sync_status = ?2, sync_error = ?3 WHERE id = ?1",
+      params![id, status.as_str(), sync_error],
     )?;
-    let user_connections = stmt.query_map(params![user_email], |row| {
-      let user_connections_id = row.get::<_, u64>(0)?;
-      let user_id = row.get::<_, u64>(1)?;
-      let connection_id = row.get::<_, u64>(2)?;
-      let token = row.get::<_, String>(3)?;
-      let last_synced = row.get::<_, Option<u64>>(4)?;
-      let scope = row.get::<_, String>(5)?;
-      let provider = row.get::<_, String>(6)?;
-      let refresh_token = row.get::<_, Option<String>>(7)?;
+    Ok(())
+  }
 
-      let connection_instance = Connection {
-        id: Some(connection_id),
-        scope,
-        provider,
-      };
+  /// Stores the outcome of a finished sync of connection `id`.
+  pub fn record_sync_result<T>(id: u64, result: &Result<T, Error>) {
+    let (status, sync_error) = match result {
+      Ok(_) => (SyncStatus::Synced, None),
+      Err(error) => (SyncStatus::Failed, Some(format!("{:?}", error))),
+    };
+    if let Err(error) = UserConnection::set_sync_status(id, status, sync_error) {
+      log::error!("Failed to store sync status of connection {}: {:?}", id, error);
+    }
+  }
 
-      Ok(UserConnection {
-        id: Some(user_connections_id),
-        user_id,
-        connection_id,
-        token,
-        refresh_token,
-        connection: Some(connection_instance),
-        last_synced: last_synced,
-      })
-    })?;
-    let result = user_connections.into_iter().collect::<Result<_, _>>()?;
-    Ok(result)
+  /// A sync interrupted by quitting the app would otherwise report
+  /// `syncing` forever.
+  pub fn reset_interrupted_syncs() -> Result<(), Error> {
+    let connection = try_get_db_conn()?;
+    connection.execute(
+      "UPDATE user_connections SET sync_status = NULL WHERE sync_status = 'syncing'",
+      [],
+    )?;
+    Ok(())
+  }
+
+  pub fn find_by_user_email(user_email: String) -> Result<Vec<UserConnection>, Error> {
+    let connection = try_get_db_conn()?;
+    let user_connections: Vec<UserConnection> = query_rows(
+      &connection,
+      &format!(
+        "SELECT {} FROM {} WHERE users.email = ?1",
+        Self::COLUMNS,
+        JOINED_TABLES
+      ),
+      params![user_email],
+    )?;
+    user_connections
+      .into_iter()
+      .map(|user_connection| user_connection.unsealed())
+      .collect()
   }
 
   pub fn get_synced_since() -> Result<SyncedSince, Error> {
-    let connection = get_db_conn();
+    let connection = try_get_db_conn()?;
     let mut stmt = connection.prepare(
       "SELECT
         (SELECT MIN(emails.date) FROM emails) as emails_synced_since,
@@ -211,62 +324,18 @@ impl UserConnection {
   }
 
   pub fn find_by_id(id: u64) -> Result<UserConnection, Error> {
-    let connection = get_db_conn();
-    let mut stmt = connection.prepare(
-      "
-      SELECT
-        user_connections.id,
-        user_connections.user_id,
-        user_connections.connection_id,
-        user_connections.token,
-        user_connections.refresh_token,
-        user_connections.last_synced,
-        scope,
-        provider,
-        email
-      FROM user_connections
-      LEFT JOIN connections on connection_id = connections.id
-      LEFT JOIN users on user_id = users.id
-      WHERE user_connections.id = ?1",
+    let connection = try_get_db_conn()?;
+    let user_connection: Option<UserConnection> = query_optional(
+      &connection,
+      &format!(
+        "SELECT {} FROM {} WHERE user_connections.id = ?1",
+        Self::COLUMNS,
+        JOINED_TABLES
+      ),
+      params![id],
     )?;
-    let row = stmt.query_row(params![id], |row| {
-      Ok((
-        row.get::<_, u64>(0)?,
-        row.get::<_, u64>(1)?,
-        row.get::<_, u64>(2)?,
-        row.get::<_, String>(3)?,
-        row.get::<_, Option<String>>(4)?,
-        row.get::<_, Option<u64>>(5)?,
-        row.get::<_, String>(6)?,
-        row.get::<_, String>(7)?,
-        row.get::<_, String>(8)?,
-      ))
-    })?;
-    let (
-      user_connections_id,
-      user_id,
-      connection_id,
-      token,
-      refresh_token,
-      last_synced,
-      scope,
-      provider,
-      _email,
-    ) = row;
-    let connection_instance = Connection {
-      id: Some(connection_id),
-      scope,
-      provider,
-    };
-    let user_connection = UserConnection {
-      id: Some(user_connections_id),
-      user_id,
-      connection_id,
-      token,
-      refresh_token,
-      connection: Some(connection_instance),
-      last_synced: last_synced,
-    };
-    Ok(user_connection)
+    user_connection
+      .ok_or(rusqlite::Error::QueryReturnedNoRows)?
+      .unsealed()
   }
 }
@@ -0,0 +1,113 @@
+//! Database access for async code.
+//!
+//! rusqlite and the r2d2 pool block, so async handlers must not query the
+//! database on a runtime thread. `run_blocking` moves model calls onto
+//! tokio's blocking pool and `with_connection` does the same for ad hoc
+//! queries. Both return `Result` instead of panicking.
+//!
+//! Models mapped through `FromRow` select the same explicit column list the
+//! row mapper reads, rather than `SELECT *`, which silently breaks when a
+//! migration reorders columns. Older models still map rows by hand.
+
+use rusqlite::{Connection, OptionalExtension, Params, Row};
+
+use crate::db::db::try_get_db_conn;
+use crate::error::Error;
+
+pub trait FromRow: Sized {
+  /// Comma separated columns, in the order `from_row` reads them.
+  const COLUMNS: &'static str;
+
+  fn from_row(row: &Row) -> rusqlite::Result<Self>;
+}
+
+/// Runs blocking database work off the async runtime.
+pub async fn run_blocking<F, T>(f: F) -> Result<T, Error>
+where
+  F: FnOnce() -> Result<T, Error> + Send + 'static,
+  T: Send + 'static,
+{
+  tokio::task::spawn_blocking(f).await?
+}
+
+/// Runs `f` with a pooled connection on the blocking pool.
+pub async fn with_connection<F, T>(f: F) -> Result<T, Error>
+where
+  F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+  T: Send + 'static,
+{
+  run_blocking(move || {
+    let connection = try_get_db_conn()?;
+    f(&connection)
+  })
+  .await
+}
+
+pub fn query_rows<T: FromRow, P: Params>(
+  connection: &Connection,
+  sql: &str,
+  params: P,
+) -> Result<Vec<T>, Error> {
+  let mut stmt = connection.prepare(sql)?;
+  let rows = stmt
+    .query_map(params, |row| T::from_row(row))?
+    .collect::<rusqlite::Result<Vec<T>>>()?;
+  Ok(rows)
+}
+
+pub fn query_optional<T: FromRow, P: Params>(
+  connection: &Connection,
+  sql: &str,
+  params: P,
+) -> Result<Option<T>, Error> {
+  let mut stmt = connection.prepare(sql)?;
+  let row = stmt.query_row(params, |row| T::from_row(row)).optional()?;
+  Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Debug, PartialEq)]
+  struct Pair {
+    id: u64,
+    name: String,
+  }
+
+  impl FromRow for Pair {
+    const COLUMNS: &'static str = "id, name";
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+      Ok(Pair {
+        id: row.get(0)?,
+        name: row.get(1)?,
+      })
+    }
+  }
+
+  #[test]
+  fn maps_rows_through_the_column_list_regardless_of_table_order() {
+    let connection = Connection::open_in_memory().unwrap();
+    connection
+      .execute_batch(
+        "CREATE TABLE pairs (name TEXT NOT NULL, extra TEXT, id INTEGER PRIMARY KEY);
+         INSERT INTO pairs (id, name) VALUES (1, 'a'), (2, 'b');",
+      )
+      .unwrap();
+    let sql = format!("SELECT {} FROM pairs ORDER BY id", Pair::COLUMNS);
+    let pairs: Vec<Pair> = query_rows(&connection, &sql, []).unwrap();
+    assert_eq!(pairs[1], Pair { id: 2, name: "b".into() });
+
+    let sql = format!("SELECT {} FROM pairs WHERE id = ?1", Pair::COLUMNS);
+    let missing: Option<Pair> = query_optional(&connection, &sql, [3]).unwrap();
+    assert!(missing.is_none());
+  }
+
+  #[tokio::test]
+  async fn run_blocking_returns_errors_instead_of_panicking() {
+    let result: Result<(), Error> =
+      run_blocking(|| Err(Error::KSError("query failed".into()))).await;
+    assert!(matches!(result, Err(Error::KSError(_))));
+  }
+}
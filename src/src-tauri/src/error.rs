@@ -44,6 +44,15 @@ pub enum Error {
 
   #[from]
   FetchUuidError(FetchUuidError),
+
+  #[from]
+  SerdeJsonError(serde_json::Error),
+
+  #[from]
+  ZipError(zip::result::ZipError),
+
+  #[from]
+  PoolError(r2d2::Error),
 }
 
 #[derive(Debug)]
@@ -56,6 +56,13 @@ impl LlamaBinding {
     // PANIC SAFETY: Just inserted the element if it isn't already inside the map, so must be present in the map
     self.models.get(&key).unwrap()
   }
+
+  /// Returns a handle to the model that can tokenize synchronously, e.g. to
+  /// size text chunks without holding the binding lock.
+  pub async fn tokenizer(&self, model_path: &Path) -> LlamaModel {
+    let model = self.get(model_path, ChatFormat::Llama3).await;
+    model.model.clone()
+  }
 }
 
 impl ChatCompletionLlm for LlamaBinding {
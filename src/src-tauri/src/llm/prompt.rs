@@ -100,11 +100,9 @@ pub async fn build_user_message(
   user_prompt = format!("{} Be informative but not wordy. Respond succintly. Give your entire response in Markdown, so that I can display it nicely.", user_prompt);
 
   // Save prompt to debug file
-  if let Some(home_dir) = dirs::home_dir() {
-    let debug_path = home_dir.join(".knapsack").join("debug_user_prompt.txt");
-    if let Err(e) = fs::write(&debug_path, &user_prompt) {
-      log::error!("Failed to write debug prompt file: {}", e);
-    }
+  let debug_path = crate::config::data_dir().join("debug_user_prompt.txt");
+  if let Err(e) = fs::write(&debug_path, &user_prompt) {
+    log::error!("Failed to write debug prompt file: {}", e);
   }
 
   Message {
@@ -4,6 +4,7 @@ use flume::Receiver;
 use futures::stream::Stream;
 
 use std::pin::Pin;
+use std::sync::Mutex as StdMutex;
 use std::sync::RwLock as StdRwLock;
 use std::sync::{
   atomic::{AtomicBool, Ordering},
@@ -171,6 +172,26 @@ fn extract_anthropic_usage(json: &serde_json::Value) -> CompletionUsage {
   CompletionUsage { input_tokens, output_tokens }
 }
 
+/// What one completion used and cost.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionCost {
+  pub provider: String,
+  pub model: String,
+  pub input_tokens: i64,
+  pub output_tokens: i64,
+  pub cost_usd: f64,
+}
+
+tokio::task_local! {
+  /// The automation run whose completions the current task makes; their
+  /// token usage is recorded against it.
+  pub static AUTOMATION_RUN_ID: u64;
+  /// Collects the cost of each completion the current task makes, e.g. for
+  /// automation previews.
+  pub static COMPLETION_COSTS: Arc<StdMutex<Vec<CompletionCost>>>;
+}
+
 /// Record token usage to the database (best-effort, never fails the request).
 fn record_usage(provider: &str, model: &str, usage: &CompletionUsage, request_type: &str) {
   let pricing = get_pricing(provider, model);
@@ -184,6 +205,18 @@ fn record_usage(provider: &str, model: &str, usage: &CompletionUsage, request_ty
     cost,
     request_type.to_string(),
   );
+  record.automation_run_id = AUTOMATION_RUN_ID.try_with(|run_id| *run_id).ok();
+  let _ = COMPLETION_COSTS.try_with(|costs| {
+    if let Ok(mut costs) = costs.lock() {
+      costs.push(CompletionCost {
+        provider: provider.to_string(),
+        model: model.to_string(),
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        cost_usd: cost,
+      });
+    }
+  });
 
   if let Err(e) = record.create() {
     log::warn!("[cost] Failed to record token usage: {:?}", e);
@@ -339,7 +372,7 @@ async fn anthropic_completion(
 }
 
 /// Complete using the best available provider. Falls back through providers on failure.
-async fn multi_provider_completion(
+pub(crate) async fn multi_provider_completion(
   messages: Vec<LlmMessage>,
 ) -> Result<String, LLMError> {
   let mut provider = resolve_provider()?;
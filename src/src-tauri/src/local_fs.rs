@@ -7,8 +7,6 @@ use std::collections::HashSet;
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU16, Ordering};
-use std::sync::Arc;
 use std::time::UNIX_EPOCH;
 use tauri::api::process::Command;
 
@@ -16,14 +14,14 @@ use crate::api::document::DisplayDocument;
 use crate::db::models::{document::Document, local_file::LocalFile};
 use crate::error::Error;
 
-use crate::memory::text_splitter::TextSplitter;
-use crate::server::status::{KNAPSACK_STATUS_READHOME_MAX, KNAPSACK_STATUS_READHOME_MIN};
+use crate::memory::text_splitter::{ContentType, TextChunk, TextSplitter};
 use crate::utils::platform::{get_os, OS};
 
 use dotext::*;
 
 const SINGLE_FILE_CONTENT_LIMIT: u64 = 150000000; // Limit in MB
 pub const PDF_TO_TEXT_BINARY_NAME: &str = "pdftotext";
+const PDF_PAGE_SEPARATOR: &str = "\x0c";
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -36,7 +34,7 @@ pub struct LocalFileResponseDoc {
 
 lazy_static! {
   static ref FILE_CONTENT_SEARCHABLE_EXTS: HashSet<&'static str> = {
-    HashSet::from(["doc", "docx", "pdf", "odp", "ods", "odt", "xlsx", "pptx", "txt", "rtf"])
+    HashSet::from(["doc", "docx", "pdf", "odp", "ods", "odt", "xlsx", "pptx", "txt", "rtf", "md", "markdown"])
   };
   static ref FILE_NAME_SEARCHABLE_EXTS: HashSet<&'static str> = {
 
@@ -93,10 +91,9 @@ fn is_file_name_searchable_extension(path: &Path) -> bool {
     .unwrap_or(false)
 }
 
-fn expand_home(path: &Path) -> PathBuf {
+pub(crate) fn expand_home(path: &Path) -> PathBuf {
   if let Ok(stripped_path) = path.strip_prefix("~") {
     if let Some(home_dir) = home_dir() {
-      println!("\n\nNEW PATH AFTER HOME DIR: {:?} \n\n", path);
       return home_dir.join(stripped_path);
     }
   }
@@ -107,7 +104,7 @@ fn remove_repeated_whitespace(input: &str) -> String {
   input.split_whitespace().collect::<Vec<&str>>().join(" ")
 }
 
-pub fn read_pdf_contents(path: PathBuf) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+pub fn read_pdf_text(path: PathBuf) -> Result<String, Box<dyn std::error::Error>> {
   // println!("read_pdf_contents: inside");
   let output = Command::new_sidecar(PDF_TO_TEXT_BINARY_NAME)
     .expect("Failed to execute command.")
@@ -124,73 +121,88 @@ pub fn read_pdf_contents(path: PathBuf) -> Result<Vec<String>, Box<dyn std::erro
   }
   debug!("Content len: {}", output.stdout.len());
 
-  let trimmed_output = remove_repeated_whitespace(&output.stdout);
+  // pdftotext separates pages with form feeds; keep them so chunks can be
+  // attributed to a page.
+  let pdf_text = output
+    .stdout
+    .split(PDF_PAGE_SEPARATOR)
+    .map(|page| remove_repeated_whitespace(page))
+    .collect::<Vec<String>>()
+    .join(PDF_PAGE_SEPARATOR);
 
-  let pdf_text = String::from_utf8_lossy(trimmed_output.as_bytes()).to_string();
-
-  let splitter = TextSplitter::default();
-  Ok(splitter.split_text(&pdf_text))
+  Ok(pdf_text)
 }
 
-pub fn read_contents(content_path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+pub fn read_contents_chunks(
+  content_path: &Path,
+) -> Result<Vec<TextChunk>, Box<dyn std::error::Error>> {
   let path: PathBuf = expand_home(&content_path);
   // println!("read_contents: reading file: {:?}", path);
 
-  // let text_splitter = TextSplitter::default();
-  // let text_chunker = TextChunker::new().max_chunk_token_size(150);
-  // let config = ChunkConfig::new(600).with_overlap(50).unwrap().with_trim(true);
-  // let splitter = TextSplitter::new(config);
-  let splitter = TextSplitter::default();
   if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
-    match extension.to_lowercase().as_str() {
+    let splitter = TextSplitter::for_content_type(ContentType::from_extension(extension));
+    let text = match extension.to_lowercase().as_str() {
       "pdf" => {
         // println!("read_contents: reading pdf: {:?}", path);
-        read_pdf_contents(path)
+        read_pdf_text(path)?
       }
       "docx" | "doc" | "odp" | "ods" | "odt" => {
         if let Ok(mut file) = Docx::open(path) {
           let mut text = String::new();
           let _ = file.read_to_string(&mut text);
-          Ok(splitter.split_text(&text))
+          text
         } else {
-          Err("Error opening document.".into())
+          return Err("Error opening document.".into());
         }
       }
       "xlsx" => {
         if let Ok(mut file) = Xlsx::open(path) {
           let mut text = String::new();
           let _ = file.read_to_string(&mut text);
-          Ok(splitter.split_text(&text))
+          text
         } else {
-          Err("Error opening spreadsheet.".into())
+          return Err("Error opening spreadsheet.".into());
         }
       }
       "pptx" => {
         if let Ok(mut file) = Pptx::open(path) {
           let mut text = String::new();
           let _ = file.read_to_string(&mut text);
-          Ok(splitter.split_text(&text))
+          text
         } else {
-          Err("Error opening powerpoint.".into())
+          return Err("Error opening powerpoint.".into());
         }
       }
-      "txt" | "rtf" => {
+      "txt" | "rtf" | "md" | "markdown" => {
         if let Ok(text) = fs::read_to_string(path) {
-          Ok(splitter.split_text(&text))
+          text
         } else {
-          Err("Error opening text file.".into())
+          return Err("Error opening text file.".into());
         }
       }
       _ => return Err("Unsupported file extension".into()),
-    }
+    };
+    Ok(splitter.split_chunks(&text))
   } else {
     return Err("File has no extension".into());
   }
 }
 
+pub fn read_contents(content_path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  let chunks = read_contents_chunks(content_path)?;
+  Ok(chunks.into_iter().map(|chunk| chunk.text).collect())
+}
+
 pub fn read_file_contents(
   local_file: &LocalFile,
 ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  let chunks = read_file_chunks(local_file)?;
+  Ok(chunks.into_iter().map(|chunk| chunk.text).collect())
+}
+
+pub fn read_file_chunks(
+  local_file: &LocalFile,
+) -> Result<Vec<TextChunk>, Box<dyn std::error::Error>> {
   let path = Path::new(&local_file.path);
 
   if !is_file_content_searchable_extension(path) {
@@ -204,7 +216,7 @@ pub fn read_file_contents(
     return Err(e.into());
   } else {
     debug!("read_file_contents: reading file: {:?}", path);
-    read_contents(Path::new(&local_file.path))
+    read_contents_chunks(Path::new(&local_file.path))
   }
 }
 
@@ -255,98 +267,32 @@ pub fn kn_trigger_file_read_permissions() -> serde_json::Value {
   json!({ "success": true, "permissions": results })
 }
 
+/// Lists the searchable files under Desktop, Downloads and Documents. Progress
+/// is reported by the embedding jobs the files are queued as, see
+/// `SemanticService::add_local_files_to_queue`.
 #[tauri::command]
-pub fn read_home_dir(
-  knapsack_search_indexing_progress: &Arc<AtomicU16>,
-) -> Result<Vec<LocalFile>, String> {
+pub fn read_home_dir() -> Result<Vec<LocalFile>, String> {
   let home_dir = dirs::home_dir().ok_or("Could not find home directory")?;
   debug!("Message from Rust: {}", home_dir.display());
 
   let mut file_infos: Vec<LocalFile> = Vec::new();
-
-  let mut desktop_dir = PathBuf::from(&home_dir);
-  let mut documents_dir = PathBuf::from(&home_dir);
-  let mut downloads_dir = PathBuf::from(&home_dir);
-  let desktop = "Desktop";
-  let documents = "Documents";
-  let downloads = "Downloads";
-
-  desktop_dir.push(desktop);
-  documents_dir.push(documents);
-  downloads_dir.push(downloads);
-
-  knapsack_search_indexing_progress.store(
-    (KNAPSACK_STATUS_READHOME_MIN * 100.0) as u16,
-    Ordering::SeqCst,
-  );
-
-  if let Err(e) = visit_dirs(
-    &desktop_dir,
-    &mut file_infos,
-    &knapsack_search_indexing_progress,
-  ) {
-    log::debug!(
-      "Message from Rust: {}",
-      format!("Error reading directories: {:?}", e)
-    );
-    return Err(format!("Error reading directories: {:?}", e));
-  }
-  knapsack_search_indexing_progress.store(
-    ((KNAPSACK_STATUS_READHOME_MAX - KNAPSACK_STATUS_READHOME_MIN) * (1.0 / 3.0) * 100.0) as u16,
-    Ordering::SeqCst,
-  );
-
-  if let Err(e) = visit_dirs(
-    &downloads_dir,
-    &mut file_infos,
-    &knapsack_search_indexing_progress,
-  ) {
-    log::debug!(
-      "Message from Rust: {}",
-      format!("Error reading directories: {:?}", e)
-    );
-    return Err(format!("Error reading directories: {:?}", e));
-  }
-  knapsack_search_indexing_progress.store(
-    ((KNAPSACK_STATUS_READHOME_MAX - KNAPSACK_STATUS_READHOME_MIN) * (2.0 / 3.0) * 100.0) as u16,
-    Ordering::SeqCst,
-  );
-
-  if let Err(e) = visit_dirs(
-    &documents_dir,
-    &mut file_infos,
-    &knapsack_search_indexing_progress,
-  ) {
-    log::debug!(
-      "Message from Rust: {}",
-      format!("Error reading directories: {:?}", e)
-    );
-    return Err(format!("Error reading directories: {:?}", e));
+  for directory in ["Desktop", "Downloads", "Documents"] {
+    if let Err(e) = visit_dirs(&home_dir.join(directory), &mut file_infos) {
+      log::debug!(
+        "Message from Rust: {}",
+        format!("Error reading directories: {:?}", e)
+      );
+      return Err(format!("Error reading directories: {:?}", e));
+    }
   }
-  knapsack_search_indexing_progress.store(
-    ((KNAPSACK_STATUS_READHOME_MAX - KNAPSACK_STATUS_READHOME_MIN) * (3.0 / 3.0) * 100.0) as u16,
-    Ordering::SeqCst,
-  );
 
   file_infos.sort_by_key(|fi| fi.date_modified);
   debug!("Number Of paths: {}", file_infos.len());
 
-  // TODO: do this in separate thread.
-  // file_upload::upload_and_process_files(paths.clone());
-
-  knapsack_search_indexing_progress.store(
-    (KNAPSACK_STATUS_READHOME_MAX * 100.0) as u16,
-    Ordering::SeqCst,
-  );
-
   Ok(file_infos)
 }
 
-fn visit_dirs(
-  dir: &Path,
-  file_infos: &mut Vec<LocalFile>,
-  knapsack_search_indexing_progress: &Arc<AtomicU16>,
-) -> Result<(), Error> {
+fn visit_dirs(dir: &Path, file_infos: &mut Vec<LocalFile>) -> Result<(), Error> {
   if dir.is_dir() {
     for entry in fs::read_dir(dir)? {
       let entry = entry?;
@@ -356,31 +302,43 @@ fn visit_dirs(
       }
 
       if path.is_dir() {
-        visit_dirs(&path, file_infos, &knapsack_search_indexing_progress)?;
+        visit_dirs(&path, file_infos)?;
       } else if is_file_name_searchable_extension(&path)
         || is_file_content_searchable_extension(&path)
       {
-        let file_size = get_file_size(&path)?;
-        let (date_modified, date_created) = get_file_times(&path)?;
         // TODO: Currently, can't insert yet because we don't have title.
-        file_infos.push(LocalFile {
-          id: None,
-          filename: path.file_name().unwrap().to_string_lossy().into_owned(),
-          path: path.to_string_lossy().into_owned(),
-          file_size,
-          date_modified,
-          date_created,
-          title: "".to_string(),
-          summary: None,
-          checksum: None,
-          timestamp: None,
-        });
+        let mut local_file = local_file_from_path(&path)?;
+        local_file.title = "".to_string();
+        file_infos.push(local_file);
       }
     }
   }
   Ok(())
 }
 
+/// A not yet stored `LocalFile` for `path`, titled after its file name.
+pub fn local_file_from_path(path: &PathBuf) -> Result<LocalFile, Error> {
+  let filename = path
+    .file_name()
+    .ok_or(Error::KSError(format!("Not a file: {}", path.display())))?
+    .to_string_lossy()
+    .into_owned();
+  let file_size = get_file_size(path)?;
+  let (date_modified, date_created) = get_file_times(path)?;
+  Ok(LocalFile {
+    id: None,
+    filename: filename.clone(),
+    path: path.to_string_lossy().into_owned(),
+    file_size,
+    date_modified,
+    date_created,
+    title: filename,
+    summary: None,
+    checksum: None,
+    timestamp: None,
+  })
+}
+
 fn get_file_size(path: &PathBuf) -> Result<u64, Error> {
   let metadata = fs::metadata(path)?;
   Ok(metadata.len())
@@ -413,7 +371,7 @@ pub fn get_local_files_from_dir(dir_path: String) -> Result<Vec<LocalFile>, Stri
 
   let dir = PathBuf::from(&dir_path);
 
-  if let Err(e) = visit_dirs(&dir, &mut file_infos, &Arc::new(AtomicU16::new(0))) {
+  if let Err(e) = visit_dirs(&dir, &mut file_infos) {
     log::debug!(
       "Message from Rust: {}",
       format!("Error reading directories: {:?}", e)
@@ -496,3 +454,37 @@ pub fn get_filename_from_path(path_str: String) -> Option<String> {
   let path = PathBuf::from(path_str);
   path.file_name()?.to_str().map(|s| s.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn scans_searchable_files_and_skips_hidden_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("nested")).unwrap();
+    fs::create_dir_all(dir.path().join(".hidden")).unwrap();
+    fs::write(dir.path().join("notes.md"), "# Notes").unwrap();
+    fs::write(dir.path().join("nested").join("report.pdf"), "pdf").unwrap();
+    fs::write(dir.path().join(".hidden").join("secret.txt"), "secret").unwrap();
+    fs::write(dir.path().join(".draft.txt"), "draft").unwrap();
+
+    let files = get_local_files_from_dir(dir.path().to_string_lossy().to_string()).unwrap();
+    let mut filenames: Vec<String> = files.iter().map(|file| file.filename.clone()).collect();
+    filenames.sort();
+    assert_eq!(filenames, vec!["notes.md".to_string(), "report.pdf".to_string()]);
+    assert!(files.iter().all(|file| file.id.is_none()));
+  }
+
+  #[test]
+  fn local_file_from_path_is_titled_after_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("plan.txt");
+    fs::write(&path, "hello").unwrap();
+
+    let local_file = local_file_from_path(&path).unwrap();
+    assert_eq!(local_file.title, "plan.txt");
+    assert_eq!(local_file.file_size, 5);
+    assert!(local_file_from_path(&dir.path().join("missing.txt")).is_err());
+  }
+}
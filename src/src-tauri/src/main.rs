@@ -13,6 +13,7 @@ extern crate tokio;
 
 mod api;
 mod audio;
+mod backup;
 mod automations;
 mod clawd;
 mod config;
@@ -24,7 +25,9 @@ mod file_upload;
 mod llm;
 mod local_fs;
 mod memory;
+mod retention;
 mod search;
+mod secrets;
 mod server;
 mod spotlight;
 mod transcribe;
@@ -32,6 +35,7 @@ mod user;
 mod utils;
 
 use connections::api::ConnectionsData;
+use clap::Parser;
 use log::info;
 use memory::semantic::start_embed_service;
 use memory::semantic::SemanticService;
@@ -40,7 +44,6 @@ use std::env;
 use std::fs::create_dir_all;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
-use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex as StdMutex;
 use tauri::async_runtime::TokioJoinHandle;
@@ -56,7 +59,8 @@ use window_shadows::set_shadow;
 use crate::audio::microphone::open_microphone_settings;
 use crate::audio::permission::open_screen_recording_settings;
 use crate::connections::microsoft::auth::start_oauth;
-use crate::db::db::{start_database, KNAPSACK_DB_FILENAME};
+use crate::db::db::start_database;
+use crate::db::models::embedding_job::EmbeddingJob;
 use crate::utils::log::setup_logger;
 
 use serde_json::json;
@@ -93,9 +97,11 @@ pub fn release_type() -> Release {
   }
 }
 
-fn setup_database() {
-  tokio::spawn(async {
+fn setup_database(semantic_service: Arc<Mutex<Option<SemanticService>>>) {
+  tokio::spawn(async move {
     start_database().await;
+    retention::start_retention_sweeper();
+    automations::executor::start_automation_executor(semantic_service);
   });
 }
 
@@ -126,7 +132,6 @@ fn setup_database() {
 fn setup_handler(
   app: &mut tauri::App,
   // llm_path: PathBuf,
-  knapsack_gmail_indexing_progress: Arc<AtomicU16>,
   semantic_service: Arc<Mutex<Option<SemanticService>>>,
   is_chatting: Arc<Mutex<AtomicBool>>,
   connections_data: Arc<Mutex<ConnectionsData>>,
@@ -139,17 +144,12 @@ fn setup_handler(
 
   let app_handle = app.handle();
 
-  config::init_knapsack_config(
-    app_handle
-      .path_resolver()
-      .app_local_data_dir()
-      .unwrap_or_default(),
-  );
-
   // Load saved LLM API keys into env vars early, before the actix server
   // starts, so that llm_complete (meeting notes) and transcribe can use them.
   clawd::service::propagate_llm_keys_to_env(&app_handle);
 
+  automations::actions::set_app_handle(app.handle());
+
   let actix_app_handle = app.handle();
 
   // Start the server
@@ -158,7 +158,6 @@ fn setup_handler(
       8897,
       // llm_path,
       actix_app_handle,
-      knapsack_gmail_indexing_progress,
       semantic_service,
       is_chatting,
       connections_data,
@@ -190,11 +189,6 @@ struct UUIDState {
   uuid: StdMutex<Option<String>>,
 }
 
-struct ProgressState {
-  files: Arc<AtomicU16>,
-  emails: Arc<AtomicU16>,
-}
-
 #[tauri::command]
 fn kn_get_or_generate_uuid(state: State<'_, UUIDState>, app: AppHandle) -> String {
   let mut uuid_guard = state.uuid.lock().unwrap();
@@ -203,9 +197,7 @@ fn kn_get_or_generate_uuid(state: State<'_, UUIDState>, app: AppHandle) -> Strin
     uuid.clone()
   } else {
     // Attempt to load the UUID from Knapsack's data dir
-    let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-    let knapsack_data_dir = home_dir.join(".knapsack");
-    let uuid_file = knapsack_data_dir.join("uuid.txt");
+    let uuid_file = config::data_dir().join("uuid.txt");
     let maybe_stored_uuid: Option<String> = uuid_file
       .to_str()
       .and_then(|path| std::fs::read_to_string(path).ok());
@@ -268,30 +260,15 @@ impl Default for RecordingState {
 }
 
 #[tauri::command]
-async fn kn_get_search_indexing_status(
-  progress_state: State<'_, ProgressState>,
-) -> Result<KSearchIndexingStatusResponse, String> {
-  let files_progress = &progress_state.files;
-  let emails_progress = &progress_state.emails;
+async fn kn_get_search_indexing_status() -> Result<KSearchIndexingStatusResponse, String> {
+  let progress = EmbeddingJob::progress().map_err(|error| format!("{:?}", error))?;
   info!(
-    "kn_get_search_indexing_status_files:: progress: {}",
-    files_progress.load(Ordering::SeqCst)
+    "kn_get_search_indexing_status:: pending: {} running: {} failed: {} done: {} progress: {}",
+    progress.pending, progress.running, progress.failed, progress.done, progress.progress
   );
-  info!(
-    "kn_get_search_indexing_status_emails:: progress: {}",
-    emails_progress.load(Ordering::SeqCst)
-  );
-  let email_progress_value = emails_progress.load(Ordering::SeqCst);
-  if email_progress_value > 0 {
-    return Ok(KSearchIndexingStatusResponse {
-      success: true,
-      progress: (files_progress.load(Ordering::SeqCst) + emails_progress.load(Ordering::SeqCst))
-        / 2,
-    });
-  }
   Ok(KSearchIndexingStatusResponse {
     success: true,
-    progress: files_progress.load(Ordering::SeqCst),
+    progress: progress.progress,
   })
 }
 
@@ -467,19 +444,25 @@ async fn kn_execute_command(command: String, cwd: Option<String>) -> Result<Stri
     }
 }
 
+/// Resolves the data root from `--data-root` or `KNAPSACK_DATA_ROOT` before
+/// anything opens the database.
+fn init_config() {
+  let cli_data_root = config::Cli::try_parse()
+    .ok()
+    .and_then(|cli| cli.data_root);
+  config::init_knapsack_config(config::resolve_data_root(cli_data_root));
+}
+
 fn create_data_dir() {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let data_dir = home_dir.join(KNAPSACK_DATA_DIR);
-  let transcripts_dir = data_dir.join(TRANSCRIPTS_DIR);
+  let config = config::knapsack_config();
 
-  create_dir_all(data_dir).expect("Failed to create .knapsack directory");
-  create_dir_all(transcripts_dir).expect("Failed to create transcripts directory");
+  create_dir_all(config.data_dir()).expect("Failed to create .knapsack directory");
+  create_dir_all(config.transcripts_dir()).expect("Failed to create transcripts directory");
 }
 
 // make the db path OS agnostic
 fn create_db_env_variable() {
-  let home_dir = dirs::home_dir().expect("Could not determine the home directory");
-  let db_dir = home_dir.join(KNAPSACK_DB_FILENAME);
+  let db_dir = config::db_path();
   let db_path = db_dir.as_path();
   let db_path_str = db_path.to_str().unwrap();
 
@@ -488,6 +471,7 @@ fn create_db_env_variable() {
 
 #[tokio::main]
 async fn main() {
+  init_config();
   create_data_dir();
   create_db_env_variable();
 
@@ -509,15 +493,9 @@ async fn main() {
   // TODO: enable when we start offering embeddings/RAG.
   // server::qdrant::qdrant_server(8897).await
 
-  let knapsack_search_indexing_progress = Arc::new(AtomicU16::new(0));
-  let knapsack_gmail_indexing_progress = Arc::new(AtomicU16::new(0));
   let is_chatting = Arc::new(Mutex::new(AtomicBool::new(false)));
   let semantic_service = Arc::new(Mutex::new(None));
   let connections_data = Arc::new(Mutex::new(ConnectionsData::new()));
-  let progress_state = ProgressState {
-    files: knapsack_search_indexing_progress.clone(),
-    emails: knapsack_gmail_indexing_progress.clone(),
-  };
   let recording_state = RecordingState::default();
 
   let mut context = tauri::generate_context!();
@@ -613,7 +591,7 @@ async fn main() {
       //     .resolve_resource("resources/embedder.gguf")
       //     .expect("failed to resolve resource"),
       // );
-      setup_database();
+      setup_database(semantic_service.clone());
       // setup_vector_database();
       // setup_embedding_service(
       //   is_chatting.clone(),
@@ -625,7 +603,6 @@ async fn main() {
       setup_handler(
         app,
         // llm_path,
-        knapsack_gmail_indexing_progress,
         semantic_service,
         is_chatting.clone(),
         connections_data,
@@ -657,7 +634,6 @@ async fn main() {
     .manage(UUIDState {
       uuid: StdMutex::new(None),
     }) // Initialize state with no UUID
-    .manage(progress_state)
     .on_window_event(|event| match event.event() {
       tauri::WindowEvent::Focused(true) => {
         event
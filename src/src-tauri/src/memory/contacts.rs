@@ -0,0 +1,575 @@
+use serde_json::Value;
+
+use crate::db::db::try_get_db_conn;
+use crate::db::models::calendar_event::CalendarEvent;
+use crate::db::models::contact::Contact;
+use crate::db::models::email::Email;
+use crate::db::models::local_file::LocalFile;
+use crate::db::models::transcript::{MeetingDetails, Transcript};
+use crate::error::Error;
+
+/// Consumer mail providers; an address there says nothing about employer.
+const PERSONAL_EMAIL_DOMAINS: &[&str] = &[
+  "gmail.com",
+  "googlemail.com",
+  "outlook.com",
+  "hotmail.com",
+  "live.com",
+  "msn.com",
+  "yahoo.com",
+  "icloud.com",
+  "me.com",
+  "mac.com",
+  "aol.com",
+  "proton.me",
+  "protonmail.com",
+  "gmx.com",
+];
+
+/// Second-level suffixes under which the organization is one label deeper,
+/// e.g. `acme.co.uk`.
+const COMPOUND_SUFFIXES: &[&str] = &["co", "com", "org", "net", "ac", "gov", "edu"];
+
+const AUTOMATED_SENDER_MARKERS: &[&str] = &["noreply", "no-reply", "donotreply", "mailer-daemon"];
+
+/// One `Name <address>` pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participant {
+  pub name: Option<String>,
+  pub email: String,
+}
+
+/// Parses an address header such as `"Doe, Jane" <jane@acme.com>, bob@x.io`.
+pub fn parse_address_list(header: &str) -> Vec<Participant> {
+  let mut entries = Vec::new();
+  let mut current = String::new();
+  let mut in_quotes = false;
+  let mut in_angle = false;
+  for c in header.chars() {
+    match c {
+      '"' => in_quotes = !in_quotes,
+      '<' if !in_quotes => in_angle = true,
+      '>' if !in_quotes => in_angle = false,
+      ',' | ';' if !in_quotes && !in_angle => {
+        entries.push(std::mem::take(&mut current));
+        continue;
+      }
+      _ => {}
+    }
+    current.push(c);
+  }
+  entries.push(current);
+
+  entries
+    .iter()
+    .filter_map(|entry| parse_address(entry))
+    .collect()
+}
+
+fn parse_address(entry: &str) -> Option<Participant> {
+  let entry = entry.trim();
+  let (name, email) = match (entry.rfind('<'), entry.rfind('>')) {
+    (Some(start), Some(end)) if start < end => {
+      let name = entry[..start].trim().trim_matches('"').trim();
+      (
+        if name.is_empty() { None } else { Some(name.to_string()) },
+        entry[start + 1..end].trim(),
+      )
+    }
+    _ => (None, entry.trim_matches('"')),
+  };
+  if !email.contains('@') || email.contains(char::is_whitespace) {
+    return None;
+  }
+  Some(Participant {
+    name,
+    email: email.to_lowercase(),
+  })
+}
+
+/// Reads Google (`email`/`displayName`) and Microsoft
+/// (`emailAddress.address`/`emailAddress.name`) attendee lists.
+pub fn parse_attendees_json(attendees_json: &str) -> Vec<Participant> {
+  let attendees: Vec<Value> = serde_json::from_str(attendees_json).unwrap_or_default();
+  attendees
+    .iter()
+    .filter_map(|attendee| {
+      let email = attendee
+        .get("email")
+        .and_then(|email| email.as_str())
+        .or_else(|| {
+          attendee
+            .get("emailAddress")
+            .and_then(|address| address.get("address"))
+            .and_then(|email| email.as_str())
+        })?;
+      let name = attendee
+        .get("displayName")
+        .and_then(|name| name.as_str())
+        .or_else(|| {
+          attendee
+            .get("emailAddress")
+            .and_then(|address| address.get("name"))
+            .and_then(|name| name.as_str())
+        })
+        .filter(|name| !name.is_empty() && !name.contains('@'))
+        .map(|name| name.to_string());
+      parse_address(email).map(|participant| Participant { name, ..participant })
+    })
+    .collect()
+}
+
+/// The registrable domain of an address, or `None` for personal mailboxes.
+pub fn organization_from_email(email: &str) -> Option<String> {
+  let domain = email.rsplit_once('@')?.1.trim().to_lowercase();
+  if domain.is_empty() || PERSONAL_EMAIL_DOMAINS.contains(&domain.as_str()) {
+    return None;
+  }
+  let labels: Vec<&str> = domain.split('.').filter(|label| !label.is_empty()).collect();
+  if labels.len() < 2 {
+    return None;
+  }
+  let keep = if labels.len() >= 3
+    && labels[labels.len() - 1].len() == 2
+    && COMPOUND_SUFFIXES.contains(&labels[labels.len() - 2])
+  {
+    3
+  } else {
+    2
+  };
+  Some(labels[labels.len().saturating_sub(keep)..].join("."))
+}
+
+/// Addresses written anywhere in `text`, e.g. in a contract's signature
+/// block, lowercased and without duplicates.
+pub fn mentioned_addresses(text: &str) -> Vec<String> {
+  let mut addresses: Vec<String> = Vec::new();
+  let tokens = text.split(|c: char| c.is_whitespace() || "<>()[]{},;:\"'".contains(c));
+  for token in tokens {
+    let token = token.trim_end_matches('.');
+    let domain = match token.split_once('@') {
+      Some((local, domain)) if !local.is_empty() => domain,
+      _ => continue,
+    };
+    if !domain.contains('.') || domain.starts_with('.') || domain.contains('@') {
+      continue;
+    }
+    let address = token.to_lowercase();
+    if !addresses.contains(&address) {
+      addresses.push(address);
+    }
+  }
+  addresses
+}
+
+/// Serializes a drive file's owners and last editor for
+/// `DriveDocument::set_people_json`. Each entry is
+/// `(email, display name, role)`.
+pub fn drive_people_json(people: Vec<(String, Option<String>, &str)>) -> String {
+  let people: Vec<Value> = people
+    .into_iter()
+    .map(|(email, name, role)| {
+      serde_json::json!({ "email": email, "displayName": name, "role": role })
+    })
+    .collect();
+  Value::Array(people).to_string()
+}
+
+fn parse_drive_people(people_json: &str) -> Vec<(Participant, &'static str)> {
+  let people: Vec<Value> = serde_json::from_str(people_json).unwrap_or_default();
+  people
+    .iter()
+    .filter_map(|person| {
+      let participant = parse_address(person.get("email")?.as_str()?)?;
+      let name = person
+        .get("displayName")
+        .and_then(|name| name.as_str())
+        .filter(|name| !name.is_empty() && !name.contains('@'))
+        .map(|name| name.to_string());
+      let role = match person.get("role").and_then(|role| role.as_str()) {
+        Some("owner") => "owner",
+        _ => "editor",
+      };
+      Some((Participant { name, ..participant }, role))
+    })
+    .collect()
+}
+
+fn is_automated(email: &str) -> bool {
+  AUTOMATED_SENDER_MARKERS
+    .iter()
+    .any(|marker| email.contains(marker))
+}
+
+/// Addresses of the signed-in accounts; they are not contacts.
+fn own_addresses() -> Vec<String> {
+  let connection = match try_get_db_conn() {
+    Ok(connection) => connection,
+    Err(_) => return Vec::new(),
+  };
+  let mut stmt = match connection.prepare("SELECT email FROM users") {
+    Ok(stmt) => stmt,
+    Err(_) => return Vec::new(),
+  };
+  stmt
+    .query_map([], |row| row.get::<_, String>(0))
+    .map(|rows| rows.filter_map(|row| row.ok()).map(|email| email.to_lowercase()).collect())
+    .unwrap_or_default()
+}
+
+fn link_participants(
+  participants: Vec<(Participant, &str)>,
+  foreign_table: &str,
+  foreign_table_id: u64,
+  occurred_at: Option<i64>,
+  own: &[String],
+) -> Result<(), Error> {
+  for (participant, role) in participants {
+    if own.contains(&participant.email) || is_automated(&participant.email) {
+      continue;
+    }
+    let organization = organization_from_email(&participant.email);
+    let contact_id = Contact::find_or_create(
+      &participant.email,
+      participant.name.as_deref(),
+      organization.as_deref(),
+    )?;
+    Contact::add_link(contact_id, foreign_table, foreign_table_id, role, occurred_at)?;
+  }
+  Ok(())
+}
+
+fn email_participants(email: &Email) -> Vec<(Participant, &'static str)> {
+  let mut participants = Vec::new();
+  for participant in parse_address_list(&email.sender) {
+    participants.push((participant, "sender"));
+  }
+  for participant in parse_address_list(&email.recipient) {
+    participants.push((participant, "recipient"));
+  }
+  for participant in parse_address_list(&email.cc) {
+    participants.push((participant, "cc"));
+  }
+  participants
+}
+
+pub fn index_email(email: &Email) -> Result<(), Error> {
+  let id = match email.id {
+    Some(id) => id,
+    None => return Ok(()),
+  };
+  link_participants(
+    email_participants(email),
+    "emails",
+    id,
+    Some(email.date as i64),
+    &own_addresses(),
+  )
+}
+
+pub fn index_calendar_event(calendar_event: &CalendarEvent) -> Result<(), Error> {
+  let id = match calendar_event.id {
+    Some(id) => id,
+    None => return Ok(()),
+  };
+  let mut participants: Vec<(Participant, &str)> = calendar_event
+    .attendees_json
+    .as_deref()
+    .map(parse_attendees_json)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|participant| (participant, "attendee"))
+    .collect();
+  if let Some(creator) = calendar_event
+    .creator_email
+    .as_deref()
+    .and_then(parse_address)
+  {
+    participants.push((creator, "organizer"));
+  }
+  link_participants(
+    participants,
+    "calendar_events",
+    id,
+    calendar_event.start,
+    &own_addresses(),
+  )
+}
+
+/// Links the people mentioned in a file's text. Only people already known
+/// from mail or meetings are linked, so a mailing list pasted into a PDF
+/// does not create contacts.
+fn link_mentions(
+  text: &str,
+  foreign_table: &str,
+  foreign_table_id: u64,
+  occurred_at: Option<i64>,
+  own: &[String],
+) -> Result<(), Error> {
+  for address in mentioned_addresses(text) {
+    if own.contains(&address) {
+      continue;
+    }
+    if let Some(contact_id) = Contact::find_by_email(&address)?.and_then(|contact| contact.id) {
+      Contact::add_link(contact_id, foreign_table, foreign_table_id, "mentioned", occurred_at)?;
+    }
+  }
+  Ok(())
+}
+
+pub fn index_local_file(local_file: &LocalFile, text: &str) -> Result<(), Error> {
+  let id = match local_file.id {
+    Some(id) => id,
+    None => return Ok(()),
+  };
+  link_mentions(
+    text,
+    "local_files",
+    id,
+    Some(local_file.date_modified as i64),
+    &own_addresses(),
+  )
+}
+
+/// Links a drive file's owners and last editor, plus the known people its
+/// text mentions.
+pub fn index_drive_document(
+  drive_document_id: u64,
+  people_json: &str,
+  text: &str,
+  date_modified: u64,
+) -> Result<(), Error> {
+  let own = own_addresses();
+  link_participants(
+    parse_drive_people(people_json),
+    "drive_documents",
+    drive_document_id,
+    Some(date_modified as i64),
+    &own,
+  )?;
+  link_mentions(
+    text,
+    "drive_documents",
+    drive_document_id,
+    Some(date_modified as i64),
+    &own,
+  )
+}
+
+pub fn index_transcript(transcript: &Transcript) -> Result<(), Error> {
+  let (id, thread_id) = match (transcript.id, transcript.thread_id) {
+    (Some(id), Some(thread_id)) => (id, thread_id),
+    _ => return Ok(()),
+  };
+  let details = MeetingDetails::for_thread(thread_id);
+  let participants = details
+    .participants
+    .as_deref()
+    .map(parse_attendees_json)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|participant| (participant, "participant"))
+    .collect();
+  link_participants(
+    participants,
+    "transcripts",
+    id,
+    transcript.start_time.or(details.date),
+    &own_addresses(),
+  )
+}
+
+/// Links a freshly synced email by uid. The row is re-read because `create`
+/// does not report the id of an upserted row.
+pub fn index_email_by_uid(email_uid: &str) {
+  let result = match Email::find_by_uid(email_uid) {
+    Ok(Some(email)) => index_email(&email),
+    Ok(None) => Ok(()),
+    Err(error) => Err(error),
+  };
+  if let Err(error) = result {
+    log::error!("Failed to index contacts for email {}: {:?}", email_uid, error);
+  }
+}
+
+/// Links a freshly synced calendar event by its provider id.
+pub fn index_calendar_event_by_event_id(event_id: &str) {
+  let result = match CalendarEvent::find_by_event_id(event_id) {
+    Ok(Some(calendar_event)) => index_calendar_event(&calendar_event),
+    Ok(None) => Ok(()),
+    Err(error) => Err(error),
+  };
+  if let Err(error) = result {
+    log::error!("Failed to index contacts for event {}: {:?}", event_id, error);
+  }
+}
+
+/// Rebuilds the whole contact graph from emails, calendar events, meeting
+/// transcripts and drive file owners. Mentions in file text come back as the
+/// files are indexed again. Returns the number of contacts.
+pub fn rebuild_contacts() -> Result<u64, Error> {
+  Contact::delete_all()?;
+  let own = own_addresses();
+
+  let email_ids: Vec<u64> = {
+    let connection = try_get_db_conn()?;
+    let mut stmt =
+      connection.prepare("SELECT id FROM emails WHERE is_deleted IS NULL OR is_deleted = 0")?;
+    let ids = stmt
+      .query_map([], |row| row.get(0))?
+      .collect::<Result<Vec<u64>, _>>()?;
+    ids
+  };
+  for email_id in email_ids {
+    if let Some(email) = Email::find_by_id(email_id)? {
+      link_participants(
+        email_participants(&email),
+        "emails",
+        email_id,
+        Some(email.date as i64),
+        &own,
+      )?;
+    }
+  }
+
+  for calendar_event in CalendarEvent::find_all()? {
+    index_calendar_event(&calendar_event)?;
+  }
+  for transcript in Transcript::find_all()? {
+    index_transcript(&transcript)?;
+  }
+
+  let drive_people: Vec<(u64, u64, String)> = {
+    let connection = try_get_db_conn()?;
+    let mut stmt = connection.prepare(
+      "SELECT id, date_modified, people_json FROM drive_documents WHERE people_json IS NOT NULL",
+    )?;
+    let rows = stmt
+      .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+      .collect::<Result<Vec<(u64, u64, String)>, _>>()?;
+    rows
+  };
+  for (drive_document_id, date_modified, people_json) in drive_people {
+    link_participants(
+      parse_drive_people(&people_json),
+      "drive_documents",
+      drive_document_id,
+      Some(date_modified as i64),
+      &own,
+    )?;
+  }
+
+  Contact::count()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_quoted_names_with_commas() {
+    let participants =
+      parse_address_list("\"Doe, Jane\" <Jane@Acme.com>, bob@example.io; Carol <carol@x.org>");
+    assert_eq!(
+      participants,
+      vec![
+        Participant {
+          name: Some("Doe, Jane".to_string()),
+          email: "jane@acme.com".to_string()
+        },
+        Participant {
+          name: None,
+          email: "bob@example.io".to_string()
+        },
+        Participant {
+          name: Some("Carol".to_string()),
+          email: "carol@x.org".to_string()
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn infers_organization_from_domain() {
+    assert_eq!(organization_from_email("a@mail.acme.com"), Some("acme.com".to_string()));
+    assert_eq!(organization_from_email("a@acme.co.uk"), Some("acme.co.uk".to_string()));
+    assert_eq!(organization_from_email("a@gmail.com"), None);
+  }
+
+  #[test]
+  fn reads_google_and_microsoft_attendees() {
+    let google = r#"[{"email":"a@acme.com","displayName":"Alice"}]"#;
+    let microsoft = r#"[{"emailAddress":{"address":"b@acme.com","name":"Bob"}}]"#;
+    assert_eq!(parse_attendees_json(google)[0].name.as_deref(), Some("Alice"));
+    assert_eq!(parse_attendees_json(microsoft)[0].email, "b@acme.com");
+  }
+
+  #[test]
+  fn finds_addresses_mentioned_in_text() {
+    let text = "Signed by Jane (Jane.Doe@Acme.com) and <bob@x.io>.\nCC: jane.doe@acme.com, @handle, a@localhost";
+    assert_eq!(
+      mentioned_addresses(text),
+      vec!["jane.doe@acme.com".to_string(), "bob@x.io".to_string()]
+    );
+  }
+
+  #[test]
+  fn drive_people_round_trip_with_roles() {
+    let people_json = drive_people_json(vec![
+      ("Owner@Acme.com".to_string(), Some("Olivia".to_string()), "owner"),
+      ("editor@acme.com".to_string(), None, "editor"),
+      ("not an address".to_string(), None, "editor"),
+    ]);
+    let people = parse_drive_people(&people_json);
+    assert_eq!(people.len(), 2);
+    assert_eq!(people[0].0.email, "owner@acme.com");
+    assert_eq!(people[0].0.name.as_deref(), Some("Olivia"));
+    assert_eq!(people[0].1, "owner");
+    assert_eq!(people[1].1, "editor");
+  }
+
+  #[test]
+  fn file_mentions_only_link_known_contacts() {
+    let known = "mention-test-known@acme-mentions.com";
+    let contact_id = Contact::find_or_create(known, Some("Known"), None).unwrap();
+    let local_file = LocalFile {
+      id: Some(9_310_001),
+      filename: "contract.pdf".to_string(),
+      path: "/tmp/contract.pdf".to_string(),
+      file_size: 1,
+      date_modified: 1_700_000_000,
+      date_created: None,
+      title: "contract.pdf".to_string(),
+      summary: None,
+      checksum: None,
+      timestamp: None,
+    };
+    let text = format!("Signed by {} and mention-test-unknown@acme-mentions.com", known);
+    index_local_file(&local_file, &text).unwrap();
+
+    let links = Contact::links(contact_id, 10).unwrap();
+    assert!(links.iter().any(|link| link.foreign_table == "local_files"
+      && link.foreign_table_id == 9_310_001
+      && link.role == "mentioned"));
+    assert!(Contact::find_by_email("mention-test-unknown@acme-mentions.com")
+      .unwrap()
+      .is_none());
+  }
+
+  #[test]
+  fn merging_into_a_missing_contact_keeps_the_other() {
+    let other_id =
+      Contact::find_or_create("merge-test-other@acme-merge.com", Some("Other"), None).unwrap();
+    let missing_id = other_id + 1_000_000;
+
+    assert!(matches!(
+      Contact::merge(missing_id, other_id),
+      Err(Error::RusqliteError(rusqlite::Error::QueryReturnedNoRows))
+    ));
+    assert!(Contact::find_by_id(other_id).unwrap().is_some());
+    let owner = Contact::find_by_email("merge-test-other@acme-merge.com")
+      .unwrap()
+      .unwrap();
+    assert_eq!(owner.id, Some(other_id));
+  }
+}
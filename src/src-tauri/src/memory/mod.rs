@@ -1,3 +1,6 @@
+pub mod contacts;
 pub mod qdrant;
+pub mod related;
+pub mod search_filter;
 pub mod semantic;
 pub mod text_splitter;
@@ -342,3 +342,303 @@ pub async fn get_points(point_ids: Vec<String>) -> Result<GetPointsResponse, Qdr
     Err(error) => Err(handle_qdrant_request_error(error)),
   }
 }
+
+pub async fn set_payload(
+  point_ids: Vec<String>,
+  payload: HashMap<String, Value>,
+) -> Result<(), QdrantError> {
+  let mut qdrant_log_file = get_qdrant_logfile();
+
+  let body = json!({ "payload": payload, "points": point_ids });
+  let client = Client::new();
+  let result = client
+    .post(format!(
+      "{}/collections/{}/points/payload",
+      get_qdrant_base_url(),
+      COLLECTION_NAME
+    ))
+    .json(&body)
+    .send()
+    .await;
+  match result {
+    Ok(response) => {
+      let json = response
+        .json::<BaseQdrantResponse>()
+        .await
+        .map_err(|e| QdrantError::ActionError(CustomQdrantError::new(&e.to_string())))?;
+      match json.status.as_str() {
+        Some("ok") => return Ok(()),
+        Some(status) => {
+          let _ = writeln!(qdrant_log_file, "Knapsack - error setting payload: {}", status);
+        }
+        None => {}
+      }
+      return Err(QdrantError::ActionError(CustomQdrantError::new(
+        "Setting payload failed",
+      )));
+    }
+    Err(error) => Err(handle_qdrant_request_error(error)),
+  }
+}
+
+pub async fn delete_points(point_ids: Vec<String>) -> Result<(), QdrantError> {
+  let mut qdrant_log_file = get_qdrant_logfile();
+
+  let body = json!({ "points": point_ids });
+  let client = Client::new();
+  let result = client
+    .post(format!(
+      "{}/collections/{}/points/delete",
+      get_qdrant_base_url(),
+      COLLECTION_NAME
+    ))
+    .json(&body)
+    .send()
+    .await;
+  match result {
+    Ok(response) => {
+      let json = response
+        .json::<BaseQdrantResponse>()
+        .await
+        .map_err(|e| QdrantError::ActionError(CustomQdrantError::new(&e.to_string())))?;
+      match json.status.as_str() {
+        Some("ok") => return Ok(()),
+        Some(status) => {
+          let _ = writeln!(qdrant_log_file, "Knapsack - error deleting points: {}", status);
+        }
+        None => {}
+      }
+      return Err(QdrantError::ActionError(CustomQdrantError::new(
+        "Deleting points failed",
+      )));
+    }
+    Err(error) => Err(handle_qdrant_request_error(error)),
+  }
+}
+
+/// Deletes every point matching a Qdrant filter, e.g. all chunks of a
+/// document.
+pub async fn delete_points_by_filter(filter: Value) -> Result<(), QdrantError> {
+  let mut qdrant_log_file = get_qdrant_logfile();
+
+  let body = json!({ "filter": filter });
+  let client = Client::new();
+  let result = client
+    .post(format!(
+      "{}/collections/{}/points/delete",
+      get_qdrant_base_url(),
+      COLLECTION_NAME
+    ))
+    .json(&body)
+    .send()
+    .await;
+  match result {
+    Ok(response) => {
+      let json = response
+        .json::<BaseQdrantResponse>()
+        .await
+        .map_err(|e| QdrantError::ActionError(CustomQdrantError::new(&e.to_string())))?;
+      match json.status.as_str() {
+        Some("ok") => return Ok(()),
+        Some(status) => {
+          let _ = writeln!(qdrant_log_file, "Knapsack - error deleting points by filter: {}", status);
+        }
+        None => {}
+      }
+      return Err(QdrantError::ActionError(CustomQdrantError::new(
+        "Deleting points by filter failed",
+      )));
+    }
+    Err(error) => Err(handle_qdrant_request_error(error)),
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScrollPointsResponseResultPoint {
+  pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScrollPointsResponseResult {
+  pub points: Vec<ScrollPointsResponseResultPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScrollPointsResponse {
+  pub result: ScrollPointsResponseResult,
+}
+
+/// Ids of the points matching `filter`, without payloads or vectors.
+pub async fn scroll_point_ids(filter: Value, limit: usize) -> Result<Vec<String>, QdrantError> {
+  let body = json!({
+    "filter": filter,
+    "limit": limit,
+    "with_payload": false,
+    "with_vector": false
+  });
+
+  let client = Client::new();
+  let result = client
+    .post(format!(
+      "{}/collections/{}/points/scroll",
+      get_qdrant_base_url(),
+      COLLECTION_NAME
+    ))
+    .json(&body)
+    .send()
+    .await;
+  match result {
+    Ok(response) => match response.json::<ScrollPointsResponse>().await {
+      Ok(response) => Ok(
+        response
+          .result
+          .points
+          .into_iter()
+          .map(|point| point.id)
+          .collect(),
+      ),
+      Err(error) => Err(QdrantError::ActionError(CustomQdrantError::new(
+        &error.to_string(),
+      ))),
+    },
+    Err(error) => Err(handle_qdrant_request_error(error)),
+  }
+}
+
+/// Points closest to the stored vectors of `positive`, grouped by document
+/// like `search_points`.
+pub async fn recommend_points(
+  positive: Vec<String>,
+  limit: usize,
+  maybe_filter: Option<Value>,
+) -> Result<SearchPointsResponse, QdrantError> {
+  let mut qdrant_log_file = get_qdrant_logfile();
+
+  let mut body = json!({
+    "positive": positive,
+    "limit": limit,
+    "group_by": "document_id",
+    "group_size": 5,
+    "with_payload": true
+  });
+  if let Some(filter) = maybe_filter {
+    body["filter"] = filter;
+  }
+
+  let client = Client::new();
+  let result = client
+    .post(format!(
+      "{}/collections/{}/points/recommend/groups",
+      get_qdrant_base_url(),
+      COLLECTION_NAME
+    ))
+    .json(&body)
+    .send()
+    .await;
+  match result {
+    Ok(response) => match response.json::<SearchPointsResponse>().await {
+      Ok(response) => Ok(response),
+      Err(error) => {
+        let qdrant_err = error.to_string();
+        let _ = writeln!(
+          qdrant_log_file,
+          "Knapsack - error recommending points: {}",
+          qdrant_err
+        );
+        Err(QdrantError::ActionError(CustomQdrantError::new(
+          &qdrant_err,
+        )))
+      }
+    },
+    Err(error) => Err(handle_qdrant_request_error(error)),
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotDescription {
+  pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnapshotResponse {
+  pub result: SnapshotDescription,
+}
+
+fn snapshot_error(message: &str) -> QdrantError {
+  QdrantError::ActionError(CustomQdrantError::new(message))
+}
+
+/// Snapshots the collection and returns the snapshot file. The snapshot is
+/// removed from the Qdrant storage dir once downloaded.
+pub async fn download_collection_snapshot() -> Result<Vec<u8>, QdrantError> {
+  let client = Client::new();
+  let snapshots_url = format!(
+    "{}/collections/{}/snapshots",
+    get_qdrant_base_url(),
+    COLLECTION_NAME
+  );
+  let snapshot = match client
+    .post(format!("{}?wait=true", snapshots_url))
+    .send()
+    .await
+  {
+    Ok(response) => match response.json::<CreateSnapshotResponse>().await {
+      Ok(response) => response.result,
+      Err(error) => return Err(snapshot_error(&error.to_string())),
+    },
+    Err(error) => return Err(handle_qdrant_request_error(error)),
+  };
+
+  let snapshot_url = format!("{}/{}", snapshots_url, snapshot.name);
+  let bytes = match client.get(&snapshot_url).send().await {
+    Ok(response) if response.status().is_success() => response
+      .bytes()
+      .await
+      .map_err(|error| snapshot_error(&error.to_string()))?,
+    Ok(response) => {
+      return Err(snapshot_error(&format!(
+        "Downloading snapshot failed with status {}",
+        response.status()
+      )))
+    }
+    Err(error) => return Err(handle_qdrant_request_error(error)),
+  };
+
+  if let Err(error) = client.delete(&snapshot_url).send().await {
+    log::warn!("Failed to delete snapshot {}: {:?}", snapshot.name, error);
+  }
+  Ok(bytes.to_vec())
+}
+
+/// Replaces the collection with the contents of a snapshot file.
+pub async fn upload_collection_snapshot(snapshot: Vec<u8>) -> Result<(), QdrantError> {
+  let part = reqwest::multipart::Part::bytes(snapshot).file_name("knapsack.snapshot");
+  let form = reqwest::multipart::Form::new().part("snapshot", part);
+  let client = Client::new();
+  let result = client
+    .post(format!(
+      "{}/collections/{}/snapshots/upload?priority=snapshot&wait=true",
+      get_qdrant_base_url(),
+      COLLECTION_NAME
+    ))
+    .multipart(form)
+    .send()
+    .await;
+  match result {
+    Ok(response) => {
+      let json = response
+        .json::<BaseQdrantResponse>()
+        .await
+        .map_err(|error| snapshot_error(&error.to_string()))?;
+      if json.status.as_str() == Some("ok") {
+        Ok(())
+      } else {
+        Err(snapshot_error(&format!(
+          "Restoring snapshot failed: {}",
+          json.status
+        )))
+      }
+    }
+    Err(error) => Err(handle_qdrant_request_error(error)),
+  }
+}
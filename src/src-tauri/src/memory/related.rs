@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::db::models::document::Document;
+use crate::error::Error;
+use crate::memory::semantic::SemanticService;
+
+/// Being in the same email thread or meeting outweighs all but the closest
+/// vector matches.
+const SAME_THREAD_SCORE: f32 = 0.5;
+const SHARED_CONTACT_SCORE: f32 = 0.15;
+const MAX_SHARED_CONTACTS_SCORED: u64 = 3;
+
+/// Documents related to `document_id`, best first. Combines vector
+/// similarity (when the embedding service is running) with thread
+/// membership and shared participants, so it still answers offline.
+pub async fn related_documents(
+  semantic_service: Option<SemanticService>,
+  document_id: u64,
+  limit: usize,
+) -> Result<Vec<Document>, Error> {
+  let mut similar: Vec<(u64, f32)> = Vec::new();
+  if let Some(service) = semantic_service {
+    match service.similar_documents(document_id, limit * 2).await {
+      Ok(results) => {
+        similar = results
+          .into_iter()
+          .map(|result| (result.document_id, result.score))
+          .collect();
+      }
+      Err(error) => log::warn!("Similar documents lookup failed: {:?}", error),
+    }
+  }
+
+  let siblings: Vec<u64> = Document::find_thread_siblings(document_id, limit)?
+    .into_iter()
+    .filter_map(|sibling| sibling.id)
+    .collect();
+  let sharing: Vec<(u64, u64)> = Document::find_sharing_contacts(document_id, limit * 2)?
+    .into_iter()
+    .filter_map(|(document, shared)| document.id.map(|id| (id, shared)))
+    .collect();
+
+  let document_ids = rank(document_id, &similar, &siblings, &sharing, limit);
+  if document_ids.is_empty() {
+    return Ok(Vec::new());
+  }
+  let mut documents_by_id: HashMap<u64, Document> = Document::find_by_ids(&document_ids)?
+    .into_iter()
+    .filter_map(|document| document.id.map(|id| (id, document)))
+    .collect();
+  Ok(
+    document_ids
+      .iter()
+      .filter_map(|document_id| documents_by_id.remove(document_id))
+      .collect(),
+  )
+}
+
+/// Sums the signals per document: vector scores as they are, a bonus for the
+/// same thread and one per shared contact, up to `MAX_SHARED_CONTACTS_SCORED`.
+/// Ties go to the newer (higher) id.
+fn rank(
+  document_id: u64,
+  similar: &[(u64, f32)],
+  siblings: &[u64],
+  sharing: &[(u64, u64)],
+  limit: usize,
+) -> Vec<u64> {
+  let mut scores: HashMap<u64, f32> = HashMap::new();
+  for (id, score) in similar {
+    *scores.entry(*id).or_default() += score;
+  }
+  for id in siblings {
+    *scores.entry(*id).or_default() += SAME_THREAD_SCORE;
+  }
+  for (id, shared) in sharing {
+    *scores.entry(*id).or_default() +=
+      SHARED_CONTACT_SCORE * (*shared).min(MAX_SHARED_CONTACTS_SCORED) as f32;
+  }
+  scores.remove(&document_id);
+
+  let mut ranked: Vec<(u64, f32)> = scores.into_iter().collect();
+  ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+  ranked.truncate(limit);
+  ranked.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_thread_outranks_a_weak_vector_match() {
+    let ranked = rank(1, &[(2, 0.3), (3, 0.9)], &[4], &[], 10);
+    assert_eq!(ranked, vec![3, 4, 2]);
+  }
+
+  #[test]
+  fn signals_add_up_per_document() {
+    // 0.3 + 0.15 * 2 beats a lone 0.55 vector match.
+    let ranked = rank(1, &[(2, 0.3), (3, 0.55)], &[], &[(2, 2)], 10);
+    assert_eq!(ranked, vec![2, 3]);
+  }
+
+  #[test]
+  fn shared_contacts_are_capped() {
+    // Ten shared contacts count as three, so the thread sibling still wins.
+    let ranked = rank(1, &[], &[3], &[(2, 10)], 10);
+    assert_eq!(ranked, vec![3, 2]);
+  }
+
+  #[test]
+  fn excludes_the_source_breaks_ties_by_id_and_truncates() {
+    let ranked = rank(1, &[(1, 1.0), (5, 0.4), (7, 0.4), (9, 0.1)], &[], &[], 2);
+    assert_eq!(ranked, vec![7, 5]);
+  }
+}
@@ -0,0 +1,324 @@
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Kinds of documents that can be searched. Each maps to the `type` payload
+/// field in Qdrant and to the `foreign_table` of the `documents` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceType {
+  Email,
+  Drive,
+  File,
+  Transcript,
+  Notes,
+}
+
+impl SourceType {
+  pub fn payload_type(&self) -> &'static str {
+    match self {
+      SourceType::Email => "gmail",
+      SourceType::Drive => "drive",
+      SourceType::File => "file",
+      SourceType::Transcript => "transcript",
+      SourceType::Notes => "meeting_notes",
+    }
+  }
+
+  pub fn foreign_table(&self) -> &'static str {
+    match self {
+      SourceType::Email => "emails",
+      SourceType::Drive => "drive_documents",
+      SourceType::File => "local_files",
+      SourceType::Transcript => "transcripts",
+      SourceType::Notes => "meeting_notes",
+    }
+  }
+
+  /// Accepts both the payload names ("gmail") used by the legacy
+  /// `data_sources` field and the DSL names ("email").
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "email" | "emails" | "gmail" => Some(SourceType::Email),
+      "drive" | "drive_documents" => Some(SourceType::Drive),
+      "file" | "files" | "local_files" => Some(SourceType::File),
+      "transcript" | "transcripts" => Some(SourceType::Transcript),
+      "notes" | "meeting_notes" => Some(SourceType::Notes),
+      _ => None,
+    }
+  }
+}
+
+/// Structured search filter. Every set field narrows the result (AND);
+/// multiple `sources` or `document_ids` widen within that field (OR), while
+/// every entry in `participants` has to appear on the email or meeting.
+///
+/// Dates are unix seconds and both bounds are inclusive. `account_id` keeps
+/// emails and drive documents of other accounts out; documents without an
+/// owner (local files, meetings, rows synced before accounts) always match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchFilter {
+  #[serde(default)]
+  pub sources: Vec<SourceType>,
+  pub date_from: Option<u64>,
+  pub date_to: Option<u64>,
+  #[serde(default)]
+  pub participants: Vec<String>,
+  pub email_thread_id: Option<String>,
+  pub calendar_event_id: Option<u64>,
+  pub path_prefix: Option<String>,
+  #[serde(default)]
+  pub document_ids: Vec<u64>,
+  pub account_id: Option<u64>,
+}
+
+/// Joins that expose every source table next to `documents d`, for use with
+/// the clause returned by `SearchFilter::to_sql`.
+pub const SQL_DOCUMENT_JOINS: &str = "
+  LEFT JOIN emails e ON d.foreign_table = 'emails' AND e.id = d.foreign_table_id
+  LEFT JOIN drive_documents dd ON d.foreign_table = 'drive_documents' AND dd.id = d.foreign_table_id
+  LEFT JOIN local_files lf ON d.foreign_table = 'local_files' AND lf.id = d.foreign_table_id
+  LEFT JOIN transcripts t ON d.foreign_table = 'transcripts' AND t.id = d.foreign_table_id
+  LEFT JOIN threads th ON th.id = COALESCE(
+    t.thread_id,
+    CASE WHEN d.foreign_table = 'meeting_notes' THEN d.foreign_table_id END
+  )";
+
+/// A `LIKE ? ESCAPE '\'` pattern matching `text` anywhere, with `%`, `_`
+/// and the escape character itself taken literally.
+pub fn contains_pattern(text: &str) -> String {
+  let mut pattern = String::with_capacity(text.len() + 2);
+  pattern.push('%');
+  for c in text.chars() {
+    if c == '%' || c == '_' || c == '\\' {
+      pattern.push('\\');
+    }
+    pattern.push(c);
+  }
+  pattern.push('%');
+  pattern
+}
+
+impl SearchFilter {
+  pub fn is_empty(&self) -> bool {
+    *self == SearchFilter::default()
+  }
+
+  /// Compiles to a Qdrant filter, or `None` when nothing is constrained.
+  /// Qdrant has no prefix match, so `path_prefix` becomes a substring match
+  /// here; use `matches_payload` to drop the false positives.
+  pub fn to_qdrant_filter(&self) -> Option<Value> {
+    let mut must: Vec<Value> = Vec::new();
+
+    if !self.sources.is_empty() {
+      let types: Vec<&str> = self.sources.iter().map(|s| s.payload_type()).collect();
+      must.push(json!({ "key": "type", "match": { "any": types } }));
+    }
+    if !self.document_ids.is_empty() {
+      must.push(json!({ "key": "document_id", "match": { "any": self.document_ids } }));
+    }
+    if self.date_from.is_some() || self.date_to.is_some() {
+      let mut range = serde_json::Map::new();
+      if let Some(date_from) = self.date_from {
+        range.insert("gte".to_string(), json!(date_from));
+      }
+      if let Some(date_to) = self.date_to {
+        range.insert("lte".to_string(), json!(date_to));
+      }
+      must.push(json!({ "key": "date", "range": range }));
+    }
+    for participant in &self.participants {
+      must.push(json!({
+        "should": [
+          { "key": "sender", "match": { "text": participant } },
+          { "key": "recipient", "match": { "text": participant } },
+          { "key": "cc", "match": { "text": participant } },
+          { "key": "participants", "match": { "text": participant } }
+        ]
+      }));
+    }
+    if let Some(thread_id) = &self.email_thread_id {
+      must.push(json!({ "key": "thread_id", "match": { "value": thread_id } }));
+    }
+    if let Some(calendar_event_id) = self.calendar_event_id {
+      must.push(json!({ "key": "calendar_event_id", "match": { "value": calendar_event_id } }));
+    }
+    if let Some(path_prefix) = &self.path_prefix {
+      must.push(json!({ "key": "path", "match": { "text": path_prefix } }));
+    }
+    if let Some(account_id) = self.account_id {
+      must.push(json!({
+        "should": [
+          { "is_empty": { "key": "account_id" } },
+          { "key": "account_id", "match": { "value": account_id } }
+        ]
+      }));
+    }
+
+    if must.is_empty() {
+      return None;
+    }
+    Some(json!({ "must": must }))
+  }
+
+  /// Re-checks the conditions Qdrant can only approximate.
+  pub fn matches_payload(&self, payload: &Value) -> bool {
+    if let Some(path_prefix) = &self.path_prefix {
+      let path = payload.get("path").and_then(|path| path.as_str());
+      if !path.map_or(false, |path| path.starts_with(path_prefix.as_str())) {
+        return false;
+      }
+    }
+    true
+  }
+
+  /// Compiles to a SQL condition over `documents d` joined with
+  /// `SQL_DOCUMENT_JOINS`, with its positional parameters.
+  pub fn to_sql(&self) -> (String, Vec<SqlValue>) {
+    let mut clauses: Vec<String> = Vec::new();
+    let mut params: Vec<SqlValue> = Vec::new();
+
+    if !self.sources.is_empty() {
+      let placeholders = self.sources.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+      clauses.push(format!("d.foreign_table IN ({})", placeholders));
+      for source in &self.sources {
+        params.push(SqlValue::Text(source.foreign_table().to_string()));
+      }
+    }
+    if !self.document_ids.is_empty() {
+      let placeholders = self.document_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+      clauses.push(format!("d.id IN ({})", placeholders));
+      for document_id in &self.document_ids {
+        params.push(SqlValue::Integer(*document_id as i64));
+      }
+    }
+    let date_column = "COALESCE(e.date, dd.date_modified, lf.date_modified, th.timestamp)";
+    if let Some(date_from) = self.date_from {
+      clauses.push(format!("{} >= ?", date_column));
+      params.push(SqlValue::Integer(date_from as i64));
+    }
+    if let Some(date_to) = self.date_to {
+      clauses.push(format!("{} <= ?", date_column));
+      params.push(SqlValue::Integer(date_to as i64));
+    }
+    for participant in &self.participants {
+      clauses.push(
+        "(e.sender LIKE ? ESCAPE '\\' OR e.recipient LIKE ? ESCAPE '\\'
+          OR e.cc LIKE ? ESCAPE '\\' OR EXISTS (
+          SELECT 1 FROM automation_runs ar
+          JOIN calendar_events ce ON ce.id = json_extract(ar.run_params, '$.event_id')
+          WHERE ar.feed_item_id = th.feed_item_id AND ce.attendees_json LIKE ? ESCAPE '\\'))"
+          .to_string(),
+      );
+      let pattern = contains_pattern(participant);
+      for _ in 0..4 {
+        params.push(SqlValue::Text(pattern.clone()));
+      }
+    }
+    if let Some(thread_id) = &self.email_thread_id {
+      clauses.push("e.thread_id = ?".to_string());
+      params.push(SqlValue::Text(thread_id.clone()));
+    }
+    if let Some(calendar_event_id) = self.calendar_event_id {
+      // Documents only know about a calendar event through the meeting
+      // thread the event's automation run produced.
+      clauses.push(
+        "EXISTS (SELECT 1 FROM automation_runs ar
+          WHERE ar.feed_item_id = th.feed_item_id
+          AND json_extract(ar.run_params, '$.event_id') = ?)"
+          .to_string(),
+      );
+      params.push(SqlValue::Integer(calendar_event_id as i64));
+    }
+    if let Some(path_prefix) = &self.path_prefix {
+      clauses.push("substr(lf.path, 1, length(?)) = ?".to_string());
+      params.push(SqlValue::Text(path_prefix.clone()));
+      params.push(SqlValue::Text(path_prefix.clone()));
+    }
+    if let Some(account_id) = self.account_id {
+      clauses.push(
+        "(COALESCE(e.account_id, dd.account_id) IS NULL OR COALESCE(e.account_id, dd.account_id) = ?)"
+          .to_string(),
+      );
+      params.push(SqlValue::Integer(account_id as i64));
+    }
+
+    if clauses.is_empty() {
+      return ("1 = 1".to_string(), params);
+    }
+    (clauses.join(" AND "), params)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn contains_pattern_escapes_like_wildcards() {
+    assert_eq!(contains_pattern("acme"), "%acme%");
+    assert_eq!(contains_pattern("100%"), "%100\\%%");
+    assert_eq!(contains_pattern("first_name"), "%first\\_name%");
+    assert_eq!(contains_pattern("C:\\notes"), "%C:\\\\notes%");
+  }
+
+  #[test]
+  fn to_sql_binds_one_param_per_placeholder() {
+    let filter = SearchFilter {
+      sources: vec![SourceType::Email, SourceType::File],
+      date_from: Some(10),
+      date_to: Some(20),
+      participants: vec!["alice@acme.com".to_string()],
+      email_thread_id: Some("thread".to_string()),
+      calendar_event_id: Some(7),
+      path_prefix: Some("/Users/me".to_string()),
+      document_ids: vec![1, 2, 3],
+      account_id: Some(4),
+    };
+    let (clause, params) = filter.to_sql();
+    assert_eq!(clause.matches('?').count(), params.len());
+    assert_eq!(params[0], SqlValue::Text("emails".to_string()));
+    assert_eq!(params[1], SqlValue::Text("local_files".to_string()));
+    assert!(params.contains(&SqlValue::Text("%alice@acme.com%".to_string())));
+    assert_eq!(params.last(), Some(&SqlValue::Integer(4)));
+
+    let (clause, params) = SearchFilter::default().to_sql();
+    assert_eq!(clause, "1 = 1");
+    assert!(params.is_empty());
+  }
+
+  #[test]
+  fn to_qdrant_filter_maps_sources_and_accounts() {
+    assert!(SearchFilter::default().to_qdrant_filter().is_none());
+
+    let filter = SearchFilter {
+      sources: vec![SourceType::Email, SourceType::Notes],
+      account_id: Some(2),
+      ..Default::default()
+    };
+    let qdrant_filter = filter.to_qdrant_filter().unwrap();
+    let must = qdrant_filter["must"].as_array().unwrap();
+    assert_eq!(must[0]["match"]["any"], json!(["gmail", "meeting_notes"]));
+    assert_eq!(must[1]["should"][0]["is_empty"]["key"], "account_id");
+  }
+
+  #[test]
+  fn matches_payload_checks_the_path_prefix() {
+    let filter = SearchFilter {
+      path_prefix: Some("/Users/me/Documents".to_string()),
+      ..Default::default()
+    };
+    assert!(filter.matches_payload(&json!({ "path": "/Users/me/Documents/plan.md" })));
+    assert!(!filter.matches_payload(&json!({ "path": "/tmp/Users/me/Documents/plan.md" })));
+    assert!(!filter.matches_payload(&json!({ "type": "gmail" })));
+    assert!(SearchFilter::default().matches_payload(&json!({})));
+  }
+
+  #[test]
+  fn parses_payload_and_dsl_source_names() {
+    assert_eq!(SourceType::parse("gmail"), Some(SourceType::Email));
+    assert_eq!(SourceType::parse("email"), Some(SourceType::Email));
+    assert_eq!(SourceType::parse("meeting_notes"), Some(SourceType::Notes));
+    assert_eq!(SourceType::parse("calendar"), None);
+  }
+}
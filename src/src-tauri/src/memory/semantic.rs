@@ -1,13 +1,12 @@
 use actix_web::{
-  post,
-  web::{Data, Json},
+  get, post,
+  web::{Data, Json, Query},
   Responder,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sha256::digest;
 use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -17,7 +16,6 @@ use uuid::Uuid;
 use crate::{
   api::document::DisplayDocument,
   llm::types::{LLMError, MaxTokensArgs, StringToTokensArgs},
-  memory::qdrant::{get_points, GetPointsResponse},
 };
 use crate::{
   connections::api::ConnectionsEnum,
@@ -25,32 +23,73 @@ use crate::{
 };
 use crate::{
   db::models::document::Document,
+  db::models::embedding_job::{EmbeddingJob, EmbeddingJobProgress, EmbeddingJobStatus},
+  db::models::embedding_manifest::{EmbeddingManifestEntry, ManifestChange},
+  db::models::local_file::LocalFile,
+  db::models::user::User,
+  db::repo::run_blocking,
   llm::{llama_binding::llm::LlamaBinding, types::EmbeddingLlm},
 };
 use crate::{
   llm::types::{EmbeddingArgs, EmbeddingTokensArgs},
   ConnectionsData,
 };
-use priority_queue::PriorityQueue;
 use std::time::Instant;
+use crate::backup::BackgroundWork;
+use crate::constants::EMBEDDING_BATCH_SIZE;
+use crate::local_fs::local_file_from_path;
+use crate::memory::contacts::index_local_file;
 use crate::utils::platform::{OS, get_os};
 
-use super::qdrant::{create_collection, get_point, search_points, upsert_points};
+use super::search_filter::{SearchFilter, SourceType};
+use super::text_splitter::{ContentType, TextSplitter, TokenCounter};
 
-#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+use super::qdrant::{
+  create_collection, delete_points, delete_points_by_filter, recommend_points, scroll_point_ids, search_points,
+  set_payload, upsert_points, SearchPointsResponseResultItem,
+};
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 enum QueueAction {
   CreateCollection,
   UpsertPoints,
   HandleFinishEmbedding,
+  DeletePoints,
+  IndexLocalFiles,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+impl QueueAction {
+  fn as_str(&self) -> &'static str {
+    match self {
+      QueueAction::CreateCollection => "create_collection",
+      QueueAction::UpsertPoints => "upsert_points",
+      QueueAction::HandleFinishEmbedding => "handle_finish_embedding",
+      QueueAction::DeletePoints => "delete_points",
+      QueueAction::IndexLocalFiles => "index_local_files",
+    }
+  }
+
+  fn from_str(action: &str) -> Option<Self> {
+    match action {
+      "create_collection" => Some(QueueAction::CreateCollection),
+      "upsert_points" => Some(QueueAction::UpsertPoints),
+      "handle_finish_embedding" => Some(QueueAction::HandleFinishEmbedding),
+      "delete_points" => Some(QueueAction::DeletePoints),
+      "index_local_files" => Some(QueueAction::IndexLocalFiles),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 enum QueueItemPayload {
   Embedding(QueueItemEmbeddingPayload),
   HandleFinishEmbedding(QueueItemFinishEmbeddingPayload),
+  DeletePoints(QueueItemDeletePointsPayload),
+  IndexLocalFiles(QueueItemIndexLocalFilesPayload),
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 struct QueueItemEmbeddingPayload {
   ids: Vec<String>,
   payloads: Vec<HashMap<String, Value>>,
@@ -58,35 +97,47 @@ struct QueueItemEmbeddingPayload {
   hashes: Vec<(String, String)>,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 struct QueueItemFinishEmbeddingPayload {
   connection: ConnectionsEnum,
 }
 
-impl Hash for QueueItemEmbeddingPayload {
-  fn hash<H: Hasher>(&self, state: &mut H) {
-    self.ids.hash(state);
-    self.embed_fields.hash(state);
-  }
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+struct QueueItemDeletePointsPayload {
+  document_ids: Vec<u64>,
 }
-impl Hash for QueueItemFinishEmbeddingPayload {
-  fn hash<H: Hasher>(&self, state: &mut H) {
-    self.connection.hash(state);
-  }
+
+/// Files found by a local scan, read and embedded as one job per batch.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+struct QueueItemIndexLocalFilesPayload {
+  paths: Vec<String>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 struct QueueItem {
   action: QueueAction,
-  retries: u16,
   payload: Option<QueueItemPayload>,
 }
 
+impl QueueItem {
+  fn from_job(job: &EmbeddingJob) -> Result<Self, String> {
+    let action = QueueAction::from_str(&job.action)
+      .ok_or(format!("Unknown embedding job action {}", job.action))?;
+    let payload = match &job.payload {
+      Some(payload) => Some(
+        serde_json::from_value(payload.clone())
+          .map_err(|error| format!("Invalid embedding job payload {:?}", error))?,
+      ),
+      None => None,
+    };
+    Ok(QueueItem { action, payload })
+  }
+}
+
 #[derive(Clone)]
 pub struct SemanticService {
   llama: Arc<Mutex<LlamaBinding>>,
   embedder_path: Arc<RwLock<PathBuf>>,
-  queue: Arc<Mutex<PriorityQueue<QueueItem, u16>>>,
   is_chatting: Arc<Mutex<AtomicBool>>,
   app_handle: tauri::AppHandle,
   connections_data: Arc<Mutex<ConnectionsData>>,
@@ -108,7 +159,6 @@ impl SemanticService {
     connections_data: Arc<Mutex<ConnectionsData>>,
   ) -> Self {
     Self {
-      queue: Arc::new(Mutex::new(PriorityQueue::new())),
       llama: Arc::new(Mutex::new(LlamaBinding::default())),
       embedder_path: Arc::new(RwLock::new(embedder_path)),
       is_chatting,
@@ -139,29 +189,79 @@ impl SemanticService {
       .await
   }
 
-  pub async fn get_sliced_tokens(&self, data: String) -> Vec<Vec<i32>> {
+  /// A splitter sized in tokens of the active embedder, so chunks never need
+  /// to be truncated to fit its context.
+  pub async fn text_splitter(&self, content_type: ContentType) -> TextSplitter {
     let llama = self.llama.lock().await;
     let embedder_path = self.embedder_path.read().await;
-    let mut splitted_embed_fields = Vec::new();
     let max_tokens = llama
       .get_max_tokens(MaxTokensArgs {
         model_path: embedder_path.to_string_lossy().to_string(),
       })
       .await;
-    let mut tokens = llama
-      .string_to_tokens(StringToTokensArgs {
+    let tokenizer = llama.tokenizer(&embedder_path).await;
+    let token_counter: TokenCounter = Arc::new(move |text: &str| {
+      tokenizer
+        .tokenize_bytes(text.as_bytes(), false, false)
+        .map(|tokens| tokens.len())
+        .unwrap_or(text.len())
+    });
+    TextSplitter::for_content_type(content_type).with_token_counter(token_counter, max_tokens)
+  }
+
+  pub async fn get_sliced_tokens(&self, data: String) -> Vec<Vec<i32>> {
+    let splitter = self.text_splitter(ContentType::Plain).await;
+    let mut pieces = splitter.split_text(&data);
+    if pieces.is_empty() {
+      pieces.push(data);
+    }
+
+    let llama = self.llama.lock().await;
+    let embedder_path = self.embedder_path.read().await;
+    let max_tokens = llama
+      .get_max_tokens(MaxTokensArgs {
         model_path: embedder_path.to_string_lossy().to_string(),
-        data,
       })
       .await;
-    while tokens.len() > 0 {
-      let slice = tokens.iter().take(max_tokens).cloned().collect::<Vec<_>>();
-      splitted_embed_fields.push(slice);
-      tokens = tokens.iter().skip(max_tokens).cloned().collect();
+    let mut splitted_embed_fields = Vec::new();
+    for piece in pieces {
+      let tokens = llama
+        .string_to_tokens(StringToTokensArgs {
+          model_path: embedder_path.to_string_lossy().to_string(),
+          data: piece,
+        })
+        .await;
+      // The splitter counts without special tokens; keep a hard cap anyway.
+      splitted_embed_fields.push(tokens.into_iter().take(max_tokens).collect::<Vec<_>>());
     }
     splitted_embed_fields
   }
 
+  async fn embedding_model_id(&self) -> String {
+    let embedder_path = self.embedder_path.read().await;
+    let file_name = embedder_path
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+    let file_size = std::fs::metadata(&*embedder_path)
+      .map(|metadata| metadata.len())
+      .unwrap_or(0);
+    format!("{}:{}", file_name, file_size)
+  }
+
+  fn payload_chunk_id(payload: &HashMap<String, Value>) -> u64 {
+    payload
+      .get("chunk_id")
+      .and_then(|chunk_id| chunk_id.as_u64())
+      .unwrap_or(0)
+  }
+
+  fn payload_document_id(payload: &HashMap<String, Value>) -> Option<u64> {
+    payload
+      .get("document_id")
+      .and_then(|document_id| document_id.as_u64())
+  }
+
   pub async fn upsert_points(
     &self,
     ids: Vec<String>,
@@ -170,94 +270,166 @@ impl SemanticService {
     hashes: Vec<(String, String)>,
   ) -> Result<(), String> {
     let perf_start = Instant::now();
-    let mut tokenized_embed_fields: Vec<Vec<i32>> = Vec::new();
-    let mut embed_fields_split_count: Vec<usize> = Vec::new();
+    let model_id = self.embedding_model_id().await;
+    let manifest: HashMap<(String, u64), EmbeddingManifestEntry> =
+      match EmbeddingManifestEntry::find_by_base_ids(&ids) {
+        Ok(entries) => entries
+          .into_iter()
+          .map(|entry| ((entry.base_id.clone(), entry.chunk_id), entry))
+          .collect(),
+        Err(error) => {
+          log::error!("Failed to read embedding manifest {:?}", error);
+          HashMap::new()
+        }
+      };
 
-    let mut payloads = payloads.clone();
-    let mut ids = ids.clone();
-    let mut embed_fields = embed_fields.clone();
-    let embed_hash_key = "embed_hash".to_string();
-    let metadata_hash_key = "metadata_hash".to_string();
-    let mut remove_counts = 0;
-    let response = get_points(ids.clone())
-      .await
-      .unwrap_or(GetPointsResponse { result: None });
-    let points = response.result.unwrap_or(vec![]);
-    for (index, document_id) in ids.clone().into_iter().enumerate() {
-      let points_clone = points.clone();
-      for point in points_clone {
-        if point.id == document_id {
-          let (embed_hash, metadata_hash) = hashes[index].clone();
-          let found_embed_hash = point
-            .payload
-            .get(&embed_hash_key)
-            .unwrap()
-            .as_str()
-            .unwrap();
-          let found_embed_metadata = point
-            .payload
-            .get(&metadata_hash_key)
-            .unwrap()
-            .as_str()
-            .unwrap();
-          if found_embed_hash == embed_hash.clone() && found_embed_metadata == metadata_hash.clone()
-          {
-            payloads.remove(index - remove_counts);
-            ids.remove(index - remove_counts);
-            embed_fields.remove(index - remove_counts);
-            remove_counts += 1;
-            break;
+    let mut embed_ids = Vec::new();
+    let mut embed_payloads = Vec::new();
+    let mut embed_texts = Vec::new();
+    let mut embed_hashes = Vec::new();
+    let mut stale_entries: HashMap<String, EmbeddingManifestEntry> = HashMap::new();
+    let mut skipped_count = 0;
+    let mut payload_only_count = 0;
+
+    for (index, id) in ids.into_iter().enumerate() {
+      let (embed_hash, metadata_hash) = hashes[index].clone();
+      let chunk_id = Self::payload_chunk_id(&payloads[index]);
+      if let Some(entry) = manifest.get(&(id.clone(), chunk_id)) {
+        match entry.classify(&embed_hash, &metadata_hash, &model_id) {
+          ManifestChange::Unchanged => {
+            let document_id = Self::payload_document_id(&payloads[index]);
+            if entry.document_id.is_none() && document_id.is_some() {
+              let mut updated_entry = entry.clone();
+              updated_entry.document_id = document_id;
+              if let Err(error) = updated_entry.upsert() {
+                log::error!("Failed to update embedding manifest {:?}", error);
+              }
+            }
+            skipped_count += 1;
+            continue;
+          }
+          ManifestChange::MetadataOnly => {
+            if let Err(error) = set_payload(entry.point_ids.clone(), payloads[index].clone()).await
+            {
+              log::error!("Updating payload failed {:?}", error);
+              continue;
+            }
+            let mut updated_entry = entry.clone();
+            updated_entry.metadata_hash = metadata_hash;
+            updated_entry.document_id = Self::payload_document_id(&payloads[index]);
+            if let Err(error) = updated_entry.upsert() {
+              log::error!("Failed to update embedding manifest {:?}", error);
+            }
+            payload_only_count += 1;
+            continue;
+          }
+          ManifestChange::Embedding => {
+            stale_entries.insert(id.clone(), entry.clone());
           }
         }
       }
+      embed_ids.push(id);
+      embed_payloads.push(payloads[index].clone());
+      embed_texts.push(embed_fields[index].clone());
+      embed_hashes.push((embed_hash, metadata_hash));
     }
 
-    if embed_fields.len() == 0 {
+    log::debug!(
+      "Embedding manifest: {} unchanged, {} payload-only, {} to embed",
+      skipped_count,
+      payload_only_count,
+      embed_ids.len()
+    );
+    if embed_texts.len() == 0 {
       return Ok(());
     }
-    for embed_field in embed_fields {
+
+    let mut tokenized_embed_fields: Vec<Vec<i32>> = Vec::new();
+    let mut embed_fields_split_count: Vec<usize> = Vec::new();
+    for embed_field in embed_texts {
       let splitted_embedded_fields = self.get_sliced_tokens(embed_field).await;
       embed_fields_split_count.push(splitted_embedded_fields.len());
       tokenized_embed_fields.extend(splitted_embedded_fields);
     }
 
-    if let Ok(vectors) = self.embed_tokens(tokenized_embed_fields.clone()).await {
-      let mut vector_index = 0;
-      for (document_index, id) in ids.into_iter().enumerate() {
-        let split_count = (&embed_fields_split_count[document_index]).clone();
-        let mut point_id = id.clone();
-        let payload = payloads[document_index].clone();
+    let vectors = match self.embed_tokens(tokenized_embed_fields.clone()).await {
+      Ok(vectors) => vectors,
+      Err(error) => return Err(format!("Embedding failed {:?}", error)),
+    };
 
-        for split_index in 0..split_count {
-          let vector = &vectors[vector_index];
-          if split_index > 0 {
-            point_id = Uuid::new_v5(
-              &Uuid::NAMESPACE_DNS,
-              format!("{}/{}", point_id.clone(), split_index).as_bytes(),
-            )
-            .to_string();
-          }
+    let mut vector_index = 0;
+    let mut failed_count = 0;
+    for (document_index, id) in embed_ids.into_iter().enumerate() {
+      let split_count = (&embed_fields_split_count[document_index]).clone();
+      let mut point_id = id.clone();
+      let payload = embed_payloads[document_index].clone();
+      let mut point_ids = Vec::new();
+      let mut upserted = true;
 
-          let result = upsert_points(
-            vec![point_id.clone()],
-            vec![payload.clone()],
-            vec![vector.clone()],
+      for split_index in 0..split_count {
+        let vector = &vectors[vector_index];
+        if split_index > 0 {
+          point_id = Uuid::new_v5(
+            &Uuid::NAMESPACE_DNS,
+            format!("{}/{}", point_id.clone(), split_index).as_bytes(),
           )
-          .await;
-          if let Err(error) = result {
-            log::error!("Upsert point failed {:?}", error);
+          .to_string();
+        }
+
+        let result = upsert_points(
+          vec![point_id.clone()],
+          vec![payload.clone()],
+          vec![vector.clone()],
+        )
+        .await;
+        if let Err(error) = result {
+          log::error!("Upsert point failed {:?}", error);
+          upserted = false;
+        }
+        point_ids.push(point_id.clone());
+        vector_index += 1;
+      }
+
+      if !upserted {
+        failed_count += 1;
+        continue;
+      }
+
+      if let Some(previous_entry) = stale_entries.get(&id) {
+        let orphaned_point_ids = previous_entry.orphaned_point_ids(&point_ids);
+        if orphaned_point_ids.len() > 0 {
+          if let Err(error) = delete_points(orphaned_point_ids).await {
+            log::error!("Deleting stale points failed {:?}", error);
           }
-          vector_index += 1;
         }
       }
+
+      let (embed_hash, metadata_hash) = embed_hashes[document_index].clone();
+      let mut entry = EmbeddingManifestEntry {
+        id: None,
+        base_id: id.clone(),
+        chunk_id: Self::payload_chunk_id(&payload),
+        embed_hash,
+        metadata_hash,
+        model_id: model_id.clone(),
+        point_ids,
+        document_id: Self::payload_document_id(&payload),
+        timestamp: None,
+      };
+      if let Err(error) = entry.upsert() {
+        log::error!("Failed to write embedding manifest {:?}", error);
+      }
     }
     let duration = perf_start.elapsed();
     println!("Embedding::done: time {:?}", duration);
-    // Todo: return Err in case upsert failed
+    if failed_count > 0 {
+      return Err(format!("Upserting {} documents failed", failed_count));
+    }
     Ok(())
   }
 
   pub async fn create_collection(&self) -> Result<(), QdrantError> {
+    crate::db::db::migrate_qdrant().await;
     create_collection().await
   }
 
@@ -319,31 +491,9 @@ impl SemanticService {
         metadata_hash_key.clone(),
         Value::String(metadata_hash.clone()),
       );
-      // let wow = (embed_hash.clone(), metadata_hash.clone());
       document_payload.insert("base_id".to_string(), Value::String(id.clone().to_string()));
       upsert_hash_keys.push((embed_hash.clone(), metadata_hash.clone()));
 
-      // let maybe_point = get_point(id.to_string()).await;
-      // if let Ok(point) = maybe_point {
-      //   if let Some(result) = point.result {
-      //     let found_embed_hash = result
-      //       .payload
-      //       .get(&embed_hash_key)
-      //       .unwrap()
-      //       .as_str()
-      //       .unwrap();
-      //     let found_embed_metadata = result
-      //       .payload
-      //       .get(&metadata_hash_key)
-      //       .unwrap()
-      //       .as_str()
-      //       .unwrap();
-      //     if found_embed_hash == embed_hash.clone() && found_embed_metadata == metadata_hash.clone()
-      //     {
-      //       continue;
-      //     }
-      //   }
-      // }
       upsert_payloads.push(document_payload);
       let parsed_embed_fields: Vec<String> = document_embed_fields
         .clone()
@@ -440,38 +590,7 @@ impl SemanticService {
         )
         .await;
         match response {
-          Ok(response) => {
-            let mut results = Vec::new();
-            let groups = response.result.groups;
-            for item in groups {
-              let mut chunk_ids = Vec::new();
-              let mut payloads: Vec<Value> = vec![];
-              let id = item.hits[0].id.clone();
-              let score = item.hits[0].score.clone();
-              for hit in item.hits.into_iter() {
-                let chunk_id = hit
-                  .payload
-                  .get("chunk_id")
-                  .map(|chunk_id| chunk_id.as_u64())
-                  .flatten();
-                if let Some(chunk_id) = chunk_id {
-                  println!("SS CHUNK ID: {}", chunk_id);
-                  chunk_ids.push(chunk_id);
-                }
-                payloads.push(hit.payload);
-              }
-              let document_id = payloads[0].get("document_id").unwrap().as_u64();
-              println!("SS for DOC ID: {:?}", document_id);
-              results.push(SemanticSearchResult {
-                id,
-                score,
-                payloads,
-                chunk_ids,
-                document_id: document_id.unwrap(),
-              });
-            }
-            Ok(results)
-          }
+          Ok(response) => Ok(Self::group_results(response.result.groups)),
           Err(error) => Err(Error::KSError(error.to_string())),
         }
       }
@@ -479,16 +598,86 @@ impl SemanticService {
     }
   }
 
+  fn group_results(groups: Vec<SearchPointsResponseResultItem>) -> Vec<SemanticSearchResult> {
+    let mut results = Vec::new();
+    for item in groups {
+      let mut chunk_ids = Vec::new();
+      let mut payloads: Vec<Value> = vec![];
+      let id = item.hits[0].id.clone();
+      let score = item.hits[0].score.clone();
+      for hit in item.hits.into_iter() {
+        let chunk_id = hit
+          .payload
+          .get("chunk_id")
+          .map(|chunk_id| chunk_id.as_u64())
+          .flatten();
+        if let Some(chunk_id) = chunk_id {
+          chunk_ids.push(chunk_id);
+        }
+        payloads.push(hit.payload);
+      }
+      let document_id = match payloads[0].get("document_id").and_then(|id| id.as_u64()) {
+        Some(document_id) => document_id,
+        None => continue,
+      };
+      results.push(SemanticSearchResult {
+        id,
+        score,
+        payloads,
+        chunk_ids,
+        document_id,
+      });
+    }
+    results
+  }
+
+  /// Documents whose chunks sit closest to the stored vectors of
+  /// `document_id`, excluding the document itself.
+  pub async fn similar_documents(
+    &self,
+    document_id: u64,
+    limit: usize,
+  ) -> Result<Vec<SemanticSearchResult>, Error> {
+    let document_filter = json!({ "key": "document_id", "match": { "value": document_id } });
+    let point_ids = scroll_point_ids(json!({ "must": [document_filter.clone()] }), 16)
+      .await
+      .map_err(|error| Error::KSError(error.to_string()))?;
+    if point_ids.is_empty() {
+      return Ok(Vec::new());
+    }
+    let response = recommend_points(point_ids, limit, Some(json!({ "must_not": [document_filter] })))
+      .await
+      .map_err(|error| Error::KSError(error.to_string()))?;
+    Ok(Self::group_results(response.result.groups))
+  }
+
+  /// Semantic search constrained by a typed filter.
+  pub async fn filtered_search(
+    &self,
+    query: String,
+    limit: usize,
+    filter: &SearchFilter,
+  ) -> Result<Vec<SemanticSearchResult>, Error> {
+    let results = self
+      .semantic_search(query, limit, filter.to_qdrant_filter(), None, None, Some(true), None)
+      .await?;
+    Ok(
+      results
+        .into_iter()
+        .filter(|result| result.payloads.iter().any(|payload| filter.matches_payload(payload)))
+        .collect(),
+    )
+  }
+
   pub async fn add_handle_embed_finish_to_queue(&self, connection: ConnectionsEnum, priority: u16) {
-    let queue_item = QueueItem {
-      action: QueueAction::HandleFinishEmbedding,
-      payload: Some(QueueItemPayload::HandleFinishEmbedding(
+    self.enqueue(
+      QueueAction::HandleFinishEmbedding,
+      Some(QueueItemPayload::HandleFinishEmbedding(
         QueueItemFinishEmbeddingPayload { connection },
       )),
-      retries: 1,
-    };
-    let mut queue = self.queue.lock().await;
-    queue.push(queue_item, priority);
+      priority,
+      1,
+    );
   }
 
   pub async fn handle_embed_finish(&self, payload: QueueItemFinishEmbeddingPayload) {
@@ -500,6 +689,50 @@ impl SemanticService {
     .await;
   }
 
+  /// Queues the files of a local scan in batches, then marks the connection
+  /// as synced once every batch and the upserts it produces are done. The
+  /// batches show up in `EmbeddingJob::progress` like any other job.
+  pub async fn add_local_files_to_queue(&self, paths: Vec<String>) {
+    for batch in local_file_batches(paths) {
+      self.enqueue(QueueAction::IndexLocalFiles, Some(batch), 1, 3);
+    }
+    self
+      .add_handle_embed_finish_to_queue(ConnectionsEnum::LocalFiles, 0)
+      .await;
+  }
+
+  async fn index_local_files(
+    &self,
+    payload: QueueItemIndexLocalFilesPayload,
+  ) -> Result<(), String> {
+    let mut documents = Vec::new();
+    for path in payload.paths {
+      let mut local_file = match local_file_from_path(&PathBuf::from(&path)) {
+        Ok(local_file) => local_file,
+        // Moved or deleted since the scan.
+        Err(error) => {
+          log::debug!("Skipping local file {} - {:?}", path, error);
+          continue;
+        }
+      };
+      let local_file = local_file
+        .upsert()
+        .map_err(|error| format!("Failed to store local file {} - {:?}", path, error))?;
+      let file_documents = local_file.get_documents();
+      let text = file_documents
+        .iter()
+        .filter_map(|document| document.get("content").and_then(|content| content.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n");
+      if let Err(error) = index_local_file(&local_file, &text) {
+        log::error!("Failed to index contacts for local file {} - {:?}", path, error);
+      }
+      documents.extend(file_documents);
+    }
+    self.learn(documents, LocalFile::get_attrs(), 1).await;
+    Ok(())
+  }
+
   async fn add_point_upsert_to_queue(
     &self,
     ids: Vec<String>,
@@ -508,28 +741,40 @@ impl SemanticService {
     hashes: Vec<(String, String)>,
     priority: u16,
   ) {
-    let queue_item = QueueItem {
-      action: QueueAction::UpsertPoints,
-      payload: Some(QueueItemPayload::Embedding(QueueItemEmbeddingPayload {
+    self.enqueue(
+      QueueAction::UpsertPoints,
+      Some(QueueItemPayload::Embedding(QueueItemEmbeddingPayload {
         ids,
         payloads,
         hashes,
         embed_fields,
       })),
-      retries: 3,
-    };
-    let mut queue = self.queue.lock().await;
-    queue.push(queue_item, priority);
+      priority,
+      3,
+    );
   }
 
   async fn add_create_collection_to_queue(&self, priority: u16) {
-    let queue_item = QueueItem {
-      action: QueueAction::CreateCollection,
-      payload: None,
-      retries: 3,
+    self.enqueue(QueueAction::CreateCollection, None, priority, 3);
+  }
+
+  fn enqueue(
+    &self,
+    action: QueueAction,
+    payload: Option<QueueItemPayload>,
+    priority: u16,
+    max_attempts: u32,
+  ) {
+    let payload = match payload.map(serde_json::to_value).transpose() {
+      Ok(payload) => payload,
+      Err(error) => {
+        log::error!("Failed to encode embedding job {:?} - {:?}", action, error);
+        return;
+      }
     };
-    let mut queue = self.queue.lock().await;
-    queue.push(queue_item, priority);
+    if let Err(error) = EmbeddingJob::enqueue(action.as_str(), payload, priority, max_attempts) {
+      log::error!("Failed to enqueue embedding job {:?} - {:?}", action, error);
+    }
   }
 
   fn hash(fields: Vec<String>) -> String {
@@ -546,7 +791,7 @@ impl SemanticService {
       };
     }
     if queue_item.action == QueueAction::UpsertPoints {
-      return match queue_item.payload.unwrap() {
+      return match queue_item.payload.ok_or_else(|| String::from("Missing payload"))? {
         QueueItemPayload::Embedding(payload) => {
           self
             .upsert_points(
@@ -555,14 +800,13 @@ impl SemanticService {
               payload.embed_fields,
               payload.hashes,
             )
-            .await;
-          Ok(())
+            .await
         }
         _ => Err(String::from("Invalid payload")),
       };
     }
     if queue_item.action == QueueAction::HandleFinishEmbedding {
-      return match queue_item.payload.unwrap() {
+      return match queue_item.payload.ok_or_else(|| String::from("Missing payload"))? {
         QueueItemPayload::HandleFinishEmbedding(payload) => {
           self.handle_embed_finish(payload).await;
           Ok(())
@@ -570,16 +814,64 @@ impl SemanticService {
         _ => Err(String::from("Invalid payload")),
       };
     }
+    if queue_item.action == QueueAction::DeletePoints {
+      return match queue_item.payload.ok_or_else(|| String::from("Missing payload"))? {
+        QueueItemPayload::DeletePoints(payload) => {
+          match delete_points_by_filter(document_ids_filter(&payload.document_ids)).await {
+            Ok(_) => Ok(()),
+            Err(error) => Err(error.to_string()),
+          }
+        }
+        _ => Err(String::from("Invalid payload")),
+      };
+    }
+    if queue_item.action == QueueAction::IndexLocalFiles {
+      return match queue_item.payload.ok_or_else(|| String::from("Missing payload"))? {
+        QueueItemPayload::IndexLocalFiles(payload) => self.index_local_files(payload).await,
+        _ => Err(String::from("Invalid payload")),
+      };
+    }
     Err(String::from("Not implemented"))
   }
 
   pub async fn clear_queue(&self) {
-    let mut queue = self.queue.lock().await;
-    queue.clear();
+    if let Err(error) = EmbeddingJob::delete_unfinished() {
+      log::error!("Failed to clear embedding jobs {:?}", error);
+    }
+  }
+
+  async fn handle_job(&self, mut job: EmbeddingJob) {
+    let result = match QueueItem::from_job(&job) {
+      Ok(queue_item) => self.handle_queue_item(queue_item).await,
+      Err(error) => Err(error),
+    };
+    let update = match result {
+      Ok(_) => job.mark_done(),
+      Err(error) => {
+        log::error!(
+          "Error processing embedding job {:?} ({}) attempt {}/{} - {:?}",
+          job.id,
+          job.action,
+          job.attempts,
+          job.max_attempts,
+          error
+        );
+        job.mark_failed(&error)
+      }
+    };
+    if let Err(error) = update {
+      log::error!("Failed to update embedding job {:?} - {:?}", job.id, error);
+    }
   }
 
   async fn start_embed_worker(&self) {
-    let queue = self.queue.clone();
+    match EmbeddingJob::recover_interrupted() {
+      Ok(recovered) if recovered > 0 => {
+        log::info!("Resuming {} interrupted embedding jobs", recovered)
+      }
+      Ok(_) => {}
+      Err(error) => log::error!("Failed to recover embedding jobs {:?}", error),
+    }
     let self_clone = self.clone();
 
     tauri::async_runtime::spawn(async move {
@@ -596,33 +888,24 @@ impl SemanticService {
           continue;
         }
 
-        let item = {
-          let mut queue = queue.lock().await;
-          queue.pop()
-        };
-
-        match item {
-          Some((queue_item, priority)) => {
-            log::debug!("Embedding queue size: {:?}", queue.lock().await.len());
-            if let Err(e) = self_clone.handle_queue_item(queue_item.clone()).await {
-              log::error!("Error processing queue_item {:?} - {:?} ", queue_item, e);
-              if queue_item.retries <= 1 {
-                continue;
-              }
-              tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-              let mut queue = queue.lock().await;
-              queue.push(
-                QueueItem {
-                  action: queue_item.action.clone(),
-                  payload: queue_item.payload.clone(),
-                  retries: queue_item.retries - 1,
-                },
-                priority,
-              );
-            }
-          },
+        let work = match BackgroundWork::begin() {
+          Some(work) => work,
           None => {
-            // No items in queue, sleep for a while
+            // A backup is being restored
+            tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+            continue;
+          }
+        };
+        match EmbeddingJob::claim_next() {
+          Ok(Some(job)) => self_clone.handle_job(job).await,
+          Ok(None) => {
+            drop(work);
+            // No runnable jobs, sleep for a while
+            tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
+          }
+          Err(error) => {
+            drop(work);
+            log::error!("Failed to claim embedding job {:?}", error);
             tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
           }
         }
@@ -643,6 +926,17 @@ impl SemanticService {
   }
 }
 
+fn local_file_batches(paths: Vec<String>) -> Vec<QueueItemPayload> {
+  paths
+    .chunks(EMBEDDING_BATCH_SIZE)
+    .map(|batch| {
+      QueueItemPayload::IndexLocalFiles(QueueItemIndexLocalFilesPayload {
+        paths: batch.to_vec(),
+      })
+    })
+    .collect()
+}
+
 pub fn start_embed_service(
   embedder_path: PathBuf,
   is_chatting: Arc<Mutex<AtomicBool>>,
@@ -658,18 +952,31 @@ pub fn start_embed_service(
 #[derive(Deserialize, Debug)]
 struct SemanticSearchRequest {
   query: String,
+  #[serde(default)]
   documents: Vec<DisplayDocument>,
+  #[serde(default)]
   data_sources: Vec<String>,
+  #[serde(default)]
+  filter: SearchFilter,
   top: usize,
 }
 
-#[derive(Serialize)]
-struct SemanticSearchResponseItem {
-  id: String,
-  score: f32,
-  payload: Value,
-  chunk_id: u64,
-  document_id: u64,
+impl SemanticSearchRequest {
+  /// Folds the older `documents`/`data_sources` fields into the typed filter.
+  fn search_filter(&self) -> SearchFilter {
+    let mut filter = self.filter.clone();
+    filter
+      .document_ids
+      .extend(self.documents.iter().map(|doc| doc.document_id));
+    for data_source in &self.data_sources {
+      match SourceType::parse(data_source) {
+        Some(source) if !filter.sources.contains(&source) => filter.sources.push(source),
+        Some(_) => {}
+        None => log::warn!("Ignoring unknown data source {}", data_source),
+      }
+    }
+    filter
+  }
 }
 
 #[derive(Serialize)]
@@ -683,36 +990,92 @@ struct SemanticClearQueueResponse {
   success: bool,
 }
 
-fn construct_documents_filter(documents: Vec<DisplayDocument>) -> Option<Value> {
-  if documents.is_empty() {
-    return None;
-  }
+fn document_ids_filter(document_ids: &Vec<u64>) -> Value {
+  json!({ "must": [{ "key": "document_id", "match": { "any": document_ids } }] })
+}
 
-  let doc_ids: Vec<u64> = documents.iter().map(|doc| doc.document_id).collect();
+/// Removes the vectors and embedding manifest entries of purged documents.
+/// If Qdrant is unreachable the vector deletion is queued as an embedding
+/// job, so it is retried once the embedder runs again instead of leaving
+/// searchable copies behind.
+pub async fn purge_document_vectors(document_ids: Vec<u64>) {
+  if document_ids.is_empty() {
+    return;
+  }
+  if let Err(error) = EmbeddingManifestEntry::delete_by_document_ids(&document_ids) {
+    log::error!(
+      "Failed to delete embedding manifest of {} documents - {:?}",
+      document_ids.len(),
+      error
+    );
+  }
+  if let Err(error) = delete_points_by_filter(document_ids_filter(&document_ids)).await {
+    log::warn!(
+      "Could not delete vectors of {} documents now, queueing - {:?}",
+      document_ids.len(),
+      error
+    );
+    let payload = serde_json::to_value(QueueItemPayload::DeletePoints(
+      QueueItemDeletePointsPayload { document_ids },
+    ))
+    .ok();
+    if let Err(error) =
+      EmbeddingJob::enqueue(QueueAction::DeletePoints.as_str(), payload, 0, 10)
+    {
+      log::error!("Failed to enqueue vector deletion - {:?}", error);
+    }
+  }
+}
 
-  Some(json!({
-      "must": [{
-          "key": "document_id",
-          "match": {
-              "any": doc_ids
-          }
-      }]
-  }))
+pub fn documents_to_display(documents: Vec<Document>) -> Vec<DisplayDocument> {
+  let mut display_documents = Vec::new();
+  for document in documents {
+    let knowledge = match document.as_knowledge_snippet() {
+      Ok(k) => k,
+      _ => continue,
+    };
+    display_documents.push(DisplayDocument {
+      document_id: document.id.expect("Document id not found"),
+      title: knowledge.get_title(),
+      summary: Some(knowledge.get_summary()),
+      document_type: knowledge.get_document_type(),
+      uri: knowledge.get_hyperlink(),
+    })
+  }
+  display_documents
 }
 
-fn construct_data_sources_filter(data_sources: Vec<String>) -> Option<Value> {
-  if data_sources.is_empty() {
-    return None;
+/// Runs the typed filter against Qdrant when the embedding service is up,
+/// and against SQLite (keyword match on `query`) otherwise. Without an
+/// explicit `account_id` the active account's documents are searched.
+pub async fn search_documents(
+  semantic_service: Option<SemanticService>,
+  query: String,
+  top: usize,
+  filter: &SearchFilter,
+) -> Result<Vec<Document>, Error> {
+  let mut filter = filter.clone();
+  if filter.account_id.is_none() {
+    filter.account_id = User::active_account_id();
   }
+  let filter = &filter;
+  let service = match semantic_service {
+    Some(service) => service,
+    None => return Document::find_by_filter(filter, Some(&query), top),
+  };
 
-  Some(json!({
-      "must": [{
-          "key": "type",
-          "match": {
-              "any": data_sources
-          }
-      }]
-  }))
+  let results = service.filtered_search(query, top, filter).await?;
+  let document_ids: Vec<u64> = results.iter().map(|result| result.document_id).collect();
+  let mut documents_by_id: HashMap<u64, Document> = Document::find_by_ids(&document_ids)?
+    .into_iter()
+    .filter_map(|document| document.id.map(|id| (id, document)))
+    .collect();
+  Ok(
+    document_ids
+      .iter()
+      .filter_map(|document_id| documents_by_id.remove(document_id))
+      .collect(),
+  )
 }
 
 #[post("/api/knapsack/semantic_search")]
@@ -720,60 +1083,117 @@ pub async fn semantic_search(
   payload: Json<SemanticSearchRequest>,
   semantic_service: Data<Arc<Mutex<Option<SemanticService>>>>,
 ) -> impl Responder {
-  // let maybe_locked_semantic_service = semantic_service.lock().await;
-  // let locked_semantic_service = maybe_locked_semantic_service.as_ref().unwrap();
-  // let query = payload.query.clone();
-  // let top = payload.top.clone();
-  // let doc_filter = construct_documents_filter(payload.documents.clone());
-  // let source_filter = construct_data_sources_filter(payload.data_sources.clone());
-
-  // let filter = match (doc_filter, source_filter) {
-  //   (Some(f1), Some(f2)) => Some(json!({
-  //     "must": [
-  //       f1["must"][0],
-  //       f2["must"][0]
-  //     ]
-  //   })),
-  //   (Some(f1), None) => Some(f1),
-  //   (None, Some(f2)) => Some(f2),
-  //   (None, None) => None,
-  // };
-
-  // let semantic_search_results: Vec<SemanticSearchResult> = match locked_semantic_service
-  //   .semantic_search(query, top, filter, None, None, Some(true), None)
-  //   .await
-  // {
-  //   Ok(response) => response,
-  //   Err(error) => {
-  //     log::error!("failed to do semantic search{:?}", error);
-  //     return actix_web::HttpResponse::BadRequest().json(SemanticSearchResponse {
-  //       success: false,
-  //       display_documents: vec![],
-  //     });
-  //   }
-  // };
+  let filter = payload.search_filter();
+  let maybe_semantic_service = semantic_service.lock().await.clone();
+
+  let documents = match search_documents(
+    maybe_semantic_service,
+    payload.query.clone(),
+    payload.top,
+    &filter,
+  )
+  .await
+  {
+    Ok(documents) => documents,
+    Err(error) => {
+      log::error!("failed to do semantic search{:?}", error);
+      return actix_web::HttpResponse::BadRequest().json(SemanticSearchResponse {
+        success: false,
+        display_documents: vec![],
+      });
+    }
+  };
 
-  let mut display_documents = Vec::new();
-  // for ss_result in semantic_search_results {
-  //   let document = match Document::find_by_id(ss_result.document_id) {
-  //     Ok(Some(d)) => d,
-  //     _ => continue,
-  //   };
-  //   let knowledge = match document.as_knowledge_snippet() {
-  //     Ok(k) => k,
-  //     _ => continue,
-  //   };
-  //   display_documents.push(DisplayDocument {
-  //     document_id: document.id.expect("Document id not found"),
-  //     title: knowledge.get_title(),
-  //     summary: Some(knowledge.get_summary()),
-  //     document_type: knowledge.get_document_type(),
-  //     uri: knowledge.get_hyperlink(),
-  //   })
-  // }
   let response = SemanticSearchResponse {
     success: true,
-    display_documents,
+    display_documents: documents_to_display(documents),
   };
   return actix_web::HttpResponse::Ok().json(response);
 }
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingJobsQuery {
+  status: Option<String>,
+  limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct EmbeddingJobsResponse {
+  success: bool,
+  progress: EmbeddingJobProgress,
+  jobs: Vec<EmbeddingJob>,
+}
+
+#[get("/api/knapsack/embedding/jobs")]
+pub async fn get_embedding_jobs(query: Query<EmbeddingJobsQuery>) -> impl Responder {
+  let status = match &query.status {
+    Some(status) => match EmbeddingJobStatus::from_str(status) {
+      Some(status) => Some(status),
+      None => {
+        return actix_web::HttpResponse::BadRequest()
+          .json(json!({ "success": false, "error": format!("Unknown status {}", status) }))
+      }
+    },
+    None => None,
+  };
+  let limit = query.limit.unwrap_or(50).min(500);
+
+  let result = run_blocking(move || {
+    Ok((EmbeddingJob::progress()?, EmbeddingJob::find_recent(status, limit)?))
+  })
+  .await;
+  match result {
+    Ok((progress, jobs)) => actix_web::HttpResponse::Ok().json(EmbeddingJobsResponse {
+      success: true,
+      progress,
+      jobs,
+    }),
+    Err(error) => {
+      log::error!("Failed to read embedding jobs {:?}", error);
+      actix_web::HttpResponse::InternalServerError()
+        .json(json!({ "success": false, "error": format!("{:?}", error) }))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn local_files_are_queued_in_batches_that_survive_a_restart() {
+    let paths: Vec<String> = (0..EMBEDDING_BATCH_SIZE * 2 + 1)
+      .map(|index| format!("/Users/me/Documents/{}.pdf", index))
+      .collect();
+    let batches = local_file_batches(paths.clone());
+    assert_eq!(batches.len(), 3);
+
+    let mut queued_paths = Vec::new();
+    for batch in batches {
+      // Jobs are stored as JSON and decoded again by the worker.
+      let job = EmbeddingJob {
+        id: Some(1),
+        action: QueueAction::IndexLocalFiles.as_str().to_string(),
+        payload: Some(serde_json::to_value(&batch).unwrap()),
+        priority: 1,
+        status: EmbeddingJobStatus::Pending,
+        attempts: 0,
+        max_attempts: 3,
+        next_attempt_at: 0,
+        last_error: None,
+        created_timestamp: None,
+        updated_timestamp: None,
+      };
+      let queue_item = QueueItem::from_job(&job).unwrap();
+      assert_eq!(queue_item.action, QueueAction::IndexLocalFiles);
+      match queue_item.payload {
+        Some(QueueItemPayload::IndexLocalFiles(payload)) => {
+          assert!(payload.paths.len() <= EMBEDDING_BATCH_SIZE);
+          queued_paths.extend(payload.paths);
+        }
+        other => panic!("unexpected payload {:?}", other),
+      }
+    }
+    assert_eq!(queued_paths, paths);
+  }
+}
@@ -1,12 +1,74 @@
-use text_splitter::{ChunkConfig, TextSplitter as TextSplitterPackage};
+use std::sync::Arc;
 
 const DEFAULT_CHUNK_SIZE: usize = 600;
 const DEFAULT_CHUNK_OVERLAP: usize = 50;
+const PAGE_SEPARATOR: char = '\x0c';
+const MAX_SPEAKER_LABEL_LEN: usize = 40;
 
+/// Picks the section boundaries the splitter respects before it falls back
+/// to separator-based splitting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+  Plain,
+  Markdown,
+  Transcript,
+  Email,
+  Pdf,
+}
+
+impl ContentType {
+  pub fn from_extension(extension: &str) -> Self {
+    match extension.to_lowercase().as_str() {
+      "md" | "markdown" => ContentType::Markdown,
+      "pdf" => ContentType::Pdf,
+      _ => ContentType::Plain,
+    }
+  }
+}
+
+/// Counts tokens the way the active embedder would.
+pub type TokenCounter = Arc<dyn Fn(&str) -> usize + Send + Sync>;
+
+/// A chunk of the source text. `start` and `end` are byte offsets into the
+/// string handed to the splitter, so a hit can be cited back to its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+  pub text: String,
+  pub start: usize,
+  pub end: usize,
+  pub page: Option<usize>,
+  pub heading: Option<String>,
+  pub speaker: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Section {
+  start: usize,
+  end: usize,
+  page: Option<usize>,
+  heading: Option<String>,
+  speaker: Option<String>,
+}
+
+impl Section {
+  fn new(start: usize, end: usize) -> Self {
+    Section {
+      start,
+      end,
+      page: None,
+      heading: None,
+      speaker: None,
+    }
+  }
+}
+
+#[derive(Clone)]
 pub struct TextSplitter {
   chunk_size: usize,
   chunk_overlap: usize,
   separators: Vec<&'static str>,
+  content_type: ContentType,
+  token_counter: Option<TokenCounter>,
 }
 
 impl Default for TextSplitter {
@@ -15,81 +77,449 @@ impl Default for TextSplitter {
       chunk_size: DEFAULT_CHUNK_SIZE,
       chunk_overlap: DEFAULT_CHUNK_OVERLAP,
       separators: vec!["\n\n", "\n", ". ", "! ", "? ", ";", ":", ", ", " ", ""],
+      content_type: ContentType::Plain,
+      token_counter: None,
     }
   }
 }
 
 impl TextSplitter {
+  pub fn for_content_type(content_type: ContentType) -> Self {
+    TextSplitter {
+      content_type,
+      ..Default::default()
+    }
+  }
+
+  /// Sizes chunks in embedder tokens instead of characters. The overlap keeps
+  /// the same proportion as the character defaults.
+  pub fn with_token_counter(mut self, token_counter: TokenCounter, max_tokens: usize) -> Self {
+    self.chunk_size = max_tokens.max(1);
+    self.chunk_overlap = max_tokens * DEFAULT_CHUNK_OVERLAP / DEFAULT_CHUNK_SIZE;
+    self.token_counter = Some(token_counter);
+    self
+  }
+
   pub fn split_text(&self, text: &str) -> Vec<String> {
-    let config = ChunkConfig::new(self.chunk_size)
-      .with_overlap(self.chunk_overlap)
-      .unwrap()
-      .with_trim(true);
-    let splitter = TextSplitterPackage::new(config);
-    splitter
-      .chunks(&text)
+    self
+      .split_chunks(text)
       .into_iter()
-      .map(|item| String::from(item))
-      .collect::<Vec<_>>()
-  }
-}
-//     let mut chunks = Vec::new();
-//     let mut text_to_split = text;
-//     while !text_to_split.is_empty() {
-//       let (chunk, remainder) = self.split_chunk(&text_to_split);
-//       chunks.push(chunk);
-//       text_to_split = remainder;
-//     }
-
-//     chunks
-//   }
-
-//   fn split_chunk<'a>(&self, text: &'a str) -> (String, String) {
-//     if text.len() <= self.chunk_size {
-//       return (text.to_string(), "".to_string());
-//     }
-//     let mut best_end = self.chunk_size;
-//     let mut found_separator = false;
-
-//     for separator in &self.separators {
-//       let mut window_end = std::cmp::min(text.len() - 1, self.chunk_size + separator.len());
-//       window_end = text.char_indices()
-//         .nth(window_end)
-//         .map(|(idx, _)| idx)
-//         .unwrap_or(text.len());
-
-//       let window = &text.chars().take(window_end).collect::<String>();
-
-//       if let Some(last_separator) = window.rfind(separator) {
-//         if last_separator > 0 && last_separator > self.chunk_overlap {
-//           best_end = last_separator + separator.len();
-//           found_separator = true;
-//           break;
-//         }
-//       }
-//     }
-
-//     if !found_separator {
-//       best_end = text.char_indices()
-//         .nth(self.chunk_size)
-//         .map(|(idx, _)| idx)
-//         .unwrap_or(text.len());
-//     } else {
-//       best_end = text.char_indices()
-//         .nth(best_end)
-//         .map(|(idx, _)| idx)
-//         .unwrap_or(text.len());
-//       if best_end > self.chunk_size {
-//         best_end = self.chunk_size;
-//       }
-//     }
-
-//     let chunk = text.chars().take(best_end).collect::<String>();
-
-//     let start_index = best_end.saturating_sub(self.chunk_overlap);
-//     let start_index = text.char_indices().nth(start_index).map(|(idx, _)| idx).unwrap_or(0);
-//     let remainder = text.chars().skip(start_index).collect::<String>();
-
-//     (chunk, remainder)
-//   }
-// }
+      .map(|chunk| chunk.text)
+      .collect()
+  }
+
+  pub fn split_chunks(&self, text: &str) -> Vec<TextChunk> {
+    let sections = match self.content_type {
+      ContentType::Plain => vec![Section::new(0, text.len())],
+      ContentType::Markdown => markdown_sections(text),
+      ContentType::Transcript => transcript_sections(text),
+      ContentType::Email => email_sections(text),
+      ContentType::Pdf => page_sections(text),
+    };
+
+    let mut chunks = Vec::new();
+    for (section, section_size) in self.merge_small_sections(text, sections) {
+      let mut ranges = Vec::new();
+      self.split_range(
+        text,
+        section.start,
+        section.end,
+        section_size,
+        &self.separators,
+        &mut ranges,
+      );
+      for (start, end) in ranges {
+        if let Some(chunk) = make_chunk(text, start, end, &section) {
+          chunks.push(chunk);
+        }
+      }
+    }
+    chunks
+  }
+
+  fn size(&self, text: &str) -> usize {
+    match &self.token_counter {
+      Some(token_counter) => token_counter(text),
+      None => text.chars().count(),
+    }
+  }
+
+  /// Packs neighbouring sections together while they fit, without crossing a
+  /// page or a heading. Returns each section with its size. Sizes are summed
+  /// as sections grow, so every byte is counted at most twice instead of once
+  /// per merge.
+  fn merge_small_sections(&self, text: &str, sections: Vec<Section>) -> Vec<(Section, usize)> {
+    let mut merged: Vec<(Section, usize)> = Vec::new();
+    for section in sections {
+      if let Some((current, current_size)) = merged.last_mut() {
+        if current.page == section.page && current.heading == section.heading {
+          let extension_size = self.size(&text[current.end..section.end]);
+          if *current_size + extension_size <= self.chunk_size {
+            current.end = section.end;
+            *current_size += extension_size;
+            if current.speaker != section.speaker {
+              current.speaker = None;
+            }
+            continue;
+          }
+        }
+      }
+      let section_size = self.size(&text[section.start..section.end]);
+      merged.push((section, section_size));
+    }
+    merged
+  }
+
+  /// `range_size` is the already counted size of `text[start..end]`.
+  fn split_range(
+    &self,
+    text: &str,
+    start: usize,
+    end: usize,
+    range_size: usize,
+    separators: &[&'static str],
+    ranges: &mut Vec<(usize, usize)>,
+  ) {
+    if range_size <= self.chunk_size {
+      ranges.push((start, end));
+      return;
+    }
+
+    let slice = &text[start..end];
+    let separator_index = separators
+      .iter()
+      .position(|separator| separator.is_empty() || slice.contains(separator));
+    let (separator, remaining_separators) = match separator_index {
+      Some(index) => (separators[index], &separators[index + 1..]),
+      None => ("", &separators[separators.len()..]),
+    };
+
+    let mut window: Vec<(usize, usize, usize)> = Vec::new();
+    let mut window_size = 0;
+    for (piece_start, piece_end) in split_keeping_separator(slice, separator) {
+      let piece_start = start + piece_start;
+      let piece_end = start + piece_end;
+      let piece_size = self.size(&text[piece_start..piece_end]);
+
+      if piece_size > self.chunk_size {
+        if let (Some(first), Some(last)) = (window.first(), window.last()) {
+          ranges.push((first.0, last.1));
+        }
+        window.clear();
+        window_size = 0;
+        if separator.is_empty() {
+          ranges.push((piece_start, piece_end));
+        } else {
+          self.split_range(
+            text,
+            piece_start,
+            piece_end,
+            piece_size,
+            remaining_separators,
+            ranges,
+          );
+        }
+        continue;
+      }
+
+      if window_size + piece_size > self.chunk_size && !window.is_empty() {
+        ranges.push((window[0].0, window[window.len() - 1].1));
+        while !window.is_empty()
+          && (window_size > self.chunk_overlap || window_size + piece_size > self.chunk_size)
+        {
+          window_size -= window[0].2;
+          window.remove(0);
+        }
+      }
+      window.push((piece_start, piece_end, piece_size));
+      window_size += piece_size;
+    }
+    if let (Some(first), Some(last)) = (window.first(), window.last()) {
+      ranges.push((first.0, last.1));
+    }
+  }
+}
+
+fn make_chunk(text: &str, start: usize, end: usize, section: &Section) -> Option<TextChunk> {
+  let slice = &text[start..end];
+  let trimmed_start = start + (slice.len() - slice.trim_start().len());
+  let trimmed_end = start + slice.trim_end().len();
+  if trimmed_start >= trimmed_end {
+    return None;
+  }
+  Some(TextChunk {
+    text: text[trimmed_start..trimmed_end].to_string(),
+    start: trimmed_start,
+    end: trimmed_end,
+    page: section.page,
+    heading: section.heading.clone(),
+    speaker: section.speaker.clone(),
+  })
+}
+
+/// Splits `text` after every occurrence of `separator`, so the pieces cover
+/// the whole input. An empty separator splits into characters.
+fn split_keeping_separator(text: &str, separator: &str) -> Vec<(usize, usize)> {
+  if separator.is_empty() {
+    return text
+      .char_indices()
+      .map(|(index, c)| (index, index + c.len_utf8()))
+      .collect();
+  }
+  let mut pieces = Vec::new();
+  let mut last = 0;
+  for (index, _) in text.match_indices(separator) {
+    let piece_end = index + separator.len();
+    if piece_end > last {
+      pieces.push((last, piece_end));
+      last = piece_end;
+    }
+  }
+  if last < text.len() {
+    pieces.push((last, text.len()));
+  }
+  pieces
+}
+
+fn lines_with_offsets(text: &str) -> impl Iterator<Item = (usize, &str)> {
+  text.split_inclusive('\n').scan(0, |offset, line| {
+    let start = *offset;
+    *offset += line.len();
+    Some((start, line))
+  })
+}
+
+fn markdown_heading(line: &str) -> Option<String> {
+  let trimmed = line.trim();
+  let level = trimmed.chars().take_while(|c| *c == '#').count();
+  if level == 0 || level > 6 || !trimmed[level..].starts_with(' ') {
+    return None;
+  }
+  Some(trimmed[level..].trim().to_string())
+}
+
+fn markdown_sections(text: &str) -> Vec<Section> {
+  let mut sections = Vec::new();
+  let mut section_start = 0;
+  let mut heading: Option<String> = None;
+  let mut in_code_block = false;
+  for (offset, line) in lines_with_offsets(text) {
+    if line.trim_start().starts_with("```") {
+      in_code_block = !in_code_block;
+      continue;
+    }
+    if in_code_block {
+      continue;
+    }
+    if let Some(next_heading) = markdown_heading(line) {
+      if offset > section_start {
+        let mut section = Section::new(section_start, offset);
+        section.heading = heading.clone();
+        sections.push(section);
+      }
+      section_start = offset;
+      heading = Some(next_heading);
+    }
+  }
+  if section_start < text.len() {
+    let mut section = Section::new(section_start, text.len());
+    section.heading = heading;
+    sections.push(section);
+  }
+  sections
+}
+
+/// Matches the "Me: " / "Them: " prefixes written by `audio::transcribe`, as
+/// well as named speakers such as "Alice Smith: ".
+fn speaker_label(line: &str) -> Option<String> {
+  let colon = line.find(": ")?;
+  let label = &line[..colon];
+  if label.is_empty() || label.len() > MAX_SPEAKER_LABEL_LEN {
+    return None;
+  }
+  let starts_uppercase = label.chars().next().is_some_and(|c| c.is_uppercase());
+  let is_name = label
+    .chars()
+    .all(|c| c.is_alphanumeric() || c == ' ' || c == '.' || c == '-' || c == '\'');
+  if starts_uppercase && is_name {
+    Some(label.to_string())
+  } else {
+    None
+  }
+}
+
+fn transcript_sections(text: &str) -> Vec<Section> {
+  let mut sections: Vec<Section> = Vec::new();
+  for (offset, line) in lines_with_offsets(text) {
+    match speaker_label(line) {
+      Some(speaker) => {
+        if let Some(previous) = sections.last_mut() {
+          previous.end = offset;
+        }
+        let mut section = Section::new(offset, text.len());
+        section.speaker = Some(speaker);
+        sections.push(section);
+      }
+      None if sections.is_empty() => sections.push(Section::new(offset, text.len())),
+      None => {}
+    }
+  }
+  sections
+}
+
+fn is_reply_separator(line: &str) -> bool {
+  line.starts_with("-----Original Message-----")
+    || line.starts_with("________________________________")
+    || (line.starts_with("On ") && line.ends_with("wrote:"))
+}
+
+/// Keeps the new part of an email and drops quoted replies: `>` lines and
+/// everything after an "On ... wrote:" or "Original Message" separator.
+fn email_sections(text: &str) -> Vec<Section> {
+  let mut sections = Vec::new();
+  let mut run_start: Option<usize> = None;
+  let mut previous_line: Option<(usize, &str)> = None;
+  let mut body_end = text.len();
+  for (offset, line) in lines_with_offsets(text) {
+    let trimmed = line.trim();
+    if is_reply_separator(trimmed) {
+      body_end = offset;
+      break;
+    }
+    // Gmail wraps long "On <date> <sender>\nwrote:" headers over two lines.
+    if trimmed == "wrote:" {
+      body_end = match previous_line {
+        Some((previous_offset, previous)) if previous.trim().starts_with("On ") => previous_offset,
+        _ => offset,
+      };
+      break;
+    }
+    if trimmed.starts_with('>') {
+      if let Some(start) = run_start.take() {
+        sections.push(Section::new(start, offset));
+      }
+    } else if run_start.is_none() {
+      run_start = Some(offset);
+    }
+    previous_line = Some((offset, line));
+  }
+  if let Some(start) = run_start {
+    if start < body_end {
+      sections.push(Section::new(start, body_end));
+    }
+  }
+  sections.retain(|section| section.start < body_end);
+  for section in sections.iter_mut() {
+    section.end = section.end.min(body_end);
+  }
+  if sections.is_empty() {
+    sections.push(Section::new(0, text.len()));
+  }
+  sections
+}
+
+/// Splits on the form feeds `pdftotext` emits between pages. Pages are
+/// numbered from 1.
+fn page_sections(text: &str) -> Vec<Section> {
+  let mut sections = Vec::new();
+  let mut page_start = 0;
+  for (page_index, page) in text.split(PAGE_SEPARATOR).enumerate() {
+    let mut section = Section::new(page_start, page_start + page.len());
+    section.page = Some(page_index + 1);
+    sections.push(section);
+    page_start += page.len() + PAGE_SEPARATOR.len_utf8();
+  }
+  sections
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn offsets_point_back_into_the_source() {
+    let text = "First sentence here. ".repeat(80);
+    let splitter = TextSplitter::default();
+    let chunks = splitter.split_chunks(&text);
+    assert!(chunks.len() > 1);
+    for chunk in chunks {
+      assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+      assert!(chunk.text.chars().count() <= DEFAULT_CHUNK_SIZE);
+    }
+  }
+
+  #[test]
+  fn markdown_chunks_keep_their_heading() {
+    let text = "# Intro\nHello there.\n\n## Pricing\nWe discussed pricing.\n";
+    let splitter = TextSplitter::for_content_type(ContentType::Markdown);
+    let chunks = splitter.split_chunks(text);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].heading.as_deref(), Some("Intro"));
+    assert_eq!(chunks[1].heading.as_deref(), Some("Pricing"));
+    assert!(chunks[1].text.starts_with("## Pricing"));
+  }
+
+  #[test]
+  fn transcript_turns_are_split_by_speaker() {
+    let turn = "word ".repeat(100);
+    let text = format!("Me: {}\nThem: {}\n", turn, turn);
+    let splitter = TextSplitter::for_content_type(ContentType::Transcript);
+    let chunks = splitter.split_chunks(&text);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].speaker.as_deref(), Some("Me"));
+    assert_eq!(chunks[1].speaker.as_deref(), Some("Them"));
+  }
+
+  #[test]
+  fn email_quotes_are_stripped() {
+    let text = "Sounds good, see you then.\n\nOn Mon, Jan 6, 2025 at 9:00 AM Alice <alice@acme.com>\nwrote:\n> Can we meet Tuesday?\n";
+    let splitter = TextSplitter::for_content_type(ContentType::Email);
+    let chunks = splitter.split_chunks(text);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].text, "Sounds good, see you then.");
+  }
+
+  #[test]
+  fn pdf_chunks_carry_page_numbers() {
+    let text = "Page one text.\x0cPage two text.";
+    let splitter = TextSplitter::for_content_type(ContentType::Pdf);
+    let chunks = splitter.split_chunks(text);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].page, Some(1));
+    assert_eq!(chunks[1].page, Some(2));
+    assert_eq!(&text[chunks[1].start..chunks[1].end], "Page two text.");
+  }
+
+  #[test]
+  fn token_counter_controls_chunk_size() {
+    let text = "one two three four five six seven eight nine ten";
+    let token_counter: TokenCounter = Arc::new(|text: &str| text.split_whitespace().count());
+    let splitter = TextSplitter::default().with_token_counter(token_counter, 4);
+    let chunks = splitter.split_chunks(text);
+    assert!(chunks.len() >= 3);
+    for chunk in chunks {
+      assert!(chunk.text.split_whitespace().count() <= 4);
+    }
+  }
+
+  #[test]
+  fn token_counter_sees_each_byte_a_bounded_number_of_times() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let turns: String = (0..400).map(|index| format!("Me: turn {}\n", index)).collect();
+    let counted_bytes = Arc::new(AtomicUsize::new(0));
+    let counter_bytes = counted_bytes.clone();
+    let token_counter: TokenCounter = Arc::new(move |text: &str| {
+      counter_bytes.fetch_add(text.len(), Ordering::SeqCst);
+      text.split_whitespace().count()
+    });
+    let splitter = TextSplitter::for_content_type(ContentType::Transcript)
+      .with_token_counter(token_counter, 120);
+    let chunks = splitter.split_chunks(&turns);
+    assert!(chunks.len() > 1);
+    for chunk in &chunks {
+      assert!(chunk.text.split_whitespace().count() <= 120);
+    }
+    assert!(counted_bytes.load(Ordering::SeqCst) <= turns.len() * 2);
+  }
+}
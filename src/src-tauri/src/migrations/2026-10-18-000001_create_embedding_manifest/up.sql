@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS embedding_manifest (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  base_id TEXT NOT NULL,
+  chunk_id INTEGER NOT NULL DEFAULT 0,
+  embed_hash TEXT NOT NULL,
+  metadata_hash TEXT NOT NULL,
+  model_id TEXT NOT NULL,
+  point_ids TEXT NOT NULL DEFAULT '[]',
+  timestamp INTEGER DEFAULT (strftime('%s','now')),
+  UNIQUE(base_id, chunk_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_embedding_manifest_model_id ON embedding_manifest(model_id);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS embedding_jobs (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  action TEXT NOT NULL,
+  payload TEXT,
+  priority INTEGER NOT NULL DEFAULT 0,
+  status TEXT NOT NULL DEFAULT 'pending',
+  attempts INTEGER NOT NULL DEFAULT 0,
+  max_attempts INTEGER NOT NULL DEFAULT 3,
+  next_attempt_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+  last_error TEXT,
+  created_timestamp INTEGER DEFAULT (strftime('%s','now')),
+  updated_timestamp INTEGER DEFAULT (strftime('%s','now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_embedding_jobs_claim ON embedding_jobs(status, next_attempt_at, priority);
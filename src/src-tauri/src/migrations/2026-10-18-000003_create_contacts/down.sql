@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS contact_links;
+DROP TABLE IF EXISTS contact_aliases;
+DROP TABLE IF EXISTS contact_emails;
+DROP TABLE IF EXISTS contacts;
@@ -0,0 +1,38 @@
+CREATE TABLE IF NOT EXISTS contacts (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  display_name TEXT,
+  organization TEXT,
+  interaction_count INTEGER NOT NULL DEFAULT 0,
+  last_contacted INTEGER,
+  created_timestamp INTEGER DEFAULT (strftime('%s','now')),
+  updated_timestamp INTEGER DEFAULT (strftime('%s','now'))
+);
+
+CREATE TABLE IF NOT EXISTS contact_emails (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  contact_id INTEGER NOT NULL,
+  email TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS contact_aliases (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  contact_id INTEGER NOT NULL,
+  alias TEXT NOT NULL,
+  UNIQUE(contact_id, alias)
+);
+
+CREATE TABLE IF NOT EXISTS contact_links (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  contact_id INTEGER NOT NULL,
+  foreign_table TEXT NOT NULL,
+  foreign_table_id INTEGER NOT NULL,
+  role TEXT NOT NULL,
+  occurred_at INTEGER,
+  UNIQUE(contact_id, foreign_table, foreign_table_id, role)
+);
+
+CREATE INDEX IF NOT EXISTS idx_contact_emails_contact_id ON contact_emails(contact_id);
+CREATE INDEX IF NOT EXISTS idx_contact_aliases_contact_id ON contact_aliases(contact_id);
+CREATE INDEX IF NOT EXISTS idx_contact_links_contact_id ON contact_links(contact_id);
+CREATE INDEX IF NOT EXISTS idx_contact_links_foreign ON contact_links(foreign_table, foreign_table_id);
+CREATE INDEX IF NOT EXISTS idx_contacts_organization ON contacts(organization);
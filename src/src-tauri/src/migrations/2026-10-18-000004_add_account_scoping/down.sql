@@ -0,0 +1,12 @@
+DROP INDEX IF EXISTS idx_drive_documents_account_id;
+DROP INDEX IF EXISTS idx_calendar_events_account_id;
+DROP INDEX IF EXISTS idx_emails_account_id;
+
+ALTER TABLE user_connections DROP COLUMN sync_error;
+ALTER TABLE user_connections DROP COLUMN sync_status;
+
+ALTER TABLE users DROP COLUMN is_active;
+
+ALTER TABLE drive_documents DROP COLUMN account_id;
+ALTER TABLE calendar_events DROP COLUMN account_id;
+ALTER TABLE emails DROP COLUMN account_id;
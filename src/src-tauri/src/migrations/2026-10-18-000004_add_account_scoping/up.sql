@@ -0,0 +1,20 @@
+ALTER TABLE emails ADD COLUMN account_id INTEGER;
+ALTER TABLE calendar_events ADD COLUMN account_id INTEGER;
+ALTER TABLE drive_documents ADD COLUMN account_id INTEGER;
+
+ALTER TABLE users ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT 0;
+
+ALTER TABLE user_connections ADD COLUMN sync_status TEXT;
+ALTER TABLE user_connections ADD COLUMN sync_error TEXT;
+
+-- With a single signed-in account every synced row belongs to it. With
+-- several, ownership is unknown and rows stay unowned (visible everywhere)
+-- until the next sync claims them.
+UPDATE emails SET account_id = (SELECT id FROM users) WHERE (SELECT COUNT(*) FROM users) = 1;
+UPDATE calendar_events SET account_id = (SELECT id FROM users) WHERE (SELECT COUNT(*) FROM users) = 1;
+UPDATE drive_documents SET account_id = (SELECT id FROM users) WHERE (SELECT COUNT(*) FROM users) = 1;
+UPDATE users SET is_active = 1 WHERE (SELECT COUNT(*) FROM users) = 1;
+
+CREATE INDEX IF NOT EXISTS idx_emails_account_id ON emails(account_id);
+CREATE INDEX IF NOT EXISTS idx_calendar_events_account_id ON calendar_events(account_id);
+CREATE INDEX IF NOT EXISTS idx_drive_documents_account_id ON drive_documents(account_id);
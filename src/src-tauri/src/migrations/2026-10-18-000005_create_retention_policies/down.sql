@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS retention_purges;
+DROP TABLE IF EXISTS retention_policies;
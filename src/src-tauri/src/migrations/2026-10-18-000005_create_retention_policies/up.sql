@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS retention_policies (
+  data_type TEXT PRIMARY KEY,
+  retain_days INTEGER NOT NULL,
+  is_enabled BOOLEAN NOT NULL DEFAULT 1,
+  last_applied INTEGER,
+  updated_timestamp INTEGER DEFAULT (strftime('%s','now'))
+);
+
+CREATE TABLE IF NOT EXISTS retention_purges (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  data_type TEXT NOT NULL,
+  purged_count INTEGER NOT NULL,
+  details TEXT,
+  created_timestamp INTEGER DEFAULT (strftime('%s','now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_retention_purges_created_timestamp ON retention_purges(created_timestamp);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS sync_runs (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_connection_id INTEGER NOT NULL,
+  started_at INTEGER NOT NULL,
+  finished_at INTEGER,
+  status TEXT NOT NULL,
+  items_added INTEGER NOT NULL DEFAULT 0,
+  items_updated INTEGER NOT NULL DEFAULT 0,
+  items_deleted INTEGER NOT NULL DEFAULT 0,
+  error TEXT,
+  cursor TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_runs_user_connection_id ON sync_runs(user_connection_id, started_at);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_automation_runs_schedule_timestamp;
+
+ALTER TABLE automation_runs DROP COLUMN error;
+ALTER TABLE automation_runs DROP COLUMN status;
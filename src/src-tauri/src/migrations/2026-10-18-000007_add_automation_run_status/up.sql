@@ -0,0 +1,7 @@
+ALTER TABLE automation_runs ADD COLUMN status TEXT;
+ALTER TABLE automation_runs ADD COLUMN error TEXT;
+
+-- Runs the frontend already reported back are done.
+UPDATE automation_runs SET status = 'succeeded' WHERE execution_timestamp IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_automation_runs_schedule_timestamp ON automation_runs(schedule_timestamp);
@@ -0,0 +1 @@
+ALTER TABLE automation_runs DROP COLUMN step_results;
@@ -0,0 +1 @@
+ALTER TABLE automation_runs ADD COLUMN step_results TEXT;
@@ -0,0 +1,4 @@
+ALTER TABLE cadence_triggers DROP COLUMN day_of_month;
+ALTER TABLE cadence_triggers DROP COLUMN interval_minutes;
+ALTER TABLE cadence_triggers DROP COLUMN timezone;
+ALTER TABLE cadence_triggers DROP COLUMN cron_expr;
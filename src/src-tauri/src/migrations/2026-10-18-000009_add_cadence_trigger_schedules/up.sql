@@ -0,0 +1,4 @@
+ALTER TABLE cadence_triggers ADD COLUMN cron_expr TEXT;
+ALTER TABLE cadence_triggers ADD COLUMN timezone TEXT;
+ALTER TABLE cadence_triggers ADD COLUMN interval_minutes INTEGER;
+ALTER TABLE cadence_triggers ADD COLUMN day_of_month INTEGER;
@@ -0,0 +1,3 @@
+DROP INDEX idx_automation_runs_run_key;
+ALTER TABLE automation_runs DROP COLUMN run_key;
+DROP TABLE automation_schedules;
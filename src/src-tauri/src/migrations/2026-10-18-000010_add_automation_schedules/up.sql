@@ -0,0 +1,19 @@
+CREATE TABLE automation_schedules (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  cadence_id INTEGER NOT NULL,
+  automation_uuid TEXT NOT NULL,
+  user_id INTEGER NOT NULL,
+  next_fire_timestamp INTEGER,
+  last_fire_timestamp INTEGER,
+  UNIQUE (cadence_id, user_id)
+);
+CREATE INDEX idx_automation_schedules_next_fire_timestamp ON automation_schedules (next_fire_timestamp);
+
+ALTER TABLE automation_runs ADD COLUMN run_key TEXT;
+CREATE UNIQUE INDEX idx_automation_runs_run_key ON automation_runs (run_key);
+
+-- Placeholder feed items created ahead of cadence runs that were never saved
+UPDATE feed_items SET deleted = 1
+WHERE title IN (SELECT name FROM automations)
+  AND id NOT IN (SELECT feed_item_id FROM automation_runs WHERE feed_item_id IS NOT NULL)
+  AND id NOT IN (SELECT feed_item_id FROM threads WHERE feed_item_id IS NOT NULL);
@@ -0,0 +1,3 @@
+DELETE FROM data_source_trigger WHERE data_source = 'microsoft_calendar';
+ALTER TABLE data_source_trigger DROP COLUMN cursor;
+ALTER TABLE data_source_trigger DROP COLUMN filter_json;
@@ -0,0 +1,7 @@
+ALTER TABLE data_source_trigger ADD COLUMN filter_json TEXT;
+ALTER TABLE data_source_trigger ADD COLUMN cursor INTEGER;
+-- google_calendar triggers used to fire for every synced calendar; keep
+-- Outlook events covered now that they have their own trigger.
+INSERT INTO data_source_trigger (automation_uuid, data_source, offset_minutes)
+SELECT automation_uuid, 'microsoft_calendar', offset_minutes FROM data_source_trigger
+WHERE data_source = 'google_calendar';
@@ -0,0 +1,7 @@
+DROP INDEX idx_token_usage_automation_run_id;
+ALTER TABLE token_usage DROP COLUMN automation_run_id;
+
+DROP INDEX idx_automation_runs_schedule_timestamp;
+UPDATE automation_runs SET status = 'pending' WHERE status = 'scheduled';
+ALTER TABLE automation_runs DROP COLUMN attempts;
+ALTER TABLE automation_runs DROP COLUMN finished_timestamp;
@@ -0,0 +1,8 @@
+ALTER TABLE automation_runs ADD COLUMN finished_timestamp INTEGER;
+ALTER TABLE automation_runs ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+UPDATE automation_runs SET attempts = 1 WHERE execution_timestamp IS NOT NULL;
+UPDATE automation_runs SET status = 'scheduled' WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_automation_runs_schedule_timestamp ON automation_runs(schedule_timestamp);
+
+ALTER TABLE token_usage ADD COLUMN automation_run_id INTEGER;
+CREATE INDEX IF NOT EXISTS idx_token_usage_automation_run_id ON token_usage(automation_run_id);
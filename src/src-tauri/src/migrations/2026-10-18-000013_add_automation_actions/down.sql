@@ -0,0 +1,4 @@
+ALTER TABLE automation_runs DROP COLUMN action_results;
+
+DROP INDEX idx_automation_actions_automation_uuid;
+DROP TABLE automation_actions;
@@ -0,0 +1,10 @@
+CREATE TABLE automation_actions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  automation_uuid TEXT NOT NULL,
+  action_type TEXT NOT NULL,
+  config_json TEXT,
+  ordering INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX idx_automation_actions_automation_uuid ON automation_actions (automation_uuid);
+
+ALTER TABLE automation_runs ADD COLUMN action_results TEXT;
@@ -0,0 +1 @@
+ALTER TABLE automation_steps DROP COLUMN guard;
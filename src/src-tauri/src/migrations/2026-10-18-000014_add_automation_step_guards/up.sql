@@ -0,0 +1 @@
+ALTER TABLE automation_steps ADD COLUMN guard TEXT;
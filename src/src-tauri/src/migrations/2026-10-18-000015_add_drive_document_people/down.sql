@@ -0,0 +1 @@
+ALTER TABLE drive_documents DROP COLUMN people_json;
@@ -0,0 +1,3 @@
+-- Owners and last editor of a drive file, as reported by Drive, so the
+-- contact graph can be rebuilt without another sync.
+ALTER TABLE drive_documents ADD COLUMN people_json TEXT;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_embedding_manifest_document_id;
+ALTER TABLE embedding_manifest DROP COLUMN document_id;
@@ -0,0 +1,5 @@
+-- The document a manifest entry's vectors belong to, so purging a document
+-- can drop its entries. Older entries are filled in on their next upsert.
+ALTER TABLE embedding_manifest ADD COLUMN document_id INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_embedding_manifest_document_id ON embedding_manifest(document_id);
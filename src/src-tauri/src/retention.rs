@@ -0,0 +1,258 @@
+//! Applies the user's retention policies in the background.
+//!
+//! Every hour the sweeper expires transcripts, email bodies and audio chunks
+//! older than their policy allows, then removes `documents` rows whose source
+//! row is gone. Vectors and embedding manifest entries of every removed
+//! document are deleted too, so purged content no longer shows up in search
+//! and isn't mistaken for already embedded. Each purge is logged and
+//! recorded in `retention_purges`.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::backup::BackgroundWork;
+use crate::config;
+use crate::db::models::document::Document;
+use crate::db::models::email::Email;
+use crate::db::models::retention_policy::{
+  RetentionDataType, RetentionPolicy, RetentionPurge,
+};
+use crate::db::models::transcript::Transcript;
+use crate::error::Error;
+use crate::memory::semantic::purge_document_vectors;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const ORPHANED_DOCUMENTS: &str = "orphaned_documents";
+const OVERWRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionSweepSummary {
+  pub purges: Vec<RetentionPurge>,
+  pub errors: Vec<String>,
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or(0)
+}
+
+/// Overwrites a file with zeros before unlinking it. On SSDs and
+/// copy-on-write filesystems old blocks may survive the overwrite, so this
+/// only narrows what can be recovered from disk.
+pub fn secure_remove_file(path: &Path) -> io::Result<()> {
+  let length = fs::metadata(path)?.len();
+  let mut file = OpenOptions::new().write(true).open(path)?;
+  let zeros = vec![0u8; OVERWRITE_BUFFER_SIZE];
+  let mut remaining = length;
+  while remaining > 0 {
+    let chunk = remaining.min(OVERWRITE_BUFFER_SIZE as u64) as usize;
+    file.write_all(&zeros[..chunk])?;
+    remaining -= chunk as u64;
+  }
+  file.sync_all()?;
+  drop(file);
+  fs::remove_file(path)
+}
+
+async fn purge_transcripts(cutoff: u64) -> Result<RetentionPurge, Error> {
+  let transcripts = Transcript::find_before(cutoff as i64)?;
+  let mut document_ids = Vec::new();
+  for transcript in transcripts.iter() {
+    let path = transcript.path();
+    if path.exists() {
+      secure_remove_file(&path)?;
+    }
+    if let Some(document) =
+      Document::find_by_foreign_table_and_id("transcripts", transcript.id.unwrap())?
+    {
+      document_ids.extend(document.id);
+    }
+    transcript.delete()?;
+  }
+  Document::delete_by_ids(&document_ids)?;
+  purge_document_vectors(document_ids).await;
+  Ok(RetentionPurge {
+    id: None,
+    data_type: RetentionDataType::Transcripts.as_str().to_string(),
+    purged_count: transcripts.len() as u64,
+    details: None,
+    created_timestamp: None,
+  })
+}
+
+/// Email documents stay so metadata remains listable; only their vectors,
+/// which embed the body, are removed.
+async fn purge_email_bodies(cutoff: u64) -> Result<RetentionPurge, Error> {
+  let email_ids = Email::clear_bodies_before(cutoff)?;
+  let mut document_ids = Vec::new();
+  for email_id in email_ids.iter() {
+    if let Some(document) = Document::find_by_foreign_table_and_id("emails", *email_id)? {
+      document_ids.extend(document.id);
+    }
+  }
+  let vector_count = document_ids.len();
+  purge_document_vectors(document_ids).await;
+  Ok(RetentionPurge {
+    id: None,
+    data_type: RetentionDataType::EmailBodies.as_str().to_string(),
+    purged_count: email_ids.len() as u64,
+    details: Some(format!("vectors removed for {} documents", vector_count)),
+    created_timestamp: None,
+  })
+}
+
+fn purge_audio(cutoff: u64) -> Result<RetentionPurge, Error> {
+  let audio_dir = config::audio_dir();
+  let mut purged_count = 0;
+  if audio_dir.exists() {
+    for entry in fs::read_dir(&audio_dir)? {
+      let path = entry?.path();
+      if !path.is_file() {
+        continue;
+      }
+      let modified = fs::metadata(&path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+      if modified < cutoff {
+        secure_remove_file(&path)?;
+        purged_count += 1;
+      }
+    }
+  }
+  Ok(RetentionPurge {
+    id: None,
+    data_type: RetentionDataType::Audio.as_str().to_string(),
+    purged_count,
+    details: None,
+    created_timestamp: None,
+  })
+}
+
+async fn purge_orphaned_documents() -> Result<RetentionPurge, Error> {
+  let document_ids: Vec<u64> = Document::find_orphaned()?
+    .into_iter()
+    .filter_map(|document| document.id)
+    .collect();
+  let purged_count = Document::delete_by_ids(&document_ids)? as u64;
+  purge_document_vectors(document_ids).await;
+  Ok(RetentionPurge {
+    id: None,
+    data_type: ORPHANED_DOCUMENTS.to_string(),
+    purged_count,
+    details: None,
+    created_timestamp: None,
+  })
+}
+
+async fn apply_policy(policy: &RetentionPolicy, now: u64) -> Result<RetentionPurge, Error> {
+  let cutoff = policy.cutoff(now);
+  match policy.data_type {
+    RetentionDataType::Transcripts => purge_transcripts(cutoff).await,
+    RetentionDataType::EmailBodies => purge_email_bodies(cutoff).await,
+    RetentionDataType::Audio => purge_audio(cutoff),
+  }
+}
+
+/// Records a purge that removed something; empty purges are not kept.
+fn record_purge(mut purge: RetentionPurge, purges: &mut Vec<RetentionPurge>) -> Result<(), Error> {
+  if purge.purged_count == 0 {
+    return Ok(());
+  }
+  log::info!(
+    "Retention purged {} {} {}",
+    purge.purged_count,
+    purge.data_type,
+    purge.details.clone().unwrap_or_default()
+  );
+  purge.create()?;
+  purges.push(purge);
+  Ok(())
+}
+
+/// Applies every enabled policy once, then clears orphaned documents.
+pub async fn run_retention_sweep() -> Result<RetentionSweepSummary, Error> {
+  let now = now_secs();
+  let mut summary = RetentionSweepSummary {
+    purges: Vec::new(),
+    errors: Vec::new(),
+  };
+  for policy in RetentionPolicy::find_all()? {
+    if !policy.is_enabled {
+      continue;
+    }
+    let result = match apply_policy(&policy, now).await {
+      Ok(purge) => record_purge(purge, &mut summary.purges)
+        .and_then(|_| RetentionPolicy::mark_applied(policy.data_type, now)),
+      Err(error) => Err(error),
+    };
+    if let Err(error) = result {
+      log::error!(
+        "Retention policy {} failed - {:?}",
+        policy.data_type.as_str(),
+        error
+      );
+      summary
+        .errors
+        .push(format!("{}: {:?}", policy.data_type.as_str(), error));
+    }
+  }
+  let result = match purge_orphaned_documents().await {
+    Ok(purge) => record_purge(purge, &mut summary.purges),
+    Err(error) => Err(error),
+  };
+  if let Err(error) = result {
+    log::error!("Purging orphaned documents failed - {:?}", error);
+    summary
+      .errors
+      .push(format!("{}: {:?}", ORPHANED_DOCUMENTS, error));
+  }
+  Ok(summary)
+}
+
+pub fn start_retention_sweeper() {
+  tokio::spawn(async {
+    loop {
+      if let Some(_work) = BackgroundWork::begin() {
+        if let Err(error) = run_retention_sweep().await {
+          log::error!("Retention sweep failed - {:?}", error);
+        }
+      }
+      tokio::time::sleep(SWEEP_INTERVAL).await;
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn secure_remove_file_deletes_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chunk.wav");
+    fs::write(&path, vec![7u8; OVERWRITE_BUFFER_SIZE + 10]).unwrap();
+    secure_remove_file(&path).unwrap();
+    assert!(!path.exists());
+  }
+
+  #[test]
+  fn cutoff_is_retain_days_before_now() {
+    let policy = RetentionPolicy {
+      data_type: RetentionDataType::Transcripts,
+      retain_days: 2,
+      is_enabled: true,
+      last_applied: None,
+    };
+    assert_eq!(policy.cutoff(3 * 86_400), 86_400);
+    assert_eq!(policy.cutoff(100), 0);
+  }
+}
@@ -9,7 +9,8 @@ use actix_web::{get, post, put, web::Json, HttpResponse, Responder, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::api::document::DisplayDocument;
-use crate::db::models::{calendar_event::CalendarEvent, document::Document, email::Email};
+use crate::db::models::{calendar_event::CalendarEvent, document::Document, email::Email, user::User};
+use crate::db::repo::run_blocking;
 use crate::error::Error as CustomError;
 use crate::user::UserInfo;
 
@@ -88,6 +89,7 @@ pub struct CalendarSearchResponseDoc {
   pub end: Option<i64>,
   pub google_meet_url: Option<String>,
   pub id: Option<u64>,
+  pub account_id: Option<u64>,
 }
 
 #[derive(Serialize)]
@@ -115,6 +117,7 @@ pub struct GmailSearchResponseDoc {
   pub is_read: Option<bool>,
   pub is_archived: Option<bool>,
   pub is_deleted: Option<bool>,
+  pub account_id: Option<u64>,
 }
 
 #[derive(Serialize)]
@@ -136,50 +139,88 @@ struct StandardResponse {
   message: Option<String>,
 }
 
+/// Loads emails off the async runtime and maps them to response docs.
+async fn email_search_response<F>(query: F) -> HttpResponse
+where
+  F: FnOnce() -> std::result::Result<Vec<Email>, CustomError> + Send + 'static,
+{
+  let result = run_blocking(move || {
+    Ok(
+      query()?
+        .into_iter()
+        .filter_map(build_gmail_response_doc_from_email_row)
+        .collect::<Vec<_>>(),
+    )
+  })
+  .await;
+  match result {
+    Ok(display_docs) => HttpResponse::Ok().json(GmailSearchResponse {
+      success: true,
+      display_docs,
+      error: None,
+    }),
+    Err(error) => {
+      log::error!("Failed to list emails: {:?}", error);
+      HttpResponse::InternalServerError().json(GmailSearchResponse {
+        success: false,
+        display_docs: vec![],
+        error: Some(format!("Failed to list emails: {:?}", error)),
+      })
+    }
+  }
+}
+
+fn calendar_response_doc(calendar_event: CalendarEvent) -> CalendarSearchResponseDoc {
+  CalendarSearchResponseDoc {
+    event_id: calendar_event.event_id,
+    title: calendar_event.title,
+    description: calendar_event.description,
+    creator_email: calendar_event.creator_email,
+    attendees_json: calendar_event.attendees_json,
+    location: calendar_event.location,
+    start: calendar_event.start,
+    end: calendar_event.end,
+    google_meet_url: calendar_event.google_meet_url,
+    id: calendar_event.id,
+    account_id: calendar_event.account_id,
+  }
+}
+
+/// Loads calendar events off the async runtime and maps them to response
+/// docs.
+async fn calendar_search_response<F>(query: F) -> HttpResponse
+where
+  F: FnOnce() -> std::result::Result<Vec<CalendarEvent>, CustomError> + Send + 'static,
+{
+  match run_blocking(query).await {
+    Ok(calendar_events) => HttpResponse::Ok().json(GoogleCalendarSearchResponse {
+      success: true,
+      display_docs: calendar_events.into_iter().map(calendar_response_doc).collect(),
+      error: None,
+    }),
+    Err(error) => {
+      log::error!("Failed to list calendar events: {:?}", error);
+      HttpResponse::InternalServerError().json(GoogleCalendarSearchResponse {
+        success: false,
+        display_docs: vec![],
+        error: Some(format!("Failed to list calendar events: {:?}", error)),
+      })
+    }
+  }
+}
+
 #[post("/api/knapsack/recent_emails_search")]
 async fn get_recent_emails(payload: Json<GetMostRecentEmailsRequest>) -> impl Responder {
-  let mut display_docs = Vec::new();
-  let recent_emails = Email::get_recent_emails(payload.top);
-  for email in recent_emails {
-    match build_gmail_response_doc_from_email_row(email) {
-      Some(doc) => display_docs.push(doc),
-      None => continue,
-    };
-  }
-  let response = GmailSearchResponse {
-    success: true,
-    display_docs,
-    error: None,
-  };
-  return actix_web::HttpResponse::Ok().json(response);
+  let top = payload.top;
+  email_search_response(move || Email::get_recent_emails(top)).await
 }
 
 #[post("/api/knapsack/recent_calendar_events")]
 async fn get_recent_calendar_events(
   payload: Json<GetMostRecentCalendarEventsRequest>,
 ) -> impl Responder {
-  let mut display_docs = Vec::new();
-  let recent_calendar_events = CalendarEvent::get_recent_calendar_events(payload.top);
-  for calendar_event in recent_calendar_events {
-    display_docs.push(CalendarSearchResponseDoc {
-      event_id: calendar_event.event_id,
-      title: calendar_event.title,
-      description: calendar_event.description,
-      creator_email: calendar_event.creator_email,
-      attendees_json: calendar_event.attendees_json,
-      location: calendar_event.location,
-      start: calendar_event.start,
-      end: calendar_event.end,
-      google_meet_url: calendar_event.google_meet_url,
-      id: calendar_event.id,
-    });
-  }
-  let response = GoogleCalendarSearchResponse {
-    success: true,
-    display_docs,
-    error: None,
-  };
-  return actix_web::HttpResponse::Ok().json(response);
+  let top = payload.top;
+  calendar_search_response(move || CalendarEvent::get_recent_calendar_events(top)).await
 }
 
 #[derive(Deserialize)]
@@ -190,40 +231,17 @@ struct CalendarGetEventsParams {
 
 #[get("/api/knapsack/calendar/get_events")]
 async fn get_events(query: web::Query<CalendarGetEventsParams>) -> impl Responder {
-    let events = CalendarEvent::find_by_timestamp_range(
-        query.start_timestamp,
-        query.end_timestamp,
-    );
-
-    let display_docs: Vec<CalendarSearchResponseDoc> = events
-        .into_iter()
-        .map(|calendar_event| CalendarSearchResponseDoc {
-            event_id: calendar_event.event_id,
-            title: calendar_event.title,
-            description: calendar_event.description,
-            creator_email: calendar_event.creator_email,
-            attendees_json: calendar_event.attendees_json,
-            location: calendar_event.location,
-            start: calendar_event.start,
-            end: calendar_event.end,
-            google_meet_url: calendar_event.google_meet_url,
-            id: calendar_event.id,
-        })
-        .collect();
-
-    let response = GoogleCalendarSearchResponse {
-        success: true,
-        display_docs,
-        error: None,
-    };
-
-    HttpResponse::Ok().json(response)
+  let (start_timestamp, end_timestamp) = (query.start_timestamp, query.end_timestamp);
+  calendar_search_response(move || {
+    CalendarEvent::find_by_timestamp_range(start_timestamp, end_timestamp)
+  })
+  .await
 }
 
 #[get("/api/knapsack/calendar_event/{event_id}")]
 async fn get_calendar_event_by_id(path: web::Path<u64>) -> impl Responder {
   let event_id = path.into_inner();
-  match CalendarEvent::find_by_id(event_id) {
+  match run_blocking(move || CalendarEvent::find_by_id(event_id)).await {
     Ok(maybe_calendar_event) => match maybe_calendar_event {
       Some(calendar_event) => {
         actix_web::HttpResponse::Ok().json(json!({ "success": true, "data": calendar_event}))
@@ -244,13 +262,17 @@ pub async fn filter_emails_by_addresses(
   payload: Json<FilterEmailsRequest>,
   user_info: Data<Arc<RwLock<UserInfo>>>,
 ) -> impl Responder {
-  let mut display_docs = Vec::new();
   let ten_days_ago_timestamp = (chrono::Utc::now() - chrono::Duration::days(10)).timestamp();
   let mut maybe_email_addresses: Option<Vec<String>> = None;
   let email_addresses = payload.addresses.clone();
   let my_email_address = match user_info.read().await.email.clone() {
     Some(email) => email,
-    None => "".to_string(),
+    None => run_blocking(|| Ok(User::find_active()))
+      .await
+      .ok()
+      .flatten()
+      .map(|user| user.email)
+      .unwrap_or_default(),
   };
   let mut parsed_email_addresses = Vec::new();
   for email_address in email_addresses {
@@ -261,77 +283,39 @@ pub async fn filter_emails_by_addresses(
   if !parsed_email_addresses.is_empty() {
     maybe_email_addresses = Some(parsed_email_addresses);
   }
-  let recent_emails = Email::filter_emails(
-    payload.top,
-    maybe_email_addresses,
-    Some(ten_days_ago_timestamp),
-    None,
-  );
-  for email in recent_emails {
-    match build_gmail_response_doc_from_email_row(email) {
-      Some(doc) => display_docs.push(doc),
-      None => continue,
-    };
-  }
-  let response = GmailSearchResponse {
-    success: true,
-    display_docs,
-    error: None,
-  };
-  return actix_web::HttpResponse::Ok().json(response);
+  let top = payload.top;
+  email_search_response(move || {
+    Email::filter_emails(top, maybe_email_addresses, Some(ten_days_ago_timestamp), None)
+  })
+  .await
 }
 
 #[post("/api/knapsack/list_emails_within_timestamps")]
 pub async fn list_emails_within_timestamps(
   payload: Json<ListEmailsWithinTimestampsRequest>,
 ) -> impl Responder {
-  let mut display_docs = Vec::new();
-  let recent_emails = Email::filter_emails(
-    payload.top,
-    None,
-    Some(payload.from_timestamp.clone()),
-    Some(payload.to_timestamp.clone()),
-  );
-
-  for email in recent_emails {
-    match build_gmail_response_doc_from_email_row(email) {
-      Some(doc) => display_docs.push(doc),
-      None => continue,
-    };
-  }
-  let response = GmailSearchResponse {
-    success: true,
-    display_docs,
-    error: None,
-  };
-  return actix_web::HttpResponse::Ok().json(response);
+  let (top, from_timestamp, to_timestamp) =
+    (payload.top, payload.from_timestamp, payload.to_timestamp);
+  email_search_response(move || {
+    Email::filter_emails(top, None, Some(from_timestamp), Some(to_timestamp))
+  })
+  .await
 }
 
 #[post("/api/knapsack/list_sent_emails_within_timestamps")]
 pub async fn list_sent_emails_within_timestamps(
   payload: Json<ListSentEmailsWithinTimestampsRequest>,
 ) -> impl Responder {
-  let mut display_docs = Vec::new();
-  let sent_emails = Email::filter_emails_by_sender(
-    payload.top,
-    &payload.email,
-    payload.from_timestamp.clone(),
-    payload.to_timestamp.clone(),
-  );
-
-  for email in sent_emails {
-    match build_gmail_response_doc_from_email_row(email) {
-      Some(doc) => display_docs.push(doc),
-      None => continue,
-    };
-  }
-
-  let response = GmailSearchResponse {
-    success: true,
-    display_docs,
-    error: None,
-  };
-  return actix_web::HttpResponse::Ok().json(response);
+  let payload = payload.into_inner();
+  email_search_response(move || {
+    Email::filter_emails_by_sender(
+      payload.top,
+      &payload.email,
+      payload.from_timestamp,
+      payload.to_timestamp,
+    )
+  })
+  .await
 }
 
 fn get_document_id_from_email(email: Email) -> Option<u64> {
@@ -386,6 +370,7 @@ fn build_gmail_response_doc_from_email_row(email: Email) -> Option<GmailSearchRe
     is_read: email.is_read,
     is_archived: email.is_archived,
     is_deleted: email.is_deleted,
+    account_id: email.account_id,
   };
   return Some(doc);
 }
@@ -394,7 +379,7 @@ fn build_gmail_response_doc_from_email_row(email: Email) -> Option<GmailSearchRe
 pub async fn get_email_thread(path: web::Path<u64>) -> Result<HttpResponse> {
   let document_id = path.into_inner();
 
-  let document = match Document::find_by_id(document_id) {
+  let document = match run_blocking(move || Document::find_by_id(document_id)).await {
     Ok(Some(doc)) => doc,
     Ok(None) => {
       return Ok(HttpResponse::NotFound().json(StandardResponse {
@@ -412,7 +397,8 @@ pub async fn get_email_thread(path: web::Path<u64>) -> Result<HttpResponse> {
     }
   };
 
-  let email = match Email::find_by_id(document.foreign_table_id) {
+  let email_id = document.foreign_table_id;
+  let email = match run_blocking(move || Email::find_by_id(email_id)).await {
     Ok(Some(email)) => email,
     Ok(None) => {
       return Ok(HttpResponse::NotFound().json(StandardResponse {
@@ -430,17 +416,18 @@ pub async fn get_email_thread(path: web::Path<u64>) -> Result<HttpResponse> {
     }
   };
 
-  let thread_emails = match Email::get_last_email_by_thread_id(&email.thread_id.unwrap_or_default())
-  {
-    Ok(emails) => emails,
-    Err(e) => {
-      return Ok(HttpResponse::InternalServerError().json(StandardResponse {
-        success: false,
-        error_code: Some("DATABASE_ERROR".to_string()),
-        message: Some(format!("Error fetching thread emails: {:?}", e)),
-      }))
-    }
-  };
+  let thread_id = email.thread_id.unwrap_or_default();
+  let thread_emails =
+    match run_blocking(move || Email::get_last_email_by_thread_id(&thread_id)).await {
+      Ok(emails) => emails,
+      Err(e) => {
+        return Ok(HttpResponse::InternalServerError().json(StandardResponse {
+          success: false,
+          error_code: Some("DATABASE_ERROR".to_string()),
+          message: Some(format!("Error fetching thread emails: {:?}", e)),
+        }))
+      }
+    };
 
   let display_docs: Vec<GmailSearchResponseDoc> = thread_emails
     .into_iter()
@@ -472,7 +459,8 @@ struct UpdateEmailRequest {
 
 #[put("/api/knapsack/update_email")]
 pub async fn update_email(payload: Json<UpdateEmailRequest>) -> Result<HttpResponse> {
-  let email = match Email::find_by_uid(&payload.email_uid) {
+  let email_uid = payload.email_uid.clone();
+  let email = match run_blocking(move || Email::find_by_uid(&email_uid)).await {
     Ok(Some(email)) => email,
     Ok(None) => {
       return Ok(HttpResponse::NotFound().json(StandardResponse {
@@ -523,7 +511,7 @@ pub async fn update_email(payload: Json<UpdateEmailRequest>) -> Result<HttpRespo
     updated_email.is_archived = Some(is_archived);
   }
 
-  match updated_email.update() {
+  match run_blocking(move || updated_email.update()).await {
     Ok(_) => Ok(HttpResponse::Ok().json(StandardResponse {
       success: true,
       error_code: None,
@@ -0,0 +1,397 @@
+//! Encryption at rest for OAuth tokens and API keys.
+//!
+//! Secrets are sealed with XChaCha20-Poly1305 under a key set kept in the OS
+//! keyring. When no keyring is available the key set is stored in
+//! `~/.knapsack/secret_keys.json`, itself sealed with a key derived from
+//! `KNAPSACK_SECRETS_PASSPHRASE`. A passphrase key set is moved into the
+//! keyring once one becomes available.
+//!
+//! Sealed values look like `ks1:<key id>:<base64 nonce + ciphertext>`. Values
+//! without the prefix are legacy plaintext: `decrypt` passes them through and
+//! `needs_reseal` reports them so callers can migrate them.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::error::Error;
+
+const SEALED_PREFIX: &str = "ks1:";
+const NONCE_LEN: usize = 24;
+const KEYRING_SERVICE: &str = "ai.knap.knapsack";
+const KEYRING_USER: &str = "secret-store";
+const PASSPHRASE_ENV: &str = "KNAPSACK_SECRETS_PASSPHRASE";
+const PASSPHRASE_KEYS_FILENAME: &str = "secret_keys.json";
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// All keys that may still be referenced by sealed values; new values are
+/// sealed with `active`.
+#[derive(Clone, Serialize, Deserialize)]
+struct KeySet {
+  active: u32,
+  keys: BTreeMap<u32, String>,
+}
+
+impl KeySet {
+  fn generate() -> KeySet {
+    let mut key_set = KeySet {
+      active: 0,
+      keys: BTreeMap::new(),
+    };
+    key_set.add_key();
+    key_set
+  }
+
+  fn add_key(&mut self) -> u32 {
+    let key_id = self.keys.keys().max().map_or(1, |key_id| key_id + 1);
+    let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+    self.keys.insert(key_id, BASE64.encode(key));
+    self.active = key_id;
+    key_id
+  }
+
+  fn cipher(&self, key_id: u32) -> Result<XChaCha20Poly1305, Error> {
+    let encoded = self
+      .keys
+      .get(&key_id)
+      .ok_or_else(|| Error::KSError(format!("Unknown secret key {}", key_id)))?;
+    let key = BASE64
+      .decode(encoded)
+      .map_err(|_| Error::KSError(format!("Corrupt secret key {}", key_id)))?;
+    if key.len() != 32 {
+      return Err(Error::KSError(format!("Corrupt secret key {}", key_id)));
+    }
+    Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+  }
+
+  fn seal(&self, plaintext: &str) -> Result<String, Error> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = self
+      .cipher(self.active)?
+      .encrypt(&nonce, plaintext.as_bytes())
+      .map_err(|_| Error::KSError("Failed to encrypt secret".to_string()))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(format!(
+      "{}{}:{}",
+      SEALED_PREFIX,
+      self.active,
+      BASE64.encode(sealed)
+    ))
+  }
+
+  /// Whether `value` is plaintext or sealed with another key than the
+  /// active one. Values that look sealed but can't be read are errors.
+  fn needs_reseal(&self, value: &str) -> Result<bool, Error> {
+    match split_sealed(value) {
+      Some((key_id, _)) if self.keys.contains_key(&key_id) => Ok(key_id != self.active),
+      Some((key_id, _)) => Err(Error::KSError(format!("Unknown secret key {}", key_id))),
+      None if value.starts_with(SEALED_PREFIX) => {
+        Err(Error::KSError("Corrupt sealed secret".to_string()))
+      }
+      None => Ok(!value.is_empty()),
+    }
+  }
+
+  fn open(&self, value: &str) -> Result<String, Error> {
+    let (key_id, data) = match split_sealed(value) {
+      Some(parts) => parts,
+      None => return Ok(value.to_string()),
+    };
+    let data = BASE64
+      .decode(data)
+      .map_err(|_| Error::KSError("Corrupt sealed secret".to_string()))?;
+    if data.len() < NONCE_LEN {
+      return Err(Error::KSError("Corrupt sealed secret".to_string()));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plaintext = self
+      .cipher(key_id)?
+      .decrypt(XNonce::from_slice(nonce), ciphertext)
+      .map_err(|_| Error::KSError("Failed to decrypt secret".to_string()))?;
+    String::from_utf8(plaintext).map_err(|_| Error::KSError("Corrupt sealed secret".to_string()))
+  }
+}
+
+fn split_sealed(value: &str) -> Option<(u32, &str)> {
+  let (key_id, data) = value.strip_prefix(SEALED_PREFIX)?.split_once(':')?;
+  Some((key_id.parse().ok()?, data))
+}
+
+/// Where the key set lives.
+#[derive(Clone)]
+enum KeySource {
+  Keyring,
+  Passphrase(String),
+}
+
+/// On-disk form of a passphrase-protected key set.
+#[derive(Serialize, Deserialize)]
+struct PassphraseKeyFile {
+  salt: String,
+  iterations: u32,
+  sealed: String,
+}
+
+fn passphrase_keys_path() -> PathBuf {
+  crate::config::data_dir().join(PASSPHRASE_KEYS_FILENAME)
+}
+
+fn derive_key_encryption_key(passphrase: &str, salt: &[u8], iterations: u32) -> KeySet {
+  let mut key = [0u8; 32];
+  pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+  KeySet {
+    active: 0,
+    keys: BTreeMap::from([(0, BASE64.encode(key))]),
+  }
+}
+
+impl KeySource {
+  fn detect() -> Result<KeySource, Error> {
+    match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).and_then(|entry| entry.get_password()) {
+      Ok(_) | Err(keyring::Error::NoEntry) => return Ok(KeySource::Keyring),
+      Err(error) => log::warn!("OS keyring unavailable, trying passphrase: {:?}", error),
+    }
+    match std::env::var(PASSPHRASE_ENV) {
+      Ok(passphrase) if !passphrase.is_empty() => Ok(KeySource::Passphrase(passphrase)),
+      _ => Err(Error::KSError(format!(
+        "No OS keyring available and {} is not set",
+        PASSPHRASE_ENV
+      ))),
+    }
+  }
+
+  fn load(&self) -> Result<Option<KeySet>, Error> {
+    match self {
+      KeySource::Keyring => {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+          .map_err(|e| Error::KSError(format!("{:?}", e)))?;
+        match entry.get_password() {
+          Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+          Err(keyring::Error::NoEntry) => Ok(None),
+          Err(e) => Err(Error::KSError(format!("{:?}", e))),
+        }
+      }
+      KeySource::Passphrase(passphrase) => {
+        let path = passphrase_keys_path();
+        if !path.exists() {
+          return Ok(None);
+        }
+        let file: PassphraseKeyFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let salt = BASE64
+          .decode(&file.salt)
+          .map_err(|_| Error::KSError("Corrupt secret key file".to_string()))?;
+        let json = derive_key_encryption_key(passphrase, &salt, file.iterations)
+          .open(&file.sealed)
+          .map_err(|_| Error::KSError("Wrong secrets passphrase".to_string()))?;
+        Ok(Some(serde_json::from_str(&json)?))
+      }
+    }
+  }
+
+  fn save(&self, key_set: &KeySet) -> Result<(), Error> {
+    let json = serde_json::to_string(key_set)?;
+    match self {
+      KeySource::Keyring => keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .and_then(|entry| entry.set_password(&json))
+        .map_err(|e| Error::KSError(format!("{:?}", e))),
+      KeySource::Passphrase(passphrase) => {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let file = PassphraseKeyFile {
+          salt: BASE64.encode(salt),
+          iterations: PBKDF2_ITERATIONS,
+          sealed: derive_key_encryption_key(passphrase, &salt, PBKDF2_ITERATIONS).seal(&json)?,
+        };
+        let path = passphrase_keys_path();
+        if let Some(parent) = path.parent() {
+          fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(&file)?)?;
+        #[cfg(unix)]
+        {
+          use std::os::unix::fs::PermissionsExt;
+          let _ = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+/// Process-wide access to the key set. Loaded on first use; a failed load
+/// is retried on the next call.
+pub struct SecretStore {
+  keys: KeySet,
+  source: KeySource,
+}
+
+static SECRET_STORE: Lazy<RwLock<Option<SecretStore>>> = Lazy::new(|| RwLock::new(None));
+
+impl SecretStore {
+  fn open_or_create() -> Result<SecretStore, Error> {
+    let source = KeySource::detect()?;
+    let keys = match source.load()? {
+      Some(keys) => keys,
+      None if matches!(source, KeySource::Keyring) && passphrase_keys_path().exists() => {
+        SecretStore::migrate_passphrase_keys(&source)?
+      }
+      None => {
+        let keys = KeySet::generate();
+        source.save(&keys)?;
+        log::info!("Created secret store key set");
+        keys
+      }
+    };
+    Ok(SecretStore { keys, source })
+  }
+
+  /// Moves a key set written while no keyring was available into the
+  /// keyring, so values sealed under it stay readable. Generating a fresh key
+  /// set instead would orphan them.
+  fn migrate_passphrase_keys(keyring: &KeySource) -> Result<KeySet, Error> {
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+      Ok(passphrase) if !passphrase.is_empty() => passphrase,
+      _ => {
+        return Err(Error::KSError(format!(
+          "{} exists; set {} once so its keys can move to the OS keyring",
+          PASSPHRASE_KEYS_FILENAME, PASSPHRASE_ENV
+        )))
+      }
+    };
+    let keys = KeySource::Passphrase(passphrase)
+      .load()?
+      .ok_or_else(|| Error::KSError("Secret key file disappeared".to_string()))?;
+    keyring.save(&keys)?;
+    fs::remove_file(passphrase_keys_path())?;
+    log::info!("Moved secret store key set into the OS keyring");
+    Ok(keys)
+  }
+
+  fn with<T>(f: impl FnOnce(&mut SecretStore) -> Result<T, Error>) -> Result<T, Error> {
+    let mut store = SECRET_STORE
+      .write()
+      .map_err(|_| Error::KSError("Secret store lock poisoned".to_string()))?;
+    if store.is_none() {
+      *store = Some(SecretStore::open_or_create()?);
+    }
+    f(store.as_mut().unwrap())
+  }
+
+  pub fn encrypt(plaintext: &str) -> Result<String, Error> {
+    SecretStore::with(|store| store.keys.seal(plaintext))
+  }
+
+  /// Opens a sealed value; legacy plaintext is returned unchanged.
+  pub fn decrypt(value: &str) -> Result<String, Error> {
+    if split_sealed(value).is_none() {
+      return Ok(value.to_string());
+    }
+    SecretStore::with(|store| store.keys.open(value))
+  }
+
+  /// True for plaintext values and values sealed with a retired key. Fails
+  /// when no key source is available or `value` is a corrupt sealed value.
+  pub fn needs_reseal(value: &str) -> Result<bool, Error> {
+    SecretStore::with(|store| store.keys.needs_reseal(value))
+  }
+
+  /// Decrypts with whichever key sealed `value` and seals it again with the
+  /// active key.
+  pub fn reseal(value: &str) -> Result<String, Error> {
+    SecretStore::with(|store| {
+      let plaintext = store.keys.open(value)?;
+      store.keys.seal(&plaintext)
+    })
+  }
+
+  /// Makes a new key active. Older keys stay readable until
+  /// `retire_inactive_keys` is called once every secret has been resealed.
+  pub fn rotate_key() -> Result<u32, Error> {
+    SecretStore::with(|store| {
+      let mut keys = store.keys.clone();
+      let key_id = keys.add_key();
+      store.source.save(&keys)?;
+      store.keys = keys;
+      log::info!("Rotated secret store key to {}", key_id);
+      Ok(key_id)
+    })
+  }
+
+  pub fn retire_inactive_keys() -> Result<usize, Error> {
+    SecretStore::with(|store| {
+      let mut keys = store.keys.clone();
+      let active = keys.active;
+      keys.keys.retain(|key_id, _| *key_id == active);
+      let retired = store.keys.keys.len() - keys.keys.len();
+      store.source.save(&keys)?;
+      store.keys = keys;
+      Ok(retired)
+    })
+  }
+
+  /// Drops the cached key set so the next use reads it again, e.g. after a
+  /// backup restored `secret_keys.json`.
+  pub fn reload() {
+    if let Ok(mut store) = SECRET_STORE.write() {
+      *store = None;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn seals_and_opens_with_the_key_that_sealed() {
+    let mut keys = KeySet::generate();
+    let sealed = keys.seal("refresh-token").unwrap();
+    assert!(sealed.starts_with("ks1:1:"));
+    assert_ne!(sealed, keys.seal("refresh-token").unwrap());
+
+    keys.add_key();
+    assert_eq!(keys.open(&sealed).unwrap(), "refresh-token");
+    assert!(keys.seal("refresh-token").unwrap().starts_with("ks1:2:"));
+  }
+
+  #[test]
+  fn passes_legacy_plaintext_through() {
+    let keys = KeySet::generate();
+    assert_eq!(keys.open("ya29.plain-token").unwrap(), "ya29.plain-token");
+  }
+
+  #[test]
+  fn rejects_tampered_ciphertext() {
+    let keys = KeySet::generate();
+    let sealed = keys.seal("secret").unwrap();
+    let (prefix, data) = sealed.rsplit_once(':').unwrap();
+    let mut bytes = BASE64.decode(data).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    let tampered = format!("{}:{}", prefix, BASE64.encode(bytes));
+    assert!(keys.open(&tampered).is_err());
+  }
+
+  #[test]
+  fn reports_values_to_reseal() {
+    let mut keys = KeySet::generate();
+    let old = keys.seal("secret").unwrap();
+    keys.add_key();
+    assert!(keys.needs_reseal(&old).unwrap());
+    assert!(!keys.needs_reseal(&keys.seal("secret").unwrap()).unwrap());
+    assert!(keys.needs_reseal("ya29.plain-token").unwrap());
+    assert!(!keys.needs_reseal("").unwrap());
+    assert!(keys.needs_reseal("ks1:x:abc").is_err());
+    assert!(keys.needs_reseal("ks1:9:abc").is_err());
+  }
+}
@@ -4,10 +4,7 @@ use actix_web::web::Data;
 use core::time::Duration;
 
 use std::path::PathBuf;
-use std::sync::{
-  atomic::{AtomicBool, AtomicU16},
-  Arc,
-};
+use std::sync::{atomic::AtomicBool, Arc};
 use tokio::runtime::Handle;
 use tokio::sync::{Mutex, RwLock};
 
@@ -26,7 +23,7 @@ use clawd::sidecar::ClawdbotConfig;
 use clawd::sidecar::SharedClawdbotConfig;
 
 use crate::llm::llama_binding::process::InferenceThreadRequest;
-use crate::memory::semantic::{semantic_search, SemanticService};
+use crate::memory::semantic::{get_embedding_jobs, semantic_search, SemanticService};
 
 use crate::api;
 use crate::audio;
@@ -50,7 +47,6 @@ pub async fn start_server<'a>(
   port: u16,
   //llm_path: PathBuf,
   app_handle: tauri::AppHandle,
-  knapsack_gmail_indexing_progress: Arc<AtomicU16>,
   semantic_service: Arc<Mutex<Option<SemanticService>>>,
   is_chatting: Arc<Mutex<AtomicBool>>,
   connections_data: Arc<Mutex<ConnectionsData>>,
@@ -91,7 +87,6 @@ pub async fn start_server<'a>(
     App::new()
       .app_data(Data::new(app_handle.clone()))
       .app_data(Data::new(semantic_service.clone()))
-      .app_data(Data::new(knapsack_gmail_indexing_progress.clone()))
       .app_data(user_info.clone())
       .app_data(qdrant_client.clone())
       .app_data(Data::clone(&llama_data))
@@ -115,12 +110,19 @@ pub async fn start_server<'a>(
       .service(automation_api::create_message)
       .service(automation_api::delete_automation)
       .service(automation_api::update_automation)
+      .service(automation_api::get_step_catalog)
+      .service(automation_api::export_automations)
+      .service(automation_api::import_automations)
       .service(automation_api::upsert_automations_feedback)
       .service(automation_api::get_feedbacks)
       .service(automation_api::start_check)
       .service(automation_api::create_system_message)
       .service(automation_api::schedule_automation_runs)
       .service(automation_api::get_automation_runs)
+      .service(automation_api::get_automation_run)
+      .service(automation_api::retry_automation_run)
+      .service(automation_api::run_automation_now)
+      .service(automation_api::preview_automation)
       .service(automation_api::get_feed_items)
       .service(automation_api::create_feed_item)
       .service(automation_api::update_feed_item)
@@ -134,10 +136,24 @@ pub async fn start_server<'a>(
       .service(audio::audio::pause_recording)
       .service(audio::audio::list_all_transcripts)
       .service(api::document::get_document_infos)
+      .service(api::document::get_related_documents)
       .service(api::notes::list_all_notes)
       .service(api::notes::get_notes)
       .service(api::notes::save_notes)
       .service(api::audio::delete_audio_files)
+      .service(api::contacts::search_contacts)
+      .service(api::contacts::rebuild_contacts_api)
+      .service(api::contacts::get_contact)
+      .service(api::contacts::merge_contact)
+      .service(api::secrets::rotate_secret_key)
+      .service(api::backup::create_backup_api)
+      .service(api::backup::restore_backup_api)
+      .service(api::accounts::list_accounts)
+      .service(api::accounts::set_active_account)
+      .service(api::retention::get_retention)
+      .service(api::retention::update_retention_policy)
+      .service(api::retention::run_retention_sweep_api)
+      .service(api::sync_runs::list_sync_runs)
       .service(search::get_recent_emails)
       .service(search::get_recent_calendar_events)
       .service(search::filter_emails_by_addresses)
@@ -148,6 +164,7 @@ pub async fn start_server<'a>(
       .service(search::update_email)
       .service(search::get_events)
       .service(semantic_search)
+      .service(get_embedding_jobs)
       .service(connections::google::auth::google_signin_api)
       .service(connections::google::auth::complete_google_signin)
       .service(connections::google::auth::focus)
@@ -1,4 +1,3 @@
 pub mod actix;
 pub mod gpu;
 pub mod qdrant;
-pub mod status;
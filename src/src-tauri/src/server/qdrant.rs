@@ -24,8 +24,7 @@ struct QdrantService {
 }
 
 pub fn get_qdrant_logfile() -> File {
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let log_pathbuf = home_dir.join(QDRANT_LOG);
+  let log_pathbuf = crate::config::data_root().join(QDRANT_LOG);
   let log_path = log_pathbuf.as_path();
 
   let log_file = OpenOptions::new()
@@ -55,8 +54,7 @@ pub fn start_qdrant() -> Result<(), String> {
     .unwrap()
     .store(true, Ordering::SeqCst);
 
-  let home_dir = dirs::home_dir().expect("Couldn't get home_dir for platform.");
-  let qdrant_pathbuf = home_dir.join(QDRANT_STORAGE);
+  let qdrant_pathbuf = crate::config::data_root().join(QDRANT_STORAGE);
   let qdrant_path = qdrant_pathbuf.as_path();
   let qdrant_path_str = qdrant_path.to_str().unwrap();
 
@@ -68,7 +66,7 @@ pub fn start_qdrant() -> Result<(), String> {
 
   let mut log_file = get_qdrant_logfile();
 
-  let snapshot_pathbuf = home_dir.join(QDRANT_SNAPSHOT);
+  let snapshot_pathbuf = crate::config::data_root().join(QDRANT_SNAPSHOT);
   let snapshot_path = snapshot_pathbuf.as_path();
   let snapshot_path_str = snapshot_path.to_str().unwrap();
 
@@ -16,8 +16,7 @@ use std::path::PathBuf;
 
 
 fn get_user_uuid_from_profile() -> Option<(String, String)> {
-  let home_dir = dirs::home_dir()?;
-  let profile_path = home_dir.join(".knapsack").join("profile.dat");
+  let profile_path = crate::config::data_dir().join("profile.dat");
   
   match fs::read_to_string(profile_path) {
     Ok(content) => {
@@ -3,6 +3,7 @@ import BaseStep from 'src/automations/steps/Base'
 import BusinessCoachAutomation from 'src/automations/steps/BusinessCoach'
 import FinraCompliance from 'src/automations/steps/FinraCompliance'
 import LeadScoring from 'src/automations/steps/LeadScoring'
+import PipelineStep, { PIPELINE_STEP_NAMES } from 'src/automations/steps/Pipeline'
 import PostSafely from 'src/automations/steps/PostSafely'
 import SocialMediaPlanner from 'src/automations/steps/SocialMediaPlanner'
 import StrategicPlan from 'src/automations/steps/StrategicPlan'
@@ -52,6 +53,10 @@ const serializeAutomation = (automationData: {
     cadenceType: string
     dayOfWeek?: string | null
     time: string | null
+    cronExpr?: string | null
+    timezone?: string | null
+    intervalMinutes?: number | null
+    dayOfMonth?: number | null
   }[]
   runs: [
     {
@@ -89,6 +94,9 @@ const serializeAutomation = (automationData: {
     return step.ordering
   })
   const automationStepsMapping = automationData.steps.map(step => {
+    if (PIPELINE_STEP_NAMES.includes(step.name)) {
+      return PipelineStep.forName(step.name, step.argsJson)
+    }
     const Step = constructors.find(constructor => constructor.getName() === step.name)
     if (!Step) {
       console.error('Missing step implementation')
@@ -107,6 +115,10 @@ const serializeAutomation = (automationData: {
       type: cadence.cadenceType as CadenceType,
       dayOfWeek: (cadence.dayOfWeek as DaysOfWeek) ?? undefined,
       time: cadence.time ?? undefined,
+      cronExpr: cadence.cronExpr ?? undefined,
+      timezone: cadence.timezone ?? undefined,
+      intervalMinutes: cadence.intervalMinutes ?? undefined,
+      dayOfMonth: cadence.dayOfMonth ?? undefined,
     })),
     steps: automationSteps as BaseStep[],
     isActive: automationData.isActive,
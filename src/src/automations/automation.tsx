@@ -5,11 +5,27 @@ import { SourceDocument } from 'src/utils/SourceDocument'
 import { genAutomationUUID } from '../utils/uuid'
 import BaseStep from './steps/Base'
 
+// Steps the backend executor runs on schedule (src-tauri/src/automations/steps.rs
+// and pipeline.rs)
+const BACKEND_EXECUTED_STEPS = [
+  'meeting-prep',
+  'email-summary',
+  'finra-compliance',
+  'fetch_emails',
+  'filter_emails',
+  'llm_summarize',
+  'notify',
+]
+
 export enum CadenceType {
   EVERY_MINUTE = 'every_minute',
+  EVERY_MINUTES = 'every_minutes',
   HOURLY = 'hourly',
   DAILY = 'daily',
+  BUSINESS_DAYS = 'business_days',
   WEEKLY = 'weekly',
+  MONTHLY = 'monthly',
+  CRON = 'cron',
   NEVER = 'never',
   OTHER = 'other',
 }
@@ -18,6 +34,12 @@ export type Cadence = {
   type: CadenceType
   dayOfWeek?: DaysOfWeek
   time?: string
+  // Cron expression or schedule text such as "every weekday at 9am"
+  cronExpr?: string
+  // IANA timezone, defaults to the system one
+  timezone?: string
+  intervalMinutes?: number
+  dayOfMonth?: number
 }
 
 export enum DaysOfWeek {
@@ -229,6 +251,13 @@ export class Automation {
     return this.steps
   }
 
+  isExecutedByBackend() {
+    return (
+      this.steps.length > 0 &&
+      this.steps.every(step => BACKEND_EXECUTED_STEPS.includes(step.serialize().name))
+    )
+  }
+
   findCadence(date: Date): Cadence | undefined {
     const checkTime = (cadence: Cadence) => cadence.time === dayjs(date).format('HH:mm')
     const checkDayOfWeek = (cadence: Cadence) => {
@@ -251,12 +280,33 @@ export class Automation {
     const checkEveryMinuteCadence = (cadence: Cadence) => cadence.type === CadenceType.EVERY_MINUTE
     const checkWeeklyCadence = (cadence: Cadence) =>
       cadence.type === CadenceType.WEEKLY && checkDayOfWeek(cadence) && checkTime(cadence)
+    const checkEveryMinutesCadence = (cadence: Cadence) =>
+      cadence.type === CadenceType.EVERY_MINUTES &&
+      !!cadence.intervalMinutes &&
+      (date.getHours() * 60 + date.getMinutes()) % cadence.intervalMinutes === 0
+    const checkBusinessDaysCadence = (cadence: Cadence) =>
+      cadence.type === CadenceType.BUSINESS_DAYS &&
+      date.getDay() >= 1 &&
+      date.getDay() <= 5 &&
+      checkTime(cadence)
+    const checkMonthlyCadence = (cadence: Cadence) => {
+      const lastDay = dayjs(date).daysInMonth()
+      return (
+        cadence.type === CadenceType.MONTHLY &&
+        date.getDate() === Math.min(cadence.dayOfMonth ?? 1, lastDay) &&
+        checkTime(cadence)
+      )
+    }
+    // Cron cadences are only evaluated by the backend scheduler
     return this.cadences.find(
       cadence =>
         checkDailyCadence(cadence) ||
         checkHourlyCadence(cadence) ||
         checkWeeklyCadence(cadence) ||
-        checkEveryMinuteCadence(cadence),
+        checkEveryMinuteCadence(cadence) ||
+        checkEveryMinutesCadence(cadence) ||
+        checkBusinessDaysCadence(cadence) ||
+        checkMonthlyCadence(cadence),
     )
   }
 
@@ -270,6 +320,10 @@ export class Automation {
         cadence_type: cadence.type,
         day_of_week: cadence.dayOfWeek,
         time: cadence.time,
+        cron_expr: cadence.cronExpr,
+        timezone: cadence.timezone ?? Intl.DateTimeFormat().resolvedOptions().timeZone,
+        interval_minutes: cadence.intervalMinutes,
+        day_of_month: cadence.dayOfMonth,
       })),
       runs: [],
       steps: this.steps.map((step, index) => ({ ...step.serialize(), ordering: index })),
@@ -0,0 +1,25 @@
+import BaseStep from './Base'
+
+// Typed steps only the backend runs (src-tauri/src/automations/pipeline.rs).
+// Their args bind inputs to literals or `{ $ref: 'steps.<ordering>.<output>' }`.
+export const PIPELINE_STEP_NAMES = ['fetch_emails', 'filter_emails', 'llm_summarize', 'notify']
+
+export default class PipelineStep extends BaseStep {
+  private name: string
+
+  constructor({ name, argsJSON }: { name: string; argsJSON?: string | null }) {
+    super({ sources: [], argsJSON })
+    this.name = name
+  }
+
+  serialize() {
+    return {
+      name: this.name,
+      args_json: this.argsJSON,
+    }
+  }
+
+  static forName(name: string, argsJSON: string | null) {
+    return new PipelineStep({ name, argsJSON })
+  }
+}
@@ -1175,7 +1175,8 @@ export function useFeed(
           item.run.scheduleDate &&
           item.run.scheduleDate.getTime() < now.getTime() &&
           item.run?.automationUuid
-            ? automations[item.run?.automationUuid].getIsActive()
+            ? automations[item.run?.automationUuid].getIsActive() &&
+              !automations[item.run?.automationUuid].isExecutedByBackend()
             : false,
         )
         for (const feedItem of readyRuns) {
@@ -1,3 +1,8 @@
+import emailSummaryPrompt from '../src-tauri/src/automations/prompts/email_summary.md?raw'
+import finraCompliancePrompt from '../src-tauri/src/automations/prompts/finra_compliance.md?raw'
+import meetingPrepPrompt from '../src-tauri/src/automations/prompts/meeting_prep.md?raw'
+import saasList from '../src-tauri/src/automations/prompts/saas_list.txt?raw'
+
 export const LOCAL_FILES_SUMMARIZE_PROMPT = `Take the above listed file(s) and use them to do 3 things:
 
 First, write a summary of each file. For the summary, write a maximum of 4 sentences per file, and a maximum of 4 sentence per file if there are more than 3 files. Always put a line break between each file's summary. Do not mention the file's location in the summary.
@@ -10,41 +15,20 @@ Return your output in JSON format, like this: {\"summary\": \"<SUMMARY_HERE>\",
 
 Do not return any other output besides this JSON object.`
 
-export const FINRA_COMPLIANCE_PROMPT = `Do not hallucinate. Do not make up factual information. You are responsible for reviewing email and phone communications between financial advisors and their clients to ensure compliance with FINRA's rules. Check for email and call transcripts in my local files. Your goal is to identify any statements that are non-compliant, specifically that:
-- Imply guaranteed financial success.
-- Use exceptional case studies to infer typical results.
-- Do not include appropriate disclaimers about the potential risks and rewards of investments.
-- Could be considered misleading
-- Seems to omit material information
-- References predictions or projections of investment performance without disclosing the source of any statistical information
-- Discusses performance data without discussing the impact of fees and expenses
-- Uses a hypothetical illustration that implies an investment’s future performance.
-Here are some guidelines that you must follow when responding:
-For emails, provide a revised version that would be more compliant. For phone calls, identify any potential non-compliant statements and suggest a more compliant alternative. Make sure to comment on every email and phone call (including advisor and broker calls), even if they are compliant. Always include the original file name in your analysis. At the end of your assessment, include a rating of the overall compliance of the communication. The rating can have the values: "Compliant", "Generally compliant", and "Non-compliant". Print this rating on its own line like this:
-**Compliance rating**: Generally compliant
-Output your response in Markdown. As you analyze each document, put a horizontal rule (---) before the start of the document, and only use one horizontal rule per document / section. Use **<text here>** for bold text. When you either provide a revised version of text or re-print original text from the source, don't list quotes using lists or bullet points. Instead, use Markdown blockquote formatting, like "> ", and don't use quotation marks (e.g. ' or ") alongside the blockquotes.
-Format your response in Markdown to optimize for readability, particularly so that someone can scan the output quickly to find what they need.
-Lastly, do not make up responses. If there are zero sources, write "No sources found."
-Here's an example of what your response should look like:
-# Broker Call - New ETF Opportunity
-## Compliance Assessment
-The broker call transcript reveals several potential non-compliant statements.
-* The broker mentions:
-> I’ve come across something interesting that I think you’ll want to know about. I can’t get into details, but I’ve heard from a very reliable source that a company we follow is about to make a big announcement.
-This statement could be considered non-compliant as it implies access to non-public information, which may be a violation of FINRA rules.
-* The broker states:
-> All I can say is that it’s nonpublic information that presents a rare opportunity. The potential for quick returns is high, but the decision is entirely up to you.
-This statement could be misleading, as it creates an expectation of high returns without properly disclosing the risks.
-* The broker recommends:
-> I’d recommend a moderate position, maybe around 10% of your portfolio. This way, you’re well-positioned without overcommitting.
-This statement lacks proper disclosure of the potential risks and rewards associated with the investment.
-## Revised Version
-A more compliant version of the conversation could be:
-> I've identified a potential investment opportunity in a company that we've been following. However, I must emphasize that this is speculative and involves a high degree of risk. While I believe this could be a good opportunity, I want to make sure you understand the potential risks and rewards involved.
-> To be clear, past performance is not indicative of future results, and there are no guarantees of success. I recommend that you consider your overall financial goals and risk tolerance before making any investment decisions.
-**Compliance rating**: Non-compliant
----
-`
+// Automations the backend also runs share their prompt files with it, so
+// both send the same text. Placeholders like {user_email} are filled with
+// fillPrompt.
+export const FINRA_COMPLIANCE_PROMPT = finraCompliancePrompt
+export const MEETING_PREP_PROMPT = meetingPrepPrompt
+export const EMAIL_SUMMARY_PROMPT = emailSummaryPrompt
+export const SAAS_LIST_TEXT = saasList
+
+export function fillPrompt(template: string, values: Record<string, string>): string {
+  return Object.entries(values).reduce(
+    (prompt, [name, value]) => prompt.split(`{${name}}`).join(value),
+    template,
+  )
+}
 
 export const POST_SAFELY = `You are an expert social media poster with a sharp wit and an eye for what works well on LinkedIn for engagement and inspiring confidence.
 